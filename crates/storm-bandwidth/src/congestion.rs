@@ -0,0 +1,121 @@
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MIN_GAIN: f64 = 0.05;
+const DEFAULT_SETTLE: Duration = Duration::from_secs(2);
+
+struct GateState {
+    baseline_speed: Option<f64>,
+    last_spawn: Option<Instant>,
+    saturated: bool,
+}
+
+pub struct CongestionGate {
+    state: Mutex<GateState>,
+    min_gain: f64,
+    settle: Duration,
+}
+
+impl CongestionGate {
+    pub fn new() -> Self {
+        Self::with_config(DEFAULT_MIN_GAIN, DEFAULT_SETTLE)
+    }
+
+    pub fn with_config(min_gain: f64, settle: Duration) -> Self {
+        Self {
+            state: Mutex::new(GateState {
+                baseline_speed: None,
+                last_spawn: None,
+                saturated: false,
+            }),
+            min_gain,
+            settle,
+        }
+    }
+
+    pub fn should_spawn(&self, aggregate_speed: f64) -> bool {
+        self.should_spawn_at(aggregate_speed, Instant::now())
+    }
+
+    fn should_spawn_at(&self, aggregate_speed: f64, now: Instant) -> bool {
+        let mut state = self.state.lock();
+        if state.saturated {
+            return false;
+        }
+
+        if let Some(last) = state.last_spawn
+            && now.duration_since(last) < self.settle
+        {
+            return false;
+        }
+
+        if let Some(baseline) = state.baseline_speed
+            && aggregate_speed < baseline * (1.0 + self.min_gain)
+        {
+            tracing::debug!(
+                "Throughput plateaued ({:.2} MB/s vs {:.2} MB/s before last worker), stop spawning",
+                aggregate_speed / 1_000_000.0,
+                baseline / 1_000_000.0
+            );
+            state.saturated = true;
+            return false;
+        }
+
+        true
+    }
+
+    pub fn record_spawn(&self, aggregate_speed: f64) {
+        self.record_spawn_at(aggregate_speed, Instant::now());
+    }
+
+    fn record_spawn_at(&self, aggregate_speed: f64, now: Instant) {
+        let mut state = self.state.lock();
+        state.baseline_speed = Some(aggregate_speed);
+        state.last_spawn = Some(now);
+    }
+
+    pub fn is_saturated(&self) -> bool {
+        self.state.lock().saturated
+    }
+
+    pub fn reset(&self) {
+        let mut state = self.state.lock();
+        state.baseline_speed = None;
+        state.last_spawn = None;
+        state.saturated = false;
+    }
+}
+
+impl Default for CongestionGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawns_while_throughput_grows() {
+        let gate = CongestionGate::with_config(0.05, Duration::from_secs(1));
+        let start = Instant::now();
+
+        assert!(gate.should_spawn_at(10.0, start));
+        gate.record_spawn_at(10.0, start);
+
+        assert!(!gate.should_spawn_at(20.0, start + Duration::from_millis(500)));
+        assert!(gate.should_spawn_at(20.0, start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_stops_when_throughput_plateaus() {
+        let gate = CongestionGate::with_config(0.05, Duration::from_secs(1));
+        let start = Instant::now();
+
+        gate.record_spawn_at(100.0, start);
+        assert!(!gate.should_spawn_at(102.0, start + Duration::from_secs(2)));
+        assert!(gate.is_saturated());
+        assert!(!gate.should_spawn_at(500.0, start + Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,177 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stormdl_core::Priority;
+use tokio::sync::Notify;
+
+const WINDOW: f64 = 1024.0 * 1024.0;
+const IDLE: Duration = Duration::from_millis(500);
+const RECHECK: Duration = Duration::from_millis(50);
+
+fn weight(priority: Priority) -> f64 {
+    match priority {
+        Priority::Critical => 16.0,
+        Priority::High => 8.0,
+        Priority::Normal => 4.0,
+        Priority::Low => 2.0,
+        Priority::Background => 1.0,
+    }
+}
+
+struct Flow {
+    link: String,
+    weight: f64,
+    tag: f64,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct FairState {
+    next_id: u64,
+    flows: HashMap<u64, Flow>,
+}
+
+// Flows on the same link (a host, or a rate-limited group) are served by
+// virtual finish tag: a download may run at most `WINDOW` weighted bytes ahead
+// of the slowest active flow it competes with, so extra segments buy it
+// nothing over its priority's share.
+#[derive(Clone, Default)]
+pub struct FairScheduler {
+    state: Arc<Mutex<FairState>>,
+    wake: Arc<Notify>,
+}
+
+impl FairScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn join(&self, link: impl Into<String>, priority: Priority) -> FairShare {
+        let now = Instant::now();
+        let link = link.into();
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        let tag = floor(&state.flows, &link, now).unwrap_or(0.0);
+        state.flows.insert(
+            id,
+            Flow {
+                link,
+                weight: weight(priority),
+                tag,
+                last_seen: now,
+            },
+        );
+        FairShare {
+            scheduler: self.clone(),
+            id,
+        }
+    }
+
+    pub fn flows(&self) -> usize {
+        self.state.lock().flows.len()
+    }
+
+    fn try_admit_at(&self, id: u64, bytes: usize, now: Instant) -> bool {
+        let mut state = self.state.lock();
+        let Some(flow) = state.flows.get(&id) else {
+            return true;
+        };
+        let idle = now.duration_since(flow.last_seen) >= IDLE;
+        let floor = floor(&state.flows, &flow.link, now);
+        let flow = state.flows.get_mut(&id).expect("flow checked above");
+        if idle && let Some(floor) = floor {
+            flow.tag = flow.tag.max(floor);
+        }
+        flow.last_seen = now;
+        if floor.is_some_and(|floor| flow.tag > floor + WINDOW) {
+            return false;
+        }
+        flow.tag += bytes as f64 / flow.weight;
+        true
+    }
+}
+
+fn floor(flows: &HashMap<u64, Flow>, link: &str, now: Instant) -> Option<f64> {
+    flows
+        .values()
+        .filter(|f| f.link == link && now.duration_since(f.last_seen) < IDLE)
+        .map(|f| f.tag)
+        .min_by(f64::total_cmp)
+}
+
+pub struct FairShare {
+    scheduler: FairScheduler,
+    id: u64,
+}
+
+impl FairShare {
+    pub fn set_priority(&self, priority: Priority) {
+        if let Some(flow) = self.scheduler.state.lock().flows.get_mut(&self.id) {
+            flow.weight = weight(priority);
+        }
+    }
+
+    pub fn try_admit(&self, bytes: usize) -> bool {
+        let admitted = self.scheduler.try_admit_at(self.id, bytes, Instant::now());
+        if admitted {
+            self.scheduler.wake.notify_waiters();
+        }
+        admitted
+    }
+
+    pub async fn admit(&self, bytes: usize) {
+        while !self.try_admit(bytes) {
+            tokio::select! {
+                _ = self.scheduler.wake.notified() => {}
+                _ = tokio::time::sleep(RECHECK) => {}
+            }
+        }
+    }
+}
+
+impl Drop for FairShare {
+    fn drop(&mut self) {
+        self.scheduler.state.lock().flows.remove(&self.id);
+        self.scheduler.wake.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_share_per_link() {
+        let scheduler = FairScheduler::new();
+        let start = Instant::now();
+        let bulk = scheduler.join("a.example", Priority::Normal);
+        let urgent = scheduler.join("a.example", Priority::Critical);
+        let other = scheduler.join("b.example", Priority::Background);
+        let chunk = 256 * 1024;
+
+        let mut bulk_bytes = 0;
+        let mut urgent_bytes = 0;
+        for step in 0..2000 {
+            let now = start + Duration::from_millis(step);
+            if scheduler.try_admit_at(bulk.id, chunk, now) {
+                bulk_bytes += chunk;
+            }
+            if scheduler.try_admit_at(urgent.id, chunk, now) {
+                urgent_bytes += chunk;
+            }
+            assert!(scheduler.try_admit_at(other.id, chunk, now));
+        }
+        let ratio = urgent_bytes as f64 / bulk_bytes as f64;
+        assert!((3.5..=4.5).contains(&ratio), "ratio {}", ratio);
+
+        let later = start + Duration::from_secs(5);
+        for _ in 0..64 {
+            assert!(scheduler.try_admit_at(bulk.id, chunk, later));
+        }
+        drop(urgent);
+        drop(other);
+        assert_eq!(scheduler.flows(), 1);
+    }
+}
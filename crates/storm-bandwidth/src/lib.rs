@@ -1,7 +1,15 @@
+mod congestion;
+mod fair;
 mod limiter;
 mod monitor;
+mod paths;
+mod profile;
 mod scheduler;
 
+pub use congestion::CongestionGate;
+pub use fair::{FairScheduler, FairShare};
 pub use limiter::RateLimiter;
 pub use monitor::NetworkMonitor;
+pub use paths::PathBalancer;
+pub use profile::{ProfileGovernor, ProfileSample, ProfileSwitch, SwitchReason, TransferProfile};
 pub use scheduler::{DownloadQueue, QueuedDownload};
@@ -8,6 +8,23 @@ use std::sync::Arc;
 
 type InnerLimiter = GovLimiter<NotKeyed, InMemoryState, DefaultClock>;
 
+/// Holds everything sharing it to a byte rate; unlimited ones never wait.
+///
+/// ```
+/// use stormdl_bandwidth::RateLimiter;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let limiter = RateLimiter::new(Some(1024 * 1024));
+/// assert_eq!(limiter.limit(), Some(1024 * 1024));
+/// // Waits until another 16 KiB fits under the limit.
+/// limiter.acquire(16 * 1024).await;
+///
+/// let unlimited = RateLimiter::unlimited();
+/// assert!(!unlimited.is_limited());
+/// assert!(unlimited.try_acquire(usize::MAX));
+/// # }
+/// ```
 pub struct RateLimiter {
     limiter: Option<Arc<InnerLimiter>>,
     bytes_per_second: Option<u64>,
@@ -3,13 +3,26 @@ use std::time::{Duration, Instant};
 
 const SAMPLE_WINDOW: usize = 10;
 const RTT_SAMPLE_WINDOW: usize = 20;
+const TTFB_SAMPLE_WINDOW: usize = 20;
 const EWMA_ALPHA: f64 = 0.2;
+const PATHOLOGICAL_TTFB_FLOOR: Duration = Duration::from_secs(1);
+const PATHOLOGICAL_TTFB_FACTOR: u32 = 4;
+const TTFB_OVERHEAD_RATIO: f64 = 9.0;
+// Request timeouts allow this many times the expected transfer time, so a
+// slow spell doesn't abandon a range that's still moving.
+const TIMEOUT_SLACK: f64 = 4.0;
+// Assumed per connection until the first samples are in.
+const TIMEOUT_ASSUMED_SPEED: f64 = 128.0 * 1024.0;
+const MIN_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REQUEST_TIMEOUT: Duration = Duration::from_secs(6 * 3600);
 
 pub struct NetworkMonitor {
     samples: Mutex<Vec<(Instant, u64)>>,
     last_bytes: Mutex<u64>,
     rtt_samples: Mutex<Vec<Duration>>,
     smoothed_rtt: Mutex<Option<f64>>,
+    ttfb_samples: Mutex<Vec<Duration>>,
+    smoothed_ttfb: Mutex<Option<f64>>,
 }
 
 impl NetworkMonitor {
@@ -19,6 +32,8 @@ impl NetworkMonitor {
             last_bytes: Mutex::new(0),
             rtt_samples: Mutex::new(Vec::with_capacity(RTT_SAMPLE_WINDOW)),
             smoothed_rtt: Mutex::new(None),
+            ttfb_samples: Mutex::new(Vec::with_capacity(TTFB_SAMPLE_WINDOW)),
+            smoothed_ttfb: Mutex::new(None),
         }
     }
 
@@ -64,6 +79,50 @@ impl NetworkMonitor {
         self.rtt_samples.lock().iter().min().copied()
     }
 
+    pub fn record_ttfb(&self, ttfb: Duration) {
+        let ttfb_ms = ttfb.as_secs_f64() * 1000.0;
+
+        let mut samples = self.ttfb_samples.lock();
+        samples.push(ttfb);
+        if samples.len() > TTFB_SAMPLE_WINDOW {
+            samples.remove(0);
+        }
+
+        let mut smoothed = self.smoothed_ttfb.lock();
+        *smoothed = Some(match *smoothed {
+            Some(prev) => prev * (1.0 - EWMA_ALPHA) + ttfb_ms * EWMA_ALPHA,
+            None => ttfb_ms,
+        });
+    }
+
+    pub fn smoothed_ttfb(&self) -> Option<Duration> {
+        self.smoothed_ttfb
+            .lock()
+            .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+    }
+
+    pub fn min_ttfb(&self) -> Option<Duration> {
+        self.ttfb_samples.lock().iter().min().copied()
+    }
+
+    pub fn is_pathological_ttfb(&self, ttfb: Duration) -> bool {
+        if ttfb < PATHOLOGICAL_TTFB_FLOOR {
+            return false;
+        }
+        match self.min_ttfb() {
+            Some(min) => ttfb > min * PATHOLOGICAL_TTFB_FACTOR,
+            None => true,
+        }
+    }
+
+    pub fn min_efficient_range(&self, per_connection_speed: f64) -> Option<u64> {
+        if per_connection_speed <= 0.0 {
+            return None;
+        }
+        self.smoothed_ttfb()
+            .map(|ttfb| (per_connection_speed * ttfb.as_secs_f64() * TTFB_OVERHEAD_RATIO) as u64)
+    }
+
     pub fn current_speed(&self) -> f64 {
         let samples = self.samples.lock();
         if samples.len() < 2 {
@@ -132,11 +191,26 @@ impl NetworkMonitor {
         Some(min_connections.clamp(1, max_connections))
     }
 
+    // How long a request for `len` bytes may run on one of `connections`
+    // sharing the measured speed before it's given up and retried.
+    pub fn request_timeout(&self, len: u64, connections: usize) -> Duration {
+        let speed = match self.current_speed() / connections.max(1) as f64 {
+            speed if speed > 0.0 => speed,
+            _ => TIMEOUT_ASSUMED_SPEED,
+        };
+        let ttfb = self.smoothed_ttfb().unwrap_or_default().as_secs_f64();
+        let expected = len as f64 / speed + ttfb;
+        Duration::from_secs_f64(expected * TIMEOUT_SLACK)
+            .clamp(MIN_REQUEST_TIMEOUT, MAX_REQUEST_TIMEOUT)
+    }
+
     pub fn reset(&self) {
         self.samples.lock().clear();
         *self.last_bytes.lock() = 0;
         self.rtt_samples.lock().clear();
         *self.smoothed_rtt.lock() = None;
+        self.ttfb_samples.lock().clear();
+        *self.smoothed_ttfb.lock() = None;
     }
 }
 
@@ -145,3 +219,37 @@ impl Default for NetworkMonitor {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_timeout() {
+        let monitor = NetworkMonitor::new();
+        let mb = 1024 * 1024;
+        // Nothing measured yet: 128 KiB/s is assumed.
+        assert_eq!(
+            monitor.request_timeout(16 * mb, 1),
+            Duration::from_secs(512)
+        );
+        assert_eq!(monitor.request_timeout(mb / 2, 1), MIN_REQUEST_TIMEOUT);
+        assert_eq!(
+            monitor.request_timeout(100_000 * mb, 1),
+            MAX_REQUEST_TIMEOUT
+        );
+
+        // 10 MiB/s over four connections.
+        let start = Instant::now();
+        monitor.samples.lock().push((start, 0));
+        monitor
+            .samples
+            .lock()
+            .push((start + Duration::from_secs(1), 10 * mb));
+        assert_eq!(
+            monitor.request_timeout(250 * mb, 4),
+            Duration::from_secs(400)
+        );
+        assert_eq!(monitor.request_timeout(2 * mb, 4), MIN_REQUEST_TIMEOUT);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::NetworkMonitor;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+const MIN_SHARE: f64 = 0.1;
+
+struct PathStats {
+    monitor: NetworkMonitor,
+    bytes: AtomicU64,
+    active: AtomicUsize,
+}
+
+pub struct PathBalancer {
+    paths: Vec<PathStats>,
+}
+
+impl PathBalancer {
+    pub fn new(count: usize) -> Self {
+        Self {
+            paths: (0..count.max(1))
+                .map(|_| PathStats {
+                    monitor: NetworkMonitor::new(),
+                    bytes: AtomicU64::new(0),
+                    active: AtomicUsize::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn acquire(&self) -> usize {
+        let active: Vec<usize> = self
+            .paths
+            .iter()
+            .map(|p| p.active.load(Ordering::Relaxed))
+            .collect();
+        let path = pick(&self.shares(), &active);
+        self.paths[path].active.fetch_add(1, Ordering::Relaxed);
+        path
+    }
+
+    pub fn release(&self, path: usize) {
+        if let Some(stats) = self.paths.get(path) {
+            let _ = stats
+                .active
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        }
+    }
+
+    pub fn record(&self, path: usize, bytes: u64) {
+        if let Some(stats) = self.paths.get(path) {
+            stats.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+    }
+
+    pub fn sample(&self) {
+        for stats in &self.paths {
+            stats.monitor.record(stats.bytes.load(Ordering::Relaxed));
+        }
+    }
+
+    pub fn bytes(&self, path: usize) -> u64 {
+        self.paths
+            .get(path)
+            .map_or(0, |p| p.bytes.load(Ordering::Relaxed))
+    }
+
+    pub fn speed(&self, path: usize) -> f64 {
+        self.paths
+            .get(path)
+            .map_or(0.0, |p| p.monitor.current_speed())
+    }
+
+    pub fn shares(&self) -> Vec<f64> {
+        let speeds: Vec<f64> = (0..self.paths.len()).map(|p| self.speed(p)).collect();
+        shares(&speeds)
+    }
+}
+
+fn shares(speeds: &[f64]) -> Vec<f64> {
+    let total: f64 = speeds.iter().sum();
+    if total <= 0.0 {
+        return vec![1.0 / speeds.len() as f64; speeds.len()];
+    }
+    let floored: Vec<f64> = speeds
+        .iter()
+        .map(|speed| (speed / total).max(MIN_SHARE))
+        .collect();
+    let sum: f64 = floored.iter().sum();
+    floored.iter().map(|share| share / sum).collect()
+}
+
+fn pick(shares: &[f64], active: &[usize]) -> usize {
+    let total = active.iter().sum::<usize>() + 1;
+    let deficit = |path: usize| shares[path] - active[path] as f64 / total as f64;
+    (0..shares.len())
+        .max_by(|&a, &b| deficit(a).total_cmp(&deficit(b)))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_follows_path_speed() {
+        assert_eq!(shares(&[0.0, 0.0]), vec![0.5, 0.5]);
+
+        let split = shares(&[30.0, 10.0]);
+        assert!((split[0] - 0.75).abs() < 1e-9);
+
+        let starved = shares(&[100.0, 0.0]);
+        assert!(starved[1] > 0.09);
+
+        let mut active = vec![0, 0];
+        for _ in 0..8 {
+            let path = pick(&split, &active);
+            active[path] += 1;
+        }
+        assert_eq!(active, vec![6, 2]);
+    }
+}
@@ -0,0 +1,248 @@
+use parking_lot::Mutex;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+const SETTLE: Duration = Duration::from_secs(5);
+const MIN_REQUESTS: u64 = 4;
+const MAX_ERROR_RATE: f64 = 0.1;
+const MAX_LATENCY_INFLATION: f64 = 2.5;
+const CALM_LATENCY_INFLATION: f64 = 1.5;
+const MIN_CONNECTION_SHARE: f64 = 0.5;
+const MIN_TURBO_GAIN: f64 = 0.1;
+const INITIAL_COOLDOWN: Duration = Duration::from_secs(15);
+const MAX_COOLDOWN: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferProfile {
+    Turbo,
+    Gentle,
+}
+
+impl fmt::Display for TransferProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Turbo => "turbo",
+            Self::Gentle => "gentle",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchReason {
+    Errors { rate: f64 },
+    Congestion { inflation: f64, share: f64 },
+    NoGain { turbo: f64, gentle: f64 },
+    Calm,
+}
+
+impl fmt::Display for SwitchReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Errors { rate } => write!(f, "{:.0}% of requests failed", rate * 100.0),
+            Self::Congestion { inflation, share } => write!(
+                f,
+                "latency {:.1}x its floor while each connection gets {:.0}% of its best speed",
+                inflation,
+                share * 100.0
+            ),
+            Self::NoGain { turbo, gentle } => write!(
+                f,
+                "{:.2} MB/s is no faster than {:.2} MB/s with fewer connections",
+                turbo / 1_000_000.0,
+                gentle / 1_000_000.0
+            ),
+            Self::Calm => f.write_str("no errors and latency back near its floor"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileSwitch {
+    pub from: TransferProfile,
+    pub to: TransferProfile,
+    pub reason: SwitchReason,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProfileSample {
+    pub speed: f64,
+    pub connections: usize,
+    pub latency: Option<Duration>,
+}
+
+struct GovernorState {
+    profile: TransferProfile,
+    since: Instant,
+    requests: u64,
+    errors: u64,
+    latency_floor: Option<Duration>,
+    best_per_connection: f64,
+    gentle_speed: Option<f64>,
+    cooldown: Duration,
+}
+
+pub struct ProfileGovernor {
+    state: Mutex<GovernorState>,
+}
+
+impl ProfileGovernor {
+    pub fn new(profile: TransferProfile) -> Self {
+        Self::new_at(profile, Instant::now())
+    }
+
+    fn new_at(profile: TransferProfile, now: Instant) -> Self {
+        Self {
+            state: Mutex::new(GovernorState {
+                profile,
+                since: now,
+                requests: 0,
+                errors: 0,
+                latency_floor: None,
+                best_per_connection: 0.0,
+                gentle_speed: None,
+                cooldown: INITIAL_COOLDOWN,
+            }),
+        }
+    }
+
+    pub fn profile(&self) -> TransferProfile {
+        self.state.lock().profile
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        self.profile() == TransferProfile::Turbo
+    }
+
+    pub fn record_request(&self, failed: bool) {
+        let mut state = self.state.lock();
+        state.requests += 1;
+        if failed {
+            state.errors += 1;
+        }
+    }
+
+    pub fn evaluate(&self, sample: ProfileSample) -> Option<ProfileSwitch> {
+        self.evaluate_at(sample, Instant::now())
+    }
+
+    fn evaluate_at(&self, sample: ProfileSample, now: Instant) -> Option<ProfileSwitch> {
+        let mut state = self.state.lock();
+        if let Some(latency) = sample.latency {
+            state.latency_floor = Some(state.latency_floor.map_or(latency, |f| f.min(latency)));
+        }
+        let inflation = match (sample.latency, state.latency_floor) {
+            (Some(latency), Some(floor)) if !floor.is_zero() => {
+                latency.as_secs_f64() / floor.as_secs_f64()
+            }
+            _ => 1.0,
+        };
+        let per_connection = sample.speed / sample.connections.max(1) as f64;
+        state.best_per_connection = state.best_per_connection.max(per_connection);
+
+        let elapsed = now.saturating_duration_since(state.since);
+        if elapsed < SETTLE {
+            return None;
+        }
+        let error_rate = if state.requests >= MIN_REQUESTS {
+            state.errors as f64 / state.requests as f64
+        } else {
+            0.0
+        };
+
+        let (to, reason) = match state.profile {
+            TransferProfile::Turbo => {
+                let share = per_connection / state.best_per_connection.max(f64::EPSILON);
+                if error_rate > MAX_ERROR_RATE {
+                    (
+                        TransferProfile::Gentle,
+                        SwitchReason::Errors { rate: error_rate },
+                    )
+                } else if inflation > MAX_LATENCY_INFLATION && share < MIN_CONNECTION_SHARE {
+                    (
+                        TransferProfile::Gentle,
+                        SwitchReason::Congestion { inflation, share },
+                    )
+                } else if let Some(gentle) = state.gentle_speed
+                    && sample.speed < gentle * (1.0 + MIN_TURBO_GAIN)
+                {
+                    (
+                        TransferProfile::Gentle,
+                        SwitchReason::NoGain {
+                            turbo: sample.speed,
+                            gentle,
+                        },
+                    )
+                } else {
+                    return None;
+                }
+            }
+            TransferProfile::Gentle => {
+                state.gentle_speed = Some(sample.speed);
+                if elapsed >= state.cooldown
+                    && error_rate <= MAX_ERROR_RATE / 2.0
+                    && inflation < CALM_LATENCY_INFLATION
+                {
+                    (TransferProfile::Turbo, SwitchReason::Calm)
+                } else {
+                    return None;
+                }
+            }
+        };
+
+        if to == TransferProfile::Gentle {
+            state.cooldown = if state.gentle_speed.is_some() {
+                (state.cooldown * 2).min(MAX_COOLDOWN)
+            } else {
+                INITIAL_COOLDOWN
+            };
+        }
+        let from = state.profile;
+        state.profile = to;
+        state.since = now;
+        state.requests = 0;
+        state.errors = 0;
+        Some(ProfileSwitch { from, to, reason })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(speed: f64, connections: usize, latency_ms: u64) -> ProfileSample {
+        ProfileSample {
+            speed,
+            connections,
+            latency: Some(Duration::from_millis(latency_ms)),
+        }
+    }
+
+    #[test]
+    fn test_switches_between_profiles() {
+        let start = Instant::now();
+        let governor = ProfileGovernor::new_at(TransferProfile::Turbo, start);
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert_eq!(governor.evaluate_at(sample(8.0, 8, 50), at(1)), None);
+        assert_eq!(governor.evaluate_at(sample(8.0, 8, 400), at(6)), None);
+        let switch = governor.evaluate_at(sample(8.0, 20, 400), at(7)).unwrap();
+        assert_eq!(switch.to, TransferProfile::Gentle);
+        assert!(matches!(switch.reason, SwitchReason::Congestion { .. }));
+
+        assert_eq!(governor.evaluate_at(sample(8.0, 4, 60), at(12)), None);
+        let switch = governor.evaluate_at(sample(8.0, 4, 60), at(22)).unwrap();
+        assert_eq!(switch.reason, SwitchReason::Calm);
+        assert!(governor.is_turbo());
+
+        let switch = governor.evaluate_at(sample(8.4, 8, 60), at(28)).unwrap();
+        assert!(matches!(switch.reason, SwitchReason::NoGain { .. }));
+        assert_eq!(governor.evaluate_at(sample(8.0, 4, 60), at(50)), None);
+
+        let governor = ProfileGovernor::new_at(TransferProfile::Turbo, start);
+        for failed in [true, false, true, false] {
+            governor.record_request(failed);
+        }
+        let switch = governor.evaluate_at(sample(8.0, 8, 50), at(6)).unwrap();
+        assert_eq!(switch.reason, SwitchReason::Errors { rate: 0.5 });
+    }
+}
@@ -90,6 +90,30 @@ impl DownloadQueue {
             queue.insert(insert_pos, download);
         }
     }
+
+    pub fn move_before(&self, id: DownloadId, target: DownloadId) -> Option<Priority> {
+        let mut queue = self.queue.lock();
+        let pos = queue.iter().position(|d| d.id == id)?;
+        let mut download = queue.remove(pos)?;
+
+        let Some(target_pos) = queue.iter().position(|d| d.id == target) else {
+            queue.insert(pos, download);
+            return None;
+        };
+
+        download.priority = queue[target_pos].priority;
+        let priority = download.priority;
+        queue.insert(target_pos, download);
+        Some(priority)
+    }
+
+    pub fn contains(&self, id: DownloadId) -> bool {
+        self.queue.lock().iter().any(|d| d.id == id)
+    }
+
+    pub fn order(&self) -> Vec<DownloadId> {
+        self.queue.lock().iter().map(|d| d.id).collect()
+    }
 }
 
 impl Default for DownloadQueue {
@@ -97,3 +121,58 @@ impl Default for DownloadQueue {
         Self::new(3)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    fn queued(id: u64, priority: Priority) -> QueuedDownload {
+        let url = Url::parse("https://example.com/file").unwrap();
+        QueuedDownload {
+            id: DownloadId(id),
+            options: DownloadOptions {
+                url,
+                output_dir: ".".into(),
+                filename: None,
+                segments: None,
+                priority,
+                bandwidth_limit: None,
+                headers: vec![],
+                checksum: None,
+                group: None,
+                mirrors: vec![],
+                tags: vec![],
+                note: None,
+                proxy: None,
+                auth: None,
+            },
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_move_before() {
+        let queue = DownloadQueue::new(1);
+        queue.enqueue(queued(1, Priority::High));
+        queue.enqueue(queued(2, Priority::Normal));
+        queue.enqueue(queued(3, Priority::Low));
+
+        assert_eq!(
+            queue.move_before(DownloadId(3), DownloadId(1)),
+            Some(Priority::High)
+        );
+        assert_eq!(
+            queue.order(),
+            vec![DownloadId(3), DownloadId(1), DownloadId(2)]
+        );
+
+        assert_eq!(queue.move_before(DownloadId(2), DownloadId(9)), None);
+        assert_eq!(
+            queue.order(),
+            vec![DownloadId(3), DownloadId(1), DownloadId(2)]
+        );
+
+        assert_eq!(queue.dequeue().unwrap().id, DownloadId(3));
+    }
+}
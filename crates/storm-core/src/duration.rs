@@ -0,0 +1,227 @@
+use crate::StormError;
+use chrono::{DateTime, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use std::time::Duration;
+
+const DURATION_EXAMPLES: &str = "e.g. 90, 500ms, 30s, 15m, 2h30m, 1.5h, 7d";
+const TIME_EXAMPLES: &str =
+    "e.g. 03:00, tomorrow 03:00, in 2h30m, 2026-05-01 18:00 or 2026-05-01T18:00:00Z";
+
+// `90` or `2.5` (seconds), `500ms`, `30s`, `15m`, `2h30m`, `1.5h`, `7d` or `2w`;
+// compound units add up and spaces between them are ignored.
+pub fn parse_duration(spec: &str) -> Result<Duration, StormError> {
+    let invalid = || {
+        StormError::Config(format!(
+            "invalid duration '{}' ({})",
+            spec, DURATION_EXAMPLES
+        ))
+    };
+    let compact: String = spec.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.is_empty() {
+        return Err(invalid());
+    }
+    // A bare number is seconds, fractions included as curl takes them.
+    if compact.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return compact
+            .parse()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+            .ok_or_else(invalid);
+    }
+
+    let mut total = 0f64;
+    let mut rest = compact.as_str();
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(invalid)?;
+        let (number, tail) = rest.split_at(split);
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let seconds = match unit.to_ascii_lowercase().as_str() {
+            "ms" => 0.001,
+            "s" | "sec" | "secs" => 1.0,
+            "m" | "min" | "mins" => 60.0,
+            "h" | "hr" | "hrs" => 3600.0,
+            "d" | "day" | "days" => 86400.0,
+            "w" | "week" | "weeks" => 604800.0,
+            _ => return Err(invalid()),
+        };
+        total += number * seconds;
+        rest = tail;
+    }
+    Duration::try_from_secs_f64(total).map_err(|_| invalid())
+}
+
+// The shortest form parse_duration reads back: `2h30m`, `45s`, `1d2h`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    if secs == 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let mut out = String::new();
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if secs >= size {
+            out.push_str(&format!("{}{}", secs / size, unit));
+            secs %= size;
+        }
+    }
+    out
+}
+
+// A point in time relative to `now`, in its time zone: `03:00` (the next
+// one), `today 18:30`, `tomorrow 03:00`, `now`, `in 2h30m` or `+2h30m`,
+// `2026-05-01 18:00`, or RFC 3339 with an offset.
+pub fn parse_time<Tz: TimeZone>(
+    spec: &str,
+    now: &DateTime<Tz>,
+) -> Result<DateTime<Tz>, StormError> {
+    let invalid = || StormError::Config(format!("invalid time '{}' ({})", spec, TIME_EXAMPLES));
+    let spec = spec.trim();
+    let lower = spec.to_ascii_lowercase();
+    if lower == "now" {
+        return Ok(now.clone());
+    }
+    if let Some(offset) = lower
+        .strip_prefix("in ")
+        .or_else(|| lower.strip_prefix('+'))
+    {
+        let offset = chrono::Duration::from_std(parse_duration(offset)?).map_err(|_| invalid())?;
+        return now.clone().checked_add_signed(offset).ok_or_else(invalid);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(spec) {
+        return Ok(time.with_timezone(&now.timezone()));
+    }
+
+    let local = |naive: NaiveDateTime| {
+        // In a DST gap the wall-clock time doesn't exist; in an overlap the
+        // earlier of the two is meant.
+        now.timezone()
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or_else(|| {
+                StormError::Config(format!("'{}' doesn't exist in the local time zone", spec))
+            })
+    };
+    for format in [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(spec, format) {
+            return local(naive);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return local(date.and_time(NaiveTime::MIN));
+    }
+
+    let (day, time) = match lower.split_once(char::is_whitespace) {
+        Some((day @ ("today" | "tomorrow"), time)) => (Some(day), time.trim()),
+        _ => (None, lower.as_str()),
+    };
+    let time = ["%H:%M:%S", "%H:%M"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+        .ok_or_else(invalid)?;
+    let today = now.naive_local().date();
+    match day {
+        Some("tomorrow") => local(
+            today
+                .checked_add_days(Days::new(1))
+                .ok_or_else(invalid)?
+                .and_time(time),
+        ),
+        Some(_) => local(today.and_time(time)),
+        // A bare time that has passed today means tomorrow's.
+        None => {
+            let at = local(today.and_time(time))?;
+            if at > *now {
+                Ok(at)
+            } else {
+                local(
+                    today
+                        .checked_add_days(Days::new(1))
+                        .ok_or_else(invalid)?
+                        .and_time(time),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("2h30m").unwrap(), Duration::from_secs(9000));
+        assert_eq!(
+            parse_duration("1h 30m 15s").unwrap(),
+            Duration::from_secs(5415)
+        );
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("2.5").unwrap(), Duration::from_millis(2500));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("7d").unwrap(), Duration::from_secs(604800));
+        assert_eq!(parse_duration("2W").unwrap(), Duration::from_secs(1209600));
+        for bad in ["", "h", "1x", "1.2.3s", "-5s", "30s5"] {
+            assert!(parse_duration(bad).is_err(), "{}", bad);
+        }
+
+        for secs in [0, 45, 90, 9000, 93784] {
+            let duration = Duration::from_secs(secs);
+            assert_eq!(
+                parse_duration(&format_duration(duration)).unwrap(),
+                duration
+            );
+        }
+        assert_eq!(format_duration(Duration::from_secs(9000)), "2h30m");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let zone = FixedOffset::east_opt(2 * 3600).unwrap();
+        let now = zone.with_ymd_and_hms(2026, 5, 1, 14, 20, 0).unwrap();
+        let at = |y, mo, d, h, mi| zone.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+
+        assert_eq!(parse_time("18:00", &now).unwrap(), at(2026, 5, 1, 18, 0));
+        assert_eq!(parse_time("03:00", &now).unwrap(), at(2026, 5, 2, 3, 0));
+        assert_eq!(
+            parse_time("today 09:00", &now).unwrap(),
+            at(2026, 5, 1, 9, 0)
+        );
+        assert_eq!(
+            parse_time("Tomorrow 03:00", &now).unwrap(),
+            at(2026, 5, 2, 3, 0)
+        );
+        assert_eq!(
+            parse_time("in 2h30m", &now).unwrap(),
+            at(2026, 5, 1, 16, 50)
+        );
+        assert_eq!(parse_time("+10m", &now).unwrap(), at(2026, 5, 1, 14, 30));
+        assert_eq!(parse_time("now", &now).unwrap(), now);
+        assert_eq!(
+            parse_time("2026-05-03 18:00", &now).unwrap(),
+            at(2026, 5, 3, 18, 0)
+        );
+        assert_eq!(
+            parse_time("2026-05-03", &now).unwrap(),
+            at(2026, 5, 3, 0, 0)
+        );
+        assert_eq!(
+            parse_time("2026-05-01T12:00:00Z", &now).unwrap(),
+            Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap()
+        );
+        for bad in ["", "25:00", "yesterday 03:00", "in forever", "2026-13-01"] {
+            assert!(parse_time(bad, &now).is_err(), "{}", bad);
+        }
+    }
+}
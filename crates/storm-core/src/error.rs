@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -38,12 +40,48 @@ pub enum StormError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("Host '{0}' is not permitted by the host policy")]
+    HostBlocked(String),
+
+    #[error("File too large: {size} bytes exceeds the {limit} byte limit")]
+    TooLarge { size: u64, limit: u64 },
+
+    #[error("Content type '{0}' is not accepted")]
+    ContentTypeRejected(String),
+
+    #[error("Server sent an HTML page instead of '{0}' (expired link or login page?)")]
+    UnexpectedHtml(String),
+
+    #[error("Signature verification failed: {0}")]
+    BadSignature(String),
+
+    #[error("Malware detected ({reason}); file quarantined at {}", path.display())]
+    Quarantined { reason: String, path: PathBuf },
+
+    #[error("Blocked by the locked profile: {0}")]
+    Restricted(String),
+
     #[error("Rate limited by server")]
     RateLimited,
 
+    // 202 Accepted: the server is still generating the file, e.g. an
+    // export, and says when to ask again.
+    #[error("Not ready yet; the server is still preparing it")]
+    NotReady { retry_after: Option<Duration> },
+
     #[error("Timeout: {0}")]
     Timeout(String),
 
     #[error("{0}")]
     Other(String),
 }
+
+impl StormError {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Network(_) | Self::Timeout(_) | Self::RateLimited => true,
+            Self::Http { status, .. } => *status == 408 || *status >= 500,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    ArchiveEntry, Calibration, DownloadId, DownloadOptions, DownloadState, FileMap, PageLink,
+    Priority, QuotaLevel, SegmentState, SetupChoices,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
+pub enum OrchestratorCommand {
+    AddDownload {
+        url: Url,
+        options: DownloadOptions,
+    },
+    PauseDownload(DownloadId),
+    ResumeDownload(DownloadId),
+    CancelDownload(DownloadId),
+    RemoveDownload(DownloadId),
+    SetBandwidthLimit(Option<u64>),
+    SetPriority {
+        id: DownloadId,
+        priority: Priority,
+    },
+    MoveDownload {
+        id: DownloadId,
+        before: DownloadId,
+    },
+    SetLabels {
+        id: DownloadId,
+        tags: Vec<String>,
+        note: Option<String>,
+    },
+    SetUiActive(bool),
+    GrabLinks(Url),
+    Calibrate(Url),
+    CompleteSetup(SetupChoices),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DownloadEvent {
+    DownloadAdded {
+        id: DownloadId,
+        url: Url,
+        filename: String,
+        total_size: Option<u64>,
+        group: Option<String>,
+    },
+    ProgressUpdate {
+        id: DownloadId,
+        downloaded: u64,
+        segments: Vec<SegmentState>,
+    },
+    SpeedUpdate {
+        id: DownloadId,
+        speed: f64,
+    },
+    StateChange {
+        id: DownloadId,
+        state: DownloadState,
+    },
+    SegmentRebalanced {
+        id: DownloadId,
+        old_count: usize,
+        new_count: usize,
+    },
+    Error {
+        id: DownloadId,
+        error: String,
+    },
+    Complete {
+        id: DownloadId,
+        path: PathBuf,
+        hash: String,
+    },
+    PriorityChanged {
+        id: DownloadId,
+        priority: Priority,
+    },
+    QueueChanged {
+        order: Vec<DownloadId>,
+    },
+    LabelsChanged {
+        id: DownloadId,
+        tags: Vec<String>,
+        note: Option<String>,
+    },
+    ArchiveListed {
+        id: DownloadId,
+        entries: Vec<ArchiveEntry>,
+    },
+    FileMapUpdate {
+        id: DownloadId,
+        map: FileMap,
+    },
+    QuotaUpdate {
+        used: u64,
+        limit: Option<u64>,
+        level: QuotaLevel,
+    },
+    PageLinks {
+        page: Url,
+        links: Vec<PageLink>,
+    },
+    PageLinksFailed {
+        page: Url,
+        error: String,
+    },
+    CalibrationFinished {
+        url: Url,
+        calibration: Calibration,
+    },
+    CalibrationFailed {
+        url: Url,
+        error: String,
+    },
+    SetupSaved {
+        path: PathBuf,
+    },
+    SetupFailed {
+        error: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ByteRange;
+
+    #[test]
+    fn test_events_serialize_with_type_tag() {
+        let event = DownloadEvent::ProgressUpdate {
+            id: DownloadId(3),
+            downloaded: 250,
+            segments: vec![SegmentState::new(0, ByteRange::new(0, 500))],
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "progress_update");
+        assert_eq!(json["id"], 3);
+        assert_eq!(json["segments"][0]["range"]["end"], 500);
+
+        let state = r#"{"type":"state_change","id":3,"state":"Paused"}"#;
+        let parsed: DownloadEvent = serde_json::from_str(state).unwrap();
+        assert!(matches!(
+            parsed,
+            DownloadEvent::StateChange {
+                id: DownloadId(3),
+                state: DownloadState::Paused
+            }
+        ));
+
+        let command = serde_json::to_value(OrchestratorCommand::PauseDownload(DownloadId(3)));
+        assert_eq!(command.unwrap(), serde_json::json!({ "pause_download": 3 }));
+    }
+}
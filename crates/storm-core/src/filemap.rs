@@ -0,0 +1,178 @@
+use crate::{ByteRange, SegmentState, SegmentStatus};
+use serde::{Deserialize, Serialize};
+
+const MIN_BLOCK_SIZE: u64 = 64 * 1024;
+const MAX_BLOCKS: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockState {
+    Pending,
+    InFlight,
+    Written,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapCell {
+    pub written: f32,
+    pub in_flight: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMap {
+    total: u64,
+    block_size: u64,
+    written: Vec<u64>,
+    in_flight: Vec<u64>,
+}
+
+impl FileMap {
+    pub fn new(total: u64) -> Self {
+        let block_size = total
+            .div_ceil(MAX_BLOCKS)
+            .max(MIN_BLOCK_SIZE)
+            .next_power_of_two();
+        let words = total.div_ceil(block_size).div_ceil(64) as usize;
+        Self {
+            total,
+            block_size,
+            written: vec![0; words],
+            in_flight: vec![0; words],
+        }
+    }
+
+    pub fn from_segments(total: u64, segments: &[SegmentState]) -> Self {
+        let mut map = Self::new(total);
+        for segment in segments {
+            let written_end = segment.range.start + segment.downloaded.min(segment.range.len());
+            map.mark_written(ByteRange::new(segment.range.start, written_end));
+            if segment.status == SegmentStatus::Active && written_end < segment.range.end {
+                let next = (written_end + map.block_size).min(segment.range.end);
+                map.mark_in_flight(ByteRange::new(written_end, next));
+            }
+        }
+        map
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.total.div_ceil(self.block_size) as usize
+    }
+
+    pub fn mark_written(&mut self, range: ByteRange) {
+        let first = range.start.div_ceil(self.block_size);
+        let mut last = range.end / self.block_size;
+        if range.end >= self.total && self.total > 0 {
+            last = self.block_count() as u64;
+        }
+        for block in first..last {
+            set_bit(&mut self.written, block as usize);
+            clear_bit(&mut self.in_flight, block as usize);
+        }
+    }
+
+    pub fn mark_in_flight(&mut self, range: ByteRange) {
+        if range.is_empty() {
+            return;
+        }
+        let first = range.start / self.block_size;
+        let last = range.end.div_ceil(self.block_size);
+        for block in first..last {
+            if !get_bit(&self.written, block as usize) {
+                set_bit(&mut self.in_flight, block as usize);
+            }
+        }
+    }
+
+    pub fn state(&self, block: usize) -> BlockState {
+        if get_bit(&self.written, block) {
+            BlockState::Written
+        } else if get_bit(&self.in_flight, block) {
+            BlockState::InFlight
+        } else {
+            BlockState::Pending
+        }
+    }
+
+    pub fn resample(&self, view: ByteRange, cells: usize) -> Vec<MapCell> {
+        let view = ByteRange::new(view.start, view.end.min(self.total));
+        if cells == 0 || view.is_empty() {
+            return Vec::new();
+        }
+
+        (0..cells)
+            .map(|cell| {
+                let start = view.start + view.len() * cell as u64 / cells as u64;
+                let end = view.start + view.len() * (cell as u64 + 1) / cells as u64;
+                let first = (start / self.block_size) as usize;
+                let last = (end.div_ceil(self.block_size) as usize)
+                    .max(first + 1)
+                    .min(self.block_count());
+
+                let mut written = 0;
+                let mut in_flight = false;
+                for block in first..last {
+                    match self.state(block) {
+                        BlockState::Written => written += 1,
+                        BlockState::InFlight => in_flight = true,
+                        BlockState::Pending => {}
+                    }
+                }
+
+                MapCell {
+                    written: written as f32 / (last - first).max(1) as f32,
+                    in_flight,
+                }
+            })
+            .collect()
+    }
+}
+
+fn get_bit(bits: &[u64], idx: usize) -> bool {
+    bits.get(idx / 64)
+        .is_some_and(|word| word & (1 << (idx % 64)) != 0)
+}
+
+fn set_bit(bits: &mut [u64], idx: usize) {
+    if let Some(word) = bits.get_mut(idx / 64) {
+        *word |= 1 << (idx % 64);
+    }
+}
+
+fn clear_bit(bits: &mut [u64], idx: usize) {
+    if let Some(word) = bits.get_mut(idx / 64) {
+        *word &= !(1 << (idx % 64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_map() {
+        let block = MIN_BLOCK_SIZE;
+        let mut map = FileMap::new(10 * block + 100);
+        assert_eq!(map.block_count(), 11);
+
+        map.mark_written(ByteRange::new(0, 3 * block + 10));
+        map.mark_in_flight(ByteRange::new(3 * block + 10, 4 * block));
+        map.mark_written(ByteRange::new(9 * block, 10 * block + 100));
+
+        assert_eq!(map.state(2), BlockState::Written);
+        assert_eq!(map.state(3), BlockState::InFlight);
+        assert_eq!(map.state(4), BlockState::Pending);
+        assert_eq!(map.state(10), BlockState::Written);
+
+        let cells = map.resample(ByteRange::new(0, 4 * block), 2);
+        assert_eq!(cells[0].written, 1.0);
+        assert_eq!(cells[1].written, 0.5);
+        assert!(cells[1].in_flight);
+    }
+}
@@ -1,9 +1,31 @@
+mod duration;
 mod error;
+mod events;
+mod filemap;
+mod links;
 mod mirror;
+mod pacer;
+mod policy;
+mod quota;
+mod ranges;
+mod restrict;
+mod retry;
+mod setup;
 mod traits;
 mod types;
 
+pub use duration::*;
 pub use error::*;
+pub use events::*;
+pub use filemap::*;
+pub use links::*;
 pub use mirror::*;
+pub use pacer::*;
+pub use policy::*;
+pub use quota::*;
+pub use ranges::*;
+pub use restrict::*;
+pub use retry::*;
+pub use setup::*;
 pub use traits::*;
 pub use types::*;
@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+const PAGE_EXTENSIONS: &[&str] = &[
+    "htm", "html", "xhtml", "php", "asp", "aspx", "jsp", "cgi", "shtml",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageLink {
+    pub url: Url,
+    pub filename: String,
+    pub size: Option<u64>,
+}
+
+impl PageLink {
+    pub fn new(url: Url) -> Self {
+        let filename = url
+            .path_segments()
+            .and_then(|mut s| s.next_back())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                urlencoding::decode(s)
+                    .map(|d| d.into_owned())
+                    .unwrap_or_else(|_| s.to_string())
+            })
+            .unwrap_or_else(|| "download".to_string());
+        Self {
+            url,
+            filename,
+            size: None,
+        }
+    }
+
+    pub fn extension(&self) -> Option<String> {
+        let (_, ext) = self.filename.rsplit_once('.')?;
+        (!ext.is_empty()).then(|| ext.to_ascii_lowercase())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkFilter {
+    pub extensions: Vec<String>,
+    pub min_size: Option<u64>,
+}
+
+impl LinkFilter {
+    pub fn with_extensions(mut self, list: &str) -> Self {
+        self.extensions = list
+            .split([',', ' '])
+            .map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn with_min_size(mut self, min_size: Option<u64>) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn matches(&self, link: &PageLink) -> bool {
+        let ext_ok = self.extensions.is_empty()
+            || link
+                .extension()
+                .is_some_and(|ext| self.extensions.contains(&ext));
+        let size_ok = match (self.min_size, link.size) {
+            (Some(min), Some(size)) => size >= min,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        ext_ok && size_ok
+    }
+}
+
+pub fn extract_links(html: &str, base: &Url) -> Vec<PageLink> {
+    let lower = html.to_ascii_lowercase();
+    let mut links: Vec<PageLink> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(found) = next_attribute(&lower, pos) {
+        let (value, end) = attribute_value(html, found);
+        pos = end;
+
+        let value = value.trim().replace("&amp;", "&");
+        if value.is_empty() || value.starts_with('#') {
+            continue;
+        }
+        let Ok(mut url) = base.join(&value) else {
+            continue;
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            continue;
+        }
+        url.set_fragment(None);
+
+        let link = PageLink::new(url);
+        let downloadable = link
+            .extension()
+            .is_some_and(|ext| !PAGE_EXTENSIONS.contains(&ext.as_str()));
+        if downloadable && !links.iter().any(|l| l.url == link.url) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+const SCRIPT_REDIRECTS: &[&str] = &[
+    "location.href",
+    "location.replace",
+    "location.assign",
+    "window.location",
+    "document.location",
+];
+
+pub fn find_redirect(html: &str, base: &Url, expected: Option<&str>) -> Option<Url> {
+    let resolve = |target: &str| {
+        let target = target.trim().replace("&amp;", "&");
+        base.join(&target)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .filter(|url| url != base)
+    };
+    let lower = html.to_ascii_lowercase();
+
+    if let Some(target) = meta_refresh(html, &lower).and_then(resolve) {
+        return Some(target);
+    }
+
+    let location = html.lines().find_map(|line| {
+        let line = line.trim();
+        line.get(..9)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("location:"))
+            .map(|_| &line[9..])
+    });
+    if let Some(target) = location.and_then(resolve) {
+        return Some(target);
+    }
+
+    if let Some(target) = script_redirect(html, &lower).and_then(resolve) {
+        return Some(target);
+    }
+
+    let expected = PageLink::new(base.join(expected?).ok()?);
+    let links = extract_links(html, base);
+    if let Some(link) = links
+        .iter()
+        .find(|l| l.filename.eq_ignore_ascii_case(&expected.filename))
+    {
+        return Some(link.url.clone());
+    }
+    let mut same_type = links
+        .iter()
+        .filter(|l| l.extension().is_some() && l.extension() == expected.extension());
+    match (same_type.next(), same_type.next()) {
+        (Some(link), None) => Some(link.url.clone()),
+        _ => None,
+    }
+}
+
+fn meta_refresh<'a>(html: &'a str, lower: &str) -> Option<&'a str> {
+    let mut pos = 0;
+    while let Some(idx) = lower[pos..].find("<meta") {
+        let start = pos + idx;
+        let end = lower[start..].find('>').map_or(lower.len(), |i| start + i);
+        pos = end;
+
+        let tag = &lower[..end];
+        let refresh = find_attribute(tag, start, "http-equiv")
+            .map(|at| attribute_value(tag, at).0)
+            .is_some_and(|v| v.trim() == "refresh");
+        let Some(at) = find_attribute(tag, start, "content").filter(|_| refresh) else {
+            continue;
+        };
+        let (content, _) = attribute_value(&html[..end], at);
+        let Some((_, target)) = content.split_once(';') else {
+            continue;
+        };
+        let target = target.trim_start();
+        let target = match target.get(..3) {
+            Some(key) if key.eq_ignore_ascii_case("url") => target[3..].trim_start(),
+            _ => target,
+        };
+        let target = target.strip_prefix('=').unwrap_or(target).trim();
+        return Some(target.trim_matches(|c| c == '"' || c == '\''));
+    }
+    None
+}
+
+fn script_redirect<'a>(html: &'a str, lower: &str) -> Option<&'a str> {
+    SCRIPT_REDIRECTS.iter().find_map(|pattern| {
+        lower.match_indices(pattern).find_map(|(idx, _)| {
+            let rest = html[idx + pattern.len()..].trim_start();
+            let rest = rest
+                .strip_prefix('=')
+                .or_else(|| rest.strip_prefix('('))?
+                .trim_start();
+            let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+            let body = &rest[1..];
+            body.find(quote).map(|len| &body[..len])
+        })
+    })
+}
+
+fn next_attribute(lower: &str, from: usize) -> Option<usize> {
+    ["href", "src"]
+        .iter()
+        .filter_map(|name| find_attribute(lower, from, name))
+        .min()
+}
+
+fn find_attribute(lower: &str, from: usize, name: &str) -> Option<usize> {
+    let mut at = from;
+    while let Some(idx) = lower[at..].find(name) {
+        let start = at + idx;
+        let before = lower[..start].chars().next_back();
+        let after = lower[start + name.len()..].trim_start();
+        if before.is_some_and(char::is_whitespace) && after.starts_with('=') {
+            return Some(lower.len() - after.len() + 1);
+        }
+        at = start + name.len();
+    }
+    None
+}
+
+fn attribute_value(html: &str, at: usize) -> (&str, usize) {
+    let rest = &html[at..];
+    let value = rest.trim_start();
+    let start = at + rest.len() - value.len();
+
+    match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let body = &value[1..];
+            let len = body.find(quote).unwrap_or(body.len());
+            (&body[..len], start + 1 + len)
+        }
+        _ => {
+            let len = value
+                .find(|c: char| c.is_whitespace() || c == '>')
+                .unwrap_or(value.len());
+            (&value[..len], start + len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_and_filter_links() {
+        let base = Url::parse("https://example.com/releases/").unwrap();
+        let html = r#"<html><body>
+            <a href="v1.0/app.zip">zip</a>
+            <A HREF='/files/disk%20image.ISO#top'>iso</A>
+            <a href=notes.txt>notes</a>
+            <a href="other.html">page</a>
+            <a href="mailto:me@example.com">mail</a>
+            <img src="https://cdn.example.com/logo.png">
+            <a href="v1.0/app.zip">duplicate</a>
+            <a data-href="ignored.zip">x</a>
+        </body></html>"#;
+
+        let links = extract_links(html, &base);
+        let urls: Vec<&str> = links.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/releases/v1.0/app.zip",
+                "https://example.com/files/disk%20image.ISO",
+                "https://example.com/releases/notes.txt",
+                "https://cdn.example.com/logo.png",
+            ]
+        );
+        assert_eq!(links[1].filename, "disk image.ISO");
+
+        let filter = LinkFilter::default().with_extensions(".zip, iso");
+        let kept: Vec<_> = links.iter().filter(|l| filter.matches(l)).collect();
+        assert_eq!(kept.len(), 2);
+
+        let mut sized = links[0].clone();
+        let filter = filter.with_min_size(Some(1024));
+        assert!(!filter.matches(&sized));
+        sized.size = Some(4096);
+        assert!(filter.matches(&sized));
+    }
+
+    #[test]
+    fn test_find_redirect() {
+        let base = Url::parse("https://example.com/get/ubuntu.iso").unwrap();
+        let find = |html: &str| find_redirect(html, &base, Some("ubuntu.iso")).map(String::from);
+
+        assert_eq!(
+            find(
+                r#"<META HTTP-EQUIV="Refresh" CONTENT="3; URL='/mirror/ubuntu.iso?a=1&amp;b=2'">"#
+            )
+            .as_deref(),
+            Some("https://example.com/mirror/ubuntu.iso?a=1&b=2")
+        );
+        assert_eq!(
+            find("<meta http-equiv=refresh content=\"0;url=https://cdn.example.net/u.iso\">")
+                .as_deref(),
+            Some("https://cdn.example.net/u.iso")
+        );
+        assert_eq!(
+            find("Moved.\nLocation: https://dl.example.com/ubuntu.iso\n").as_deref(),
+            Some("https://dl.example.com/ubuntu.iso")
+        );
+        assert_eq!(
+            find(r#"<script>window.location.href = "/dl/42";</script>"#).as_deref(),
+            Some("https://example.com/dl/42")
+        );
+        assert_eq!(
+            find(r#"<a href="/notes.txt">notes</a> <a href="/files/UBUNTU.iso">get</a>"#)
+                .as_deref(),
+            Some("https://example.com/files/UBUNTU.iso")
+        );
+        assert_eq!(
+            find(r#"<a href="/a/ubuntu-24.04.iso">x</a>"#).as_deref(),
+            Some("https://example.com/a/ubuntu-24.04.iso")
+        );
+        assert_eq!(
+            find(r#"<a href="/a/one.iso">1</a> <a href="/a/two.iso">2</a>"#),
+            None
+        );
+        assert_eq!(find(r#"<meta http-equiv="refresh" content="30">"#), None);
+        assert_eq!(
+            find_redirect(r#"<a href="/a/one.iso">1</a>"#, &base, None),
+            None
+        );
+    }
+}
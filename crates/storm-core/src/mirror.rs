@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 use url::Url;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -59,6 +60,7 @@ pub struct MirrorStats {
     pub errors: usize,
     pub avg_speed: f64,
     pub active_segments: usize,
+    pub avg_ttfb: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
@@ -120,6 +122,10 @@ impl MirrorSet {
             let speed = stats.map(|s| s.avg_speed).unwrap_or(0.0);
             let errors = stats.map(|s| s.errors).unwrap_or(0);
             let active = stats.map(|s| s.active_segments).unwrap_or(0);
+            let ttfb = stats
+                .and_then(|s| s.avg_ttfb)
+                .map(|t| t.as_secs_f64())
+                .unwrap_or(0.0);
 
             let priority_boost = match mirror.priority {
                 MirrorPriority::Primary => 1.5,
@@ -129,8 +135,9 @@ impl MirrorSet {
 
             let error_penalty = 1.0 / (1.0 + errors as f64 * 0.5);
             let load_factor = 1.0 / (1.0 + active as f64 * 0.1);
+            let ttfb_penalty = 1.0 / (1.0 + ttfb);
 
-            let score = speed * priority_boost * error_penalty * load_factor;
+            let score = speed * priority_boost * error_penalty * load_factor * ttfb_penalty;
 
             if score > best_score {
                 best_score = score;
@@ -141,8 +148,21 @@ impl MirrorSet {
         best_idx
     }
 
-    pub fn select_for_segment(&self, _segment_idx: usize) -> usize {
-        self.best_mirror()
+    // Mirrors that haven't moved a byte or failed yet take segments in turn,
+    // so each has a measured speed before the best one is favoured.
+    pub fn select_for_segment(&self, segment_idx: usize) -> usize {
+        let untried: Vec<usize> = (0..self.mirrors.len())
+            .filter(|idx| {
+                self.stats
+                    .get(idx)
+                    .is_none_or(|s| s.bytes_downloaded == 0 && s.errors == 0)
+            })
+            .collect();
+        if untried.is_empty() {
+            self.best_mirror()
+        } else {
+            untried[segment_idx % untried.len()]
+        }
     }
 }
 
@@ -165,3 +185,28 @@ impl From<Vec<Url>> for MirrorSet {
         set
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_tries_every_mirror_first() {
+        let url = |host: &str| Url::parse(&format!("https://{}/a.iso", host)).unwrap();
+        let mut set = MirrorSet::from(vec![url("a"), url("b"), url("c")]);
+        let picks: Vec<usize> = (0..4).map(|i| set.select_for_segment(i)).collect();
+        assert_eq!(picks, [0, 1, 2, 0]);
+
+        let measured = |speed, errors| MirrorStats {
+            bytes_downloaded: if errors == 0 { 1 << 20 } else { 0 },
+            errors,
+            avg_speed: speed,
+            ..Default::default()
+        };
+        set.update_stats(0, measured(1e6, 0));
+        set.update_stats(2, measured(0.0, 3));
+        assert_eq!(set.select_for_segment(0), 1);
+        set.update_stats(1, measured(5e6, 0));
+        assert_eq!(set.select_for_segment(0), 1);
+    }
+}
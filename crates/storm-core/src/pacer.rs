@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+const STABLE_TOLERANCE: f64 = 0.1;
+const STABLE_SAMPLES: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressPacer {
+    base: Duration,
+    max: Duration,
+    current: Duration,
+    last_speed: f64,
+    stable: u32,
+}
+
+impl ProgressPacer {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        let base = base.max(Duration::from_millis(10));
+        Self {
+            base,
+            max: max.max(base),
+            current: base,
+            last_speed: 0.0,
+            stable: 0,
+        }
+    }
+
+    pub fn low_power(mut self) -> Self {
+        self.base = self.max;
+        self.current = self.max;
+        self
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.current
+    }
+
+    pub fn max_interval(&self) -> Duration {
+        self.max
+    }
+
+    pub fn observe(&mut self, speed: f64) -> Duration {
+        let steady = self.last_speed > 0.0
+            && ((speed - self.last_speed) / self.last_speed).abs() <= STABLE_TOLERANCE;
+        self.last_speed = speed;
+
+        if !steady {
+            self.stable = 0;
+            self.current = self.base;
+        } else {
+            self.stable += 1;
+            if self.stable >= STABLE_SAMPLES {
+                self.stable = 0;
+                self.current = (self.current * 2).min(self.max);
+            }
+        }
+        self.current
+    }
+}
+
+impl Default for ProgressPacer {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer_backs_off_when_stable() {
+        let mut pacer = ProgressPacer::default();
+        let intervals: Vec<_> = (0..16).map(|_| pacer.observe(1000.0)).collect();
+        assert_eq!(intervals[0], Duration::from_millis(100));
+        assert_eq!(intervals[5], Duration::from_millis(200));
+        assert_eq!(*intervals.last().unwrap(), Duration::from_millis(800));
+
+        assert_eq!(pacer.observe(3000.0), Duration::from_millis(100));
+        assert_eq!(
+            ProgressPacer::default().low_power().observe(0.0),
+            Duration::from_secs(2)
+        );
+    }
+}
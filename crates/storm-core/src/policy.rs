@@ -0,0 +1,217 @@
+use crate::{ResourceInfo, StormError};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostPolicy {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl HostPolicy {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        let normalize = |patterns: Vec<String>| {
+            patterns
+                .into_iter()
+                .map(|p| p.trim().trim_end_matches('.').to_ascii_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect()
+        };
+        Self {
+            allow: normalize(allow),
+            deny: normalize(deny),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn deny(&self) -> &[String] {
+        &self.deny
+    }
+
+    pub fn allows_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if self.deny.iter().any(|p| host_matches(p, &host)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|p| host_matches(p, &host))
+    }
+
+    pub fn check(&self, url: &Url) -> Result<(), StormError> {
+        match url.host_str() {
+            Some(host) if self.allows_host(host) => Ok(()),
+            Some(host) => Err(StormError::HostBlocked(host.to_string())),
+            None => Err(StormError::HostBlocked(url.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentPolicy {
+    pub max_size: Option<u64>,
+    pub accept_types: Vec<String>,
+}
+
+impl ContentPolicy {
+    pub fn new(max_size: Option<u64>, accept_types: Vec<String>) -> Self {
+        Self {
+            max_size,
+            accept_types: accept_types
+                .into_iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn check_size(&self, size: u64) -> Result<(), StormError> {
+        match self.max_size {
+            Some(limit) if size > limit => Err(StormError::TooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_type(&self, content_type: Option<&str>) -> Result<(), StormError> {
+        if self.accept_types.is_empty() {
+            return Ok(());
+        }
+        let mime = content_type
+            .and_then(|t| t.split(';').next())
+            .map(|t| t.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        if self.accept_types.iter().any(|p| mime_matches(p, &mime)) {
+            Ok(())
+        } else if mime.is_empty() {
+            Err(StormError::ContentTypeRejected("(none)".to_string()))
+        } else {
+            Err(StormError::ContentTypeRejected(mime))
+        }
+    }
+
+    pub fn check(&self, info: &ResourceInfo) -> Result<(), StormError> {
+        if let Some(size) = info.size {
+            self.check_size(size)?;
+        }
+        self.check_type(info.content_type.as_deref())
+    }
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some("*") => !mime.is_empty(),
+        Some(major) => mime
+            .strip_prefix(major)
+            .is_some_and(|rest| rest.starts_with('/')),
+        None => pattern == mime,
+    }
+}
+
+const BINARY_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "appimage", "bin", "bz2", "deb", "dmg", "exe", "gz", "img", "iso", "jar", "mkv",
+    "mov", "mp3", "mp4", "msi", "parquet", "pdf", "pkg", "rar", "rpm", "tar", "tgz", "whl", "xz",
+    "zip", "zst",
+];
+
+pub fn expects_binary(name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, ext)| {
+        BINARY_EXTENSIONS
+            .iter()
+            .any(|b| b.eq_ignore_ascii_case(ext))
+    })
+}
+
+pub fn looks_like_html(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    let head: Vec<u8> = data[start..]
+        .iter()
+        .take(14)
+        .map(|b| b.to_ascii_lowercase())
+        .collect();
+    head.starts_with(b"<!doctype html") || head.starts_with(b"<html")
+}
+
+pub fn check_error_page(name: &str, content_type: Option<&str>) -> Result<(), StormError> {
+    let html = content_type
+        .and_then(|t| t.split(';').next())
+        .is_some_and(|t| t.trim().eq_ignore_ascii_case("text/html"));
+    if html && expects_binary(name) {
+        Err(StormError::UnexpectedHtml(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+// `*` matches every host and `*.example.com` any subdomain of example.com;
+// both sides are expected in lower case.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_policy() {
+        let policy = HostPolicy::new(
+            vec!["*.example.com".into(), "artifacts.internal".into()],
+            vec!["evil.example.com".into()],
+        );
+
+        let check = |u: &str| policy.check(&Url::parse(u).unwrap()).is_ok();
+        assert!(check("https://cdn.example.com/file"));
+        assert!(check("https://ARTIFACTS.internal/x"));
+        assert!(!check("https://example.com/file"));
+        assert!(!check("https://evil.example.com/file"));
+        assert!(!check("https://notexample.com/file"));
+
+        let open = HostPolicy::new(vec![], vec!["*.tracker.net".into()]);
+        assert!(open.allows_host("mirror.org"));
+        assert!(!open.allows_host("ads.tracker.net"));
+    }
+
+    #[test]
+    fn test_content_policy() {
+        let policy =
+            ContentPolicy::new(Some(1000), vec!["application/zip".into(), "image/*".into()]);
+        assert!(policy.check_size(1000).is_ok());
+        assert!(matches!(
+            policy.check_size(1001),
+            Err(StormError::TooLarge {
+                size: 1001,
+                limit: 1000
+            })
+        ));
+
+        assert!(policy.check_type(Some("application/zip")).is_ok());
+        assert!(policy.check_type(Some("Image/PNG; charset=binary")).is_ok());
+        assert!(policy.check_type(Some("text/html; charset=utf-8")).is_err());
+        assert!(policy.check_type(None).is_err());
+        assert!(ContentPolicy::default().check_type(None).is_ok());
+    }
+
+    #[test]
+    fn test_error_page_detection() {
+        assert!(check_error_page("ubuntu.ISO", Some("text/html; charset=utf-8")).is_err());
+        assert!(check_error_page("ubuntu.iso", Some("application/octet-stream")).is_ok());
+        assert!(check_error_page("index.html", Some("text/html")).is_ok());
+
+        assert!(looks_like_html(b"\xEF\xBB\xBF\n  <!DOCTYPE html><html>"));
+        assert!(looks_like_html(b"<HTML><head>"));
+        assert!(!looks_like_html(b"PK\x03\x04"));
+    }
+}
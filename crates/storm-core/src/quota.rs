@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaLevel {
+    Ok,
+    Warning,
+    Exceeded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MonthlyQuota {
+    pub limit: Option<u64>,
+    pub warn_fraction: f64,
+    pub hard_stop: bool,
+}
+
+impl MonthlyQuota {
+    pub fn new(limit: Option<u64>, warn_fraction: f64, hard_stop: bool) -> Self {
+        Self {
+            limit: limit.filter(|&l| l > 0),
+            warn_fraction: warn_fraction.clamp(0.0, 1.0),
+            hard_stop,
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None, 0.8, false)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    pub fn level(&self, used: u64) -> QuotaLevel {
+        match self.limit {
+            Some(limit) if used >= limit => QuotaLevel::Exceeded,
+            Some(limit) if used as f64 >= limit as f64 * self.warn_fraction => QuotaLevel::Warning,
+            _ => QuotaLevel::Ok,
+        }
+    }
+
+    pub fn remaining(&self, used: u64) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(used))
+    }
+
+    pub fn blocks(&self, used: u64, incoming: u64) -> bool {
+        self.hard_stop
+            && self
+                .remaining(used)
+                .is_some_and(|remaining| remaining == 0 || incoming > remaining)
+    }
+}
+
+impl Default for MonthlyQuota {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_levels() {
+        let quota = MonthlyQuota::new(Some(1000), 0.8, true);
+        assert_eq!(quota.level(100), QuotaLevel::Ok);
+        assert_eq!(quota.level(800), QuotaLevel::Warning);
+        assert_eq!(quota.level(1000), QuotaLevel::Exceeded);
+
+        assert!(!quota.blocks(100, 500));
+        assert!(quota.blocks(600, 500));
+
+        let unlimited = MonthlyQuota::new(Some(0), 0.8, true);
+        assert!(!unlimited.is_enabled());
+        assert!(!unlimited.blocks(u64::MAX, 1));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::ByteRange;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<ByteRange>", into = "Vec<ByteRange>")]
+pub struct RangeSet {
+    ranges: Vec<ByteRange>,
+}
+
+impl RangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, range: ByteRange) {
+        if range.is_empty() {
+            return;
+        }
+        let first = self.ranges.partition_point(|r| r.end < range.start);
+        let last = self.ranges.partition_point(|r| r.start <= range.end);
+        let merged = self.ranges[first..last].iter().fold(range, |acc, r| {
+            ByteRange::new(acc.start.min(r.start), acc.end.max(r.end))
+        });
+        self.ranges.splice(first..last, [merged]);
+    }
+
+    pub fn remove(&mut self, range: ByteRange) {
+        if range.is_empty() {
+            return;
+        }
+        let first = self.ranges.partition_point(|r| r.end <= range.start);
+        let last = self.ranges.partition_point(|r| r.start < range.end);
+        if first >= last {
+            return;
+        }
+        let head = ByteRange::new(self.ranges[first].start, range.start);
+        let tail = ByteRange::new(range.end, self.ranges[last - 1].end);
+        let kept = [head, tail].into_iter().filter(|r| r.start < r.end);
+        self.ranges.splice(first..last, kept);
+    }
+
+    pub fn union(&mut self, other: &RangeSet) {
+        for range in other.iter() {
+            self.insert(range);
+        }
+    }
+
+    pub fn subtract(&mut self, other: &RangeSet) {
+        for range in other.iter() {
+            self.remove(range);
+        }
+    }
+
+    pub fn contains(&self, range: ByteRange) -> bool {
+        range.is_empty() || self.covered_in(range) == range.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ByteRange> + '_ {
+        self.ranges.iter().copied()
+    }
+
+    pub fn ranges(&self) -> &[ByteRange] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn covered(&self) -> u64 {
+        self.ranges.iter().map(|r| r.len()).sum()
+    }
+
+    pub fn covered_in(&self, range: ByteRange) -> u64 {
+        self.ranges
+            .iter()
+            .map(|r| {
+                r.end
+                    .min(range.end)
+                    .saturating_sub(r.start.max(range.start))
+            })
+            .sum()
+    }
+
+    pub fn gaps(&self, within: ByteRange) -> Vec<ByteRange> {
+        let mut gaps = Vec::new();
+        let mut cursor = within.start;
+        for r in &self.ranges {
+            if r.end <= cursor {
+                continue;
+            }
+            if r.start >= within.end {
+                break;
+            }
+            if r.start > cursor {
+                gaps.push(ByteRange::new(cursor, r.start));
+            }
+            cursor = r.end;
+        }
+        if cursor < within.end {
+            gaps.push(ByteRange::new(cursor, within.end));
+        }
+        gaps
+    }
+}
+
+impl FromIterator<ByteRange> for RangeSet {
+    fn from_iter<I: IntoIterator<Item = ByteRange>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for range in iter {
+            set.insert(range);
+        }
+        set
+    }
+}
+
+impl From<Vec<ByteRange>> for RangeSet {
+    fn from(ranges: Vec<ByteRange>) -> Self {
+        ranges.into_iter().collect()
+    }
+}
+
+impl From<RangeSet> for Vec<ByteRange> {
+    fn from(set: RangeSet) -> Self {
+        set.ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_merges_and_gaps() {
+        let mut set = RangeSet::new();
+        set.insert(ByteRange::new(10, 20));
+        set.insert(ByteRange::new(40, 50));
+        set.insert(ByteRange::new(20, 25));
+        set.insert(ByteRange::new(0, 0));
+        assert_eq!(
+            set.ranges(),
+            [ByteRange::new(10, 25), ByteRange::new(40, 50)]
+        );
+
+        set.insert(ByteRange::new(5, 45));
+        assert_eq!(set.ranges(), [ByteRange::new(5, 50)]);
+        assert_eq!(set.covered(), 45);
+        assert_eq!(set.covered_in(ByteRange::new(0, 10)), 5);
+
+        set.insert(ByteRange::new(70, 80));
+        assert_eq!(
+            set.gaps(ByteRange::new(0, 100)),
+            [
+                ByteRange::new(0, 5),
+                ByteRange::new(50, 70),
+                ByteRange::new(80, 100)
+            ]
+        );
+        assert_eq!(set.gaps(ByteRange::new(60, 75)), [ByteRange::new(60, 70)]);
+        assert!(set.gaps(ByteRange::new(10, 40)).is_empty());
+    }
+
+    #[test]
+    fn test_remove_subtract_and_serde() {
+        let mut set: RangeSet = [ByteRange::new(0, 100), ByteRange::new(200, 300)]
+            .into_iter()
+            .collect();
+        set.remove(ByteRange::new(50, 250));
+        assert_eq!(
+            set.ranges(),
+            [ByteRange::new(0, 50), ByteRange::new(250, 300)]
+        );
+        set.remove(ByteRange::new(100, 200));
+        set.remove(ByteRange::new(0, 10));
+        assert_eq!(
+            set.ranges(),
+            [ByteRange::new(10, 50), ByteRange::new(250, 300)]
+        );
+        assert!(set.contains(ByteRange::new(20, 40)));
+        assert!(!set.contains(ByteRange::new(40, 260)));
+
+        let mut other = RangeSet::new();
+        other.insert(ByteRange::new(40, 260));
+        set.union(&other);
+        assert_eq!(set.ranges(), [ByteRange::new(10, 300)]);
+        set.subtract(&other);
+        assert_eq!(
+            set.ranges(),
+            [ByteRange::new(10, 40), ByteRange::new(260, 300)]
+        );
+
+        let json = serde_json::to_string(&set).unwrap();
+        let unsorted: RangeSet = serde_json::from_str(
+            r#"[{"start":260,"end":300},{"start":10,"end":30},{"start":25,"end":40}]"#,
+        )
+        .unwrap();
+        assert_eq!(unsorted, set);
+        assert_eq!(serde_json::from_str::<RangeSet>(&json).unwrap(), set);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{HostPolicy, StormError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+// A daily `HH:MM-HH:MM` window in local time; the end is exclusive and a
+// window ending before it starts runs past midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    start: u32,
+    end: u32,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, minute: u32) -> bool {
+        let minute = minute % MINUTES_PER_DAY;
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = StormError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || StormError::Config(format!("invalid window '{}' (e.g. 07:00-21:30)", s));
+        let clock = |t: &str| -> Option<u32> {
+            let (h, m) = t.trim().split_once(':')?;
+            let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+            (h <= 24 && m < 60 && h * 60 + m <= MINUTES_PER_DAY).then_some(h * 60 + m)
+        };
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let (start, end) = (
+            clock(start).ok_or_else(invalid)?,
+            clock(end).ok_or_else(invalid)?,
+        );
+        if start == end {
+            return Err(invalid());
+        }
+        Ok(Self {
+            start: start % MINUTES_PER_DAY,
+            end,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = StormError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        window.to_string()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+// Limits a locked profile puts on every download.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Restrictions {
+    pub allow_hosts: Vec<String>,
+    pub max_speed: Option<u64>,
+    pub windows: Vec<TimeWindow>,
+}
+
+impl Restrictions {
+    pub fn is_empty(&self) -> bool {
+        self.allow_hosts.is_empty() && self.max_speed.is_none() && self.windows.is_empty()
+    }
+
+    // The locked allowlist replaces any configured one; configured denies
+    // still apply.
+    pub fn host_policy(&self, configured: Option<HostPolicy>) -> Option<HostPolicy> {
+        if self.allow_hosts.is_empty() {
+            return configured;
+        }
+        let deny = configured.map(|p| p.deny().to_vec()).unwrap_or_default();
+        Some(HostPolicy::new(self.allow_hosts.clone(), deny))
+    }
+
+    pub fn cap(&self, limit: Option<u64>) -> Option<u64> {
+        match (limit, self.max_speed) {
+            (Some(limit), Some(cap)) => Some(limit.min(cap)),
+            (limit, cap) => limit.or(cap),
+        }
+    }
+
+    pub fn check_time(&self, minute: u32) -> Result<(), StormError> {
+        if self.windows.is_empty() || self.windows.iter().any(|w| w.contains(minute)) {
+            return Ok(());
+        }
+        let windows: Vec<String> = self.windows.iter().map(|w| w.to_string()).collect();
+        Err(StormError::Restricted(format!(
+            "downloads are only allowed during {}",
+            windows.join(", ")
+        )))
+    }
+
+    // Minutes from `minute` until the allowed time runs out, following
+    // back-to-back windows; `None` when it never does.
+    pub fn open_for(&self, minute: u32) -> Option<u32> {
+        if self.windows.is_empty() {
+            return None;
+        }
+        (0..=MINUTES_PER_DAY).find(|&ahead| self.check_time(minute + ahead).is_err())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_and_caps() {
+        let evening: TimeWindow = "18:00-01:30".parse().unwrap();
+        assert!(evening.contains(23 * 60) && evening.contains(60) && !evening.contains(90));
+        assert_eq!(evening.to_string(), "18:00-01:30");
+        assert!("07:00-07:00".parse::<TimeWindow>().is_err());
+        assert!("25:00-26:00".parse::<TimeWindow>().is_err());
+        assert_eq!(
+            "24:00-08:00".parse::<TimeWindow>().unwrap().to_string(),
+            "00:00-08:00"
+        );
+        let all_day: TimeWindow = "00:00-24:00".parse().unwrap();
+        assert!(all_day.contains(0) && all_day.contains(MINUTES_PER_DAY - 1));
+
+        let restrictions = Restrictions {
+            allow_hosts: vec!["*.wikipedia.org".into()],
+            max_speed: Some(1_000_000),
+            windows: vec!["07:00-12:00".parse().unwrap(), evening],
+        };
+        assert!(restrictions.check_time(7 * 60).is_ok());
+        assert!(matches!(
+            restrictions.check_time(14 * 60),
+            Err(StormError::Restricted(_))
+        ));
+        assert_eq!(restrictions.open_for(11 * 60 + 50), Some(10));
+        assert_eq!(restrictions.open_for(14 * 60), Some(0));
+        assert_eq!(restrictions.open_for(23 * 60), Some(150));
+        assert_eq!(Restrictions::default().open_for(0), None);
+
+        assert_eq!(restrictions.cap(None), Some(1_000_000));
+        assert_eq!(restrictions.cap(Some(5_000)), Some(5_000));
+        assert_eq!(restrictions.cap(Some(5_000_000)), Some(1_000_000));
+
+        let configured =
+            HostPolicy::new(vec!["example.com".into()], vec!["bad.wikipedia.org".into()]);
+        let policy = restrictions.host_policy(Some(configured)).unwrap();
+        assert!(policy.allows_host("en.wikipedia.org"));
+        assert!(!policy.allows_host("bad.wikipedia.org") && !policy.allows_host("example.com"));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::StormError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const BASE_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+const READY_WAIT: Duration = Duration::from_secs(15 * 60);
+// How often a file that isn't ready is polled when the server doesn't say,
+// and the bounds put on what it does say.
+const READY_POLL: Duration = Duration::from_secs(10);
+const MIN_READY_POLL: Duration = Duration::from_secs(1);
+const MAX_READY_POLL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Escalation {
+    SwitchMirror,
+    SingleConnection,
+}
+
+impl FromStr for Escalation {
+    type Err = StormError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "switch-mirror" | "mirror" => Ok(Self::SwitchMirror),
+            "single-connection" | "single" => Ok(Self::SingleConnection),
+            other => Err(StormError::Config(format!(
+                "Unknown escalation step '{}' (expected switch-mirror or single-connection)",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_total_retries: u32,
+    pub max_segment_retries: u32,
+    pub escalation: Vec<Escalation>,
+    // A fixed wait before each retry (curl's --retry-delay) instead of
+    // exponential backoff.
+    #[serde(default)]
+    pub delay: Option<Duration>,
+    // How long to keep polling a file the server is still preparing.
+    #[serde(default = "default_ready_wait")]
+    pub ready_wait: Duration,
+}
+
+fn default_ready_wait() -> Duration {
+    READY_WAIT
+}
+
+impl RetryPolicy {
+    pub fn new(max_total_retries: u32, max_segment_retries: u32) -> Self {
+        Self {
+            max_total_retries,
+            max_segment_retries,
+            escalation: vec![Escalation::SwitchMirror, Escalation::SingleConnection],
+            delay: None,
+            ready_wait: READY_WAIT,
+        }
+    }
+
+    pub fn with_escalation(mut self, escalation: Vec<Escalation>) -> Self {
+        self.escalation = escalation;
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Option<Duration>) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn with_ready_wait(mut self, ready_wait: Duration) -> Self {
+        self.ready_wait = ready_wait;
+        self
+    }
+
+    // How long to wait before polling a file that isn't ready again, going
+    // by the server's Retry-After, or None once `waited` has used up
+    // ready_wait.
+    pub fn ready_delay(&self, retry_after: Option<Duration>, waited: Duration) -> Option<Duration> {
+        let left = self
+            .ready_wait
+            .checked_sub(waited)
+            .filter(|left| !left.is_zero())?;
+        Some(
+            retry_after
+                .unwrap_or(READY_POLL)
+                .clamp(MIN_READY_POLL, MAX_READY_POLL)
+                .min(left),
+        )
+    }
+
+    pub fn backoff(attempt: u32) -> Duration {
+        BASE_BACKOFF
+            .saturating_mul(1 << attempt.min(6))
+            .min(MAX_BACKOFF)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(20, 3)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAction {
+    Retry(Duration),
+    SwitchMirror,
+    SingleConnection,
+    Fail,
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    total: u32,
+    segments: HashMap<usize, u32>,
+    stage: usize,
+    mirrors_left: usize,
+}
+
+#[derive(Debug)]
+pub struct RetryBudget {
+    policy: RetryPolicy,
+    state: Mutex<BudgetState>,
+}
+
+impl RetryBudget {
+    pub fn new(policy: RetryPolicy, mirrors: usize) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(BudgetState {
+                mirrors_left: mirrors,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    pub fn retries_used(&self) -> u32 {
+        self.state.lock().unwrap().total
+    }
+
+    pub fn on_failure(&self, segment: usize, error: &StormError) -> RetryAction {
+        if !error.is_retryable() {
+            return RetryAction::Fail;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.total += 1;
+        if state.total > self.policy.max_total_retries {
+            return RetryAction::Fail;
+        }
+
+        let attempts = state.segments.entry(segment).or_default();
+        *attempts += 1;
+        if *attempts <= self.policy.max_segment_retries {
+            let delay = self.policy.delay;
+            return RetryAction::Retry(
+                delay.unwrap_or_else(|| RetryPolicy::backoff(*attempts - 1)),
+            );
+        }
+        *attempts = 0;
+
+        while let Some(step) = self.policy.escalation.get(state.stage) {
+            match step {
+                Escalation::SwitchMirror if state.mirrors_left > 0 => {
+                    state.mirrors_left -= 1;
+                    return RetryAction::SwitchMirror;
+                }
+                Escalation::SingleConnection => {
+                    state.stage += 1;
+                    return RetryAction::SingleConnection;
+                }
+                Escalation::SwitchMirror => state.stage += 1,
+            }
+        }
+        RetryAction::Fail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_budget_escalation() {
+        let network = StormError::Network("reset".into());
+        let budget = RetryBudget::new(RetryPolicy::new(100, 1), 1);
+
+        assert!(matches!(
+            budget.on_failure(0, &network),
+            RetryAction::Retry(_)
+        ));
+        assert_eq!(budget.on_failure(0, &network), RetryAction::SwitchMirror);
+        assert!(matches!(
+            budget.on_failure(0, &network),
+            RetryAction::Retry(_)
+        ));
+        assert_eq!(
+            budget.on_failure(0, &network),
+            RetryAction::SingleConnection
+        );
+        budget.on_failure(0, &network);
+        assert_eq!(budget.on_failure(0, &network), RetryAction::Fail);
+
+        let not_found = StormError::NotFound("x".into());
+        let budget = RetryBudget::new(RetryPolicy::default(), 0);
+        assert_eq!(budget.on_failure(0, &not_found), RetryAction::Fail);
+
+        let budget = RetryBudget::new(RetryPolicy::new(2, 5).with_escalation(Vec::new()), 0);
+        budget.on_failure(0, &network);
+        budget.on_failure(1, &network);
+        assert_eq!(budget.on_failure(2, &network), RetryAction::Fail);
+
+        let delay = Duration::from_secs(30);
+        let budget = RetryBudget::new(RetryPolicy::new(5, 5).with_delay(Some(delay)), 0);
+        budget.on_failure(0, &network);
+        assert_eq!(budget.on_failure(0, &network), RetryAction::Retry(delay));
+    }
+
+    #[test]
+    fn test_ready_delay() {
+        let secs = Duration::from_secs;
+        let policy = RetryPolicy::default().with_ready_wait(secs(600));
+        assert_eq!(policy.ready_delay(None, secs(0)), Some(READY_POLL));
+        assert_eq!(policy.ready_delay(Some(secs(30)), secs(0)), Some(secs(30)));
+        assert_eq!(
+            policy.ready_delay(Some(secs(0)), secs(0)),
+            Some(MIN_READY_POLL)
+        );
+        assert_eq!(
+            policy.ready_delay(Some(secs(3600)), secs(0)),
+            Some(MAX_READY_POLL)
+        );
+        assert_eq!(
+            policy.ready_delay(Some(secs(30)), secs(590)),
+            Some(secs(10))
+        );
+        assert_eq!(policy.ready_delay(None, secs(600)), None);
+        assert_eq!(
+            RetryPolicy::default()
+                .with_ready_wait(Duration::ZERO)
+                .ready_delay(None, secs(0)),
+            None
+        );
+    }
+}
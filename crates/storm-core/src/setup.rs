@@ -0,0 +1,111 @@
+use crate::StormError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedProfile {
+    Gentle,
+    #[default]
+    Balanced,
+    Turbo,
+}
+
+impl SpeedProfile {
+    pub const ALL: [SpeedProfile; 3] = [Self::Gentle, Self::Balanced, Self::Turbo];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gentle => "gentle",
+            Self::Balanced => "balanced",
+            Self::Turbo => "turbo",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Gentle => "Few connections; friendly to shared networks and strict servers",
+            Self::Balanced => "Starts aggressive and backs off when it stops helping",
+            Self::Turbo => "Maximum connections for the whole transfer",
+        }
+    }
+
+    pub fn is_turbo(&self) -> bool {
+        *self != Self::Gentle
+    }
+
+    pub fn suggest(calibration: &Calibration) -> Self {
+        match calibration.segments {
+            0..=2 => Self::Gentle,
+            3..=8 => Self::Balanced,
+            _ => Self::Turbo,
+        }
+    }
+}
+
+impl FromStr for SpeedProfile {
+    type Err = StormError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                StormError::Config(format!(
+                    "Unknown profile '{}' (expected gentle, balanced or turbo)",
+                    s
+                ))
+            })
+    }
+}
+
+impl fmt::Display for SpeedProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub segments: usize,
+    pub speed: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetupChoices {
+    pub download_dir: PathBuf,
+    pub profile: SpeedProfile,
+    pub watch_clipboard: bool,
+    pub calibration: Option<Calibration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_parse_and_suggest() {
+        assert_eq!(
+            "Turbo".parse::<SpeedProfile>().unwrap(),
+            SpeedProfile::Turbo
+        );
+        assert!("fast".parse::<SpeedProfile>().is_err());
+        assert_eq!(
+            serde_json::to_value(SpeedProfile::Balanced).unwrap(),
+            "balanced"
+        );
+
+        let slow = Calibration {
+            segments: 1,
+            speed: 2e6,
+        };
+        let fast = Calibration {
+            segments: 16,
+            speed: 90e6,
+        };
+        assert_eq!(SpeedProfile::suggest(&slow), SpeedProfile::Gentle);
+        assert_eq!(SpeedProfile::suggest(&fast), SpeedProfile::Turbo);
+    }
+}
@@ -18,9 +18,29 @@ pub trait Downloader: Send + Sync {
     async fn fetch_full(&self, url: &Url, sink: &mut dyn DataSink) -> Result<(), StormError>;
 }
 
+#[async_trait]
 pub trait DataSink: Send {
     fn write(&mut self, data: Bytes) -> Result<(), StormError>;
     fn flush(&mut self) -> Result<(), StormError>;
+
+    // Waits until the sink will take another `len` bytes. Downloaders await
+    // it before each write, so a rate limit holds back the read instead of
+    // blocking a runtime thread inside `write`.
+    async fn ready(&mut self, _len: usize) -> Result<(), StormError> {
+        Ok(())
+    }
+}
+
+// Collects a body in memory, for small fetches and examples.
+impl DataSink for Vec<u8> {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        self.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
 }
 
 #[async_trait]
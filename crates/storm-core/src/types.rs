@@ -52,13 +52,23 @@ impl ByteRange {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum HttpVersion {
     Http1_1,
     Http2,
     Http3,
 }
 
+impl HttpVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpVersion::Http1_1 => "HTTP/1.1",
+            HttpVersion::Http2 => "HTTP/2",
+            HttpVersion::Http3 => "HTTP/3",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResourceInfo {
     pub url: Url,
@@ -81,10 +91,14 @@ pub struct ResourceInfo {
 pub enum DownloadState {
     Pending,
     Probing,
+    // Polling a server that is still preparing the file.
+    Waiting,
     Downloading,
     Paused,
+    Scanning,
     Complete,
     Failed,
+    Quarantined,
     Cancelled,
 }
 
@@ -97,7 +111,7 @@ pub enum SegmentStatus {
     Slow,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SegmentState {
     pub id: usize,
     pub range: ByteRange,
@@ -127,6 +141,15 @@ impl SegmentState {
         }
         self.downloaded as f64 / self.range.len() as f64
     }
+
+    pub fn merge(segments: &mut Vec<SegmentState>, changed: Vec<SegmentState>) {
+        for segment in changed {
+            match segments.iter_mut().find(|s| s.id == segment.id) {
+                Some(existing) => *existing = segment,
+                None => segments.push(segment),
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -149,6 +172,91 @@ pub struct DownloadOptions {
     pub bandwidth_limit: Option<u64>,
     pub headers: Vec<(String, String)>,
     pub checksum: Option<String>,
+    pub group: Option<String>,
+    #[serde(default)]
+    pub mirrors: Vec<Url>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub auth: Option<Credentials>,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Credentials {
+    Basic { user: String, password: String },
+    // Only sent in answer to the server's challenge.
+    Digest { user: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    // curl's `--user name:password`; without a colon the password is empty.
+    pub fn basic(spec: &str) -> Self {
+        let (user, password) = spec.split_once(':').unwrap_or((spec, ""));
+        Self::Basic {
+            user: user.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    pub fn digest(spec: &str) -> Self {
+        let (user, password) = spec.split_once(':').unwrap_or((spec, ""));
+        Self::Digest {
+            user: user.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+// Keeps secrets out of logs and `{:?}` output.
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Basic { user, .. } => write!(f, "Basic({}:***)", user),
+            Self::Digest { user, .. } => write!(f, "Digest({}:***)", user),
+            Self::Bearer(_) => write!(f, "Bearer(***)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+// Tags are stored comma-separated, so commas always split; blanks and
+// repeats are dropped.
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadGroup {
+    pub name: String,
+    pub output_dir: Option<PathBuf>,
+    pub bandwidth_limit: Option<u64>,
+    pub max_concurrent: Option<usize>,
+}
+
+impl DownloadGroup {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1,101 +1,196 @@
-use crate::state::{AppState, DownloadEvent, OrchestratorCommand};
+use crate::autocomplete::{Suggestion, SuggestionSource, UrlHistory, is_download_url};
+use crate::state::{
+    AppState, BulkAction, Download, DownloadEvent, LinkPicker, Onboarding, OnboardingStep,
+    OrchestratorCommand, QuotaStatus, SelectMode,
+};
 use adabraka_ui::components::button::{Button, ButtonVariant};
+use adabraka_ui::components::checkbox::Checkbox;
 use adabraka_ui::components::icon::Icon;
-use adabraka_ui::components::input::{Input, InputState};
+use adabraka_ui::components::input::{Input, InputEvent, InputState};
 use adabraka_ui::components::progress::ProgressBar;
 use adabraka_ui::components::scrollable::scrollable_vertical;
 use adabraka_ui::components::spinner::Spinner;
 use adabraka_ui::display::badge::{Badge, BadgeVariant};
 use adabraka_ui::prelude::*;
 use flume::{Receiver, Sender};
+use gpui::prelude::FluentBuilder;
 use gpui::*;
 use std::path::PathBuf;
-use stormdl_core::{DownloadOptions, DownloadState};
+use std::time::Duration;
+use stormdl_core::{
+    ArchiveEntry, ByteRange, DownloadGroup, DownloadId, DownloadOptions, DownloadState, FileMap,
+    MapCell, Priority, QuotaLevel, SetupChoices, SpeedProfile, parse_tags,
+};
+use stormdl_integrity::ChecksumSpec;
 use url::Url;
 
+#[derive(Clone)]
+struct DraggedDownload {
+    id: DownloadId,
+    filename: String,
+}
+
+impl Render for DraggedDownload {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        div()
+            .px(px(12.0))
+            .py(px(8.0))
+            .bg(theme.tokens.card)
+            .border_1()
+            .border_color(theme.tokens.primary)
+            .rounded(px(8.0))
+            .text_size(px(13.0))
+            .text_color(theme.tokens.foreground)
+            .child(self.filename.clone())
+    }
+}
+
 pub struct StormApp {
     state: AppState,
     url_input: Entity<InputState>,
+    checksum_input: Entity<InputState>,
+    link_filter_input: Entity<InputState>,
+    calibration_input: Entity<InputState>,
+    tags_input: Entity<InputState>,
+    note_input: Entity<InputState>,
     save_location: PathBuf,
+    clipboard_url: Option<String>,
+    last_clipboard: Option<String>,
+    clipboard_offer: Option<String>,
+    map_zoom: u64,
+    map_offset: u64,
 }
 
+const MAP_CELLS: usize = 512;
+const MAP_COLUMNS: usize = 64;
+const CONTENTS_ROWS: usize = 50;
+const MAX_MAP_ZOOM: u64 = 64;
+const CLIPBOARD_POLL: Duration = Duration::from_secs(1);
+const LINK_SIZE_FILTERS: &[(&str, Option<u64>)] = &[
+    ("Any size", None),
+    ("> 1 MB", Some(1024 * 1024)),
+    ("> 100 MB", Some(100 * 1024 * 1024)),
+    ("> 1 GB", Some(1024 * 1024 * 1024)),
+];
+
 impl StormApp {
     pub fn new(
         command_tx: Sender<OrchestratorCommand>,
         event_rx: Receiver<DownloadEvent>,
+        groups: Vec<DownloadGroup>,
+        history: Vec<String>,
+        setup: Option<SetupChoices>,
+        window: &mut Window,
         cx: &mut Context<Self>,
     ) -> Self {
-        let state = AppState::new(command_tx, event_rx.clone());
+        let mut state = AppState::new(command_tx, event_rx.clone());
+        state.groups = groups;
+        state.history = UrlHistory::new(history);
         let url_input = cx.new(InputState::new);
-        let save_location = dirs::download_dir().unwrap_or_else(|| PathBuf::from("."));
+
+        cx.subscribe(&url_input, |this, _, event: &InputEvent, cx| match event {
+            InputEvent::Focus => {
+                this.clipboard_url = cx.read_from_clipboard().and_then(|item| item.text());
+                cx.notify();
+            }
+            InputEvent::Change => cx.notify(),
+            _ => {}
+        })
+        .detach();
+        let checksum_input = cx.new(InputState::new);
+        cx.subscribe(&checksum_input, |_, _, event: &InputEvent, cx| {
+            if let InputEvent::Change = event {
+                cx.notify();
+            }
+        })
+        .detach();
+        let link_filter_input = cx.new(InputState::new);
+        cx.subscribe(&link_filter_input, |this, input, event: &InputEvent, cx| {
+            if let InputEvent::Change = event
+                && let Some(picker) = this.state.link_picker.as_mut()
+            {
+                let list = input.read(cx).content.to_string();
+                picker.filter = picker.filter.clone().with_extensions(&list);
+                cx.notify();
+            }
+        })
+        .detach();
+        let calibration_input = cx.new(InputState::new);
+        cx.subscribe(&calibration_input, |_, _, event: &InputEvent, cx| {
+            if let InputEvent::Change = event {
+                cx.notify();
+            }
+        })
+        .detach();
+        let tags_input = cx.new(InputState::new);
+        let note_input = cx.new(InputState::new);
+        match &setup {
+            Some(choices) => state.apply_setup(choices),
+            None => state.onboarding = Some(Onboarding::new(state.settings.download_dir.clone())),
+        }
+        let save_location = state.settings.download_dir.clone();
+
+        cx.observe_window_activation(window, |this, window, cx| {
+            let _ = this
+                .state
+                .command_tx
+                .send(OrchestratorCommand::SetUiActive(window.is_window_active()));
+            cx.notify();
+        })
+        .detach();
 
         cx.spawn(async move |this, cx| {
             while let Ok(event) = event_rx.recv_async().await {
+                let batch: Vec<DownloadEvent> =
+                    std::iter::once(event).chain(event_rx.drain()).collect();
                 let _ = this.update(cx, |app, cx| {
-                    app.handle_event(event, cx);
+                    app.state.apply_events(batch);
+                    cx.notify();
                 });
             }
         })
         .detach();
 
+        cx.spawn(async move |this, cx| {
+            loop {
+                cx.background_executor().timer(CLIPBOARD_POLL).await;
+                if this.update(cx, |app, cx| app.poll_clipboard(cx)).is_err() {
+                    break;
+                }
+            }
+        })
+        .detach();
+
         Self {
             state,
             url_input,
+            checksum_input,
+            link_filter_input,
+            calibration_input,
+            tags_input,
+            note_input,
             save_location,
+            clipboard_url: None,
+            last_clipboard: None,
+            clipboard_offer: None,
+            map_zoom: 1,
+            map_offset: 0,
         }
     }
 
-    fn handle_event(&mut self, event: DownloadEvent, cx: &mut Context<Self>) {
-        match event {
-            DownloadEvent::DownloadAdded {
-                id,
-                url,
-                filename,
-                total_size,
-            } => {
-                self.state.add_download(id, url, filename, total_size);
-            }
-            DownloadEvent::ProgressUpdate {
-                id,
-                downloaded,
-                segments,
-            } => {
-                if let Some(download) = self.state.get_download_mut(id) {
-                    download.downloaded_bytes = downloaded;
-                    download.segments = segments;
-                }
-            }
-            DownloadEvent::SpeedUpdate { id, speed } => {
-                if let Some(download) = self.state.get_download_mut(id) {
-                    download.add_speed_sample(speed);
-                }
-            }
-            DownloadEvent::StateChange { id, state } => {
-                if let Some(download) = self.state.get_download_mut(id) {
-                    download.state = state;
-                }
-            }
-            DownloadEvent::SegmentRebalanced { .. } => {}
-            DownloadEvent::Error { id, error } => {
-                if let Some(download) = self.state.get_download_mut(id) {
-                    download.error = Some(error);
-                    download.state = DownloadState::Failed;
-                }
-            }
-            DownloadEvent::Complete { id, .. } => {
-                if let Some(download) = self.state.get_download_mut(id) {
-                    download.state = DownloadState::Complete;
-                }
-            }
-        }
-        cx.notify();
-    }
-
     fn start_download(&mut self, cx: &mut Context<Self>) {
         let url_str = self.url_input.read(cx).content.to_string();
         if url_str.trim().is_empty() {
             return;
         }
+        let checksum = match self.checksum_input.read(cx).content.trim() {
+            "" => None,
+            checksum => match ChecksumSpec::parse(checksum) {
+                Ok(spec) => Some(spec.to_string()),
+                Err(_) => return,
+            },
+        };
         if let Ok(url) = Url::parse(&url_str) {
             let options = DownloadOptions {
                 url: url.clone(),
@@ -105,9 +200,17 @@ impl StormApp {
                 priority: stormdl_core::Priority::Normal,
                 bandwidth_limit: None,
                 headers: vec![],
-                checksum: None,
+                checksum,
+                group: self.state.active_group.clone(),
+                mirrors: vec![],
+                tags: vec![],
+                note: None,
+                proxy: None,
+                auth: None,
             };
 
+            self.state.history.record(url.as_str());
+            self.clipboard_url = None;
             let _ = self
                 .state
                 .command_tx
@@ -115,11 +218,282 @@ impl StormApp {
             self.url_input.update(cx, |input, _| {
                 input.content = SharedString::default();
             });
+            self.checksum_input.update(cx, |input, _| {
+                input.content = SharedString::default();
+            });
             cx.notify();
         }
     }
 
-    fn browse_location(&mut self, cx: &mut Context<Self>) {
+    fn grab_links(&mut self, cx: &mut Context<Self>) {
+        let url_str = self.url_input.read(cx).content.to_string();
+        let Ok(page) = Url::parse(url_str.trim()) else {
+            return;
+        };
+
+        self.state.history.record(page.as_str());
+        self.state.link_picker = Some(LinkPicker::new(page.clone()));
+        self.link_filter_input.update(cx, |input, _| {
+            input.content = SharedString::default();
+        });
+        let _ = self
+            .state
+            .command_tx
+            .send(OrchestratorCommand::GrabLinks(page));
+        cx.notify();
+    }
+
+    fn enqueue_picked_links(&mut self, cx: &mut Context<Self>) {
+        let Some(picker) = self.state.link_picker.take() else {
+            return;
+        };
+
+        for link in picker.chosen() {
+            let options = DownloadOptions {
+                url: link.url.clone(),
+                output_dir: self.save_location.clone(),
+                filename: Some(link.filename),
+                segments: None,
+                priority: stormdl_core::Priority::Normal,
+                bandwidth_limit: None,
+                headers: vec![],
+                checksum: None,
+                group: self.state.active_group.clone(),
+                mirrors: vec![],
+                tags: vec![],
+                note: None,
+                proxy: None,
+                auth: None,
+            };
+            let _ = self
+                .state
+                .command_tx
+                .send(OrchestratorCommand::AddDownload {
+                    url: link.url,
+                    options,
+                });
+        }
+        cx.notify();
+    }
+
+    fn apply_suggestion(&mut self, url: String, window: &mut Window, cx: &mut Context<Self>) {
+        self.url_input.update(cx, |input, cx| {
+            input.set_value(url, window, cx);
+        });
+        cx.notify();
+    }
+
+    fn move_download(&mut self, id: DownloadId, before: DownloadId, cx: &mut Context<Self>) {
+        if id == before || !self.state.is_queued(id) || !self.state.is_queued(before) {
+            return;
+        }
+
+        if let (Some(from), Some(to)) = (
+            self.state.queue_order.iter().position(|d| *d == id),
+            self.state.queue_order.iter().position(|d| *d == before),
+        ) {
+            let moved = self.state.queue_order.remove(from);
+            let to = if from < to { to - 1 } else { to };
+            self.state.queue_order.insert(to, moved);
+        }
+
+        let _ = self
+            .state
+            .command_tx
+            .send(OrchestratorCommand::MoveDownload { id, before });
+        cx.notify();
+    }
+
+    fn click_download(&mut self, id: DownloadId, modifiers: Modifiers, cx: &mut Context<Self>) {
+        if modifiers.shift {
+            self.state.select(id, SelectMode::Extend);
+        } else if modifiers.secondary() {
+            self.state.select(id, SelectMode::Toggle);
+        } else if self.state.selection == [id] {
+            self.state.clear_selection();
+            self.state.selected_download_id = None;
+        } else {
+            self.state.select(id, SelectMode::Replace);
+            self.state.selected_download_id = Some(id);
+            self.map_zoom = 1;
+            self.map_offset = 0;
+        }
+        cx.notify();
+    }
+
+    fn bulk(&mut self, action: BulkAction, cx: &mut Context<Self>) {
+        for id in self.state.bulk_targets(action) {
+            let _ = self.state.command_tx.send(action.command(id));
+            if action == BulkAction::Remove {
+                self.state.remove_download(id);
+            }
+        }
+        cx.notify();
+    }
+
+    fn clear_completed(&mut self, cx: &mut Context<Self>) {
+        for id in self.state.completed_ids() {
+            let _ = self
+                .state
+                .command_tx
+                .send(OrchestratorCommand::RemoveDownload(id));
+            self.state.remove_download(id);
+        }
+        cx.notify();
+    }
+
+    fn zoom_map(&mut self, zoom_in: bool, cx: &mut Context<Self>) {
+        self.map_zoom = if zoom_in {
+            (self.map_zoom * 2).min(MAX_MAP_ZOOM)
+        } else {
+            (self.map_zoom / 2).max(1)
+        };
+        self.map_offset = self.map_offset.min(self.map_zoom - 1);
+        cx.notify();
+    }
+
+    fn pan_map(&mut self, forward: bool, cx: &mut Context<Self>) {
+        self.map_offset = if forward {
+            (self.map_offset + 1).min(self.map_zoom - 1)
+        } else {
+            self.map_offset.saturating_sub(1)
+        };
+        cx.notify();
+    }
+
+    fn edit_labels(&mut self, id: DownloadId, cx: &mut Context<Self>) {
+        let Some(download) = self.state.get_download(id) else {
+            return;
+        };
+        let tags = download.tags.join(", ");
+        let note = download.note.clone().unwrap_or_default();
+        self.tags_input.update(cx, |input, _| {
+            input.content = tags.into();
+        });
+        self.note_input.update(cx, |input, _| {
+            input.content = note.into();
+        });
+        self.state.editing_labels = Some(id);
+        cx.notify();
+    }
+
+    fn save_labels(&mut self, cx: &mut Context<Self>) {
+        let Some(id) = self.state.editing_labels.take() else {
+            return;
+        };
+        let tags = parse_tags(&self.tags_input.read(cx).content);
+        let note = Some(self.note_input.read(cx).content.trim().to_string())
+            .filter(|note| !note.is_empty());
+        let _ = self
+            .state
+            .command_tx
+            .send(OrchestratorCommand::SetLabels { id, tags, note });
+        cx.notify();
+    }
+
+    fn filter_tag(&mut self, tag: Option<String>, cx: &mut Context<Self>) {
+        self.state.tag_filter = tag;
+        self.state.clear_selection();
+        cx.notify();
+    }
+
+    fn select_group(&mut self, group: Option<String>, cx: &mut Context<Self>) {
+        self.state.active_group = group;
+        self.save_location = self
+            .state
+            .active_group()
+            .and_then(|g| g.output_dir.clone())
+            .unwrap_or_else(|| self.state.settings.download_dir.clone());
+        cx.notify();
+    }
+
+    fn poll_clipboard(&mut self, cx: &mut Context<Self>) {
+        if !self.state.settings.watch_clipboard || self.state.onboarding.is_some() {
+            return;
+        }
+        let text = cx
+            .read_from_clipboard()
+            .and_then(|item| item.text())
+            .map(|text| text.trim().to_string());
+        if text == self.last_clipboard {
+            return;
+        }
+        self.last_clipboard = text.clone();
+
+        let known = |url: &str| {
+            self.state.downloads.iter().any(|d| d.url.as_str() == url)
+                || self.clipboard_offer.as_deref() == Some(url)
+        };
+        if let Some(url) = text.filter(|t| is_download_url(t) && !known(t)) {
+            self.clipboard_offer = Some(url);
+            cx.notify();
+        }
+    }
+
+    fn accept_clipboard_offer(&mut self, cx: &mut Context<Self>) {
+        let Some(url) = self.clipboard_offer.take() else {
+            return;
+        };
+        self.url_input.update(cx, |input, _| {
+            input.content = url.into();
+        });
+        self.start_download(cx);
+    }
+
+    fn start_calibration(&mut self, cx: &mut Context<Self>) {
+        let url_str = self.calibration_input.read(cx).content.to_string();
+        let Some(onboarding) = self.state.onboarding.as_mut() else {
+            return;
+        };
+        match Url::parse(url_str.trim()) {
+            Ok(url) => {
+                onboarding.calibrating = Some(url.clone());
+                onboarding.error = None;
+                let _ = self
+                    .state
+                    .command_tx
+                    .send(OrchestratorCommand::Calibrate(url));
+            }
+            Err(e) => onboarding.error = Some(format!("Invalid URL: {}", e)),
+        }
+        cx.notify();
+    }
+
+    fn onboarding_step(&mut self, forward: bool, cx: &mut Context<Self>) {
+        if let Some(onboarding) = self.state.onboarding.as_mut() {
+            let step = if forward {
+                onboarding.step.next()
+            } else {
+                onboarding.step.prev()
+            };
+            if let Some(step) = step {
+                onboarding.step = step;
+                onboarding.error = None;
+            }
+        }
+        cx.notify();
+    }
+
+    fn finish_onboarding(&mut self, cx: &mut Context<Self>) {
+        let Some(onboarding) = self.state.onboarding.as_mut() else {
+            return;
+        };
+        onboarding.saving = true;
+        onboarding.error = None;
+        let choices = onboarding.choices();
+        self.save_location = choices.download_dir.clone();
+        let _ = self
+            .state
+            .command_tx
+            .send(OrchestratorCommand::CompleteSetup(choices));
+        cx.notify();
+    }
+
+    fn browse_location(
+        &mut self,
+        cx: &mut Context<Self>,
+        apply: impl FnOnce(&mut Self, PathBuf) + 'static,
+    ) {
         cx.spawn(async move |this, cx| {
             let result = cx.update(|cx| {
                 cx.prompt_for_paths(PathPromptOptions {
@@ -130,191 +504,893 @@ impl StormApp {
                 })
             });
 
-            if let Ok(receiver) = result {
-                if let Ok(Ok(Some(paths))) = receiver.await {
-                    if let Some(selected) = paths.into_iter().next() {
-                        let _ = this.update(cx, |app, cx| {
-                            app.save_location = selected;
-                            cx.notify();
-                        });
-                    }
-                }
+            if let Ok(receiver) = result
+                && let Ok(Ok(Some(paths))) = receiver.await
+                && let Some(selected) = paths.into_iter().next()
+            {
+                let _ = this.update(cx, |app, cx| {
+                    apply(app, selected);
+                    cx.notify();
+                });
             }
         })
         .detach();
     }
-}
+}
+
+impl Render for StormApp {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+
+        div()
+            .size_full()
+            .bg(theme.tokens.background)
+            .flex()
+            .flex_col()
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .gap(px(12.0))
+                    .px(px(24.0))
+                    .py(px(20.0))
+                    .bg(theme.tokens.primary.opacity(0.05))
+                    .border_b_1()
+                    .border_color(theme.tokens.border)
+                    .child(
+                        div()
+                            .size(px(40.0))
+                            .rounded_full()
+                            .bg(theme.tokens.primary.opacity(0.15))
+                            .flex()
+                            .items_center()
+                            .justify_center()
+                            .child(Icon::new("zap").size(px(20.0)).color(theme.tokens.primary)),
+                    )
+                    .child(
+                        div()
+                            .flex()
+                            .flex_col()
+                            .child(
+                                div()
+                                    .flex()
+                                    .items_center()
+                                    .gap(px(8.0))
+                                    .child(
+                                        div()
+                                            .text_size(px(20.0))
+                                            .font_weight(FontWeight::BOLD)
+                                            .text_color(theme.tokens.foreground)
+                                            .child("StormDL"),
+                                    )
+                                    .child(Badge::new("v0.1.0").variant(BadgeVariant::Secondary)),
+                            )
+                            .child(
+                                div()
+                                    .text_size(px(12.0))
+                                    .text_color(theme.tokens.muted_foreground)
+                                    .child("Lightning-fast parallel downloads"),
+                            ),
+                    )
+                    .child(div().flex_1())
+                    .children(self.state.quota.map(render_quota)),
+            )
+            .child(if self.state.onboarding.is_some() {
+                self.render_onboarding(cx)
+            } else {
+                div()
+                    .flex_1()
+                    .overflow_hidden()
+                    .child(scrollable_vertical(
+                        div()
+                            .p(px(24.0))
+                            .flex()
+                            .flex_col()
+                            .gap(px(20.0))
+                            .child(self.render_clipboard_offer(cx))
+                            .child(
+                                div()
+                                    .flex()
+                                    .flex_col()
+                                    .gap(px(8.0))
+                                    .child(
+                                        div()
+                                            .text_size(px(13.0))
+                                            .font_weight(FontWeight::MEDIUM)
+                                            .text_color(theme.tokens.foreground)
+                                            .child("Download URL"),
+                                    )
+                                    .child(
+                                        Input::new(&self.url_input)
+                                            .placeholder("https://example.com/file.zip")
+                                            .prefix(
+                                                Icon::new("link")
+                                                    .size(px(16.0))
+                                                    .color(theme.tokens.muted_foreground),
+                                            )
+                                            .clearable(true),
+                                    )
+                                    .child(self.render_suggestions(cx)),
+                            )
+                            .child(
+                                div()
+                                    .flex()
+                                    .flex_col()
+                                    .gap(px(8.0))
+                                    .child(
+                                        div()
+                                            .text_size(px(13.0))
+                                            .font_weight(FontWeight::MEDIUM)
+                                            .text_color(theme.tokens.foreground)
+                                            .child("Checksum (optional)"),
+                                    )
+                                    .child(
+                                        Input::new(&self.checksum_input)
+                                            .placeholder("sha256:…, md5:… or a bare hex digest")
+                                            .prefix(
+                                                Icon::new("check")
+                                                    .size(px(16.0))
+                                                    .color(theme.tokens.muted_foreground),
+                                            )
+                                            .clearable(true),
+                                    )
+                                    .child(self.render_checksum_hint(cx)),
+                            )
+                            .child(
+                                div()
+                                    .flex()
+                                    .flex_col()
+                                    .gap(px(8.0))
+                                    .child(
+                                        div()
+                                            .text_size(px(13.0))
+                                            .font_weight(FontWeight::MEDIUM)
+                                            .text_color(theme.tokens.foreground)
+                                            .child("Save to"),
+                                    )
+                                    .child(
+                                        div()
+                                            .flex()
+                                            .items_center()
+                                            .gap(px(8.0))
+                                            .child(
+                                                div()
+                                                    .flex_1()
+                                                    .h(px(40.0))
+                                                    .px(px(12.0))
+                                                    .bg(theme.tokens.muted.opacity(0.3))
+                                                    .border_1()
+                                                    .border_color(theme.tokens.border)
+                                                    .rounded(theme.tokens.radius_md)
+                                                    .flex()
+                                                    .items_center()
+                                                    .gap(px(8.0))
+                                                    .child(
+                                                        Icon::new("folder")
+                                                            .size(px(16.0))
+                                                            .color(theme.tokens.muted_foreground),
+                                                    )
+                                                    .child(
+                                                        div()
+                                                            .text_size(px(14.0))
+                                                            .text_color(theme.tokens.foreground)
+                                                            .text_ellipsis()
+                                                            .overflow_hidden()
+                                                            .child(
+                                                                self.save_location
+                                                                    .to_string_lossy()
+                                                                    .to_string(),
+                                                            ),
+                                                    ),
+                                            )
+                                            .child(
+                                                Button::new("browse", "Browse")
+                                                    .variant(ButtonVariant::Ghost)
+                                                    .icon("folder-open")
+                                                    .on_click(cx.listener(
+                                                        |this, _, _window, cx| {
+                                                            this.browse_location(
+                                                                cx,
+                                                                |app, path| {
+                                                                    app.save_location = path;
+                                                                },
+                                                            );
+                                                        },
+                                                    )),
+                                            ),
+                                    ),
+                            )
+                            .child(
+                                div()
+                                    .flex()
+                                    .items_center()
+                                    .gap(px(8.0))
+                                    .child(
+                                        Button::new("download", "Download")
+                                            .icon("download")
+                                            .variant(ButtonVariant::Default)
+                                            .on_click(cx.listener(|this, _, _window, cx| {
+                                                this.start_download(cx);
+                                            })),
+                                    )
+                                    .child(
+                                        Button::new("grab-links", "Add from page")
+                                            .icon("list")
+                                            .variant(ButtonVariant::Outline)
+                                            .on_click(cx.listener(|this, _, _window, cx| {
+                                                this.grab_links(cx);
+                                            })),
+                                    ),
+                            )
+                            .child(self.render_link_picker(cx))
+                            .child(self.render_group_tabs(cx))
+                            .child(self.render_tag_filter(cx))
+                            .child(self.render_bulk_actions(cx))
+                            .child(self.render_downloads_list(cx)),
+                    ))
+                    .into_any_element()
+            })
+    }
+}
+
+impl StormApp {
+    fn render_clipboard_offer(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        let Some(url) = &self.clipboard_offer else {
+            return div().into_any_element();
+        };
+
+        div()
+            .flex()
+            .items_center()
+            .gap(px(8.0))
+            .px(px(12.0))
+            .py(px(8.0))
+            .bg(theme.tokens.primary.opacity(0.08))
+            .border_1()
+            .border_color(theme.tokens.primary.opacity(0.4))
+            .rounded(theme.tokens.radius_md)
+            .child(
+                Icon::new("clipboard")
+                    .size(px(16.0))
+                    .color(theme.tokens.primary),
+            )
+            .child(
+                div()
+                    .flex_1()
+                    .text_size(px(13.0))
+                    .text_color(theme.tokens.foreground)
+                    .text_ellipsis()
+                    .overflow_hidden()
+                    .child(url.clone()),
+            )
+            .child(
+                Button::new("clipboard-download", "Download")
+                    .icon("download")
+                    .variant(ButtonVariant::Default)
+                    .on_click(cx.listener(|this, _, _window, cx| {
+                        this.accept_clipboard_offer(cx);
+                    })),
+            )
+            .child(
+                Button::new("clipboard-dismiss", "")
+                    .icon("x")
+                    .variant(ButtonVariant::Ghost)
+                    .on_click(cx.listener(|this, _, _window, cx| {
+                        this.clipboard_offer = None;
+                        cx.notify();
+                    })),
+            )
+            .into_any_element()
+    }
+
+    fn render_onboarding(&self, cx: &mut Context<Self>) -> AnyElement {
+        let theme = use_theme();
+        let Some(onboarding) = &self.state.onboarding else {
+            return div().into_any_element();
+        };
+        let step = onboarding.step;
+
+        let hint = |text: &'static str| {
+            div()
+                .text_size(px(13.0))
+                .text_color(theme.tokens.muted_foreground)
+                .child(text)
+        };
+
+        let body = match step {
+            OnboardingStep::Folder => div()
+                .flex()
+                .flex_col()
+                .gap(px(12.0))
+                .child(hint(
+                    "Finished files are saved here unless a group says otherwise.",
+                ))
+                .child(
+                    div()
+                        .flex()
+                        .items_center()
+                        .gap(px(8.0))
+                        .child(
+                            div()
+                                .flex_1()
+                                .h(px(40.0))
+                                .px(px(12.0))
+                                .bg(theme.tokens.muted.opacity(0.3))
+                                .border_1()
+                                .border_color(theme.tokens.border)
+                                .rounded(theme.tokens.radius_md)
+                                .flex()
+                                .items_center()
+                                .gap(px(8.0))
+                                .child(
+                                    Icon::new("folder")
+                                        .size(px(16.0))
+                                        .color(theme.tokens.muted_foreground),
+                                )
+                                .child(
+                                    div()
+                                        .text_size(px(14.0))
+                                        .text_color(theme.tokens.foreground)
+                                        .text_ellipsis()
+                                        .overflow_hidden()
+                                        .child(
+                                            onboarding.download_dir.to_string_lossy().to_string(),
+                                        ),
+                                ),
+                        )
+                        .child(
+                            Button::new("setup-browse", "Browse")
+                                .variant(ButtonVariant::Ghost)
+                                .icon("folder-open")
+                                .on_click(cx.listener(|this, _, _window, cx| {
+                                    this.browse_location(cx, |app, path| {
+                                        if let Some(onboarding) = app.state.onboarding.as_mut() {
+                                            onboarding.download_dir = path;
+                                        }
+                                    });
+                                })),
+                        ),
+                ),
+            OnboardingStep::Calibrate => {
+                let result = if onboarding.calibrating.is_some() {
+                    div()
+                        .flex()
+                        .items_center()
+                        .gap(px(8.0))
+                        .child(Spinner::new())
+                        .child(hint("Trying 1 to 16 connections..."))
+                        .into_any_element()
+                } else if let Some(calibration) = onboarding.calibration {
+                    div()
+                        .flex()
+                        .items_center()
+                        .gap(px(8.0))
+                        .child(
+                            Icon::new("circle-check")
+                                .size(px(16.0))
+                                .color(theme.tokens.primary),
+                        )
+                        .child(
+                            div()
+                                .text_size(px(13.0))
+                                .text_color(theme.tokens.foreground)
+                                .child(format!(
+                                    "{}/s with {} connection{}",
+                                    bytesize::ByteSize(calibration.speed as u64),
+                                    calibration.segments,
+                                    if calibration.segments == 1 { "" } else { "s" }
+                                )),
+                        )
+                        .into_any_element()
+                } else {
+                    div().into_any_element()
+                };
+
+                div()
+                    .flex()
+                    .flex_col()
+                    .gap(px(12.0))
+                    .child(hint(
+                        "Paste a link to a large file on a fast server. Up to 16 MB is \
+                         fetched with more and more connections to see what your line rewards.",
+                    ))
+                    .child(
+                        div()
+                            .flex()
+                            .items_center()
+                            .gap(px(8.0))
+                            .child(
+                                div().flex_1().child(
+                                    Input::new(&self.calibration_input)
+                                        .placeholder("https://example.com/100MB.bin")
+                                        .prefix(
+                                            Icon::new("link")
+                                                .size(px(16.0))
+                                                .color(theme.tokens.muted_foreground),
+                                        ),
+                                ),
+                            )
+                            .child(
+                                Button::new("setup-calibrate", "Test")
+                                    .icon("gauge")
+                                    .variant(ButtonVariant::Outline)
+                                    .disabled(onboarding.calibrating.is_some())
+                                    .on_click(cx.listener(|this, _, _window, cx| {
+                                        this.start_calibration(cx);
+                                    })),
+                            ),
+                    )
+                    .child(result)
+            }
+            OnboardingStep::Profile => {
+                let suggested = onboarding.calibration.as_ref().map(SpeedProfile::suggest);
+                let options: Vec<_> = SpeedProfile::ALL
+                    .into_iter()
+                    .map(|profile| {
+                        let selected = onboarding.profile == profile;
+                        div()
+                            .id(SharedString::from(format!("profile-{}", profile)))
+                            .flex()
+                            .flex_col()
+                            .gap(px(2.0))
+                            .px(px(12.0))
+                            .py(px(8.0))
+                            .border_1()
+                            .border_color(if selected {
+                                theme.tokens.primary
+                            } else {
+                                theme.tokens.border
+                            })
+                            .rounded(theme.tokens.radius_md)
+                            .cursor_pointer()
+                            .hover(|style| style.bg(theme.tokens.muted.opacity(0.5)))
+                            .child(
+                                div()
+                                    .flex()
+                                    .items_center()
+                                    .gap(px(8.0))
+                                    .child(
+                                        div()
+                                            .text_size(px(14.0))
+                                            .font_weight(FontWeight::MEDIUM)
+                                            .text_color(theme.tokens.foreground)
+                                            .child(profile_label(profile)),
+                                    )
+                                    .when(suggested == Some(profile), |this| {
+                                        this.child(
+                                            Badge::new("Suggested")
+                                                .variant(BadgeVariant::Secondary),
+                                        )
+                                    }),
+                            )
+                            .child(hint(profile.description()))
+                            .on_click(cx.listener(move |this, _, _window, cx| {
+                                if let Some(onboarding) = this.state.onboarding.as_mut() {
+                                    onboarding.profile = profile;
+                                }
+                                cx.notify();
+                            }))
+                    })
+                    .collect();
+
+                div().flex().flex_col().gap(px(8.0)).children(options)
+            }
+            OnboardingStep::Clipboard => {
+                let entity = cx.entity();
+                div()
+                    .flex()
+                    .flex_col()
+                    .gap(px(12.0))
+                    .child(hint(
+                        "When a download link is copied anywhere, StormDL offers to fetch it.",
+                    ))
+                    .child(
+                        Checkbox::new("setup-clipboard")
+                            .checked(onboarding.watch_clipboard)
+                            .label("Watch the clipboard for download links")
+                            .on_click(move |_, _, cx| {
+                                entity.update(cx, |this, cx| {
+                                    if let Some(onboarding) = this.state.onboarding.as_mut() {
+                                        onboarding.watch_clipboard = !onboarding.watch_clipboard;
+                                    }
+                                    cx.notify();
+                                });
+                            }),
+                    )
+            }
+        };
+
+        let next = match step.next() {
+            Some(_) => {
+                let label = if step == OnboardingStep::Calibrate && onboarding.calibration.is_none()
+                {
+                    "Skip"
+                } else {
+                    "Next"
+                };
+                Button::new("setup-next", label)
+                    .variant(ButtonVariant::Default)
+                    .disabled(onboarding.calibrating.is_some())
+                    .on_click(cx.listener(|this, _, _window, cx| {
+                        this.onboarding_step(true, cx);
+                    }))
+            }
+            None => Button::new("setup-finish", "Finish")
+                .icon("check")
+                .variant(ButtonVariant::Default)
+                .loading(onboarding.saving)
+                .disabled(onboarding.saving)
+                .on_click(cx.listener(|this, _, _window, cx| {
+                    this.finish_onboarding(cx);
+                })),
+        };
+
+        div()
+            .flex_1()
+            .p(px(24.0))
+            .flex()
+            .flex_col()
+            .gap(px(16.0))
+            .child(
+                div()
+                    .text_size(px(12.0))
+                    .text_color(theme.tokens.muted_foreground)
+                    .child(format!(
+                        "Step {} of {}",
+                        step.index() + 1,
+                        OnboardingStep::ALL.len()
+                    )),
+            )
+            .child(
+                div()
+                    .text_size(px(18.0))
+                    .font_weight(FontWeight::BOLD)
+                    .text_color(theme.tokens.foreground)
+                    .child(step.title()),
+            )
+            .child(body)
+            .children(onboarding.error.clone().map(|error| {
+                div()
+                    .text_size(px(12.0))
+                    .text_color(theme.tokens.destructive)
+                    .child(error)
+            }))
+            .child(div().flex_1())
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .gap(px(8.0))
+                    .when(step.prev().is_some(), |this| {
+                        this.child(
+                            Button::new("setup-back", "Back")
+                                .variant(ButtonVariant::Ghost)
+                                .on_click(cx.listener(|this, _, _window, cx| {
+                                    this.onboarding_step(false, cx);
+                                })),
+                        )
+                    })
+                    .child(div().flex_1())
+                    .child(next),
+            )
+            .into_any_element()
+    }
+
+    fn render_checksum_hint(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        let checksum = self.checksum_input.read(cx).content().to_string();
+        if checksum.trim().is_empty() {
+            return div().into_any_element();
+        }
+
+        let (hint, color) = match ChecksumSpec::parse(&checksum) {
+            Ok(spec) => {
+                let names: Vec<&str> = spec.algorithms().iter().map(|a| a.name()).collect();
+                (
+                    format!("Verified as {} after download", names.join(" or ")),
+                    theme.tokens.muted_foreground,
+                )
+            }
+            Err(e) => (e.to_string(), theme.tokens.destructive),
+        };
+        div()
+            .text_size(px(12.0))
+            .text_color(color)
+            .child(hint)
+            .into_any_element()
+    }
+
+    fn render_suggestions(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        let query = self.url_input.read(cx).content().to_string();
+        let suggestions: Vec<Suggestion> =
+            self.state
+                .history
+                .suggestions(&query, self.clipboard_url.as_deref(), 5);
+
+        if suggestions.is_empty() {
+            return div().into_any_element();
+        }
+
+        let items: Vec<_> = suggestions
+            .into_iter()
+            .enumerate()
+            .map(|(idx, suggestion)| {
+                let icon = match suggestion.source {
+                    SuggestionSource::Clipboard => "clipboard",
+                    SuggestionSource::History => "history",
+                };
+                let url = suggestion.url.clone();
+
+                div()
+                    .id(("suggestion", idx))
+                    .flex()
+                    .items_center()
+                    .gap(px(8.0))
+                    .px(px(12.0))
+                    .py(px(6.0))
+                    .rounded(theme.tokens.radius_md)
+                    .cursor_pointer()
+                    .hover(|style| style.bg(theme.tokens.muted.opacity(0.5)))
+                    .child(
+                        Icon::new(icon)
+                            .size(px(14.0))
+                            .color(theme.tokens.muted_foreground),
+                    )
+                    .child(
+                        div()
+                            .text_size(px(13.0))
+                            .text_color(theme.tokens.foreground)
+                            .text_ellipsis()
+                            .overflow_hidden()
+                            .child(suggestion.url),
+                    )
+                    .on_click(cx.listener(move |this, _, window, cx| {
+                        this.apply_suggestion(url.clone(), window, cx);
+                    }))
+            })
+            .collect();
+
+        div()
+            .flex()
+            .flex_col()
+            .p(px(4.0))
+            .bg(theme.tokens.card)
+            .border_1()
+            .border_color(theme.tokens.border)
+            .rounded(theme.tokens.radius_md)
+            .children(items)
+            .into_any_element()
+    }
+
+    fn render_group_tabs(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        if self.state.groups.is_empty() {
+            return div().into_any_element();
+        }
+
+        let tab = |id: SharedString, label: String, group: Option<String>| {
+            let variant = if self.state.active_group == group {
+                ButtonVariant::Default
+            } else {
+                ButtonVariant::Ghost
+            };
+            Button::new(id, label)
+                .variant(variant)
+                .on_click(cx.listener(move |this, _, _window, cx| {
+                    this.select_group(group.clone(), cx);
+                }))
+        };
+
+        let mut tabs = vec![tab("group-all".into(), "All".to_string(), None)];
+        for group in &self.state.groups {
+            tabs.push(tab(
+                format!("group-{}", group.name).into(),
+                group.name.clone(),
+                Some(group.name.clone()),
+            ));
+        }
+
+        div()
+            .flex()
+            .items_center()
+            .gap(px(8.0))
+            .children(tabs)
+            .into_any_element()
+    }
+
+    fn render_tag_filter(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        let Some(tag) = &self.state.tag_filter else {
+            return div().into_any_element();
+        };
+
+        div()
+            .flex()
+            .items_center()
+            .gap(px(8.0))
+            .child(
+                div()
+                    .text_size(px(13.0))
+                    .text_color(theme.tokens.muted_foreground)
+                    .child("Tagged"),
+            )
+            .child(Badge::new(tag.clone()).variant(BadgeVariant::Secondary))
+            .child(
+                Button::new("clear-tag-filter", "Show all")
+                    .icon("x")
+                    .variant(ButtonVariant::Ghost)
+                    .on_click(cx.listener(|this, _, _window, cx| {
+                        this.filter_tag(None, cx);
+                    })),
+            )
+            .into_any_element()
+    }
+
+    fn render_labels(&self, download: &Download, cx: &mut Context<Self>) -> AnyElement {
+        let theme = use_theme();
+        if download.tags.is_empty() && download.note.is_none() {
+            return div().into_any_element();
+        }
+
+        let chips = download.tags.iter().enumerate().map(|(i, tag)| {
+            let filter = tag.clone();
+            div()
+                .id(SharedString::from(format!("tag-{}-{}", download.id.0, i)))
+                .cursor_pointer()
+                .child(Badge::new(tag.clone()).variant(BadgeVariant::Outline))
+                .on_click(cx.listener(move |this, _, _window, cx| {
+                    cx.stop_propagation();
+                    this.filter_tag(Some(filter.clone()), cx);
+                }))
+        });
+
+        div()
+            .flex()
+            .flex_wrap()
+            .items_center()
+            .gap(px(6.0))
+            .children(chips)
+            .children(download.note.clone().map(|note| {
+                div()
+                    .text_size(px(12.0))
+                    .text_color(theme.tokens.muted_foreground)
+                    .text_ellipsis()
+                    .overflow_hidden()
+                    .child(note)
+            }))
+            .into_any_element()
+    }
 
-impl Render for StormApp {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let theme = use_theme();
+    fn render_label_editor(&self, id: DownloadId, cx: &mut Context<Self>) -> AnyElement {
+        if self.state.editing_labels != Some(id) {
+            return div()
+                .child(
+                    Button::new(("edit-labels", id.0 as usize), "Edit tags")
+                        .variant(ButtonVariant::Ghost)
+                        .on_click(cx.listener(move |this, _, _window, cx| {
+                            cx.stop_propagation();
+                            this.edit_labels(id, cx);
+                        })),
+                )
+                .into_any_element();
+        }
 
         div()
-            .size_full()
-            .bg(theme.tokens.background)
             .flex()
             .flex_col()
+            .gap(px(8.0))
+            .child(
+                Input::new(&self.tags_input)
+                    .placeholder("Tags, e.g. work, datasets")
+                    .prefix(Icon::new("list").size(px(16.0))),
+            )
+            .child(
+                Input::new(&self.note_input)
+                    .placeholder("Note: why was this downloaded?")
+                    .prefix(Icon::new("file").size(px(16.0))),
+            )
             .child(
                 div()
                     .flex()
-                    .items_center()
-                    .gap(px(12.0))
-                    .px(px(24.0))
-                    .py(px(20.0))
-                    .bg(theme.tokens.primary.opacity(0.05))
-                    .border_b_1()
-                    .border_color(theme.tokens.border)
+                    .gap(px(8.0))
                     .child(
-                        div()
-                            .size(px(40.0))
-                            .rounded_full()
-                            .bg(theme.tokens.primary.opacity(0.15))
-                            .flex()
-                            .items_center()
-                            .justify_center()
-                            .child(Icon::new("zap").size(px(20.0)).color(theme.tokens.primary)),
+                        Button::new("save-labels", "Save")
+                            .icon("check")
+                            .on_click(cx.listener(|this, _, _window, cx| {
+                                cx.stop_propagation();
+                                this.save_labels(cx);
+                            })),
                     )
                     .child(
-                        div()
-                            .flex()
-                            .flex_col()
-                            .child(
-                                div()
-                                    .flex()
-                                    .items_center()
-                                    .gap(px(8.0))
-                                    .child(
-                                        div()
-                                            .text_size(px(20.0))
-                                            .font_weight(FontWeight::BOLD)
-                                            .text_color(theme.tokens.foreground)
-                                            .child("StormDL"),
-                                    )
-                                    .child(Badge::new("v0.1.0").variant(BadgeVariant::Secondary)),
-                            )
-                            .child(
-                                div()
-                                    .text_size(px(12.0))
-                                    .text_color(theme.tokens.muted_foreground)
-                                    .child("Lightning-fast parallel downloads"),
-                            ),
+                        Button::new("cancel-labels", "Cancel")
+                            .variant(ButtonVariant::Ghost)
+                            .on_click(cx.listener(|this, _, _window, cx| {
+                                cx.stop_propagation();
+                                this.state.editing_labels = None;
+                                cx.notify();
+                            })),
                     ),
             )
-            .child(
-                div().flex_1().overflow_hidden().child(scrollable_vertical(
+            .into_any_element()
+    }
+
+    fn render_bulk_actions(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        let selected = self.state.selection.len();
+        let completed = self.state.completed_ids().len();
+        if selected == 0 && completed == 0 {
+            return div().into_any_element();
+        }
+
+        let action = |label: &'static str, icon: Option<&'static str>, action: BulkAction| {
+            let button = Button::new(label, label)
+                .variant(ButtonVariant::Outline)
+                .disabled(self.state.bulk_targets(action).is_empty())
+                .on_click(cx.listener(move |this, _, _window, cx| {
+                    this.bulk(action, cx);
+                }));
+            match icon {
+                Some(icon) => button.icon(icon),
+                None => button,
+            }
+        };
+
+        div()
+            .flex()
+            .flex_wrap()
+            .items_center()
+            .gap(px(8.0))
+            .when(selected > 0, |bar| {
+                bar.child(
                     div()
-                        .p(px(24.0))
-                        .flex()
-                        .flex_col()
-                        .gap(px(20.0))
-                        .child(
-                            div()
-                                .flex()
-                                .flex_col()
-                                .gap(px(8.0))
-                                .child(
-                                    div()
-                                        .text_size(px(13.0))
-                                        .font_weight(FontWeight::MEDIUM)
-                                        .text_color(theme.tokens.foreground)
-                                        .child("Download URL"),
-                                )
-                                .child(
-                                    Input::new(&self.url_input)
-                                        .placeholder("https://example.com/file.zip")
-                                        .prefix(
-                                            Icon::new("link")
-                                                .size(px(16.0))
-                                                .color(theme.tokens.muted_foreground),
-                                        )
-                                        .clearable(true),
-                                ),
-                        )
-                        .child(
-                            div()
-                                .flex()
-                                .flex_col()
-                                .gap(px(8.0))
-                                .child(
-                                    div()
-                                        .text_size(px(13.0))
-                                        .font_weight(FontWeight::MEDIUM)
-                                        .text_color(theme.tokens.foreground)
-                                        .child("Save to"),
-                                )
-                                .child(
-                                    div()
-                                        .flex()
-                                        .items_center()
-                                        .gap(px(8.0))
-                                        .child(
-                                            div()
-                                                .flex_1()
-                                                .h(px(40.0))
-                                                .px(px(12.0))
-                                                .bg(theme.tokens.muted.opacity(0.3))
-                                                .border_1()
-                                                .border_color(theme.tokens.border)
-                                                .rounded(theme.tokens.radius_md)
-                                                .flex()
-                                                .items_center()
-                                                .gap(px(8.0))
-                                                .child(
-                                                    Icon::new("folder")
-                                                        .size(px(16.0))
-                                                        .color(theme.tokens.muted_foreground),
-                                                )
-                                                .child(
-                                                    div()
-                                                        .text_size(px(14.0))
-                                                        .text_color(theme.tokens.foreground)
-                                                        .text_ellipsis()
-                                                        .overflow_hidden()
-                                                        .child(
-                                                            self.save_location
-                                                                .to_string_lossy()
-                                                                .to_string(),
-                                                        ),
-                                                ),
-                                        )
-                                        .child(
-                                            Button::new("browse", "Browse")
-                                                .variant(ButtonVariant::Ghost)
-                                                .icon("folder-open")
-                                                .on_click(cx.listener(|this, _, _window, cx| {
-                                                    this.browse_location(cx);
-                                                })),
-                                        ),
-                                ),
-                        )
-                        .child(
-                            Button::new("download", "Download")
-                                .icon("download")
-                                .variant(ButtonVariant::Default)
-                                .on_click(cx.listener(|this, _, _window, cx| {
-                                    this.start_download(cx);
-                                })),
-                        )
-                        .child(self.render_downloads_list()),
-                )),
-            )
+                        .text_size(px(13.0))
+                        .text_color(theme.tokens.muted_foreground)
+                        .child(format!("{} selected", selected)),
+                )
+                .child(action("Pause", None, BulkAction::Pause))
+                .child(action("Resume", None, BulkAction::Resume))
+                .child(action("Cancel", Some("circle-x"), BulkAction::Cancel))
+                .child(action("Remove", Some("x"), BulkAction::Remove))
+                .child(
+                    Button::new("clear-selection", "Deselect")
+                        .variant(ButtonVariant::Ghost)
+                        .on_click(cx.listener(|this, _, _window, cx| {
+                            this.state.clear_selection();
+                            cx.notify();
+                        })),
+                )
+            })
+            .child(div().flex_1())
+            .when(completed > 0, |bar| {
+                bar.child(
+                    Button::new("clear-completed", "Clear completed")
+                        .icon("circle-check")
+                        .variant(ButtonVariant::Ghost)
+                        .on_click(cx.listener(|this, _, _window, cx| {
+                            this.clear_completed(cx);
+                        })),
+                )
+            })
+            .into_any_element()
     }
-}
 
-impl StormApp {
-    fn render_downloads_list(&self) -> impl IntoElement {
+    fn render_downloads_list(&self, cx: &mut Context<Self>) -> impl IntoElement {
         let theme = use_theme();
+        let visible = self.state.visible_downloads();
 
-        if self.state.downloads.is_empty() {
+        if visible.is_empty() {
             return div().into_any_element();
         }
 
-        let download_items: Vec<_> = self
-            .state
-            .downloads
-            .iter()
-            .rev()
+        let download_items: Vec<_> = visible
+            .into_iter()
             .map(|download| {
+                let id = download.id;
+                let queued = self.state.is_queued(id);
+                let selected = self.state.is_selected(id);
+                let priority = download.priority;
+                let file_map = download.file_map.clone();
                 let progress = download.progress();
                 let speed = download.current_speed();
                 let state = download.state;
@@ -322,44 +1398,54 @@ impl StormApp {
                 let downloaded = download.downloaded_bytes;
                 let total = download.total_bytes;
                 let error = download.error.clone();
+                let labels = self.render_labels(download, cx);
+                let contents = render_contents(&download.contents);
 
                 let state_text = match state {
                     DownloadState::Pending => "Pending",
                     DownloadState::Probing => "Probing",
+                    DownloadState::Waiting => "Waiting",
                     DownloadState::Downloading => "Downloading",
                     DownloadState::Paused => "Paused",
+                    DownloadState::Scanning => "Scanning",
                     DownloadState::Complete => "Complete",
                     DownloadState::Failed => "Failed",
+                    DownloadState::Quarantined => "Quarantined",
                     DownloadState::Cancelled => "Cancelled",
                 };
 
                 let badge_variant = if state == DownloadState::Complete {
                     BadgeVariant::Secondary
-                } else if state == DownloadState::Failed {
+                } else if matches!(state, DownloadState::Failed | DownloadState::Quarantined) {
                     BadgeVariant::Destructive
                 } else {
                     BadgeVariant::Outline
                 };
 
-                let status_icon =
-                    if state == DownloadState::Downloading || state == DownloadState::Probing {
-                        Spinner::new().into_any_element()
-                    } else if state == DownloadState::Complete {
-                        Icon::new("circle-check")
-                            .size(px(18.0))
-                            .color(theme.tokens.primary)
-                            .into_any_element()
-                    } else if state == DownloadState::Failed {
-                        Icon::new("circle-x")
-                            .size(px(18.0))
-                            .color(theme.tokens.destructive)
-                            .into_any_element()
-                    } else {
-                        Icon::new("file")
-                            .size(px(18.0))
-                            .color(theme.tokens.muted_foreground)
-                            .into_any_element()
-                    };
+                let status_icon = if matches!(
+                    state,
+                    DownloadState::Probing
+                        | DownloadState::Waiting
+                        | DownloadState::Downloading
+                        | DownloadState::Scanning
+                ) {
+                    Spinner::new().into_any_element()
+                } else if state == DownloadState::Complete {
+                    Icon::new("circle-check")
+                        .size(px(18.0))
+                        .color(theme.tokens.primary)
+                        .into_any_element()
+                } else if matches!(state, DownloadState::Failed | DownloadState::Quarantined) {
+                    Icon::new("circle-x")
+                        .size(px(18.0))
+                        .color(theme.tokens.destructive)
+                        .into_any_element()
+                } else {
+                    Icon::new("file")
+                        .size(px(18.0))
+                        .color(theme.tokens.muted_foreground)
+                        .into_any_element()
+                };
 
                 let speed_display = if state == DownloadState::Downloading && speed > 0.0 {
                     div()
@@ -381,11 +1467,35 @@ impl StormApp {
                     div().into_any_element()
                 };
 
+                let priority_badge = (queued || priority != Priority::Normal)
+                    .then(|| Badge::new(priority_label(priority)).variant(BadgeVariant::Outline));
+                let dragged = DraggedDownload {
+                    id,
+                    filename: filename.clone(),
+                };
+
                 div()
+                    .id(("download", id.0 as usize))
+                    .when(queued, |card| {
+                        card.cursor_grab()
+                            .on_drag(dragged, |dragged, _, _, cx| cx.new(|_| dragged.clone()))
+                            .drag_over::<DraggedDownload>(move |style, _, _, _| {
+                                style.border_color(theme.tokens.primary)
+                            })
+                            .on_drop(cx.listener(
+                                move |this, dragged: &DraggedDownload, _window, cx| {
+                                    this.move_download(dragged.id, id, cx);
+                                },
+                            ))
+                    })
                     .p(px(16.0))
                     .bg(theme.tokens.card)
                     .border_1()
-                    .border_color(theme.tokens.border)
+                    .border_color(if selected {
+                        theme.tokens.primary
+                    } else {
+                        theme.tokens.border
+                    })
                     .rounded(px(12.0))
                     .flex()
                     .flex_col()
@@ -412,7 +1522,14 @@ impl StormApp {
                                             .child(filename),
                                     ),
                             )
-                            .child(Badge::new(state_text).variant(badge_variant)),
+                            .child(
+                                div()
+                                    .flex()
+                                    .items_center()
+                                    .gap(px(6.0))
+                                    .children(priority_badge)
+                                    .child(Badge::new(state_text).variant(badge_variant)),
+                            ),
                     )
                     .child(ProgressBar::new(progress as f32))
                     .child(
@@ -447,7 +1564,16 @@ impl StormApp {
                                     ),
                             ),
                     )
+                    .child(labels)
                     .child(error_display)
+                    .on_click(cx.listener(move |this, event: &ClickEvent, _window, cx| {
+                        this.click_download(id, event.modifiers(), cx);
+                    }))
+                    .when(self.state.selected_download_id == Some(id), |card| {
+                        card.child(self.render_label_editor(id, cx))
+                            .child(contents)
+                            .child(self.render_file_map(file_map.as_ref(), cx))
+                    })
             })
             .collect();
 
@@ -461,6 +1587,414 @@ impl StormApp {
     }
 }
 
+impl StormApp {
+    fn render_link_picker(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+        let Some(picker) = &self.state.link_picker else {
+            return div().into_any_element();
+        };
+
+        let body = if picker.loading {
+            div()
+                .flex()
+                .items_center()
+                .gap(px(8.0))
+                .child(Spinner::new())
+                .child(
+                    div()
+                        .text_size(px(13.0))
+                        .text_color(theme.tokens.muted_foreground)
+                        .child("Fetching page and checking link sizes..."),
+                )
+                .into_any_element()
+        } else if let Some(error) = &picker.error {
+            div()
+                .text_size(px(12.0))
+                .text_color(theme.tokens.destructive)
+                .child(error.clone())
+                .into_any_element()
+        } else if picker.links.is_empty() {
+            div()
+                .text_size(px(13.0))
+                .text_color(theme.tokens.muted_foreground)
+                .child("No downloadable links found on this page")
+                .into_any_element()
+        } else {
+            let entity = cx.entity();
+            let rows: Vec<_> = picker
+                .visible()
+                .into_iter()
+                .map(|idx| {
+                    let link = &picker.links[idx];
+                    let entity = entity.clone();
+                    div()
+                        .flex()
+                        .items_center()
+                        .gap(px(8.0))
+                        .child(
+                            Checkbox::new(("link", idx))
+                                .checked(picker.selected[idx])
+                                .on_click(move |_, _, cx| {
+                                    entity.update(cx, |this, cx| {
+                                        if let Some(picker) = this.state.link_picker.as_mut() {
+                                            picker.toggle(idx);
+                                        }
+                                        cx.notify();
+                                    });
+                                }),
+                        )
+                        .child(
+                            div()
+                                .flex_1()
+                                .text_size(px(13.0))
+                                .text_color(theme.tokens.foreground)
+                                .text_ellipsis()
+                                .overflow_hidden()
+                                .child(link.filename.clone()),
+                        )
+                        .child(
+                            div()
+                                .text_size(px(12.0))
+                                .text_color(theme.tokens.muted_foreground)
+                                .child(
+                                    link.size
+                                        .map(|s| bytesize::ByteSize(s).to_string())
+                                        .unwrap_or("?".into()),
+                                ),
+                        )
+                })
+                .collect();
+
+            let size_buttons: Vec<_> = LINK_SIZE_FILTERS
+                .iter()
+                .enumerate()
+                .map(|(idx, &(label, min_size))| {
+                    let variant = if picker.filter.min_size == min_size {
+                        ButtonVariant::Default
+                    } else {
+                        ButtonVariant::Ghost
+                    };
+                    Button::new(("link-size", idx), label)
+                        .variant(variant)
+                        .on_click(cx.listener(move |this, _, _window, cx| {
+                            if let Some(picker) = this.state.link_picker.as_mut() {
+                                picker.filter = picker.filter.clone().with_min_size(min_size);
+                            }
+                            cx.notify();
+                        }))
+                })
+                .collect();
+
+            div()
+                .flex()
+                .flex_col()
+                .gap(px(8.0))
+                .child(
+                    Input::new(&self.link_filter_input)
+                        .placeholder("Extensions, e.g. zip, iso")
+                        .clearable(true),
+                )
+                .child(
+                    div()
+                        .flex()
+                        .items_center()
+                        .gap(px(4.0))
+                        .children(size_buttons),
+                )
+                .child(
+                    div()
+                        .flex()
+                        .items_center()
+                        .gap(px(8.0))
+                        .child(
+                            Button::new("links-all", "Select all")
+                                .variant(ButtonVariant::Ghost)
+                                .on_click(cx.listener(|this, _, _window, cx| {
+                                    if let Some(picker) = this.state.link_picker.as_mut() {
+                                        picker.select_visible(true);
+                                    }
+                                    cx.notify();
+                                })),
+                        )
+                        .child(
+                            Button::new("links-none", "Select none")
+                                .variant(ButtonVariant::Ghost)
+                                .on_click(cx.listener(|this, _, _window, cx| {
+                                    if let Some(picker) = this.state.link_picker.as_mut() {
+                                        picker.select_visible(false);
+                                    }
+                                    cx.notify();
+                                })),
+                        ),
+                )
+                .child(div().flex().flex_col().gap(px(6.0)).children(rows))
+                .child(
+                    Button::new(
+                        "links-add",
+                        format!("Add {} downloads", picker.chosen().len()),
+                    )
+                    .icon("download")
+                    .variant(ButtonVariant::Default)
+                    .on_click(cx.listener(|this, _, _window, cx| {
+                        this.enqueue_picked_links(cx);
+                    })),
+                )
+                .into_any_element()
+        };
+
+        div()
+            .p(px(16.0))
+            .bg(theme.tokens.card)
+            .border_1()
+            .border_color(theme.tokens.border)
+            .rounded(px(12.0))
+            .flex()
+            .flex_col()
+            .gap(px(12.0))
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .child(
+                        div()
+                            .text_size(px(13.0))
+                            .font_weight(FontWeight::MEDIUM)
+                            .text_color(theme.tokens.foreground)
+                            .text_ellipsis()
+                            .overflow_hidden()
+                            .max_w(px(360.0))
+                            .child(format!("Links on {}", picker.page)),
+                    )
+                    .child(
+                        Button::new("links-close", "Close")
+                            .variant(ButtonVariant::Ghost)
+                            .icon("x")
+                            .on_click(cx.listener(|this, _, _window, cx| {
+                                this.state.link_picker = None;
+                                cx.notify();
+                            })),
+                    ),
+            )
+            .child(body)
+            .into_any_element()
+    }
+
+    fn render_file_map(&self, map: Option<&FileMap>, cx: &mut Context<Self>) -> impl IntoElement {
+        let theme = use_theme();
+
+        let Some(map) = map.filter(|m| m.total() > 0) else {
+            return div()
+                .text_size(px(12.0))
+                .text_color(theme.tokens.muted_foreground)
+                .child("File map unavailable until the size is known")
+                .into_any_element();
+        };
+
+        let span = map.total().div_ceil(self.map_zoom);
+        let start = span * self.map_offset;
+        let view = ByteRange::new(start, (start + span).min(map.total()));
+
+        let cell_color = |cell: &MapCell| {
+            if cell.written >= 1.0 {
+                theme.tokens.primary
+            } else if cell.in_flight {
+                theme.tokens.ring
+            } else if cell.written > 0.0 {
+                theme.tokens.primary.opacity(0.25 + 0.5 * cell.written)
+            } else {
+                theme.tokens.muted
+            }
+        };
+
+        let cells = map.resample(view, MAP_CELLS);
+        let rows: Vec<_> = cells
+            .chunks(MAP_COLUMNS)
+            .map(|row| {
+                div().flex().gap(px(1.0)).children(
+                    row.iter()
+                        .map(|cell| div().size(px(5.0)).rounded(px(1.0)).bg(cell_color(cell))),
+                )
+            })
+            .collect();
+
+        div()
+            .flex()
+            .flex_col()
+            .gap(px(8.0))
+            .pt(px(4.0))
+            .border_t_1()
+            .border_color(theme.tokens.border)
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .justify_between()
+                    .child(
+                        div()
+                            .text_size(px(12.0))
+                            .text_color(theme.tokens.muted_foreground)
+                            .child(format!(
+                                "{} – {} ({}x)",
+                                bytesize::ByteSize(view.start),
+                                bytesize::ByteSize(view.end),
+                                self.map_zoom
+                            )),
+                    )
+                    .child(
+                        div()
+                            .flex()
+                            .gap(px(4.0))
+                            .child(
+                                Button::new("map-pan-back", "‹")
+                                    .variant(ButtonVariant::Ghost)
+                                    .on_click(cx.listener(|this, _, _window, cx| {
+                                        this.pan_map(false, cx);
+                                    })),
+                            )
+                            .child(
+                                Button::new("map-zoom-out", "−")
+                                    .variant(ButtonVariant::Ghost)
+                                    .on_click(cx.listener(|this, _, _window, cx| {
+                                        this.zoom_map(false, cx);
+                                    })),
+                            )
+                            .child(
+                                Button::new("map-zoom-in", "+")
+                                    .variant(ButtonVariant::Ghost)
+                                    .on_click(cx.listener(|this, _, _window, cx| {
+                                        this.zoom_map(true, cx);
+                                    })),
+                            )
+                            .child(
+                                Button::new("map-pan-forward", "›")
+                                    .variant(ButtonVariant::Ghost)
+                                    .on_click(cx.listener(|this, _, _window, cx| {
+                                        this.pan_map(true, cx);
+                                    })),
+                            ),
+                    ),
+            )
+            .child(div().flex().flex_col().gap(px(1.0)).children(rows))
+            .into_any_element()
+    }
+}
+
+fn render_contents(entries: &[ArchiveEntry]) -> AnyElement {
+    let theme = use_theme();
+    if entries.is_empty() {
+        return div().into_any_element();
+    }
+
+    let folders = entries.iter().filter(|e| e.is_dir).count();
+    let rows = entries.iter().take(CONTENTS_ROWS).map(|entry| {
+        let size = if entry.is_dir {
+            String::new()
+        } else {
+            bytesize::ByteSize(entry.size).to_string()
+        };
+        div()
+            .flex()
+            .items_center()
+            .justify_between()
+            .gap(px(12.0))
+            .text_size(px(12.0))
+            .child(
+                div()
+                    .flex()
+                    .items_center()
+                    .gap(px(6.0))
+                    .overflow_hidden()
+                    .child(Icon::new(if entry.is_dir { "folder" } else { "file" }).size(px(12.0)))
+                    .child(
+                        div()
+                            .text_color(theme.tokens.foreground)
+                            .text_ellipsis()
+                            .overflow_hidden()
+                            .child(entry.path.clone()),
+                    ),
+            )
+            .child(
+                div()
+                    .flex_shrink_0()
+                    .text_color(theme.tokens.muted_foreground)
+                    .child(size),
+            )
+    });
+    let more = entries.len().saturating_sub(CONTENTS_ROWS);
+
+    div()
+        .flex()
+        .flex_col()
+        .gap(px(4.0))
+        .pt(px(4.0))
+        .border_t_1()
+        .border_color(theme.tokens.border)
+        .child(
+            div()
+                .text_size(px(12.0))
+                .font_weight(FontWeight::MEDIUM)
+                .text_color(theme.tokens.muted_foreground)
+                .child(format!(
+                    "Archive contents: {} files, {} folders",
+                    entries.len() - folders,
+                    folders
+                )),
+        )
+        .children(rows)
+        .when(more > 0, |list| {
+            list.child(
+                div()
+                    .text_size(px(12.0))
+                    .text_color(theme.tokens.muted_foreground)
+                    .child(format!("…and {} more", more)),
+            )
+        })
+        .into_any_element()
+}
+
+fn render_quota(quota: QuotaStatus) -> impl IntoElement {
+    let theme = use_theme();
+    let (variant, color) = match quota.level {
+        QuotaLevel::Ok => (BadgeVariant::Outline, theme.tokens.muted_foreground),
+        QuotaLevel::Warning => (BadgeVariant::Secondary, theme.tokens.foreground),
+        QuotaLevel::Exceeded => (BadgeVariant::Destructive, theme.tokens.destructive),
+    };
+    let label = match quota.limit {
+        Some(limit) => format!(
+            "{} / {} this month",
+            bytesize::ByteSize(quota.used),
+            bytesize::ByteSize(limit)
+        ),
+        None => format!("{} this month", bytesize::ByteSize(quota.used)),
+    };
+
+    div()
+        .flex()
+        .items_center()
+        .gap(px(6.0))
+        .child(Icon::new("gauge").size(px(14.0)).color(color))
+        .child(Badge::new(label).variant(variant))
+}
+
+fn profile_label(profile: SpeedProfile) -> &'static str {
+    match profile {
+        SpeedProfile::Gentle => "Gentle",
+        SpeedProfile::Balanced => "Balanced",
+        SpeedProfile::Turbo => "Turbo",
+    }
+}
+
+fn priority_label(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Critical => "Critical",
+        Priority::High => "High",
+        Priority::Normal => "Normal",
+        Priority::Low => "Low",
+        Priority::Background => "Background",
+    }
+}
+
 struct Assets {
     base_path: PathBuf,
 }
@@ -507,7 +2041,13 @@ impl AssetSource for Assets {
     }
 }
 
-pub fn run_app(command_tx: Sender<OrchestratorCommand>, event_rx: Receiver<DownloadEvent>) {
+pub fn run_app(
+    command_tx: Sender<OrchestratorCommand>,
+    event_rx: Receiver<DownloadEvent>,
+    groups: Vec<DownloadGroup>,
+    history: Vec<String>,
+    setup: Option<SetupChoices>,
+) {
     Application::new()
         .with_assets(Assets::new())
         .run(move |cx| {
@@ -527,10 +2067,15 @@ pub fn run_app(command_tx: Sender<OrchestratorCommand>, event_rx: Receiver<Downl
                     window_bounds: Some(WindowBounds::Windowed(bounds)),
                     ..Default::default()
                 },
-                |_, cx| {
+                |window, cx| {
                     let command_tx = command_tx.clone();
                     let event_rx = event_rx.clone();
-                    cx.new(|cx| StormApp::new(command_tx, event_rx, cx))
+                    let groups = groups.clone();
+                    let history = history.clone();
+                    let setup = setup.clone();
+                    cx.new(|cx| {
+                        StormApp::new(command_tx, event_rx, groups, history, setup, window, cx)
+                    })
                 },
             )
             .unwrap();
@@ -0,0 +1,136 @@
+use url::Url;
+
+const MAX_HISTORY: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionSource {
+    Clipboard,
+    History,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub url: String,
+    pub source: SuggestionSource,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UrlHistory {
+    urls: Vec<String>,
+}
+
+impl UrlHistory {
+    pub fn new(urls: Vec<String>) -> Self {
+        let mut history = Self::default();
+        for url in urls.into_iter().rev() {
+            history.record(&url);
+        }
+        history
+    }
+
+    pub fn record(&mut self, url: &str) {
+        self.urls.retain(|u| u != url);
+        self.urls.insert(0, url.to_string());
+        self.urls.truncate(MAX_HISTORY);
+    }
+
+    pub fn len(&self) -> usize {
+        self.urls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.urls.is_empty()
+    }
+
+    pub fn suggestions(
+        &self,
+        query: &str,
+        clipboard: Option<&str>,
+        limit: usize,
+    ) -> Vec<Suggestion> {
+        let query = query.trim();
+        let mut suggestions = Vec::new();
+
+        if let Some(clip) = clipboard.map(str::trim).filter(|c| is_download_url(c))
+            && clip != query
+            && (query.is_empty() || fuzzy_score(query, clip).is_some())
+        {
+            suggestions.push(Suggestion {
+                url: clip.to_string(),
+                source: SuggestionSource::Clipboard,
+            });
+        }
+
+        if !query.is_empty() {
+            let mut scored: Vec<(i64, usize, &String)> = self
+                .urls
+                .iter()
+                .enumerate()
+                .filter(|(_, url)| url.as_str() != query && Some(url.as_str()) != clipboard)
+                .filter_map(|(recency, url)| {
+                    fuzzy_score(query, url).map(|score| (score, recency, url))
+                })
+                .collect();
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+            suggestions.extend(scored.into_iter().map(|(_, _, url)| Suggestion {
+                url: url.clone(),
+                source: SuggestionSource::History,
+            }));
+        }
+
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+pub fn is_download_url(s: &str) -> bool {
+    Url::parse(s).is_ok_and(|url| matches!(url.scheme(), "http" | "https" | "ftp"))
+}
+
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let haystack: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0i64;
+    let mut qi = 0;
+    let mut last_match: Option<usize> = None;
+
+    for (hi, &c) in haystack.iter().enumerate() {
+        if qi == query.len() {
+            break;
+        }
+        if c != query[qi] {
+            continue;
+        }
+
+        score += 1;
+        match last_match {
+            Some(prev) if prev + 1 == hi => score += 8,
+            Some(prev) => score -= (hi - prev - 1).min(8) as i64,
+            None => {}
+        }
+        if hi == 0 || matches!(haystack[hi - 1], '/' | '.' | '-' | '_' | '?' | '=' | '&') {
+            score += 4;
+        }
+
+        last_match = Some(hi);
+        qi += 1;
+    }
+
+    if qi < query.len() {
+        return None;
+    }
+
+    let needle: String = query.iter().collect();
+    let lowered: String = haystack.iter().collect();
+    if lowered.contains(&needle) {
+        score += 2 * query.len() as i64;
+    }
+
+    Some(score)
+}
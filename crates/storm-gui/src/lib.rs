@@ -1,7 +1,9 @@
 mod app;
+mod autocomplete;
 mod state;
 
 pub mod components;
 
 pub use app::run_app;
+pub use autocomplete::{Suggestion, SuggestionSource, UrlHistory, fuzzy_score};
 pub use state::{AppState, Download, DownloadEvent, OrchestratorCommand};
@@ -1,55 +1,14 @@
+use crate::autocomplete::UrlHistory;
 use flume::{Receiver, Sender};
 use smallvec::SmallVec;
 use std::path::PathBuf;
-use stormdl_core::{DownloadId, DownloadOptions, DownloadState, SegmentState};
+use stormdl_core::{
+    ArchiveEntry, Calibration, DownloadGroup, DownloadId, DownloadState, FileMap, LinkFilter,
+    PageLink, Priority, QuotaLevel, SegmentState, SetupChoices, SpeedProfile,
+};
+pub use stormdl_core::{DownloadEvent, OrchestratorCommand};
 use url::Url;
 
-#[derive(Debug, Clone)]
-pub enum OrchestratorCommand {
-    AddDownload { url: Url, options: DownloadOptions },
-    PauseDownload(DownloadId),
-    ResumeDownload(DownloadId),
-    CancelDownload(DownloadId),
-    SetBandwidthLimit(Option<u64>),
-}
-
-#[derive(Debug, Clone)]
-pub enum DownloadEvent {
-    DownloadAdded {
-        id: DownloadId,
-        url: Url,
-        filename: String,
-        total_size: Option<u64>,
-    },
-    ProgressUpdate {
-        id: DownloadId,
-        downloaded: u64,
-        segments: Vec<SegmentState>,
-    },
-    SpeedUpdate {
-        id: DownloadId,
-        speed: f64,
-    },
-    StateChange {
-        id: DownloadId,
-        state: DownloadState,
-    },
-    SegmentRebalanced {
-        id: DownloadId,
-        old_count: usize,
-        new_count: usize,
-    },
-    Error {
-        id: DownloadId,
-        error: String,
-    },
-    Complete {
-        id: DownloadId,
-        path: PathBuf,
-        hash: String,
-    },
-}
-
 #[derive(Debug, Clone)]
 pub struct Download {
     pub id: DownloadId,
@@ -61,6 +20,12 @@ pub struct Download {
     pub segments: Vec<SegmentState>,
     pub speed_samples: SmallVec<[f64; 30]>,
     pub error: Option<String>,
+    pub group: Option<String>,
+    pub priority: Priority,
+    pub file_map: Option<FileMap>,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub contents: Vec<ArchiveEntry>,
 }
 
 impl Download {
@@ -75,6 +40,12 @@ impl Download {
             segments: Vec::new(),
             speed_samples: SmallVec::new(),
             error: None,
+            group: None,
+            priority: Priority::Normal,
+            file_map: None,
+            tags: Vec::new(),
+            note: None,
+            contents: Vec::new(),
         }
     }
 
@@ -107,9 +78,27 @@ impl Download {
 pub struct AppState {
     pub downloads: Vec<Download>,
     pub selected_download_id: Option<DownloadId>,
+    pub selection: Vec<DownloadId>,
+    pub selection_anchor: Option<DownloadId>,
     pub command_tx: Sender<OrchestratorCommand>,
     pub event_rx: Receiver<DownloadEvent>,
     pub settings: Settings,
+    pub groups: Vec<DownloadGroup>,
+    pub active_group: Option<String>,
+    pub tag_filter: Option<String>,
+    pub editing_labels: Option<DownloadId>,
+    pub history: UrlHistory,
+    pub queue_order: Vec<DownloadId>,
+    pub quota: Option<QuotaStatus>,
+    pub link_picker: Option<LinkPicker>,
+    pub onboarding: Option<Onboarding>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaStatus {
+    pub used: u64,
+    pub limit: Option<u64>,
+    pub level: QuotaLevel,
 }
 
 #[derive(Debug, Clone)]
@@ -119,6 +108,172 @@ pub struct Settings {
     pub max_segments: usize,
     pub bandwidth_limit: Option<u64>,
     pub turbo_mode: bool,
+    pub watch_clipboard: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkPicker {
+    pub page: Url,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub links: Vec<PageLink>,
+    pub selected: Vec<bool>,
+    pub filter: LinkFilter,
+}
+
+impl LinkPicker {
+    pub fn new(page: Url) -> Self {
+        Self {
+            page,
+            loading: true,
+            error: None,
+            links: Vec::new(),
+            selected: Vec::new(),
+            filter: LinkFilter::default(),
+        }
+    }
+
+    pub fn visible(&self) -> Vec<usize> {
+        (0..self.links.len())
+            .filter(|&idx| self.filter.matches(&self.links[idx]))
+            .collect()
+    }
+
+    pub fn toggle(&mut self, idx: usize) {
+        if let Some(selected) = self.selected.get_mut(idx) {
+            *selected = !*selected;
+        }
+    }
+
+    pub fn select_visible(&mut self, selected: bool) {
+        for idx in self.visible() {
+            self.selected[idx] = selected;
+        }
+    }
+
+    pub fn chosen(&self) -> Vec<PageLink> {
+        self.visible()
+            .into_iter()
+            .filter(|&idx| self.selected[idx])
+            .map(|idx| self.links[idx].clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    Replace,
+    Toggle,
+    Extend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkAction {
+    Pause,
+    Resume,
+    Cancel,
+    Remove,
+}
+
+impl BulkAction {
+    pub fn applies_to(&self, state: DownloadState) -> bool {
+        match self {
+            Self::Pause => matches!(state, DownloadState::Probing | DownloadState::Downloading),
+            Self::Resume => state == DownloadState::Paused,
+            Self::Cancel => matches!(
+                state,
+                DownloadState::Pending
+                    | DownloadState::Probing
+                    | DownloadState::Waiting
+                    | DownloadState::Downloading
+                    | DownloadState::Paused
+            ),
+            Self::Remove => true,
+        }
+    }
+
+    pub fn command(&self, id: DownloadId) -> OrchestratorCommand {
+        match self {
+            Self::Pause => OrchestratorCommand::PauseDownload(id),
+            Self::Resume => OrchestratorCommand::ResumeDownload(id),
+            Self::Cancel => OrchestratorCommand::CancelDownload(id),
+            Self::Remove => OrchestratorCommand::RemoveDownload(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnboardingStep {
+    Folder,
+    Calibrate,
+    Profile,
+    Clipboard,
+}
+
+impl OnboardingStep {
+    pub const ALL: [OnboardingStep; 4] = [
+        Self::Folder,
+        Self::Calibrate,
+        Self::Profile,
+        Self::Clipboard,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Folder => "Where should downloads go?",
+            Self::Calibrate => "Measure your connection",
+            Self::Profile => "Pick a default speed profile",
+            Self::Clipboard => "Watch the clipboard?",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        Self::ALL.iter().position(|s| s == self).unwrap_or(0)
+    }
+
+    pub fn next(&self) -> Option<Self> {
+        Self::ALL.get(self.index() + 1).copied()
+    }
+
+    pub fn prev(&self) -> Option<Self> {
+        self.index().checked_sub(1).map(|idx| Self::ALL[idx])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Onboarding {
+    pub step: OnboardingStep,
+    pub download_dir: PathBuf,
+    pub calibrating: Option<Url>,
+    pub calibration: Option<Calibration>,
+    pub error: Option<String>,
+    pub profile: SpeedProfile,
+    pub watch_clipboard: bool,
+    pub saving: bool,
+}
+
+impl Onboarding {
+    pub fn new(download_dir: PathBuf) -> Self {
+        Self {
+            step: OnboardingStep::Folder,
+            download_dir,
+            calibrating: None,
+            calibration: None,
+            error: None,
+            profile: SpeedProfile::default(),
+            watch_clipboard: false,
+            saving: false,
+        }
+    }
+
+    pub fn choices(&self) -> SetupChoices {
+        SetupChoices {
+            download_dir: self.download_dir.clone(),
+            profile: self.profile,
+            watch_clipboard: self.watch_clipboard,
+            calibration: self.calibration,
+        }
+    }
 }
 
 impl Default for Settings {
@@ -129,6 +284,7 @@ impl Default for Settings {
             max_segments: 32,
             bandwidth_limit: None,
             turbo_mode: false,
+            watch_clipboard: false,
         }
     }
 }
@@ -138,30 +294,261 @@ impl AppState {
         Self {
             downloads: Vec::new(),
             selected_download_id: None,
+            selection: Vec::new(),
+            selection_anchor: None,
             command_tx,
             event_rx,
             settings: Settings::default(),
+            groups: Vec::new(),
+            active_group: None,
+            tag_filter: None,
+            editing_labels: None,
+            history: UrlHistory::default(),
+            queue_order: Vec::new(),
+            quota: None,
+            link_picker: None,
+            onboarding: None,
         }
     }
 
+    pub fn active_group(&self) -> Option<&DownloadGroup> {
+        let name = self.active_group.as_ref()?;
+        self.groups.iter().find(|g| &g.name == name)
+    }
+
+    pub fn is_queued(&self, id: DownloadId) -> bool {
+        self.queue_order.contains(&id)
+    }
+
+    pub fn visible_downloads(&self) -> Vec<&Download> {
+        let in_group = |d: &&Download| {
+            let group = match &self.active_group {
+                Some(name) => d.group.as_ref() == Some(name),
+                None => true,
+            };
+            group
+                && self
+                    .tag_filter
+                    .as_ref()
+                    .is_none_or(|tag| d.tags.contains(tag))
+        };
+
+        let queued = self
+            .queue_order
+            .iter()
+            .filter_map(|id| self.get_download(*id))
+            .filter(in_group);
+        let rest = self
+            .downloads
+            .iter()
+            .rev()
+            .filter(|d| !self.is_queued(d.id))
+            .filter(in_group);
+
+        queued.chain(rest).collect()
+    }
+
+    pub fn is_selected(&self, id: DownloadId) -> bool {
+        self.selection.contains(&id)
+    }
+
+    pub fn select(&mut self, id: DownloadId, mode: SelectMode) {
+        let visible: Vec<DownloadId> = self.visible_downloads().iter().map(|d| d.id).collect();
+        let position = |id: Option<DownloadId>| visible.iter().position(|v| Some(*v) == id);
+
+        match (mode, position(self.selection_anchor), position(Some(id))) {
+            (SelectMode::Extend, Some(anchor), Some(target)) => {
+                let (from, to) = (anchor.min(target), anchor.max(target));
+                self.selection = visible[from..=to].to_vec();
+                return;
+            }
+            (SelectMode::Toggle, ..) => {
+                if let Some(idx) = self.selection.iter().position(|s| *s == id) {
+                    self.selection.remove(idx);
+                } else {
+                    self.selection.push(id);
+                }
+            }
+            _ => self.selection = vec![id],
+        }
+        self.selection_anchor = Some(id);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+        self.selection_anchor = None;
+    }
+
+    pub fn bulk_targets(&self, action: BulkAction) -> Vec<DownloadId> {
+        self.selection
+            .iter()
+            .filter_map(|id| self.get_download(*id))
+            .filter(|d| action.applies_to(d.state))
+            .map(|d| d.id)
+            .collect()
+    }
+
+    pub fn completed_ids(&self) -> Vec<DownloadId> {
+        self.visible_downloads()
+            .iter()
+            .filter(|d| d.state == DownloadState::Complete)
+            .map(|d| d.id)
+            .collect()
+    }
+
     pub fn add_download(
         &mut self,
         id: DownloadId,
         url: Url,
         filename: String,
         total_bytes: Option<u64>,
+        group: Option<String>,
     ) {
         if let Some(existing) = self.get_download_mut(id) {
             existing.filename = filename;
             if total_bytes.is_some() {
                 existing.total_bytes = total_bytes;
             }
+            if group.is_some() {
+                existing.group = group;
+            }
         } else {
-            let download = Download::new(id, url, filename, total_bytes);
+            let mut download = Download::new(id, url, filename, total_bytes);
+            download.group = group;
             self.downloads.push(download);
         }
     }
 
+    pub fn apply_events(&mut self, events: impl IntoIterator<Item = DownloadEvent>) {
+        for event in events {
+            self.apply_event(event);
+        }
+    }
+
+    pub fn apply_event(&mut self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::DownloadAdded {
+                id,
+                url,
+                filename,
+                total_size,
+                group,
+            } => {
+                self.add_download(id, url, filename, total_size, group);
+            }
+            DownloadEvent::ProgressUpdate {
+                id,
+                downloaded,
+                segments,
+            } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.downloaded_bytes = downloaded;
+                    SegmentState::merge(&mut download.segments, segments);
+                }
+            }
+            DownloadEvent::SpeedUpdate { id, speed } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.add_speed_sample(speed);
+                }
+            }
+            DownloadEvent::StateChange { id, state } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.state = state;
+                }
+            }
+            DownloadEvent::SegmentRebalanced { .. } => {}
+            DownloadEvent::Error { id, error } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.error = Some(error);
+                    download.state = DownloadState::Failed;
+                }
+            }
+            DownloadEvent::Complete { id, .. } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.state = DownloadState::Complete;
+                }
+            }
+            DownloadEvent::PriorityChanged { id, priority } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.priority = priority;
+                }
+            }
+            DownloadEvent::QueueChanged { order } => {
+                self.queue_order = order;
+            }
+            DownloadEvent::LabelsChanged { id, tags, note } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.tags = tags;
+                    download.note = note;
+                }
+            }
+            DownloadEvent::ArchiveListed { id, entries } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.contents = entries;
+                }
+            }
+            DownloadEvent::QuotaUpdate { used, limit, level } => {
+                self.quota = Some(QuotaStatus { used, limit, level });
+            }
+            DownloadEvent::PageLinks { page, links } => {
+                if let Some(picker) = self.link_picker.as_mut().filter(|p| p.page == page) {
+                    picker.loading = false;
+                    picker.selected = vec![false; links.len()];
+                    picker.links = links;
+                }
+            }
+            DownloadEvent::PageLinksFailed { page, error } => {
+                if let Some(picker) = self.link_picker.as_mut().filter(|p| p.page == page) {
+                    picker.loading = false;
+                    picker.error = Some(error);
+                }
+            }
+            DownloadEvent::FileMapUpdate { id, map } => {
+                if let Some(download) = self.get_download_mut(id) {
+                    download.file_map = Some(map);
+                }
+            }
+            DownloadEvent::CalibrationFinished { url, calibration } => {
+                if let Some(onboarding) = self
+                    .onboarding
+                    .as_mut()
+                    .filter(|o| o.calibrating.as_ref() == Some(&url))
+                {
+                    onboarding.calibrating = None;
+                    onboarding.calibration = Some(calibration);
+                    onboarding.profile = SpeedProfile::suggest(&calibration);
+                }
+            }
+            DownloadEvent::CalibrationFailed { url, error } => {
+                if let Some(onboarding) = self
+                    .onboarding
+                    .as_mut()
+                    .filter(|o| o.calibrating.as_ref() == Some(&url))
+                {
+                    onboarding.calibrating = None;
+                    onboarding.error = Some(error);
+                }
+            }
+            DownloadEvent::SetupSaved { .. } => {
+                if let Some(onboarding) = self.onboarding.take() {
+                    self.apply_setup(&onboarding.choices());
+                }
+            }
+            DownloadEvent::SetupFailed { error } => {
+                if let Some(onboarding) = self.onboarding.as_mut() {
+                    onboarding.saving = false;
+                    onboarding.error = Some(error);
+                }
+            }
+        }
+    }
+
+    pub fn apply_setup(&mut self, choices: &SetupChoices) {
+        self.settings.download_dir = choices.download_dir.clone();
+        self.settings.turbo_mode = choices.profile == SpeedProfile::Turbo;
+        self.settings.watch_clipboard = choices.watch_clipboard;
+    }
+
     pub fn get_download(&self, id: DownloadId) -> Option<&Download> {
         self.downloads.iter().find(|d| d.id == id)
     }
@@ -172,6 +559,11 @@ impl AppState {
 
     pub fn remove_download(&mut self, id: DownloadId) {
         self.downloads.retain(|d| d.id != id);
+        self.queue_order.retain(|d| *d != id);
+        self.selection.retain(|d| *d != id);
+        if self.selection_anchor == Some(id) {
+            self.selection_anchor = None;
+        }
         if self.selected_download_id == Some(id) {
             self.selected_download_id = None;
         }
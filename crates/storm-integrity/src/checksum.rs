@@ -0,0 +1,108 @@
+use crate::hasher::HashAlgorithm;
+use std::fmt;
+use std::str::FromStr;
+use stormdl_core::StormError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumSpec {
+    algorithms: Vec<HashAlgorithm>,
+    digest: String,
+}
+
+impl ChecksumSpec {
+    pub fn parse(checksum: &str) -> Result<Self, StormError> {
+        let checksum = checksum.trim();
+        let (algorithms, digest) = match checksum.split_once([':', '=']) {
+            Some((name, digest)) => (vec![name.parse::<HashAlgorithm>()?], digest.trim()),
+            None => {
+                let algorithms = match checksum.len() {
+                    32 => vec![HashAlgorithm::Md5],
+                    40 => vec![HashAlgorithm::Sha1],
+                    64 => vec![HashAlgorithm::Sha256, HashAlgorithm::Blake3],
+                    _ => Vec::new(),
+                };
+                (algorithms, checksum)
+            }
+        };
+
+        let valid = !algorithms.is_empty()
+            && algorithms.iter().all(|a| a.hex_len() == digest.len())
+            && digest.chars().all(|c| c.is_ascii_hexdigit());
+        if !valid {
+            return Err(StormError::Config(format!(
+                "unrecognized checksum '{}'; expected a SHA-256, SHA-1, MD5 or BLAKE3 hex digest, optionally prefixed like sha256:",
+                checksum
+            )));
+        }
+        Ok(Self {
+            algorithms,
+            digest: digest.to_ascii_lowercase(),
+        })
+    }
+
+    pub fn algorithms(&self) -> &[HashAlgorithm] {
+        &self.algorithms
+    }
+
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+}
+
+impl FromStr for ChecksumSpec {
+    type Err = StormError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for ChecksumSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.algorithms.as_slice() {
+            [algorithm] => write!(f, "{}:{}", algorithm.prefix(), self.digest),
+            _ => f.write_str(&self.digest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_prefix_or_digest_length() {
+        let sha256 = "B94D27B9934D3E08A52E52D7DA7DABFAC484EFE37A5380EE9088F7ACE2EFCDE9";
+        let bare = ChecksumSpec::parse(sha256).unwrap();
+        assert_eq!(
+            bare.algorithms(),
+            [HashAlgorithm::Sha256, HashAlgorithm::Blake3]
+        );
+        assert_eq!(bare.to_string(), sha256.to_ascii_lowercase());
+
+        let md5: ChecksumSpec = " MD5=5eb63bbbe01eeed093cb22bb8f5acdc3".parse().unwrap();
+        assert_eq!(md5.algorithms(), [HashAlgorithm::Md5]);
+        assert_eq!(md5.to_string(), "md5:5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(ChecksumSpec::parse(&md5.to_string()).unwrap(), md5);
+
+        let blake3 = ChecksumSpec::parse(&format!("blake3:{}", sha256)).unwrap();
+        assert_eq!(blake3.algorithms(), [HashAlgorithm::Blake3]);
+        assert_eq!(
+            ChecksumSpec::parse("5eb63bbbe01eeed093cb22bb8f5acdc3")
+                .unwrap()
+                .algorithms(),
+            [HashAlgorithm::Md5]
+        );
+        assert_eq!(
+            ChecksumSpec::parse("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed")
+                .unwrap()
+                .algorithms(),
+            [HashAlgorithm::Sha1]
+        );
+
+        assert!(ChecksumSpec::parse("sha-256:abc").is_err());
+        assert!(ChecksumSpec::parse(&format!("md5:{}", sha256)).is_err());
+        assert!(ChecksumSpec::parse("crc32:12345678").is_err());
+        assert!(ChecksumSpec::parse("not a hash").is_err());
+    }
+}
@@ -1,31 +1,139 @@
-use blake3::Hasher;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use stormdl_core::StormError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Blake3,
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Blake3 => "BLAKE3",
+            Self::Sha256 => "SHA-256",
+            Self::Sha1 => "SHA-1",
+            Self::Md5 => "MD5",
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Blake3 => "blake3",
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+            Self::Md5 => "md5",
+        }
+    }
 
+    pub fn hex_len(&self) -> usize {
+        match self {
+            Self::Blake3 | Self::Sha256 => 64,
+            Self::Sha1 => 40,
+            Self::Md5 => 32,
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = StormError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "blake3" | "b3" => Ok(Self::Blake3),
+            "sha256" => Ok(Self::Sha256),
+            "sha1" => Ok(Self::Sha1),
+            "md5" => Ok(Self::Md5),
+            _ => Err(StormError::Config(format!(
+                "unsupported hash algorithm '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone)]
+enum State {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+    Sha1(Sha1),
+    Md5(Md5),
+}
+
+impl State {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Self::Blake3(Box::default()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            HashAlgorithm::Md5 => Self::Md5(Md5::new()),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct IncrementalHasher {
-    hasher: Hasher,
+    state: State,
     bytes_hashed: u64,
 }
 
 impl IncrementalHasher {
     pub fn new() -> Self {
+        Self::with_algorithm(HashAlgorithm::Blake3)
+    }
+
+    pub fn with_algorithm(algorithm: HashAlgorithm) -> Self {
         Self {
-            hasher: Hasher::new(),
+            state: State::new(algorithm),
             bytes_hashed: 0,
         }
     }
 
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self.state {
+            State::Blake3(_) => HashAlgorithm::Blake3,
+            State::Sha256(_) => HashAlgorithm::Sha256,
+            State::Sha1(_) => HashAlgorithm::Sha1,
+            State::Md5(_) => HashAlgorithm::Md5,
+        }
+    }
+
     pub fn update(&mut self, data: &[u8]) {
-        self.hasher.update(data);
+        match &mut self.state {
+            State::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            State::Sha256(hasher) => hasher.update(data),
+            State::Sha1(hasher) => hasher.update(data),
+            State::Md5(hasher) => hasher.update(data),
+        }
         self.bytes_hashed += data.len() as u64;
     }
 
     pub fn finalize(&self) -> String {
-        self.hasher.finalize().to_hex().to_string()
+        match &self.state {
+            State::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            State::Sha256(hasher) => format!("{:x}", hasher.clone().finalize()),
+            State::Sha1(hasher) => format!("{:x}", hasher.clone().finalize()),
+            State::Md5(hasher) => format!("{:x}", hasher.clone().finalize()),
+        }
     }
 
     pub fn finalize_reset(&mut self) -> String {
         let hash = self.finalize();
-        self.hasher.reset();
-        self.bytes_hashed = 0;
+        self.reset();
         hash
     }
 
@@ -34,7 +142,7 @@ impl IncrementalHasher {
     }
 
     pub fn reset(&mut self) {
-        self.hasher.reset();
+        self.state = State::new(self.algorithm());
         self.bytes_hashed = 0;
     }
 }
@@ -49,6 +157,12 @@ pub fn hash_bytes(data: &[u8]) -> String {
     blake3::hash(data).to_hex().to_string()
 }
 
+pub fn hash_bytes_with(algorithm: HashAlgorithm, data: &[u8]) -> String {
+    let mut hasher = IncrementalHasher::with_algorithm(algorithm);
+    hasher.update(data);
+    hasher.finalize()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,4 +190,33 @@ mod tests {
 
         assert_eq!(hash1, hash2);
     }
+
+    #[test]
+    fn test_sha256_and_md5() {
+        let mut sha256 = IncrementalHasher::with_algorithm(HashAlgorithm::Sha256);
+        sha256.update(b"hello ");
+        sha256.update(b"world");
+        assert_eq!(
+            sha256.finalize_reset(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(sha256.algorithm(), HashAlgorithm::Sha256);
+
+        assert_eq!(
+            hash_bytes_with(HashAlgorithm::Md5, b"hello world"),
+            "5eb63bbbe01eeed093cb22bb8f5acdc3"
+        );
+        assert_eq!(
+            hash_bytes_with(HashAlgorithm::Sha1, b"hello world"),
+            "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"
+        );
+        assert_eq!(
+            "SHA-256".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::Sha256
+        );
+        assert_eq!(
+            "sha-1".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::Sha1
+        );
+    }
 }
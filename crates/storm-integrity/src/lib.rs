@@ -1,5 +1,24 @@
+mod checksum;
 mod hasher;
+mod openpgp;
+mod password;
+mod scan;
+mod signature;
+mod stream;
+mod sums;
 mod verify;
 
-pub use hasher::IncrementalHasher;
-pub use verify::{ContentVerifier, verify_content, verify_file};
+pub use checksum::ChecksumSpec;
+pub use hasher::{HashAlgorithm, IncrementalHasher, hash_bytes, hash_bytes_with};
+pub use password::{hash_password, verify_password};
+pub use scan::{ScanVerdict, Scanner};
+pub use signature::{
+    PublicKey, SignatureCheck, discover_signature, fetch_signature, signature_urls,
+    verify_signature, verify_signature_file, verify_signature_path,
+};
+pub use stream::{HashedReader, StreamVerifier};
+pub use sums::{
+    ChecksumEntry, ChecksumFile, companion_urls, discover_checksum, fetch_checksum,
+    fetch_checksum_file,
+};
+pub use verify::{ContentVerifier, verify_content, verify_file, verify_path};
@@ -0,0 +1,532 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::digest::{self, Context, SHA1_FOR_LEGACY_USE_ONLY, SHA256, SHA384, SHA512};
+use ring::signature::{ED25519, UnparsedPublicKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use stormdl_core::StormError;
+
+const TAG_SIGNATURE: u8 = 2;
+const TAG_PUBLIC_KEY: u8 = 6;
+const TAG_PUBLIC_SUBKEY: u8 = 14;
+const ALGO_RSA: [u8; 3] = [1, 2, 3];
+const ALGO_EDDSA: u8 = 22;
+const ED25519_OID: [u8; 9] = [0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01];
+const SUBPACKET_ISSUER: u8 = 16;
+const SUBPACKET_ISSUER_FINGERPRINT: u8 = 33;
+// The same bounds as ring's RSA_PKCS1_2048_8192 verification algorithms.
+const RSA_MIN_BITS: usize = 2048;
+const RSA_MAX_BITS: usize = 8192;
+
+enum KeyMaterial {
+    Rsa(RsaPublicKey),
+    Ed25519([u8; 32]),
+}
+
+pub(crate) struct PgpKey {
+    fingerprint: [u8; 20],
+    material: KeyMaterial,
+}
+
+impl PgpKey {
+    pub(crate) fn fingerprint(&self) -> String {
+        hex(&self.fingerprint)
+    }
+}
+
+enum SignatureMaterial {
+    Rsa(Vec<u8>),
+    Ed25519([u8; 64]),
+}
+
+pub(crate) struct PgpSignature {
+    hash: &'static digest::Algorithm,
+    hashed: Vec<u8>,
+    left16: [u8; 2],
+    issuer: Option<[u8; 8]>,
+    material: SignatureMaterial,
+}
+
+impl PgpSignature {
+    pub(crate) fn hasher(&self) -> Context {
+        Context::new(self.hash)
+    }
+
+    // `hasher` has seen the signed file; the signature also covers its own
+    // hashed subpackets and a v4 trailer.
+    pub(crate) fn verify(
+        &self,
+        mut hasher: Context,
+        keys: &[PgpKey],
+    ) -> Result<String, StormError> {
+        hasher.update(&self.hashed);
+        hasher.update(&[0x04, 0xff]);
+        hasher.update(&(self.hashed.len() as u32).to_be_bytes());
+        let digest = hasher.finish();
+        let digest = digest.as_ref();
+        if digest[..2] != self.left16 {
+            return Err(StormError::BadSignature(
+                "file does not match the OpenPGP signature".to_string(),
+            ));
+        }
+
+        let candidates: Vec<&PgpKey> = keys
+            .iter()
+            .filter(|key| {
+                self.issuer
+                    .is_none_or(|issuer| key.fingerprint[12..] == issuer)
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(StormError::BadSignature(format!(
+                "signed by key {}, which is not in the provided key",
+                self.issuer.map(|i| hex(&i)).unwrap_or_default()
+            )));
+        }
+
+        candidates
+            .into_iter()
+            .find(|key| self.check(key, digest))
+            .map(PgpKey::fingerprint)
+            .ok_or_else(|| {
+                StormError::BadSignature("OpenPGP signature does not verify".to_string())
+            })
+    }
+
+    fn check(&self, key: &PgpKey, digest: &[u8]) -> bool {
+        match (&key.material, &self.material) {
+            (KeyMaterial::Rsa(public), SignatureMaterial::Rsa(signature)) => {
+                let scheme = if self.hash == &SHA256 {
+                    Pkcs1v15Sign::new::<sha2::Sha256>()
+                } else if self.hash == &SHA384 {
+                    Pkcs1v15Sign::new::<sha2::Sha384>()
+                } else {
+                    Pkcs1v15Sign::new::<sha2::Sha512>()
+                };
+                // MPIs drop leading zero bytes; PKCS#1 wants the modulus length.
+                let signature = left_pad(signature, public.size());
+                public.verify(scheme, digest, &signature).is_ok()
+            }
+            (KeyMaterial::Ed25519(public), SignatureMaterial::Ed25519(signature)) => {
+                UnparsedPublicKey::new(&ED25519, public)
+                    .verify(digest, signature)
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+pub(crate) fn is_armored(data: &[u8]) -> bool {
+    data.trim_ascii_start().starts_with(b"-----BEGIN PGP ")
+}
+
+pub(crate) fn is_binary(data: &[u8]) -> bool {
+    data.first().is_some_and(|b| b & 0x80 != 0)
+}
+
+pub(crate) fn dearmor(data: &[u8]) -> Result<Vec<u8>, StormError> {
+    if !is_armored(data) {
+        return Ok(data.to_vec());
+    }
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| !l.starts_with("-----BEGIN"));
+    lines.next();
+    // Armor headers (Version:, Comment:) end at the first blank line.
+    let body: String = lines
+        .skip_while(|l| !l.is_empty())
+        .skip(1)
+        .take_while(|l| !l.starts_with('=') && !l.starts_with("-----END"))
+        .collect();
+    STANDARD
+        .decode(body)
+        .map_err(|e| malformed(format!("bad armor: {}", e)))
+}
+
+pub(crate) fn parse_keys(data: &[u8]) -> Result<Vec<PgpKey>, StormError> {
+    let data = dearmor(data)?;
+    let mut keys = Vec::new();
+    for packet in (Packets { data: &data }) {
+        let (tag, body) = packet?;
+        if (tag == TAG_PUBLIC_KEY || tag == TAG_PUBLIC_SUBKEY)
+            && let Some(key) = parse_key(body)?
+        {
+            keys.push(key);
+        }
+    }
+    if keys.is_empty() {
+        return Err(malformed(
+            "no supported (v4 RSA of 2048 to 8192 bits, or Ed25519) public keys".to_string(),
+        ));
+    }
+    Ok(keys)
+}
+
+pub(crate) fn parse_signature(data: &[u8]) -> Result<PgpSignature, StormError> {
+    let data = dearmor(data)?;
+    for packet in (Packets { data: &data }) {
+        let (tag, body) = packet?;
+        if tag == TAG_SIGNATURE {
+            return signature_packet(body);
+        }
+    }
+    Err(malformed("no signature packet".to_string()))
+}
+
+fn parse_key(body: &[u8]) -> Result<Option<PgpKey>, StormError> {
+    if body.first() != Some(&4) || body.len() < 6 {
+        return Ok(None);
+    }
+    let mut reader = Reader { data: &body[6..] };
+    let material = match body[5] {
+        algo if ALGO_RSA.contains(&algo) => {
+            let n = reader.mpi()?;
+            let e = reader.mpi()?;
+            let bits = n.len() * 8 - n.first().map_or(8, |b| b.leading_zeros() as usize);
+            if !(RSA_MIN_BITS..=RSA_MAX_BITS).contains(&bits) {
+                return Ok(None);
+            }
+            let key = RsaPublicKey::new_with_max_size(
+                BigUint::from_bytes_be(n),
+                BigUint::from_bytes_be(e),
+                RSA_MAX_BITS,
+            )
+            .map_err(|e| malformed(format!("bad RSA public key: {}", e)))?;
+            KeyMaterial::Rsa(key)
+        }
+        ALGO_EDDSA => {
+            let oid_len = reader.take(1)?[0] as usize;
+            if reader.take(oid_len)? != ED25519_OID {
+                return Ok(None);
+            }
+            match reader.mpi()? {
+                [0x40, point @ ..] if point.len() == 32 => {
+                    KeyMaterial::Ed25519(point.try_into().expect("32-byte point"))
+                }
+                _ => return Err(malformed("bad Ed25519 public key".to_string())),
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    let mut hasher = Context::new(&SHA1_FOR_LEGACY_USE_ONLY);
+    hasher.update(&[0x99]);
+    hasher.update(&(body.len() as u16).to_be_bytes());
+    hasher.update(body);
+    let fingerprint = hasher
+        .finish()
+        .as_ref()
+        .try_into()
+        .expect("SHA-1 is 20 bytes");
+    Ok(Some(PgpKey {
+        fingerprint,
+        material,
+    }))
+}
+
+fn signature_packet(body: &[u8]) -> Result<PgpSignature, StormError> {
+    let mut reader = Reader { data: body };
+    let header = reader.take(4)?;
+    let (version, sig_type, algo, hash_algo) = (header[0], header[1], header[2], header[3]);
+    if version != 4 {
+        return Err(malformed(format!(
+            "version {} signatures are not supported",
+            version
+        )));
+    }
+    if sig_type != 0x00 {
+        return Err(malformed(
+            "only binary document signatures are supported".to_string(),
+        ));
+    }
+    let hash = match hash_algo {
+        8 => &SHA256,
+        9 => &SHA384,
+        10 => &SHA512,
+        _ => {
+            return Err(malformed(format!(
+                "hash algorithm {} is not supported",
+                hash_algo
+            )));
+        }
+    };
+
+    let hashed_len = reader.u16()? as usize;
+    let hashed_subpackets = reader.take(hashed_len)?;
+    let hashed = body[..6 + hashed_len].to_vec();
+    let unhashed_len = reader.u16()? as usize;
+    let unhashed_subpackets = reader.take(unhashed_len)?;
+    let left16 = reader.take(2)?.try_into().expect("2 bytes");
+
+    let material = match algo {
+        algo if ALGO_RSA.contains(&algo) => SignatureMaterial::Rsa(reader.mpi()?.to_vec()),
+        ALGO_EDDSA => {
+            let mut signature = [0u8; 64];
+            signature[..32].copy_from_slice(&left_pad(reader.mpi()?, 32));
+            signature[32..].copy_from_slice(&left_pad(reader.mpi()?, 32));
+            SignatureMaterial::Ed25519(signature)
+        }
+        _ => {
+            return Err(malformed(format!(
+                "public key algorithm {} is not supported",
+                algo
+            )));
+        }
+    };
+
+    let issuer = issuer(hashed_subpackets)?.or(issuer(unhashed_subpackets)?);
+    Ok(PgpSignature {
+        hash,
+        hashed,
+        left16,
+        issuer,
+        material,
+    })
+}
+
+fn issuer(subpackets: &[u8]) -> Result<Option<[u8; 8]>, StormError> {
+    let mut reader = Reader { data: subpackets };
+    while !reader.data.is_empty() {
+        let len = match reader.take(1)?[0] {
+            len @ 0..192 => len as usize,
+            first @ 192..255 => ((first as usize - 192) << 8) + reader.take(1)?[0] as usize + 192,
+            255 => reader.u32()? as usize,
+        };
+        let packet = reader.take(len)?;
+        match packet.split_first() {
+            Some((kind, id)) if kind & 0x7f == SUBPACKET_ISSUER && id.len() == 8 => {
+                return Ok(Some(id.try_into().expect("8-byte key id")));
+            }
+            Some((kind, [4, fingerprint @ ..]))
+                if kind & 0x7f == SUBPACKET_ISSUER_FINGERPRINT && fingerprint.len() == 20 =>
+            {
+                return Ok(Some(fingerprint[12..].try_into().expect("8-byte key id")));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+struct Packets<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Packets<'a> {
+    type Item = Result<(u8, &'a [u8]), StormError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let packet = self.split();
+        if packet.is_err() {
+            self.data = &[];
+        }
+        Some(packet)
+    }
+}
+
+impl<'a> Packets<'a> {
+    fn split(&mut self) -> Result<(u8, &'a [u8]), StormError> {
+        let mut reader = Reader { data: self.data };
+        let ctb = reader.take(1)?[0];
+        if ctb & 0x80 == 0 {
+            return Err(malformed("not an OpenPGP packet".to_string()));
+        }
+        let (tag, len) = if ctb & 0x40 != 0 {
+            let len = match reader.take(1)?[0] {
+                len @ 0..192 => len as usize,
+                first @ 192..224 => {
+                    ((first as usize - 192) << 8) + reader.take(1)?[0] as usize + 192
+                }
+                255 => reader.u32()? as usize,
+                _ => return Err(malformed("partial packet lengths".to_string())),
+            };
+            (ctb & 0x3f, len)
+        } else {
+            let len = match ctb & 0x03 {
+                0 => reader.take(1)?[0] as usize,
+                1 => reader.u16()? as usize,
+                2 => reader.u32()? as usize,
+                _ => reader.data.len(),
+            };
+            ((ctb >> 2) & 0x0f, len)
+        };
+        let body = reader.take(len)?;
+        self.data = reader.data;
+        Ok((tag, body))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StormError> {
+        if self.data.len() < len {
+            return Err(malformed("truncated packet".to_string()));
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, StormError> {
+        Ok(u16::from_be_bytes(
+            self.take(2)?.try_into().expect("2 bytes"),
+        ))
+    }
+
+    fn u32(&mut self) -> Result<u32, StormError> {
+        Ok(u32::from_be_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn mpi(&mut self) -> Result<&'a [u8], StormError> {
+        let bits = self.u16()? as usize;
+        self.take(bits.div_ceil(8))
+    }
+}
+
+fn left_pad(bytes: &[u8], len: usize) -> Vec<u8> {
+    let mut padded = vec![0u8; len.saturating_sub(bytes.len())];
+    padded.extend_from_slice(bytes);
+    padded
+}
+
+fn malformed(reason: String) -> StormError {
+    StormError::BadSignature(format!("unreadable OpenPGP data: {}", reason))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &[u8] = b"storm signature fixture\n";
+
+    // RSA-2048, signing FILE with SHA-512.
+    const RSA_KEY: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----
+
+mQENBGrSiJ4BCADVY37o+CnKYNvH0YxR1Q4b6TERLre54gBYlw6TjX7e7oPqOJld
+4ubxOnDVvoDV9muDzmCUULWpP5vm4rvdwxoFawTUnJm/RkAZnzmZi2PUGwXlzjor
+Q8BlLvFA61UkQk/+9iazVngzEtOlhdtTOcxb3spQ2bj932nh+CGYH9dVxBCN52nL
+yliLlPhqQ7nzuIKra9RPdWVgOwOsiZkNu4E4c8BuptFLfZteikyh2mUWd6vI5A7G
+uioUivgAMoslouWp0D7iibGYCvMO/e6EFA5MDYwo35/cRaXi+LC6bAu02FgomuZ0
+NvSYB+H+u7/Nq7ZkoYI+7iJZCd52FwQNrugTABEBAAG0G1N0b3JtIFJTQSA8cnNh
+QGV4YW1wbGUuY29tPokBTgQTAQoAOBYhBBl6bvoO3f8VbcjGYBDpAm850ArhBQJq
+0oieAhsDBQsJCAcCBhUKCQgLAgQWAgMBAh4BAheAAAoJEBDpAm850ArhVfEH/0/G
+Z6zbDyc2aODZ2ossy/DeK7rgQtxMA7TZ0w/l4+hC7yoZQC3bQ/SA8wZHPkM/PhGs
+VXZDvwzrYSY3C85s6cHHgll5gPM5shE4/TgunxRMGCzBn+z0zQi6Z/rDzo/HOm3f
+Nu4ZO2k+NFKt0zYe4q4ZgQSaQk2jPmHZYyBWSYRK3BBcu24m1A4nDJsEly1IlQ9H
+XKjL4iOyltFMGtsI8xdjeLeGzw1UQ6y7I5rsQLlI5R+736mHuwDOqwIsG/TKIQpN
+pL8CohP6FKx+VOcAN9sn7eXDaMyKdY2iDjJQngFtb8gnMe+/w97DWEewqbdxtixe
+AE410Hz3swiQmM/UE2c=
+=Ykh/
+-----END PGP PUBLIC KEY BLOCK-----
+";
+
+    const RSA_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----
+
+iQFEBAABCgAuFiEEGXpu+g7d/xVtyMZgEOkCbznQCuEFAmrSiKEQHHJzYUBleGFt
+cGxlLmNvbQAKCRAQ6QJvOdAK4fBDB/4jJCK9QjqiMQNvIkkeZeZ52ah8akM6FTly
+hyjk90LXIUvck8TNcqpYw4FHD/KegjRjeg4U+fB8Qe9qg11PDehESeBYrYB0u26v
+wSKlYtUbC2XDFb9CJ4dZ17oHDAg7brauLDYu55DdAO0vN9x59ptYwvEdKlhvzwRd
+a6hgF8n+wgygKCmfuLOyAu+jM2NGa9kADr6NMsPPuGWC9oQm3kHJ1qeWl7vOYNGE
+dVvrW9Pd5FEKje4n9IHVfQnDdFhMksgQBo/QD2FZJ6/CW+vkxoJCYewMYIPywj/I
+14NRdodToM/oVpAsnznTClPRDjzF7zvlSopv0cO7xVqz9cNsGyjz
+=86Gh
+-----END PGP SIGNATURE-----
+";
+
+    // RSA-1024, too short to trust, and its SHA-256 signature of FILE.
+    const SMALL_KEY: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----
+
+mI0EatKIngEEALkdHGY2zLLUghhHsKd/Zub9mJUB9ft9HsmMzRxQSHYiW5TdxzWT
+zwJwKePXzkDKn1/b2biyk9Zc25aSYaURoAsC1t4rSZbbcIOYB3KrbZ+z2xqhl5y/
+KPT/tjKx4i+0utrFaRWSeJTS99p+4QWtXw+VPDuPP0gNEZ+n8CDzhZFlABEBAAG0
+H1N0b3JtIFNtYWxsIDxzbWFsbEBleGFtcGxlLmNvbT6IzgQTAQoAOBYhBAdtzO0m
+O86gPVRBIFKxFedbHChzBQJq0oieAhsDBQsJCAcCBhUKCQgLAgQWAgMBAh4BAheA
+AAoJEFKxFedbHChzxasD/ilQS/U9ZSDkap2BQFoOINnrGSsOIvndfNamDfrIVAU3
+cvYHKl0KkYONIlAbP+dNu7zbU8qxXNBNpSvNXiW/4d6Pfhi7rGRqtLbEu3RCK4T6
+sZz2v+mB0/ExQ55G3LcSoazYH2iRau0YF04KI9zgA7FqFHXEvXx7ZUsMwQpvfwbu
+=hDOy
+-----END PGP PUBLIC KEY BLOCK-----
+";
+
+    const SMALL_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----
+
+iMYEAAEIADAWIQQHbcztJjvOoD1UQSBSsRXnWxwocwUCatKIoRIcc21hbGxAZXhh
+bXBsZS5jb20ACgkQUrEV51scKHMP8AP6A3XqdaK4hvlWKKdEltK75rIFf70c4sG8
+d/qTemxBYlxzCmpSjO/wUCezX2Tl2xu0bPCyC9MxBIjGVf/eahlwMKVX0xmnl97+
+R+DvENqfO4BRS5wR9KufjIqgGooJr4F48Af/+mkGkRh9acOsSvoGzJxUIQnMUxBI
++wUBM2AmyEk=
+=bPwu
+-----END PGP SIGNATURE-----
+";
+
+    fn verify(signature: &str, keys: &[PgpKey]) -> Result<String, StormError> {
+        let signature = parse_signature(signature.as_bytes())?;
+        let mut hasher = signature.hasher();
+        hasher.update(FILE);
+        signature.verify(hasher, keys)
+    }
+
+    #[test]
+    fn test_rsa_signature() {
+        let keys = parse_keys(RSA_KEY.as_bytes()).unwrap();
+        assert_eq!(
+            verify(RSA_SIGNATURE, &keys).unwrap(),
+            "197A6EFA0EDDFF156DC8C66010E9026F39D00AE1"
+        );
+
+        let signature = parse_signature(RSA_SIGNATURE.as_bytes()).unwrap();
+        let mut hasher = signature.hasher();
+        hasher.update(b"tampered");
+        assert!(signature.verify(hasher, &keys).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_id() {
+        let keys = parse_keys(RSA_KEY.as_bytes()).unwrap();
+        let Err(StormError::BadSignature(reason)) = verify(SMALL_SIGNATURE, &keys) else {
+            panic!("signature by another key verified");
+        };
+        assert!(reason.contains("52B115E75B1C2873"), "{}", reason);
+    }
+
+    #[test]
+    fn test_undersized_rsa_key() {
+        assert!(parse_keys(SMALL_KEY.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_truncated_and_malformed_packets() {
+        let signature = dearmor(RSA_SIGNATURE.as_bytes()).unwrap();
+        for len in 1..signature.len() {
+            assert!(parse_signature(&signature[..len]).is_err(), "{} bytes", len);
+        }
+        let key = dearmor(RSA_KEY.as_bytes()).unwrap();
+        for len in 1..key.len() {
+            let _ = parse_keys(&key[..len]);
+        }
+        assert!(parse_keys(&key[..40]).is_err());
+
+        assert!(parse_signature(&[0x00, 0x01, 0x02]).is_err());
+        // New-format partial body lengths are not supported.
+        assert!(parse_signature(&[0xc2, 0xe0, 0x04]).is_err());
+        let mut bad_version = signature.clone();
+        bad_version[3] = 3;
+        assert!(parse_signature(&bad_version).is_err());
+        assert!(parse_signature(b"-----BEGIN PGP SIGNATURE-----\n\n!!!!\n").is_err());
+    }
+}
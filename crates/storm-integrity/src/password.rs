@@ -0,0 +1,83 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+use stormdl_core::StormError;
+
+const SCHEME: &str = "pbkdf2-sha256";
+const ROUNDS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+// Encodes as `pbkdf2-sha256$<rounds>$<salt>$<hash>` so the cost can be raised
+// later without breaking stored passwords.
+pub fn hash_password(password: &str) -> Result<String, StormError> {
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| StormError::Config("no randomness for password hash".into()))?;
+    let rounds = NonZeroU32::new(ROUNDS).expect("rounds are non-zero");
+    let mut hash = [0u8; HASH_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        rounds,
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+    Ok(format!(
+        "{}${}${}${}",
+        SCHEME,
+        ROUNDS,
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash)
+    ))
+}
+
+pub fn verify_password(encoded: &str, password: &str) -> bool {
+    let mut parts = encoded.split('$');
+    let (Some(SCHEME), Some(rounds), Some(salt), Some(hash), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return false;
+    };
+    let (Some(rounds), Ok(salt), Ok(hash)) = (
+        rounds.parse().ok().and_then(NonZeroU32::new),
+        STANDARD_NO_PAD.decode(salt),
+        STANDARD_NO_PAD.decode(hash),
+    ) else {
+        return false;
+    };
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        rounds,
+        &salt,
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_round_trip() {
+        let encoded = hash_password("correct horse").unwrap();
+        assert!(encoded.starts_with("pbkdf2-sha256$100000$"));
+        assert_ne!(encoded, hash_password("correct horse").unwrap());
+        assert!(verify_password(&encoded, "correct horse"));
+        assert!(!verify_password(&encoded, "Correct horse"));
+        assert!(!verify_password("plain-text", "plain-text"));
+        assert!(!verify_password(
+            &format!("{}$extra", encoded),
+            "correct horse"
+        ));
+    }
+}
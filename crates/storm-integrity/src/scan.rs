@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use stormdl_core::StormError;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    program: String,
+    args: Vec<String>,
+    quarantine_dir: PathBuf,
+}
+
+impl Scanner {
+    // `command` is split on whitespace; `{}` marks where the file goes,
+    // otherwise the path is appended (`clamdscan --no-summary`).
+    pub fn new(command: &str, quarantine_dir: impl Into<PathBuf>) -> Result<Self, StormError> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words
+            .next()
+            .ok_or_else(|| StormError::Config("empty scanner command".to_string()))?;
+        let mut args: Vec<String> = words.collect();
+        if !args.iter().any(|a| a.contains("{}")) {
+            args.push("{}".to_string());
+        }
+        Ok(Self {
+            program,
+            args,
+            quarantine_dir: quarantine_dir.into(),
+        })
+    }
+
+    pub fn quarantine_dir(&self) -> &Path {
+        &self.quarantine_dir
+    }
+
+    // Exit status follows ClamAV: 0 is clean, 1 is a detection, anything
+    // else means the scan itself failed.
+    pub async fn scan(&self, path: &Path) -> Result<ScanVerdict, StormError> {
+        let target = path.to_string_lossy();
+        let output = Command::new(&self.program)
+            .args(self.args.iter().map(|a| a.replace("{}", &target)))
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| StormError::Other(format!("Failed to run {}: {}", self.program, e)))?;
+
+        match output.status.code() {
+            Some(0) => Ok(ScanVerdict::Clean),
+            Some(1) => Ok(ScanVerdict::Infected(detection(&String::from_utf8_lossy(
+                &output.stdout,
+            )))),
+            _ => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(StormError::Other(format!(
+                    "{} failed ({}): {}",
+                    self.program,
+                    output.status,
+                    stderr.trim()
+                )))
+            }
+        }
+    }
+
+    pub fn quarantine(&self, path: &Path) -> Result<PathBuf, StormError> {
+        std::fs::create_dir_all(&self.quarantine_dir)?;
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "download".to_string());
+        let mut target = self.quarantine_dir.join(&name);
+        let mut n = 1;
+        while target.exists() {
+            target = self.quarantine_dir.join(format!("{}.{}", name, n));
+            n += 1;
+        }
+
+        if std::fs::rename(path, &target).is_err() {
+            std::fs::copy(path, &target)?;
+            std::fs::remove_file(path)?;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o400))?;
+        }
+        Ok(target)
+    }
+
+    // Scans `path` and moves it to the quarantine folder on a detection.
+    pub async fn check(&self, path: &Path) -> Result<(), StormError> {
+        match self.scan(path).await? {
+            ScanVerdict::Clean => Ok(()),
+            ScanVerdict::Infected(reason) => Err(StormError::Quarantined {
+                reason,
+                path: self.quarantine(path)?,
+            }),
+        }
+    }
+}
+
+// clamdscan prints `<path>: <signature> FOUND`.
+fn detection(stdout: &str) -> String {
+    let line = stdout
+        .lines()
+        .map(str::trim)
+        .find(|l| l.ends_with(" FOUND"))
+        .or_else(|| stdout.lines().map(str::trim).find(|l| !l.is_empty()));
+    match line {
+        Some(line) => {
+            let line = line.strip_suffix(" FOUND").unwrap_or(line);
+            line.rsplit_once(": ")
+                .map_or(line, |(_, signature)| signature)
+                .to_string()
+        }
+        None => "flagged by scanner".to_string(),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scan_and_quarantine() {
+        let dir = std::env::temp_dir().join(format!("storm-scan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let clean = dir.join("clean.bin");
+        let infected = dir.join("eicar.com");
+        std::fs::write(&clean, b"hello").unwrap();
+        std::fs::write(&infected, b"X5O!P%@AP").unwrap();
+
+        // Stands in for clamdscan: flags files containing "X5O".
+        let script = "grep -q X5O {} && echo \"{}: Eicar-Signature FOUND\" && exit 1; exit 0";
+        let scanner = Scanner {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            quarantine_dir: dir.join("quarantine"),
+        };
+
+        assert_eq!(scanner.scan(&clean).await.unwrap(), ScanVerdict::Clean);
+        scanner.check(&clean).await.unwrap();
+        assert!(clean.exists());
+
+        match scanner.check(&infected).await {
+            Err(StormError::Quarantined { reason, path }) => {
+                assert_eq!(reason, "Eicar-Signature");
+                assert_eq!(path, dir.join("quarantine").join("eicar.com"));
+                assert!(path.exists() && !infected.exists());
+            }
+            other => panic!("expected quarantine, got {:?}", other),
+        }
+
+        let broken = Scanner::new("false", dir.join("quarantine")).unwrap();
+        assert_eq!(
+            broken.scan(&clean).await.unwrap(),
+            ScanVerdict::Infected("flagged by scanner".to_string())
+        );
+        let missing = Scanner::new("storm-no-such-scanner --flag", dir.join("q")).unwrap();
+        assert_eq!(missing.args, ["--flag", "{}"]);
+        assert!(missing.scan(&clean).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::openpgp::{self, PgpKey};
+use crate::sums::fetch_small;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use blake2::{Blake2b512, Digest};
+use ring::signature::{ED25519, UnparsedPublicKey};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use stormdl_core::{Downloader, StormError};
+use url::Url;
+
+const SIGNATURE_SUFFIXES: [&str; 3] = ["minisig", "asc", "sig"];
+const MINISIGN_KEY_LEN: usize = 42;
+const MINISIGN_SIG_LEN: usize = 74;
+
+enum KeyKind {
+    Minisign { id: [u8; 8], key: [u8; 32] },
+    OpenPgp(Vec<PgpKey>),
+}
+
+pub struct PublicKey {
+    kind: KeyKind,
+}
+
+impl PublicKey {
+    // Accepts a minisign public key (the `RW...` line, with or without its
+    // comment) or an OpenPGP key, armored or binary.
+    pub fn parse(data: &[u8]) -> Result<Self, StormError> {
+        let kind = if openpgp::is_armored(data) || openpgp::is_binary(data) {
+            KeyKind::OpenPgp(openpgp::parse_keys(data)?)
+        } else {
+            let text = String::from_utf8_lossy(data);
+            let line = payload_lines(&text)
+                .next()
+                .ok_or_else(|| bad_key("empty public key"))?;
+            let raw =
+                decode(line, MINISIGN_KEY_LEN).ok_or_else(|| bad_key("not a minisign key"))?;
+            if &raw[..2] != b"Ed" {
+                return Err(bad_key("unsupported minisign key algorithm"));
+            }
+            KeyKind::Minisign {
+                id: raw[2..10].try_into().expect("8-byte key id"),
+                key: raw[10..].try_into().expect("32-byte key"),
+            }
+        };
+        Ok(Self { kind })
+    }
+
+    // `source` is a key file when one exists at that path, otherwise the key
+    // itself, so `--signify-key RWQ...` works without a file.
+    pub fn load(source: &str) -> Result<Self, StormError> {
+        let path = Path::new(source);
+        if path.is_file() {
+            Self::parse(&std::fs::read(path)?)
+        } else {
+            Self::parse(source.as_bytes())
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self.kind {
+            KeyKind::Minisign { .. } => "minisign",
+            KeyKind::OpenPgp(_) => "OpenPGP",
+        }
+    }
+
+    pub fn id(&self) -> String {
+        match &self.kind {
+            KeyKind::Minisign { id, .. } => minisign_id(id),
+            KeyKind::OpenPgp(keys) => keys.first().map(PgpKey::fingerprint).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureCheck {
+    pub signer: String,
+    pub comment: Option<String>,
+}
+
+pub fn verify_signature(
+    reader: impl Read,
+    signature: &[u8],
+    key: &PublicKey,
+) -> Result<SignatureCheck, StormError> {
+    match &key.kind {
+        KeyKind::OpenPgp(keys) => {
+            let signature = openpgp::parse_signature(signature)?;
+            let mut hasher = signature.hasher();
+            for_each_chunk(reader, |chunk| hasher.update(chunk))?;
+            let signer = signature.verify(hasher, keys)?;
+            Ok(SignatureCheck {
+                signer,
+                comment: None,
+            })
+        }
+        KeyKind::Minisign { id, key } => verify_minisign(reader, signature, id, key),
+    }
+}
+
+pub fn verify_signature_path(
+    path: &Path,
+    signature: &[u8],
+    key: &PublicKey,
+) -> Result<SignatureCheck, StormError> {
+    verify_signature(File::open(path)?, signature, key)
+}
+
+pub async fn verify_signature_file(
+    path: &Path,
+    signature: Vec<u8>,
+    key: Arc<PublicKey>,
+) -> Result<SignatureCheck, StormError> {
+    let path: PathBuf = path.to_path_buf();
+    tokio::task::spawn_blocking(move || verify_signature_path(&path, &signature, &key))
+        .await
+        .map_err(|e| StormError::Other(format!("Task error: {}", e)))?
+}
+
+fn verify_minisign(
+    reader: impl Read,
+    signature: &[u8],
+    key_id: &[u8; 8],
+    key: &[u8; 32],
+) -> Result<SignatureCheck, StormError> {
+    let text = String::from_utf8_lossy(signature);
+    let mut lines = payload_lines(&text);
+    let blob = lines
+        .next()
+        .and_then(|line| decode(line, MINISIGN_SIG_LEN))
+        .ok_or_else(|| bad_signature("not a minisign signature"))?;
+    let trusted = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("trusted comment: "))
+        .ok_or_else(|| bad_signature("minisign signature has no trusted comment"))?;
+    let global = lines
+        .next()
+        .and_then(|line| STANDARD.decode(line).ok())
+        .ok_or_else(|| bad_signature("minisign signature has no global signature"))?;
+
+    let (algorithm, rest) = blob.split_at(2);
+    let (sig_id, sig) = rest.split_at(8);
+    if sig_id != key_id {
+        return Err(StormError::BadSignature(format!(
+            "signed by key {}, expected {}",
+            minisign_id(sig_id.try_into().expect("8-byte key id")),
+            minisign_id(key_id)
+        )));
+    }
+
+    // "ED" signatures cover the BLAKE2b-512 of the file; legacy "Ed" ones
+    // sign the file itself.
+    let message = match algorithm {
+        b"ED" => {
+            let mut hasher = Blake2b512::new();
+            for_each_chunk(reader, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        b"Ed" => {
+            let mut data = Vec::new();
+            for_each_chunk(reader, |chunk| data.extend_from_slice(chunk))?;
+            data
+        }
+        _ => return Err(bad_signature("unsupported minisign signature algorithm")),
+    };
+
+    let public = UnparsedPublicKey::new(&ED25519, key);
+    public
+        .verify(&message, sig)
+        .map_err(|_| bad_signature("file does not match the minisign signature"))?;
+    public
+        .verify(&[sig, trusted.as_bytes()].concat(), &global)
+        .map_err(|_| bad_signature("trusted comment has been tampered with"))?;
+
+    Ok(SignatureCheck {
+        signer: minisign_id(key_id),
+        comment: Some(trusted.to_string()),
+    })
+}
+
+fn for_each_chunk(mut reader: impl Read, mut f: impl FnMut(&[u8])) -> Result<(), StormError> {
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buf[..n]);
+    }
+}
+
+fn payload_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.contains("comment:"))
+}
+
+fn decode(line: &str, len: usize) -> Option<Vec<u8>> {
+    STANDARD.decode(line).ok().filter(|raw| raw.len() == len)
+}
+
+// minisign prints key ids as the little-endian u64 in hex.
+fn minisign_id(id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*id))
+}
+
+fn bad_key(reason: &str) -> StormError {
+    StormError::Config(format!("Invalid public key: {}", reason))
+}
+
+fn bad_signature(reason: &str) -> StormError {
+    StormError::BadSignature(reason.to_string())
+}
+
+pub fn signature_urls(url: &Url) -> Vec<Url> {
+    SIGNATURE_SUFFIXES
+        .iter()
+        .map(|suffix| {
+            let mut companion = url.clone();
+            companion.set_path(&format!("{}.{}", url.path(), suffix));
+            companion
+        })
+        .collect()
+}
+
+pub async fn fetch_signature(
+    downloader: &dyn Downloader,
+    url: &Url,
+) -> Result<Vec<u8>, StormError> {
+    fetch_small(downloader, url).await
+}
+
+// Prefers the signature format matching the key, then falls back to the
+// other suffixes in order.
+pub async fn discover_signature(
+    downloader: &dyn Downloader,
+    url: &Url,
+    key: &PublicKey,
+) -> Option<(Url, Vec<u8>)> {
+    let mut candidates = signature_urls(url);
+    if matches!(key.kind, KeyKind::OpenPgp(_)) {
+        candidates.rotate_left(1);
+    }
+    for candidate in candidates {
+        match fetch_signature(downloader, &candidate).await {
+            Ok(signature) => return Some((candidate, signature)),
+            Err(e) => tracing::debug!("No signature from {}: {}", candidate, e),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    const FILE: &[u8] = b"storm signature fixture\n";
+
+    const PGP_KEY: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----
+
+mDMEatIo/BYJKwYBBAHaRw8BAQdALWrrW57xqHbdy5M+jGM8myilNtT7J+S6qZUG
+s11zam60G1N0b3JtIFRlc3QgPGVkQGV4YW1wbGUuY29tPoiQBBMWCAA4FiEEklhc
+UF/WZL7QwOFp+fwzQcyFhVIFAmrSKPwCGwMFCwkIBwIGFQoJCAsCBBYCAwECHgEC
+F4AACgkQ+fwzQcyFhVJzGAD/caIkDobShKS0ralcpSIi9pgmsoHKdba8vgRPqjeR
+NF4A/3NjgKadoyBM9qaRf3i3XWgBCmixgNbJ5Txta5y8rJsG
+=P/+I
+-----END PGP PUBLIC KEY BLOCK-----
+";
+
+    const PGP_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----
+
+iIUEABYIAC0WIQSSWFxQX9ZkvtDA4Wn5/DNBzIWFUgUCatIo/A8cZWRAZXhhbXBs
+ZS5jb20ACgkQ+fwzQcyFhVLOFwEAmgnxraR3e6xq+93lrVI2MS8BYJ4WZnYozOhh
+CPab3VEA/ijCPUkg84uGVt5EtSbR/NMce8q7AKGjdfgSAsrZlhUM
+=8Eqv
+-----END PGP SIGNATURE-----
+";
+
+    fn minisign(pair: &Ed25519KeyPair, id: [u8; 8], trusted: &str) -> (String, String) {
+        let public = [b"Ed".as_slice(), &id, pair.public_key().as_ref()].concat();
+        let key = format!(
+            "untrusted comment: minisign public key\n{}\n",
+            STANDARD.encode(public)
+        );
+
+        let mut hasher = Blake2b512::new();
+        hasher.update(FILE);
+        let sig = pair.sign(&hasher.finalize());
+        let global = pair.sign(&[sig.as_ref(), trusted.as_bytes()].concat());
+        let blob = [b"ED".as_slice(), &id, sig.as_ref()].concat();
+        let signature = format!(
+            "untrusted comment: signature\n{}\ntrusted comment: {}\n{}\n",
+            STANDARD.encode(blob),
+            trusted,
+            STANDARD.encode(global)
+        );
+        (key, signature)
+    }
+
+    #[test]
+    fn test_verify_minisign_and_openpgp() {
+        let pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let (key, signature) = minisign(&pair, [1, 2, 3, 4, 5, 6, 7, 8], "file:fixture.txt");
+        let key = PublicKey::parse(key.as_bytes()).unwrap();
+        assert_eq!(key.kind(), "minisign");
+        let check = verify_signature(FILE, signature.as_bytes(), &key).unwrap();
+        assert_eq!(check.signer, "0807060504030201");
+        assert_eq!(check.comment.as_deref(), Some("file:fixture.txt"));
+        assert!(matches!(
+            verify_signature(&b"tampered"[..], signature.as_bytes(), &key),
+            Err(StormError::BadSignature(_))
+        ));
+        let forged = signature.replace("file:fixture.txt", "file:other.txt");
+        assert!(verify_signature(FILE, forged.as_bytes(), &key).is_err());
+        let (_, other) = minisign(&pair, [9; 8], "file:fixture.txt");
+        assert!(verify_signature(FILE, other.as_bytes(), &key).is_err());
+
+        let key = PublicKey::parse(PGP_KEY.as_bytes()).unwrap();
+        assert_eq!(key.id(), "92585C505FD664BED0C0E169F9FC3341CC858552");
+        let check = verify_signature(FILE, PGP_SIGNATURE.as_bytes(), &key).unwrap();
+        assert_eq!(check.signer, key.id());
+        assert!(matches!(
+            verify_signature(&b"tampered"[..], PGP_SIGNATURE.as_bytes(), &key),
+            Err(StormError::BadSignature(_))
+        ));
+
+        let url = Url::parse("https://example.com/pub/app.iso?sig=1").unwrap();
+        assert_eq!(
+            signature_urls(&url)[1].as_str(),
+            "https://example.com/pub/app.iso.asc?sig=1"
+        );
+    }
+}
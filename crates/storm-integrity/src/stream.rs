@@ -0,0 +1,314 @@
+use crate::checksum::ChecksumSpec;
+use crate::hasher::HashAlgorithm;
+use crate::verify::ContentVerifier;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use stormdl_core::{ByteRange, RangeSet, StormError};
+
+const READ_CHUNK: u64 = 1024 * 1024;
+const CATCH_UP_BUDGET: u64 = 4 * 1024 * 1024;
+// A tap whose reader falls this far behind is cut off rather than letting
+// the backlog grow with the download.
+const TAP_BACKLOG: u64 = 64 * 1024 * 1024;
+
+pub struct StreamVerifier {
+    path: PathBuf,
+    initial: ContentVerifier,
+    state: Mutex<StreamState>,
+    // Catch-up reads go through here one at a time, outside `state`, so
+    // writers aren't held up behind the disk.
+    reader: Mutex<Option<File>>,
+}
+
+struct StreamState {
+    verifier: ContentVerifier,
+    hashed: u64,
+    persisted: RangeSet,
+    tap: Option<Tap>,
+    // Bumped when hashing starts over, so a read begun before is dropped.
+    generation: u64,
+}
+
+struct Tap {
+    tx: Sender<Vec<u8>>,
+    queued: Arc<AtomicU64>,
+    verified: Arc<AtomicBool>,
+}
+
+// The hashed bytes in order, as they are hashed. It ends cleanly only once
+// `finish` has verified the digest; a mismatch, a rewind or a reader that
+// fell too far behind ends it with an error.
+pub struct HashedReader {
+    rx: Receiver<Vec<u8>>,
+    queued: Arc<AtomicU64>,
+    verified: Arc<AtomicBool>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl StreamVerifier {
+    pub fn new(path: &Path, spec: &ChecksumSpec) -> Self {
+        Self::with_verifier(path, ContentVerifier::with_spec(spec))
+    }
+
+    // Hashes the download without checking it against anything; `finish`
+    // returns the digest.
+    pub fn digest_only(path: &Path, algorithm: HashAlgorithm) -> Self {
+        Self::with_verifier(path, ContentVerifier::digest_only(algorithm))
+    }
+
+    fn with_verifier(path: &Path, verifier: ContentVerifier) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            initial: verifier.clone(),
+            state: Mutex::new(StreamState {
+                verifier,
+                hashed: 0,
+                persisted: RangeSet::new(),
+                tap: None,
+                generation: 0,
+            }),
+            reader: Mutex::new(None),
+        }
+    }
+
+    pub fn hashed(&self) -> u64 {
+        self.state.lock().hashed
+    }
+
+    // Taps the stream before anything is hashed; None once hashing began.
+    pub fn tap(&self) -> Option<HashedReader> {
+        let mut state = self.state.lock();
+        if state.hashed > 0 {
+            return None;
+        }
+        let (tx, rx) = channel();
+        let queued = Arc::new(AtomicU64::new(0));
+        let verified = Arc::new(AtomicBool::new(false));
+        state.tap = Some(Tap {
+            tx,
+            queued: queued.clone(),
+            verified: verified.clone(),
+        });
+        Some(HashedReader {
+            rx,
+            queued,
+            verified,
+            chunk: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    pub fn write(&self, offset: u64, data: &[u8]) {
+        {
+            let mut state = self.state.lock();
+            let end = offset + data.len() as u64;
+            if offset <= state.hashed && end > state.hashed {
+                let skip = (state.hashed - offset) as usize;
+                state.update(&data[skip..]);
+                state.hashed = end;
+            }
+        }
+        // A failed read leaves the range in place for `finish` to retry.
+        let _ = self.catch_up(CATCH_UP_BUDGET, false);
+    }
+
+    pub fn persisted(&self, range: ByteRange) {
+        self.state.lock().persisted.insert(range);
+        let _ = self.catch_up(CATCH_UP_BUDGET, false);
+    }
+
+    // A digest cannot be rewound, so rewriting bytes it already covers means
+    // hashing from the start again in `finish`.
+    pub fn invalidate(&self, range: ByteRange) {
+        let mut state = self.state.lock();
+        if range.start < state.hashed {
+            state.verifier = self.initial.clone();
+            state.hashed = 0;
+            state.tap = None;
+            state.generation += 1;
+        }
+    }
+
+    pub fn finish(&self) -> Result<(HashAlgorithm, String), StormError> {
+        let len = open(&mut self.reader.lock(), &self.path)?.metadata()?.len();
+        {
+            let mut state = self.state.lock();
+            let rest = ByteRange::new(state.hashed.min(len), len);
+            state.persisted.insert(rest);
+        }
+        self.catch_up(u64::MAX, true)?;
+        let mut state = self.state.lock();
+        let result = state.verifier.finish();
+        if let Some(tap) = state.tap.take()
+            && result.is_ok()
+        {
+            tap.verified.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    // Hashes up to `budget` persisted bytes that follow on from what was
+    // hashed, reading a chunk at a time with the state unlocked. Unless
+    // told to `wait`, it leaves the work to a catch-up already running.
+    fn catch_up(&self, budget: u64, wait: bool) -> io::Result<()> {
+        let mut reader = match wait {
+            true => self.reader.lock(),
+            false => match self.reader.try_lock() {
+                Some(reader) => reader,
+                None => return Ok(()),
+            },
+        };
+        let mut budget = budget;
+        let mut buf = Vec::new();
+        while budget > 0 {
+            let (start, generation) = {
+                let mut state = self.state.lock();
+                match state.readable() {
+                    Some(range) => {
+                        let len = range.len().min(READ_CHUNK).min(budget);
+                        buf.resize(len as usize, 0);
+                        (range.start, state.generation)
+                    }
+                    None => break,
+                }
+            };
+            let file = open(&mut reader, &self.path)?;
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut buf)?;
+            budget -= buf.len() as u64;
+
+            // Writes may have hashed part of it meanwhile.
+            let mut state = self.state.lock();
+            let end = start + buf.len() as u64;
+            if state.generation == generation && state.hashed < end {
+                let skip = (state.hashed - start) as usize;
+                state.update(&buf[skip..]);
+                state.hashed = end;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StreamState {
+    fn update(&mut self, data: &[u8]) {
+        self.verifier.update(data);
+        feed(&mut self.tap, data);
+    }
+
+    // The persisted bytes that follow on from what was hashed.
+    fn readable(&mut self) -> Option<ByteRange> {
+        while let Some(range) = self.persisted.ranges().first().copied()
+            && range.start <= self.hashed
+        {
+            if range.end > self.hashed {
+                return Some(ByteRange::new(self.hashed, range.end));
+            }
+            self.persisted.remove(range);
+        }
+        None
+    }
+}
+
+fn feed(tap: &mut Option<Tap>, data: &[u8]) {
+    if let Some(t) = tap {
+        let queued = t.queued.fetch_add(data.len() as u64, Ordering::AcqRel);
+        if queued + data.len() as u64 > TAP_BACKLOG || t.tx.send(data.to_vec()).is_err() {
+            *tap = None;
+        }
+    }
+}
+
+impl Read for HashedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => {
+                    self.queued.fetch_sub(chunk.len() as u64, Ordering::AcqRel);
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Err(_) if self.verified.load(Ordering::Acquire) => return Ok(0),
+                Err(_) => {
+                    return Err(io::Error::other(
+                        "the hashed stream ended before it was verified",
+                    ));
+                }
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// The read handle stays open, so a file renamed after its first catch-up is
+// still read from the same inode.
+fn open<'a>(slot: &'a mut Option<File>, path: &Path) -> io::Result<&'a mut File> {
+    match slot {
+        Some(file) => Ok(file),
+        None => Ok(slot.insert(File::open(path)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::hash_bytes_with;
+
+    #[test]
+    fn test_hashes_out_of_order_writes() {
+        let data: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let digest = hash_bytes_with(HashAlgorithm::Sha256, &data);
+        let spec = ChecksumSpec::parse(&format!("sha256:{}", digest)).unwrap();
+        let path = std::env::temp_dir().join(format!("stormdl-stream-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        let verifier = StreamVerifier::new(&path, &spec);
+        let (head, tail) = data.split_at(6 * 1024 * 1024);
+        verifier.persisted(ByteRange::new(6 * 1024 * 1024, data.len() as u64));
+        assert_eq!(verifier.hashed(), 0);
+        verifier.write(0, &head[..1024]);
+        verifier.write(0, &head[..4096]);
+        assert_eq!(verifier.hashed(), 4096);
+        verifier.write(4096, &head[4096..]);
+        assert_eq!(verifier.hashed(), 10 * 1024 * 1024);
+        verifier.write(6 * 1024 * 1024, tail);
+
+        let result = verifier.finish();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap(), (HashAlgorithm::Sha256, digest));
+    }
+
+    #[test]
+    fn test_tap_ends_only_when_verified() {
+        let data = b"all of the file".to_vec();
+        let digest = hash_bytes_with(HashAlgorithm::Sha256, &data);
+        let path = std::env::temp_dir().join(format!("stormdl-tap-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        let spec = ChecksumSpec::parse(&format!("sha256:{}", digest)).unwrap();
+        let verifier = StreamVerifier::new(&path, &spec);
+        let mut tap = verifier.tap().unwrap();
+        verifier.write(0, &data[..6]);
+        assert!(verifier.tap().is_none());
+        verifier.finish().unwrap();
+        let mut read = Vec::new();
+        tap.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        let spec = ChecksumSpec::parse(&format!("sha256:{}", "0".repeat(64))).unwrap();
+        let verifier = StreamVerifier::new(&path, &spec);
+        let mut tap = verifier.tap().unwrap();
+        assert!(verifier.finish().is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(tap.read_to_end(&mut Vec::new()).is_err());
+    }
+}
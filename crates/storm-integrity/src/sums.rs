@@ -0,0 +1,244 @@
+use crate::checksum::ChecksumSpec;
+use crate::hasher::HashAlgorithm;
+use bytes::Bytes;
+use stormdl_core::{DataSink, Downloader, StormError};
+use url::Url;
+
+const MAX_SUMS_SIZE: usize = 4 * 1024 * 1024;
+const COMPANION_SUFFIXES: [&str; 2] = ["sha256", "md5"];
+const SUMS_FILES: [&str; 2] = ["SHA256SUMS", "MD5SUMS"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumEntry {
+    pub filename: Option<String>,
+    pub spec: ChecksumSpec,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChecksumFile {
+    entries: Vec<ChecksumEntry>,
+}
+
+impl ChecksumFile {
+    // Reads GNU coreutils (`<hex>  <name>`, `<hex> *<name>`) and BSD
+    // (`SHA256 (<name>) = <hex>`) lines; `algorithm` comes from the file's own
+    // name when it has one, e.g. SHA256SUMS or file.iso.md5.
+    pub fn parse(text: &str, algorithm: Option<HashAlgorithm>) -> Self {
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| parse_line(line, algorithm))
+            .collect();
+        Self { entries }
+    }
+
+    pub fn entries(&self) -> &[ChecksumEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, filename: &str) -> Option<&ChecksumSpec> {
+        let exact = self
+            .entries
+            .iter()
+            .find(|e| e.filename.as_deref() == Some(filename));
+        let by_basename = || {
+            self.entries
+                .iter()
+                .find(|e| e.filename.as_deref().map(basename) == Some(filename))
+        };
+        let anonymous = || match self.entries.as_slice() {
+            [entry] if entry.filename.is_none() => Some(entry),
+            _ => None,
+        };
+        exact
+            .or_else(by_basename)
+            .or_else(anonymous)
+            .map(|e| &e.spec)
+    }
+}
+
+fn parse_line(line: &str, algorithm: Option<HashAlgorithm>) -> Option<ChecksumEntry> {
+    if let Some((tag, rest)) = line.split_once(" (")
+        && let Some((filename, digest)) = rest.rsplit_once(") = ")
+    {
+        let algorithm = tag.parse::<HashAlgorithm>().ok()?;
+        return Some(ChecksumEntry {
+            filename: Some(filename.to_string()),
+            spec: spec(digest, Some(algorithm))?,
+        });
+    }
+
+    let (digest, filename) = match line.split_once(char::is_whitespace) {
+        Some((digest, filename)) => {
+            let filename = filename.trim_start();
+            let filename = filename.strip_prefix('*').unwrap_or(filename);
+            (digest, Some(filename.to_string()))
+        }
+        None => (line, None),
+    };
+    Some(ChecksumEntry {
+        filename,
+        spec: spec(digest, algorithm)?,
+    })
+}
+
+fn spec(digest: &str, algorithm: Option<HashAlgorithm>) -> Option<ChecksumSpec> {
+    match algorithm {
+        Some(algorithm) => ChecksumSpec::parse(&format!("{}:{}", algorithm.prefix(), digest)),
+        None => ChecksumSpec::parse(digest),
+    }
+    .ok()
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn algorithm_for(url: &Url) -> Option<HashAlgorithm> {
+    let name = basename(url.path()).to_ascii_lowercase();
+    [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Md5,
+        HashAlgorithm::Blake3,
+    ]
+    .into_iter()
+    .find(|a| name.contains(a.prefix()))
+    .or_else(|| name.ends_with(".b3").then_some(HashAlgorithm::Blake3))
+}
+
+pub fn companion_urls(url: &Url) -> Vec<Url> {
+    let mut urls: Vec<Url> = COMPANION_SUFFIXES
+        .iter()
+        .map(|suffix| {
+            let mut companion = url.clone();
+            companion.set_path(&format!("{}.{}", url.path(), suffix));
+            companion
+        })
+        .collect();
+    urls.extend(SUMS_FILES.iter().filter_map(|name| url.join(name).ok()));
+    urls
+}
+
+struct TextSink {
+    data: Vec<u8>,
+}
+
+impl DataSink for TextSink {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        if self.data.len() + data.len() > MAX_SUMS_SIZE {
+            return Err(StormError::TooLarge {
+                size: (self.data.len() + data.len()) as u64,
+                limit: MAX_SUMS_SIZE as u64,
+            });
+        }
+        self.data.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
+}
+
+pub(crate) async fn fetch_small(
+    downloader: &dyn Downloader,
+    url: &Url,
+) -> Result<Vec<u8>, StormError> {
+    let mut sink = TextSink { data: Vec::new() };
+    downloader.fetch_full(url, &mut sink).await?;
+    Ok(sink.data)
+}
+
+pub async fn fetch_checksum_file(
+    downloader: &dyn Downloader,
+    url: &Url,
+) -> Result<ChecksumFile, StormError> {
+    let data = fetch_small(downloader, url).await?;
+    let text = String::from_utf8_lossy(&data);
+    Ok(ChecksumFile::parse(&text, algorithm_for(url)))
+}
+
+pub async fn fetch_checksum(
+    downloader: &dyn Downloader,
+    url: &Url,
+    filenames: &[&str],
+) -> Result<ChecksumSpec, StormError> {
+    let file = fetch_checksum_file(downloader, url).await?;
+    filenames
+        .iter()
+        .find_map(|name| file.find(name))
+        .cloned()
+        .ok_or_else(|| {
+            StormError::NotFound(format!(
+                "no checksum for {} in {}",
+                filenames.first().copied().unwrap_or_default(),
+                url
+            ))
+        })
+}
+
+pub async fn discover_checksum(
+    downloader: &dyn Downloader,
+    url: &Url,
+    filenames: &[&str],
+) -> Option<(Url, ChecksumSpec)> {
+    for candidate in companion_urls(url) {
+        match fetch_checksum(downloader, &candidate, filenames).await {
+            Ok(spec) => return Some((candidate, spec)),
+            Err(e) => tracing::debug!("No checksum from {}: {}", candidate, e),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    const MD5: &str = "5eb63bbbe01eeed093cb22bb8f5acdc3";
+
+    #[test]
+    fn test_parse_and_match_sums_files() {
+        let sums = format!(
+            "# release 1.2\n{SHA256}  ./dist/app-1.2.tar.gz\n{MD5} *app-1.2.zip\n\
+             SHA256 (app-1.2.iso) = {SHA256}\nnot a checksum line\n"
+        );
+        let file = ChecksumFile::parse(&sums, None);
+        assert_eq!(file.entries().len(), 3);
+        assert_eq!(
+            file.find("app-1.2.tar.gz").unwrap().algorithms(),
+            [HashAlgorithm::Sha256, HashAlgorithm::Blake3]
+        );
+        assert_eq!(
+            file.find("app-1.2.zip").unwrap().to_string(),
+            format!("md5:{}", MD5)
+        );
+        assert_eq!(
+            file.find("app-1.2.iso").unwrap().algorithms(),
+            [HashAlgorithm::Sha256]
+        );
+        assert!(file.find("app-1.3.zip").is_none());
+
+        let url = Url::parse("https://example.com/pub/app.iso.sha256?sig=1").unwrap();
+        let single = ChecksumFile::parse(SHA256, algorithm_for(&url));
+        assert_eq!(
+            single.find("renamed.iso").unwrap().algorithms(),
+            [HashAlgorithm::Sha256]
+        );
+
+        let url = Url::parse("https://example.com/pub/app.iso?sig=1").unwrap();
+        let companions: Vec<String> = companion_urls(&url).iter().map(|u| u.to_string()).collect();
+        assert_eq!(
+            companions,
+            [
+                "https://example.com/pub/app.iso.sha256?sig=1",
+                "https://example.com/pub/app.iso.md5?sig=1",
+                "https://example.com/pub/SHA256SUMS",
+                "https://example.com/pub/MD5SUMS",
+            ]
+        );
+    }
+}
@@ -1,62 +1,159 @@
-use crate::hasher::hash_bytes;
-use std::path::Path;
+use crate::checksum::ChecksumSpec;
+use crate::hasher::{HashAlgorithm, IncrementalHasher, hash_bytes_with};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use stormdl_core::StormError;
 
+#[derive(Clone)]
 pub struct ContentVerifier {
-    expected_hash: String,
-    algorithm: HashAlgorithm,
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum HashAlgorithm {
-    Blake3,
-    Sha256,
-    Md5,
+    // None only hashes, for callers that want the digest itself.
+    expected_hash: Option<String>,
+    hashers: Vec<IncrementalHasher>,
 }
 
 impl ContentVerifier {
     pub fn new(expected_hash: String, algorithm: HashAlgorithm) -> Self {
+        Self::with_candidates(Some(expected_hash), &[algorithm])
+    }
+
+    pub fn with_spec(spec: &ChecksumSpec) -> Self {
+        Self::with_candidates(Some(spec.digest().to_string()), spec.algorithms())
+    }
+
+    pub fn digest_only(algorithm: HashAlgorithm) -> Self {
+        Self::with_candidates(None, &[algorithm])
+    }
+
+    fn with_candidates(expected_hash: Option<String>, algorithms: &[HashAlgorithm]) -> Self {
         Self {
             expected_hash,
-            algorithm,
+            hashers: algorithms
+                .iter()
+                .map(|a| IncrementalHasher::with_algorithm(*a))
+                .collect(),
         }
     }
 
+    pub fn algorithms(&self) -> Vec<HashAlgorithm> {
+        self.hashers.iter().map(|h| h.algorithm()).collect()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for hasher in &mut self.hashers {
+            hasher.update(data);
+        }
+    }
+
+    pub fn finish(&self) -> Result<(HashAlgorithm, String), StormError> {
+        let hashes: Vec<(HashAlgorithm, String)> = self
+            .hashers
+            .iter()
+            .map(|h| (h.algorithm(), h.finalize()))
+            .collect();
+        self.check(hashes)
+    }
+
     pub fn verify(&self, data: &[u8]) -> Result<(), StormError> {
-        let actual_hash = match self.algorithm {
-            HashAlgorithm::Blake3 => hash_bytes(data),
-            HashAlgorithm::Sha256 | HashAlgorithm::Md5 => {
-                return Err(StormError::Other(format!(
-                    "{:?} verification not yet implemented",
-                    self.algorithm
-                )));
-            }
-        };
+        let hashes = self
+            .algorithms()
+            .into_iter()
+            .map(|a| (a, hash_bytes_with(a, data)))
+            .collect();
+        self.check(hashes).map(|_| ())
+    }
 
-        if actual_hash == self.expected_hash {
-            Ok(())
-        } else {
-            Err(StormError::HashMismatch {
-                expected: self.expected_hash.clone(),
-                actual: actual_hash,
-            })
+    fn check(
+        &self,
+        hashes: Vec<(HashAlgorithm, String)>,
+    ) -> Result<(HashAlgorithm, String), StormError> {
+        let Some(expected) = &self.expected_hash else {
+            return hashes
+                .into_iter()
+                .next()
+                .ok_or_else(|| StormError::Other("no hash algorithm".into()));
+        };
+        let actual = hashes.first().map(|(_, hash)| hash.clone());
+        match hashes
+            .into_iter()
+            .find(|(_, hash)| hash.eq_ignore_ascii_case(expected))
+        {
+            Some(matched) => Ok(matched),
+            None => Err(StormError::HashMismatch {
+                expected: expected.clone(),
+                actual: actual.unwrap_or_default(),
+            }),
         }
     }
 }
 
 pub fn verify_content(data: &[u8], expected_hash: &str) -> Result<(), StormError> {
-    let actual_hash = hash_bytes(data);
-    if actual_hash == expected_hash {
-        Ok(())
-    } else {
-        Err(StormError::HashMismatch {
-            expected: expected_hash.to_string(),
-            actual: actual_hash,
-        })
+    ContentVerifier::with_spec(&ChecksumSpec::parse(expected_hash)?).verify(data)
+}
+
+pub fn verify_path(
+    path: &Path,
+    checksum: &ChecksumSpec,
+) -> Result<(HashAlgorithm, String), StormError> {
+    let mut verifier = ContentVerifier::with_spec(checksum);
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        verifier.update(&buf[..n]);
     }
+    verifier.finish()
 }
 
-pub async fn verify_file(path: &Path, expected_hash: &str) -> Result<(), StormError> {
-    let data = tokio::fs::read(path).await?;
-    verify_content(&data, expected_hash)
+pub async fn verify_file(
+    path: &Path,
+    checksum: &ChecksumSpec,
+) -> Result<(HashAlgorithm, String), StormError> {
+    let path: PathBuf = path.to_path_buf();
+    let checksum = checksum.clone();
+    tokio::task::spawn_blocking(move || verify_path(&path, &checksum))
+        .await
+        .map_err(|e| StormError::Other(format!("Task error: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hasher::hash_bytes;
+
+    #[test]
+    fn test_verify_against_candidates() {
+        let bare =
+            ChecksumSpec::parse("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+                .unwrap();
+        assert!(
+            ContentVerifier::with_spec(&bare)
+                .verify(b"hello world")
+                .is_ok()
+        );
+        assert!(verify_content(b"hello world", &hash_bytes(b"hello world")).is_ok());
+
+        let md5 = ChecksumSpec::parse("md5=5eb63bbbe01eeed093cb22bb8f5acdc3").unwrap();
+        let mut verifier = ContentVerifier::with_spec(&md5);
+        verifier.update(b"hello ");
+        verifier.update(b"world");
+        assert_eq!(verifier.finish().unwrap().0, HashAlgorithm::Md5);
+        assert!(matches!(
+            ContentVerifier::with_spec(&md5).verify(b"hello there"),
+            Err(StormError::HashMismatch { .. })
+        ));
+
+        let mut digest = ContentVerifier::digest_only(HashAlgorithm::Sha256);
+        digest.update(b"hello world");
+        assert_eq!(
+            digest.finish().unwrap(),
+            (
+                HashAlgorithm::Sha256,
+                hash_bytes_with(HashAlgorithm::Sha256, b"hello world")
+            )
+        );
+    }
 }
@@ -0,0 +1,690 @@
+use flate2::CrcReader;
+use flate2::read::{DeflateDecoder, GzDecoder};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use stormdl_core::{ArchiveEntry, StormError};
+
+// Listings are for a quick look at what arrived, not an index; anything past
+// this is left out.
+pub const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+
+const EOCD: u32 = 0x0605_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_EOCD: u32 = 0x0606_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const EOCD_LEN: usize = 22;
+const MAX_ZIP_COMMENT: usize = 0xffff;
+const TAR_BLOCK: usize = 512;
+
+// Lists a zip, tar or gzipped tar by its content, whatever the file is
+// called. Ok(None) means the file isn't an archive of either kind.
+pub fn list_archive(path: &Path) -> Result<Option<Vec<ArchiveEntry>>, StormError> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let read = read_full(&mut file, &mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    match &magic[..read] {
+        [b'P', b'K', 3, 4] | [b'P', b'K', 5, 6] => list_zip(&mut file).map(Some),
+        [0x1f, 0x8b, ..] => list_tar(GzDecoder::new(BufReader::new(file))),
+        _ => list_tar(BufReader::new(file)),
+    }
+}
+
+// Unpacks a zip, tar or gzipped tar into `dest`, which should be a fresh
+// directory. Returns how many files were written; Ok(None) means the file
+// isn't an archive of either kind.
+pub fn extract_archive(path: &Path, dest: &Path) -> Result<Option<usize>, StormError> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let read = read_full(&mut file, &mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    match &magic[..read] {
+        [b'P', b'K', 3, 4] | [b'P', b'K', 5, 6] => extract_zip(&mut file, dest).map(Some),
+        _ => extract_tar(file, dest),
+    }
+}
+
+// Unpacks a tar or gzipped tar as it is read, so extraction can keep pace
+// with whatever is producing the bytes. Zips need their central directory
+// first and come back as Ok(None), like anything else that isn't a tar.
+pub fn extract_tar(mut reader: impl Read, dest: &Path) -> Result<Option<usize>, StormError> {
+    let mut magic = [0u8; 2];
+    let read = read_full(&mut reader, &mut magic)?;
+    let reader = io::Cursor::new(magic).take(read as u64).chain(reader);
+
+    let mut files = 0;
+    let mut links = Vec::new();
+    let mut unpack = |member: TarMember, data: &mut dyn Read| {
+        let Some(target) = entry_path(dest, &member.path)? else {
+            return Ok(());
+        };
+        match member.kind {
+            b'5' => fs::create_dir_all(&target)?,
+            b'0' | b'7' | 0 if member.path.ends_with('/') => fs::create_dir_all(&target)?,
+            b'0' | b'7' | 0 => {
+                write_file(&target, data, member.mode)?;
+                files += 1;
+            }
+            b'1' => {
+                let source = entry_path(dest, &member.link)?
+                    .ok_or_else(|| corrupt(&format!("bad hard link {}", member.path)))?;
+                replace_with(&target)?;
+                if fs::hard_link(&source, &target).is_err() {
+                    fs::copy(&source, &target)?;
+                }
+                files += 1;
+            }
+            b'2' => links.push((target, member.link)),
+            // Devices and fifos aren't recreated.
+            _ => {}
+        }
+        Ok(())
+    };
+    let found = if magic[..read] == [0x1f, 0x8b] {
+        walk_tar(BufReader::new(GzDecoder::new(reader)), &mut unpack)?
+    } else {
+        walk_tar(BufReader::new(reader), &mut unpack)?
+    };
+    if !found {
+        return Ok(None);
+    }
+    make_links(dest, links)?;
+    Ok(Some(files))
+}
+
+struct ZipMember {
+    path: String,
+    size: u64,
+    compressed: u64,
+    offset: u64,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    mode: u32,
+}
+
+fn list_zip(file: &mut File) -> Result<Vec<ArchiveEntry>, StormError> {
+    Ok(zip_members(file, MAX_ARCHIVE_ENTRIES)?
+        .into_iter()
+        .map(|member| ArchiveEntry {
+            is_dir: member.path.ends_with('/'),
+            path: member.path,
+            size: member.size,
+        })
+        .collect())
+}
+
+fn extract_zip(file: &mut File, dest: &Path) -> Result<usize, StormError> {
+    let mut files = 0;
+    let mut links = Vec::new();
+    for member in zip_members(file, usize::MAX)? {
+        let Some(target) = entry_path(dest, &member.path)? else {
+            continue;
+        };
+        if member.path.ends_with('/') {
+            fs::create_dir_all(&target)?;
+            continue;
+        }
+        if member.flags & 1 != 0 {
+            return Err(StormError::Other(format!(
+                "{} is encrypted; storm can't extract it",
+                member.path
+            )));
+        }
+
+        file.seek(SeekFrom::Start(member.offset))?;
+        let mut local = [0u8; 30];
+        file.read_exact(&mut local)?;
+        if le32(&local, 0) != LOCAL_HEADER {
+            return Err(corrupt("bad zip local header"));
+        }
+        let skip = le16(&local, 26) as i64 + le16(&local, 28) as i64;
+        file.seek(SeekFrom::Current(skip))?;
+
+        let raw = (&*file).take(member.compressed);
+        let body: Box<dyn Read + '_> = match member.method {
+            0 => Box::new(raw),
+            8 => Box::new(DeflateDecoder::new(raw)),
+            method => {
+                return Err(StormError::Other(format!(
+                    "{} uses zip compression method {}, which storm can't extract",
+                    member.path, method
+                )));
+            }
+        };
+        let mut body = CrcReader::new(body.take(member.size));
+        if member.mode & 0o170_000 == 0o120_000 {
+            let mut link = String::new();
+            body.read_to_string(&mut link)?;
+            links.push((target, link));
+            continue;
+        }
+        write_file(&target, &mut body, member.mode)?;
+        if body.crc().sum() != member.crc {
+            return Err(corrupt(&format!("CRC mismatch in {}", member.path)));
+        }
+        files += 1;
+    }
+    make_links(dest, links)?;
+    Ok(files)
+}
+
+fn zip_members(file: &mut File, limit: usize) -> Result<Vec<ZipMember>, StormError> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let tail_len = len.min((EOCD_LEN + MAX_ZIP_COMMENT) as u64) as usize;
+    let tail_start = len - tail_len as u64;
+    let mut tail = vec![0u8; tail_len];
+    file.seek(SeekFrom::Start(tail_start))?;
+    file.read_exact(&mut tail)?;
+
+    let eocd = (0..=tail_len.saturating_sub(EOCD_LEN))
+        .rev()
+        .find(|&at| le32(&tail, at) == EOCD)
+        .ok_or_else(|| corrupt("zip end of central directory not found"))?;
+    let mut count = le16(&tail, eocd + 10) as u64;
+    let mut offset = le32(&tail, eocd + 16) as u64;
+
+    if (count == 0xffff || offset == 0xffff_ffff)
+        && eocd >= 20
+        && le32(&tail, eocd - 20) == ZIP64_LOCATOR
+    {
+        let mut record = [0u8; 56];
+        file.seek(SeekFrom::Start(le64(&tail, eocd - 20 + 8)))?;
+        file.read_exact(&mut record)?;
+        if le32(&record, 0) != ZIP64_EOCD {
+            return Err(corrupt("bad zip64 end of central directory"));
+        }
+        count = le64(&record, 32);
+        offset = le64(&record, 48);
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file);
+    let mut members = Vec::new();
+    for _ in 0..count.min(limit as u64) {
+        let mut header = [0u8; 46];
+        reader.read_exact(&mut header)?;
+        if le32(&header, 0) != CENTRAL_HEADER {
+            return Err(corrupt("bad zip central directory entry"));
+        }
+        let mut compressed = le32(&header, 20) as u64;
+        let mut size = le32(&header, 24) as u64;
+        let mut offset = le32(&header, 42) as u64;
+        let name_len = le16(&header, 28) as usize;
+        let extra_len = le16(&header, 30) as usize;
+        let comment_len = le16(&header, 32) as usize;
+
+        let mut name = vec![0u8; name_len];
+        reader.read_exact(&mut name)?;
+        let mut extra = vec![0u8; extra_len];
+        reader.read_exact(&mut extra)?;
+        io::copy(
+            &mut reader.by_ref().take(comment_len as u64),
+            &mut io::sink(),
+        )?;
+
+        // The real sizes and offset of a >4GB member sit in the zip64 extra
+        // field, holding only the ones that overflowed, in this order.
+        let mut at = 0;
+        while at + 4 <= extra.len() {
+            let (id, len) = (le16(&extra, at), le16(&extra, at + 2) as usize);
+            if id == 1 {
+                let end = (at + 4 + len).min(extra.len());
+                let mut field = at + 4;
+                for value in [&mut size, &mut compressed, &mut offset] {
+                    if *value == 0xffff_ffff && field + 8 <= end {
+                        *value = le64(&extra, field);
+                        field += 8;
+                    }
+                }
+                break;
+            }
+            at += 4 + len;
+        }
+
+        // Unix permissions ride in the high half of the external attributes.
+        let made_on_unix = header[5] == 3;
+        members.push(ZipMember {
+            path: String::from_utf8_lossy(&name).into_owned(),
+            size,
+            compressed,
+            offset,
+            method: le16(&header, 10),
+            flags: le16(&header, 8),
+            crc: le32(&header, 16),
+            mode: if made_on_unix {
+                le32(&header, 38) >> 16
+            } else {
+                0
+            },
+        });
+    }
+    Ok(members)
+}
+
+fn list_tar(reader: impl Read) -> Result<Option<Vec<ArchiveEntry>>, StormError> {
+    let mut entries = Vec::new();
+    walk_tar(reader, |member, _| {
+        if entries.len() < MAX_ARCHIVE_ENTRIES {
+            let is_dir = member.kind == b'5' || member.path.ends_with('/');
+            entries.push(ArchiveEntry {
+                size: if is_dir { 0 } else { member.size },
+                path: member.path,
+                is_dir,
+            });
+        }
+        Ok(())
+    })?;
+    Ok((!entries.is_empty()).then_some(entries))
+}
+
+struct TarMember {
+    path: String,
+    link: String,
+    kind: u8,
+    size: u64,
+    mode: u32,
+}
+
+// Hands each tar member and a reader over its data to `visit`; whatever
+// `visit` leaves unread is skipped. Ok(false) means the stream doesn't
+// start with a tar header.
+fn walk_tar(
+    mut reader: impl Read,
+    mut visit: impl FnMut(TarMember, &mut dyn Read) -> Result<(), StormError>,
+) -> Result<bool, StormError> {
+    let mut seen = false;
+    let mut long_name: Option<String> = None;
+    let mut long_link: Option<String> = None;
+    let mut header = [0u8; TAR_BLOCK];
+
+    loop {
+        if read_full(&mut reader, &mut header)? < TAR_BLOCK || header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if !tar_checksum_ok(&header) {
+            if !seen {
+                return Ok(false);
+            }
+            return Err(corrupt("bad tar header checksum"));
+        }
+        seen = true;
+
+        let size = tar_number(&header[124..136]);
+        let kind = header[156];
+        let padded = size.div_ceil(TAR_BLOCK as u64) * TAR_BLOCK as u64;
+        match kind {
+            // GNU long names and pax headers carry the next entry's paths.
+            b'L' | b'K' | b'x' => {
+                let mut data = Vec::new();
+                (&mut reader)
+                    .take(size.min(1 << 20))
+                    .read_to_end(&mut data)?;
+                io::copy(
+                    &mut (&mut reader).take(padded - data.len() as u64),
+                    &mut io::sink(),
+                )?;
+                match kind {
+                    b'L' => long_name = Some(cstr(&data)),
+                    b'K' => long_link = Some(cstr(&data)),
+                    _ => {
+                        long_name = pax_value(&data, "path").or(long_name);
+                        long_link = pax_value(&data, "linkpath").or(long_link);
+                    }
+                }
+                continue;
+            }
+            b'g' => {
+                io::copy(&mut (&mut reader).take(padded), &mut io::sink())?;
+                continue;
+            }
+            _ => {}
+        }
+
+        let path = long_name.take().unwrap_or_else(|| {
+            let name = cstr(&header[..100]);
+            let prefix = cstr(&header[345..500]);
+            if header[257..262] == *b"ustar" && !prefix.is_empty() {
+                format!("{}/{}", prefix, name)
+            } else {
+                name
+            }
+        });
+        let link = long_link.take().unwrap_or_else(|| cstr(&header[157..257]));
+        // Links and directories have no data blocks whatever size says.
+        let (data, padded) = if matches!(kind, b'1'..=b'6') {
+            (0, 0)
+        } else {
+            (size, padded)
+        };
+
+        let mut body = (&mut reader).take(data);
+        visit(
+            TarMember {
+                path,
+                link,
+                kind,
+                size,
+                mode: tar_number(&header[100..108]) as u32,
+            },
+            &mut body,
+        )?;
+        let unread = body.limit() + padded - data;
+        io::copy(&mut (&mut reader).take(unread), &mut io::sink())?;
+    }
+
+    Ok(seen)
+}
+
+// Maps a member name under `dest`, refusing anything that would land
+// outside it. Leading slashes are dropped, as tar does; None is the archive
+// root itself.
+fn entry_path(dest: &Path, name: &str) -> Result<Option<PathBuf>, StormError> {
+    let mut path = dest.to_path_buf();
+    for part in name
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+    {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(part)), None) => path.push(part),
+            _ => return Err(corrupt(&format!("unsafe path {}", name))),
+        }
+    }
+    Ok((path != dest).then_some(path))
+}
+
+fn write_file(target: &Path, data: &mut dyn Read, mode: u32) -> Result<u64, StormError> {
+    replace_with(target)?;
+    let mut file = File::create(target)?;
+    let written = io::copy(data, &mut file)?;
+    #[cfg(unix)]
+    if mode & 0o777 != 0 {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(written)
+}
+
+// Makes room for a member: parents exist and a previous member of the same
+// name is gone.
+fn replace_with(target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::symlink_metadata(target) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(target),
+        Ok(_) => fs::remove_file(target),
+        Err(_) => Ok(()),
+    }
+}
+
+// Symlinks go in last so no member can be written through one, and only
+// when they point inside the tree.
+fn make_links(dest: &Path, links: Vec<(PathBuf, String)>) -> Result<(), StormError> {
+    for (target, link) in links {
+        let mut depth = target
+            .strip_prefix(dest)
+            .map_or(0, |rel| rel.components().count() as i64 - 1);
+        let inside = Path::new(&link).components().all(|component| {
+            depth += match component {
+                Component::Normal(_) => 1,
+                Component::ParentDir => -1,
+                Component::CurDir => 0,
+                _ => return false,
+            };
+            depth >= 0
+        });
+        if !inside {
+            tracing::warn!(
+                "Skipping {}: link to {} leaves the archive",
+                target.display(),
+                link
+            );
+            continue;
+        }
+        replace_with(&target)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&link, &target)?;
+    }
+    Ok(())
+}
+
+fn tar_checksum_ok(header: &[u8; TAR_BLOCK]) -> bool {
+    let expected = tar_number(&header[148..156]);
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+        .sum();
+    sum == expected
+}
+
+// Octal, or base-256 with the top bit set for GNU's large sizes.
+fn tar_number(field: &[u8]) -> u64 {
+    if field.first().is_some_and(|b| b & 0x80 != 0) {
+        return field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |n, &b| (n << 8) | b as u64);
+    }
+    let text = cstr(field);
+    u64::from_str_radix(text.trim(), 8).unwrap_or(0)
+}
+
+fn pax_value(data: &[u8], key: &str) -> Option<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|record| record.split_once(' ').map(|(_, kv)| kv))
+        .find_map(|kv| {
+            kv.split_once('=')
+                .filter(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+}
+
+fn cstr(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+fn corrupt(what: &str) -> StormError {
+    StormError::Other(format!("Corrupt archive: {}", what))
+}
+
+fn le16(b: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([b[at], b[at + 1]])
+}
+
+fn le32(b: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(b[at..at + 4].try_into().expect("4 bytes"))
+}
+
+fn le64(b: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(b[at..at + 8].try_into().expect("8 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn tar_header(name: &str, size: u64, kind: u8) -> [u8; TAR_BLOCK] {
+        tar_link_header(name, size, kind, "")
+    }
+
+    fn tar_link_header(name: &str, size: u64, kind: u8, link: &str) -> [u8; TAR_BLOCK] {
+        let mut header = [0u8; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000755");
+        header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].fill(b' ');
+        let sum: u64 = header.iter().map(|&b| b as u64).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        header
+    }
+
+    #[test]
+    fn test_list_tar_gz_and_zip() {
+        let dir = std::env::temp_dir().join(format!("storm-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut tar = Vec::new();
+        tar.extend(tar_header("pkg/", 0, b'5'));
+        tar.extend(tar_header("pkg/readme.txt", 5, b'0'));
+        tar.extend(b"hello");
+        tar.resize(tar.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+        tar.extend([0u8; TAR_BLOCK * 2]);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::fast());
+        gz.write_all(&tar).unwrap();
+        let tgz = dir.join("pkg.tgz");
+        std::fs::write(&tgz, gz.finish().unwrap()).unwrap();
+
+        let entries = list_archive(&tgz).unwrap().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_dir);
+        assert_eq!(entries[1].path, "pkg/readme.txt");
+        assert_eq!(entries[1].size, 5);
+
+        // Only the central directory matters for a listing.
+        let mut zip = b"PK\x03\x04".to_vec();
+        let offset = zip.len() as u32;
+        for (name, size) in [("docs/", 0u32), ("docs/a.pdf", 1234)] {
+            let mut header = [0u8; 46];
+            header[..4].copy_from_slice(&CENTRAL_HEADER.to_le_bytes());
+            header[24..28].copy_from_slice(&size.to_le_bytes());
+            header[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            zip.extend(header);
+            zip.extend(name.as_bytes());
+        }
+        let mut eocd = [0u8; EOCD_LEN];
+        eocd[..4].copy_from_slice(&EOCD.to_le_bytes());
+        eocd[10..12].copy_from_slice(&2u16.to_le_bytes());
+        eocd[16..20].copy_from_slice(&offset.to_le_bytes());
+        zip.extend(eocd);
+        let zip_path = dir.join("docs.bin");
+        std::fs::write(&zip_path, zip).unwrap();
+
+        let entries = list_archive(&zip_path).unwrap().unwrap();
+        assert_eq!(entries[1].path, "docs/a.pdf");
+        assert_eq!(entries[1].size, 1234);
+        assert!(entries[0].is_dir && !entries[1].is_dir);
+
+        let plain = dir.join("notes.txt");
+        std::fs::write(&plain, "just text").unwrap();
+        assert_eq!(list_archive(&plain).unwrap(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_tar_and_zip() {
+        let dir = std::env::temp_dir().join(format!("storm-extract-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut tar = Vec::new();
+        tar.extend(tar_header("./pkg/", 0, b'5'));
+        tar.extend(tar_header("pkg/bin/run", 5, b'0'));
+        tar.extend(b"hello");
+        tar.resize(tar.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+        tar.extend(tar_link_header("pkg/again", 0, b'1', "pkg/bin/run"));
+        tar.extend(tar_link_header("pkg/current", 0, b'2', "bin/run"));
+        tar.extend(tar_link_header("pkg/passwd", 0, b'2', "../../etc/passwd"));
+        tar.extend([0u8; TAR_BLOCK * 2]);
+        let mut gz = GzEncoder::new(Vec::new(), Compression::fast());
+        gz.write_all(&tar).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let dest = dir.join("tar");
+        assert_eq!(extract_tar(&gz[..], &dest).unwrap(), Some(2));
+        assert_eq!(std::fs::read(dest.join("pkg/again")).unwrap(), b"hello");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let meta = std::fs::metadata(dest.join("pkg/bin/run")).unwrap();
+            assert_eq!(meta.permissions().mode() & 0o777, 0o755);
+            assert_eq!(std::fs::read(dest.join("pkg/current")).unwrap(), b"hello");
+        }
+        assert!(std::fs::symlink_metadata(dest.join("pkg/passwd")).is_err());
+
+        let mut evil = tar_header("../outside", 1, b'0').to_vec();
+        evil.extend([b'x'; TAR_BLOCK]);
+        assert!(extract_tar(&evil[..], &dir.join("evil")).is_err());
+        assert!(!dir.join("outside").exists());
+        assert_eq!(
+            extract_tar(&b"PK\x03\x04"[..], &dir.join("zip")).unwrap(),
+            None
+        );
+
+        // A stored and a deflated member, with local headers this time.
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for (name, method) in [("docs/a.txt", 0u16), ("docs/b.txt", 8)] {
+            let data = format!("contents of {}", name).repeat(20);
+            let mut crc = flate2::Crc::new();
+            crc.update(data.as_bytes());
+            let body = if method == 8 {
+                let mut deflate =
+                    flate2::write::DeflateEncoder::new(Vec::new(), Compression::fast());
+                deflate.write_all(data.as_bytes()).unwrap();
+                deflate.finish().unwrap()
+            } else {
+                data.clone().into_bytes()
+            };
+
+            let mut local = [0u8; 30];
+            local[..4].copy_from_slice(&LOCAL_HEADER.to_le_bytes());
+            local[26..28].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            let mut header = [0u8; 46];
+            header[..4].copy_from_slice(&CENTRAL_HEADER.to_le_bytes());
+            header[10..12].copy_from_slice(&method.to_le_bytes());
+            header[16..20].copy_from_slice(&crc.sum().to_le_bytes());
+            header[20..24].copy_from_slice(&(body.len() as u32).to_le_bytes());
+            header[24..28].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            header[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            header[42..46].copy_from_slice(&(zip.len() as u32).to_le_bytes());
+            central.extend(header);
+            central.extend(name.as_bytes());
+            zip.extend(local);
+            zip.extend(name.as_bytes());
+            zip.extend(body);
+        }
+        let offset = zip.len() as u32;
+        zip.extend(central);
+        let mut eocd = [0u8; EOCD_LEN];
+        eocd[..4].copy_from_slice(&EOCD.to_le_bytes());
+        eocd[10..12].copy_from_slice(&2u16.to_le_bytes());
+        eocd[16..20].copy_from_slice(&offset.to_le_bytes());
+        zip.extend(eocd);
+        let zip_path = dir.join("docs.zip");
+        std::fs::write(&zip_path, zip).unwrap();
+
+        let dest = dir.join("zip");
+        assert_eq!(extract_archive(&zip_path, &dest).unwrap(), Some(2));
+        let b = std::fs::read_to_string(dest.join("docs/b.txt")).unwrap();
+        assert_eq!(b, "contents of docs/b.txt".repeat(20));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use std::alloc::{Layout, alloc_zeroed, dealloc};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+    len: usize,
+}
+
+// SAFETY: the buffer owns its allocation exclusively and exposes it only
+// through `&self`/`&mut self` borrows.
+unsafe impl Send for AlignedBuffer {}
+
+impl AlignedBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity
+            .max(DIRECT_IO_ALIGNMENT)
+            .div_ceil(DIRECT_IO_ALIGNMENT)
+            * DIRECT_IO_ALIGNMENT;
+        let layout =
+            Layout::from_size_align(capacity, DIRECT_IO_ALIGNMENT).expect("aligned buffer layout");
+        // SAFETY: layout has a non-zero size.
+        let ptr = unsafe { alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| std::alloc::handle_alloc_error(layout));
+        Self {
+            ptr,
+            layout,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        // SAFETY: the first `len` bytes are initialized (allocation is zeroed).
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.capacity() - self.len);
+        // SAFETY: `n` bytes fit after `len` within the allocation.
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.as_ptr().add(self.len), n);
+        }
+        self.len += n;
+        n
+    }
+
+    pub fn consume(&mut self, n: usize) {
+        let n = n.min(self.len);
+        // SAFETY: both regions lie within the allocation; `copy` handles overlap.
+        unsafe {
+            std::ptr::copy(self.ptr.as_ptr().add(n), self.ptr.as_ptr(), self.len - n);
+        }
+        self.len -= n;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `new` with this exact layout.
+        unsafe { dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+pub struct DirectWriter {
+    file: File,
+    path: PathBuf,
+    offset: u64,
+    buffer: AlignedBuffer,
+    fallback: Option<File>,
+}
+
+impl DirectWriter {
+    pub fn open(path: &Path, offset: u64, buffer_size: usize) -> io::Result<Self> {
+        if !offset.is_multiple_of(DIRECT_IO_ALIGNMENT as u64) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "direct I/O offset {} is not {}-byte aligned",
+                    offset, DIRECT_IO_ALIGNMENT
+                ),
+            ));
+        }
+
+        Ok(Self {
+            file: open_direct(path)?,
+            path: path.to_path_buf(),
+            offset,
+            buffer: AlignedBuffer::new(buffer_size),
+            fallback: None,
+        })
+    }
+
+    fn write_aligned_prefix(&mut self) -> io::Result<()> {
+        let aligned = self.buffer.len() / DIRECT_IO_ALIGNMENT * DIRECT_IO_ALIGNMENT;
+        if aligned == 0 {
+            return Ok(());
+        }
+        write_all_at(&self.file, &self.buffer.data()[..aligned], self.offset)?;
+        self.offset += aligned as u64;
+        self.buffer.consume(aligned);
+        Ok(())
+    }
+
+    // The unaligned tail goes through the page cache but stays buffered,
+    // so the offset stays aligned: later writes extend the tail and it goes
+    // out again with them, direct.
+    fn write_tail(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.fallback.is_none() {
+            self.fallback = Some(OpenOptions::new().write(true).open(&self.path)?);
+        }
+        let fallback = self.fallback.as_ref().expect("fallback file opened");
+        write_all_at(fallback, self.buffer.data(), self.offset)
+    }
+}
+
+impl Write for DirectWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = self.buffer.append(data);
+        if self.buffer.is_full() {
+            self.write_aligned_prefix()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_aligned_prefix()?;
+        self.write_tail()
+    }
+}
+
+impl Drop for DirectWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_direct(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+#[cfg(target_os = "macos")]
+fn open_direct(path: &Path) -> io::Result<File> {
+    use std::os::unix::io::AsRawFd;
+    let file = OpenOptions::new().write(true).open(path)?;
+    // SAFETY: fcntl on a valid, owned descriptor.
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+#[cfg(target_os = "windows")]
+fn open_direct(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(FILE_FLAG_NO_BUFFERING)
+        .open(path)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn open_direct(_path: &Path) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "direct I/O is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, data: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(data, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut data: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !data.is_empty() {
+        let n = file.seek_write(data, offset)?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        data = &data[n..];
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_buffer() {
+        let mut buffer = AlignedBuffer::new(5000);
+        assert_eq!(buffer.capacity(), 2 * DIRECT_IO_ALIGNMENT);
+        assert_eq!(buffer.data().as_ptr() as usize % DIRECT_IO_ALIGNMENT, 0);
+
+        assert_eq!(buffer.append(&[7u8; 10_000]), 2 * DIRECT_IO_ALIGNMENT);
+        assert!(buffer.is_full());
+
+        buffer.consume(DIRECT_IO_ALIGNMENT);
+        assert_eq!(buffer.len(), DIRECT_IO_ALIGNMENT);
+        assert!(buffer.data().iter().all(|b| *b == 7));
+    }
+
+    #[test]
+    fn test_writes_after_tail_stay_direct() {
+        let path = std::env::temp_dir().join(format!("storm-direct-{}.bin", std::process::id()));
+        File::create(&path).unwrap();
+        let data: Vec<u8> = (0..3 * DIRECT_IO_ALIGNMENT + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        // Filesystems like tmpfs refuse O_DIRECT.
+        let Ok(mut writer) = DirectWriter::open(&path, 0, 2 * DIRECT_IO_ALIGNMENT) else {
+            std::fs::remove_file(&path).ok();
+            return;
+        };
+
+        writer.write_all(&data[..100]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.offset, 0);
+        assert_eq!(std::fs::read(&path).unwrap(), &data[..100]);
+
+        // Only the aligned part goes out direct; the new tail stays behind.
+        writer.write_all(&data[100..]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.offset, 3 * DIRECT_IO_ALIGNMENT as u64);
+        assert_eq!(writer.buffer.len(), 100);
+        drop(writer);
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        std::fs::remove_file(&path).ok();
+    }
+}
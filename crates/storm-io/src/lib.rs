@@ -1,4 +1,7 @@
+mod archive;
 mod coalesce;
+mod direct;
+mod sparse;
 
 #[cfg(target_os = "linux")]
 mod uring;
@@ -9,7 +12,10 @@ mod kqueue;
 #[cfg(target_os = "windows")]
 mod iocp;
 
+pub use archive::{MAX_ARCHIVE_ENTRIES, extract_archive, extract_tar, list_archive};
 pub use coalesce::WriteBuffer;
+pub use direct::{AlignedBuffer, DIRECT_IO_ALIGNMENT, DirectWriter};
+pub use sparse::{make_sparse, punch_hole};
 
 #[cfg(target_os = "linux")]
 pub use uring::UringBackend;
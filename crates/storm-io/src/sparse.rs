@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io;
+
+// Lets a preallocated file keep its unwritten regions as holes. Unix
+// filesystems do that for any file extended with set_len; NTFS allocates
+// the whole length unless the file is marked sparse first.
+pub fn make_sparse(file: &File) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::IO::DeviceIoControl;
+        use windows_sys::Win32::System::Ioctl::FSCTL_SET_SPARSE;
+
+        let mut returned = 0u32;
+        // SAFETY: the handle is open for the call; FSCTL_SET_SPARSE takes no
+        // buffers.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                FSCTL_SET_SPARSE,
+                std::ptr::null(),
+                0,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    #[cfg(not(windows))]
+    let _ = file;
+    Ok(())
+}
+
+// Gives the disk blocks behind `offset..offset + len` back to the
+// filesystem; the range reads as zeros afterwards and the file keeps its
+// length. Fails with Unsupported where the filesystem can't punch holes.
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = offset.checked_add(len).ok_or(io::ErrorKind::InvalidInput)?;
+    let overflow = |_| io::Error::from(io::ErrorKind::InvalidInput);
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        let offset = libc::off_t::try_from(offset).map_err(overflow)?;
+        let len = libc::off_t::try_from(end).map_err(overflow)? - offset;
+        // SAFETY: the descriptor is open for the call.
+        let rc = unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                offset,
+                len,
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        // APFS only punches whole blocks; the partial ones at either end
+        // stay allocated.
+        const BLOCK: u64 = 4096;
+        let start = offset.div_ceil(BLOCK) * BLOCK;
+        let end = end / BLOCK * BLOCK;
+        if start >= end {
+            return Ok(());
+        }
+        let args = libc::fpunchhole_t {
+            fp_flags: 0,
+            reserved: 0,
+            fp_offset: libc::off_t::try_from(start).map_err(overflow)?,
+            fp_length: libc::off_t::try_from(end - start).map_err(overflow)?,
+        };
+        // SAFETY: the descriptor is open and `args` outlives the call.
+        let rc = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_PUNCHHOLE, &args) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use windows_sys::Win32::System::IO::DeviceIoControl;
+        use windows_sys::Win32::System::Ioctl::{FILE_ZERO_DATA_INFORMATION, FSCTL_SET_ZERO_DATA};
+
+        // Zeroing only deallocates in a sparse file.
+        make_sparse(file)?;
+        let info = FILE_ZERO_DATA_INFORMATION {
+            FileOffset: i64::try_from(offset).map_err(overflow)?,
+            BeyondFinalZero: i64::try_from(end).map_err(overflow)?,
+        };
+        let mut returned = 0u32;
+        // SAFETY: the handle is open and `info` outlives the call.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                FSCTL_SET_ZERO_DATA,
+                std::ptr::from_ref(&info).cast(),
+                std::mem::size_of::<FILE_ZERO_DATA_INFORMATION>() as u32,
+                std::ptr::null_mut(),
+                0,
+                &mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = (file, end, overflow);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_punch_hole() {
+        let path = std::env::temp_dir().join(format!("stormdl-punch-{}", std::process::id()));
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&vec![7u8; 1 << 20]).unwrap();
+        file.sync_all().unwrap();
+        let before = file.metadata().unwrap().blocks();
+
+        match punch_hole(&file, 4096, (1 << 20) - 8192) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                let _ = std::fs::remove_file(&path);
+                return;
+            }
+            result => result.unwrap(),
+        }
+        let meta = file.metadata().unwrap();
+        assert_eq!(meta.len(), 1 << 20);
+        assert!(meta.blocks() < before);
+
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut data).unwrap();
+        assert!(data[..4096].iter().all(|&b| b == 7));
+        assert!(data[4096..(1 << 20) - 4096].iter().all(|&b| b == 0));
+        assert!(data[(1 << 20) - 4096..].iter().all(|&b| b == 7));
+        let _ = std::fs::remove_file(&path);
+    }
+}
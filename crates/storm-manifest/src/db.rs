@@ -1,7 +1,11 @@
 use rusqlite::{Connection, Result as SqlResult, params};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use stormdl_core::{ByteRange, DownloadState, StormError};
+use std::time::Duration;
+use stormdl_core::{
+    ArchiveEntry, ByteRange, DownloadState, HttpVersion, RangeSet, ResourceInfo, StormError,
+    parse_tags,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ManifestEntry {
@@ -13,6 +17,13 @@ pub struct ManifestEntry {
     pub etag: Option<String>,
     pub last_modified: Option<String>,
     pub state: DownloadState,
+    pub group: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -35,10 +46,78 @@ impl SegmentEntry {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolStats {
+    pub host: String,
+    pub version: HttpVersion,
+    pub successes: u64,
+    pub downgrades: u64,
+    pub last_error: Option<String>,
+    pub updated_at: String,
+}
+
+/// The SQLite record of downloads and their segments that interrupted
+/// downloads resume from.
+///
+/// ```
+/// use std::path::Path;
+/// use stormdl_core::ByteRange;
+/// use stormdl_manifest::Manifest;
+///
+/// let manifest = Manifest::open_in_memory()?;
+/// let id = manifest.create_download(
+///     "https://example.com/file.iso",
+///     "file.iso",
+///     Path::new("file.iso"),
+///     Some(2048),
+///     None,
+///     None,
+/// )?;
+/// let segment = manifest.add_segment(id, 0, ByteRange::new(0, 2048))?;
+/// manifest.update_segment_progress(segment, 1024, None)?;
+///
+/// let incomplete = manifest.get_incomplete_downloads()?;
+/// assert_eq!(incomplete[0].filename, "file.iso");
+/// assert_eq!(manifest.get_segments(id)?[0].downloaded_bytes, 1024);
+/// # Ok::<(), stormdl_core::StormError>(())
+/// ```
 pub struct Manifest {
     conn: Connection,
 }
 
+const DOWNLOAD_COLUMNS: &str = "id, url, filename, output_path, total_size, etag, last_modified, state, group_name, tags, note, headers, created_at, updated_at";
+
+fn entry_from_row(row: &rusqlite::Row) -> SqlResult<ManifestEntry> {
+    Ok(ManifestEntry {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        filename: row.get(2)?,
+        output_path: PathBuf::from(row.get::<_, String>(3)?),
+        total_size: row.get(4)?,
+        etag: row.get(5)?,
+        last_modified: row.get(6)?,
+        state: parse_state(&row.get::<_, String>(7)?),
+        group: row.get(8)?,
+        tags: row
+            .get::<_, Option<String>>(9)?
+            .map(|tags| parse_tags(&tags))
+            .unwrap_or_default(),
+        note: row.get(10)?,
+        headers: row
+            .get::<_, Option<String>>(11)?
+            .map(|headers| {
+                headers
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .map(|(name, value)| (name.to_string(), value.trim_start().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        created_at: row.get(12)?,
+        updated_at: row.get(13)?,
+    })
+}
+
 impl Manifest {
     pub fn open(path: &Path) -> Result<Self, StormError> {
         let conn = Connection::open(path).map_err(|e| StormError::Database(e.to_string()))?;
@@ -88,10 +167,77 @@ impl Manifest {
             );
 
             CREATE INDEX IF NOT EXISTS idx_segments_download ON segments(download_id);
+
+            CREATE TABLE IF NOT EXISTS archive_entries (
+                download_id INTEGER NOT NULL,
+                entry_index INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                is_dir INTEGER NOT NULL,
+                PRIMARY KEY (download_id, entry_index),
+                FOREIGN KEY (download_id) REFERENCES downloads(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS usage (
+                month TEXT PRIMARY KEY,
+                bytes INTEGER NOT NULL DEFAULT 0
+            );
+
+            CREATE TABLE IF NOT EXISTS protocols (
+                host TEXT NOT NULL,
+                version TEXT NOT NULL,
+                successes INTEGER NOT NULL DEFAULT 0,
+                downgrades INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                PRIMARY KEY (host, version)
+            );
+
+            CREATE TABLE IF NOT EXISTS probes (
+                url TEXT PRIMARY KEY,
+                info TEXT NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
             ",
             )
             .map_err(|e| StormError::Database(e.to_string()))?;
 
+        self.add_column_if_missing("downloads", "group_name", "TEXT")?;
+        self.add_column_if_missing("downloads", "tags", "TEXT")?;
+        self.add_column_if_missing("downloads", "note", "TEXT")?;
+        self.add_column_if_missing("downloads", "headers", "TEXT")?;
+
+        Ok(())
+    }
+
+    fn add_column_if_missing(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), StormError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .collect::<SqlResult<Vec<_>>>()
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .iter()
+            .any(|name| name == column);
+
+        if !exists {
+            self.conn
+                .execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, definition
+                ))
+                .map_err(|e| StormError::Database(e.to_string()))?;
+        }
+
         Ok(())
     }
 
@@ -182,30 +328,71 @@ impl Manifest {
         Ok(())
     }
 
+    pub fn set_download_group(
+        &self,
+        download_id: i64,
+        group: Option<&str>,
+    ) -> Result<(), StormError> {
+        self.conn
+            .execute(
+                "UPDATE downloads SET group_name = ?1, updated_at = datetime('now') WHERE id = ?2",
+                params![group, download_id],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn set_download_labels(
+        &self,
+        download_id: i64,
+        tags: &[String],
+        note: Option<&str>,
+    ) -> Result<(), StormError> {
+        let tags = (!tags.is_empty()).then(|| tags.join(","));
+        self.conn
+            .execute(
+                "UPDATE downloads SET tags = ?1, note = ?2, updated_at = datetime('now') WHERE id = ?3",
+                params![tags, note, download_id],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn set_download_headers(
+        &self,
+        download_id: i64,
+        headers: &[(String, String)],
+    ) -> Result<(), StormError> {
+        let headers = (!headers.is_empty()).then(|| {
+            headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        self.conn
+            .execute(
+                "UPDATE downloads SET headers = ?1, updated_at = datetime('now') WHERE id = ?2",
+                params![headers, download_id],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
     pub fn get_download(&self, download_id: i64) -> Result<Option<ManifestEntry>, StormError> {
         let mut stmt = self
             .conn
-            .prepare(
-                "SELECT id, url, filename, output_path, total_size, etag, last_modified, state, created_at, updated_at
-                 FROM downloads WHERE id = ?1",
-            )
+            .prepare(&format!(
+                "SELECT {} FROM downloads WHERE id = ?1",
+                DOWNLOAD_COLUMNS
+            ))
             .map_err(|e| StormError::Database(e.to_string()))?;
 
         let result = stmt
-            .query_row(params![download_id], |row| {
-                Ok(ManifestEntry {
-                    id: row.get(0)?,
-                    url: row.get(1)?,
-                    filename: row.get(2)?,
-                    output_path: PathBuf::from(row.get::<_, String>(3)?),
-                    total_size: row.get(4)?,
-                    etag: row.get(5)?,
-                    last_modified: row.get(6)?,
-                    state: parse_state(&row.get::<_, String>(7)?),
-                    created_at: row.get(8)?,
-                    updated_at: row.get(9)?,
-                })
-            })
+            .query_row(params![download_id], entry_from_row)
             .optional()
             .map_err(|e| StormError::Database(e.to_string()))?;
 
@@ -241,38 +428,339 @@ impl Manifest {
         Ok(segments)
     }
 
+    pub fn written_ranges(&self, download_id: i64) -> Result<RangeSet, StormError> {
+        Ok(self
+            .get_segments(download_id)?
+            .iter()
+            .map(|s| {
+                let end = s.start_byte + s.downloaded_bytes.min(s.end_byte - s.start_byte);
+                ByteRange::new(s.start_byte, end)
+            })
+            .collect())
+    }
+
     pub fn get_incomplete_downloads(&self) -> Result<Vec<ManifestEntry>, StormError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM downloads WHERE state NOT IN ('Complete', 'Quarantined', 'Cancelled')",
+                DOWNLOAD_COLUMNS
+            ))
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        let downloads = stmt
+            .query_map([], entry_from_row)
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .collect::<SqlResult<Vec<_>>>()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(downloads)
+    }
+
+    pub fn find_resumable(
+        &self,
+        url: &str,
+        output_path: &Path,
+    ) -> Result<Option<ManifestEntry>, StormError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                &format!(
+                    "SELECT {} FROM downloads WHERE url = ?1 AND output_path = ?2 AND state NOT IN ('Complete', 'Quarantined', 'Cancelled')
+                 ORDER BY id DESC LIMIT 1",
+                DOWNLOAD_COLUMNS
+            ))
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        let result = stmt
+            .query_row(params![url, output_path.to_string_lossy()], entry_from_row)
+            .optional()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(result)
+    }
+
+    pub fn replace_segments(
+        &self,
+        download_id: i64,
+        segments: &[(ByteRange, u64, Option<String>)],
+    ) -> Result<(), StormError> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        tx.execute(
+            "DELETE FROM segments WHERE download_id = ?1",
+            params![download_id],
+        )
+        .map_err(|e| StormError::Database(e.to_string()))?;
+
+        for (index, (range, downloaded, hash)) in segments.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO segments (download_id, segment_index, start_byte, end_byte, downloaded_bytes, hash, complete)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    download_id,
+                    index,
+                    range.start,
+                    range.end,
+                    downloaded,
+                    hash,
+                    *downloaded >= range.len()
+                ],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+        }
+
+        tx.execute(
+            "UPDATE downloads SET updated_at = datetime('now') WHERE id = ?1",
+            params![download_id],
+        )
+        .map_err(|e| StormError::Database(e.to_string()))?;
+
+        tx.commit().map_err(|e| StormError::Database(e.to_string()))
+    }
+
+    pub fn set_archive_entries(
+        &self,
+        download_id: i64,
+        entries: &[ArchiveEntry],
+    ) -> Result<(), StormError> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        tx.execute(
+            "DELETE FROM archive_entries WHERE download_id = ?1",
+            params![download_id],
+        )
+        .map_err(|e| StormError::Database(e.to_string()))?;
+
+        for (index, entry) in entries.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO archive_entries (download_id, entry_index, path, size, is_dir)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![download_id, index, entry.path, entry.size, entry.is_dir],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+        }
+
+        tx.commit().map_err(|e| StormError::Database(e.to_string()))
+    }
+
+    pub fn archive_entries(&self, download_id: i64) -> Result<Vec<ArchiveEntry>, StormError> {
         let mut stmt = self
             .conn
             .prepare(
-                "SELECT id, url, filename, output_path, total_size, etag, last_modified, state, created_at, updated_at
-                 FROM downloads WHERE state NOT IN ('Complete', 'Cancelled')",
+                "SELECT path, size, is_dir FROM archive_entries
+                 WHERE download_id = ?1 ORDER BY entry_index",
             )
             .map_err(|e| StormError::Database(e.to_string()))?;
 
+        let entries = stmt
+            .query_map(params![download_id], |row| {
+                Ok(ArchiveEntry {
+                    path: row.get(0)?,
+                    size: row.get(1)?,
+                    is_dir: row.get(2)?,
+                })
+            })
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .collect::<SqlResult<Vec<_>>>()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(entries)
+    }
+
+    pub fn get_all_downloads(&self) -> Result<Vec<ManifestEntry>, StormError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM downloads ORDER BY id",
+                DOWNLOAD_COLUMNS
+            ))
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        let downloads = stmt
+            .query_map([], entry_from_row)
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .collect::<SqlResult<Vec<_>>>()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(downloads)
+    }
+
+    pub fn get_group_downloads(&self, group: &str) -> Result<Vec<ManifestEntry>, StormError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM downloads WHERE group_name = ?1 ORDER BY id",
+                DOWNLOAD_COLUMNS
+            ))
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
         let downloads = stmt
+            .query_map(params![group], entry_from_row)
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .collect::<SqlResult<Vec<_>>>()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(downloads)
+    }
+
+    pub fn recent_urls(&self, limit: usize) -> Result<Vec<String>, StormError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT url FROM downloads GROUP BY url ORDER BY MAX(id) DESC LIMIT ?1")
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        let urls = stmt
+            .query_map(params![limit as i64], |row| row.get(0))
+            .map_err(|e| StormError::Database(e.to_string()))?
+            .collect::<SqlResult<Vec<String>>>()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(urls)
+    }
+
+    pub fn add_usage(&self, bytes: u64) -> Result<(), StormError> {
+        self.conn
+            .execute(
+                "INSERT INTO usage (month, bytes) VALUES (strftime('%Y-%m', 'now'), ?1)
+                 ON CONFLICT(month) DO UPDATE SET bytes = bytes + excluded.bytes",
+                params![bytes],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn month_usage(&self) -> Result<u64, StormError> {
+        let bytes = self
+            .conn
+            .query_row(
+                "SELECT bytes FROM usage WHERE month = strftime('%Y-%m', 'now')",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(bytes.unwrap_or(0))
+    }
+
+    pub fn record_protocol_success(
+        &self,
+        host: &str,
+        version: HttpVersion,
+    ) -> Result<(), StormError> {
+        self.conn
+            .execute(
+                "INSERT INTO protocols (host, version, successes) VALUES (?1, ?2, 1)
+                 ON CONFLICT(host, version) DO UPDATE SET
+                     successes = successes + 1, updated_at = datetime('now')",
+                params![host, version.as_str()],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn record_downgrade(
+        &self,
+        host: &str,
+        version: HttpVersion,
+        reason: &str,
+    ) -> Result<(), StormError> {
+        self.conn
+            .execute(
+                "INSERT INTO protocols (host, version, downgrades, last_error) VALUES (?1, ?2, 1, ?3)
+                 ON CONFLICT(host, version) DO UPDATE SET
+                     downgrades = downgrades + 1, last_error = excluded.last_error,
+                     updated_at = datetime('now')",
+                params![host, version.as_str(), reason],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn record_probe(&self, url: &str, info: &ResourceInfo) -> Result<(), StormError> {
+        let info = serde_json::to_string(info).map_err(|e| StormError::Database(e.to_string()))?;
+        self.conn
+            .execute(
+                "INSERT INTO probes (url, info) VALUES (?1, ?2)
+                 ON CONFLICT(url) DO UPDATE SET
+                     info = excluded.info, updated_at = datetime('now')",
+                params![url, info],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(())
+    }
+
+    // What probing `url` found, if that was less than `max_age` ago.
+    pub fn recent_probe(
+        &self,
+        url: &str,
+        max_age: Duration,
+    ) -> Result<Option<ResourceInfo>, StormError> {
+        let info: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT info FROM probes
+                 WHERE url = ?1 AND updated_at > datetime('now', ?2)",
+                params![url, format!("-{} seconds", max_age.as_secs())],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        Ok(info.and_then(|info| serde_json::from_str(&info).ok()))
+    }
+
+    pub fn protocol_stats(&self) -> Result<Vec<ProtocolStats>, StormError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT host, version, successes, downgrades, last_error, updated_at
+                 FROM protocols ORDER BY host, version",
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+
+        let stats = stmt
             .query_map([], |row| {
-                Ok(ManifestEntry {
-                    id: row.get(0)?,
-                    url: row.get(1)?,
-                    filename: row.get(2)?,
-                    output_path: PathBuf::from(row.get::<_, String>(3)?),
-                    total_size: row.get(4)?,
-                    etag: row.get(5)?,
-                    last_modified: row.get(6)?,
-                    state: parse_state(&row.get::<_, String>(7)?),
-                    created_at: row.get(8)?,
-                    updated_at: row.get(9)?,
+                Ok(ProtocolStats {
+                    host: row.get(0)?,
+                    version: parse_version(&row.get::<_, String>(1)?),
+                    successes: row.get(2)?,
+                    downgrades: row.get(3)?,
+                    last_error: row.get(4)?,
+                    updated_at: row.get(5)?,
                 })
             })
             .map_err(|e| StormError::Database(e.to_string()))?
             .collect::<SqlResult<Vec<_>>>()
             .map_err(|e| StormError::Database(e.to_string()))?;
 
-        Ok(downloads)
+        Ok(stats)
     }
 
     pub fn delete_download(&self, download_id: i64) -> Result<(), StormError> {
+        self.conn
+            .execute(
+                "DELETE FROM segments WHERE download_id = ?1",
+                params![download_id],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
+        self.conn
+            .execute(
+                "DELETE FROM archive_entries WHERE download_id = ?1",
+                params![download_id],
+            )
+            .map_err(|e| StormError::Database(e.to_string()))?;
         self.conn
             .execute("DELETE FROM downloads WHERE id = ?1", params![download_id])
             .map_err(|e| StormError::Database(e.to_string()))?;
@@ -285,15 +773,26 @@ fn parse_state(s: &str) -> DownloadState {
     match s {
         "Pending" => DownloadState::Pending,
         "Probing" => DownloadState::Probing,
+        "Waiting" => DownloadState::Waiting,
         "Downloading" => DownloadState::Downloading,
         "Paused" => DownloadState::Paused,
         "Complete" => DownloadState::Complete,
         "Failed" => DownloadState::Failed,
+        "Scanning" => DownloadState::Scanning,
+        "Quarantined" => DownloadState::Quarantined,
         "Cancelled" => DownloadState::Cancelled,
         _ => DownloadState::Pending,
     }
 }
 
+fn parse_version(s: &str) -> HttpVersion {
+    match s {
+        "HTTP/2" => HttpVersion::Http2,
+        "HTTP/3" => HttpVersion::Http3,
+        _ => HttpVersion::Http1_1,
+    }
+}
+
 trait OptionalExt<T> {
     fn optional(self) -> Result<Option<T>, rusqlite::Error>;
 }
@@ -307,3 +806,184 @@ impl<T> OptionalExt<T> for Result<T, rusqlite::Error> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resumable_segments_round_trip() {
+        let manifest = Manifest::open_in_memory().unwrap();
+        let path = Path::new("/tmp/file.iso");
+        let id = manifest
+            .create_download(
+                "https://example.com/file.iso",
+                "file.iso",
+                path,
+                Some(100),
+                Some("\"v1\""),
+                None,
+            )
+            .unwrap();
+
+        manifest
+            .replace_segments(
+                id,
+                &[
+                    (ByteRange::new(0, 40), 40, Some("a".into())),
+                    (ByteRange::new(40, 100), 0, None),
+                ],
+            )
+            .unwrap();
+        manifest
+            .replace_segments(
+                id,
+                &[
+                    (ByteRange::new(0, 70), 70, Some("b".into())),
+                    (ByteRange::new(70, 100), 0, None),
+                ],
+            )
+            .unwrap();
+        let headers = vec![
+            ("Authorization".to_string(), "Bearer a:b".to_string()),
+            ("X-Empty".to_string(), String::new()),
+        ];
+        manifest.set_download_headers(id, &headers).unwrap();
+
+        let entry = manifest
+            .find_resumable("https://example.com/file.iso", path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.id, id);
+        assert_eq!(entry.headers, headers);
+        let segments = manifest.get_segments(id).unwrap();
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].complete && !segments[1].complete);
+        assert_eq!(segments[0].hash.as_deref(), Some("b"));
+        assert_eq!(segments[1].range(), ByteRange::new(70, 100));
+        assert_eq!(
+            manifest
+                .written_ranges(id)
+                .unwrap()
+                .gaps(ByteRange::new(0, 100)),
+            [ByteRange::new(70, 100)]
+        );
+
+        manifest
+            .update_download_state(id, DownloadState::Complete)
+            .unwrap();
+        assert!(
+            manifest
+                .find_resumable("https://example.com/file.iso", path)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_labels_round_trip() {
+        let manifest = Manifest::open_in_memory().unwrap();
+        let path = Path::new("/tmp/data.csv");
+        let id = manifest
+            .create_download(
+                "https://example.com/data.csv",
+                "data.csv",
+                path,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(manifest.get_download(id).unwrap().unwrap().tags.is_empty());
+
+        let tags = parse_tags("thesis, datasets,,thesis");
+        assert_eq!(tags, ["thesis", "datasets"]);
+        manifest
+            .set_download_labels(id, &tags, Some("Figures for chapter 3"))
+            .unwrap();
+        let entry = manifest.get_all_downloads().unwrap().remove(0);
+        assert_eq!(entry.tags, tags);
+        assert_eq!(entry.note.as_deref(), Some("Figures for chapter 3"));
+
+        manifest.set_download_labels(id, &[], None).unwrap();
+        let entry = manifest.get_download(id).unwrap().unwrap();
+        assert!(entry.tags.is_empty() && entry.note.is_none());
+
+        let listing = vec![
+            ArchiveEntry {
+                path: "figures/".into(),
+                size: 0,
+                is_dir: true,
+            },
+            ArchiveEntry {
+                path: "figures/plot.svg".into(),
+                size: 4096,
+                is_dir: false,
+            },
+        ];
+        manifest.set_archive_entries(id, &listing).unwrap();
+        manifest.set_archive_entries(id, &listing).unwrap();
+        assert_eq!(manifest.archive_entries(id).unwrap(), listing);
+        manifest.delete_download(id).unwrap();
+        assert!(manifest.archive_entries(id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_protocol_stats_accumulate_per_host() {
+        let manifest = Manifest::open_in_memory().unwrap();
+        manifest
+            .record_downgrade("example.com", HttpVersion::Http3, "timed out")
+            .unwrap();
+        manifest
+            .record_downgrade("example.com", HttpVersion::Http3, "connection refused")
+            .unwrap();
+        manifest
+            .record_protocol_success("example.com", HttpVersion::Http2)
+            .unwrap();
+        manifest
+            .record_protocol_success("example.com", HttpVersion::Http2)
+            .unwrap();
+
+        let stats = manifest.protocol_stats().unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].version, HttpVersion::Http2);
+        assert_eq!((stats[0].successes, stats[0].downgrades), (2, 0));
+        assert_eq!(stats[1].version, HttpVersion::Http3);
+        assert_eq!((stats[1].successes, stats[1].downgrades), (0, 2));
+        assert_eq!(stats[1].last_error.as_deref(), Some("connection refused"));
+    }
+
+    #[test]
+    fn test_recent_probe() {
+        let manifest = Manifest::open_in_memory().unwrap();
+        let url = "https://example.com/a.bin";
+        let info = ResourceInfo {
+            url: url.parse().unwrap(),
+            size: Some(1234),
+            supports_range: true,
+            etag: Some("\"v1\"".into()),
+            last_modified: None,
+            content_type: Some("application/octet-stream".into()),
+            filename: Some("a.bin".into()),
+            http_version: HttpVersion::Http2,
+            connection_rtt: None,
+        };
+        manifest.record_probe(url, &info).unwrap();
+
+        let hour = Duration::from_secs(3600);
+        let found = manifest.recent_probe(url, hour).unwrap().unwrap();
+        assert_eq!((found.size, found.etag), (info.size, info.etag));
+        assert!(
+            manifest
+                .recent_probe(url, Duration::ZERO)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            manifest
+                .recent_probe("https://example.com/b.bin", hour)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -1,3 +1,3 @@
 mod db;
 
-pub use db::{Manifest, ManifestEntry, SegmentEntry};
+pub use db::{Manifest, ManifestEntry, ProtocolStats, SegmentEntry};
@@ -0,0 +1,368 @@
+use crate::Pieces;
+use roxmltree::{Document, Node};
+use std::path::Path;
+use stormdl_core::{Mirror, MirrorSet, StormError};
+use stormdl_integrity::{ChecksumSpec, HashAlgorithm};
+use url::Url;
+
+// Torrents, magnets and ed2k links in a metalink are skipped.
+const SCHEMES: [&str; 10] = [
+    "http", "https", "ftp", "ftps", "ftpes", "sftp", "dav", "davs", "webdav", "webdavs",
+];
+
+// Strongest first; a file's checksum is the first of these it lists.
+const ALGORITHMS: [HashAlgorithm; 3] = [
+    HashAlgorithm::Sha256,
+    HashAlgorithm::Sha1,
+    HashAlgorithm::Md5,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metalink {
+    pub files: Vec<MetalinkFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetalinkFile {
+    // A relative path, which may name directories.
+    pub name: String,
+    pub size: Option<u64>,
+    // Best first.
+    pub sources: Vec<Source>,
+    // Strongest first.
+    pub checksums: Vec<ChecksumSpec>,
+    pub pieces: Option<Pieces>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub url: Url,
+    pub location: Option<String>,
+    pub max_connections: Option<usize>,
+}
+
+impl Metalink {
+    pub fn is_metalink(path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("metalink") || e.eq_ignore_ascii_case("meta4"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, StormError> {
+        let xml = std::fs::read_to_string(path)?;
+        Self::parse(&xml).map_err(|e| match e {
+            StormError::Config(message) => {
+                StormError::Config(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    // Metalink 4 (RFC 5854, .meta4) and 3 (.metalink) documents. Elements
+    // are matched by local name, so either namespace works.
+    pub fn parse(xml: &str) -> Result<Self, StormError> {
+        let doc = Document::parse(xml).map_err(|e| invalid(&e.to_string()))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "metalink" {
+            return Err(invalid("not a Metalink document"));
+        }
+        let files = root
+            .descendants()
+            .filter(|node| is(node, "file"))
+            .map(parse_file)
+            .collect::<Result<Vec<_>, _>>()?;
+        if files.is_empty() {
+            return Err(invalid("no files listed"));
+        }
+        Ok(Self { files })
+    }
+}
+
+impl MetalinkFile {
+    pub fn mirrors(&self) -> MirrorSet {
+        let mut set = MirrorSet::new(self.sources[0].url.clone());
+        for source in &self.sources[1..] {
+            let mut mirror = Mirror::new(source.url.clone());
+            if let Some(location) = &source.location {
+                mirror = mirror.with_region(location);
+            }
+            if let Some(max) = source.max_connections {
+                mirror = mirror.with_max_connections(max);
+            }
+            set.add(mirror);
+        }
+        set
+    }
+
+    pub fn checksum(&self) -> Option<&ChecksumSpec> {
+        self.checksums.first()
+    }
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Config(format!("invalid metalink: {}", message))
+}
+
+fn is(node: &Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn text<'a>(node: &Node<'a, '_>) -> &'a str {
+    node.text().unwrap_or_default().trim()
+}
+
+fn parse_file(file: Node) -> Result<MetalinkFile, StormError> {
+    let name = file
+        .attribute("name")
+        .ok_or_else(|| invalid("a file has no name"))?;
+    let name = safe_name(name).ok_or_else(|| invalid(&format!("unsafe file name '{}'", name)))?;
+    let size = file
+        .children()
+        .find(|node| is(node, "size"))
+        .map(|node| text(&node).parse::<u64>())
+        .transpose()
+        .map_err(|_| invalid(&format!("bad size for {}", name)))?;
+
+    // Metalink 4 ranks by `priority` (1 is best), 3 by `preference` (100 is
+    // best); unranked URLs come last.
+    let mut ranked = Vec::new();
+    for node in file.descendants().filter(|node| is(node, "url")) {
+        if node.attribute("type").is_some_and(|t| t == "bittorrent") {
+            continue;
+        }
+        let Some(url) = Url::parse(text(&node))
+            .ok()
+            .filter(|url| SCHEMES.contains(&url.scheme()))
+        else {
+            continue;
+        };
+        let rank = match (node.attribute("priority"), node.attribute("preference")) {
+            (Some(priority), _) => priority.parse().unwrap_or(u32::MAX),
+            (None, Some(preference)) => preference
+                .parse::<u32>()
+                .map_or(u32::MAX, |p| 101u32.saturating_sub(p)),
+            (None, None) => u32::MAX,
+        };
+        ranked.push((
+            rank,
+            Source {
+                url,
+                location: node.attribute("location").map(str::to_ascii_lowercase),
+                max_connections: node
+                    .attribute("maxconnections")
+                    .and_then(|m| m.parse().ok())
+                    .filter(|m| *m > 0),
+            },
+        ));
+    }
+    ranked.sort_by_key(|(rank, _)| *rank);
+    let sources: Vec<Source> = ranked.into_iter().map(|(_, source)| source).collect();
+    if sources.is_empty() {
+        return Err(invalid(&format!("no usable URLs for {}", name)));
+    }
+
+    let hashes: Vec<(HashAlgorithm, &str)> = file
+        .descendants()
+        .filter(|node| is(node, "hash"))
+        .filter(|node| !node.parent().is_some_and(|parent| is(&parent, "pieces")))
+        .filter_map(|node| Some((node.attribute("type")?.parse().ok()?, text(&node))))
+        .collect();
+    let mut checksums = Vec::new();
+    for algorithm in ALGORITHMS {
+        if let Some((_, digest)) = hashes.iter().find(|(a, _)| *a == algorithm) {
+            let spec = ChecksumSpec::parse(&format!("{}:{}", algorithm.prefix(), digest))
+                .map_err(|_| invalid(&format!("bad {} hash for {}", algorithm, name)))?;
+            checksums.push(spec);
+        }
+    }
+
+    let pieces = file
+        .descendants()
+        .filter(|node| is(node, "pieces"))
+        .filter_map(|node| parse_pieces(node).transpose())
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .min_by_key(|pieces| ALGORITHMS.iter().position(|a| *a == pieces.algorithm));
+    if let (Some(pieces), Some(size)) = (&pieces, size)
+        && pieces.hashes.len() as u64 != size.div_ceil(pieces.length)
+    {
+        return Err(invalid(&format!(
+            "{} piece hashes don't cover the {} bytes of {}",
+            pieces.hashes.len(),
+            size,
+            name
+        )));
+    }
+
+    Ok(MetalinkFile {
+        name,
+        size,
+        sources,
+        checksums,
+        pieces,
+    })
+}
+
+// None for an algorithm storm can't hash.
+fn parse_pieces(node: Node) -> Result<Option<Pieces>, StormError> {
+    let Some(algorithm) = node
+        .attribute("type")
+        .and_then(|t| t.parse::<HashAlgorithm>().ok())
+        .filter(|a| ALGORITHMS.contains(a))
+    else {
+        return Ok(None);
+    };
+    let length = node
+        .attribute("length")
+        .and_then(|l| l.parse().ok())
+        .filter(|l| *l > 0)
+        .ok_or_else(|| invalid("pieces without a length"))?;
+    // Metalink 3 numbers its piece hashes.
+    let mut hashes = Vec::new();
+    for (index, hash) in node.children().filter(|n| is(n, "hash")).enumerate() {
+        let piece = hash
+            .attribute("piece")
+            .map_or(Ok(index), str::parse)
+            .map_err(|_| invalid("bad piece number"))?;
+        let digest = text(&hash).to_ascii_lowercase();
+        if digest.len() != algorithm.hex_len() || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid(&format!("bad {} piece hash", algorithm)));
+        }
+        hashes.push((piece, digest));
+    }
+    hashes.sort_by_key(|(piece, _)| *piece);
+    if hashes.iter().enumerate().any(|(i, (piece, _))| i != *piece) {
+        return Err(invalid("piece hashes are missing or repeated"));
+    }
+    Ok(Some(Pieces {
+        algorithm,
+        length,
+        hashes: hashes.into_iter().map(|(_, digest)| digest).collect(),
+    }))
+}
+
+// Names may place files in subdirectories, but never outside the output
+// directory.
+fn safe_name(name: &str) -> Option<String> {
+    let parts: Vec<&str> = name.split('/').collect();
+    let safe = parts.iter().all(|part| {
+        !part.is_empty() && *part != "." && *part != ".." && !part.contains(['\\', ':', '\0'])
+    });
+    safe.then(|| parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stormdl_core::MirrorPriority;
+
+    const META4: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <published>2026-05-01T12:00:00Z</published>
+  <file name="isos/os.iso">
+    <size>2500</size>
+    <hash type="md5">5eb63bbbe01eeed093cb22bb8f5acdc3</hash>
+    <hash type="sha-256">B94D27B9934D3E08A52E52D7DA7DABFAC484EFE37A5380EE9088F7ACE2EFCDE9</hash>
+    <hash type="sha-512">00</hash>
+    <pieces length="1000" type="sha-1">
+      <hash>2aae6c35c94fcfb415dbe95f408b9ce91ee846ed</hash>
+      <hash>2aae6c35c94fcfb415dbe95f408b9ce91ee846ed</hash>
+      <hash>2aae6c35c94fcfb415dbe95f408b9ce91ee846ed</hash>
+    </pieces>
+    <url location="us" priority="20">https://us.example.com/os.iso</url>
+    <url location="DE" priority="1">https://de.example.com/os.iso</url>
+    <url>ftp://ftp.example.com/os.iso</url>
+    <url priority="5">magnet:?xt=urn:btih:abc</url>
+    <metaurl mediatype="torrent" priority="2">https://example.com/os.torrent</metaurl>
+  </file>
+</metalink>"#;
+
+    const METALINK3: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<metalink version="3.0" xmlns="http://www.metalinker.org/">
+  <files>
+    <file name="tool.tar.gz">
+      <size>1500</size>
+      <verification>
+        <hash type="sha1">2aae6c35c94fcfb415dbe95f408b9ce91ee846ed</hash>
+        <pieces length="1000" type="sha1">
+          <hash piece="1">2aae6c35c94fcfb415dbe95f408b9ce91ee846ed</hash>
+          <hash piece="0">5aae6c35c94fcfb415dbe95f408b9ce91ee846ed</hash>
+        </pieces>
+      </verification>
+      <resources>
+        <url type="bittorrent" preference="100">https://example.com/tool.torrent</url>
+        <url type="http" location="gb" preference="50">http://b.example.com/tool.tar.gz</url>
+        <url type="http" preference="90" maxconnections="2">http://a.example.com/tool.tar.gz</url>
+      </resources>
+    </file>
+    <file name="tool.tar.gz.asc">
+      <resources><url type="http">http://a.example.com/tool.tar.gz.asc</url></resources>
+    </file>
+  </files>
+</metalink>"#;
+
+    #[test]
+    fn test_parse_meta4() {
+        let metalink = Metalink::parse(META4).unwrap();
+        let [file] = metalink.files.as_slice() else {
+            panic!("expected one file");
+        };
+        assert_eq!(file.name, "isos/os.iso");
+        assert_eq!(file.size, Some(2500));
+        let urls: Vec<&str> = file.sources.iter().map(|s| s.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://de.example.com/os.iso",
+                "https://us.example.com/os.iso",
+                "ftp://ftp.example.com/os.iso"
+            ]
+        );
+        assert_eq!(file.sources[0].location.as_deref(), Some("de"));
+        assert_eq!(
+            file.checksum().unwrap().to_string(),
+            "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(file.checksums.len(), 2);
+        let pieces = file.pieces.as_ref().unwrap();
+        assert_eq!(pieces.algorithm, HashAlgorithm::Sha1);
+        assert_eq!((pieces.length, pieces.hashes.len()), (1000, 3));
+
+        let mirrors = file.mirrors();
+        assert_eq!(mirrors.len(), 3);
+        assert_eq!(mirrors.get(0).unwrap().priority, MirrorPriority::Primary);
+        assert_eq!(mirrors.get(1).unwrap().region.as_deref(), Some("us"));
+    }
+
+    #[test]
+    fn test_parse_metalink3() {
+        let metalink = Metalink::parse(METALINK3).unwrap();
+        assert_eq!(metalink.files.len(), 2);
+        let file = &metalink.files[0];
+        assert_eq!(
+            file.sources[0].url.as_str(),
+            "http://a.example.com/tool.tar.gz"
+        );
+        assert_eq!(file.sources[0].max_connections, Some(2));
+        assert_eq!(file.sources.len(), 2);
+        assert_eq!(file.checksum().unwrap().algorithms(), [HashAlgorithm::Sha1]);
+        let pieces = file.pieces.as_ref().unwrap();
+        assert!(pieces.hashes[0].starts_with("5aae"));
+        assert_eq!(metalink.files[1].size, None);
+        assert!(Metalink::is_metalink(Path::new("dl/tool.Meta4")));
+        assert!(!Metalink::is_metalink(Path::new("tool.xml")));
+    }
+
+    #[test]
+    fn test_rejects_bad_documents() {
+        let bad = |xml: &str| Metalink::parse(xml).unwrap_err().to_string();
+        assert!(bad("<html/>").contains("not a Metalink"));
+        assert!(bad("<metalink").contains("invalid metalink"));
+        assert!(bad("<metalink/>").contains("no files"));
+        assert!(bad(&META4.replace("isos/os.iso", "../os.iso")).contains("unsafe file name"));
+        assert!(bad(&META4.replace("isos/os.iso", "/etc/os.iso")).contains("unsafe"));
+        assert!(bad(&META4.replace("<size>2500", "<size>4500")).contains("don't cover"));
+        let no_urls = r#"<metalink><file name="a"><url>magnet:?xt=1</url></file></metalink>"#;
+        assert!(bad(no_urls).contains("no usable URLs"));
+    }
+}
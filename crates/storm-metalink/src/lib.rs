@@ -0,0 +1,5 @@
+mod document;
+mod pieces;
+
+pub use document::{Metalink, MetalinkFile, Source};
+pub use pieces::Pieces;
@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use stormdl_core::{ByteRange, StormError};
+use stormdl_integrity::{HashAlgorithm, hash_bytes_with};
+
+// Hashes of consecutive `length`-byte pieces of a file; the last one may be
+// shorter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pieces {
+    pub algorithm: HashAlgorithm,
+    pub length: u64,
+    pub hashes: Vec<String>,
+}
+
+impl Pieces {
+    pub fn range(&self, index: usize, size: u64) -> ByteRange {
+        let start = index as u64 * self.length;
+        ByteRange::new(start.min(size), (start + self.length).min(size))
+    }
+
+    // The pieces of the `size`-byte file at `path` that don't match their
+    // hash.
+    pub fn corrupted(&self, path: &Path, size: u64) -> Result<Vec<ByteRange>, StormError> {
+        let mut file = File::open(path)?;
+        let mut buf = Vec::new();
+        let mut corrupted = Vec::new();
+        for (index, expected) in self.hashes.iter().enumerate() {
+            let range = self.range(index, size);
+            buf.resize(range.len() as usize, 0);
+            file.read_exact(&mut buf)?;
+            if hash_bytes_with(self.algorithm, &buf) != *expected {
+                corrupted.push(range);
+            }
+        }
+        Ok(corrupted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrupted_pieces() {
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        let pieces = Pieces {
+            algorithm: HashAlgorithm::Sha1,
+            length: 1000,
+            hashes: data
+                .chunks(1000)
+                .map(|chunk| hash_bytes_with(HashAlgorithm::Sha1, chunk))
+                .collect(),
+        };
+        assert_eq!(pieces.range(2, 2500), ByteRange::new(2000, 2500));
+
+        let path = std::env::temp_dir().join(format!("storm-pieces-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        assert!(pieces.corrupted(&path, 2500).unwrap().is_empty());
+
+        let mut damaged = data.clone();
+        damaged[1500] ^= 0xff;
+        std::fs::write(&path, &damaged).unwrap();
+        assert_eq!(
+            pieces.corrupted(&path, 2500).unwrap(),
+            [ByteRange::new(1000, 2000)]
+        );
+
+        std::fs::write(&path, &data[..2000]).unwrap();
+        assert!(pieces.corrupted(&path, 2500).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
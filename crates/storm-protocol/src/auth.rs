@@ -0,0 +1,395 @@
+use crate::digest::Challenge;
+use crate::oauth::{OAuth2Grant, OAuthState};
+use crate::webdav;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use parking_lot::Mutex;
+use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use std::fmt;
+use std::sync::Arc;
+use stormdl_core::{Credentials, StormError, host_matches};
+use url::{Origin, Url};
+
+// Authorization bound to host patterns: the download's own host for
+// --user, or a profile's `*.example.com`. Mirrors and redirects only get
+// the credentials of a pattern they match, so leaving that trust scope
+// drops them (curl does the same for a single host).
+#[derive(Debug, Clone)]
+pub struct HostAuth {
+    // Tried in order; the first matching pattern wins.
+    scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    pattern: String,
+    // --user credentials stay on the download's scheme, host and port.
+    origin: Option<Origin>,
+    // Basic and Bearer go out with the first request.
+    upfront: Option<HeaderValue>,
+    // A user and password can also answer a Digest challenge; the challenge
+    // is shared by every clone so segment requests reuse its nonce.
+    digest: Option<Arc<DigestState>>,
+    // An OAuth2 grant's access token, refreshed as it expires.
+    oauth: Option<Arc<OAuthState>>,
+}
+
+struct DigestState {
+    user: String,
+    password: String,
+    challenge: Mutex<Option<Challenge>>,
+}
+
+impl DigestState {
+    fn new(user: &str, password: &str) -> Arc<Self> {
+        Arc::new(Self {
+            user: user.to_string(),
+            password: password.to_string(),
+            challenge: Mutex::new(None),
+        })
+    }
+}
+
+impl fmt::Debug for DigestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DigestState")
+            .field("user", &self.user)
+            .finish_non_exhaustive()
+    }
+}
+
+// The url crate gives schemes it doesn't know, like sftp://, an opaque
+// origin that equals nothing, so theirs is spelled out. dav:// requests go
+// out over http and share its origin.
+fn origin(url: &Url) -> Origin {
+    if let Ok(Some(http)) = webdav::http_url(url) {
+        return http.origin();
+    }
+    match (url.origin(), url.host()) {
+        (Origin::Opaque(_), Some(host)) => Origin::Tuple(
+            url.scheme().to_string(),
+            host.to_owned(),
+            url.port().unwrap_or_default(),
+        ),
+        (origin, _) => origin,
+    }
+}
+
+impl HostAuth {
+    // Credentials for `url`'s origin alone.
+    pub fn new(url: &Url, credentials: &Credentials) -> Result<Self, StormError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| StormError::InvalidUrl(format!("{} has no host", url)))?;
+        let mut auth = Self::scoped(host, credentials)?;
+        auth.scopes[0].origin = Some(origin(url));
+        Ok(auth)
+    }
+
+    // Credentials for every host matching `pattern`, e.g. `*.example.com`.
+    pub fn scoped(pattern: &str, credentials: &Credentials) -> Result<Self, StormError> {
+        let pattern = normalize(pattern)?;
+        let (upfront, digest) = match credentials {
+            Credentials::Basic { user, password } => (
+                Some(format!(
+                    "Basic {}",
+                    STANDARD.encode(format!("{}:{}", user, password))
+                )),
+                Some(DigestState::new(user, password)),
+            ),
+            Credentials::Digest { user, password } => {
+                (None, Some(DigestState::new(user, password)))
+            }
+            Credentials::Bearer(token) => (Some(format!("Bearer {}", token)), None),
+        };
+        Ok(Self {
+            scopes: vec![Scope {
+                pattern,
+                origin: None,
+                upfront: upfront.map(|value| sensitive(&value)).transpose()?,
+                digest,
+                oauth: None,
+            }],
+        })
+    }
+
+    // Access tokens from an OAuth2 grant for hosts matching `pattern`.
+    pub fn oauth(pattern: &str, grant: OAuth2Grant) -> Result<Self, StormError> {
+        Ok(Self {
+            scopes: vec![Scope {
+                pattern: normalize(pattern)?,
+                origin: None,
+                upfront: None,
+                digest: None,
+                oauth: Some(OAuthState::new(grant)),
+            }],
+        })
+    }
+
+    // An Authorization value handed out for `url`'s origin alone, like the
+    // one an LFS server sends with an object's download link.
+    pub(crate) fn header(url: &Url, mut value: HeaderValue) -> Result<Self, StormError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| StormError::InvalidUrl(format!("{} has no host", url)))?;
+        value.set_sensitive(true);
+        Ok(Self {
+            scopes: vec![Scope {
+                pattern: normalize(host)?,
+                origin: Some(origin(url)),
+                upfront: Some(value),
+                digest: None,
+                oauth: None,
+            }],
+        })
+    }
+
+    // Falls back to `other` for hosts none of these patterns match.
+    pub fn or(mut self, other: HostAuth) -> Self {
+        self.scopes.extend(other.scopes);
+        self
+    }
+
+    // The pattern whose credentials `url` gets.
+    pub fn scope(&self, url: &Url) -> Option<&str> {
+        self.scope_for(url).map(|scope| scope.pattern.as_str())
+    }
+
+    fn scope_for(&self, url: &Url) -> Option<&Scope> {
+        let host = url.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+        self.scopes.iter().find(|scope| {
+            host_matches(&scope.pattern, &host)
+                && scope.origin.as_ref().is_none_or(|o| *o == origin(url))
+        })
+    }
+
+    // The Authorization value for a GET of `url`: a Digest answer once the
+    // server has challenged, otherwise Basic/Bearer.
+    pub fn header_for(&self, url: &Url) -> Option<HeaderValue> {
+        self.request_header("GET", url)
+    }
+
+    // The same for any other method, which a Digest answer covers.
+    pub fn request_header(&self, method: &str, url: &Url) -> Option<HeaderValue> {
+        let scope = self.scope_for(url)?;
+        if let Some(digest) = &scope.digest
+            && let Some(challenge) = digest.challenge.lock().as_mut()
+        {
+            let uri = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            };
+            let value = challenge.authorization(&digest.user, &digest.password, method, &uri);
+            return sensitive(&value).ok();
+        }
+        if let Some(oauth) = &scope.oauth {
+            return oauth.header();
+        }
+        scope.upfront.clone()
+    }
+
+    // Fetches an OAuth2 access token for `url` when it has none that's
+    // still good, so the first request doesn't have to fail for one.
+    pub(crate) async fn authorize(&self, url: &Url, client: &Client) -> Result<(), StormError> {
+        match self.scope_for(url).and_then(|s| s.oauth.as_ref()) {
+            Some(oauth) if oauth.header().is_none() => oauth.refresh(client, None).await,
+            _ => Ok(()),
+        }
+    }
+
+    // After a 401 for `url`, which was sent `rejected`: whether a refreshed
+    // OAuth2 access token is worth repeating the request with.
+    pub(crate) async fn refreshed(
+        &self,
+        url: &Url,
+        client: &Client,
+        rejected: Option<&HeaderValue>,
+    ) -> Result<bool, StormError> {
+        let Some(oauth) = self.scope_for(url).and_then(|s| s.oauth.as_ref()) else {
+            return Ok(false);
+        };
+        oauth.refresh(client, rejected).await?;
+        Ok(oauth.header().as_ref() != rejected)
+    }
+
+    // The user and password for logins that aren't HTTP, like FTP.
+    pub(crate) fn login_for(&self, url: &Url) -> Option<(&str, &str)> {
+        let digest = self.scope_for(url)?.digest.as_ref()?;
+        Some((&digest.user, &digest.password))
+    }
+
+    // Takes the Digest challenge from a 401 for `url`. Returns whether the
+    // request is worth repeating: a first challenge or a stale nonce, but
+    // not a fresh challenge for credentials that were just rejected.
+    pub fn challenged(&self, url: &Url, headers: &HeaderMap) -> bool {
+        let Some(digest) = self.scope_for(url).and_then(|s| s.digest.as_ref()) else {
+            return false;
+        };
+        let Some(challenge) = Challenge::pick(
+            headers
+                .get_all(WWW_AUTHENTICATE)
+                .iter()
+                .filter_map(|v| v.to_str().ok()),
+        ) else {
+            return false;
+        };
+        let mut current = digest.challenge.lock();
+        let retry = match current.as_ref() {
+            None => true,
+            Some(previous) => challenge.is_stale() || challenge.nonce() != previous.nonce(),
+        };
+        *current = Some(challenge);
+        retry
+    }
+}
+
+fn normalize(pattern: &str) -> Result<String, StormError> {
+    let pattern = pattern.trim().trim_end_matches('.').to_ascii_lowercase();
+    if pattern.is_empty() {
+        return Err(StormError::Config(
+            "empty host pattern for credentials".into(),
+        ));
+    }
+    Ok(pattern)
+}
+
+fn sensitive(value: &str) -> Result<HeaderValue, StormError> {
+    let mut value = HeaderValue::from_str(value)
+        .map_err(|_| StormError::Config("credentials contain invalid characters".into()))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_stays_on_its_host() {
+        let url = Url::parse("https://Files.example.com/a.iso").unwrap();
+        let auth = HostAuth::new(&url, &Credentials::basic("aladdin:open sesame")).unwrap();
+        assert_eq!(auth.scope(&url), Some("files.example.com"));
+        let same = Url::parse("https://files.example.com:443/b.iso").unwrap();
+        assert_eq!(
+            auth.header_for(&same).unwrap(),
+            "Basic YWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+        let plain = Url::parse("http://files.example.com/a.iso").unwrap();
+        assert!(auth.header_for(&plain).is_none());
+        let port = Url::parse("https://files.example.com:8443/a.iso").unwrap();
+        assert!(auth.header_for(&port).is_none());
+        let mirror = Url::parse("https://mirror.example.com/a.iso").unwrap();
+        assert!(auth.header_for(&mirror).is_none());
+        assert!(format!("{:?}", auth).contains("Sensitive"));
+
+        let bearer = HostAuth::new(&url, &Credentials::Bearer("t0ken".into())).unwrap();
+        assert_eq!(bearer.header_for(&url).unwrap(), "Bearer t0ken");
+        assert!(HostAuth::new(&url, &Credentials::Bearer("bad\ntoken".into())).is_err());
+        assert!(format!("{:?}", Credentials::basic("me:secret")).ends_with("me:***)"));
+
+        // dav:// is http's origin; sftp:// gets one of its own.
+        let dav = Url::parse("davs://files.example.com/a.iso").unwrap();
+        let auth = HostAuth::new(&dav, &Credentials::basic("alice:pw")).unwrap();
+        assert!(auth.header_for(&same).is_some());
+        assert!(auth.header_for(&plain).is_none());
+        let sftp = Url::parse("sftp://files.example.com/a.iso").unwrap();
+        let auth = HostAuth::new(&sftp, &Credentials::basic("alice:pw")).unwrap();
+        assert_eq!(auth.login_for(&sftp), Some(("alice", "pw")));
+        assert!(auth.login_for(&same).is_none());
+    }
+
+    #[test]
+    fn test_scoped_profiles() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        let auth = HostAuth::new(
+            &url("https://cdn.example.com/a.iso"),
+            &Credentials::basic("me:pw"),
+        )
+        .unwrap()
+        .or(HostAuth::scoped("*.Example.com.", &Credentials::Bearer("wide".into())).unwrap())
+        .or(HostAuth::scoped("files.internal", &Credentials::Bearer("int".into())).unwrap());
+
+        assert_eq!(
+            auth.scope(&url("https://cdn.example.com/b")),
+            Some("cdn.example.com")
+        );
+        assert_eq!(
+            auth.header_for(&url("https://dl.eu.example.com./a.iso"))
+                .unwrap(),
+            "Bearer wide"
+        );
+        assert_eq!(
+            auth.header_for(&url("https://files.internal/a.iso"))
+                .unwrap(),
+            "Bearer int"
+        );
+        assert!(auth.scope(&url("https://example.com/a.iso")).is_none());
+        assert!(auth.header_for(&url("https://evil-example.com/")).is_none());
+        assert!(HostAuth::scoped(" ", &Credentials::Bearer("x".into())).is_err());
+    }
+
+    #[test]
+    fn test_digest_challenge_is_reused() {
+        let url = Url::parse("https://files.example.com/a.iso?v=2").unwrap();
+        let auth = HostAuth::new(&url, &Credentials::digest("alice:s3cret")).unwrap();
+        assert!(auth.header_for(&url).is_none());
+        assert!(!format!("{:?}", auth).contains("s3cret"));
+
+        let challenge = |nonce: &str, stale: bool| {
+            let mut headers = HeaderMap::new();
+            headers.append(
+                WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"x\""),
+            );
+            let digest = format!(
+                "Digest realm=\"files\", qop=\"auth\", nonce=\"{}\", stale={}",
+                nonce, stale
+            );
+            headers.append(WWW_AUTHENTICATE, digest.parse().unwrap());
+            headers
+        };
+        let mirror = Url::parse("https://mirror.example.com/a.iso").unwrap();
+        assert!(!auth.challenged(&mirror, &challenge("n1", false)));
+        assert!(auth.challenged(&url, &challenge("n1", false)));
+
+        // Clones share the nonce, as segment workers do.
+        let worker = auth.clone();
+        let first = auth.header_for(&url).unwrap();
+        let second = worker.header_for(&url).unwrap();
+        let first = first.to_str().unwrap();
+        assert!(first.starts_with("Digest username=\"alice\", realm=\"files\", nonce=\"n1\""));
+        assert!(first.contains("uri=\"/a.iso?v=2\"") && first.contains("nc=00000001"));
+        assert!(second.to_str().unwrap().contains("nc=00000002"));
+
+        // A repeated challenge means the password was wrong; stale means retry.
+        assert!(!auth.challenged(&url, &challenge("n1", false)));
+        assert!(worker.challenged(&url, &challenge("n2", true)));
+        assert!(
+            auth.header_for(&url)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .contains("nonce=\"n2\"")
+        );
+
+        let basic = HostAuth::new(&url, &Credentials::basic("alice:s3cret")).unwrap();
+        assert!(
+            basic
+                .header_for(&url)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("Basic ")
+        );
+        assert!(basic.challenged(&url, &challenge("n1", false)));
+        assert!(
+            basic
+                .header_for(&url)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("Digest ")
+        );
+    }
+}
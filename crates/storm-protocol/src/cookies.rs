@@ -0,0 +1,565 @@
+use parking_lot::Mutex;
+use ring::aead::{self, Aad, CHACHA20_POLY1305, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use stormdl_core::StormError;
+use url::Url;
+
+const MAGIC: &[u8] = b"STORMCK1";
+const SALT_LEN: usize = 16;
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    expires: Option<u64>,
+}
+
+impl Cookie {
+    fn parse(header: &str, url: &Url, now: u64) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut attributes = header.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().trim_matches('"').to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            secure: false,
+            expires: None,
+        };
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = attribute
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .unwrap_or((attribute.trim(), ""));
+            match key.to_ascii_lowercase().as_str() {
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if domain.is_empty() {
+                        continue;
+                    }
+                    if !domain.contains('.') || !domain_match(&host, &domain) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "max-age" => max_age = value.parse::<i64>().ok(),
+                "expires" => cookie.expires = parse_expires(value),
+                _ => {}
+            }
+        }
+        if let Some(age) = max_age {
+            cookie.expires = Some(now.saturating_add_signed(age.min(i64::MAX / 2)));
+        }
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn same_slot(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_ok && path_match(url.path(), &self.path) && (!self.secure || url.scheme() == "https")
+    }
+
+    fn to_line(&self) -> String {
+        let domain = if self.host_only {
+            self.domain.clone()
+        } else {
+            format!(".{}", self.domain)
+        };
+        let flag = |b: bool| if b { "TRUE" } else { "FALSE" };
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            domain,
+            flag(!self.host_only),
+            self.path,
+            flag(self.secure),
+            self.expires.unwrap_or(0),
+            self.name,
+            self.value
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.starts_with('#') || line.trim().is_empty() {
+            return None;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+            return None;
+        };
+        Some(Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            host_only: !subdomains.eq_ignore_ascii_case("TRUE"),
+            path: path.to_string(),
+            secure: secure.eq_ignore_ascii_case("TRUE"),
+            expires: expires.parse().ok().filter(|&e| e > 0),
+        })
+    }
+}
+
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(idx) => url.path()[..idx].to_string(),
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<std::net::IpAddr>().is_err())
+}
+
+fn path_match(request: &str, cookie: &str) -> bool {
+    request == cookie
+        || (request.starts_with(cookie)
+            && (cookie.ends_with('/') || request[cookie.len()..].starts_with('/')))
+}
+
+fn parse_expires(value: &str) -> Option<u64> {
+    let time = httpdate::parse_http_date(value)
+        .or_else(|_| httpdate::parse_http_date(&value.replace('-', " ")))
+        .ok()?;
+    Some(
+        time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+struct Cipher {
+    salt: [u8; SALT_LEN],
+    key: LessSafeKey,
+}
+
+impl Cipher {
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ROUNDS).expect("rounds are non-zero"),
+            &salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key).expect("key length matches");
+        Self {
+            salt,
+            key: LessSafeKey::new(key),
+        }
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, StormError> {
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| StormError::Config("no randomness for cookie jar".into()))?;
+        let mut data = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(MAGIC),
+                &mut data,
+            )
+            .map_err(|_| StormError::Config("failed to encrypt cookie jar".into()))?;
+        Ok([MAGIC, &self.salt, &nonce, &data].concat())
+    }
+
+    fn open(passphrase: &str, contents: &[u8]) -> Result<(Self, String), StormError> {
+        let invalid = || StormError::Config("cookie jar is corrupt or the key is wrong".into());
+        let body = contents.strip_prefix(MAGIC).ok_or_else(invalid)?;
+        if body.len() < SALT_LEN + aead::NONCE_LEN {
+            return Err(invalid());
+        }
+        let (salt, body) = body.split_at(SALT_LEN);
+        let (nonce, sealed) = body.split_at(aead::NONCE_LEN);
+        let cipher = Self::derive(passphrase, salt.try_into().expect("salt length checked"));
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| invalid())?;
+        let mut data = sealed.to_vec();
+        let plaintext = cipher
+            .key
+            .open_in_place(nonce, Aad::from(MAGIC), &mut data)
+            .map_err(|_| invalid())?;
+        let text = String::from_utf8(plaintext.to_vec()).map_err(|_| invalid())?;
+        Ok((cipher, text))
+    }
+}
+
+struct JarFile {
+    path: PathBuf,
+    cipher: Option<Cipher>,
+}
+
+impl JarFile {
+    fn save(&self, cookies: &[Cookie]) -> Result<(), StormError> {
+        let now = now();
+        let mut text = String::from("# Netscape HTTP Cookie File\n");
+        for cookie in cookies.iter().filter(|c| !c.is_expired(now)) {
+            text.push_str(&cookie.to_line());
+            text.push('\n');
+        }
+        let contents = match &self.cipher {
+            Some(cipher) => cipher.seal(text.as_bytes())?,
+            None => text.into_bytes(),
+        };
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        write_private(&tmp, &contents)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+    file: Option<JarFile>,
+}
+
+impl std::fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieJar")
+            .field("cookies", &self.cookies.lock().len())
+            .field("path", &self.file.as_ref().map(|file| &file.path))
+            .field(
+                "encrypted",
+                &self.file.as_ref().is_some_and(|f| f.cipher.is_some()),
+            )
+            .finish()
+    }
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self {
+            cookies: Mutex::new(Vec::new()),
+            file: None,
+        }
+    }
+
+    pub fn open(path: impl Into<PathBuf>, passphrase: Option<&str>) -> Result<Self, StormError> {
+        let path = path.into();
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let (cipher, text) = match (passphrase, contents) {
+            (Some(passphrase), Some(contents)) if contents.starts_with(MAGIC) => {
+                let (cipher, text) = Cipher::open(passphrase, &contents)?;
+                (Some(cipher), text)
+            }
+            (Some(passphrase), contents) => {
+                let mut salt = [0u8; SALT_LEN];
+                SystemRandom::new()
+                    .fill(&mut salt)
+                    .map_err(|_| StormError::Config("no randomness for cookie jar".into()))?;
+                let text = contents
+                    .map(|c| String::from_utf8_lossy(&c).into_owned())
+                    .unwrap_or_default();
+                (Some(Cipher::derive(passphrase, salt)), text)
+            }
+            (None, Some(contents)) if contents.starts_with(MAGIC) => {
+                return Err(StormError::Config(format!(
+                    "cookie jar {} is encrypted but no key was given",
+                    path.display()
+                )));
+            }
+            (None, contents) => (
+                None,
+                contents
+                    .map(|c| String::from_utf8_lossy(&c).into_owned())
+                    .unwrap_or_default(),
+            ),
+        };
+
+        let now = now();
+        let cookies = text
+            .lines()
+            .filter_map(Cookie::from_line)
+            .filter(|c| !c.is_expired(now))
+            .collect();
+        Ok(Self {
+            cookies: Mutex::new(cookies),
+            file: Some(JarFile { path, cipher }),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let now = now();
+        let cookies = self.cookies.lock();
+        let mut matching: Vec<&Cookie> = cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    pub fn store<'a>(&self, url: &Url, set_cookies: impl IntoIterator<Item = &'a str>) {
+        let now = now();
+        let mut cookies = self.cookies.lock();
+        let mut changed = false;
+        for cookie in set_cookies
+            .into_iter()
+            .filter_map(|header| Cookie::parse(header, url, now))
+        {
+            let before = cookies.len();
+            cookies.retain(|c| !c.same_slot(&cookie));
+            changed |= cookies.len() != before;
+            if !cookie.is_expired(now) {
+                cookies.push(cookie);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.save(&cookies);
+        }
+    }
+
+    // Merges a Netscape cookies.txt, as exported by browsers or `curl -c`.
+    pub fn import(&self, path: impl AsRef<Path>) -> Result<usize, StormError> {
+        let text = std::fs::read_to_string(path)?;
+        let now = now();
+        let imported: Vec<Cookie> = text
+            .lines()
+            .filter_map(Cookie::from_line)
+            .filter(|c| !c.is_expired(now))
+            .collect();
+        Ok(self.merge(imported))
+    }
+
+    // `NAME=VALUE[; NAME=VALUE...]` as given to `--cookie`: session cookies
+    // for every path on the URL's host.
+    pub fn insert(&self, url: &Url, pairs: &str) -> Result<usize, StormError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| StormError::InvalidUrl(url.to_string()))?
+            .to_ascii_lowercase();
+        let mut cookies = Vec::new();
+        for pair in pairs.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .filter(|(name, _)| !name.trim().is_empty())
+                .ok_or_else(|| {
+                    StormError::Config(format!("cookie '{}': expected NAME=VALUE", pair))
+                })?;
+            cookies.push(Cookie {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+                domain: host.clone(),
+                host_only: true,
+                path: "/".to_string(),
+                secure: false,
+                expires: None,
+            });
+        }
+        Ok(self.merge(cookies))
+    }
+
+    fn merge(&self, new: Vec<Cookie>) -> usize {
+        let count = new.len();
+        if count == 0 {
+            return 0;
+        }
+        let mut cookies = self.cookies.lock();
+        for cookie in new {
+            cookies.retain(|c| !c.same_slot(&cookie));
+            cookies.push(cookie);
+        }
+        self.save(&cookies);
+        count
+    }
+
+    fn save(&self, cookies: &[Cookie]) {
+        if let Some(file) = &self.file
+            && let Err(e) = file.save(cookies)
+        {
+            tracing::warn!("Failed to save cookies to {}: {}", file.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookies_scoped_by_host_and_path() {
+        let jar = CookieJar::new();
+        let landing = Url::parse("https://www.example.com/downloads/index.html").unwrap();
+        jar.store(
+            &landing,
+            [
+                "session=abc123; Path=/; Domain=example.com; HttpOnly",
+                "page=1",
+                "token=s3cret; Secure; Max-Age=3600",
+                "evil=1; Domain=other.com",
+                "gone=1; Max-Age=0",
+            ],
+        );
+        assert_eq!(jar.len(), 3);
+
+        let file = Url::parse("https://cdn.example.com/downloads/file.iso").unwrap();
+        assert_eq!(jar.header(&file).as_deref(), Some("session=abc123"));
+
+        let same_dir = Url::parse("https://www.example.com/downloads/file.iso").unwrap();
+        assert_eq!(
+            jar.header(&same_dir).as_deref(),
+            Some("page=1; token=s3cret; session=abc123")
+        );
+        let plain = Url::parse("http://www.example.com/downloads/file.iso").unwrap();
+        assert_eq!(
+            jar.header(&plain).as_deref(),
+            Some("page=1; session=abc123")
+        );
+        assert!(
+            jar.header(&Url::parse("https://example.org/").unwrap())
+                .is_none()
+        );
+
+        jar.store(
+            &landing,
+            ["session=gone; Path=/; Domain=example.com; Max-Age=-1"],
+        );
+        assert_eq!(jar.header(&file), None);
+    }
+
+    #[test]
+    fn test_import_and_insert() {
+        let path = std::env::temp_dir().join(format!("storm-import-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Netscape HTTP Cookie File\n\
+             .example.com\tTRUE\t/\tFALSE\t0\tsession\tabc123\n\
+             #HttpOnly_files.example.com\tFALSE\t/dl\tTRUE\t4102444800\tauth\txyz\n\
+             example.com\tFALSE\t/\tFALSE\t1\texpired\t1\n",
+        )
+        .unwrap();
+
+        let jar = CookieJar::new();
+        assert_eq!(jar.import(&path).unwrap(), 2);
+        let url = Url::parse("https://files.example.com/dl/file.iso").unwrap();
+        assert_eq!(
+            jar.header(&url).as_deref(),
+            Some("auth=xyz; session=abc123")
+        );
+
+        let mirror = Url::parse("http://mirror.example.org/pub/file.iso").unwrap();
+        assert_eq!(jar.insert(&mirror, "token=1; lang=en").unwrap(), 2);
+        assert_eq!(jar.insert(&mirror, "token=2").unwrap(), 1);
+        let other_path = Url::parse("http://mirror.example.org/other").unwrap();
+        assert_eq!(jar.header(&other_path).as_deref(), Some("lang=en; token=2"));
+        assert!(jar.insert(&mirror, "novalue").is_err());
+        assert!(jar.insert(&mirror, "=1").is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_jar_persists_encrypted() {
+        let path = std::env::temp_dir().join(format!("storm-cookies-{}.txt", std::process::id()));
+        let url = Url::parse("https://example.com/landing").unwrap();
+
+        let jar = CookieJar::open(&path, Some("hunter2")).unwrap();
+        jar.store(
+            &url,
+            ["session=abc123; Expires=Fri, 01-Jan-2100 00:00:00 GMT"],
+        );
+        let contents = std::fs::read(&path).unwrap();
+        assert!(contents.starts_with(MAGIC));
+        assert!(!String::from_utf8_lossy(&contents).contains("abc123"));
+
+        let reopened = CookieJar::open(&path, Some("hunter2")).unwrap();
+        assert_eq!(reopened.header(&url).as_deref(), Some("session=abc123"));
+        assert!(CookieJar::open(&path, Some("wrong")).is_err());
+        assert!(CookieJar::open(&path, None).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
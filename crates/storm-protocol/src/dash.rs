@@ -0,0 +1,603 @@
+use crate::hls::typed_or_named;
+use roxmltree::{Document, Node};
+use std::time::Duration;
+use stormdl_core::{ByteRange, ResourceInfo, StormError};
+use url::Url;
+
+const MIME_TYPES: [&str; 1] = ["application/dash+xml"];
+// A template over a long presentation can name more segments than anything
+// real would have.
+const MAX_SEGMENTS: u64 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mpd {
+    pub duration: Option<Duration>,
+    pub adaptation_sets: Vec<AdaptationSet>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Video,
+    Audio,
+    Text,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptationSet {
+    pub kind: ContentKind,
+    pub lang: Option<String>,
+    // ContentProtection: the segments are DRM-encrypted.
+    pub protected: bool,
+    pub representations: Vec<Representation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Representation {
+    pub id: String,
+    pub bandwidth: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codecs: Option<String>,
+    pub mime_type: Option<String>,
+    pub init: Option<SegmentRef>,
+    pub segments: Vec<SegmentRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentRef {
+    pub uri: Url,
+    pub range: Option<ByteRange>,
+}
+
+// By type, or by a .mpd name when the server sends a generic one.
+pub fn is_manifest(info: &ResourceInfo) -> bool {
+    typed_or_named(info, &MIME_TYPES, ".mpd")
+}
+
+impl Mpd {
+    // Static (on-demand) manifests. Only the first period is read; later
+    // ones are usually inserted ads.
+    pub fn parse(xml: &str, base: &Url) -> Result<Self, StormError> {
+        let doc = Document::parse(xml).map_err(|e| invalid(&e.to_string()))?;
+        let root = doc.root_element();
+        if root.tag_name().name() != "MPD" {
+            return Err(invalid("not an MPD document"));
+        }
+        if root.attribute("type") == Some("dynamic") {
+            return Err(StormError::Protocol(
+                "live DASH manifests are not supported".into(),
+            ));
+        }
+        let duration = root
+            .attribute("mediaPresentationDuration")
+            .map(parse_duration)
+            .transpose()?;
+        let base = base_url(root, base)?;
+        let period = children(root, "Period")
+            .next()
+            .ok_or_else(|| invalid("no Period"))?;
+        let start = period
+            .attribute("start")
+            .map(parse_duration)
+            .transpose()?
+            .unwrap_or_default();
+        let period_duration = match period.attribute("duration") {
+            Some(value) => Some(parse_duration(value)?),
+            None => duration.map(|d| d.saturating_sub(start)),
+        };
+        let base = base_url(period, &base)?;
+
+        let mut adaptation_sets = Vec::new();
+        for set in children(period, "AdaptationSet") {
+            let set_base = base_url(set, &base)?;
+            let representations = children(set, "Representation")
+                .map(|rep| parse_representation(&[period, set, rep], &set_base, period_duration))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mime = set.attribute("mimeType").or_else(|| {
+                representations
+                    .first()
+                    .and_then(|rep| rep.mime_type.as_deref())
+            });
+            let kind = match set
+                .attribute("contentType")
+                .or_else(|| mime.and_then(|m| m.split('/').next()))
+            {
+                Some("video") => ContentKind::Video,
+                Some("audio") => ContentKind::Audio,
+                Some("text") => ContentKind::Text,
+                _ if mime == Some("application/ttml+xml") => ContentKind::Text,
+                _ => ContentKind::Other,
+            };
+            let protected = set.descendants().any(|node| is(&node, "ContentProtection"));
+            adaptation_sets.push(AdaptationSet {
+                kind,
+                lang: set.attribute("lang").map(String::from),
+                protected,
+                representations,
+            });
+        }
+        if adaptation_sets
+            .iter()
+            .all(|set| set.representations.is_empty())
+        {
+            return Err(invalid("no representations"));
+        }
+        Ok(Self {
+            duration,
+            adaptation_sets,
+        })
+    }
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Protocol(format!("invalid DASH manifest: {}", message))
+}
+
+fn is(node: &Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |child| is(child, name))
+}
+
+fn join(base: &Url, uri: &str) -> Result<Url, StormError> {
+    base.join(uri.trim())
+        .map_err(|e| invalid(&format!("bad URL '{}': {}", uri, e)))
+}
+
+fn base_url(node: Node, base: &Url) -> Result<Url, StormError> {
+    match children(node, "BaseURL").next().and_then(|n| n.text()) {
+        Some(uri) => join(base, uri),
+        None => Ok(base.clone()),
+    }
+}
+
+// The innermost level that sets it wins.
+fn inherited<'a>(levels: &[Node<'a, '_>], name: &str) -> Option<&'a str> {
+    levels.iter().rev().find_map(|node| node.attribute(name))
+}
+
+fn parse_attr<T: std::str::FromStr>(
+    value: Option<&str>,
+    name: &str,
+) -> Result<Option<T>, StormError> {
+    value
+        .map(|v| {
+            v.trim()
+                .parse()
+                .map_err(|_| invalid(&format!("bad {}", name)))
+        })
+        .transpose()
+}
+
+fn parse_representation(
+    levels: &[Node; 3],
+    base: &Url,
+    period_duration: Option<Duration>,
+) -> Result<Representation, StormError> {
+    let rep = levels[2];
+    let id = rep.attribute("id").unwrap_or_default().to_string();
+    let bandwidth = parse_attr(rep.attribute("bandwidth"), "bandwidth")?.unwrap_or(0);
+    let base = base_url(rep, base)?;
+    let mut representation = Representation {
+        width: parse_attr(inherited(&levels[1..], "width"), "width")?,
+        height: parse_attr(inherited(&levels[1..], "height"), "height")?,
+        codecs: inherited(&levels[1..], "codecs").map(String::from),
+        mime_type: inherited(&levels[1..], "mimeType").map(String::from),
+        id,
+        bandwidth,
+        init: None,
+        segments: Vec::new(),
+    };
+
+    let templates: Vec<Node> = levels
+        .iter()
+        .filter_map(|node| children(*node, "SegmentTemplate").next())
+        .collect();
+    let lists: Vec<Node> = levels
+        .iter()
+        .filter_map(|node| children(*node, "SegmentList").next())
+        .collect();
+    if !templates.is_empty() {
+        parse_template(&templates, &base, period_duration, &mut representation)?;
+    } else if !lists.is_empty() {
+        let init = lists
+            .iter()
+            .rev()
+            .find_map(|list| children(*list, "Initialization").next());
+        if let Some(init) = init {
+            representation.init = Some(segment_ref(
+                &base,
+                init.attribute("sourceURL"),
+                init.attribute("range"),
+            )?);
+        }
+        if let Some(list) = lists
+            .iter()
+            .rev()
+            .find(|list| children(**list, "SegmentURL").next().is_some())
+        {
+            representation.segments = children(*list, "SegmentURL")
+                .map(|s| segment_ref(&base, s.attribute("media"), s.attribute("mediaRange")))
+                .collect::<Result<_, _>>()?;
+        }
+    } else {
+        // SegmentBase, or nothing: one file holding it all.
+        let init = levels
+            .iter()
+            .rev()
+            .filter_map(|node| children(*node, "SegmentBase").next())
+            .find_map(|segment_base| children(segment_base, "Initialization").next());
+        if let Some(init) = init.filter(|init| init.attribute("range").is_some()) {
+            representation.init = Some(segment_ref(&base, None, init.attribute("range"))?);
+        }
+        representation.segments.push(SegmentRef {
+            uri: base,
+            range: None,
+        });
+    }
+    if representation.segments.is_empty() {
+        return Err(invalid(&format!(
+            "representation '{}' has no segments",
+            representation.id
+        )));
+    }
+    Ok(representation)
+}
+
+fn segment_ref(
+    base: &Url,
+    uri: Option<&str>,
+    range: Option<&str>,
+) -> Result<SegmentRef, StormError> {
+    let uri = match uri {
+        Some(uri) => join(base, uri)?,
+        None => base.clone(),
+    };
+    let range = match range {
+        Some(value) => {
+            let (first, last) = value
+                .split_once('-')
+                .and_then(|(a, b)| {
+                    Some((a.trim().parse::<u64>().ok()?, b.trim().parse::<u64>().ok()?))
+                })
+                .filter(|(first, last)| first <= last)
+                .ok_or_else(|| invalid(&format!("bad byte range '{}'", value)))?;
+            Some(ByteRange::new(first, last + 1))
+        }
+        None => None,
+    };
+    Ok(SegmentRef { uri, range })
+}
+
+fn parse_template(
+    templates: &[Node],
+    base: &Url,
+    period_duration: Option<Duration>,
+    representation: &mut Representation,
+) -> Result<(), StormError> {
+    let media =
+        inherited(templates, "media").ok_or_else(|| invalid("SegmentTemplate without media"))?;
+    let timescale: u64 = parse_attr(inherited(templates, "timescale"), "timescale")?.unwrap_or(1);
+    let start_number: u64 =
+        parse_attr(inherited(templates, "startNumber"), "startNumber")?.unwrap_or(1);
+    let id = representation.id.clone();
+    let bandwidth = representation.bandwidth;
+    let url = |number: u64, time: u64| -> Result<SegmentRef, StormError> {
+        let uri = fill(media, &id, bandwidth, number, time)?;
+        Ok(SegmentRef {
+            uri: join(base, &uri)?,
+            range: None,
+        })
+    };
+    if let Some(init) = inherited(templates, "initialization") {
+        let uri = fill(init, &id, bandwidth, 0, 0)?;
+        representation.init = Some(SegmentRef {
+            uri: join(base, &uri)?,
+            range: None,
+        });
+    }
+    let period_ticks = period_duration.map(|d| (d.as_secs_f64() * timescale as f64).round() as u64);
+
+    let timeline = templates
+        .iter()
+        .rev()
+        .find_map(|template| children(*template, "SegmentTimeline").next());
+    let segments = &mut representation.segments;
+    if let Some(timeline) = timeline {
+        let entries: Vec<Node> = children(timeline, "S").collect();
+        let mut time = 0;
+        let mut number = start_number;
+        for (i, entry) in entries.iter().enumerate() {
+            if let Some(t) = parse_attr(entry.attribute("t"), "S@t")? {
+                time = t;
+            }
+            let d: u64 = parse_attr(entry.attribute("d"), "S@d")?
+                .filter(|d| *d > 0)
+                .ok_or_else(|| invalid("a SegmentTimeline entry has no duration"))?;
+            let r: i64 = parse_attr(entry.attribute("r"), "S@r")?.unwrap_or(0);
+            // A negative repeat runs up to the next entry or the period end.
+            let count = if r < 0 {
+                let end = match entries.get(i + 1).and_then(|next| next.attribute("t")) {
+                    Some(t) => parse_attr(Some(t), "S@t")?,
+                    None => period_ticks,
+                }
+                .ok_or_else(|| invalid("open-ended SegmentTimeline without a period duration"))?;
+                end.saturating_sub(time).div_ceil(d)
+            } else {
+                r as u64 + 1
+            };
+            if segments.len() as u64 + count > MAX_SEGMENTS {
+                return Err(invalid("too many segments"));
+            }
+            for _ in 0..count {
+                segments.push(url(number, time)?);
+                time += d;
+                number += 1;
+            }
+        }
+    } else {
+        let duration: u64 = parse_attr(inherited(templates, "duration"), "duration")?
+            .filter(|d| *d > 0)
+            .ok_or_else(|| invalid("SegmentTemplate without a duration or SegmentTimeline"))?;
+        let total =
+            period_ticks.ok_or_else(|| invalid("no presentation duration to count segments by"))?;
+        let count = total.div_ceil(duration);
+        if count > MAX_SEGMENTS {
+            return Err(invalid("too many segments"));
+        }
+        for k in 0..count {
+            segments.push(url(start_number + k, k * duration)?);
+        }
+    }
+    Ok(())
+}
+
+// `$RepresentationID$`, `$Number$`, `$Time$` and `$Bandwidth$`, the numeric
+// ones with an optional `%0<width>d`; `$$` is a dollar sign.
+fn fill(
+    template: &str,
+    id: &str,
+    bandwidth: u64,
+    number: u64,
+    time: u64,
+) -> Result<String, StormError> {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find('$')
+            .ok_or_else(|| invalid(&format!("unterminated identifier in '{}'", template)))?;
+        let identifier = &after[..end];
+        rest = &after[end + 1..];
+        let (name, format) = match identifier.split_once('%') {
+            Some((name, format)) => (name, Some(format)),
+            None => (identifier, None),
+        };
+        let value = match name {
+            "" => {
+                out.push('$');
+                continue;
+            }
+            "RepresentationID" => {
+                out.push_str(id);
+                continue;
+            }
+            "Number" => number,
+            "Time" => time,
+            "Bandwidth" => bandwidth,
+            _ => return Err(invalid(&format!("unknown identifier ${}$", identifier))),
+        };
+        let width = match format {
+            Some(format) => {
+                let digits = format
+                    .trim_start_matches('0')
+                    .strip_suffix('d')
+                    .ok_or_else(|| invalid(&format!("bad format in ${}$", identifier)))?;
+                if digits.is_empty() {
+                    0
+                } else {
+                    digits
+                        .parse()
+                        .map_err(|_| invalid(&format!("bad format in ${}$", identifier)))?
+                }
+            }
+            None => 0,
+        };
+        out.push_str(&format!("{:0width$}", value, width = width));
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+// ISO 8601 durations as MPDs write them: `PT1H2M3.5S`, `P1DT2H`.
+fn parse_duration(value: &str) -> Result<Duration, StormError> {
+    let bad = || invalid(&format!("bad duration '{}'", value));
+    let rest = value.trim().strip_prefix('P').ok_or_else(bad)?;
+    let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let date_units = [
+        ('Y', 365.0 * 86400.0),
+        ('M', 30.0 * 86400.0),
+        ('W', 7.0 * 86400.0),
+        ('D', 86400.0),
+    ];
+    let time_units = [('H', 3600.0), ('M', 60.0), ('S', 1.0)];
+    let mut secs = 0.0;
+    for (part, units) in [(date, &date_units[..]), (time, &time_units[..])] {
+        let mut digits = String::new();
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                digits.push(c);
+                continue;
+            }
+            let (_, unit) = units.iter().find(|(u, _)| *u == c).ok_or_else(bad)?;
+            secs += digits.parse::<f64>().map_err(|_| bad())? * unit;
+            digits.clear();
+        }
+        if !digits.is_empty() {
+            return Err(bad());
+        }
+    }
+    Duration::try_from_secs_f64(secs).map_err(|_| bad())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_mpd() {
+        let xml = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT0H0M9.5S">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet contentType="video" mimeType="video/mp4">
+      <SegmentTemplate timescale="1000" duration="4000" startNumber="1"
+          initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%03d$.m4s"/>
+      <Representation id="v720" bandwidth="3000000" width="1280" height="720" codecs="avc1.64001f"/>
+      <Representation id="v360" bandwidth="800000" width="640" height="360"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4" lang="en">
+      <Representation id="a" bandwidth="128000">
+        <SegmentTemplate timescale="48000" initialization="a/init.mp4" media="a/$Time$.m4s">
+          <SegmentTimeline>
+            <S t="0" d="96000" r="2"/>
+            <S d="24000" r="-1"/>
+          </SegmentTimeline>
+        </SegmentTemplate>
+      </Representation>
+    </AdaptationSet>
+    <AdaptationSet mimeType="text/vtt">
+      <ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011"/>
+      <Representation id="t" bandwidth="100">
+        <SegmentList>
+          <Initialization sourceURL="subs.mp4" range="0-99"/>
+          <SegmentURL mediaRange="100-199"/>
+          <SegmentURL media="more.mp4"/>
+        </SegmentList>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let mpd = Mpd::parse(xml, &url("https://example.com/v/manifest.mpd")).unwrap();
+        assert_eq!(mpd.duration, Some(Duration::from_millis(9500)));
+        let [video, audio, text] = &mpd.adaptation_sets[..] else {
+            panic!("expected 3 adaptation sets");
+        };
+
+        assert_eq!(video.kind, ContentKind::Video);
+        let hd = &video.representations[0];
+        assert_eq!((hd.width, hd.height), (Some(1280), Some(720)));
+        assert_eq!(hd.mime_type.as_deref(), Some("video/mp4"));
+        assert_eq!(
+            hd.init.as_ref().unwrap().uri.as_str(),
+            "https://example.com/v/media/v720/init.mp4"
+        );
+        let names: Vec<&str> = hd.segments.iter().map(|s| s.uri.path()).collect();
+        assert_eq!(
+            names,
+            [
+                "/v/media/v720/seg-001.m4s",
+                "/v/media/v720/seg-002.m4s",
+                "/v/media/v720/seg-003.m4s"
+            ]
+        );
+        assert_eq!(video.representations[1].height, Some(360));
+
+        assert_eq!(audio.kind, ContentKind::Audio);
+        assert_eq!(audio.lang.as_deref(), Some("en"));
+        let names: Vec<&str> = audio.representations[0]
+            .segments
+            .iter()
+            .map(|s| s.uri.path())
+            .collect();
+        // Three of two seconds, then half-second ones to 9.5s.
+        assert_eq!(
+            names,
+            [
+                "/v/media/a/0.m4s",
+                "/v/media/a/96000.m4s",
+                "/v/media/a/192000.m4s",
+                "/v/media/a/288000.m4s",
+                "/v/media/a/312000.m4s",
+                "/v/media/a/336000.m4s",
+                "/v/media/a/360000.m4s",
+                "/v/media/a/384000.m4s",
+                "/v/media/a/408000.m4s",
+                "/v/media/a/432000.m4s"
+            ]
+        );
+
+        assert_eq!(text.kind, ContentKind::Text);
+        assert!(text.protected && !video.protected);
+        let subs = &text.representations[0];
+        assert_eq!(
+            subs.init,
+            Some(SegmentRef {
+                uri: url("https://example.com/v/media/subs.mp4"),
+                range: Some(ByteRange::new(0, 100)),
+            })
+        );
+        assert_eq!(subs.segments[0].range, Some(ByteRange::new(100, 200)));
+        assert_eq!(subs.segments[0].uri.path(), "/v/media/");
+        assert_eq!(subs.segments[1].uri.path(), "/v/media/more.mp4");
+    }
+
+    #[test]
+    fn test_single_file_and_errors() {
+        let xml = r#"<MPD mediaPresentationDuration="P1DT1M">
+  <Period><AdaptationSet>
+    <Representation id="1" mimeType="audio/webm" bandwidth="64000">
+      <BaseURL>https://cdn.example.com/audio.webm</BaseURL>
+      <SegmentBase indexRange="300-900"><Initialization range="0-299"/></SegmentBase>
+    </Representation>
+  </AdaptationSet></Period>
+</MPD>"#;
+        let mpd = Mpd::parse(xml, &url("https://example.com/a.mpd")).unwrap();
+        assert_eq!(mpd.duration, Some(Duration::from_secs(86460)));
+        let set = &mpd.adaptation_sets[0];
+        assert_eq!(set.kind, ContentKind::Audio);
+        let rep = &set.representations[0];
+        assert_eq!(
+            rep.init.as_ref().unwrap().range,
+            Some(ByteRange::new(0, 300))
+        );
+        assert_eq!(
+            rep.segments,
+            [SegmentRef {
+                uri: url("https://cdn.example.com/audio.webm"),
+                range: None,
+            }]
+        );
+
+        let base = url("https://example.com/");
+        assert!(Mpd::parse("<html/>", &base).is_err());
+        assert!(Mpd::parse(r#"<MPD type="dynamic"><Period/></MPD>"#, &base).is_err());
+        assert!(
+            Mpd::parse(
+                r#"<MPD><Period><AdaptationSet><Representation id="x">
+                <SegmentTemplate media="$Number$.m4s" duration="2"/>
+                </Representation></AdaptationSet></Period></MPD>"#,
+                &base
+            )
+            .is_err()
+        );
+        assert_eq!(
+            fill("$$$Bandwidth%08d$", "", 4200, 0, 0).unwrap(),
+            "$00004200"
+        );
+        assert!(fill("$Other$", "", 0, 0, 0).is_err());
+        assert!(parse_duration("PT1X").is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use md5::Md5;
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::{Digest as _, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Algorithm {
+    Md5,
+    Sha256,
+}
+
+impl Algorithm {
+    fn hash(self, data: &str) -> String {
+        match self {
+            Self::Md5 => format!("{:x}", Md5::digest(data.as_bytes())),
+            Self::Sha256 => format!("{:x}", Sha256::digest(data.as_bytes())),
+        }
+    }
+}
+
+// One `WWW-Authenticate: Digest ...` challenge (RFC 7616). The nonce is
+// kept and reused with a rising nonce count until the server calls it
+// stale, so segments don't each pay for a 401 round trip.
+#[derive(Debug, Clone)]
+pub(crate) struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    sess: bool,
+    qop: bool,
+    stale: bool,
+    cnonce: String,
+    count: u32,
+}
+
+impl Challenge {
+    // Picks the strongest Digest challenge we can answer from the
+    // WWW-Authenticate headers of a 401.
+    pub(crate) fn pick<'a>(headers: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        headers
+            .into_iter()
+            .flat_map(parse_challenges)
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("digest"))
+            .filter_map(|(_, params)| Self::from_params(&params))
+            .max_by_key(|c| c.algorithm)
+    }
+
+    fn from_params(params: &[(String, String)]) -> Option<Self> {
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+        };
+        let algorithm = param("algorithm").unwrap_or_else(|| "MD5".to_string());
+        let (algorithm, sess) = match algorithm.to_ascii_uppercase().as_str() {
+            "MD5" => (Algorithm::Md5, false),
+            "MD5-SESS" => (Algorithm::Md5, true),
+            "SHA-256" => (Algorithm::Sha256, false),
+            "SHA-256-SESS" => (Algorithm::Sha256, true),
+            _ => return None,
+        };
+        // Only qop=auth is spoken; a challenge offering just auth-int is
+        // skipped, one without qop is the RFC 2069 form.
+        let qop = match param("qop") {
+            Some(qop) => {
+                if !qop
+                    .split(',')
+                    .any(|q| q.trim().eq_ignore_ascii_case("auth"))
+                {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        Some(Self {
+            realm: param("realm")?,
+            nonce: param("nonce")?,
+            opaque: param("opaque"),
+            algorithm,
+            sess,
+            qop,
+            stale: param("stale").is_some_and(|s| s.eq_ignore_ascii_case("true")),
+            cnonce: cnonce(),
+            count: 0,
+        })
+    }
+
+    pub(crate) fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    pub(crate) fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub(crate) fn authorization(
+        &mut self,
+        user: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+    ) -> String {
+        self.count += 1;
+        let h = |data: String| self.algorithm.hash(&data);
+        let mut ha1 = h(format!("{}:{}:{}", user, self.realm, password));
+        if self.sess {
+            ha1 = h(format!("{}:{}:{}", ha1, self.nonce, self.cnonce));
+        }
+        let ha2 = h(format!("{}:{}", method, uri));
+        let nc = format!("{:08x}", self.count);
+        let response = if self.qop {
+            h(format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, self.cnonce, ha2
+            ))
+        } else {
+            h(format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+
+        let algorithm = match (self.algorithm, self.sess) {
+            (Algorithm::Md5, false) => "MD5",
+            (Algorithm::Md5, true) => "MD5-sess",
+            (Algorithm::Sha256, false) => "SHA-256",
+            (Algorithm::Sha256, true) => "SHA-256-sess",
+        };
+        let mut header = format!(
+            "Digest username={}, realm={}, nonce={}, uri={}, algorithm={}, response=\"{}\"",
+            quote(user),
+            quote(&self.realm),
+            quote(&self.nonce),
+            quote(uri),
+            algorithm,
+            response
+        );
+        if self.qop {
+            header.push_str(&format!(
+                ", qop=auth, nc={}, cnonce=\"{}\"",
+                nc, self.cnonce
+            ));
+        }
+        if let Some(opaque) = &self.opaque {
+            header.push_str(&format!(", opaque={}", quote(opaque)));
+        }
+        header
+    }
+}
+
+fn cnonce() -> String {
+    let mut bytes = [0u8; 16];
+    // A predictable cnonce only weakens -sess; it's still a valid answer.
+    let _ = SystemRandom::new().fill(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Splits a WWW-Authenticate value into (scheme, params) challenges; one
+// header may carry several, e.g. `Digest ..., algorithm=SHA-256, Basic ...`.
+pub(crate) fn parse_challenges(value: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut challenges: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let token_end = rest
+            .find(|c: char| c == '=' || c == ',' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let token = &rest[..token_end];
+        let after = rest[token_end..].trim_start();
+        if token.is_empty() {
+            break;
+        }
+
+        // A bare token starts a new challenge; `name=value` adds a parameter
+        // to the current one.
+        if let Some(after) = after.strip_prefix('=').filter(|a| !a.starts_with('=')) {
+            let after = after.trim_start();
+            let (value, next) = match after.strip_prefix('"') {
+                Some(quoted) => unquote(quoted),
+                None => {
+                    let end = after.find(',').unwrap_or(after.len());
+                    (after[..end].trim().to_string(), &after[end..])
+                }
+            };
+            if let Some((_, params)) = challenges.last_mut() {
+                params.push((token.to_string(), value));
+            }
+            rest = next;
+        } else {
+            challenges.push((token.to_string(), Vec::new()));
+            rest = after;
+        }
+    }
+    challenges
+}
+
+// Reads a quoted-string (after the opening quote); returns it and the rest.
+fn unquote(s: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    value.push(escaped);
+                }
+            }
+            '"' => return (value, &s[i + 1..]),
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 7616, section 3.9.1.
+    const RFC_CHALLENGE: &str = r#"Digest
+        realm="http-auth@example.org",
+        qop="auth, auth-int",
+        algorithm=SHA-256,
+        nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v",
+        opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#;
+
+    #[test]
+    fn test_rfc_7616_example() {
+        let md5 = RFC_CHALLENGE.replace("algorithm=SHA-256", "algorithm=MD5");
+        let mut challenge = Challenge::pick([md5.as_str(), RFC_CHALLENGE]).unwrap();
+        assert_eq!(challenge.algorithm, Algorithm::Sha256);
+        challenge.cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ".to_string();
+        let header = challenge.authorization("Mufasa", "Circle of Life", "GET", "/dir/index.html");
+        assert!(header.contains(
+            "response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\""
+        ));
+        assert!(header.contains("nc=00000001"));
+        assert!(header.contains("opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""));
+
+        let mut challenge = Challenge::pick([md5.as_str()]).unwrap();
+        challenge.cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ".to_string();
+        challenge.authorization("Mufasa", "Circle of Life", "GET", "/dir/index.html");
+        let header = challenge.authorization("Mufasa", "Circle of Life", "GET", "/dir/index.html");
+        assert!(header.contains("nc=00000002"));
+        challenge.count = 0;
+        let header = challenge.authorization("Mufasa", "Circle of Life", "GET", "/dir/index.html");
+        assert!(header.contains("response=\"8ca523f5e9506fed4657c9700eebdbec\""));
+    }
+
+    #[test]
+    fn test_parse_challenges() {
+        let challenges = parse_challenges(
+            r#"Basic realm="files", Digest realm="a, \"b\"", nonce=abc, stale=TRUE"#,
+        );
+        assert_eq!(challenges.len(), 2);
+        assert_eq!(challenges[0].0, "Basic");
+        assert_eq!(challenges[1].1[0].1, "a, \"b\"");
+
+        let challenge = Challenge::pick([
+            r#"Basic realm="x""#,
+            r#"Digest realm="a", nonce=abc, stale=TRUE"#,
+        ])
+        .unwrap();
+        assert!(challenge.is_stale() && !challenge.qop && challenge.nonce() == "abc");
+        assert!(Challenge::pick([r#"Digest realm="x", nonce="n", qop="auth-int""#]).is_none());
+        assert!(
+            Challenge::pick([r#"Digest realm="x", nonce="n", algorithm=SHA-512-256"#]).is_none()
+        );
+    }
+}
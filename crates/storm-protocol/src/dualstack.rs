@@ -0,0 +1,48 @@
+use crate::Route;
+use std::net::SocketAddr;
+use url::{Host, Url};
+
+#[derive(Debug, Clone)]
+pub struct DualStack {
+    host: String,
+    ipv4: Vec<SocketAddr>,
+    ipv6: Vec<SocketAddr>,
+}
+
+impl DualStack {
+    pub async fn resolve(url: &Url) -> Option<Self> {
+        let Some(Host::Domain(host)) = url.host() else {
+            return None;
+        };
+        let port = url.port_or_known_default()?;
+        let addrs = match tokio::net::lookup_host((host, port)).await {
+            Ok(addrs) => addrs,
+            Err(e) => {
+                tracing::debug!("Failed to resolve {}: {}", host, e);
+                return None;
+            }
+        };
+
+        let (ipv6, ipv4): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.partition(|a| a.is_ipv6());
+        if ipv4.is_empty() || ipv6.is_empty() {
+            tracing::debug!("{} is not reachable over both IPv4 and IPv6", host);
+            return None;
+        }
+        Some(Self {
+            host: host.to_string(),
+            ipv4,
+            ipv6,
+        })
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn routes(&self) -> Vec<Route> {
+        vec![
+            Route::pinned("IPv4", &self.host, self.ipv4.clone()),
+            Route::pinned("IPv6", &self.host, self.ipv6.clone()),
+        ]
+    }
+}
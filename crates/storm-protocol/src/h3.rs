@@ -1,14 +1,78 @@
+use crate::socks::SocksUdpSocket;
+use crate::{ClientOptions, HostAuth, PreferredProtocol, ProxyConfig, SocketOptions};
 use async_trait::async_trait;
-use bytes::Buf;
+use bytes::{Buf, Bytes};
+use parking_lot::Mutex;
 use quinn::{ClientConfig, Endpoint, TransportConfig};
-use std::net::ToSocketAddrs;
+use socket2::SockRef;
+use std::collections::{HashMap, HashSet};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use stormdl_core::{ByteRange, DataSink, Downloader, HttpVersion, ResourceInfo, StormError};
-use url::Url;
+use url::{Origin, Url};
+
+// Requests multiplexed over one QUIC connection before another is opened to
+// the host, well under the 100 concurrent streams servers commonly allow:
+// a 16-segment download shares a single handshake.
+const STREAMS_PER_CONNECTION: usize = 16;
+
+type SendRequest = h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>;
 
 pub struct Http3Downloader {
     endpoint: Endpoint,
+    relay: Option<SocketAddr>,
+    headers: http::HeaderMap,
+    auth: Option<HostAuth>,
+    // Open connections by host and port.
+    connections: Mutex<HashMap<String, Vec<Pooled>>>,
+    // Held while a connection is opened, so segments starting together
+    // wait for one handshake instead of each making their own.
+    connecting: tokio::sync::Mutex<()>,
+}
+
+struct Pooled {
+    connection: quinn::Connection,
+    send: SendRequest,
+    handshake: Duration,
+    streams: Arc<AtomicUsize>,
+}
+
+impl Pooled {
+    fn is_open(&self) -> bool {
+        self.connection.close_reason().is_none()
+    }
+
+    fn has_room(&self) -> bool {
+        self.streams.load(Ordering::Acquire) < STREAMS_PER_CONNECTION
+    }
+
+    fn lease(&self, fresh: bool) -> Lease {
+        self.streams.fetch_add(1, Ordering::AcqRel);
+        Lease {
+            send: self.send.clone(),
+            rtt: if fresh {
+                self.handshake
+            } else {
+                self.connection.rtt()
+            },
+            streams: self.streams.clone(),
+        }
+    }
+}
+
+// One request's share of a pooled connection.
+struct Lease {
+    send: SendRequest,
+    rtt: Duration,
+    streams: Arc<AtomicUsize>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.streams.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 impl Http3Downloader {
@@ -35,7 +99,14 @@ impl Http3Downloader {
             .map_err(|e| StormError::Network(format!("Failed to create endpoint: {}", e)))?;
         endpoint.set_default_client_config(client_config);
 
-        Ok(Self { endpoint })
+        Ok(Self {
+            endpoint,
+            relay: None,
+            headers: http::HeaderMap::new(),
+            auth: None,
+            connections: Mutex::new(HashMap::new()),
+            connecting: tokio::sync::Mutex::new(()),
+        })
     }
 
     pub fn turbo() -> Result<Self, StormError> {
@@ -72,7 +143,68 @@ impl Http3Downloader {
             .map_err(|e| StormError::Network(format!("Failed to create endpoint: {}", e)))?;
         endpoint.set_default_client_config(client_config);
 
-        Ok(Self { endpoint })
+        Ok(Self {
+            endpoint,
+            relay: None,
+            headers: http::HeaderMap::new(),
+            auth: None,
+            connections: Mutex::new(HashMap::new()),
+            connecting: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn with_headers(mut self, headers: &[(String, String)]) -> Result<Self, StormError> {
+        self.headers = crate::http::header_map(headers)?;
+        Ok(self)
+    }
+
+    pub fn with_auth(mut self, auth: Option<HostAuth>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_socket_options(self, socket: SocketOptions) -> Result<Self, StormError> {
+        if socket.is_empty() {
+            return Ok(self);
+        }
+        let udp = UdpSocket::bind("0.0.0.0:0")
+            .map_err(|e| StormError::Network(format!("Failed to bind UDP socket: {}", e)))?;
+        socket
+            .apply_udp(SockRef::from(&udp))
+            .map_err(|e| StormError::Network(format!("Failed to tune UDP socket: {}", e)))?;
+        self.endpoint
+            .rebind(udp)
+            .map_err(|e| StormError::Network(format!("Failed to rebind endpoint: {}", e)))?;
+        Ok(self)
+    }
+
+    // QUIC can only cross a SOCKS5 proxy, through a UDP relay; HTTP proxies
+    // get an error so negotiation settles on HTTP/2 instead.
+    pub async fn with_proxy(
+        mut self,
+        proxy: &ProxyConfig,
+        url: &Url,
+        socket: SocketOptions,
+    ) -> Result<Self, StormError> {
+        let Some(route) = proxy.circuit_route(url) else {
+            return Ok(self);
+        };
+        if !route.scheme().starts_with("socks5") {
+            return Err(StormError::Network(format!(
+                "{} proxy can't carry QUIC",
+                route.scheme().to_uppercase()
+            )));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| StormError::InvalidUrl("Missing host".into()))?;
+        let relay =
+            SocksUdpSocket::associate(&route, host, url.port().unwrap_or(443), socket).await?;
+        self.relay = Some(relay.relay());
+        self.endpoint
+            .rebind_abstract(Arc::new(relay))
+            .map_err(|e| StormError::Network(format!("Failed to rebind endpoint: {}", e)))?;
+        Ok(self)
     }
 
     fn create_tls_config() -> Result<rustls::ClientConfig, StormError> {
@@ -88,32 +220,56 @@ impl Http3Downloader {
         Ok(tls_config)
     }
 
-    async fn connect(
-        &self,
-        url: &Url,
-    ) -> Result<
-        (
-            h3::client::SendRequest<h3_quinn::OpenStreams, bytes::Bytes>,
-            Duration,
-        ),
-        StormError,
-    > {
+    // A stream on an open connection to `url`'s host with room for one,
+    // or on a new one.
+    async fn connection(&self, url: &Url) -> Result<Lease, StormError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| StormError::InvalidUrl("Missing host".into()))?;
+        let key = format!("{}:{}", host, url.port().unwrap_or(443));
+        let pooled = |connections: &mut HashMap<String, Vec<Pooled>>| {
+            let open = connections.entry(key.clone()).or_default();
+            open.retain(Pooled::is_open);
+            open.iter()
+                .filter(|pooled| pooled.has_room())
+                .min_by_key(|pooled| pooled.streams.load(Ordering::Acquire))
+                .map(|pooled| pooled.lease(false))
+        };
+        if let Some(lease) = pooled(&mut self.connections.lock()) {
+            return Ok(lease);
+        }
+
+        let _connecting = self.connecting.lock().await;
+        if let Some(lease) = pooled(&mut self.connections.lock()) {
+            return Ok(lease);
+        }
+        let fresh = self.connect(url).await?;
+        let lease = fresh.lease(true);
+        self.connections.lock().entry(key).or_default().push(fresh);
+        Ok(lease)
+    }
+
+    async fn connect(&self, url: &Url) -> Result<Pooled, StormError> {
         let host = url
             .host_str()
             .ok_or_else(|| StormError::InvalidUrl("Missing host".into()))?;
         let port = url.port().unwrap_or(443);
 
-        let addr = format!("{}:{}", host, port)
-            .to_socket_addrs()
-            .map_err(|e| StormError::Network(format!("DNS resolution failed: {}", e)))?
-            .find(|a| a.is_ipv4())
-            .or_else(|| {
-                format!("{}:{}", host, port)
-                    .to_socket_addrs()
-                    .ok()
-                    .and_then(|mut addrs| addrs.next())
-            })
-            .ok_or_else(|| StormError::Network("No addresses found for host".into()))?;
+        // Through a relay the proxy resolves and reaches the host.
+        let addr = match self.relay {
+            Some(relay) => relay,
+            None => format!("{}:{}", host, port)
+                .to_socket_addrs()
+                .map_err(|e| StormError::Network(format!("DNS resolution failed: {}", e)))?
+                .find(|a| a.is_ipv4())
+                .or_else(|| {
+                    format!("{}:{}", host, port)
+                        .to_socket_addrs()
+                        .ok()
+                        .and_then(|mut addrs| addrs.next())
+                })
+                .ok_or_else(|| StormError::Network("No addresses found for host".into()))?,
+        };
 
         let start = Instant::now();
         let connection = self
@@ -122,13 +278,23 @@ impl Http3Downloader {
             .map_err(|e| StormError::Network(format!("Connection failed: {}", e)))?
             .await
             .map_err(|e| StormError::Network(format!("Connection error: {}", e)))?;
-        let rtt = start.elapsed();
+        let handshake = start.elapsed();
 
-        let h3_conn = h3::client::new(h3_quinn::Connection::new(connection))
+        let (mut driver, send) = h3::client::new(h3_quinn::Connection::new(connection.clone()))
             .await
             .map_err(|e| StormError::Protocol(format!("HTTP/3 handshake failed: {}", e)))?;
-
-        Ok((h3_conn.1, rtt))
+        // The driver keeps the connection's control streams going until it
+        // closes.
+        tokio::spawn(async move {
+            let _ = driver.wait_idle().await;
+        });
+
+        Ok(Pooled {
+            connection,
+            send,
+            handshake,
+            streams: Arc::new(AtomicUsize::new(0)),
+        })
     }
 
     fn build_request(&self, url: &Url, range: Option<ByteRange>) -> http::Request<()> {
@@ -144,6 +310,14 @@ impl Http3Downloader {
             .header("host", url.host_str().unwrap_or(""))
             .header("user-agent", "StormDL/0.1");
 
+        if let Some(map) = builder.headers_mut() {
+            for (name, value) in &self.headers {
+                map.insert(name, value.clone());
+            }
+            if let Some(value) = self.auth.as_ref().and_then(|a| a.header_for(url)) {
+                map.insert(http::header::AUTHORIZATION, value);
+            }
+        }
         if let Some(r) = range {
             builder = builder.header("range", format!("bytes={}-{}", r.start, r.end - 1));
         }
@@ -161,11 +335,13 @@ impl Default for Http3Downloader {
 #[async_trait]
 impl Downloader for Http3Downloader {
     async fn probe(&self, url: &Url) -> Result<ResourceInfo, StormError> {
-        let (mut send_request, connection_rtt) = self.connect(url).await?;
+        let mut lease = self.connection(url).await?;
+        let connection_rtt = lease.rtt;
 
         let req = self.build_request(url, Some(ByteRange::new(0, 0)));
 
-        let mut stream = send_request
+        let mut stream = lease
+            .send
             .send_request(req)
             .await
             .map_err(|e| StormError::Network(format!("Failed to send request: {}", e)))?;
@@ -194,7 +370,7 @@ impl Downloader for Http3Downloader {
             let size = headers
                 .get(http::header::CONTENT_RANGE)
                 .and_then(|v| v.to_str().ok())
-                .and_then(|s| s.split('/').last())
+                .and_then(|s| s.split('/').next_back())
                 .and_then(|s| s.parse().ok());
             (size, true)
         } else {
@@ -231,7 +407,7 @@ impl Downloader for Http3Downloader {
             .and_then(parse_content_disposition)
             .or_else(|| {
                 url.path_segments()
-                    .and_then(|segments| segments.last())
+                    .and_then(|mut segments| segments.next_back())
                     .filter(|s| !s.is_empty())
                     .map(String::from)
             });
@@ -255,11 +431,12 @@ impl Downloader for Http3Downloader {
         range: ByteRange,
         sink: &mut dyn DataSink,
     ) -> Result<(), StormError> {
-        let (mut send_request, _) = self.connect(url).await?;
+        let mut lease = self.connection(url).await?;
 
         let req = self.build_request(url, Some(range));
 
-        let mut stream = send_request
+        let mut stream = lease
+            .send
             .send_request(req)
             .await
             .map_err(|e| StormError::Network(format!("Failed to send request: {}", e)))?;
@@ -295,6 +472,7 @@ impl Downloader for Http3Downloader {
             .await
             .map_err(|e| StormError::Network(format!("Failed to receive data: {}", e)))?
         {
+            sink.ready(chunk.remaining()).await?;
             sink.write(chunk.copy_to_bytes(chunk.remaining()))?;
         }
         sink.flush()?;
@@ -303,11 +481,12 @@ impl Downloader for Http3Downloader {
     }
 
     async fn fetch_full(&self, url: &Url, sink: &mut dyn DataSink) -> Result<(), StormError> {
-        let (mut send_request, _) = self.connect(url).await?;
+        let mut lease = self.connection(url).await?;
 
         let req = self.build_request(url, None);
 
-        let mut stream = send_request
+        let mut stream = lease
+            .send
             .send_request(req)
             .await
             .map_err(|e| StormError::Network(format!("Failed to send request: {}", e)))?;
@@ -334,6 +513,7 @@ impl Downloader for Http3Downloader {
             .await
             .map_err(|e| StormError::Network(format!("Failed to receive data: {}", e)))?
         {
+            sink.ready(chunk.remaining()).await?;
             sink.write(chunk.copy_to_bytes(chunk.remaining()))?;
         }
         sink.flush()?;
@@ -363,3 +543,222 @@ fn parse_content_disposition(header: &str) -> Option<String> {
         }
     })
 }
+
+// The HTTP/3 side of an HttpDownloader: origins whose requests go over
+// QUIC once a probe there succeeded, until a request over it fails.
+#[derive(Default)]
+pub(crate) struct Http3Routes {
+    // What an Http3Downloader is built from; None when requests carry
+    // something only the HTTP/2 client sends.
+    options: Option<ClientOptions>,
+    advertised: Mutex<HashSet<Origin>>,
+    routes: Mutex<HashMap<Origin, Arc<Http3Route>>>,
+}
+
+struct Http3Route {
+    downloader: Http3Downloader,
+    healthy: AtomicBool,
+}
+
+impl Http3Routes {
+    pub(crate) fn new(options: &ClientOptions) -> Self {
+        let capable = options.protocol != PreferredProtocol::Http1
+            && options.request.is_none()
+            && options.cookies.is_none()
+            && options.s3.is_none();
+        Self {
+            options: capable.then(|| options.clone()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn record_alt_svc(&self, url: &Url, headers: &reqwest::header::HeaderMap) {
+        if headers
+            .get_all(reqwest::header::ALT_SVC)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| advertises_h3(value, url))
+        {
+            self.advertised.lock().insert(url.origin());
+        }
+    }
+
+    pub(crate) fn capable(&self) -> bool {
+        self.options.is_some()
+    }
+
+    // Advertised and reachable: only a SOCKS5 proxy carries QUIC, so a
+    // download through any other stays where it is without trying.
+    pub(crate) fn advertised(&self, url: &Url) -> bool {
+        let Some(options) = &self.options else {
+            return false;
+        };
+        options
+            .proxy
+            .as_ref()
+            .and_then(|proxy| proxy.route(url))
+            .is_none_or(|route| route.scheme().starts_with("socks5"))
+            && self.advertised.lock().contains(&url.origin())
+    }
+
+    // Probes `url` over HTTP/3 and, if that works, sends the rest of its
+    // origin's requests there.
+    pub(crate) async fn open(&self, url: &Url) -> Result<ResourceInfo, StormError> {
+        let Some(options) = &self.options else {
+            return Err(StormError::Protocol(
+                "HTTP/3 can't send cookies, S3 signatures or custom requests".into(),
+            ));
+        };
+        if url.scheme() != "https" {
+            return Err(StormError::Protocol("HTTP/3 needs an https:// URL".into()));
+        }
+        let downloader = if options.turbo {
+            Http3Downloader::turbo()?
+        } else {
+            Http3Downloader::new()?
+        };
+        let mut downloader = downloader
+            .with_headers(&options.headers)?
+            .with_auth(options.auth.clone())
+            .with_socket_options(options.socket)?;
+        if let Some(proxy) = &options.proxy {
+            downloader = downloader.with_proxy(proxy, url, options.socket).await?;
+        }
+        let info = downloader.probe(url).await?;
+        self.routes.lock().insert(
+            url.origin(),
+            Arc::new(Http3Route {
+                downloader,
+                healthy: AtomicBool::new(true),
+            }),
+        );
+        Ok(info)
+    }
+
+    fn route(&self, url: &Url) -> Option<Arc<Http3Route>> {
+        self.routes
+            .lock()
+            .get(&url.origin())
+            .filter(|route| route.healthy.load(Ordering::Acquire))
+            .cloned()
+    }
+
+    // None hands the request to HTTP/2: the origin isn't on HTTP/3, or its
+    // route failed before any data arrived.
+    pub(crate) async fn fetch_range(
+        &self,
+        url: &Url,
+        range: ByteRange,
+        sink: &mut dyn DataSink,
+    ) -> Option<Result<(), StormError>> {
+        let route = self.route(url)?;
+        let mut tally = Tally::new(sink);
+        let result = route.downloader.fetch_range(url, range, &mut tally).await;
+        route.settle(url, result, tally.written)
+    }
+
+    pub(crate) async fn fetch_full(
+        &self,
+        url: &Url,
+        sink: &mut dyn DataSink,
+    ) -> Option<Result<(), StormError>> {
+        let route = self.route(url)?;
+        let mut tally = Tally::new(sink);
+        let result = route.downloader.fetch_full(url, &mut tally).await;
+        route.settle(url, result, tally.written)
+    }
+}
+
+impl Http3Route {
+    fn settle(
+        &self,
+        url: &Url,
+        result: Result<(), StormError>,
+        written: bool,
+    ) -> Option<Result<(), StormError>> {
+        match result {
+            Ok(()) => Some(Ok(())),
+            // The server's answer, whichever protocol asks.
+            Err(StormError::RateLimited) => Some(Err(StormError::RateLimited)),
+            Err(e) => {
+                if self.healthy.swap(false, Ordering::AcqRel) {
+                    tracing::warn!(
+                        "HTTP/3 failed for {}, falling back to HTTP/2: {}",
+                        url.host_str().unwrap_or_default(),
+                        e
+                    );
+                }
+                // Data already written can't be taken back; the retry
+                // resumes over HTTP/2.
+                written.then_some(Err(e))
+            }
+        }
+    }
+}
+
+struct Tally<'a> {
+    sink: &'a mut dyn DataSink,
+    written: bool,
+}
+
+impl<'a> Tally<'a> {
+    fn new(sink: &'a mut dyn DataSink) -> Self {
+        Self {
+            sink,
+            written: false,
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for Tally<'_> {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        self.written |= !data.is_empty();
+        self.sink.write(data)
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        self.sink.flush()
+    }
+
+    async fn ready(&mut self, len: usize) -> Result<(), StormError> {
+        self.sink.ready(len).await
+    }
+}
+
+// Whether an Alt-Svc value offers h3 on `url`'s own host and port, the
+// only alternative the HTTP/3 client reaches without rewriting the
+// request's authority.
+fn advertises_h3(value: &str, url: &Url) -> bool {
+    value.split(',').any(|service| {
+        let Some((protocol, authority)) = service
+            .split(';')
+            .next()
+            .and_then(|alternative| alternative.trim().split_once('='))
+        else {
+            return false;
+        };
+        let Some((host, port)) = authority.trim_matches('"').rsplit_once(':') else {
+            return false;
+        };
+        protocol == "h3"
+            && (host.is_empty() || Some(host) == url.host_str())
+            && port.parse().ok() == url.port_or_known_default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advertises_h3() {
+        let url = Url::parse("https://example.com/file.bin").unwrap();
+        assert!(advertises_h3(r#"h3=":443"; ma=86400"#, &url));
+        assert!(advertises_h3(r#"h3-29=":443", h3="example.com:443""#, &url));
+        assert!(!advertises_h3(r#"h3=":8443""#, &url));
+        assert!(!advertises_h3(r#"h3="cdn.example.net:443""#, &url));
+        assert!(!advertises_h3(r#"h2=":443""#, &url));
+        assert!(!advertises_h3("clear", &url));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{CookieJar, CustomRequest};
+use bytes::Bytes;
+use serde_json::Value;
+use std::path::Path;
+use stormdl_core::StormError;
+use url::Url;
+
+// Headers the client sets itself or that would change what comes back: a
+// replayed Range or Accept-Encoding breaks segmenting, and cookies go
+// through the jar so they stay scoped to their hosts.
+const DROPPED_HEADERS: &[&str] = &[
+    "host",
+    "cookie",
+    "content-length",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "te",
+    "upgrade",
+    "range",
+    "if-range",
+    "if-match",
+    "if-none-match",
+    "if-modified-since",
+    "if-unmodified-since",
+    "accept-encoding",
+];
+
+// A browser's recording of a session (an HTTP Archive, exported from the
+// network tab of its developer tools), for downloads that only work with
+// what the browser sent.
+#[derive(Debug)]
+pub struct Har {
+    entries: Vec<Value>,
+}
+
+// What the browser sent for a download.
+#[derive(Debug, Clone)]
+pub struct HarRequest {
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    // The method and body when it wasn't a bare GET.
+    pub request: Option<CustomRequest>,
+}
+
+impl Har {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StormError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, StormError> {
+        let json = serde_json::from_str::<Value>(text)
+            .map_err(|e| StormError::Config(format!("not a HAR file: {}", e)))?;
+        let entries = json
+            .pointer("/log/entries")
+            .and_then(Value::as_array)
+            .ok_or_else(|| StormError::Config("not a HAR file: no log.entries".into()))?;
+        Ok(Self {
+            entries: entries.clone(),
+        })
+    }
+
+    // The latest request the browser made to `url`, or failing that to
+    // the same path with another query. Headers alone come from the latest
+    // request to the host if neither was recorded.
+    pub fn request(&self, url: &Url) -> Result<Option<HarRequest>, StormError> {
+        let requests: Vec<(Url, &Value)> = self
+            .entries
+            .iter()
+            .rev()
+            .filter_map(|entry| {
+                let request = entry.get("request")?;
+                let url = Url::parse(request.get("url")?.as_str()?).ok()?;
+                Some((url, request))
+            })
+            .collect();
+        let same_origin = |other: &Url| other.origin() == url.origin();
+        let exact = requests.iter().find(|(other, _)| {
+            same_origin(other) && other.path() == url.path() && other.query() == url.query()
+        });
+        let same_path = || {
+            requests
+                .iter()
+                .find(|(other, _)| same_origin(other) && other.path() == url.path())
+        };
+        if let Some((found, request)) = exact.or_else(same_path) {
+            return Ok(Some(Self::replay(found, request, true)?));
+        }
+        match requests.iter().find(|(other, _)| same_origin(other)) {
+            Some((found, request)) => Ok(Some(Self::replay(found, request, false)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn replay(url: &Url, request: &Value, with_body: bool) -> Result<HarRequest, StormError> {
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .unwrap_or("GET");
+        let custom = if with_body && !method.eq_ignore_ascii_case("GET") {
+            let body = request
+                .pointer("/postData/text")
+                .and_then(Value::as_str)
+                .map(|text| Bytes::from(text.to_string()));
+            Some(CustomRequest::new(method, body)?)
+        } else {
+            None
+        };
+        let headers = pairs(request.get("headers"))
+            .filter(|(name, _)| {
+                let name = name.to_ascii_lowercase();
+                !name.starts_with(':')
+                    && !DROPPED_HEADERS.contains(&name.as_str())
+                    && (custom.is_some() || name != "content-type")
+            })
+            .collect();
+        Ok(HarRequest {
+            url: url.clone(),
+            headers,
+            request: custom,
+        })
+    }
+
+    // Adds the cookies the session was given and sent to `jar`, in the
+    // order the browser saw them. Returns how many it added.
+    pub fn import_cookies(&self, jar: &CookieJar) -> usize {
+        let now = chrono::Utc::now();
+        let mut count = 0;
+        for entry in &self.entries {
+            let Some(url) = entry
+                .pointer("/request/url")
+                .and_then(Value::as_str)
+                .and_then(|url| Url::parse(url).ok())
+            else {
+                continue;
+            };
+            // What the request sent is mostly what earlier responses set;
+            // the rest, set by scripts, is taken as the host's.
+            let held = jar.header(&url).unwrap_or_default();
+            let held: Vec<&str> = held.split("; ").collect();
+            let sent: Vec<String> = pairs(entry.pointer("/request/cookies"))
+                .map(|(name, value)| format!("{}={}", name, value))
+                .filter(|pair| !held.contains(&pair.as_str()))
+                .collect();
+            if !sent.is_empty() {
+                count += jar.insert(&url, &sent.join("; ")).unwrap_or(0);
+            }
+
+            let given: Vec<(String, bool)> = entry
+                .pointer("/response/cookies")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|cookie| set_cookie(cookie, now))
+                .collect();
+            count += given.iter().filter(|(_, live)| *live).count();
+            jar.store(&url, given.iter().map(|(header, _)| header.as_str()));
+        }
+        count
+    }
+}
+
+fn pairs(list: Option<&Value>) -> impl Iterator<Item = (String, String)> + '_ {
+    list.and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|pair| {
+            Some((
+                pair.get("name")?.as_str()?.to_string(),
+                pair.get("value")?.as_str()?.to_string(),
+            ))
+        })
+}
+
+// A recorded response cookie as the Set-Cookie header that gave it, and
+// whether it's still live rather than one deleting an earlier cookie.
+fn set_cookie(cookie: &Value, now: chrono::DateTime<chrono::Utc>) -> Option<(String, bool)> {
+    let field = |name| cookie.get(name).and_then(Value::as_str);
+    let mut header = format!("{}={}", field("name")?, field("value")?);
+    if let Some(path) = field("path") {
+        header.push_str(&format!("; Path={}", path));
+    }
+    if let Some(domain) = field("domain") {
+        header.push_str(&format!("; Domain={}", domain));
+    }
+    if cookie.get("secure").and_then(Value::as_bool) == Some(true) {
+        header.push_str("; Secure");
+    }
+    let mut live = true;
+    if let Some(expires) =
+        field("expires").and_then(|e| chrono::DateTime::parse_from_rfc3339(e).ok())
+    {
+        let age = (expires.with_timezone(&chrono::Utc) - now).num_seconds();
+        header.push_str(&format!("; Max-Age={}", age));
+        live = age > 0;
+    }
+    Some((header, live))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAR: &str = r#"{"log": {"version": "1.2", "entries": [
+        {"request": {"method": "GET", "url": "https://example.com/login",
+            "headers": [], "cookies": []},
+         "response": {"status": 302, "cookies": [
+            {"name": "session", "value": "abc", "path": "/", "domain": ".example.com",
+             "expires": "2100-01-01T00:00:00.000Z", "httpOnly": true, "secure": true},
+            {"name": "old", "value": "1", "expires": "2000-01-01T00:00:00Z"}]}},
+        {"request": {"method": "POST", "url": "https://files.example.com/export?id=7",
+            "headers": [
+                {"name": ":authority", "value": "files.example.com"},
+                {"name": "User-Agent", "value": "Mozilla/5.0"},
+                {"name": "Authorization", "value": "Bearer xyz"},
+                {"name": "Cookie", "value": "session=abc; csrf=42"},
+                {"name": "Content-Type", "value": "application/json"},
+                {"name": "Accept-Encoding", "value": "gzip, br"}],
+            "cookies": [{"name": "session", "value": "abc"}, {"name": "csrf", "value": "42"}],
+            "postData": {"mimeType": "application/json", "text": "{\"format\":\"csv\"}"}},
+         "response": {"status": 200, "cookies": []}}
+    ]}}"#;
+
+    #[test]
+    fn test_request_and_cookies() {
+        let har = Har::parse(HAR).unwrap();
+
+        let url = Url::parse("https://files.example.com/export?id=7").unwrap();
+        let captured = har.request(&url).unwrap().unwrap();
+        let names: Vec<&str> = captured.headers.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["User-Agent", "Authorization", "Content-Type"]);
+        assert_eq!(captured.request.as_ref().map(|r| r.method()), Some("POST"));
+
+        // Another file on the host gets the headers, not the POST.
+        let other = Url::parse("https://files.example.com/other.zip").unwrap();
+        let captured = har.request(&other).unwrap().unwrap();
+        assert!(captured.request.is_none());
+        assert!(captured.headers.iter().all(|(n, _)| n != "Content-Type"));
+        assert!(
+            har.request(&Url::parse("https://example.org/").unwrap())
+                .unwrap()
+                .is_none()
+        );
+
+        let jar = CookieJar::new();
+        assert_eq!(har.import_cookies(&jar), 2);
+        assert_eq!(jar.header(&url).as_deref(), Some("session=abc; csrf=42"));
+        assert_eq!(
+            jar.header(&Url::parse("https://www.example.com/").unwrap())
+                .as_deref(),
+            Some("session=abc")
+        );
+
+        assert!(Har::parse("{\"log\": {}}").is_err());
+    }
+}
@@ -0,0 +1,440 @@
+use aes::Aes128;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, KeyIvInit};
+use std::time::Duration;
+use stormdl_core::{ByteRange, ResourceInfo, StormError};
+use url::Url;
+
+const MIME_TYPES: [&str; 4] = [
+    "application/vnd.apple.mpegurl",
+    "application/x-mpegurl",
+    "audio/mpegurl",
+    "audio/x-mpegurl",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Playlist {
+    Master(Vec<Variant>),
+    Media(MediaPlaylist),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub uri: Url,
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaPlaylist {
+    pub target_duration: Duration,
+    pub media_sequence: u64,
+    pub segments: Vec<MediaSegment>,
+    // EXT-X-ENDLIST or a VOD playlist: nothing will be added.
+    pub ended: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaSegment {
+    pub sequence: u64,
+    pub uri: Url,
+    pub duration: Duration,
+    pub range: Option<ByteRange>,
+    pub key: Option<SegmentKey>,
+    // The EXT-X-MAP initialization section of fragmented MP4 segments.
+    pub map: Option<InitSection>,
+}
+
+// AES-128 in CBC mode over the whole segment; without an IV the segment's
+// media sequence number is the IV.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SegmentKey {
+    pub uri: Url,
+    pub iv: Option<[u8; 16]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitSection {
+    pub uri: Url,
+    pub range: Option<ByteRange>,
+}
+
+// By type, or by a .m3u8 name when the server sends a generic one.
+pub fn is_playlist(info: &ResourceInfo) -> bool {
+    typed_or_named(info, &MIME_TYPES, ".m3u8")
+}
+
+pub(crate) fn typed_or_named(info: &ResourceInfo, types: &[&str], extension: &str) -> bool {
+    let mime = info
+        .content_type
+        .as_deref()
+        .and_then(|t| t.split(';').next())
+        .map(|t| t.trim().to_ascii_lowercase());
+    if mime.as_deref().is_some_and(|t| types.contains(&t)) {
+        return true;
+    }
+    let generic = mime.as_deref().is_none_or(|t| {
+        matches!(
+            t,
+            "application/octet-stream" | "text/plain" | "binary/octet-stream"
+        )
+    });
+    generic
+        && [Some(info.url.path()), info.filename.as_deref()]
+            .into_iter()
+            .flatten()
+            .any(|name| name.to_ascii_lowercase().ends_with(extension))
+}
+
+impl Playlist {
+    pub fn parse(text: &str, base: &Url) -> Result<Self, StormError> {
+        let mut lines = text
+            .trim_start_matches('\u{feff}')
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        if lines.next() != Some("#EXTM3U") {
+            return Err(invalid("missing #EXTM3U header"));
+        }
+        let lines: Vec<&str> = lines.collect();
+        if lines
+            .iter()
+            .any(|line| line.starts_with("#EXT-X-STREAM-INF:"))
+        {
+            parse_master(&lines, base).map(Self::Master)
+        } else {
+            parse_media(&lines, base).map(Self::Media)
+        }
+    }
+}
+
+impl MediaPlaylist {
+    pub fn duration(&self) -> Duration {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+
+    pub fn is_fragmented_mp4(&self) -> bool {
+        self.segments.iter().any(|s| s.map.is_some())
+    }
+}
+
+impl MediaSegment {
+    pub fn iv(&self) -> [u8; 16] {
+        match self.key.as_ref().and_then(|key| key.iv) {
+            Some(iv) => iv,
+            None => (self.sequence as u128).to_be_bytes(),
+        }
+    }
+}
+
+pub fn decrypt_segment(
+    data: &mut Vec<u8>,
+    key: &[u8; 16],
+    iv: &[u8; 16],
+) -> Result<(), StormError> {
+    let decryptor = cbc::Decryptor::<Aes128>::new(key.into(), iv.into());
+    let len = decryptor
+        .decrypt_padded_mut::<Pkcs7>(data)
+        .map_err(|_| StormError::Protocol("HLS segment failed to decrypt".into()))?
+        .len();
+    data.truncate(len);
+    Ok(())
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Protocol(format!("invalid HLS playlist: {}", message))
+}
+
+fn join(base: &Url, uri: &str) -> Result<Url, StormError> {
+    base.join(uri)
+        .map_err(|e| invalid(&format!("bad URI '{}': {}", uri, e)))
+}
+
+fn parse_master(lines: &[&str], base: &Url) -> Result<Vec<Variant>, StormError> {
+    let mut variants = Vec::new();
+    let mut lines = lines.iter();
+    while let Some(line) = lines.next() {
+        let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+        let attrs = attributes(attrs);
+        let uri = lines
+            .by_ref()
+            .find(|line| !line.starts_with('#'))
+            .ok_or_else(|| invalid("#EXT-X-STREAM-INF without a URI"))?;
+        let attr = |name: &str| {
+            attrs
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        variants.push(Variant {
+            uri: join(base, uri)?,
+            bandwidth: attr("BANDWIDTH").and_then(|b| b.parse().ok()).unwrap_or(0),
+            resolution: attr("RESOLUTION").and_then(|r| {
+                let (w, h) = r.split_once(['x', 'X'])?;
+                Some((w.parse().ok()?, h.parse().ok()?))
+            }),
+            codecs: attr("CODECS").map(String::from),
+        });
+    }
+    if variants.is_empty() {
+        return Err(invalid("no variant streams"));
+    }
+    Ok(variants)
+}
+
+fn parse_media(lines: &[&str], base: &Url) -> Result<MediaPlaylist, StormError> {
+    let mut playlist = MediaPlaylist {
+        target_duration: Duration::ZERO,
+        media_sequence: 0,
+        segments: Vec::new(),
+        ended: false,
+    };
+    let mut duration = None;
+    let mut range = None;
+    let mut key = None;
+    let mut map = None;
+    // Where a byte range without an offset starts, per resource.
+    let mut next_offset: Option<(Url, u64)> = None;
+
+    for line in lines {
+        if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+            let secs: u64 = value
+                .parse()
+                .map_err(|_| invalid("bad #EXT-X-TARGETDURATION"))?;
+            playlist.target_duration = Duration::from_secs(secs);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            playlist.media_sequence = value
+                .parse()
+                .map_err(|_| invalid("bad #EXT-X-MEDIA-SEQUENCE"))?;
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let secs = value.split(',').next().unwrap_or_default().trim();
+            let secs: f64 = secs.parse().map_err(|_| invalid("bad #EXTINF"))?;
+            duration = Some(Duration::try_from_secs_f64(secs).map_err(|_| invalid("bad #EXTINF"))?);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            range = Some(parse_range(value).ok_or_else(|| invalid("bad #EXT-X-BYTERANGE"))?);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-KEY:") {
+            key = parse_key(value, base)?;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = attributes(value);
+            let attr = |name: &str| attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+            let uri = attr("URI").ok_or_else(|| invalid("#EXT-X-MAP without a URI"))?;
+            let range = match attr("BYTERANGE") {
+                Some(value) => match parse_range(value) {
+                    Some((len, Some(offset))) => Some(ByteRange::new(offset, offset + len)),
+                    _ => return Err(invalid("bad #EXT-X-MAP byte range")),
+                },
+                None => None,
+            };
+            map = Some(InitSection {
+                uri: join(base, uri)?,
+                range,
+            });
+        } else if *line == "#EXT-X-ENDLIST" || *line == "#EXT-X-PLAYLIST-TYPE:VOD" {
+            playlist.ended = true;
+        } else if !line.starts_with('#') {
+            let uri = join(base, line)?;
+            let range = match range.take() {
+                Some((len, offset)) => {
+                    let start = match (offset, &next_offset) {
+                        (Some(offset), _) => offset,
+                        (None, Some((previous, end))) if *previous == uri => *end,
+                        (None, _) => {
+                            return Err(invalid("#EXT-X-BYTERANGE without an offset to follow"));
+                        }
+                    };
+                    next_offset = Some((uri.clone(), start + len));
+                    Some(ByteRange::new(start, start + len))
+                }
+                None => None,
+            };
+            playlist.segments.push(MediaSegment {
+                sequence: playlist.media_sequence + playlist.segments.len() as u64,
+                uri,
+                duration: duration
+                    .take()
+                    .ok_or_else(|| invalid("a segment has no #EXTINF"))?,
+                range,
+                key: key.clone(),
+                map: map.clone(),
+            });
+        }
+    }
+    Ok(playlist)
+}
+
+// `<length>[@<offset>]`
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    match value.split_once('@') {
+        Some((len, offset)) => Some((len.parse().ok()?, Some(offset.parse().ok()?))),
+        None => Some((value.parse().ok()?, None)),
+    }
+}
+
+fn parse_key(value: &str, base: &Url) -> Result<Option<SegmentKey>, StormError> {
+    let attrs = attributes(value);
+    let attr = |name: &str| attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+    match attr("METHOD").map(String::as_str) {
+        Some("NONE") => Ok(None),
+        Some("AES-128") => {
+            let uri = attr("URI").ok_or_else(|| invalid("#EXT-X-KEY without a URI"))?;
+            let iv = match attr("IV") {
+                Some(iv) => {
+                    let hex = iv
+                        .strip_prefix("0x")
+                        .or_else(|| iv.strip_prefix("0X"))
+                        .unwrap_or(iv);
+                    let iv = u128::from_str_radix(hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() <= 32)
+                        .ok_or_else(|| invalid("bad #EXT-X-KEY IV"))?;
+                    Some(iv.to_be_bytes())
+                }
+                None => None,
+            };
+            Ok(Some(SegmentKey {
+                uri: join(base, uri)?,
+                iv,
+            }))
+        }
+        Some(method) => Err(StormError::Protocol(format!(
+            "HLS encryption method {} is not supported",
+            method
+        ))),
+        None => Err(invalid("#EXT-X-KEY without a METHOD")),
+    }
+}
+
+// `NAME=value,NAME="quoted, value"`
+fn attributes(list: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = list.trim();
+    while let Some((name, after)) = rest.split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, after)) => (value, after),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').map_or((after, ""), |(v, a)| (v, a)),
+        };
+        attrs.push((name.trim().to_string(), value.to_string()));
+        rest = after.trim_start_matches(',').trim_start();
+    }
+    attrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cbc::cipher::BlockEncryptMut;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_master() {
+        let text = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"\n\
+            low/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080\n\
+            https://cdn.example.com/hi/index.m3u8\n";
+        let Playlist::Master(variants) =
+            Playlist::parse(text, &url("https://example.com/v/master.m3u8")).unwrap()
+        else {
+            panic!("not a master playlist");
+        };
+        assert_eq!(variants.len(), 2);
+        assert_eq!(
+            variants[0].uri.as_str(),
+            "https://example.com/v/low/index.m3u8"
+        );
+        assert_eq!(variants[0].resolution, Some((640, 360)));
+        assert_eq!(variants[0].codecs.as_deref(), Some("avc1.4d401e,mp4a.40.2"));
+        assert_eq!(variants[1].bandwidth, 5000000);
+    }
+
+    #[test]
+    fn test_parse_media() {
+        let text = "\u{feff}#EXTM3U\n\
+            #EXT-X-TARGETDURATION:6\n\
+            #EXT-X-MEDIA-SEQUENCE:40\n\
+            #EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n\
+            #EXTINF:6.0,\n\
+            #EXT-X-BYTERANGE:1000@720\n\
+            media.m4s\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"https://keys.example.com/k?id=1,2\",IV=0x0000000000000000000000000000002A\n\
+            #EXTINF:5.5,title\n\
+            #EXT-X-BYTERANGE:500\n\
+            media.m4s\n\
+            #EXT-X-KEY:METHOD=NONE\n\
+            #EXTINF:2,\n\
+            last.m4s\n\
+            #EXT-X-ENDLIST\n";
+        let Playlist::Media(playlist) =
+            Playlist::parse(text, &url("https://example.com/v/index.m3u8")).unwrap()
+        else {
+            panic!("not a media playlist");
+        };
+        assert!(playlist.ended);
+        assert!(playlist.is_fragmented_mp4());
+        assert_eq!(playlist.target_duration, Duration::from_secs(6));
+        assert_eq!(playlist.duration(), Duration::from_millis(13500));
+
+        let [first, second, third] = &playlist.segments[..] else {
+            panic!("expected 3 segments");
+        };
+        assert_eq!(first.sequence, 40);
+        assert_eq!(first.range, Some(ByteRange::new(720, 1720)));
+        assert_eq!(
+            first.map,
+            Some(InitSection {
+                uri: url("https://example.com/v/init.mp4"),
+                range: Some(ByteRange::new(0, 720)),
+            })
+        );
+        assert_eq!(first.iv(), 40u128.to_be_bytes());
+        assert_eq!(second.range, Some(ByteRange::new(1720, 2220)));
+        let key = second.key.as_ref().unwrap();
+        assert_eq!(key.uri.as_str(), "https://keys.example.com/k?id=1,2");
+        assert_eq!(second.iv(), 42u128.to_be_bytes());
+        assert_eq!(third.sequence, 42);
+        assert_eq!(third.key, None);
+
+        let live = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\na.ts\n";
+        let Playlist::Media(live) = Playlist::parse(live, &url("http://h/l.m3u8")).unwrap() else {
+            panic!("not a media playlist");
+        };
+        assert!(!live.ended);
+        assert!(!live.is_fragmented_mp4());
+
+        assert!(Playlist::parse("<html>", &url("http://h/")).is_err());
+        assert!(Playlist::parse("#EXTM3U\na.ts\n", &url("http://h/")).is_err());
+        assert!(
+            Playlist::parse(
+                "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"k\"\n#EXTINF:1,\na.ts\n",
+                &url("http://h/")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_segment() {
+        let key = [7u8; 16];
+        let iv = 5u128.to_be_bytes();
+        let plain = b"a transport stream segment".to_vec();
+        let mut data = vec![0u8; 32];
+        let len = cbc::Encryptor::<Aes128>::new(&key.into(), &iv.into())
+            .encrypt_padded_b2b_mut::<Pkcs7>(&plain, &mut data)
+            .unwrap()
+            .len();
+        data.truncate(len);
+
+        decrypt_segment(&mut data, &key, &iv).unwrap();
+        assert_eq!(data, plain);
+        assert!(decrypt_segment(&mut vec![1u8; 16], &key, &iv).is_err());
+    }
+}
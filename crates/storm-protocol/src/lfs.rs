@@ -0,0 +1,248 @@
+use crate::{HostAuth, HttpDownloader};
+use bytes::Bytes;
+use reqwest::StatusCode;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use stormdl_core::StormError;
+use url::Url;
+
+const LFS_MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+// What a repository holds in place of a file tracked by Git LFS: the
+// object's sha256 and size. Hosts serve it as the file's raw content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfsPointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+// Where the object a pointer stands for is downloaded from.
+#[derive(Debug)]
+pub struct LfsObject {
+    pub url: Url,
+    // Some servers, GitLab's among them, hand out a credential that only
+    // the link's own host gets.
+    pub auth: Option<HostAuth>,
+}
+
+impl LfsPointer {
+    // The spec caps pointer files at this size.
+    pub const MAX_SIZE: u64 = 1024;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data).ok()?;
+        let mut lines = text.lines();
+        let version = lines.next()?.strip_prefix("version ")?;
+        if !version.starts_with("https://git-lfs.github.com/spec/")
+            && !version.starts_with("https://hawser.github.com/spec/")
+        {
+            return None;
+        }
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            match line.split_once(' ') {
+                Some(("oid", value)) => oid = value.strip_prefix("sha256:"),
+                Some(("size", value)) => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        let oid =
+            oid.filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))?;
+        Some(Self {
+            oid: oid.to_ascii_lowercase(),
+            size: size?,
+        })
+    }
+
+    // The LFS batch endpoint of the repository a raw-file link points
+    // into, for the hosts whose links say which repository that is:
+    // raw.githubusercontent.com/owner/repo/..., host/owner/repo/raw/...
+    // (GitHub, Gitea, Forgejo) and host/group/repo/-/raw/... (GitLab).
+    pub fn endpoint(url: &Url) -> Option<Url> {
+        let segments: Vec<&str> = url.path_segments()?.collect();
+        let repository = if url.host_str()? == "raw.githubusercontent.com" {
+            (segments.len() > 3)
+                .then(|| format!("https://github.com/{}/{}", segments[0], segments[1]))
+        } else if let Some(dash) = segments.iter().position(|s| *s == "-")
+            && dash >= 2
+            && segments.get(dash + 1) == Some(&"raw")
+        {
+            Some(format!(
+                "{}/{}",
+                url.origin().ascii_serialization(),
+                segments[..dash].join("/")
+            ))
+        } else if segments.len() > 4 && segments[2] == "raw" {
+            Some(format!(
+                "{}/{}/{}",
+                url.origin().ascii_serialization(),
+                segments[0],
+                segments[1]
+            ))
+        } else {
+            None
+        }?;
+        let repository = repository.trim_end_matches(".git");
+        Url::parse(&format!("{}.git/info/lfs/objects/batch", repository)).ok()
+    }
+
+    // The pointer `url` serves, if that's what it serves.
+    pub async fn fetch(downloader: &HttpDownloader, url: &Url) -> Result<Option<Self>, StormError> {
+        let mut response = downloader.get_response(url, HeaderMap::new()).await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| StormError::Network(e.to_string()))?
+        {
+            data.extend_from_slice(&chunk);
+            if data.len() as u64 > Self::MAX_SIZE {
+                return Ok(None);
+            }
+        }
+        Ok(Self::parse(&data))
+    }
+
+    // Asks the LFS server at `endpoint` where to download the object.
+    pub async fn resolve(
+        &self,
+        downloader: &HttpDownloader,
+        endpoint: &Url,
+    ) -> Result<LfsObject, StormError> {
+        let request = serde_json::json!({
+            "operation": "download",
+            "transfers": ["basic"],
+            "objects": [{ "oid": self.oid, "size": self.size }],
+            "hash_algo": "sha256",
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(LFS_MEDIA_TYPE));
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(LFS_MEDIA_TYPE),
+        );
+        let response = downloader
+            .post_response(endpoint, headers, Bytes::from(request.to_string()))
+            .await?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| StormError::Network(e.to_string()))?;
+        let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default();
+        let message = |json: &serde_json::Value| {
+            json.get("message")
+                .and_then(|m| m.as_str())
+                .map(str::to_string)
+        };
+        if !status.is_success() {
+            let reason = match status {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => {
+                    "pass credentials for private repositories with --user".to_string()
+                }
+                _ => message(&json)
+                    .unwrap_or_else(|| status.canonical_reason().unwrap_or("failed").into()),
+            };
+            return Err(StormError::Http {
+                status: status.as_u16(),
+                message: format!(
+                    "LFS server {} refused the object: {}",
+                    endpoint.host_str().unwrap_or_default(),
+                    reason
+                ),
+            });
+        }
+
+        let object = json
+            .get("objects")
+            .and_then(|objects| objects.as_array())
+            .and_then(|objects| {
+                objects
+                    .iter()
+                    .find(|o| o.get("oid").and_then(|oid| oid.as_str()) == Some(&self.oid))
+            })
+            .ok_or_else(|| StormError::Protocol("LFS batch response lists no object".into()))?;
+        if let Some(error) = object.get("error") {
+            return Err(StormError::Http {
+                status: error
+                    .get("code")
+                    .and_then(|c| c.as_u64())
+                    .and_then(|c| u16::try_from(c).ok())
+                    .unwrap_or(StatusCode::NOT_FOUND.as_u16()),
+                message: format!(
+                    "LFS object {}: {}",
+                    self.oid,
+                    message(error).unwrap_or_else(|| "unavailable".into())
+                ),
+            });
+        }
+        let download = object
+            .pointer("/actions/download")
+            .ok_or_else(|| StormError::Protocol("LFS object has no download link".into()))?;
+        let url = download
+            .get("href")
+            .and_then(|href| href.as_str())
+            .and_then(|href| Url::parse(href).ok())
+            .ok_or_else(|| StormError::Protocol("LFS download link is not a URL".into()))?;
+        // Only Authorization is kept; it's the header servers send in
+        // practice.
+        let auth = download
+            .get("header")
+            .and_then(|h| h.as_object())
+            .and_then(|h| {
+                h.iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+            })
+            .and_then(|(_, value)| HeaderValue::from_str(value.as_str()?).ok())
+            .map(|value| HostAuth::header(&url, value))
+            .transpose()?;
+        Ok(LfsObject { url, auth })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let oid = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+        let pointer = LfsPointer::parse(
+            format!(
+                "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+                oid
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(pointer.oid, oid);
+        assert_eq!(pointer.size, 12345);
+
+        assert!(
+            LfsPointer::parse(b"version https://git-lfs.github.com/spec/v1\nsize 1\n").is_none()
+        );
+        assert!(LfsPointer::parse(format!("oid sha256:{}\nsize 1\n", oid).as_bytes()).is_none());
+        assert!(LfsPointer::parse(b"hello world\n").is_none());
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let endpoint =
+            |url: &str| LfsPointer::endpoint(&Url::parse(url).unwrap()).map(String::from);
+        assert_eq!(
+            endpoint("https://raw.githubusercontent.com/org/repo/main/models/m.bin").as_deref(),
+            Some("https://github.com/org/repo.git/info/lfs/objects/batch")
+        );
+        assert_eq!(
+            endpoint("https://github.com/org/repo/raw/main/m.bin").as_deref(),
+            Some("https://github.com/org/repo.git/info/lfs/objects/batch")
+        );
+        assert_eq!(
+            endpoint("https://gitlab.com/group/sub/repo/-/raw/main/m.bin").as_deref(),
+            Some("https://gitlab.com/group/sub/repo.git/info/lfs/objects/batch")
+        );
+        assert_eq!(endpoint("https://example.com/files/m.bin"), None);
+    }
+}
@@ -1,13 +1,60 @@
+mod auth;
+mod cookies;
+mod dash;
+mod digest;
+mod dualstack;
+mod ftp;
+mod har;
+mod hls;
 mod http;
+mod lfs;
+mod mp4;
 mod negotiation;
+mod oauth;
+mod oci;
 mod pool;
+mod proxy;
+mod route;
+mod s3;
+mod sftp;
+mod socket;
+mod ssh;
+mod webdav;
 
 #[cfg(feature = "http3")]
 mod h3;
+#[cfg(feature = "http3")]
+mod socks;
+#[cfg(feature = "test-util")]
+mod testing;
 
-pub use http::HttpDownloader;
-pub use negotiation::{PreferredProtocol, ProtocolNegotiator};
+pub use auth::HostAuth;
+pub use cookies::CookieJar;
+pub use dash::{AdaptationSet, ContentKind, Mpd, Representation, SegmentRef, is_manifest};
+pub use dualstack::DualStack;
+pub use ftp::FtpDownloader;
+pub use har::{Har, HarRequest};
+pub use hls::{
+    InitSection, MediaPlaylist, MediaSegment, Playlist, SegmentKey, Variant, decrypt_segment,
+    is_playlist,
+};
+pub use http::{ClientOptions, CustomRequest, HttpDownloader, parse_header};
+pub use lfs::{LfsObject, LfsPointer};
+pub use mp4::Fmp4Muxer;
+pub use negotiation::{
+    Downgrade, Negotiated, PreferredProtocol, ProtocolNegotiator, probe_with_fallback,
+    upgrade_http3,
+};
+pub use oauth::OAuth2Grant;
+pub use oci::OciBlob;
 pub use pool::ConnectionPool;
+pub use proxy::ProxyConfig;
+pub use route::{LocalBind, Route};
+pub use s3::{S3Config, S3Credentials, S3Downloader, S3Signer};
+pub use sftp::SftpDownloader;
+pub use socket::SocketOptions;
 
 #[cfg(feature = "http3")]
 pub use h3::Http3Downloader;
+#[cfg(feature = "test-util")]
+pub use testing::test_server;
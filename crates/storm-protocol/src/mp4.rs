@@ -0,0 +1,340 @@
+use stormdl_core::StormError;
+
+// Puts the tracks of several fragmented MP4 streams into one file, the way
+// DASH serves video and audio apart: their moov boxes are merged, track n
+// becoming track ID n + 1, and every fragment's tfhd is pointed at the new
+// ID. Fragments stay self-contained (their data offsets are relative to
+// their moof), so they can follow the merged header in any order.
+pub struct Fmp4Muxer {
+    headers: Vec<Option<Header>>,
+    started: bool,
+    // Fragments that arrived before every track's header.
+    pending: Vec<(usize, Vec<u8>)>,
+}
+
+struct Header {
+    ftyp: Vec<u8>,
+    moov: Vec<u8>,
+}
+
+struct Mp4Box<'a> {
+    kind: [u8; 4],
+    data: &'a [u8],
+    header: usize,
+}
+
+impl Mp4Box<'_> {
+    fn body(&self) -> &[u8] {
+        &self.data[self.header..]
+    }
+}
+
+impl Fmp4Muxer {
+    pub fn new(tracks: usize) -> Self {
+        Self {
+            headers: (0..tracks).map(|_| None).collect(),
+            started: false,
+            pending: Vec::new(),
+        }
+    }
+
+    // Takes the next piece of a track (an initialization section, media
+    // segments, or a whole file) and returns what to write next.
+    pub fn push(&mut self, track: usize, data: &[u8]) -> Result<Vec<u8>, StormError> {
+        if track >= self.headers.len() {
+            return Err(invalid("no such track"));
+        }
+        let mut fragments = Vec::new();
+        let mut ftyp = None;
+        for b in boxes(data)? {
+            match &b.kind {
+                b"ftyp" => ftyp = Some(b.data.to_vec()),
+                b"moov" => {
+                    if self.headers[track].is_none() {
+                        self.headers[track] = Some(Header {
+                            ftyp: ftyp.take().unwrap_or_default(),
+                            moov: b.data.to_vec(),
+                        });
+                    }
+                }
+                // Indexes and segment types describe the stream they came
+                // from, not the merged file.
+                b"sidx" | b"styp" | b"ssix" | b"free" | b"skip" => {}
+                _ => fragments.extend_from_slice(b.data),
+            }
+        }
+        let mut out = Vec::new();
+        if !self.started && self.headers.iter().all(Option::is_some) {
+            out = self.header()?;
+            self.started = true;
+            for (track, fragment) in std::mem::take(&mut self.pending) {
+                out.extend(retrack(&fragment, track as u32 + 1)?);
+            }
+        }
+        if !fragments.is_empty() {
+            if self.started {
+                out.extend(retrack(&fragments, track as u32 + 1)?);
+            } else {
+                self.pending.push((track, fragments));
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn finish(&self) -> Result<(), StormError> {
+        if self.started {
+            Ok(())
+        } else {
+            Err(invalid("a track has no moov box"))
+        }
+    }
+
+    fn header(&self) -> Result<Vec<u8>, StormError> {
+        let headers: Vec<&Header> = self.headers.iter().flatten().collect();
+        let count = headers.len() as u32;
+        let mut moov = Vec::new();
+        let mut mvex = Vec::new();
+        for (i, header) in headers.iter().enumerate() {
+            let id = i as u32 + 1;
+            let moov_box = boxes(&header.moov)?.remove(0);
+            let children = boxes(moov_box.body())?;
+            let traks: Vec<&Mp4Box> = children.iter().filter(|b| &b.kind == b"trak").collect();
+            let [trak] = traks[..] else {
+                return Err(invalid("expected one track per stream"));
+            };
+            let trex = children
+                .iter()
+                .filter(|b| &b.kind == b"mvex")
+                .map(|mvex| boxes(mvex.body()))
+                .next()
+                .transpose()?
+                .ok_or_else(|| invalid("not a fragmented MP4 stream"))?;
+            if i == 0 {
+                for child in &children {
+                    match &child.kind {
+                        b"mvhd" => {
+                            // next_track_ID is the box's last field.
+                            let mut mvhd = child.data.to_vec();
+                            let at = mvhd
+                                .len()
+                                .checked_sub(4)
+                                .ok_or_else(|| invalid("short mvhd"))?;
+                            mvhd[at..].copy_from_slice(&(count + 1).to_be_bytes());
+                            moov.extend(mvhd);
+                        }
+                        b"trak" | b"mvex" => {}
+                        _ => moov.extend_from_slice(child.data),
+                    }
+                }
+                for b in trex.iter().filter(|b| &b.kind == b"mehd") {
+                    mvex.extend_from_slice(b.data);
+                }
+            }
+            moov.extend(renumber_trak(trak, id)?);
+            for b in trex.iter().filter(|b| &b.kind == b"trex") {
+                mvex.extend(patched(b, 4, id)?);
+            }
+        }
+        let mut out = headers[0].ftyp.clone();
+        write_box(&mut moov, b"mvex", &mvex);
+        write_box(&mut out, b"moov", &moov);
+        Ok(out)
+    }
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Protocol(format!("can't mux MP4 tracks: {}", message))
+}
+
+fn boxes(data: &[u8]) -> Result<Vec<Mp4Box<'_>>, StormError> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let rest = &data[pos..];
+        if rest.len() < 8 {
+            return Err(invalid("truncated box"));
+        }
+        let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+        let (size, header) = match u32::from_be_bytes(rest[..4].try_into().unwrap()) {
+            0 => (rest.len() as u64, 8),
+            1 if rest.len() >= 16 => (u64::from_be_bytes(rest[8..16].try_into().unwrap()), 16),
+            1 => return Err(invalid("truncated box")),
+            size => (size as u64, 8),
+        };
+        if size < header as u64 || size > rest.len() as u64 {
+            return Err(invalid("bad box size"));
+        }
+        out.push(Mp4Box {
+            kind,
+            data: &rest[..size as usize],
+            header,
+        });
+        pos += size as usize;
+    }
+    Ok(out)
+}
+
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+}
+
+// A copy of a full box with the u32 at `offset` into its body replaced.
+fn patched(b: &Mp4Box, offset: usize, value: u32) -> Result<Vec<u8>, StormError> {
+    let mut data = b.data.to_vec();
+    let at = b.header + offset;
+    data.get_mut(at..at + 4)
+        .ok_or_else(|| invalid("short box"))?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(data)
+}
+
+fn renumber_trak(trak: &Mp4Box, id: u32) -> Result<Vec<u8>, StormError> {
+    let mut body = Vec::new();
+    for child in boxes(trak.body())? {
+        if &child.kind == b"tkhd" {
+            // After version and flags, and 64- or 32-bit creation and
+            // modification times.
+            let offset = if child.body().first() == Some(&1) {
+                20
+            } else {
+                12
+            };
+            body.extend(patched(&child, offset, id)?);
+        } else {
+            body.extend_from_slice(child.data);
+        }
+    }
+    let mut out = Vec::new();
+    write_box(&mut out, b"trak", &body);
+    Ok(out)
+}
+
+// Sizes don't change, so the IDs are patched in place.
+fn retrack(fragments: &[u8], id: u32) -> Result<Vec<u8>, StormError> {
+    let mut out = fragments.to_vec();
+    let mut patches = Vec::new();
+    let mut offset = 0;
+    for moof in boxes(fragments)? {
+        if &moof.kind == b"moof" {
+            let mut traf_offset = offset + moof.header;
+            for traf in boxes(moof.body())? {
+                if &traf.kind == b"traf" {
+                    let mut tfhd_offset = traf_offset + traf.header;
+                    for tfhd in boxes(traf.body())? {
+                        if &tfhd.kind == b"tfhd" {
+                            if tfhd.body().len() < 8 {
+                                return Err(invalid("short tfhd"));
+                            }
+                            patches.push(tfhd_offset + tfhd.header + 4);
+                        }
+                        tfhd_offset += tfhd.data.len();
+                    }
+                }
+                traf_offset += traf.data.len();
+            }
+        }
+        offset += moof.data.len();
+    }
+    for at in patches {
+        out[at..at + 4].copy_from_slice(&id.to_be_bytes());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_box(kind: &[u8; 4], fields: &[u32]) -> Vec<u8> {
+        let mut body = vec![0u8; 4];
+        for field in fields {
+            body.extend_from_slice(&field.to_be_bytes());
+        }
+        let mut out = Vec::new();
+        write_box(&mut out, kind, &body);
+        out
+    }
+
+    fn container(kind: &[u8; 4], children: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_box(&mut out, kind, &children.concat());
+        out
+    }
+
+    // ftyp + moov(mvhd, trak(tkhd), mvex(trex)) for a stream whose only
+    // track has `id`.
+    fn init(brand: &[u8; 4], id: u32) -> Vec<u8> {
+        let mut ftyp = Vec::new();
+        write_box(&mut ftyp, b"ftyp", brand);
+        let moov = container(
+            b"moov",
+            &[
+                full_box(b"mvhd", &[0, 0, 1000, 0, id + 1]),
+                container(b"trak", &[full_box(b"tkhd", &[0, 0, id, 0, 0])]),
+                container(b"mvex", &[full_box(b"trex", &[id, 1, 0, 0, 0])]),
+            ],
+        );
+        [ftyp, moov].concat()
+    }
+
+    fn fragment(id: u32, payload: &[u8]) -> Vec<u8> {
+        let moof = container(b"moof", &[container(b"traf", &[full_box(b"tfhd", &[id])])]);
+        let mut mdat = Vec::new();
+        write_box(&mut mdat, b"mdat", payload);
+        [moof, mdat].concat()
+    }
+
+    fn track_ids(data: &[u8], path: &[&[u8; 4]], offset: usize) -> Vec<u32> {
+        let mut found = Vec::new();
+        for b in boxes(data).unwrap() {
+            if &b.kind == path[0] {
+                if path.len() == 1 {
+                    let at = b.header + offset;
+                    found.push(u32::from_be_bytes(b.data[at..at + 4].try_into().unwrap()));
+                } else {
+                    found.extend(track_ids(b.body(), &path[1..], offset));
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn test_mux_two_tracks() {
+        let mut muxer = Fmp4Muxer::new(2);
+        // Audio's fragment comes before video's header and waits for it.
+        assert!(muxer.push(1, &init(b"dash", 1)).unwrap().is_empty());
+        let mut sidx = Vec::new();
+        write_box(&mut sidx, b"sidx", &[0; 12]);
+        assert!(
+            muxer
+                .push(1, &[sidx, fragment(1, b"aa")].concat())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(muxer.finish().is_err());
+
+        let mut file = muxer.push(0, &init(b"iso6", 1)).unwrap();
+        file.extend(muxer.push(0, &fragment(1, b"vv")).unwrap());
+        file.extend(muxer.push(0, &init(b"iso6", 1)).unwrap());
+        muxer.finish().unwrap();
+
+        let kinds: Vec<[u8; 4]> = boxes(&file).unwrap().iter().map(|b| b.kind).collect();
+        assert_eq!(
+            kinds,
+            [*b"ftyp", *b"moov", *b"moof", *b"mdat", *b"moof", *b"mdat"]
+        );
+        assert_eq!(&boxes(&file).unwrap()[0].body(), b"iso6");
+        assert_eq!(track_ids(&file, &[b"moov", b"trak", b"tkhd"], 12), [1, 2]);
+        assert_eq!(track_ids(&file, &[b"moov", b"mvex", b"trex"], 4), [1, 2]);
+        // mvhd's last field.
+        assert_eq!(track_ids(&file, &[b"moov", b"mvhd"], 20), [3]);
+        assert_eq!(track_ids(&file, &[b"moof", b"traf", b"tfhd"], 4), [2, 1]);
+        assert!(file.ends_with(b"vv"));
+
+        let mut plain = Fmp4Muxer::new(1);
+        assert!(plain.push(0, &fragment(1, b"x")[..10]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
+use crate::{ClientOptions, HttpDownloader};
 use reqwest::Client;
 use std::time::Duration;
-use stormdl_core::StormError;
+use stormdl_core::{Downloader, HttpVersion, ResourceInfo, StormError};
 use url::Url;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -12,6 +13,189 @@ pub enum PreferredProtocol {
     Auto,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downgrade {
+    pub from: HttpVersion,
+    pub to: HttpVersion,
+    pub reason: String,
+}
+
+impl Downgrade {
+    pub fn hint(from: HttpVersion) -> &'static str {
+        match from {
+            HttpVersion::Http3 => "h3 blocked by network?",
+            HttpVersion::Http2 => "server or proxy without HTTP/2?",
+            HttpVersion::Http1_1 => "",
+        }
+    }
+}
+
+pub struct Negotiated {
+    pub downloader: HttpDownloader,
+    pub info: ResourceInfo,
+    pub working: Vec<HttpVersion>,
+    pub downgrades: Vec<Downgrade>,
+}
+
+impl Negotiated {
+    fn new(
+        downloader: HttpDownloader,
+        info: ResourceInfo,
+        mut working: Vec<HttpVersion>,
+        failed: Vec<(HttpVersion, String)>,
+    ) -> Self {
+        if !working.contains(&info.http_version) {
+            working.push(info.http_version);
+        }
+        let downgrades = failed
+            .into_iter()
+            .map(|(from, reason)| Downgrade {
+                from,
+                to: info.http_version,
+                reason,
+            })
+            .collect();
+        Self {
+            downloader,
+            info,
+            working,
+            downgrades,
+        }
+    }
+}
+
+pub async fn probe_with_fallback(
+    options: &ClientOptions,
+    url: &Url,
+) -> Result<Negotiated, StormError> {
+    let mut options = options.clone();
+    let mut working = Vec::new();
+    let mut failed = Vec::new();
+
+    if options.protocol == PreferredProtocol::Http3 {
+        options.protocol = PreferredProtocol::Auto;
+        #[cfg(feature = "http3")]
+        {
+            let downloader = HttpDownloader::with_options(&options)?;
+            if url.scheme() == "https" && downloader.http3_capable() {
+                let (http3, http2) = race_http3(&downloader, url).await;
+                let http3 = match http3 {
+                    Some(Ok(probe)) => Ok(probe),
+                    Some(Err(e)) => Err(e.to_string()),
+                    None => Err("HTTP/2 answered first".to_string()),
+                };
+                let info = match (http2, http3) {
+                    (Ok(mut info), Ok(probe)) => {
+                        working.push(info.http_version);
+                        info.http_version = HttpVersion::Http3;
+                        info.connection_rtt = probe.connection_rtt;
+                        info
+                    }
+                    (Ok(info), Err(reason)) => {
+                        failed.push((HttpVersion::Http3, reason));
+                        info
+                    }
+                    (Err(e), Ok(probe)) if is_transport_error(&e) => {
+                        failed.push((HttpVersion::Http2, e.to_string()));
+                        probe
+                    }
+                    (Err(e), _) => return Err(e),
+                };
+                return Ok(Negotiated::new(downloader, info, working, failed));
+            }
+        }
+        #[cfg(not(feature = "http3"))]
+        tracing::warn!("Built without HTTP/3 support; negotiating HTTP/2 instead");
+    }
+
+    if options.protocol == PreferredProtocol::Http2 {
+        let downloader = HttpDownloader::with_options(&options)?;
+        match downloader.probe(url).await {
+            Ok(info) => return Ok(Negotiated::new(downloader, info, working, failed)),
+            Err(e) if is_transport_error(&e) => failed.push((HttpVersion::Http2, e.to_string())),
+            Err(e) => return Err(e),
+        }
+        options.protocol = PreferredProtocol::Auto;
+    }
+    let downloader = HttpDownloader::with_options(&options)?;
+    let mut info = downloader.probe(url).await?;
+
+    // Left to negotiation, segments move to HTTP/3 where the server
+    // advertises it.
+    let http2 = info.http_version;
+    match upgrade_http3(&downloader, &mut info).await {
+        Ok(true) => working.push(http2),
+        Ok(false) => {}
+        Err(e) => failed.push((HttpVersion::Http3, e.to_string())),
+    }
+
+    Ok(Negotiated::new(downloader, info, working, failed))
+}
+
+// How long HTTP/3 has to itself before HTTP/2 joins the race. QUIC needs
+// fewer round trips to connect, so it wins wherever it gets through; where
+// UDP is blocked HTTP/2 takes over long before the QUIC handshake times
+// out.
+#[cfg(feature = "http3")]
+const HTTP3_HEAD_START: Duration = Duration::from_millis(300);
+
+// Probes `url` over HTTP/3 and HTTP/2 at once, the way Happy Eyeballs
+// races address families. HTTP/3's result is None when HTTP/2 answered
+// first and the QUIC attempt was dropped; HTTP/2's probe always completes,
+// since it's the one that follows landing pages and the like.
+#[cfg(feature = "http3")]
+async fn race_http3(
+    downloader: &HttpDownloader,
+    url: &Url,
+) -> (
+    Option<Result<ResourceInfo, StormError>>,
+    Result<ResourceInfo, StormError>,
+) {
+    let http3 = downloader.use_http3(url);
+    tokio::pin!(http3);
+    let http3 = tokio::select! {
+        result = &mut http3 => result,
+        _ = tokio::time::sleep(HTTP3_HEAD_START) => {
+            let http2 = downloader.probe(url);
+            tokio::pin!(http2);
+            tokio::select! {
+                result = &mut http3 => return (Some(result), http2.await),
+                result = &mut http2 => match result {
+                    Ok(info) => return (None, Ok(info)),
+                    Err(e) => return (Some(http3.await), Err(e)),
+                },
+            }
+        }
+    };
+    (Some(http3), downloader.probe(url).await)
+}
+
+// Moves a probed download onto HTTP/3 if its server advertises it. The
+// error is why HTTP/3 didn't work out; the download carries on over
+// `info.http_version`.
+pub async fn upgrade_http3(
+    downloader: &HttpDownloader,
+    info: &mut ResourceInfo,
+) -> Result<bool, StormError> {
+    #[cfg(feature = "http3")]
+    if info.url.scheme() == "https" && downloader.advertises_http3(&info.url) {
+        let probe = downloader.use_http3(&info.url).await?;
+        info.http_version = HttpVersion::Http3;
+        info.connection_rtt = probe.connection_rtt;
+        return Ok(true);
+    }
+    #[cfg(not(feature = "http3"))]
+    let _ = (downloader, info);
+    Ok(false)
+}
+
+fn is_transport_error(e: &StormError) -> bool {
+    matches!(
+        e,
+        StormError::Network(_) | StormError::Protocol(_) | StormError::Timeout(_)
+    )
+}
+
 pub struct ProtocolNegotiator {
     client: Client,
 }
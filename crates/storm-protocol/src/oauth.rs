@@ -0,0 +1,181 @@
+use parking_lot::Mutex;
+use reqwest::header::{self, HeaderValue};
+use reqwest::{Client, StatusCode};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stormdl_core::StormError;
+use url::Url;
+
+// An access token this close to expiring is refreshed before it's sent.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Rotated = Arc<dyn Fn(&str) + Send + Sync>;
+
+// An OAuth2 refresh token and the client it was issued to (RFC 6749,
+// section 6), traded at `token_url` for the short-lived access tokens
+// requests carry.
+#[derive(Clone)]
+pub struct OAuth2Grant {
+    pub token_url: Url,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub refresh_token: String,
+    // Told about a new refresh token when the server rotates it, so it can
+    // be saved for the next run.
+    rotated: Option<Rotated>,
+}
+
+impl OAuth2Grant {
+    pub fn new(token_url: Url, client_id: &str, refresh_token: &str) -> Self {
+        Self {
+            token_url,
+            client_id: client_id.to_string(),
+            client_secret: None,
+            refresh_token: refresh_token.to_string(),
+            rotated: None,
+        }
+    }
+
+    pub fn with_client_secret(mut self, secret: Option<String>) -> Self {
+        self.client_secret = secret;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotated: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.rotated = Some(Arc::new(rotated));
+        self
+    }
+}
+
+impl fmt::Debug for OAuth2Grant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2Grant")
+            .field("token_url", &self.token_url.as_str())
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Access {
+    header: HeaderValue,
+    expires: Option<Instant>,
+}
+
+// One grant's current access token, shared by every clone of the client so
+// a token expiring mid-download is refreshed once, not once per segment.
+pub(crate) struct OAuthState {
+    grant: Mutex<OAuth2Grant>,
+    access: Mutex<Option<Access>>,
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl fmt::Debug for OAuthState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OAuthState")
+            .field(&*self.grant.lock())
+            .finish()
+    }
+}
+
+impl OAuthState {
+    pub(crate) fn new(grant: OAuth2Grant) -> Arc<Self> {
+        Arc::new(Self {
+            grant: Mutex::new(grant),
+            access: Mutex::new(None),
+            refreshing: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    // The Authorization value while the access token is good.
+    pub(crate) fn header(&self) -> Option<HeaderValue> {
+        let access = self.access.lock();
+        let access = access.as_ref()?;
+        match access.expires {
+            Some(expires) if Instant::now() + EXPIRY_MARGIN >= expires => None,
+            _ => Some(access.header.clone()),
+        }
+    }
+
+    // Trades the refresh token for a new access token, unless another
+    // request already replaced `rejected`, the one the server turned down.
+    pub(crate) async fn refresh(
+        &self,
+        client: &Client,
+        rejected: Option<&HeaderValue>,
+    ) -> Result<(), StormError> {
+        let _refreshing = self.refreshing.lock().await;
+        if let Some(current) = self.header()
+            && rejected != Some(&current)
+        {
+            return Ok(());
+        }
+
+        let grant = self.grant.lock().clone();
+        let form = {
+            let mut form = url::form_urlencoded::Serializer::new(String::new());
+            form.append_pair("grant_type", "refresh_token")
+                .append_pair("refresh_token", &grant.refresh_token)
+                .append_pair("client_id", &grant.client_id);
+            if let Some(secret) = &grant.client_secret {
+                form.append_pair("client_secret", secret);
+            }
+            form.finish()
+        };
+        let response = client
+            .post(grant.token_url.clone())
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::ACCEPT, "application/json")
+            .body(form)
+            .timeout(REFRESH_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| StormError::Network(format!("refreshing the access token: {}", e)))?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| StormError::Network(e.to_string()))?;
+        let json = serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default();
+        let field = |name: &str| json.get(name).and_then(|v| v.as_str());
+        if !status.is_success() {
+            // invalid_grant: the refresh token was revoked or has expired.
+            let reason = field("error_description")
+                .or_else(|| field("error"))
+                .unwrap_or_else(|| status.canonical_reason().unwrap_or("failed"));
+            return Err(StormError::Http {
+                status: if status == StatusCode::BAD_REQUEST {
+                    StatusCode::UNAUTHORIZED.as_u16()
+                } else {
+                    status.as_u16()
+                },
+                message: format!(
+                    "OAuth2 token refresh at {} failed: {}",
+                    grant.token_url.host_str().unwrap_or_default(),
+                    reason
+                ),
+            });
+        }
+        let token = field("access_token")
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| StormError::Protocol("token response has no access_token".into()))?;
+        let mut header = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| StormError::Protocol("access token has invalid characters".into()))?;
+        header.set_sensitive(true);
+        let expires = json
+            .get("expires_in")
+            .and_then(|v| v.as_u64().or_else(|| v.as_str()?.parse().ok()))
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+        *self.access.lock() = Some(Access { header, expires });
+
+        if let Some(rotated) = field("refresh_token").filter(|t| *t != grant.refresh_token) {
+            tracing::debug!("{} issued a new refresh token", grant.token_url);
+            if let Some(save) = &grant.rotated {
+                save(rotated);
+            }
+            self.grant.lock().refresh_token = rotated.to_string();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use crate::digest::parse_challenges;
+use crate::{HostAuth, HttpDownloader};
+use reqwest::StatusCode;
+use reqwest::header::{self, HeaderMap, WWW_AUTHENTICATE};
+use stormdl_core::StormError;
+use url::Url;
+
+// Docker Hub's registry isn't at the name images are pulled by.
+const DOCKER_HUB: &str = "registry-1.docker.io";
+
+// A blob in an OCI (or Docker) registry, named as
+// `oci://registry/repository@sha256:digest`. Blobs are content-addressed,
+// so the digest doubles as the checksum of the download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OciBlob {
+    registry: String,
+    // Like docker, registries on the local machine are spoken to over
+    // plain HTTP.
+    local: bool,
+    repository: String,
+    algorithm: String,
+    hex: String,
+}
+
+impl OciBlob {
+    pub fn is_oci(url: &Url) -> bool {
+        url.scheme() == "oci"
+    }
+
+    pub fn parse(url: &Url) -> Result<Self, StormError> {
+        let invalid = |why: &str| {
+            StormError::InvalidUrl(format!(
+                "{}: {} (expected oci://registry/repository@sha256:digest)",
+                url, why
+            ))
+        };
+        let host = url
+            .host_str()
+            .filter(|h| !h.is_empty())
+            .ok_or_else(|| invalid("no registry"))?;
+        let registry = match (host, url.port()) {
+            ("docker.io" | "index.docker.io", None) => DOCKER_HUB.to_string(),
+            (host, Some(port)) => format!("{}:{}", host, port),
+            (host, None) => host.to_string(),
+        };
+        let (repository, digest) = url
+            .path()
+            .trim_start_matches('/')
+            .rsplit_once('@')
+            .ok_or_else(|| invalid("no digest"))?;
+        let repository = repository.trim_end_matches('/');
+        if repository.is_empty() {
+            return Err(invalid("no repository"));
+        }
+        // Official images live under library/ on Docker Hub.
+        let repository = if registry == DOCKER_HUB && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository.to_ascii_lowercase()
+        };
+        let (algorithm, hex) = digest.split_once(':').ok_or_else(|| invalid("no digest"))?;
+        let len = match algorithm {
+            "sha256" => 64,
+            "sha512" => 128,
+            _ => return Err(invalid("unsupported digest algorithm")),
+        };
+        if hex.len() != len || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid("malformed digest"));
+        }
+        Ok(Self {
+            registry,
+            local: matches!(host, "localhost" | "127.0.0.1" | "[::1]"),
+            repository,
+            algorithm: algorithm.to_string(),
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+
+    // `sha256:<hex>`, as registries and checksums spell it.
+    pub fn digest(&self) -> String {
+        format!("{}:{}", self.algorithm, self.hex)
+    }
+
+    // The default name for the download; a colon doesn't travel well.
+    pub fn file_name(&self) -> String {
+        format!("{}-{}", self.algorithm, self.hex)
+    }
+
+    // The registry's blob endpoint.
+    pub fn url(&self) -> Url {
+        let scheme = if self.local { "http" } else { "https" };
+        Url::parse(&format!(
+            "{}://{}/v2/{}/blobs/{}",
+            scheme,
+            self.registry,
+            self.repository,
+            self.digest()
+        ))
+        .expect("registry URLs are valid")
+    }
+
+    // A pull token from the registry's auth server, when it asks for one
+    // (the Docker token protocol). `auth` credentials for the registry,
+    // e.g. a GitHub token for ghcr.io, are shown to the auth server; without
+    // them the token is an anonymous one, which public images accept.
+    pub async fn token(
+        &self,
+        downloader: &HttpDownloader,
+        auth: Option<&HostAuth>,
+    ) -> Result<Option<String>, StormError> {
+        let mut base = self.url();
+        base.set_path("/v2/");
+        let response = downloader.get_response(&base, HeaderMap::new()).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(None);
+        }
+        let challenge = response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(parse_challenges)
+            .find(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"));
+        // Basic registries take --user credentials on every request.
+        let Some((_, params)) = challenge else {
+            return Ok(None);
+        };
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+        let mut realm = param("realm")
+            .and_then(|realm| Url::parse(realm).ok())
+            .ok_or_else(|| StormError::Protocol(format!("{} names no token realm", base)))?;
+        {
+            let mut query = realm.query_pairs_mut();
+            if let Some(service) = param("service") {
+                query.append_pair("service", service);
+            }
+            query.append_pair("scope", &format!("repository:{}:pull", self.repository));
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(value) = auth.and_then(|auth| auth.header_for(&base)) {
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        let response = downloader.get_response(&realm, headers).await?;
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                return Err(StormError::Http {
+                    status: response.status().as_u16(),
+                    message: format!(
+                        "registry refused to pull {}; pass credentials with --user",
+                        self.repository
+                    ),
+                });
+            }
+            status => {
+                return Err(StormError::Http {
+                    status: status.as_u16(),
+                    message: format!("token request to {} failed", realm.host_str().unwrap_or("")),
+                });
+            }
+        }
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| StormError::Network(e.to_string()))?;
+        let token = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|json| {
+                json.get("token")
+                    .or_else(|| json.get("access_token"))
+                    .and_then(|t| t.as_str())
+                    .map(str::to_string)
+            })
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| StormError::Protocol("token response has no token".into()))?;
+        Ok(Some(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let digest = "a".repeat(64);
+        let blob = OciBlob::parse(
+            &Url::parse(&format!("oci://ghcr.io/Org/model@sha256:{}", digest)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            blob.url().as_str(),
+            format!("https://ghcr.io/v2/org/model/blobs/sha256:{}", digest)
+        );
+        assert_eq!(blob.digest(), format!("sha256:{}", digest));
+        assert_eq!(blob.file_name(), format!("sha256-{}", digest));
+
+        let blob = OciBlob::parse(
+            &Url::parse(&format!("oci://docker.io/ubuntu@sha256:{}", digest)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            blob.url().as_str(),
+            format!(
+                "https://registry-1.docker.io/v2/library/ubuntu/blobs/sha256:{}",
+                digest
+            )
+        );
+        let blob = OciBlob::parse(
+            &Url::parse(&format!("oci://localhost:5000/a/b/c@sha256:{}", digest)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            blob.url().as_str(),
+            format!("http://localhost:5000/v2/a/b/c/blobs/sha256:{}", digest)
+        );
+
+        for bad in [
+            "oci://ghcr.io/org/model".to_string(),
+            "oci://ghcr.io/org/model:latest".to_string(),
+            format!("oci://ghcr.io/@sha256:{}", digest),
+            "oci://ghcr.io/org/model@sha256:abc".to_string(),
+            format!("oci://ghcr.io/org/model@md5:{}", &digest[..32]),
+        ] {
+            assert!(
+                OciBlob::parse(&Url::parse(&bad).unwrap()).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use reqwest::Proxy;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use stormdl_core::StormError;
+use url::Url;
+
+static NEXT_CIRCUIT: AtomicU64 = AtomicU64::new(0);
+
+// Per target scheme, checked in order, as curl does. HTTP_PROXY is only read
+// lowercase: CGI servers put a request's Proxy header in HTTP_PROXY (httpoxy).
+const ENV_SCHEME_PROXIES: [(&str, &[&str]); 2] = [
+    ("https", &["https_proxy", "HTTPS_PROXY"]),
+    ("http", &["http_proxy"]),
+];
+const ENV_ALL_PROXY: [&str; 2] = ["all_proxy", "ALL_PROXY"];
+const ENV_NO_PROXY: [&str; 2] = ["no_proxy", "NO_PROXY"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub url: Option<Url>,
+    // Proxies for targets of one scheme, ahead of `url`.
+    pub schemes: Vec<(String, Url)>,
+    pub isolate: bool,
+    pub single_stream: bool,
+    pub hosts: Vec<(String, Option<Url>)>,
+    pub bypass: Option<String>,
+}
+
+impl ProxyConfig {
+    pub fn new(url: &str) -> Result<Self, StormError> {
+        if url.trim() == "direct" {
+            return Ok(Self::direct());
+        }
+        Ok(Self {
+            url: Some(parse_proxy(url)?),
+            schemes: Vec::new(),
+            isolate: false,
+            single_stream: false,
+            hosts: Vec::new(),
+            bypass: None,
+        })
+    }
+
+    // "direct": no proxy, and keeps reqwest from reading the proxy variables
+    // on its own.
+    pub fn direct() -> Self {
+        Self {
+            url: None,
+            schemes: Vec::new(),
+            isolate: false,
+            single_stream: false,
+            hosts: Vec::new(),
+            bypass: None,
+        }
+    }
+
+    pub fn from_env() -> Result<Option<Self>, StormError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, StormError> {
+        let set = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| var(name))
+                .find(|value| !value.trim().is_empty())
+        };
+        let mut proxy = match set(&ENV_ALL_PROXY) {
+            Some(url) => Self::new(url.trim())?,
+            None => Self::direct(),
+        };
+        for (scheme, names) in ENV_SCHEME_PROXIES {
+            if let Some(url) = set(names) {
+                proxy
+                    .schemes
+                    .push((scheme.to_string(), parse_proxy(url.trim())?));
+            }
+        }
+        if proxy.url.is_none() && proxy.schemes.is_empty() {
+            return Ok(None);
+        }
+        if let Some(bypass) = set(&ENV_NO_PROXY) {
+            proxy = proxy.with_bypass(&bypass);
+        }
+        Ok(Some(proxy))
+    }
+
+    pub fn with_isolation(mut self, isolate: bool) -> Self {
+        self.isolate = isolate;
+        self
+    }
+
+    pub fn with_single_stream(mut self, single_stream: bool) -> Self {
+        self.single_stream = single_stream;
+        self
+    }
+
+    // Routes `host` and its subdomains through `proxy` instead, or directly
+    // when `proxy` is "direct".
+    pub fn with_host(mut self, host: &str, proxy: &str) -> Result<Self, StormError> {
+        let host = host.trim().trim_start_matches('.').to_ascii_lowercase();
+        if host.is_empty() {
+            return Err(StormError::Config("proxy override needs a host".into()));
+        }
+        let proxy = match proxy.trim() {
+            "direct" => None,
+            url => Some(parse_proxy(url)?),
+        };
+        self.hosts.retain(|(h, _)| *h != host);
+        self.hosts.push((host, proxy));
+        Ok(self)
+    }
+
+    pub fn with_bypass(mut self, no_proxy: &str) -> Self {
+        self.bypass = Some(no_proxy.to_string()).filter(|b| !b.trim().is_empty());
+        self
+    }
+
+    pub fn is_socks(&self) -> bool {
+        self.url
+            .as_ref()
+            .is_some_and(|url| url.scheme().starts_with("socks5"))
+    }
+
+    pub fn is_direct(&self) -> bool {
+        self.url.is_none()
+            && self.schemes.is_empty()
+            && self.hosts.iter().all(|(_, proxy)| proxy.is_none())
+    }
+
+    pub fn route(&self, target: &Url) -> Option<&Url> {
+        let host = target
+            .host_str()
+            .unwrap_or("")
+            .trim_matches(['[', ']'])
+            .to_ascii_lowercase();
+        if let Some((_, proxy)) = self.hosts.iter().find(|(h, _)| in_domain(&host, h)) {
+            return proxy.as_ref();
+        }
+        let bypassed = self.bypass.as_deref().is_some_and(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .any(|entry| bypasses(&host, entry))
+        });
+        if bypassed {
+            return None;
+        }
+        self.schemes
+            .iter()
+            .find(|(scheme, _)| *scheme == target.scheme())
+            .map(|(_, url)| url)
+            .or(self.url.as_ref())
+    }
+
+    pub fn circuit_url(&self, circuit: &str) -> Option<Url> {
+        self.url.as_ref().map(|url| self.isolated(url, circuit))
+    }
+
+    // Credentials the user configured are never replaced; only an anonymous
+    // SOCKS proxy gets per-circuit ones.
+    fn isolated(&self, proxy: &Url, circuit: &str) -> Url {
+        let mut url = proxy.clone();
+        if self.isolate && url.scheme().starts_with("socks5") && url.username().is_empty() {
+            let _ = url.set_username(&format!("storm-{}", circuit));
+            let _ = url.set_password(Some("storm"));
+        }
+        url
+    }
+
+    // The proxy `target` goes through, with fresh circuit credentials when
+    // isolating.
+    #[cfg_attr(not(feature = "http3"), allow(dead_code))]
+    pub(crate) fn circuit_route(&self, target: &Url) -> Option<Url> {
+        self.route(target)
+            .map(|url| self.isolated(url, &new_circuit()))
+    }
+
+    pub(crate) fn for_new_circuit(&self) -> Result<Proxy, StormError> {
+        let circuit = new_circuit();
+        // reqwest ignores its own no_proxy list on custom proxies, so the
+        // bypass is applied in route() instead.
+        let config = self.clone();
+        Ok(Proxy::custom(move |target| {
+            config
+                .route(target)
+                .map(|url| config.isolated(url, &circuit))
+        }))
+    }
+}
+
+fn new_circuit() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!(
+        "{:x}-{:x}-{}",
+        std::process::id(),
+        nanos,
+        NEXT_CIRCUIT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn in_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// One NO_PROXY entry: `*`, a domain (with or without the leading dot), an IP
+// address or a CIDR block.
+fn bypasses(host: &str, entry: &str) -> bool {
+    let entry = entry.to_ascii_lowercase();
+    if entry == "*" {
+        return true;
+    }
+    if let Some((network, bits)) = entry.split_once('/') {
+        let (Ok(host), Ok(network), Ok(bits)) = (
+            host.parse::<IpAddr>(),
+            network.parse::<IpAddr>(),
+            bits.parse::<u32>(),
+        ) else {
+            return false;
+        };
+        let (host, network, width) = match (host, network) {
+            (IpAddr::V4(h), IpAddr::V4(n)) => (u32::from(h) as u128, u32::from(n) as u128, 32),
+            (IpAddr::V6(h), IpAddr::V6(n)) => (u128::from(h), u128::from(n), 128),
+            _ => return false,
+        };
+        let shift = width - bits.min(width);
+        return host.checked_shr(shift).unwrap_or(0) == network.checked_shr(shift).unwrap_or(0);
+    }
+    let entry = entry.trim_matches(['[', ']']);
+    if entry.parse::<IpAddr>().is_ok() {
+        return host == entry;
+    }
+    let entry = entry.trim_start_matches("*.").trim_start_matches('.');
+    let entry = entry.split(':').next().unwrap_or(entry);
+    in_domain(host, entry)
+}
+
+fn parse_proxy(url: &str) -> Result<Url, StormError> {
+    let url = Url::parse(url).map_err(|e| StormError::Config(format!("proxy: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(StormError::Config(format!(
+            "unsupported proxy scheme '{}'",
+            url.scheme()
+        )));
+    }
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_isolation() {
+        let socks = ProxyConfig::new("socks5h://127.0.0.1:9050").unwrap();
+        assert!(!socks.isolate && !socks.single_stream);
+        assert_eq!(socks.circuit_url("a").unwrap().username(), "");
+
+        let tor = socks.with_isolation(true);
+
+        let a = tor.circuit_url("a").unwrap();
+        let b = tor.circuit_url("b").unwrap();
+        assert_eq!(a.username(), "storm-a");
+        assert_ne!(a.username(), b.username());
+        assert_eq!(a.host_str(), Some("127.0.0.1"));
+
+        let shared = tor.clone().with_isolation(false).circuit_url("a").unwrap();
+        assert_eq!(shared.username(), "");
+
+        let corp = ProxyConfig::new("socks5://alice:pw@proxy:1080")
+            .unwrap()
+            .with_isolation(true);
+        let url = corp.circuit_url("a").unwrap();
+        assert_eq!((url.username(), url.password()), ("alice", Some("pw")));
+
+        let http = ProxyConfig::new("http://proxy:3128").unwrap();
+        assert!(!http.isolate && !http.single_stream);
+        assert!(ProxyConfig::new("ftp://proxy").is_err());
+    }
+
+    #[test]
+    fn test_env_and_host_routes() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+        assert_eq!(ProxyConfig::from_vars(env(&[])).unwrap(), None);
+        let proxy = ProxyConfig::from_vars(env(&[
+            ("http_proxy", "http://web:3128"),
+            ("ALL_PROXY", ""),
+            ("HTTPS_PROXY", "http://secure:3128"),
+            ("no_proxy", "localhost,.internal"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(proxy.url, None);
+        assert_eq!(proxy.bypass.as_deref(), Some("localhost,.internal"));
+        let via = |proxy: &ProxyConfig, url: &str| {
+            proxy
+                .route(&Url::parse(url).unwrap())
+                .and_then(|p| p.host_str().map(str::to_string))
+        };
+        assert_eq!(via(&proxy, "http://example.com/a").unwrap(), "web");
+        assert_eq!(via(&proxy, "ftp://example.com/a"), None);
+
+        // Uppercase HTTP_PROXY can come from a request header under CGI.
+        assert_eq!(
+            ProxyConfig::from_vars(env(&[("HTTP_PROXY", "http://evil:3128")])).unwrap(),
+            None
+        );
+        let all = ProxyConfig::from_vars(env(&[
+            ("all_proxy", "socks5h://tor:9050"),
+            ("https_proxy", "http://secure:3128"),
+            ("HTTP_PROXY", "http://evil:3128"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(via(&all, "https://example.com/a").unwrap(), "secure");
+        assert_eq!(via(&all, "http://example.com/a").unwrap(), "tor");
+        assert_eq!(via(&all, "ftp://example.com/a").unwrap(), "tor");
+
+        let proxy = proxy
+            .with_host("mirror.example.org", "socks5h://127.0.0.1:9050")
+            .unwrap()
+            .with_host("example.com", "direct")
+            .unwrap();
+        let route = |url: &str| via(&proxy, url);
+        assert_eq!(
+            route("https://mirror.example.org/a.iso").unwrap(),
+            "127.0.0.1"
+        );
+        assert_eq!(route("https://cdn.example.com/a.iso"), None);
+        assert_eq!(route("https://notexample.com/a.iso").unwrap(), "secure");
+        assert_eq!(route("http://localhost:8080/a.iso"), None);
+        assert_eq!(route("http://build.internal/a.iso"), None);
+
+        let lan = ProxyConfig::new("http://proxy:3128")
+            .unwrap()
+            .with_bypass("10.0.0.0/8, ::1");
+        assert!(
+            lan.route(&Url::parse("http://10.1.2.3/").unwrap())
+                .is_none()
+        );
+        assert!(lan.route(&Url::parse("http://[::1]/").unwrap()).is_none());
+        assert!(
+            lan.route(&Url::parse("http://11.1.2.3/").unwrap())
+                .is_some()
+        );
+        assert!(!proxy.is_direct());
+        assert!(ProxyConfig::new("direct").unwrap().is_direct());
+        assert!(proxy.with_host("x.org", "ftp://proxy").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{HttpDownloader, SocketOptions};
+use reqwest::ClientBuilder;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::str::FromStr;
+use stormdl_core::StormError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalBind {
+    Interface(String),
+    Address(IpAddr),
+}
+
+impl LocalBind {
+    pub fn check(&self) -> Result<(), StormError> {
+        match self {
+            Self::Address(addr) => UdpSocket::bind((*addr, 0)).map(|_| ()).map_err(|e| {
+                StormError::Config(format!("cannot bind to local address {}: {}", addr, e))
+            }),
+            Self::Interface(name) => check_interface(name),
+        }
+    }
+
+    fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder, StormError> {
+        match self {
+            Self::Address(addr) => Ok(builder.local_address(*addr)),
+            Self::Interface(name) => bind_interface(builder, name),
+        }
+    }
+}
+
+impl FromStr for LocalBind {
+    type Err = StormError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(StormError::Config("empty interface name".into()));
+        }
+        Ok(match s.parse() {
+            Ok(addr) => Self::Address(addr),
+            Err(_) => Self::Interface(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for LocalBind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interface(name) => f.write_str(name),
+            Self::Address(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    label: String,
+    bind: Option<LocalBind>,
+    pin: Option<(String, Vec<SocketAddr>)>,
+}
+
+impl Route {
+    pub fn bound(bind: LocalBind) -> Self {
+        Self {
+            label: bind.to_string(),
+            bind: Some(bind),
+            pin: None,
+        }
+    }
+
+    pub fn pinned(label: &str, host: &str, addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            label: label.to_string(),
+            bind: None,
+            pin: Some((host.to_string(), addrs)),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn worker(
+        &self,
+        turbo: bool,
+        http1: bool,
+        socket: SocketOptions,
+    ) -> Result<HttpDownloader, StormError> {
+        let mut builder = HttpDownloader::keep_alive_builder(turbo, socket);
+        if http1 {
+            builder = builder.http1_only();
+        }
+        if let Some(bind) = &self.bind {
+            builder = bind.apply(builder)?;
+        }
+        if let Some((host, addrs)) = &self.pin {
+            builder = builder.resolve_to_addrs(host, addrs);
+        }
+        HttpDownloader::worker(builder)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_interface(name: &str) -> Result<(), StormError> {
+    if std::path::Path::new("/sys/class/net").join(name).exists() {
+        Ok(())
+    } else {
+        Err(StormError::Config(format!(
+            "no network interface named '{}'",
+            name
+        )))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_interface(_name: &str) -> Result<(), StormError> {
+    Ok(())
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "fuchsia",
+    target_os = "illumos",
+    target_os = "ios",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "solaris",
+    target_os = "tvos",
+    target_os = "visionos",
+    target_os = "watchos",
+))]
+fn bind_interface(builder: ClientBuilder, name: &str) -> Result<ClientBuilder, StormError> {
+    Ok(builder.interface(name))
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "fuchsia",
+    target_os = "illumos",
+    target_os = "ios",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "solaris",
+    target_os = "tvos",
+    target_os = "visionos",
+    target_os = "watchos",
+)))]
+fn bind_interface(_builder: ClientBuilder, name: &str) -> Result<ClientBuilder, StormError> {
+    Err(StormError::Config(format!(
+        "binding to interface '{}' is not supported on this platform; use its IP address",
+        name
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_bind() {
+        assert_eq!(
+            "wlan0".parse::<LocalBind>().unwrap(),
+            LocalBind::Interface("wlan0".into())
+        );
+        assert_eq!(
+            " 192.168.1.20 ".parse::<LocalBind>().unwrap(),
+            LocalBind::Address([192, 168, 1, 20].into())
+        );
+        assert!("fe80::1".parse::<LocalBind>().unwrap() != LocalBind::Interface("fe80::1".into()));
+        assert!("".parse::<LocalBind>().is_err());
+        assert!(LocalBind::Address([127, 0, 0, 1].into()).check().is_ok());
+    }
+}
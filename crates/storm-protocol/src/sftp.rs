@@ -0,0 +1,386 @@
+use crate::ssh::{self, KnownHosts, Login, ssh_error};
+use crate::{ClientOptions, HostAuth, ProxyConfig};
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use ssh2::{ErrorCode, Sftp};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use stormdl_core::{ByteRange, DataSink, Downloader, HttpVersion, ResourceInfo, StormError};
+use tokio::sync::mpsc;
+use url::Url;
+
+const MAX_IDLE: usize = 4;
+// libssh2 pipelines the reads a large buffer needs, much as OpenSSH's sftp
+// keeps 64 requests of 32KiB in flight.
+const CHUNK: usize = 2 * 1024 * 1024;
+
+const FX_EOF: i32 = 1;
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_PERMISSION_DENIED: i32 = 3;
+
+// Where and as whom to log in; idle sessions are pooled per target.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Target {
+    host: String,
+    port: u16,
+    login: Login,
+}
+
+impl Target {
+    // A user in the URL wins over --user and profiles, then $USER as ssh
+    // does. A password is only tried when one is given.
+    fn new(url: &Url, auth: Option<&HostAuth>, keys: Vec<PathBuf>) -> Result<Self, StormError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| StormError::InvalidUrl(format!("{} has no host", url)))?
+            .trim_matches(['[', ']'])
+            .to_string();
+        let (user, password) = if !url.username().is_empty() {
+            (decode(url.username()), url.password().map(decode))
+        } else if let Some((user, password)) = auth.and_then(|auth| auth.login_for(url)) {
+            (user.to_string(), Some(password.to_string()))
+        } else {
+            let user = std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .map_err(|_| {
+                    StormError::Config(format!(
+                        "no user to log in to {} as; put one in the URL",
+                        host
+                    ))
+                })?;
+            (user, None)
+        };
+        Ok(Self {
+            host,
+            port: url.port().unwrap_or(22),
+            login: Login {
+                user,
+                password: password.filter(|p| !p.is_empty()),
+                keys,
+            },
+        })
+    }
+}
+
+fn decode(s: &str) -> String {
+    urlencoding::decode(s)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| s.to_string())
+}
+
+// Paths are absolute, and `/~/` starts from the login directory, as in curl.
+fn remote_path(url: &Url) -> Result<String, StormError> {
+    let path = decode(url.path());
+    if path.is_empty() || path.ends_with('/') {
+        return Err(StormError::InvalidUrl(format!(
+            "{} doesn't name a file",
+            url
+        )));
+    }
+    Ok(match path.strip_prefix("/~/") {
+        Some(relative) => relative.to_string(),
+        None => path,
+    })
+}
+
+fn status_error(code: i32, message: &str, path: &str) -> StormError {
+    match code {
+        FX_NO_SUCH_FILE => StormError::NotFound(format!("sftp: {}", path)),
+        FX_PERMISSION_DENIED => {
+            StormError::Config(format!("sftp: permission denied reading {}", path))
+        }
+        FX_EOF => StormError::Network(format!("sftp: {} ended early", path)),
+        _ => StormError::Protocol(format!("sftp: {} ({}): {}", path, code, message)),
+    }
+}
+
+fn sftp_error(e: ssh2::Error, path: &str) -> StormError {
+    match e.code() {
+        ErrorCode::SFTP(code) => status_error(code, e.message(), path),
+        ErrorCode::Session(_) => ssh_error(e),
+    }
+}
+
+// Runs blocking libssh2 work off the async threads.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, StormError> + Send + 'static,
+) -> Result<T, StormError> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| StormError::Protocol(format!("sftp: {}", e)))?
+}
+
+// One SSH connection running the sftp subsystem.
+struct Session {
+    // Owns the connection the sftp channel runs on.
+    _ssh: ssh2::Session,
+    sftp: Sftp,
+}
+
+impl Session {
+    async fn open(target: &Target, known_hosts: Vec<PathBuf>) -> Result<Self, StormError> {
+        let target = target.clone();
+        blocking(move || {
+            let ssh = ssh::connect(&target.host, target.port, &target.login, known_hosts)?;
+            let sftp = ssh.sftp().map_err(|e| match e.code() {
+                ErrorCode::Session(_) => StormError::Protocol(format!(
+                    "the SSH server has no sftp subsystem: {}",
+                    e.message()
+                )),
+                _ => ssh_error(e),
+            })?;
+            Ok(Self { _ssh: ssh, sftp })
+        })
+        .await
+    }
+
+    async fn stat(self, path: String) -> Result<(Self, ssh2::FileStat), StormError> {
+        blocking(move || {
+            let stat = self
+                .sftp
+                .stat(Path::new(&path))
+                .map_err(|e| sftp_error(e, &path))?;
+            Ok((self, stat))
+        })
+        .await
+    }
+
+    async fn ping(self) -> Result<Self, StormError> {
+        blocking(move || {
+            self.sftp
+                .realpath(Path::new("."))
+                .map_err(|e| sftp_error(e, "."))?;
+            Ok(self)
+        })
+        .await
+    }
+
+    // Reads `len` bytes from `start` (to EOF without a length) on a blocking
+    // thread, writing them to the sink as they arrive. Gives the session
+    // back when it is clean for reuse.
+    async fn retrieve(
+        self,
+        path: String,
+        start: u64,
+        len: Option<u64>,
+        sink: &mut dyn DataSink,
+    ) -> Result<Self, StormError> {
+        let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+        let reader = tokio::task::spawn_blocking(move || -> Result<Self, StormError> {
+            let mut file = self
+                .sftp
+                .open(Path::new(&path))
+                .map_err(|e| sftp_error(e, &path))?;
+            let end = match len {
+                Some(len) => start + len,
+                None => file
+                    .stat()
+                    .map_err(|e| sftp_error(e, &path))?
+                    .size
+                    .ok_or_else(|| StormError::Protocol(format!("sftp: {} has no size", path)))?,
+            };
+            file.seek(SeekFrom::Start(start))?;
+            let mut offset = start;
+            while offset < end {
+                let mut buf = vec![0u8; (end - offset).min(CHUNK as u64) as usize];
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    return Err(status_error(FX_EOF, "", &path));
+                }
+                buf.truncate(n);
+                offset += n as u64;
+                if tx.blocking_send(Bytes::from(buf)).is_err() {
+                    return Err(StormError::Cancelled);
+                }
+            }
+            drop(file);
+            Ok(self)
+        });
+
+        while let Some(data) = rx.recv().await {
+            sink.ready(data.len()).await?;
+            sink.write(data)?;
+        }
+        let session = reader
+            .await
+            .map_err(|e| StormError::Protocol(format!("sftp: {}", e)))??;
+        sink.flush()?;
+        Ok(session)
+    }
+}
+
+// SFTP over SSH, logging in with a password or a key from ssh-agent or the
+// usual files, and checking the host against known_hosts. Reads at any
+// offset let segmented and resumed downloads split the file; each range gets
+// its own session.
+pub struct SftpDownloader {
+    auth: Option<HostAuth>,
+    proxy: Option<ProxyConfig>,
+    ssh_key: Option<PathBuf>,
+    known_hosts: Vec<PathBuf>,
+    idle: Mutex<HashMap<Target, Vec<Session>>>,
+}
+
+impl SftpDownloader {
+    pub fn new() -> Self {
+        Self::with_options(&ClientOptions::default())
+    }
+
+    pub fn with_options(options: &ClientOptions) -> Self {
+        Self {
+            auth: options.auth.clone(),
+            proxy: options.proxy.clone(),
+            ssh_key: options.ssh_key.clone(),
+            known_hosts: KnownHosts::default_files(),
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn handles(url: &Url) -> bool {
+        url.scheme() == "sftp"
+    }
+
+    pub(crate) fn has_key(&self) -> bool {
+        self.ssh_key.is_some()
+    }
+
+    fn target(&self, url: &Url) -> Result<Target, StormError> {
+        // As with FTP, going around a configured proxy would leak the
+        // transfer.
+        if self.proxy.as_ref().is_some_and(|p| p.route(url).is_some()) {
+            return Err(StormError::Config(format!(
+                "SFTP can't go through a proxy; exclude {} with NO_PROXY or use --proxy direct",
+                url.host_str().unwrap_or_default()
+            )));
+        }
+        let mut keys: Vec<PathBuf> = self.ssh_key.iter().cloned().collect();
+        if let Some(ssh) = dirs::home_dir().map(|home| home.join(".ssh")) {
+            keys.extend(["id_ed25519", "id_ecdsa", "id_rsa"].map(|name| ssh.join(name)));
+        }
+        Target::new(url, self.auth.as_ref(), keys)
+    }
+
+    async fn session(&self, target: &Target) -> Result<Session, StormError> {
+        loop {
+            let idle = self.idle.lock().get_mut(target).and_then(Vec::pop);
+            let Some(session) = idle else {
+                return Session::open(target, self.known_hosts.clone()).await;
+            };
+            if let Ok(session) = session.ping().await {
+                return Ok(session);
+            }
+        }
+    }
+
+    fn release(&self, target: &Target, session: Session) {
+        let mut idle = self.idle.lock();
+        let sessions = idle.entry(target.clone()).or_default();
+        if sessions.len() < MAX_IDLE {
+            sessions.push(session);
+        }
+    }
+}
+
+impl Default for SftpDownloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Downloader for SftpDownloader {
+    async fn probe(&self, url: &Url) -> Result<ResourceInfo, StormError> {
+        let target = self.target(url)?;
+        let path = remote_path(url)?;
+        let start_time = Instant::now();
+        let session = self.session(&target).await?;
+        let connection_rtt = start_time.elapsed();
+
+        let (session, attrs) = session.stat(path.clone()).await?;
+        self.release(&target, session);
+
+        Ok(ResourceInfo {
+            url: url.clone(),
+            size: attrs.size,
+            supports_range: attrs.size.is_some(),
+            etag: None,
+            last_modified: attrs.mtime.map(|t| {
+                httpdate::fmt_http_date(std::time::UNIX_EPOCH + std::time::Duration::from_secs(t))
+            }),
+            content_type: None,
+            filename: path
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty())
+                .map(String::from),
+            http_version: HttpVersion::Http1_1,
+            connection_rtt: Some(connection_rtt),
+        })
+    }
+
+    async fn fetch_range(
+        &self,
+        url: &Url,
+        range: ByteRange,
+        sink: &mut dyn DataSink,
+    ) -> Result<(), StormError> {
+        let target = self.target(url)?;
+        let path = remote_path(url)?;
+        let session = self.session(&target).await?;
+        let session = session
+            .retrieve(path, range.start, Some(range.len()), sink)
+            .await?;
+        self.release(&target, session);
+        Ok(())
+    }
+
+    async fn fetch_full(&self, url: &Url, sink: &mut dyn DataSink) -> Result<(), StormError> {
+        let target = self.target(url)?;
+        let path = remote_path(url)?;
+        let session = self.session(&target).await?;
+        let session = session.retrieve(path, 0, None, sink).await?;
+        self.release(&target, session);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets_and_paths() {
+        let url = Url::parse("sftp://deploy:s%40cret@[::1]:2222/~/builds/app.tar").unwrap();
+        let target = Target::new(&url, None, Vec::new()).unwrap();
+        assert_eq!((target.host.as_str(), target.port), ("::1", 2222));
+        assert_eq!(target.login.user, "deploy");
+        assert_eq!(target.login.password.as_deref(), Some("s@cret"));
+        assert_eq!(remote_path(&url).unwrap(), "builds/app.tar");
+
+        let url = Url::parse("sftp://deploy@files.example.com/srv/app%20v2.tar").unwrap();
+        let target = Target::new(&url, None, Vec::new()).unwrap();
+        assert_eq!(target.port, 22);
+        assert_eq!(target.login.password, None);
+        assert_eq!(remote_path(&url).unwrap(), "/srv/app v2.tar");
+        assert!(remote_path(&Url::parse("sftp://host/srv/").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_status_errors() {
+        assert!(matches!(
+            status_error(FX_NO_SUCH_FILE, "", "/x"),
+            StormError::NotFound(_)
+        ));
+        assert!(matches!(
+            status_error(FX_PERMISSION_DENIED, "", "/x"),
+            StormError::Config(_)
+        ));
+        assert!(matches!(
+            status_error(4, "failure", "/x"),
+            StormError::Protocol(_)
+        ));
+    }
+}
@@ -0,0 +1,245 @@
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpInfo};
+use reqwest::ClientBuilder;
+use socket2::SockRef;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+    pub tos: Option<u8>,
+    pub notsent_lowat: Option<u32>,
+}
+
+impl SocketOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.tos = Some(dscp << 2);
+        self
+    }
+
+    pub(crate) fn apply(self, builder: ClientBuilder) -> ClientBuilder {
+        if self.is_empty() {
+            builder
+        } else {
+            builder.connector_layer(SocketLayer(self))
+        }
+    }
+
+    pub(crate) fn apply_tcp(&self, socket: SockRef<'_>) -> io::Result<()> {
+        self.apply_common(&socket)?;
+        if let Some(lowat) = self.notsent_lowat {
+            set_notsent_lowat(&socket, lowat)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "http3")]
+    pub(crate) fn apply_udp(&self, socket: SockRef<'_>) -> io::Result<()> {
+        self.apply_common(&socket)
+    }
+
+    fn apply_common(&self, socket: &SockRef<'_>) -> io::Result<()> {
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(tos) = self.tos {
+            match socket.local_addr()?.as_socket() {
+                Some(SocketAddr::V6(_)) => set_tclass(socket, tos)?,
+                _ => socket.set_tos_v4(tos as u32)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn tune(&self, connected: &Connected) {
+        let mut extensions = hyper::http::Extensions::new();
+        connected.get_extras(&mut extensions);
+        let Some(info) = extensions.get::<HttpInfo>() else {
+            return;
+        };
+        let (local, remote) = (info.local_addr(), info.remote_addr());
+        let result = find_socket(local, remote, |socket| self.apply_tcp(socket));
+        match result {
+            Some(Ok(())) => tracing::debug!("Tuned socket {} -> {}: {:?}", local, remote, self),
+            Some(Err(e)) => tracing::warn!("Failed to tune socket {} -> {}: {}", local, remote, e),
+            None => tracing::debug!("No socket found for {} -> {}", local, remote),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SocketLayer(SocketOptions);
+
+impl<S> Layer<S> for SocketLayer {
+    type Service = Tuned<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Tuned {
+            inner,
+            options: self.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Tuned<S> {
+    inner: S,
+    options: SocketOptions,
+}
+
+impl<S, R> Service<R> for Tuned<S>
+where
+    S: Service<R>,
+    S::Response: Connection + Send + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let options = self.options;
+        let connecting = self.inner.call(req);
+        Box::pin(async move {
+            let conn = connecting.await?;
+            options.tune(&conn.connected());
+            Ok(conn)
+        })
+    }
+}
+
+// reqwest never hands out its TCP streams, so the connection is matched back
+// to its descriptor by address pair; that pair is unique while `conn` is alive.
+#[cfg(target_os = "linux")]
+fn find_socket<T>(
+    local: SocketAddr,
+    remote: SocketAddr,
+    f: impl FnOnce(SockRef<'_>) -> T,
+) -> Option<T> {
+    use std::os::fd::{BorrowedFd, RawFd};
+
+    let fds = std::fs::read_dir("/proc/self/fd").ok()?;
+    let fd = fds
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<RawFd>().ok())
+        .find(|&fd| {
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            let socket = SockRef::from(&fd);
+            matches!(
+                (socket.local_addr(), socket.peer_addr()),
+                (Ok(l), Ok(p)) if l.as_socket() == Some(local) && p.as_socket() == Some(remote)
+            )
+        })?;
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    Some(f(SockRef::from(&fd)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn find_socket<T>(
+    _local: SocketAddr,
+    _remote: SocketAddr,
+    _f: impl FnOnce(SockRef<'_>) -> T,
+) -> Option<T> {
+    None
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_notsent_lowat(socket: &SockRef<'_>, lowat: u32) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let value = lowat as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_NOTSENT_LOWAT,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_notsent_lowat(_socket: &SockRef<'_>, _lowat: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP_NOTSENT_LOWAT is not supported on this platform",
+    ))
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+))]
+fn set_tclass(socket: &SockRef<'_>, tos: u8) -> io::Result<()> {
+    socket.set_tclass_v6(tos as u32)
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+)))]
+fn set_tclass(_socket: &SockRef<'_>, _tos: u8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "IPv6 traffic class is not supported on this platform",
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_tune_connected_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _accepted = listener.accept().unwrap();
+        let options = SocketOptions {
+            recv_buffer: Some(256 * 1024),
+            notsent_lowat: Some(16 * 1024),
+            ..Default::default()
+        }
+        .with_dscp(46);
+
+        let local = stream.local_addr().unwrap();
+        let remote = stream.peer_addr().unwrap();
+        assert!(matches!(
+            find_socket(local, remote, |socket| options.apply_tcp(socket)),
+            Some(Ok(()))
+        ));
+
+        let socket = SockRef::from(&stream);
+        assert!(socket.recv_buffer_size().unwrap() >= 256 * 1024);
+        assert_eq!(socket.tos_v4().unwrap(), 46 << 2);
+        assert!(find_socket(remote, local, |_| ()).is_some());
+        assert!(find_socket(local, local, |_| ()).is_none());
+    }
+}
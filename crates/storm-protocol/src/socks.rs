@@ -0,0 +1,394 @@
+use crate::SocketOptions;
+use quinn::udp::{RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, UdpPoller};
+use socket2::SockRef;
+use std::fmt;
+use std::io::{self, IoSliceMut};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use stormdl_core::StormError;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpStream, UdpSocket};
+use url::Url;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASS: u8 = 2;
+const UDP_ASSOCIATE: u8 = 3;
+const ATYP_V4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_V6: u8 = 4;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// QUIC through a SOCKS5 UDP ASSOCIATE relay (RFC 1928, section 7). Each
+// datagram is prefixed with the destination, and the relay only lives as
+// long as the TCP control connection, so the socket keeps that open.
+pub(crate) struct SocksUdpSocket {
+    io: UdpSocket,
+    relay: SocketAddr,
+    header: Vec<u8>,
+    _control: TcpStream,
+}
+
+impl SocksUdpSocket {
+    pub(crate) async fn associate(
+        proxy: &Url,
+        host: &str,
+        port: u16,
+        socket: SocketOptions,
+    ) -> Result<Self, StormError> {
+        tokio::time::timeout(
+            HANDSHAKE_TIMEOUT,
+            Self::handshake(proxy, host, port, socket),
+        )
+        .await
+        .map_err(|_| StormError::Timeout("SOCKS5 UDP ASSOCIATE".into()))?
+    }
+
+    async fn handshake(
+        proxy: &Url,
+        host: &str,
+        port: u16,
+        socket: SocketOptions,
+    ) -> Result<Self, StormError> {
+        let proxy_host = proxy
+            .host_str()
+            .ok_or_else(|| StormError::Config("proxy URL has no host".into()))?;
+        let proxy_port = proxy.port().unwrap_or(1080);
+        let mut control = TcpStream::connect((proxy_host.trim_matches(['[', ']']), proxy_port))
+            .await
+            .map_err(|e| socks_error(format!("connect to proxy: {}", e)))?;
+        let proxy_addr = control.peer_addr().map_err(io_error)?;
+
+        authenticate(&mut control, proxy).await?;
+        // The client's own address isn't known before the relay answers, so
+        // it's sent as 0.0.0.0:0, which RFC 1928 allows.
+        control
+            .write_all(&[VERSION, UDP_ASSOCIATE, 0, ATYP_V4, 0, 0, 0, 0, 0, 0])
+            .await
+            .map_err(io_error)?;
+        let relay = read_reply(&mut control).await?;
+        let relay = if relay.ip().is_unspecified() {
+            SocketAddr::new(proxy_addr.ip(), relay.port())
+        } else {
+            relay
+        };
+
+        let bind: SocketAddr = if relay.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let udp = std::net::UdpSocket::bind(bind).map_err(io_error)?;
+        socket.apply_udp(SockRef::from(&udp)).map_err(io_error)?;
+        udp.set_nonblocking(true).map_err(io_error)?;
+        let io = UdpSocket::from_std(udp).map_err(io_error)?;
+
+        // socks5h leaves name resolution to the proxy, as on the TCP path.
+        let host = host.trim_matches(['[', ']']);
+        let header = match host.parse::<IpAddr>() {
+            Ok(ip) => destination(&ip.to_string(), Some(ip), port),
+            Err(_) if proxy.scheme() == "socks5h" => destination(host, None, port),
+            Err(_) => {
+                let addr = tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|e| StormError::Network(format!("DNS resolution failed: {}", e)))?
+                    .next()
+                    .ok_or_else(|| StormError::Network("No addresses found for host".into()))?;
+                destination(host, Some(addr.ip()), port)
+            }
+        }?;
+
+        Ok(Self {
+            io,
+            relay,
+            header,
+            _control: control,
+        })
+    }
+
+    // The address QUIC is pointed at; the real destination travels in the
+    // relay header.
+    pub(crate) fn relay(&self) -> SocketAddr {
+        self.relay
+    }
+}
+
+async fn authenticate(control: &mut TcpStream, proxy: &Url) -> Result<(), StormError> {
+    let user = urlencoding::decode(proxy.username())
+        .map_err(|e| StormError::Config(format!("proxy username: {}", e)))?;
+    let password = urlencoding::decode(proxy.password().unwrap_or(""))
+        .map_err(|e| StormError::Config(format!("proxy password: {}", e)))?;
+    let methods: &[u8] = if user.is_empty() {
+        &[NO_AUTH]
+    } else {
+        &[NO_AUTH, USER_PASS]
+    };
+
+    let mut hello = vec![VERSION, methods.len() as u8];
+    hello.extend_from_slice(methods);
+    control.write_all(&hello).await.map_err(io_error)?;
+    let mut chosen = [0u8; 2];
+    control.read_exact(&mut chosen).await.map_err(io_error)?;
+
+    match chosen {
+        [VERSION, NO_AUTH] => Ok(()),
+        [VERSION, USER_PASS] if !user.is_empty() => {
+            if user.len() > 255 || password.len() > 255 {
+                return Err(StormError::Config("proxy credentials too long".into()));
+            }
+            let mut login = vec![1, user.len() as u8];
+            login.extend_from_slice(user.as_bytes());
+            login.push(password.len() as u8);
+            login.extend_from_slice(password.as_bytes());
+            control.write_all(&login).await.map_err(io_error)?;
+            let mut status = [0u8; 2];
+            control.read_exact(&mut status).await.map_err(io_error)?;
+            if status[1] != 0 {
+                return Err(socks_error("proxy rejected the credentials".into()));
+            }
+            Ok(())
+        }
+        [VERSION, _] => Err(socks_error("no acceptable authentication method".into())),
+        _ => Err(socks_error("not a SOCKS5 proxy".into())),
+    }
+}
+
+async fn read_reply(control: &mut TcpStream) -> Result<SocketAddr, StormError> {
+    let mut head = [0u8; 4];
+    control.read_exact(&mut head).await.map_err(io_error)?;
+    if head[0] != VERSION {
+        return Err(socks_error("not a SOCKS5 proxy".into()));
+    }
+    if head[1] != 0 {
+        return Err(socks_error(format!(
+            "proxy can't relay UDP: {}",
+            reply_reason(head[1])
+        )));
+    }
+
+    let ip = match head[3] {
+        ATYP_V4 => {
+            let mut octets = [0u8; 4];
+            control.read_exact(&mut octets).await.map_err(io_error)?;
+            IpAddr::from(octets)
+        }
+        ATYP_V6 => {
+            let mut octets = [0u8; 16];
+            control.read_exact(&mut octets).await.map_err(io_error)?;
+            IpAddr::from(octets)
+        }
+        atyp => {
+            return Err(socks_error(format!(
+                "unsupported relay address type {}",
+                atyp
+            )));
+        }
+    };
+    let port = control.read_u16().await.map_err(io_error)?;
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn destination(host: &str, ip: Option<IpAddr>, port: u16) -> Result<Vec<u8>, StormError> {
+    let mut header = vec![0, 0, 0];
+    match ip {
+        Some(IpAddr::V4(ip)) => {
+            header.push(ATYP_V4);
+            header.extend_from_slice(&ip.octets());
+        }
+        Some(IpAddr::V6(ip)) => {
+            header.push(ATYP_V6);
+            header.extend_from_slice(&ip.octets());
+        }
+        None => {
+            if host.len() > 255 {
+                return Err(StormError::InvalidUrl(format!(
+                    "host name too long: {}",
+                    host
+                )));
+            }
+            header.push(ATYP_DOMAIN);
+            header.push(host.len() as u8);
+            header.extend_from_slice(host.as_bytes());
+        }
+    }
+    header.extend_from_slice(&port.to_be_bytes());
+    Ok(header)
+}
+
+// Length of the relay header on an incoming datagram; fragments aren't
+// supported and are dropped like any other unparseable packet.
+fn header_len(datagram: &[u8]) -> Option<usize> {
+    let len = match datagram.get(..4)? {
+        [0, 0, 0, ATYP_V4] => 10,
+        [0, 0, 0, ATYP_V6] => 22,
+        [0, 0, 0, ATYP_DOMAIN] => 7 + *datagram.get(4)? as usize,
+        _ => return None,
+    };
+    (datagram.len() >= len).then_some(len)
+}
+
+fn reply_reason(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "UDP ASSOCIATE not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn socks_error(message: String) -> StormError {
+    StormError::Network(format!("SOCKS5: {}", message))
+}
+
+// Relay failures should read as transport errors so negotiation falls back
+// to HTTP/2 instead of giving up.
+fn io_error(e: io::Error) -> StormError {
+    socks_error(e.to_string())
+}
+
+impl fmt::Debug for SocksUdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocksUdpSocket")
+            .field("relay", &self.relay)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncUdpSocket for SocksUdpSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        Box::pin(Writable(self))
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        let mut datagram = Vec::with_capacity(self.header.len() + transmit.contents.len());
+        datagram.extend_from_slice(&self.header);
+        datagram.extend_from_slice(transmit.contents);
+        self.io.try_send_to(&datagram, self.relay).map(|_| ())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let (Some(buf), Some(meta)) = (bufs.first_mut(), meta.first_mut()) else {
+            return Poll::Ready(Ok(0));
+        };
+        loop {
+            let mut read = ReadBuf::new(&mut buf[..]);
+            let from = ready!(self.io.poll_recv_from(cx, &mut read))?;
+            let len = read.filled().len();
+            let Some(skip) = header_len(&buf[..len]).filter(|_| from == self.relay) else {
+                continue;
+            };
+            buf.copy_within(skip..len, 0);
+            *meta = RecvMeta {
+                addr: self.relay,
+                len: len - skip,
+                stride: len - skip,
+                ecn: None,
+                dst_ip: None,
+            };
+            return Poll::Ready(Ok(1));
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.local_addr()
+    }
+}
+
+#[derive(Debug)]
+struct Writable(Arc<SocksUdpSocket>);
+
+impl UdpPoller for Writable {
+    fn poll_writable(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.0.io.poll_send_ready(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_udp_associate_relays_datagrams() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let proxy = Url::parse(&format!(
+            "socks5h://storm-a:storm@{}",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let relay_port = relay.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut control, _) = listener.accept().await.unwrap();
+            let mut hello = [0u8; 4];
+            control.read_exact(&mut hello).await.unwrap();
+            assert_eq!(hello, [VERSION, 2, NO_AUTH, USER_PASS]);
+            control.write_all(&[VERSION, USER_PASS]).await.unwrap();
+            let mut login = [0u8; 15];
+            control.read_exact(&mut login).await.unwrap();
+            assert_eq!(&login[2..9], b"storm-a");
+            control.write_all(&[1, 0]).await.unwrap();
+            let mut request = [0u8; 10];
+            control.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[1], UDP_ASSOCIATE);
+            let mut reply = vec![VERSION, 0, 0, ATYP_V4, 0, 0, 0, 0];
+            reply.extend_from_slice(&relay_port.to_be_bytes());
+            control.write_all(&reply).await.unwrap();
+            control
+        });
+
+        let socket =
+            SocksUdpSocket::associate(&proxy, "example.com", 443, SocketOptions::default())
+                .await
+                .unwrap();
+        let _control = server.await.unwrap();
+        assert_eq!(socket.relay().port(), relay_port);
+
+        // quinn waits on the poller after WouldBlock; do the same up front.
+        socket.io.writable().await.unwrap();
+        socket
+            .try_send(&Transmit {
+                destination: socket.relay(),
+                ecn: None,
+                contents: b"quic",
+                segment_size: None,
+                src_ip: None,
+            })
+            .unwrap();
+        let mut datagram = [0u8; 64];
+        let (len, client) = relay.recv_from(&mut datagram).await.unwrap();
+        assert_eq!(&datagram[..5], [0, 0, 0, ATYP_DOMAIN, 11]);
+        assert_eq!(&datagram[5..16], b"example.com");
+        assert_eq!(&datagram[16..len], [1, 187, b'q', b'u', b'i', b'c']);
+
+        let mut answer = vec![0, 0, 0, ATYP_V4, 93, 184, 216, 34, 1, 187];
+        answer.extend_from_slice(b"reply");
+        relay.send_to(&answer, client).await.unwrap();
+
+        let mut buf = [0u8; 64];
+        let mut meta = [RecvMeta::default()];
+        let received = std::future::poll_fn(|cx| {
+            socket.poll_recv(cx, &mut [IoSliceMut::new(&mut buf)], &mut meta)
+        })
+        .await
+        .unwrap();
+        assert_eq!(received, 1);
+        assert_eq!(&buf[..meta[0].len], b"reply");
+        assert_eq!(meta[0].addr, socket.relay());
+    }
+}
@@ -0,0 +1,399 @@
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use sha2::{Digest, Sha256};
+use ssh2::{ErrorCode, KeyboardInteractivePrompt, MethodType, Prompt, Session};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+use stormdl_core::StormError;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+// libssh2's session timeout; its error code for running into it.
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
+const HOST_KEY_ALGORITHMS: &[&str] = &[
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "rsa-sha2-512",
+    "rsa-sha2-256",
+];
+
+pub(crate) fn fingerprint(blob: &[u8]) -> String {
+    format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(blob)))
+}
+
+pub(crate) fn ssh_error(e: ssh2::Error) -> StormError {
+    match e.code() {
+        ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT) => StormError::Timeout("SSH".into()),
+        _ => StormError::Protocol(format!("SSH: {}", e.message())),
+    }
+}
+
+// The key type a public key blob starts with.
+fn key_kind(blob: &[u8]) -> Option<&str> {
+    let len = u32::from_be_bytes(blob.get(..4)?.try_into().ok()?) as usize;
+    std::str::from_utf8(blob.get(4..4 + len)?).ok()
+}
+
+// Who to log in as and with what. Without a password, keys from the agent
+// and then the key files are offered.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Login {
+    pub(crate) user: String,
+    pub(crate) password: Option<String>,
+    pub(crate) keys: Vec<PathBuf>,
+}
+
+// The host as known_hosts names it, and the files to look it up in.
+pub(crate) struct KnownHosts {
+    pub(crate) host: String,
+    pub(crate) files: Vec<PathBuf>,
+}
+
+impl KnownHosts {
+    pub(crate) fn new(host: &str, port: u16, files: Vec<PathBuf>) -> Self {
+        let host = if port == 22 {
+            host.to_string()
+        } else {
+            format!("[{}]:{}", host, port)
+        };
+        Self { host, files }
+    }
+
+    pub(crate) fn default_files() -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = dirs::home_dir()
+            .map(|home| home.join(".ssh/known_hosts"))
+            .into_iter()
+            .collect();
+        files.push(PathBuf::from("/etc/ssh/ssh_known_hosts"));
+        files
+    }
+
+    // (revoked, key blob) for every line naming this host.
+    fn keys(&self) -> Vec<(bool, Vec<u8>)> {
+        let mut keys = Vec::new();
+        for file in &self.files {
+            let Ok(text) = std::fs::read_to_string(file) else {
+                continue;
+            };
+            for line in text.lines() {
+                let mut fields = line.split_whitespace();
+                let Some(mut patterns) = fields.next().filter(|f| !f.starts_with('#')) else {
+                    continue;
+                };
+                let mut revoked = false;
+                if patterns.starts_with('@') {
+                    revoked = patterns == "@revoked";
+                    if !revoked {
+                        // @cert-authority lines vouch for certificates,
+                        // which aren't offered.
+                        continue;
+                    }
+                    let Some(next) = fields.next() else {
+                        continue;
+                    };
+                    patterns = next;
+                }
+                let (Some(_), Some(key)) = (fields.next(), fields.next()) else {
+                    continue;
+                };
+                if host_matches(patterns, &self.host)
+                    && let Ok(blob) = STANDARD.decode(key)
+                {
+                    keys.push((revoked, blob));
+                }
+            }
+        }
+        keys
+    }
+
+    // Host key algorithms, those with a known key for this host first, so
+    // the server proves itself with a key we can check.
+    fn algorithms(&self) -> Vec<&'static str> {
+        let known: Vec<String> = self
+            .keys()
+            .iter()
+            .filter(|(revoked, _)| !revoked)
+            .filter_map(|(_, blob)| key_kind(blob).map(String::from))
+            .collect();
+        let mut algorithms: Vec<&'static str> = HOST_KEY_ALGORITHMS
+            .iter()
+            .copied()
+            .filter(|alg| known.iter().any(|kind| key_type(alg) == kind))
+            .collect();
+        for alg in HOST_KEY_ALGORITHMS {
+            if !algorithms.contains(alg) {
+                algorithms.push(alg);
+            }
+        }
+        algorithms
+    }
+
+    fn check(&self, blob: &[u8]) -> Result<(), StormError> {
+        let keys = self.keys();
+        if keys.iter().any(|(revoked, key)| *revoked && key == blob) {
+            return Err(StormError::Config(format!(
+                "the host key of {} ({}) is marked revoked in known_hosts",
+                self.host,
+                fingerprint(blob)
+            )));
+        }
+        if keys.iter().any(|(_, key)| key == blob) {
+            return Ok(());
+        }
+        let kind = key_kind(blob).unwrap_or_default();
+        if keys
+            .iter()
+            .any(|(revoked, key)| !revoked && key_kind(key) == Some(kind))
+        {
+            return Err(StormError::Config(format!(
+                "the {} host key of {} doesn't match known_hosts (now {}); \
+                 it may have been replaced, or the connection intercepted",
+                kind,
+                self.host,
+                fingerprint(blob)
+            )));
+        }
+        let (host, port) = match self.host.strip_prefix('[') {
+            Some(rest) => rest.split_once("]:").unwrap_or((rest, "22")),
+            None => (self.host.as_str(), "22"),
+        };
+        Err(StormError::Config(format!(
+            "{} isn't in known_hosts (host key {}); connect once with ssh, or add it with \
+             `ssh-keyscan -p {} {} >> ~/.ssh/known_hosts`",
+            self.host,
+            fingerprint(blob),
+            port,
+            host
+        )))
+    }
+}
+
+// A known_hosts pattern list: comma-separated globs, `!` negating, or a
+// hashed `|1|salt|hash` entry.
+fn host_matches(patterns: &str, host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split(',') {
+        if let Some(hashed) = pattern.strip_prefix("|1|") {
+            let Some((salt, hash)) = hashed.split_once('|') else {
+                continue;
+            };
+            let (Ok(salt), Ok(hash)) = (STANDARD.decode(salt), STANDARD.decode(hash)) else {
+                continue;
+            };
+            let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &salt);
+            if ring::hmac::verify(&key, host.as_bytes(), &hash).is_ok() {
+                matched = true;
+            }
+        } else if let Some(negated) = pattern.strip_prefix('!') {
+            if glob(&negated.to_ascii_lowercase(), &host.to_ascii_lowercase()) {
+                return false;
+            }
+        } else if glob(&pattern.to_ascii_lowercase(), &host.to_ascii_lowercase()) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+fn glob(pattern: &str, text: &str) -> bool {
+    match pattern.as_bytes().first() {
+        None => text.is_empty(),
+        Some(b'*') => (0..=text.len())
+            .filter(|&i| text.is_char_boundary(i))
+            .any(|i| glob(&pattern[1..], &text[i..])),
+        Some(b'?') => {
+            let mut chars = text.chars();
+            chars.next().is_some() && glob(&pattern[1..], chars.as_str())
+        }
+        Some(_) => {
+            let mut p = pattern.chars();
+            let mut t = text.chars();
+            p.next() == t.next() && glob(p.as_str(), t.as_str())
+        }
+    }
+}
+
+fn key_type(algorithm: &str) -> &str {
+    match algorithm {
+        "rsa-sha2-256" | "rsa-sha2-512" => "ssh-rsa",
+        alg => alg,
+    }
+}
+
+// Answers every keyboard-interactive prompt with the password (RFC 4256).
+struct PasswordPrompt<'a>(&'a str);
+
+impl KeyboardInteractivePrompt for PasswordPrompt<'_> {
+    fn prompt<'a>(
+        &mut self,
+        _username: &str,
+        _instructions: &str,
+        prompts: &[Prompt<'a>],
+    ) -> Vec<String> {
+        vec![self.0.to_string(); prompts.len()]
+    }
+}
+
+// Connects, checks the host against known_hosts before anything is sent,
+// and logs in. Blocking; callers run it off the async threads.
+pub(crate) fn connect(
+    host: &str,
+    port: u16,
+    login: &Login,
+    known_hosts: Vec<PathBuf>,
+) -> Result<Session, StormError> {
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| StormError::Network(format!("{} has no address", host)))?;
+    let stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|e| match e.kind() {
+        std::io::ErrorKind::TimedOut => StormError::Timeout("SSH connect".into()),
+        _ => StormError::Network(e.to_string()),
+    })?;
+    stream.set_nodelay(true)?;
+
+    let known_hosts = KnownHosts::new(host, port, known_hosts);
+    let mut session = Session::new().map_err(ssh_error)?;
+    session.set_timeout(TIMEOUT.as_millis() as u32);
+    session
+        .method_pref(MethodType::HostKey, &known_hosts.algorithms().join(","))
+        .map_err(ssh_error)?;
+    session.set_tcp_stream(stream);
+    session.handshake().map_err(ssh_error)?;
+    if let Some(banner) = session.banner() {
+        tracing::debug!("SSH server: {}", banner);
+    }
+    let (blob, _) = session
+        .host_key()
+        .ok_or_else(|| StormError::Protocol("SSH: the server sent no host key".into()))?;
+    known_hosts.check(blob)?;
+
+    authenticate(&session, login)?;
+    Ok(session)
+}
+
+// A password first (also through keyboard-interactive, which PAM setups
+// often require), then keys from ssh-agent, then the key files.
+fn authenticate(session: &Session, login: &Login) -> Result<(), StormError> {
+    let user = login.user.as_str();
+    // Asking for the methods is itself a "none" login, which some servers
+    // accept.
+    let methods: Vec<String> = session
+        .auth_methods(user)
+        .map(|methods| methods.split(',').map(String::from).collect())
+        .unwrap_or_default();
+    let permits = |method: &str| methods.iter().any(|m| m == method);
+    if session.authenticated() {
+        return Ok(());
+    }
+
+    if let Some(password) = &login.password {
+        tracing::debug!("SSH: trying password for {}", user);
+        if permits("password") && session.userauth_password(user, password).is_ok() {
+            return Ok(());
+        }
+        if permits("keyboard-interactive")
+            && session
+                .userauth_keyboard_interactive(user, &mut PasswordPrompt(password))
+                .is_ok()
+        {
+            return Ok(());
+        }
+    }
+    if permits("publickey") {
+        match session.userauth_agent(user) {
+            Ok(()) => return Ok(()),
+            Err(e) => tracing::debug!("SSH: ssh-agent: {}", e.message()),
+        }
+        for path in login.keys.iter().filter(|path| path.exists()) {
+            tracing::debug!("SSH: trying key {}", path.display());
+            match session.userauth_pubkey_file(user, None, path, None) {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::debug!("Skipping SSH key {}: {}", path.display(), e.message()),
+            }
+        }
+    }
+
+    Err(StormError::Config(format!(
+        "SSH login refused for {}{}",
+        user,
+        if methods.is_empty() {
+            String::new()
+        } else {
+            format!(" (the server accepts {})", methods.join(", "))
+        }
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An SSH wire-format key blob: each field length-prefixed.
+    fn blob(fields: &[&[u8]]) -> Vec<u8> {
+        let mut blob = Vec::new();
+        for field in fields {
+            blob.extend((field.len() as u32).to_be_bytes());
+            blob.extend(*field);
+        }
+        blob
+    }
+
+    #[test]
+    fn test_known_hosts() {
+        let dir = std::env::temp_dir().join(format!("storm-ssh-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = blob(&[b"ssh-ed25519", &[7u8; 32]]);
+        let other = blob(&[b"ssh-ed25519", &[8u8; 32]]);
+        let rsa = blob(&[b"ssh-rsa", &[1], &[9u8; 64]]);
+
+        // `|1|...` is build.example.com hashed with an all-zero salt.
+        let salt = [0u8; 20];
+        let hmac = ring::hmac::sign(
+            &ring::hmac::Key::new(ring::hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &salt),
+            b"build.example.com",
+        );
+        let known = dir.join("known_hosts");
+        std::fs::write(
+            &known,
+            format!(
+                "# comment\n\
+                 |1|{}|{} ssh-rsa {}\n\
+                 *.example.org,!evil.example.org ssh-ed25519 {}\n\
+                 [files.example.net]:2222 ssh-ed25519 {}\n\
+                 @revoked * ssh-ed25519 {}\n",
+                STANDARD.encode(salt),
+                STANDARD.encode(hmac.as_ref()),
+                STANDARD.encode(&rsa),
+                STANDARD.encode(&key),
+                STANDARD.encode(&key),
+                STANDARD.encode(&other),
+            ),
+        )
+        .unwrap();
+
+        let hosts = |host: &str, port| KnownHosts::new(host, port, vec![known.clone()]);
+        assert!(hosts("ci.example.org", 22).check(&key).is_ok());
+        assert!(hosts("evil.example.org", 22).check(&key).is_err());
+        assert!(hosts("files.example.net", 2222).check(&key).is_ok());
+        assert!(hosts("files.example.net", 22).check(&key).is_err());
+        assert!(hosts("build.example.com", 22).check(&rsa).is_ok());
+        let revoked = hosts("ci.example.org", 22).check(&other).unwrap_err();
+        assert!(revoked.to_string().contains("revoked"));
+        let changed = hosts("build.example.com", 22).check(&blob(&[b"ssh-rsa", &[1], &[3u8; 64]]));
+        assert!(changed.unwrap_err().to_string().contains("doesn't match"));
+        let unknown = hosts("new.example.com", 2200).check(&key).unwrap_err();
+        assert!(
+            unknown
+                .to_string()
+                .contains("ssh-keyscan -p 2200 new.example.com")
+        );
+
+        // Algorithms with a known key for the host go first.
+        assert_eq!(
+            hosts("build.example.com", 22).algorithms()[..2],
+            ["rsa-sha2-512", "rsa-sha2-256"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use bytes::Bytes;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use url::Url;
+
+// A local HTTP/1.1 server for examples and tests: serves `body` at any
+// path until the runtime shuts down, answering Range requests with 206.
+pub async fn test_server(body: impl Into<Bytes>) -> std::io::Result<Url> {
+    let body = body.into();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let url = Url::parse(&format!("http://{}/file.bin", listener.local_addr()?))
+        .map_err(std::io::Error::other)?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(respond(stream, body.clone()));
+        }
+    });
+    Ok(url)
+}
+
+async fn respond(mut stream: TcpStream, body: Bytes) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let range = request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        let (start, end) = name
+            .eq_ignore_ascii_case("range")
+            .then(|| value.trim().strip_prefix("bytes="))??
+            .split_once('-')?;
+        let start: usize = start.parse().ok()?;
+        let end = end.parse().map_or(body.len(), |end: usize| end + 1);
+        (start < end && end <= body.len()).then_some((start, end))
+    });
+    let (status, extra, part) = match range {
+        Some((start, end)) => (
+            "206 Partial Content",
+            format!(
+                "Content-Range: bytes {}-{}/{}\r\n",
+                start,
+                end - 1,
+                body.len()
+            ),
+            body.slice(start..end),
+        ),
+        None => ("200 OK", String::new(), body),
+    };
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n{}Connection: close\r\n\r\n",
+        status,
+        part.len(),
+        extra
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&part).await?;
+    stream.shutdown().await
+}
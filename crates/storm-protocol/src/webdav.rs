@@ -0,0 +1,243 @@
+use stormdl_core::StormError;
+use url::Url;
+
+// What a Depth: 0 PROPFIND says about one resource.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Properties {
+    pub size: Option<u64>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: Option<String>,
+    pub display_name: Option<String>,
+    pub collection: bool,
+}
+
+pub(crate) fn handles(url: &Url) -> bool {
+    matches!(url.scheme(), "dav" | "davs" | "webdav" | "webdavs")
+}
+
+// `dav://` and `webdav://` are WebDAV over http, `davs://` and `webdavs://`
+// over https; other URLs are left alone.
+pub(crate) fn http_url(url: &Url) -> Result<Option<Url>, StormError> {
+    let scheme = match url.scheme() {
+        "dav" | "webdav" => "http",
+        "davs" | "webdavs" => "https",
+        _ => return Ok(None),
+    };
+    // The url crate won't switch a URL between special and other schemes,
+    // so the rest of it is parsed again.
+    let rest = &url.as_str()[url.scheme().len()..];
+    Url::parse(&format!("{}{}", scheme, rest))
+        .map(Some)
+        .map_err(|e| StormError::InvalidUrl(format!("{}: {}", url, e)))
+}
+
+// A Nextcloud or ownCloud public share link, `https://host/s/TOKEN` (or
+// `/index.php/s/TOKEN`, `.../download?path=/dir&files=name` and
+// `.../download/name` for a file in a shared folder), as the public WebDAV
+// URL behind it. The token is the login and a password in the link is the
+// share's password.
+pub(crate) fn share_link(url: &Url) -> Option<Url> {
+    let scheme = match url.scheme() {
+        "http" => "dav",
+        "https" => "davs",
+        _ => return None,
+    };
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    let at = segments.iter().position(|s| *s == "s")?;
+    let token = segments
+        .get(at + 1)
+        .filter(|t| !t.is_empty() && t.bytes().all(|b| b.is_ascii_alphanumeric()))?;
+    let mut prefix = &segments[..at];
+    if prefix.last() == Some(&"index.php") {
+        prefix = &prefix[..prefix.len() - 1];
+    }
+
+    let mut path: Vec<String> = Vec::new();
+    match &segments[at + 2..] {
+        [] | [""] => {}
+        ["download", rest @ ..] => {
+            let query = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            let dir = query("path").unwrap_or_default();
+            path.extend(dir.split('/').filter(|s| !s.is_empty()).map(String::from));
+            match query("files") {
+                // Several files come back as a zip, which has no WebDAV URL.
+                Some(files) if files.starts_with('[') || files.contains(';') => return None,
+                Some(file) => path.push(file),
+                None => {}
+            }
+            for segment in rest.iter().filter(|s| !s.is_empty()) {
+                path.push(urlencoding::decode(segment).ok()?.into_owned());
+            }
+        }
+        _ => return None,
+    }
+
+    let mut dav = Url::parse(&format!("{}://{}/", scheme, url.host_str()?)).ok()?;
+    dav.set_port(url.port()).ok()?;
+    dav.set_username(token).ok()?;
+    dav.set_password(url.password()).ok()?;
+    {
+        let mut segments = dav.path_segments_mut().ok()?;
+        segments.pop_if_empty();
+        segments.extend(prefix.iter().filter(|s| !s.is_empty()));
+        segments.extend(["public.php", "webdav"]);
+        segments.extend(&path);
+        if path.is_empty() {
+            segments.push("");
+        }
+    }
+    Some(dav)
+}
+
+// Reads the first response of a 207 Multi-Status body. Property names are
+// matched whatever their namespace prefix.
+pub(crate) fn parse_multistatus(xml: &str) -> Result<Properties, StormError> {
+    let invalid = || StormError::Protocol("malformed WebDAV multistatus response".into());
+    let mut props = Properties::default();
+    let mut multistatus = false;
+    let mut responses = 0;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>').ok_or_else(invalid)?;
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        let name = tag
+            .trim_end_matches('/')
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        let local = name.rsplit(':').next().unwrap_or(name);
+        let text = if tag.ends_with('/') {
+            String::new()
+        } else {
+            unescape(rest[..rest.find('<').unwrap_or(rest.len())].trim())
+        };
+        let value = || Some(text.clone()).filter(|t| !t.is_empty());
+        match local {
+            "multistatus" => multistatus = true,
+            "response" => {
+                responses += 1;
+                if responses > 1 {
+                    break;
+                }
+            }
+            "getcontentlength" => props.size = Some(text.parse().map_err(|_| invalid())?),
+            "getetag" => props.etag = value(),
+            "getlastmodified" => props.last_modified = value(),
+            "getcontenttype" => props.content_type = value(),
+            "displayname" => props.display_name = value(),
+            "collection" => props.collection = true,
+            _ => {}
+        }
+    }
+    if !multistatus || responses == 0 {
+        return Err(invalid());
+    }
+    Ok(props)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            http_url(&url("davs://alice@dav.example.com:8443/files/a%20b.iso")).unwrap(),
+            Some(url("https://alice@dav.example.com:8443/files/a%20b.iso"))
+        );
+        assert_eq!(
+            http_url(&url("webdav://nas.local/share/x.bin")).unwrap(),
+            Some(url("http://nas.local/share/x.bin"))
+        );
+        assert_eq!(http_url(&url("https://example.com/a")).unwrap(), None);
+
+        let share = |s: &str| share_link(&url(s)).map(String::from);
+        assert_eq!(
+            share("https://cloud.example.com/s/AbC123xyz").as_deref(),
+            Some("davs://AbC123xyz@cloud.example.com/public.php/webdav/")
+        );
+        assert_eq!(
+            share("https://:pw@cloud.example.com/nc/index.php/s/AbC123xyz/download").as_deref(),
+            Some("davs://AbC123xyz:pw@cloud.example.com/nc/public.php/webdav/")
+        );
+        assert_eq!(
+            share("http://cloud.local:8080/s/AbC/download?path=%2Fisos&files=a%20b.iso").as_deref(),
+            Some("dav://AbC@cloud.local:8080/public.php/webdav/isos/a%20b.iso")
+        );
+        assert_eq!(
+            share("https://cloud.example.com/s/AbC/download/x.tar").as_deref(),
+            Some("davs://AbC@cloud.example.com/public.php/webdav/x.tar")
+        );
+        assert_eq!(share("https://example.com/s/"), None);
+        assert_eq!(share("https://example.com/s/a-b"), None);
+        assert_eq!(share("https://example.com/s/AbC/edit"), None);
+        assert_eq!(share("https://example.com/docs/x.pdf"), None);
+        assert_eq!(
+            share("https://cloud.example.com/s/AbC/download?files=[\"a\",\"b\"]"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_multistatus() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns">
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/a.iso</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:getcontentlength>3145728</d:getcontentlength>
+        <d:getetag>&quot;5f1a&quot;</d:getetag>
+        <d:getlastmodified>Fri, 01 May 2026 12:00:00 GMT</d:getlastmodified>
+        <d:getcontenttype>application/x-iso9660-image</d:getcontenttype>
+        <d:resourcetype/>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+        assert_eq!(
+            parse_multistatus(xml).unwrap(),
+            Properties {
+                size: Some(3145728),
+                etag: Some("\"5f1a\"".into()),
+                last_modified: Some("Fri, 01 May 2026 12:00:00 GMT".into()),
+                content_type: Some("application/x-iso9660-image".into()),
+                display_name: None,
+                collection: false,
+            }
+        );
+
+        let folder = r#"<multistatus xmlns="DAV:"><response><href>/d/</href>
+<propstat><prop><displayname>d</displayname><resourcetype><collection/></resourcetype>
+</prop></propstat></response></multistatus>"#;
+        let props = parse_multistatus(folder).unwrap();
+        assert!(props.collection);
+        assert_eq!(props.display_name.as_deref(), Some("d"));
+
+        assert!(parse_multistatus("<html><body>hi</body></html>").is_err());
+        assert!(parse_multistatus("<d:multistatus xmlns:d=\"DAV:\">").is_err());
+    }
+}
@@ -42,9 +42,22 @@ impl AdaptiveController {
         self.current_segments.load(Ordering::Relaxed)
     }
 
-    pub fn evaluate(&self, bdp: Option<u64>, _current_speed: f64) -> Option<SegmentAdjustment> {
+    pub fn evaluate(&self, bdp: Option<u64>, current_speed: f64) -> Option<SegmentAdjustment> {
+        self.evaluate_at(Instant::now(), bdp, current_speed)
+    }
+
+    pub fn reset_clock(&self, now: Instant) {
+        *self.last_adjustment.lock() = now;
+    }
+
+    pub fn evaluate_at(
+        &self,
+        now: Instant,
+        bdp: Option<u64>,
+        _current_speed: f64,
+    ) -> Option<SegmentAdjustment> {
         let mut last = self.last_adjustment.lock();
-        if last.elapsed() < self.adjustment_interval {
+        if now.saturating_duration_since(*last) < self.adjustment_interval {
             return None;
         }
 
@@ -67,7 +80,7 @@ impl AdaptiveController {
             return None;
         }
 
-        *last = Instant::now();
+        *last = now;
         self.current_segments
             .store(current + segments_to_add, Ordering::Relaxed);
 
@@ -2,12 +2,17 @@ mod controller;
 mod manager;
 mod multi_source;
 mod rebalancer;
+mod simulator;
 mod splitter;
+mod work;
 
 pub use controller::{AdaptiveController, AdjustmentReason, SegmentAdjustment};
 pub use manager::SegmentManager;
 pub use multi_source::MultiSourceManager;
 pub use rebalancer::Rebalancer;
+pub use simulator::{SimConfig, SimReport, Simulator, SpeedTrace};
 pub use splitter::{
-    SplitStrategy, initial_segments, optimal_segments, split_range, turbo_segments,
+    SplitHint, SplitStrategy, initial_segments, optimal_segments, split_range,
+    split_range_with_hint, turbo_segments,
 };
+pub use work::{RangeClaim, SegmentTracker, WorkQueue};
@@ -1,13 +1,34 @@
-use crate::splitter::{initial_segments, split_range};
+use crate::splitter::{SplitHint, initial_segments, split_range, split_range_with_hint};
 use parking_lot::RwLock;
 use std::sync::Arc;
 use stormdl_core::{ByteRange, SegmentState, SegmentStatus};
 
+/// Tracks the byte ranges a download is split into while they're fetched,
+/// and splits slow ones so idle workers can take over their tails.
+///
+/// ```
+/// use stormdl_segment::SegmentManager;
+///
+/// let manager = SegmentManager::with_segments(1_000_000, 4);
+/// let segments = manager.get_segments();
+/// assert_eq!(segments.len(), 4);
+/// assert_eq!(segments[0].range.start, 0);
+/// assert_eq!(segments[3].range.end, 1_000_000);
+///
+/// for segment in &segments {
+///     manager.mark_active(segment.id);
+///     manager.update_segment(segment.id, segment.range.len(), 1e6);
+///     manager.mark_complete(segment.id);
+/// }
+/// assert!(manager.all_complete());
+/// assert_eq!(manager.total_downloaded(), 1_000_000);
+/// ```
 pub struct SegmentManager {
     segments: Arc<RwLock<Vec<SegmentState>>>,
     total_size: u64,
     min_segment_size: u64,
     max_segments: usize,
+    hint: Option<SplitHint>,
 }
 
 impl SegmentManager {
@@ -17,6 +38,7 @@ impl SegmentManager {
             total_size,
             min_segment_size: 256 * 1024,
             max_segments: 32,
+            hint: None,
         }
     }
 
@@ -26,6 +48,7 @@ impl SegmentManager {
             total_size,
             min_segment_size,
             max_segments,
+            hint: None,
         }
     }
 
@@ -41,6 +64,19 @@ impl SegmentManager {
         manager
     }
 
+    pub fn with_hint(total_size: u64, num_segments: usize, hint: SplitHint) -> Self {
+        let mut manager = Self::new(total_size);
+        manager.min_segment_size = hint.min_range;
+        manager.hint = Some(hint);
+        let segments: Vec<SegmentState> = split_range_with_hint(total_size, num_segments, hint)
+            .into_iter()
+            .enumerate()
+            .map(|(id, range)| SegmentState::new(id, range))
+            .collect();
+        *manager.segments.write() = segments;
+        manager
+    }
+
     pub fn initialize(&self) -> Vec<SegmentState> {
         let num_segments = initial_segments(self.total_size);
         let ranges = split_range(self.total_size, num_segments);
@@ -67,6 +103,13 @@ impl SegmentManager {
         }
     }
 
+    pub fn mark_active(&self, id: usize) {
+        let mut segments = self.segments.write();
+        if let Some(segment) = segments.get_mut(id) {
+            segment.status = SegmentStatus::Active;
+        }
+    }
+
     pub fn mark_complete(&self, id: usize) {
         let mut segments = self.segments.write();
         if let Some(segment) = segments.get_mut(id) {
@@ -83,6 +126,14 @@ impl SegmentManager {
     }
 
     pub fn split_segment(&self, id: usize) -> Option<SegmentState> {
+        self.split_segment_with(id, self.hint)
+    }
+
+    pub fn steal(&self, id: usize, hint: SplitHint) -> Option<SegmentState> {
+        self.split_segment_with(id, Some(hint))
+    }
+
+    fn split_segment_with(&self, id: usize, hint: Option<SplitHint>) -> Option<SegmentState> {
         let mut segments = self.segments.write();
 
         if segments.len() >= self.max_segments {
@@ -97,7 +148,10 @@ impl SegmentManager {
         }
 
         let current_offset = segment.range.start + segment.downloaded;
-        let split_point = current_offset + remaining / 2;
+        let split_point = match hint {
+            Some(hint) => hint.split_point(current_offset, segment.range.end)?,
+            None => current_offset + remaining / 2,
+        };
 
         let new_id = segments.len();
         let new_range = ByteRange::new(split_point, segment.range.end);
@@ -110,6 +164,33 @@ impl SegmentManager {
         Some(new_segment)
     }
 
+    pub fn merge_segment(&self, id: usize) -> bool {
+        let mut segments = self.segments.write();
+        let Some(segment) = segments.get(id).cloned() else {
+            return false;
+        };
+        if segment.status != SegmentStatus::Pending
+            || segment.downloaded > 0
+            || segment.range.is_empty()
+        {
+            return false;
+        }
+
+        let Some(prev) = segments.iter_mut().find(|s| {
+            s.range.end == segment.range.start
+                && !s.range.is_empty()
+                && s.status != SegmentStatus::Complete
+        }) else {
+            return false;
+        };
+        prev.range.end = segment.range.end;
+
+        let merged = &mut segments[id];
+        merged.range = ByteRange::new(segment.range.end, segment.range.end);
+        merged.status = SegmentStatus::Complete;
+        true
+    }
+
     pub fn total_downloaded(&self) -> u64 {
         self.segments.read().iter().map(|s| s.downloaded).sum()
     }
@@ -135,3 +216,85 @@ impl SegmentManager {
         active.iter().map(|s| s.speed).sum::<f64>() / active.len() as f64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Progress(usize, u64),
+        Split(usize),
+        Steal(usize, u64, u64),
+        Merge(usize),
+        Complete(usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..64usize, 0..u64::MAX).prop_map(|(id, n)| Op::Progress(id, n)),
+            (0..64usize).prop_map(Op::Split),
+            (0..64usize, 0..4096u64, 1..4096u64)
+                .prop_map(|(id, min, align)| Op::Steal(id, min, align)),
+            (0..64usize).prop_map(Op::Merge),
+            (0..64usize).prop_map(Op::Complete),
+        ]
+    }
+
+    fn assert_covers(segments: &[SegmentState], total: u64) {
+        let mut ranges: Vec<_> = segments
+            .iter()
+            .inspect(|s| assert!(s.downloaded <= s.range.len(), "{:?}", s))
+            .map(|s| s.range)
+            .filter(|r| !r.is_empty())
+            .collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut offset = 0;
+        for range in ranges {
+            assert_eq!(range.start, offset, "gap or overlap at {}", offset);
+            offset = range.end;
+        }
+        assert_eq!(offset, total);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_operations_preserve_coverage(
+            total in prop_oneof![1..256u64, 1..1u64 << 32],
+            count in 1..16usize,
+            min_range in 0..4096u64,
+            alignment in 1..1u64 << 22,
+            ops in prop::collection::vec(op(), 0..64),
+        ) {
+            let manager = SegmentManager::with_hint(total, count, SplitHint::new(min_range, alignment));
+            assert_covers(&manager.get_segments(), total);
+
+            for op in ops {
+                let segments = manager.get_segments();
+                let pick = |id: usize| id % segments.len();
+                match op {
+                    Op::Progress(id, n) => {
+                        let segment = &segments[pick(id)];
+                        if segment.status != SegmentStatus::Complete {
+                            manager.mark_active(segment.id);
+                            manager.update_segment(segment.id, n % (segment.range.len() + 1), 0.0);
+                        }
+                    }
+                    Op::Split(id) => {
+                        manager.split_segment(pick(id));
+                    }
+                    Op::Steal(id, min, align) => {
+                        manager.steal(pick(id), SplitHint::new(min, align));
+                    }
+                    Op::Merge(id) => {
+                        manager.merge_segment(pick(id));
+                    }
+                    Op::Complete(id) => manager.mark_complete(pick(id)),
+                }
+                assert_covers(&manager.get_segments(), total);
+            }
+        }
+    }
+}
@@ -1,12 +1,35 @@
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 use stormdl_core::{ByteRange, MirrorSet, MirrorStats};
 
+/// Spreads a download's segments over its mirrors and moves them off
+/// sources that fail or fall behind.
+///
+/// ```
+/// use stormdl_core::{ByteRange, MirrorSet};
+/// use stormdl_segment::MultiSourceManager;
+/// use url::Url;
+///
+/// let mut mirrors = MirrorSet::new(Url::parse("https://example.com/file.iso").unwrap());
+/// mirrors.add_url(Url::parse("https://mirror.example.org/file.iso").unwrap());
+/// let manager = MultiSourceManager::new(mirrors, 2_000_000);
+///
+/// let source = manager.assign_segment(0, ByteRange::new(0, 1_000_000));
+/// assert_eq!(manager.get_assignment(0), Some(source));
+///
+/// // A source that went away is retired, and its segment goes elsewhere.
+/// assert!(manager.retire(source));
+/// let other = manager.reassign_segment(0).unwrap();
+/// assert_ne!(other, source);
+/// assert!(manager.get_mirror_url(other).is_some());
+/// ```
 pub struct MultiSourceManager {
     mirrors: RwLock<MirrorSet>,
     segment_assignments: RwLock<HashMap<usize, usize>>,
     source_stats: RwLock<HashMap<usize, SourceStats>>,
+    retired: RwLock<HashSet<usize>>,
     #[allow(dead_code)]
     total_size: u64,
 }
@@ -16,6 +39,7 @@ struct SourceStats {
     errors: AtomicUsize,
     active_segments: AtomicUsize,
     speed_samples: RwLock<Vec<f64>>,
+    ttfb_samples: RwLock<Vec<Duration>>,
 }
 
 impl SourceStats {
@@ -25,6 +49,7 @@ impl SourceStats {
             errors: AtomicUsize::new(0),
             active_segments: AtomicUsize::new(0),
             speed_samples: RwLock::new(Vec::with_capacity(10)),
+            ttfb_samples: RwLock::new(Vec::with_capacity(10)),
         }
     }
 
@@ -35,6 +60,14 @@ impl SourceStats {
         }
         samples.iter().sum::<f64>() / samples.len() as f64
     }
+
+    fn avg_ttfb(&self) -> Option<Duration> {
+        let samples = self.ttfb_samples.read();
+        if samples.is_empty() {
+            return None;
+        }
+        Some(samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
 }
 
 impl MultiSourceManager {
@@ -43,14 +76,19 @@ impl MultiSourceManager {
             mirrors: RwLock::new(mirrors),
             segment_assignments: RwLock::new(HashMap::new()),
             source_stats: RwLock::new(HashMap::new()),
+            retired: RwLock::new(HashSet::new()),
             total_size,
         }
     }
 
     pub fn assign_segment(&self, segment_idx: usize, _range: ByteRange) -> usize {
         let mirrors = self.mirrors.read();
-        let source_idx = mirrors.select_for_segment(segment_idx);
+        let mut source_idx = mirrors.select_for_segment(segment_idx);
+        let count = mirrors.len();
         drop(mirrors);
+        if self.retired.read().contains(&source_idx) {
+            source_idx = self.best_source(count, |_| false).unwrap_or(source_idx);
+        }
 
         self.segment_assignments
             .write()
@@ -83,34 +121,71 @@ impl MultiSourceManager {
             }
         }
 
-        let mirrors = self.mirrors.read();
-        let mirror_count = mirrors.len();
-
+        let mirror_count = self.mirror_count();
         if mirror_count <= 1 {
             return None;
         }
 
         let excluded = old_source.unwrap_or(usize::MAX);
+        let best_idx = self.best_source(mirror_count, |idx| idx == excluded);
+
+        if let Some(new_idx) = best_idx {
+            self.segment_assignments
+                .write()
+                .insert(segment_idx, new_idx);
+
+            let mut stats = self.source_stats.write();
+            stats
+                .entry(new_idx)
+                .or_insert_with(SourceStats::new)
+                .active_segments
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        best_idx
+    }
+
+    // Takes a source out of rotation for good, e.g. after a 404. The last
+    // live one is never retired, so a segment always has somewhere to go.
+    pub fn retire(&self, source_idx: usize) -> bool {
+        let count = self.mirror_count();
+        let mut retired = self.retired.write();
+        if retired.contains(&source_idx) {
+            return true;
+        }
+        if count.saturating_sub(retired.len()) <= 1 {
+            return false;
+        }
+        retired.insert(source_idx)
+    }
+
+    fn best_source(&self, count: usize, excluded: impl Fn(usize) -> bool) -> Option<usize> {
+        let retired = self.retired.read();
+        let stats_guard = self.source_stats.read();
         let mut best_idx = None;
         let mut best_score = f64::NEG_INFINITY;
 
-        for idx in 0..mirror_count {
-            if idx == excluded {
+        for idx in 0..count {
+            if excluded(idx) || retired.contains(&idx) {
                 continue;
             }
 
-            let stats_guard = self.source_stats.read();
             let stats = stats_guard.get(&idx);
-
             let speed = stats.map(|s| s.avg_speed()).unwrap_or(0.0);
             let errors = stats.map(|s| s.errors.load(Ordering::Relaxed)).unwrap_or(0);
             let active = stats
                 .map(|s| s.active_segments.load(Ordering::Relaxed))
                 .unwrap_or(0);
 
+            let ttfb = stats
+                .and_then(|s| s.avg_ttfb())
+                .map(|t| t.as_secs_f64())
+                .unwrap_or(0.0);
+
             let error_penalty = 1.0 / (1.0 + errors as f64 * 0.5);
             let load_factor = 1.0 / (1.0 + active as f64 * 0.1);
-            let score = (speed + 1.0) * error_penalty * load_factor;
+            let ttfb_penalty = 1.0 / (1.0 + ttfb);
+            let score = (speed + 1.0) * error_penalty * load_factor * ttfb_penalty;
 
             if score > best_score {
                 best_score = score;
@@ -118,19 +193,6 @@ impl MultiSourceManager {
             }
         }
 
-        if let Some(new_idx) = best_idx {
-            self.segment_assignments
-                .write()
-                .insert(segment_idx, new_idx);
-
-            let mut stats = self.source_stats.write();
-            stats
-                .entry(new_idx)
-                .or_insert_with(SourceStats::new)
-                .active_segments
-                .fetch_add(1, Ordering::Relaxed);
-        }
-
         best_idx
     }
 
@@ -149,6 +211,38 @@ impl MultiSourceManager {
         }
     }
 
+    pub fn record_ttfb(&self, source_idx: usize, ttfb: Duration) {
+        let mut stats = self.source_stats.write();
+        let source_stats = stats.entry(source_idx).or_insert_with(SourceStats::new);
+
+        let mut samples = source_stats.ttfb_samples.write();
+        samples.push(ttfb);
+        if samples.len() > 10 {
+            samples.remove(0);
+        }
+    }
+
+    pub fn has_pathological_ttfb(&self, source_idx: usize) -> bool {
+        let stats = self.source_stats.read();
+        let Some(ttfb) = stats.get(&source_idx).and_then(|s| s.avg_ttfb()) else {
+            return false;
+        };
+        if ttfb < Duration::from_secs(1) {
+            return false;
+        }
+
+        let best_other = stats
+            .iter()
+            .filter(|(idx, _)| **idx != source_idx)
+            .filter_map(|(_, s)| s.avg_ttfb())
+            .min();
+
+        match best_other {
+            Some(best) => ttfb > best * 4,
+            None => false,
+        }
+    }
+
     pub fn record_error(&self, source_idx: usize) {
         let mut stats = self.source_stats.write();
         stats
@@ -190,6 +284,7 @@ impl MultiSourceManager {
                 errors: stats.errors.load(Ordering::Relaxed),
                 avg_speed: stats.avg_speed(),
                 active_segments: stats.active_segments.load(Ordering::Relaxed),
+                avg_ttfb: stats.avg_ttfb(),
             };
             mirrors.update_stats(*idx, mirror_stats);
         }
@@ -210,3 +305,32 @@ impl MultiSourceManager {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    #[test]
+    fn test_retired_sources_get_no_segments() {
+        let url = |host: &str| Url::parse(&format!("https://{}/a.iso", host)).unwrap();
+        let manager =
+            MultiSourceManager::new(MirrorSet::from(vec![url("a"), url("b"), url("c")]), 1 << 20);
+        let range = ByteRange::new(0, 1024);
+        assert_eq!(manager.assign_segment(0, range), 0);
+
+        manager.record_error(1);
+        assert!(manager.retire(1));
+        manager.record_progress(0, 1024, 1e6);
+        manager.record_error(2);
+        manager.sync_mirror_stats();
+        for ticket in 1..5 {
+            assert_ne!(manager.assign_segment(ticket, range), 1);
+        }
+        assert_eq!(manager.reassign_segment(1), Some(2));
+
+        assert!(manager.retire(2));
+        assert!(!manager.retire(0));
+        assert_eq!(manager.assign_segment(9, range), 0);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{AdaptiveController, Rebalancer, SegmentAdjustment, SegmentManager, SplitHint};
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stormdl_core::{SegmentState, SegmentStatus, StormError};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpeedTrace {
+    segments: Vec<Vec<(Duration, f64)>>,
+}
+
+impl SpeedTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn constant(speeds: &[f64]) -> Self {
+        let mut trace = Self::new();
+        for (segment, &speed) in speeds.iter().enumerate() {
+            trace.record(segment, Duration::ZERO, speed);
+        }
+        trace
+    }
+
+    pub fn record(&mut self, segment: usize, at: Duration, speed: f64) {
+        if self.segments.len() <= segment {
+            self.segments.resize(segment + 1, Vec::new());
+        }
+        let samples = &mut self.segments[segment];
+        let idx = samples.partition_point(|(t, _)| *t <= at);
+        samples.insert(idx, (at, speed));
+    }
+
+    pub fn parse(text: &str) -> Result<Self, StormError> {
+        let mut trace = Self::new();
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match fields.as_slice() {
+                [ms, segment, speed] => ms
+                    .parse::<u64>()
+                    .ok()
+                    .zip(segment.parse::<usize>().ok())
+                    .zip(speed.parse::<f64>().ok()),
+                _ => None,
+            };
+            let Some(((ms, segment), speed)) = parsed else {
+                return Err(StormError::Config(format!(
+                    "Invalid trace line {}: '{}' (expected '<ms> <segment> <bytes/s>')",
+                    lineno + 1,
+                    line
+                )));
+            };
+            trace.record(segment, Duration::from_millis(ms), speed);
+        }
+        Ok(trace)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (segment, samples) in self.segments.iter().enumerate() {
+            for (at, speed) in samples {
+                let _ = writeln!(out, "{} {} {}", at.as_millis(), segment, speed);
+            }
+        }
+        out
+    }
+
+    pub fn speed_at(&self, segment: usize, at: Duration) -> f64 {
+        if self.segments.is_empty() {
+            return 0.0;
+        }
+        let samples = &self.segments[segment % self.segments.len()];
+        let idx = samples.partition_point(|(t, _)| *t <= at);
+        samples
+            .get(idx.saturating_sub(1))
+            .map_or(0.0, |(_, speed)| *speed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub total_size: u64,
+    pub initial_segments: usize,
+    pub max_segments: usize,
+    pub min_segment_size: u64,
+    pub slow_threshold_pct: f64,
+    pub tick: Duration,
+    pub rebalance_interval: Duration,
+    pub rtt: Option<Duration>,
+    pub rebalance: bool,
+    pub max_time: Duration,
+}
+
+impl SimConfig {
+    pub fn new(total_size: u64, initial_segments: usize) -> Self {
+        Self {
+            total_size,
+            initial_segments,
+            max_segments: 32,
+            min_segment_size: 256 * 1024,
+            slow_threshold_pct: 0.2,
+            tick: Duration::from_millis(100),
+            rebalance_interval: Duration::from_millis(500),
+            rtt: None,
+            rebalance: true,
+            max_time: Duration::from_secs(3600),
+        }
+    }
+
+    pub fn with_rtt(mut self, rtt: Duration) -> Self {
+        self.rtt = Some(rtt);
+        self
+    }
+
+    pub fn without_rebalancing(mut self) -> Self {
+        self.rebalance = false;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimReport {
+    pub elapsed: Duration,
+    pub completed: bool,
+    pub splits: usize,
+    pub peak_connections: usize,
+    pub segments: Vec<SegmentState>,
+}
+
+impl SimReport {
+    pub fn average_speed(&self) -> f64 {
+        let bytes: u64 = self.segments.iter().map(|s| s.downloaded).sum();
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => bytes as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+pub struct Simulator {
+    config: SimConfig,
+    trace: SpeedTrace,
+}
+
+impl Simulator {
+    pub fn new(config: SimConfig, trace: SpeedTrace) -> Self {
+        Self { config, trace }
+    }
+
+    pub fn run(&self) -> SimReport {
+        let config = &self.config;
+        let manager = Arc::new(SegmentManager::with_hint(
+            config.total_size,
+            config.initial_segments,
+            SplitHint::new(config.min_segment_size, 1),
+        ));
+        let rebalancer = Rebalancer::with_config(
+            manager.clone(),
+            config.slow_threshold_pct,
+            config.min_segment_size,
+            config.max_segments,
+        );
+        let controller = AdaptiveController::with_config(
+            config.total_size,
+            manager.get_segments().len(),
+            config.max_segments,
+            config.min_segment_size,
+        );
+
+        let start = Instant::now();
+        controller.reset_clock(start);
+        let tick = config.tick.as_secs_f64();
+        let mut now = Duration::ZERO;
+        let mut next_rebalance = config.rebalance_interval;
+        let mut progress: Vec<f64> = Vec::new();
+        let mut splits = 0;
+        let mut peak_connections = 0;
+        let mut completed = false;
+
+        while now < config.max_time {
+            let segments = manager.get_segments();
+            if segments.iter().all(|s| s.status == SegmentStatus::Complete) {
+                completed = true;
+                break;
+            }
+
+            let limit = controller.current_segments().max(1);
+            let mut active = segments
+                .iter()
+                .filter(|s| s.status == SegmentStatus::Active)
+                .count();
+            for segment in segments
+                .iter()
+                .filter(|s| s.status == SegmentStatus::Pending)
+            {
+                if active >= limit {
+                    break;
+                }
+                manager.mark_active(segment.id);
+                active += 1;
+            }
+            peak_connections = peak_connections.max(active);
+
+            now += config.tick;
+            progress.resize(manager.get_segments().len(), 0.0);
+            let mut aggregate = 0.0;
+            for segment in manager.get_segments() {
+                if segment.status != SegmentStatus::Active {
+                    continue;
+                }
+                let speed = self.trace.speed_at(segment.id, now);
+                let len = segment.range.len();
+                let done = &mut progress[segment.id];
+                *done = (*done + speed * tick).min(len as f64);
+                manager.update_segment(segment.id, *done as u64, speed);
+                aggregate += speed;
+                if *done as u64 >= len {
+                    manager.mark_complete(segment.id);
+                }
+            }
+
+            if config.rebalance && now >= next_rebalance {
+                next_rebalance += config.rebalance_interval;
+                let bdp = config.rtt.map(|rtt| (aggregate * rtt.as_secs_f64()) as u64);
+
+                let new_segments = rebalancer.check_and_rebalance_with_bdp(bdp);
+                for _ in &new_segments {
+                    controller.record_split();
+                }
+                splits += new_segments.len();
+
+                if let Some(SegmentAdjustment::Split { count, .. }) =
+                    controller.evaluate_at(start + now, bdp, aggregate)
+                {
+                    for _ in 0..count {
+                        let largest = manager
+                            .get_segments()
+                            .into_iter()
+                            .filter(|s| s.status == SegmentStatus::Active)
+                            .max_by_key(|s| s.remaining());
+                        if let Some(segment) = largest
+                            && manager.split_segment(segment.id).is_some()
+                        {
+                            splits += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        SimReport {
+            elapsed: now,
+            completed,
+            splits,
+            peak_connections,
+            segments: manager.get_segments(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: f64 = 1024.0 * 1024.0;
+
+    #[test]
+    fn test_trace_round_trip() {
+        let trace =
+            SpeedTrace::parse("# ms segment speed\n0 0 1000\n0 1 500\n2000 1 50\n").unwrap();
+        assert_eq!(trace.speed_at(1, Duration::from_millis(1999)), 500.0);
+        assert_eq!(trace.speed_at(1, Duration::from_secs(5)), 50.0);
+        assert_eq!(trace.speed_at(2, Duration::ZERO), 1000.0);
+        assert_eq!(SpeedTrace::parse(&trace.to_text()).unwrap(), trace);
+        assert!(SpeedTrace::parse("0 0").is_err());
+    }
+
+    #[test]
+    fn test_rebalancing_rescues_slow_segment() {
+        let trace = SpeedTrace::constant(&[MB, MB, MB, 0.05 * MB]);
+        let config = SimConfig::new(40 * 1024 * 1024, 4);
+
+        let baseline = Simulator::new(config.clone().without_rebalancing(), trace.clone()).run();
+        let rebalanced = Simulator::new(config.clone(), trace.clone()).run();
+        let replay = Simulator::new(config, trace).run();
+
+        assert!(baseline.completed && rebalanced.completed);
+        assert_eq!(baseline.splits, 0);
+        assert!(rebalanced.splits > 0);
+        assert!(rebalanced.elapsed < baseline.elapsed / 2);
+        assert_eq!(replay.elapsed, rebalanced.elapsed);
+        assert_eq!(replay.splits, rebalanced.splits);
+
+        let mut ranges: Vec<_> = rebalanced.segments.iter().map(|s| s.range).collect();
+        ranges.sort_by_key(|r| r.start);
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, 40 * 1024 * 1024);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+    }
+}
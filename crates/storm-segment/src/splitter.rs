@@ -43,6 +43,7 @@ pub fn split_range(total_size: u64, num_segments: usize) -> Vec<stormdl_core::By
         return vec![];
     }
 
+    let num_segments = num_segments.min(total_size.min(usize::MAX as u64) as usize);
     let segment_size = total_size / num_segments as u64;
     let remainder = total_size % num_segments as u64;
 
@@ -59,9 +60,91 @@ pub fn split_range(total_size: u64, num_segments: usize) -> Vec<stormdl_core::By
     ranges
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitHint {
+    pub min_range: u64,
+    pub alignment: u64,
+}
+
+impl SplitHint {
+    pub fn new(min_range: u64, alignment: u64) -> Self {
+        Self {
+            min_range,
+            alignment: alignment.max(1),
+        }
+    }
+
+    fn align_down(&self, offset: u64) -> u64 {
+        offset / self.alignment * self.alignment
+    }
+
+    pub fn split_point(&self, start: u64, end: u64) -> Option<u64> {
+        if end <= start {
+            return None;
+        }
+
+        let mid = start + (end - start) / 2;
+        let aligned = self.align_down(mid);
+        let point = if aligned > start { aligned } else { mid };
+
+        if point == start || point - start < self.min_range || end - point < self.min_range {
+            return None;
+        }
+
+        Some(point)
+    }
+}
+
+impl Default for SplitHint {
+    fn default() -> Self {
+        Self::new(256 * 1024, 1024 * 1024)
+    }
+}
+
+pub fn split_range_with_hint(
+    total_size: u64,
+    num_segments: usize,
+    hint: SplitHint,
+) -> Vec<stormdl_core::ByteRange> {
+    if num_segments == 0 || total_size == 0 {
+        return vec![];
+    }
+
+    let max_by_floor = (total_size / hint.min_range.max(1)).max(1) as usize;
+    let count = num_segments.min(max_by_floor);
+    let base = total_size.div_ceil(count as u64);
+
+    let segment_size = if hint.alignment < base {
+        base.div_ceil(hint.alignment) * hint.alignment
+    } else {
+        base
+    };
+
+    let mut ranges = Vec::with_capacity(count);
+    let mut offset = 0;
+    while offset < total_size {
+        let end = (offset + segment_size).min(total_size);
+        ranges.push(stormdl_core::ByteRange::new(offset, end));
+        offset = end;
+    }
+
+    if ranges.len() > 1
+        && let Some(tail) = ranges.last().copied()
+        && tail.len() < hint.min_range
+    {
+        ranges.pop();
+        if let Some(prev) = ranges.last_mut() {
+            prev.end = tail.end;
+        }
+    }
+
+    ranges
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_split_range_even() {
@@ -79,4 +162,78 @@ mod tests {
         assert_eq!(ranges.len(), 3);
         assert_eq!(ranges[0].len() + ranges[1].len() + ranges[2].len(), 10);
     }
+
+    #[test]
+    fn test_split_range_with_hint_aligned() {
+        let hint = SplitHint::new(256 * 1024, 1024 * 1024);
+        let total = 10 * 1024 * 1024 + 123;
+        let ranges = split_range_with_hint(total, 4, hint);
+
+        assert_eq!(ranges.first().unwrap().start, 0);
+        assert_eq!(ranges.last().unwrap().end, total);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert_eq!(pair[0].end % hint.alignment, 0);
+        }
+        for range in &ranges {
+            assert!(range.len() >= hint.min_range);
+        }
+    }
+
+    fn assert_contiguous(ranges: &[stormdl_core::ByteRange], total: u64) {
+        let mut offset = 0;
+        for range in ranges {
+            assert_eq!(range.start, offset);
+            assert!(!range.is_empty());
+            offset = range.end;
+        }
+        assert_eq!(offset, total);
+    }
+
+    fn size() -> impl Strategy<Value = u64> {
+        prop_oneof![1..64u64, 1..1u64 << 40]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_split_range_covers(total in size(), count in 1..64usize) {
+            let ranges = split_range(total, count);
+            prop_assert!(ranges.len() <= count);
+            assert_contiguous(&ranges, total);
+        }
+
+        #[test]
+        fn prop_split_range_with_hint_covers(
+            total in size(),
+            count in 1..64usize,
+            min_range in size(),
+            alignment in size(),
+        ) {
+            let ranges = split_range_with_hint(total, count, SplitHint::new(min_range, alignment));
+            prop_assert!(ranges.len() <= count);
+            assert_contiguous(&ranges, total);
+        }
+
+        #[test]
+        fn prop_split_point_inside_range(
+            start in size(),
+            len in size(),
+            min_range in prop_oneof![Just(0u64), size()],
+            alignment in size(),
+        ) {
+            let hint = SplitHint::new(min_range, alignment);
+            if let Some(point) = hint.split_point(start, start + len) {
+                prop_assert!(point > start && point < start + len);
+                prop_assert!(point - start >= min_range && start + len - point >= min_range);
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_point_respects_floor() {
+        let hint = SplitHint::new(1000, 4096);
+        assert_eq!(hint.split_point(0, 1500), None);
+        assert_eq!(hint.split_point(0, 16384), Some(8192));
+        assert_eq!(hint.split_point(5000, 9000), Some(7000));
+    }
 }
@@ -0,0 +1,271 @@
+use crate::SplitHint;
+use std::collections::VecDeque;
+use std::time::Instant;
+use stormdl_core::ByteRange;
+use sync::{Arc, AtomicU64, Mutex, Ordering};
+
+#[cfg(not(loom))]
+mod sync {
+    pub use parking_lot::Mutex;
+    pub use std::sync::Arc;
+    pub use std::sync::atomic::{AtomicU64, Ordering};
+}
+
+#[cfg(loom)]
+mod sync {
+    pub use loom::sync::Arc;
+    pub use loom::sync::atomic::{AtomicU64, Ordering};
+
+    pub struct Mutex<T>(loom::sync::Mutex<T>);
+
+    impl<T> Mutex<T> {
+        pub fn new(value: T) -> Self {
+            Self(loom::sync::Mutex::new(value))
+        }
+
+        pub fn lock(&self) -> loom::sync::MutexGuard<'_, T> {
+            self.0.lock().unwrap()
+        }
+    }
+}
+
+pub struct RangeClaim {
+    window: Mutex<ByteRange>,
+}
+
+impl RangeClaim {
+    pub fn new(range: ByteRange) -> Self {
+        Self {
+            window: Mutex::new(range),
+        }
+    }
+
+    pub fn remaining(&self) -> ByteRange {
+        *self.window.lock()
+    }
+
+    pub fn reserve(&self, len: u64) -> u64 {
+        let mut window = self.window.lock();
+        let granted = len.min(window.len());
+        window.start += granted;
+        granted
+    }
+
+    pub fn release(&self, len: u64) {
+        let mut window = self.window.lock();
+        window.start = window.start.saturating_sub(len);
+    }
+
+    pub fn steal(&self, hint: SplitHint) -> Option<ByteRange> {
+        let mut window = self.window.lock();
+        let point = hint.split_point(window.start, window.end)?;
+        let stolen = ByteRange::new(point, window.end);
+        window.end = point;
+        Some(stolen)
+    }
+}
+
+pub struct SegmentTracker {
+    pub total: u64,
+    downloaded: AtomicU64,
+    last_progress: Mutex<(u64, Instant)>,
+    current: Mutex<Option<Arc<RangeClaim>>>,
+}
+
+impl SegmentTracker {
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            downloaded: AtomicU64::new(0),
+            last_progress: Mutex::new((0, Instant::now())),
+            current: Mutex::new(None),
+        }
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded.load(Ordering::Acquire)
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.downloaded.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    pub fn speed(&self) -> f64 {
+        let (last_bytes, last_time) = *self.last_progress.lock();
+        let elapsed = last_time.elapsed().as_secs_f64();
+        if elapsed > 0.5 {
+            self.downloaded().saturating_sub(last_bytes) as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    pub fn update_speed_sample(&self) {
+        let current = self.downloaded();
+        *self.last_progress.lock() = (current, Instant::now());
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.total.saturating_sub(self.downloaded())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.downloaded() >= self.total
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.current.lock().is_some()
+    }
+
+    pub fn begin(&self, claim: Arc<RangeClaim>) {
+        *self.current.lock() = Some(claim);
+    }
+
+    pub fn finish(&self, claim: &Arc<RangeClaim>) {
+        let mut current = self.current.lock();
+        if current.as_ref().is_some_and(|c| Arc::ptr_eq(c, claim)) {
+            *current = None;
+        }
+    }
+
+    pub fn steal(&self, hint: SplitHint) -> Option<ByteRange> {
+        self.current.lock().as_ref()?.steal(hint)
+    }
+}
+
+pub struct WorkQueue {
+    ranges: Mutex<VecDeque<(ByteRange, usize)>>,
+}
+
+impl WorkQueue {
+    pub fn new() -> Self {
+        Self {
+            ranges: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn push(&self, range: ByteRange, segment_idx: usize) {
+        if !range.is_empty() {
+            self.ranges.lock().push_back((range, segment_idx));
+        }
+    }
+
+    pub fn pop(&self) -> Option<(ByteRange, usize)> {
+        self.ranges.lock().pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.lock().is_empty()
+    }
+}
+
+impl Default for WorkQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steal_shrinks_owner_claim() {
+        let tracker = SegmentTracker::new(1000);
+        let claim = Arc::new(RangeClaim::new(ByteRange::new(0, 1000)));
+        tracker.begin(claim.clone());
+
+        assert_eq!(claim.reserve(100), 100);
+        tracker.add(100);
+        let stolen = tracker.steal(SplitHint::new(10, 1)).unwrap();
+        assert_eq!(stolen, ByteRange::new(550, 1000));
+        assert_eq!(claim.reserve(1000), 450);
+        assert_eq!(claim.reserve(1), 0);
+
+        tracker.add(450);
+        tracker.finish(&claim);
+        assert!(!tracker.is_active());
+        assert!(!tracker.is_complete());
+        tracker.add(stolen.len());
+        assert!(tracker.is_complete());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn loom_steal_races_reserve() {
+        loom::model(|| {
+            let tracker = Arc::new(SegmentTracker::new(8));
+            let claim = Arc::new(RangeClaim::new(ByteRange::new(0, 8)));
+            tracker.begin(claim.clone());
+
+            let owner = {
+                let tracker = tracker.clone();
+                let claim = claim.clone();
+                thread::spawn(move || {
+                    let mut written = 0;
+                    loop {
+                        let granted = claim.reserve(3);
+                        if granted == 0 {
+                            break;
+                        }
+                        tracker.add(granted);
+                        written += granted;
+                    }
+                    tracker.finish(&claim);
+                    written
+                })
+            };
+
+            let stolen = tracker.steal(SplitHint::new(1, 1));
+            let written = owner.join().unwrap();
+            let stolen_len = stolen.map_or(0, |r| r.len());
+
+            assert_eq!(written + stolen_len, 8);
+            if let Some(range) = stolen {
+                assert_eq!(range.start, written);
+                assert_eq!(range.end, 8);
+            }
+        });
+    }
+
+    #[test]
+    fn loom_workers_only_exit_when_complete() {
+        loom::model(|| {
+            let tracker = Arc::new(SegmentTracker::new(4));
+            let queue = Arc::new(WorkQueue::new());
+            queue.push(ByteRange::new(0, 4), 0);
+
+            let worker = || {
+                let tracker = tracker.clone();
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    loop {
+                        match queue.pop() {
+                            Some((range, idx)) => {
+                                let claim = RangeClaim::new(range);
+                                tracker.add(claim.reserve(2));
+                                queue.push(claim.remaining(), idx);
+                            }
+                            None if tracker.is_complete() => break,
+                            None => thread::yield_now(),
+                        }
+                    }
+                })
+            };
+
+            let a = worker();
+            let b = worker();
+            a.join().unwrap();
+            b.join().unwrap();
+
+            assert!(tracker.is_complete());
+            assert_eq!(tracker.downloaded(), 4);
+            assert!(queue.is_empty());
+        });
+    }
+}
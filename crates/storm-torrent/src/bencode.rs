@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use stormdl_core::StormError;
+
+// Deeper nesting than any real torrent has; it keeps a hostile file from
+// exhausting the stack.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(BTreeMap<Vec<u8>, Value>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|b| std::str::from_utf8(b).ok())
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn dict(entries: Vec<(&str, Value)>) -> Self {
+        Value::Dict(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v))
+                .collect(),
+        )
+    }
+
+    pub fn bytes(bytes: impl Into<Vec<u8>>) -> Self {
+        Value::Bytes(bytes.into())
+    }
+}
+
+// The whole of `data` as one value.
+pub fn decode(data: &[u8]) -> Result<Value, StormError> {
+    let (value, used) = decode_prefix(data)?;
+    if used != data.len() {
+        return Err(invalid("trailing data"));
+    }
+    Ok(value)
+}
+
+// The value `data` starts with and its length; ut_metadata messages carry
+// raw bytes after theirs.
+pub fn decode_prefix(data: &[u8]) -> Result<(Value, usize), StormError> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let value = decoder.value(0)?;
+    Ok((value, decoder.pos))
+}
+
+// Where a top-level dictionary keeps `key`'s value, byte for byte; the info
+// hash is taken over the bytes as sent, not a re-encoding.
+pub fn raw_value(data: &[u8], key: &str) -> Result<Option<Range<usize>>, StormError> {
+    let mut decoder = Decoder { data, pos: 0 };
+    if decoder.peek()? != b'd' {
+        return Err(invalid("expected a dictionary"));
+    }
+    decoder.pos += 1;
+    while decoder.peek()? != b'e' {
+        let name = decoder.string()?;
+        let start = decoder.pos;
+        decoder.value(1)?;
+        if name == key.as_bytes() {
+            return Ok(Some(start..decoder.pos));
+        }
+    }
+    Ok(None)
+}
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(value, &mut out);
+    out
+}
+
+fn encode_into(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Int(n) => out.extend_from_slice(format!("i{}e", n).as_bytes()),
+        Value::Bytes(bytes) => {
+            out.extend_from_slice(format!("{}:", bytes.len()).as_bytes());
+            out.extend_from_slice(bytes);
+        }
+        Value::List(list) => {
+            out.push(b'l');
+            for item in list {
+                encode_into(item, out);
+            }
+            out.push(b'e');
+        }
+        Value::Dict(dict) => {
+            out.push(b'd');
+            for (key, item) in dict {
+                encode_into(&Value::Bytes(key.clone()), out);
+                encode_into(item, out);
+            }
+            out.push(b'e');
+        }
+    }
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Protocol(format!("invalid bencoding: {}", message))
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn peek(&self) -> Result<u8, StormError> {
+        self.data
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| invalid("unexpected end"))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, StormError> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deeply"));
+        }
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let digits = self.until(b'e')?;
+                let text = std::str::from_utf8(digits).map_err(|_| invalid("bad integer"))?;
+                if text.starts_with("-0") || (text.starts_with('0') && text.len() > 1) {
+                    return Err(invalid("bad integer"));
+                }
+                text.parse()
+                    .map(Value::Int)
+                    .map_err(|_| invalid("bad integer"))
+            }
+            b'l' => {
+                self.pos += 1;
+                let mut list = Vec::new();
+                while self.peek()? != b'e' {
+                    list.push(self.value(depth + 1)?);
+                }
+                self.pos += 1;
+                Ok(Value::List(list))
+            }
+            b'd' => {
+                self.pos += 1;
+                let mut dict = BTreeMap::new();
+                while self.peek()? != b'e' {
+                    let key = self.string()?.to_vec();
+                    let value = self.value(depth + 1)?;
+                    dict.insert(key, value);
+                }
+                self.pos += 1;
+                Ok(Value::Dict(dict))
+            }
+            b'0'..=b'9' => Ok(Value::Bytes(self.string()?.to_vec())),
+            _ => Err(invalid("unexpected byte")),
+        }
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], StormError> {
+        let digits = self.until(b':')?;
+        let len: usize = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(|| invalid("bad string length"))?;
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("unexpected end"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    // The bytes up to `end`, which is skipped.
+    fn until(&mut self, end: u8) -> Result<&'a [u8], StormError> {
+        let data = self.data;
+        let rest = &data[self.pos..];
+        let at = rest
+            .iter()
+            .position(|b| *b == end)
+            .ok_or_else(|| invalid("unexpected end"))?;
+        self.pos += at + 1;
+        Ok(&rest[..at])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bencode() {
+        let data = b"d8:announce3:url4:infod6:lengthi-42e4:name1:ae5:peersl2:ab0:ee";
+        let value = decode(data).unwrap();
+        assert_eq!(value.get("announce").and_then(Value::as_str), Some("url"));
+        let info = value.get("info").unwrap();
+        assert_eq!(info.get("length").and_then(Value::as_int), Some(-42));
+        assert_eq!(
+            value.get("peers").and_then(Value::as_list).unwrap(),
+            [Value::bytes("ab"), Value::bytes("")]
+        );
+        assert_eq!(encode(&value), data);
+
+        let range = raw_value(data, "info").unwrap().unwrap();
+        assert_eq!(&data[range], b"d6:lengthi-42e4:name1:ae");
+        assert_eq!(raw_value(data, "missing").unwrap(), None);
+
+        let (prefix, used) = decode_prefix(b"d1:xi1eeRAW").unwrap();
+        assert_eq!(prefix.get("x"), Some(&Value::Int(1)));
+        assert_eq!(used, 8);
+
+        for bad in [
+            &b"i01e"[..],
+            b"i-0e",
+            b"5:abc",
+            b"l",
+            b"d1:xi1eeX",
+            b"x",
+            b"99999999999999999999:",
+        ] {
+            assert!(decode(bad).is_err(), "{:?}", bad);
+        }
+        let deep = [vec![b'l'; 100], vec![b'e'; 100]].concat();
+        assert!(decode(&deep).is_err());
+    }
+}
@@ -0,0 +1,18 @@
+mod bencode;
+mod magnet;
+mod metainfo;
+mod peer;
+mod picker;
+mod session;
+mod storage;
+mod tracker;
+mod webseed;
+
+pub use bencode::{Value, decode, encode};
+pub use magnet::Magnet;
+pub use metainfo::{InfoHash, Torrent, TorrentFile};
+pub use peer::{Message, Peer};
+pub use picker::{Pick, PieceMap};
+pub use session::{Progress, Session, resolve};
+pub use storage::Storage;
+pub use tracker::{Announce, AnnounceResponse, Event, announce};
@@ -0,0 +1,95 @@
+use crate::metainfo::InfoHash;
+use std::net::SocketAddr;
+use stormdl_core::StormError;
+use url::Url;
+
+// A magnet link: the info hash and whatever hints came with it. The
+// torrent's metadata has to be fetched from peers (BEP 9).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Magnet {
+    pub info_hash: InfoHash,
+    pub name: Option<String>,
+    pub trackers: Vec<Url>,
+    pub web_seeds: Vec<Url>,
+    pub peers: Vec<SocketAddr>,
+}
+
+impl Magnet {
+    pub fn is_magnet(url: &Url) -> bool {
+        url.scheme() == "magnet"
+    }
+
+    pub fn parse(url: &Url) -> Result<Self, StormError> {
+        if !Self::is_magnet(url) {
+            return Err(StormError::InvalidUrl(format!(
+                "{} is not a magnet link",
+                url
+            )));
+        }
+        let mut info_hash = None;
+        let mut magnet = Self {
+            info_hash: InfoHash([0; 20]),
+            name: None,
+            trackers: Vec::new(),
+            web_seeds: Vec::new(),
+            peers: Vec::new(),
+        };
+        for (key, value) in url.query_pairs() {
+            // Repeated keys may be numbered: tr.1, tr.2...
+            let key = match key.split_once('.') {
+                Some((key, n)) if n.bytes().all(|b| b.is_ascii_digit()) => key,
+                _ => &key,
+            };
+            match key {
+                "xt" => {
+                    info_hash =
+                        info_hash.or(value.strip_prefix("urn:btih:").and_then(InfoHash::parse));
+                }
+                "dn" => magnet.name = Some(value.into_owned()),
+                "tr" => magnet.trackers.extend(Url::parse(&value).ok()),
+                "ws" => magnet.web_seeds.extend(
+                    Url::parse(&value)
+                        .ok()
+                        .filter(|u| matches!(u.scheme(), "http" | "https")),
+                ),
+                "x.pe" => magnet.peers.extend(value.parse::<SocketAddr>().ok()),
+                _ => {}
+            }
+        }
+        magnet.info_hash = info_hash.ok_or_else(|| {
+            StormError::InvalidUrl("magnet link has no BitTorrent info hash (xt=urn:btih:)".into())
+        })?;
+        Ok(magnet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_magnet() {
+        let url = Url::parse(
+            "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&dn=Big+File\
+             &tr=udp%3A%2F%2Ftracker.example%3A6969&tr.1=http://t2.example/announce\
+             &ws=https%3A%2F%2Fmirror.example%2Ffile&ws=ftp://x/y&x.pe=10.0.0.2:51413",
+        )
+        .unwrap();
+        let magnet = Magnet::parse(&url).unwrap();
+        assert_eq!(
+            magnet.info_hash.to_string(),
+            "c12fe1c06bba254a9dc9f519b335aa7c1367a88a"
+        );
+        assert_eq!(magnet.name.as_deref(), Some("Big File"));
+        assert_eq!(magnet.trackers.len(), 2);
+        assert_eq!(
+            magnet.web_seeds,
+            [Url::parse("https://mirror.example/file").unwrap()]
+        );
+        assert_eq!(magnet.peers, ["10.0.0.2:51413".parse().unwrap()]);
+
+        let v2 = Url::parse("magnet:?xt=urn:btmh:1220abcd").unwrap();
+        assert!(Magnet::parse(&v2).is_err());
+        assert!(Magnet::parse(&Url::parse("https://example.com/").unwrap()).is_err());
+    }
+}
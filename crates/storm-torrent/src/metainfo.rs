@@ -0,0 +1,387 @@
+use crate::bencode::{self, Value};
+use sha1::{Digest, Sha1};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use stormdl_core::{ByteRange, StormError};
+use url::Url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InfoHash(pub [u8; 20]);
+
+impl InfoHash {
+    pub fn of(info: &[u8]) -> Self {
+        Self(Sha1::digest(info).into())
+    }
+
+    // 40 hex digits or, as older magnets have it, 32 base32 characters.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut hash = [0u8; 20];
+        match text.len() {
+            40 => {
+                for (i, byte) in hash.iter_mut().enumerate() {
+                    *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+                }
+            }
+            32 => {
+                let mut bits = 0u64;
+                let mut count = 0;
+                let mut out = 0;
+                for c in text.bytes() {
+                    let value = match c.to_ascii_uppercase() {
+                        c @ b'A'..=b'Z' => c - b'A',
+                        c @ b'2'..=b'7' => c - b'2' + 26,
+                        _ => return None,
+                    };
+                    bits = (bits << 5) | value as u64;
+                    count += 5;
+                    if count >= 8 {
+                        count -= 8;
+                        hash[out] = (bits >> count) as u8;
+                        out += 1;
+                    }
+                }
+            }
+            _ => return None,
+        }
+        Some(Self(hash))
+    }
+}
+
+impl fmt::Display for InfoHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Torrent {
+    pub info_hash: InfoHash,
+    pub name: String,
+    pub piece_length: u64,
+    pub pieces: Vec<[u8; 20]>,
+    // In torrent order; offsets run across them as one stream.
+    pub files: Vec<TorrentFile>,
+    pub trackers: Vec<Url>,
+    pub web_seeds: Vec<Url>,
+    pub private: bool,
+    // The bencoded info dictionary, for peers that ask for it.
+    pub info: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    // Relative to the download directory; multi-file torrents start with
+    // the torrent's name.
+    pub path: PathBuf,
+    pub length: u64,
+    pub offset: u64,
+    // BEP 47 padding, which is zeros and never written.
+    pub padding: bool,
+}
+
+impl Torrent {
+    pub fn is_torrent(path: &Path) -> bool {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("torrent"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, StormError> {
+        let data = std::fs::read(path)?;
+        Self::parse(&data).map_err(|e| match e {
+            StormError::Protocol(message) => {
+                StormError::Protocol(format!("{}: {}", path.display(), message))
+            }
+            e => e,
+        })
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, StormError> {
+        let root = bencode::decode(data)?;
+        let range = bencode::raw_value(data, "info")?.ok_or_else(|| invalid("no info"))?;
+        let mut trackers = Vec::new();
+        // announce-list's tiers are tried in order; the tiers themselves
+        // aren't kept.
+        if let Some(tiers) = root.get("announce-list").and_then(Value::as_list) {
+            for tier in tiers {
+                trackers.extend(tier.as_list().unwrap_or_default().iter().filter_map(url));
+            }
+        }
+        trackers.extend(root.get("announce").and_then(url));
+        let mut web_seeds: Vec<Url> = match root.get("url-list") {
+            Some(Value::List(list)) => list.iter().filter_map(url).collect(),
+            Some(value) => url(value).into_iter().collect(),
+            None => Vec::new(),
+        };
+        web_seeds.retain(|u| matches!(u.scheme(), "http" | "https"));
+        Self::from_info(&data[range], trackers, web_seeds)
+    }
+
+    // A torrent from its info dictionary alone, as a magnet link's peers
+    // hand it over.
+    pub fn from_info(
+        info: &[u8],
+        mut trackers: Vec<Url>,
+        web_seeds: Vec<Url>,
+    ) -> Result<Self, StormError> {
+        let dict = bencode::decode(info)?;
+        let name = dict
+            .get("name.utf-8")
+            .or_else(|| dict.get("name"))
+            .and_then(Value::as_str)
+            .map(sanitize)
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| invalid("no name"))?;
+        let piece_length = dict
+            .get("piece length")
+            .and_then(Value::as_int)
+            .filter(|n| *n > 0)
+            .ok_or_else(|| invalid("no piece length"))? as u64;
+        let hashes = dict
+            .get("pieces")
+            .and_then(Value::as_bytes)
+            .ok_or_else(|| {
+                if dict.get("meta version").and_then(Value::as_int) == Some(2) {
+                    invalid("BitTorrent v2-only torrents aren't supported")
+                } else {
+                    invalid("no piece hashes")
+                }
+            })?;
+        if hashes.len() % 20 != 0 {
+            return Err(invalid("piece hashes aren't a multiple of 20 bytes"));
+        }
+        let pieces: Vec<[u8; 20]> = hashes
+            .chunks(20)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+
+        let mut files = Vec::new();
+        let mut offset = 0u64;
+        match dict.get("files").and_then(Value::as_list) {
+            Some(list) => {
+                for file in list {
+                    let length = length(file)?;
+                    let parts = file
+                        .get("path.utf-8")
+                        .or_else(|| file.get("path"))
+                        .and_then(Value::as_list)
+                        .ok_or_else(|| invalid("file without a path"))?;
+                    let mut path = PathBuf::from(&name);
+                    for part in parts {
+                        let part = part.as_str().map(sanitize).unwrap_or_default();
+                        if !part.is_empty() {
+                            path.push(part);
+                        }
+                    }
+                    if path.components().count() < 2 {
+                        return Err(invalid("file without a path"));
+                    }
+                    let padding = file
+                        .get("attr")
+                        .and_then(Value::as_str)
+                        .is_some_and(|attr| attr.contains('p'));
+                    files.push(TorrentFile {
+                        path,
+                        length,
+                        offset,
+                        padding,
+                    });
+                    offset = offset
+                        .checked_add(length)
+                        .ok_or_else(|| invalid("bad file length"))?;
+                }
+            }
+            None => {
+                offset = length(&dict)?;
+                files.push(TorrentFile {
+                    path: PathBuf::from(&name),
+                    length: offset,
+                    offset: 0,
+                    padding: false,
+                });
+            }
+        }
+        if offset.div_ceil(piece_length) != pieces.len() as u64 {
+            return Err(invalid("piece count doesn't match the size"));
+        }
+        trackers.dedup();
+        Ok(Self {
+            info_hash: InfoHash::of(info),
+            name,
+            piece_length,
+            pieces,
+            files,
+            trackers,
+            web_seeds,
+            private: dict.get("private").and_then(Value::as_int) == Some(1),
+            info: info.to_vec(),
+        })
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.files.last().map_or(0, |f| f.offset + f.length)
+    }
+
+    pub fn is_multi_file(&self) -> bool {
+        self.files
+            .first()
+            .is_some_and(|f| f.path.components().count() > 1)
+    }
+
+    pub fn piece_range(&self, index: usize) -> ByteRange {
+        let size = self.total_size();
+        let start = index as u64 * self.piece_length;
+        ByteRange::new(start.min(size), (start + self.piece_length).min(size))
+    }
+
+    pub fn check_piece(&self, index: usize, data: &[u8]) -> bool {
+        self.pieces
+            .get(index)
+            .is_some_and(|hash| Sha1::digest(data)[..] == hash[..])
+    }
+
+    // The files a byte range of the torrent falls in: (file, offset in the
+    // file, range of the torrent).
+    pub fn spans(&self, range: ByteRange) -> Vec<(usize, u64, ByteRange)> {
+        let mut spans = Vec::new();
+        for (index, file) in self.files.iter().enumerate() {
+            let start = range.start.max(file.offset);
+            let end = range.end.min(file.offset + file.length);
+            if start < end {
+                spans.push((index, start - file.offset, ByteRange::new(start, end)));
+            }
+        }
+        spans
+    }
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Protocol(format!("invalid torrent: {}", message))
+}
+
+fn url(value: &Value) -> Option<Url> {
+    value.as_str().and_then(|text| Url::parse(text.trim()).ok())
+}
+
+fn length(dict: &Value) -> Result<u64, StormError> {
+    dict.get("length")
+        .and_then(Value::as_int)
+        .filter(|n| *n >= 0)
+        .map(|n| n as u64)
+        .ok_or_else(|| invalid("bad file length"))
+}
+
+// One path component: no separators, and nothing that climbs out of the
+// download directory.
+fn sanitize(name: &str) -> String {
+    let name = name.replace(['/', '\\', '\0'], "_");
+    match Path::new(&name).components().next() {
+        Some(Component::Normal(_)) => name,
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn info(files: &[(&str, &[u8])], piece_length: usize) -> (Value, Vec<u8>) {
+        let data: Vec<u8> = files.iter().flat_map(|(_, d)| d.iter().copied()).collect();
+        let pieces: Vec<u8> = data
+            .chunks(piece_length)
+            .flat_map(|chunk| Sha1::digest(chunk).to_vec())
+            .collect();
+        let mut entries = vec![
+            ("piece length", Value::Int(piece_length as i64)),
+            ("pieces", Value::Bytes(pieces)),
+        ];
+        match files {
+            [(name, data)] => {
+                entries.push(("name", Value::bytes(*name)));
+                entries.push(("length", Value::Int(data.len() as i64)));
+            }
+            _ => {
+                entries.push(("name", Value::bytes("set")));
+                let list = files
+                    .iter()
+                    .map(|(path, data)| {
+                        Value::dict(vec![
+                            ("length", Value::Int(data.len() as i64)),
+                            (
+                                "path",
+                                Value::List(path.split('/').map(Value::bytes).collect()),
+                            ),
+                        ])
+                    })
+                    .collect();
+                entries.push(("files", Value::List(list)));
+            }
+        }
+        (Value::dict(entries), data)
+    }
+
+    #[test]
+    fn test_parse_torrent() {
+        let (info, data) = info(&[("a/one.bin", &[1; 300]), ("../two.bin", &[2; 500])], 256);
+        let torrent = bencode::encode(&Value::dict(vec![
+            ("announce", Value::bytes("http://tracker.example/announce")),
+            (
+                "announce-list",
+                Value::List(vec![Value::List(vec![
+                    Value::bytes("udp://tracker.example:6969"),
+                    Value::bytes("http://tracker.example/announce"),
+                ])]),
+            ),
+            ("url-list", Value::bytes("https://mirror.example/pub/")),
+            ("info", info.clone()),
+        ]));
+        let torrent = Torrent::parse(&torrent).unwrap();
+        assert_eq!(torrent.info_hash, InfoHash::of(&bencode::encode(&info)));
+        assert_eq!(torrent.name, "set");
+        assert!(torrent.is_multi_file());
+        assert_eq!(torrent.total_size(), 800);
+        assert_eq!(torrent.pieces.len(), 4);
+        let paths: Vec<&Path> = torrent.files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            [Path::new("set/a/one.bin"), Path::new("set/two.bin")]
+        );
+        assert_eq!(
+            torrent.trackers,
+            [
+                Url::parse("udp://tracker.example:6969").unwrap(),
+                Url::parse("http://tracker.example/announce").unwrap(),
+            ]
+        );
+        assert_eq!(torrent.web_seeds.len(), 1);
+        assert_eq!(torrent.piece_range(3), ByteRange::new(768, 800));
+        assert!(torrent.check_piece(1, &data[256..512]));
+        assert!(!torrent.check_piece(1, &data[..256]));
+        assert_eq!(
+            torrent.spans(torrent.piece_range(1)),
+            [
+                (0, 256, ByteRange::new(256, 300)),
+                (1, 0, ByteRange::new(300, 512))
+            ]
+        );
+
+        let hash = torrent.info_hash.to_string();
+        assert_eq!(InfoHash::parse(&hash), Some(torrent.info_hash));
+        assert_eq!(
+            InfoHash::parse("MFRGGZDFMZTWQ2LKNNWG23TPOBYXE43U")
+                .unwrap()
+                .to_string(),
+            "6162636465666768696a6b6c6d6e6f7071727374"
+        );
+        assert_eq!(InfoHash::parse("xyz"), None);
+
+        let (mut info, _) = super::tests::info(&[("one.bin", &[1; 300])], 256);
+        if let Value::Dict(dict) = &mut info {
+            dict.insert(b"piece length".to_vec(), Value::Int(100));
+        }
+        assert!(Torrent::from_info(&bencode::encode(&info), Vec::new(), Vec::new()).is_err());
+    }
+}
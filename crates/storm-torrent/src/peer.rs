@@ -0,0 +1,374 @@
+use crate::bencode::{self, Value};
+use crate::metainfo::InfoHash;
+use std::net::SocketAddr;
+use std::time::Duration;
+use stormdl_core::StormError;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const BLOCK_SIZE: u32 = 16 * 1024;
+
+const PROTOCOL: &[u8] = b"\x13BitTorrent protocol";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Big enough for a bitfield of 16 million pieces; blocks are far smaller.
+const MAX_MESSAGE: usize = 2 << 20;
+// BEP 9 hands metadata over in 16 KiB pieces; real info dictionaries are a
+// few megabytes at most.
+const MAX_METADATA: usize = 16 << 20;
+// The extended message ID peers send ut_metadata replies to us with.
+const UT_METADATA: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    KeepAlive,
+    Choke,
+    Unchoke,
+    Interested,
+    NotInterested,
+    Have(u32),
+    Bitfield(Vec<u8>),
+    Request {
+        index: u32,
+        begin: u32,
+        length: u32,
+    },
+    Piece {
+        index: u32,
+        begin: u32,
+        data: Vec<u8>,
+    },
+    Cancel {
+        index: u32,
+        begin: u32,
+        length: u32,
+    },
+    // BEP 10; ID 0 is the extension handshake.
+    Extended {
+        id: u8,
+        payload: Vec<u8>,
+    },
+    Other(u8),
+}
+
+impl Message {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        match self {
+            Message::KeepAlive => {}
+            Message::Choke => body.push(0),
+            Message::Unchoke => body.push(1),
+            Message::Interested => body.push(2),
+            Message::NotInterested => body.push(3),
+            Message::Have(index) => {
+                body.push(4);
+                body.extend_from_slice(&index.to_be_bytes());
+            }
+            Message::Bitfield(bits) => {
+                body.push(5);
+                body.extend_from_slice(bits);
+            }
+            Message::Request {
+                index,
+                begin,
+                length,
+            }
+            | Message::Cancel {
+                index,
+                begin,
+                length,
+            } => {
+                body.push(if matches!(self, Message::Request { .. }) {
+                    6
+                } else {
+                    8
+                });
+                for field in [index, begin, length] {
+                    body.extend_from_slice(&field.to_be_bytes());
+                }
+            }
+            Message::Piece { index, begin, data } => {
+                body.push(7);
+                body.extend_from_slice(&index.to_be_bytes());
+                body.extend_from_slice(&begin.to_be_bytes());
+                body.extend_from_slice(data);
+            }
+            Message::Extended { id, payload } => {
+                body.push(20);
+                body.push(*id);
+                body.extend_from_slice(payload);
+            }
+            Message::Other(id) => body.push(*id),
+        }
+        let mut out = (body.len() as u32).to_be_bytes().to_vec();
+        out.extend(body);
+        out
+    }
+
+    pub(crate) fn decode(body: Vec<u8>) -> Result<Self, StormError> {
+        let Some((&id, rest)) = body.split_first() else {
+            return Ok(Message::KeepAlive);
+        };
+        let field = |at: usize| -> Result<u32, StormError> {
+            rest.get(at..at + 4)
+                .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+                .ok_or_else(|| invalid("short message"))
+        };
+        Ok(match id {
+            0 => Message::Choke,
+            1 => Message::Unchoke,
+            2 => Message::Interested,
+            3 => Message::NotInterested,
+            4 => Message::Have(field(0)?),
+            5 => Message::Bitfield(rest.to_vec()),
+            6 | 8 => {
+                let (index, begin, length) = (field(0)?, field(4)?, field(8)?);
+                if id == 6 {
+                    Message::Request {
+                        index,
+                        begin,
+                        length,
+                    }
+                } else {
+                    Message::Cancel {
+                        index,
+                        begin,
+                        length,
+                    }
+                }
+            }
+            7 => Message::Piece {
+                index: field(0)?,
+                begin: field(4)?,
+                data: rest.get(8..).unwrap_or_default().to_vec(),
+            },
+            20 => Message::Extended {
+                id: *rest.first().ok_or_else(|| invalid("short message"))?,
+                payload: rest[1..].to_vec(),
+            },
+            id => Message::Other(id),
+        })
+    }
+}
+
+// A connection to one peer, past the handshake.
+pub struct Peer {
+    stream: TcpStream,
+    // Read but not yet returned, so a recv() cut short by a timeout loses
+    // nothing.
+    buf: Vec<u8>,
+    pub addr: SocketAddr,
+    pub peer_id: [u8; 20],
+    // Whether the peer speaks the extension protocol (BEP 10).
+    pub extensions: bool,
+}
+
+impl Peer {
+    pub async fn connect(
+        addr: SocketAddr,
+        info_hash: InfoHash,
+        peer_id: [u8; 20],
+    ) -> Result<Self, StormError> {
+        let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| StormError::Timeout(format!("connecting to peer {}", addr)))?
+            .map_err(|e| StormError::Network(format!("peer {}: {}", addr, e)))?;
+        let mut peer = Self {
+            stream,
+            buf: Vec::new(),
+            addr,
+            peer_id: [0; 20],
+            extensions: false,
+        };
+        tokio::time::timeout(CONNECT_TIMEOUT, peer.handshake(info_hash, peer_id))
+            .await
+            .map_err(|_| StormError::Timeout(format!("handshake with peer {}", addr)))??;
+        Ok(peer)
+    }
+
+    async fn handshake(
+        &mut self,
+        info_hash: InfoHash,
+        peer_id: [u8; 20],
+    ) -> Result<(), StormError> {
+        let mut reserved = [0u8; 8];
+        reserved[5] |= 0x10;
+        let mut hello = PROTOCOL.to_vec();
+        hello.extend_from_slice(&reserved);
+        hello.extend_from_slice(&info_hash.0);
+        hello.extend_from_slice(&peer_id);
+        self.stream.write_all(&hello).await?;
+
+        let mut reply = [0u8; 68];
+        self.stream.read_exact(&mut reply).await?;
+        if reply[..20] != *PROTOCOL {
+            return Err(invalid("not a BitTorrent peer"));
+        }
+        if reply[28..48] != info_hash.0 {
+            return Err(invalid("peer is serving another torrent"));
+        }
+        self.peer_id.copy_from_slice(&reply[48..]);
+        self.extensions = reply[25] & 0x10 != 0;
+        if self.extensions {
+            let handshake = Value::dict(vec![
+                (
+                    "m",
+                    Value::dict(vec![("ut_metadata", Value::Int(UT_METADATA as i64))]),
+                ),
+                (
+                    "v",
+                    Value::bytes(concat!("storm ", env!("CARGO_PKG_VERSION"))),
+                ),
+            ]);
+            self.send(&Message::Extended {
+                id: 0,
+                payload: bencode::encode(&handshake),
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn send(&mut self, message: &Message) -> Result<(), StormError> {
+        self.stream.write_all(&message.encode()).await?;
+        Ok(())
+    }
+
+    pub async fn recv(&mut self) -> Result<Message, StormError> {
+        loop {
+            if let Some(prefix) = self.buf.get(..4) {
+                let len = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+                if len > MAX_MESSAGE {
+                    return Err(invalid("message too large"));
+                }
+                if self.buf.len() >= 4 + len {
+                    let body = self.buf[4..4 + len].to_vec();
+                    self.buf.drain(..4 + len);
+                    return Message::decode(body);
+                }
+            }
+            self.buf.reserve(BLOCK_SIZE as usize);
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(StormError::Network(format!(
+                    "peer {} disconnected",
+                    self.addr
+                )));
+            }
+        }
+    }
+
+    // The torrent's info dictionary, asked of a peer that has it (BEP 9)
+    // and checked against the info hash.
+    pub async fn fetch_metadata(&mut self, info_hash: InfoHash) -> Result<Vec<u8>, StormError> {
+        if !self.extensions {
+            return Err(invalid("peer can't send metadata"));
+        }
+        let (their_id, size) = loop {
+            if let Message::Extended { id: 0, payload } = self.recv().await? {
+                let handshake = bencode::decode(&payload)?;
+                let id = handshake
+                    .get("m")
+                    .and_then(|m| m.get("ut_metadata"))
+                    .and_then(Value::as_int)
+                    .filter(|id| (1..=255).contains(id));
+                let size = handshake
+                    .get("metadata_size")
+                    .and_then(Value::as_int)
+                    .filter(|size| *size > 0 && *size as usize <= MAX_METADATA);
+                match (id, size) {
+                    (Some(id), Some(size)) => break (id as u8, size as usize),
+                    _ => return Err(invalid("peer can't send metadata")),
+                }
+            }
+        };
+
+        let mut metadata = vec![0; size];
+        for piece in 0..size.div_ceil(BLOCK_SIZE as usize) {
+            let request = Value::dict(vec![
+                ("msg_type", Value::Int(0)),
+                ("piece", Value::Int(piece as i64)),
+            ]);
+            self.send(&Message::Extended {
+                id: their_id,
+                payload: bencode::encode(&request),
+            })
+            .await?;
+            let data = loop {
+                let Message::Extended {
+                    id: UT_METADATA,
+                    payload,
+                } = self.recv().await?
+                else {
+                    continue;
+                };
+                let (header, used) = bencode::decode_prefix(&payload)?;
+                match header.get("msg_type").and_then(Value::as_int) {
+                    Some(1)
+                        if header.get("piece").and_then(Value::as_int) == Some(piece as i64) =>
+                    {
+                        break payload[used..].to_vec();
+                    }
+                    Some(2) => return Err(invalid("peer refused to send metadata")),
+                    _ => {}
+                }
+            };
+            let start = piece * BLOCK_SIZE as usize;
+            let end = (start + BLOCK_SIZE as usize).min(size);
+            if data.len() != end - start {
+                return Err(invalid("metadata piece has the wrong size"));
+            }
+            metadata[start..end].copy_from_slice(&data);
+        }
+        if InfoHash::of(&metadata) != info_hash {
+            return Err(invalid("metadata doesn't match the info hash"));
+        }
+        Ok(metadata)
+    }
+}
+
+fn invalid(message: &str) -> StormError {
+    StormError::Protocol(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages() {
+        let messages = [
+            Message::KeepAlive,
+            Message::Unchoke,
+            Message::Have(7),
+            Message::Bitfield(vec![0b1010_0000]),
+            Message::Request {
+                index: 1,
+                begin: 16384,
+                length: 16384,
+            },
+            Message::Cancel {
+                index: 1,
+                begin: 0,
+                length: 5,
+            },
+            Message::Piece {
+                index: 2,
+                begin: 0,
+                data: b"abc".to_vec(),
+            },
+            Message::Extended {
+                id: 0,
+                payload: b"de".to_vec(),
+            },
+        ];
+        for message in messages {
+            let bytes = message.encode();
+            assert_eq!(
+                u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize,
+                bytes.len() - 4
+            );
+            assert_eq!(Message::decode(bytes[4..].to_vec()).unwrap(), message);
+        }
+        assert!(Message::decode(vec![4, 0, 0]).is_err());
+        assert_eq!(Message::decode(vec![9, 1]).unwrap(), Message::Other(9));
+    }
+}
@@ -0,0 +1,182 @@
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+// Sources racing for the same piece once nothing is left unclaimed, so one
+// slow peer can't hold up the end of a download.
+const MAX_CLAIMANTS: u32 = 2;
+
+// The piece bitmap peers and web seeds share: each piece is claimed by one
+// source at a time and counted once, whoever finishes it.
+pub struct PieceMap {
+    inner: Mutex<Inner>,
+    changed: Notify,
+}
+
+struct Inner {
+    states: Vec<State>,
+    // How many connected peers have each piece.
+    availability: Vec<u32>,
+    remaining: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Missing,
+    Claimed(u32),
+    Done,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pick {
+    // Peers take the rarest piece they have, spreading the swarm's copies.
+    Rarest,
+    // Web seeds have every piece and go front to back.
+    InOrder,
+}
+
+impl PieceMap {
+    pub fn new(count: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                states: vec![State::Missing; count],
+                availability: vec![0; count],
+                remaining: count,
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn done_count(&self) -> usize {
+        let inner = self.inner.lock();
+        inner.states.len() - inner.remaining
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.inner.lock().remaining == 0
+    }
+
+    pub fn has(&self, index: usize) -> bool {
+        self.inner.lock().states.get(index) == Some(&State::Done)
+    }
+
+    // The next piece for a source that has the pieces `has` accepts, or
+    // None when there's nothing it can help with.
+    pub fn claim(&self, pick: Pick, has: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let missing =
+            (0..inner.states.len()).filter(|&i| inner.states[i] == State::Missing && has(i));
+        let index = match pick {
+            Pick::Rarest => missing.min_by_key(|&i| inner.availability[i]),
+            Pick::InOrder => missing.min(),
+        };
+        let index = index.or_else(|| {
+            (0..inner.states.len())
+                .filter(|&i| has(i))
+                .filter_map(|i| match inner.states[i] {
+                    State::Claimed(n) if n < MAX_CLAIMANTS => Some((n, i)),
+                    _ => None,
+                })
+                .min()
+                .map(|(_, i)| i)
+        })?;
+        inner.states[index] = match inner.states[index] {
+            State::Claimed(n) => State::Claimed(n + 1),
+            _ => State::Claimed(1),
+        };
+        Some(index)
+    }
+
+    // Gives a claimed piece up, e.g. when its source failed it.
+    pub fn release(&self, index: usize) {
+        let mut inner = self.inner.lock();
+        inner.states[index] = match inner.states[index] {
+            State::Claimed(n) if n > 1 => State::Claimed(n - 1),
+            State::Claimed(_) => State::Missing,
+            state => state,
+        };
+    }
+
+    // Records a verified piece; false when another source got there first
+    // and this copy isn't needed.
+    pub fn complete(&self, index: usize) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.states[index] == State::Done {
+            return false;
+        }
+        inner.states[index] = State::Done;
+        inner.remaining -= 1;
+        drop(inner);
+        self.changed.notify_waiters();
+        true
+    }
+
+    pub fn add_availability(&self, pieces: impl IntoIterator<Item = usize>) {
+        let mut inner = self.inner.lock();
+        for index in pieces {
+            if let Some(count) = inner.availability.get_mut(index) {
+                *count += 1;
+            }
+        }
+    }
+
+    pub fn remove_availability(&self, pieces: impl IntoIterator<Item = usize>) {
+        let mut inner = self.inner.lock();
+        for index in pieces {
+            if let Some(count) = inner.availability.get_mut(index) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+
+    // Resolves once every piece is done.
+    pub async fn finished(&self) {
+        loop {
+            let changed = self.changed.notified();
+            if self.is_done() {
+                return;
+            }
+            changed.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims() {
+        let map = PieceMap::new(4);
+        map.add_availability([0, 1, 2, 3]);
+        map.add_availability([0, 1, 3]);
+        assert!(map.complete(1));
+        assert_eq!(map.claim(Pick::Rarest, |_| true), Some(2));
+        assert_eq!(map.claim(Pick::InOrder, |_| true), Some(0));
+        // Nothing unclaimed that this source has: it doubles up on 2.
+        assert_eq!(map.claim(Pick::Rarest, |i| i == 2), Some(2));
+        // Endgame: 3 is the last unclaimed piece, then 0 takes a second
+        // source; 2 already has two.
+        assert_eq!(map.claim(Pick::InOrder, |_| true), Some(3));
+        assert_eq!(map.claim(Pick::InOrder, |i| i != 3), Some(0));
+        assert_eq!(map.claim(Pick::InOrder, |i| i == 2), None);
+
+        map.release(3);
+        assert_eq!(map.claim(Pick::Rarest, |i| i == 3), Some(3));
+        assert!(map.complete(0));
+        assert!(!map.complete(0));
+        map.release(0);
+        assert!(map.has(0));
+        assert_eq!(map.done_count(), 2);
+        assert!(map.complete(2) && map.complete(3));
+        assert!(map.is_done());
+    }
+}
@@ -0,0 +1,837 @@
+use crate::magnet::Magnet;
+use crate::metainfo::{InfoHash, Torrent};
+use crate::peer::{BLOCK_SIZE, Message, Peer};
+use crate::picker::{Pick, PieceMap};
+use crate::storage::Storage;
+use crate::tracker::{self, Announce, Event};
+use crate::webseed::WebSeeds;
+use sha1::{Digest, Sha1};
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use stormdl_bandwidth::RateLimiter;
+use stormdl_core::{Downloader, StormError};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use url::Url;
+
+const MAX_PEERS: usize = 30;
+// Block requests kept in flight per peer.
+const PIPELINE: usize = 16;
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+const IDLE_TICK: Duration = Duration::from_secs(1);
+// Pieces a peer may fail its hash check on before it's dropped.
+const MAX_BAD_PIECES: usize = 3;
+// HTTP connections per web seed, and across all of them.
+const SEED_CONNECTIONS: usize = 2;
+const MAX_SEED_WORKERS: usize = 8;
+// A web seed worker stops after this many failures in a row, leaving the
+// pieces to the swarm and the other mirrors.
+const MAX_SEED_FAILURES: u32 = 5;
+// A tracker is dropped after failing this many announces in a row.
+const MAX_TRACKER_FAILURES: u32 = 3;
+const TRACKER_RETRY: Duration = Duration::from_secs(30);
+const MIN_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+// Peers asked for a magnet link's metadata at once.
+const METADATA_PEERS: usize = 8;
+// Storm doesn't accept incoming connections; trackers still want a port.
+const ANNOUNCE_PORT: u16 = 6881;
+
+// One torrent's download: peers from trackers and web seeds claim pieces
+// from the same map, and each piece is checked against its hash before
+// it's written.
+pub struct Session {
+    torrent: Arc<Torrent>,
+    storage: Storage,
+    downloader: Arc<dyn Downloader>,
+    peer_id: [u8; 20],
+    web_seeds: Vec<Url>,
+    peers: Vec<SocketAddr>,
+    limiter: Arc<RateLimiter>,
+    progress: Arc<Progress>,
+}
+
+// Live counts for a progress display.
+pub struct Progress {
+    pub map: PieceMap,
+    pub downloaded: Arc<AtomicU64>,
+    pub from_peers: AtomicU64,
+    pub from_web_seeds: AtomicU64,
+    pub peers: AtomicUsize,
+    pub web_seeds: AtomicUsize,
+}
+
+struct Shared {
+    torrent: Arc<Torrent>,
+    storage: Storage,
+    progress: Arc<Progress>,
+    limiter: Arc<RateLimiter>,
+    peer_id: [u8; 20],
+}
+
+impl Session {
+    // Opens (or creates) the torrent's files under `dir`; web seeds from
+    // the torrent come along.
+    pub fn new(
+        torrent: Torrent,
+        dir: &Path,
+        downloader: Arc<dyn Downloader>,
+    ) -> Result<Self, StormError> {
+        let storage = Storage::open(dir, &torrent)?;
+        let progress = Progress::new(torrent.pieces.len(), Arc::default());
+        Ok(Self {
+            web_seeds: torrent.web_seeds.clone(),
+            torrent: Arc::new(torrent),
+            storage,
+            downloader,
+            peer_id: peer_id(),
+            peers: Vec::new(),
+            limiter: Arc::new(RateLimiter::unlimited()),
+            progress,
+        })
+    }
+
+    // More HTTP mirrors of the torrent's content, e.g. a magnet's ws=.
+    pub fn with_web_seeds(mut self, urls: impl IntoIterator<Item = Url>) -> Self {
+        for url in urls {
+            if !self.web_seeds.contains(&url) {
+                self.web_seeds.push(url);
+            }
+        }
+        self
+    }
+
+    // Peers to try besides the trackers'.
+    pub fn with_peers(mut self, peers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.peers.extend(peers);
+        self
+    }
+
+    pub fn with_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    // Counts received bytes into `counter`; set it before taking
+    // progress().
+    pub fn with_counter(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.progress = Progress::new(self.torrent.pieces.len(), counter);
+        self
+    }
+
+    pub fn torrent(&self) -> &Torrent {
+        &self.torrent
+    }
+
+    pub fn progress(&self) -> Arc<Progress> {
+        self.progress.clone()
+    }
+
+    // Pieces already on disk from an earlier run are kept; the rest are
+    // fetched until every piece is done.
+    pub async fn run(self) -> Result<(), StormError> {
+        let Session {
+            torrent,
+            storage,
+            downloader,
+            peer_id,
+            web_seeds,
+            peers,
+            limiter,
+            progress,
+        } = self;
+        for index in storage.recheck(&torrent)? {
+            progress.map.complete(index);
+        }
+        if progress.map.is_done() {
+            return Ok(());
+        }
+        let shared = Arc::new(Shared {
+            torrent: torrent.clone(),
+            storage,
+            progress: progress.clone(),
+            limiter,
+            peer_id,
+        });
+
+        let mut seeds = JoinSet::new();
+        if !web_seeds.is_empty() {
+            let workers = (web_seeds.len() * SEED_CONNECTIONS).min(MAX_SEED_WORKERS);
+            let web_seeds = Arc::new(WebSeeds::new(
+                web_seeds,
+                torrent.clone(),
+                downloader.clone(),
+            ));
+            for _ in 0..workers {
+                seeds.spawn(seed_worker(web_seeds.clone(), shared.clone()));
+            }
+        }
+
+        let (found, mut discovered) = mpsc::unbounded_channel();
+        let _ = found.send(peers);
+        let trackers = torrent.trackers.clone();
+        let announcing = tokio::spawn(track(
+            trackers.clone(),
+            downloader.clone(),
+            shared.clone(),
+            found,
+        ));
+
+        let mut connections = JoinSet::new();
+        let mut queue = VecDeque::new();
+        let mut seen = HashSet::new();
+        let mut tracking = true;
+        let result = loop {
+            while connections.len() < MAX_PEERS
+                && let Some(addr) = queue.pop_front()
+            {
+                connections.spawn(download_from(addr, shared.clone()));
+            }
+            if !tracking && queue.is_empty() && connections.is_empty() && seeds.is_empty() {
+                break Err(StormError::Network(
+                    "No peers or web seeds left to download from".into(),
+                ));
+            }
+            tokio::select! {
+                _ = progress.map.finished() => break Ok(()),
+                addrs = discovered.recv(), if tracking => match addrs {
+                    Some(addrs) => queue.extend(addrs.into_iter().filter(|addr| seen.insert(*addr))),
+                    None => tracking = false,
+                },
+                Some(joined) = connections.join_next() => {
+                    if let Ok(Err(e)) = joined {
+                        break Err(e);
+                    }
+                }
+                Some(joined) = seeds.join_next() => {
+                    if let Ok(Err(e)) = joined {
+                        break Err(e);
+                    }
+                }
+            }
+        };
+        announcing.abort();
+        connections.shutdown().await;
+        seeds.shutdown().await;
+        shared.storage.sync()?;
+        if result.is_ok() {
+            announce_all(&trackers, &*downloader, &shared, Some(Event::Completed)).await;
+        }
+        result
+    }
+}
+
+impl Progress {
+    fn new(pieces: usize, downloaded: Arc<AtomicU64>) -> Arc<Self> {
+        Arc::new(Self {
+            map: PieceMap::new(pieces),
+            downloaded,
+            from_peers: AtomicU64::new(0),
+            from_web_seeds: AtomicU64::new(0),
+            peers: AtomicUsize::new(0),
+            web_seeds: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn pieces(&self) -> (usize, usize) {
+        (self.map.done_count(), self.map.len())
+    }
+}
+
+// The torrent behind a magnet link, fetched from the peers its trackers
+// (or its x.pe hints) name. There's no DHT, so a magnet without either
+// can't be resolved.
+pub async fn resolve(
+    magnet: &Magnet,
+    downloader: &dyn Downloader,
+) -> Result<(Torrent, Vec<SocketAddr>), StormError> {
+    let peer_id = peer_id();
+    let mut peers = magnet.peers.clone();
+    let mut errors = Vec::new();
+    let request = Announce {
+        info_hash: magnet.info_hash,
+        peer_id,
+        port: ANNOUNCE_PORT,
+        downloaded: 0,
+        // The size isn't known yet; anything above zero marks a leecher.
+        left: 1,
+        event: None,
+    };
+    for tracker in &magnet.trackers {
+        match tracker::announce(downloader, tracker, &request).await {
+            Ok(response) => peers.extend(response.peers),
+            Err(e) => errors.push(format!("{}: {}", tracker, e)),
+        }
+    }
+    let mut seen = HashSet::new();
+    peers.retain(|addr| seen.insert(*addr));
+    if peers.is_empty() {
+        return Err(StormError::Network(if magnet.trackers.is_empty() {
+            "Magnet link names no trackers or peers to fetch the torrent from (DHT isn't supported)"
+                .into()
+        } else {
+            format!("No peers for the magnet link ({})", errors.join("; "))
+        }));
+    }
+
+    let mut pending = peers.clone().into_iter();
+    let mut fetches = JoinSet::new();
+    let mut failed = 0;
+    loop {
+        while fetches.len() < METADATA_PEERS
+            && let Some(addr) = pending.next()
+        {
+            fetches.spawn(fetch_metadata(addr, magnet.info_hash, peer_id));
+        }
+        let Some(joined) = fetches.join_next().await else {
+            return Err(StormError::Network(format!(
+                "None of {} peers sent the torrent's metadata",
+                failed
+            )));
+        };
+        match joined {
+            Ok(Ok(info)) => {
+                let torrent =
+                    Torrent::from_info(&info, magnet.trackers.clone(), magnet.web_seeds.clone())?;
+                return Ok((torrent, peers));
+            }
+            Ok(Err(e)) => {
+                tracing::debug!("metadata: {}", e);
+                failed += 1;
+            }
+            Err(_) => failed += 1,
+        }
+    }
+}
+
+async fn fetch_metadata(
+    addr: SocketAddr,
+    info_hash: InfoHash,
+    peer_id: [u8; 20],
+) -> Result<Vec<u8>, StormError> {
+    let mut peer = Peer::connect(addr, info_hash, peer_id).await?;
+    tokio::time::timeout(PEER_TIMEOUT, peer.fetch_metadata(info_hash))
+        .await
+        .map_err(|_| StormError::Timeout(format!("metadata from peer {}", addr)))?
+}
+
+// Announces to every tracker, passing new peers on, until the trackers
+// have all failed for good.
+async fn track(
+    trackers: Vec<Url>,
+    downloader: Arc<dyn Downloader>,
+    shared: Arc<Shared>,
+    found: mpsc::UnboundedSender<Vec<SocketAddr>>,
+) {
+    let mut failures = vec![0u32; trackers.len()];
+    let mut event = Some(Event::Started);
+    loop {
+        let mut next = None::<Duration>;
+        for (tracker, failures) in trackers.iter().zip(&mut failures) {
+            if *failures >= MAX_TRACKER_FAILURES {
+                continue;
+            }
+            match tracker::announce(&*downloader, tracker, &shared.announce(event)).await {
+                Ok(response) => {
+                    *failures = 0;
+                    let interval = response.interval.max(MIN_ANNOUNCE_INTERVAL);
+                    next = Some(next.map_or(interval, |next| next.min(interval)));
+                    if found.send(response.peers).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!("tracker {}: {}", tracker, e);
+                    *failures += 1;
+                }
+            }
+        }
+        if failures.iter().all(|n| *n >= MAX_TRACKER_FAILURES) {
+            return;
+        }
+        event = None;
+        tokio::time::sleep(next.unwrap_or(TRACKER_RETRY)).await;
+    }
+}
+
+// Best effort: a finished download shouldn't wait on slow trackers.
+async fn announce_all(
+    trackers: &[Url],
+    downloader: &dyn Downloader,
+    shared: &Shared,
+    event: Option<Event>,
+) {
+    let announces = async {
+        for tracker in trackers {
+            let _ = tracker::announce(downloader, tracker, &shared.announce(event)).await;
+        }
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(5), announces).await;
+}
+
+impl Shared {
+    fn announce(&self, event: Option<Event>) -> Announce {
+        let have: u64 = (0..self.torrent.pieces.len())
+            .filter(|&i| self.progress.map.has(i))
+            .map(|i| self.torrent.piece_range(i).len())
+            .sum();
+        Announce {
+            info_hash: self.torrent.info_hash,
+            peer_id: self.peer_id,
+            port: ANNOUNCE_PORT,
+            downloaded: self.progress.downloaded.load(Ordering::Relaxed),
+            left: self.torrent.total_size() - have,
+            event,
+        }
+    }
+
+    // Writes a verified piece unless another source already did.
+    fn finish_piece(&self, index: usize, data: &[u8], from: &AtomicU64) -> Result<(), StormError> {
+        if self.progress.map.complete(index) {
+            self.storage
+                .write(&self.torrent, self.torrent.piece_range(index), data)?;
+            from.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+async fn seed_worker(seeds: Arc<WebSeeds>, shared: Arc<Shared>) -> Result<(), StormError> {
+    let progress = &shared.progress;
+    progress.web_seeds.fetch_add(1, Ordering::Relaxed);
+    let _active = Counted(&progress.web_seeds);
+    let mut failures = 0;
+    loop {
+        let Some(index) = progress.map.claim(Pick::InOrder, |_| true) else {
+            if progress.map.is_done() {
+                return Ok(());
+            }
+            // Everything left is with peers for now; one may give a
+            // piece back.
+            tokio::time::sleep(IDLE_TICK).await;
+            continue;
+        };
+        let claim = Claim {
+            map: &progress.map,
+            index,
+        };
+        match seeds
+            .fetch_piece(index, &progress.downloaded, &shared.limiter)
+            .await
+        {
+            Ok(data) if shared.torrent.check_piece(index, &data) => {
+                failures = 0;
+                std::mem::forget(claim);
+                shared.finish_piece(index, &data, &progress.from_web_seeds)?;
+            }
+            Ok(_) => {
+                tracing::debug!("web seed sent a bad copy of piece {}", index);
+                seeds.reject(index);
+                failures += 1;
+            }
+            Err(e) => {
+                tracing::debug!("web seed: piece {}: {}", index, e);
+                failures += 1;
+            }
+        }
+        if failures >= MAX_SEED_FAILURES {
+            return Ok(());
+        }
+        if failures > 0 {
+            tokio::time::sleep(Duration::from_secs(failures as u64)).await;
+        }
+    }
+}
+
+async fn download_from(addr: SocketAddr, shared: Arc<Shared>) -> Result<(), StormError> {
+    let result = async {
+        let peer = Peer::connect(addr, shared.torrent.info_hash, shared.peer_id).await?;
+        PeerSession::new(peer, &shared).exchange().await
+    };
+    match result.await {
+        Ok(()) => Ok(()),
+        Err(Ended::Storage(e)) => Err(e),
+        Err(Ended::Peer(e)) => {
+            tracing::debug!("peer {}: {}", addr, e);
+            Ok(())
+        }
+    }
+}
+
+// Why a connection ended: storage errors end the whole download, anything
+// the peer does only its connection.
+enum Ended {
+    Peer(StormError),
+    Storage(StormError),
+}
+
+impl From<StormError> for Ended {
+    fn from(e: StormError) -> Self {
+        Ended::Peer(e)
+    }
+}
+
+struct PeerSession<'a> {
+    peer: Peer,
+    shared: &'a Shared,
+    has: Vec<bool>,
+    choked: bool,
+    current: Option<PieceBuffer>,
+    bad_pieces: usize,
+}
+
+struct PieceBuffer {
+    index: usize,
+    data: Vec<u8>,
+    received: usize,
+    requested: usize,
+    outstanding: usize,
+}
+
+impl<'a> PeerSession<'a> {
+    fn new(peer: Peer, shared: &'a Shared) -> Self {
+        shared.progress.peers.fetch_add(1, Ordering::Relaxed);
+        Self {
+            peer,
+            shared,
+            has: vec![false; shared.torrent.pieces.len()],
+            choked: true,
+            current: None,
+            bad_pieces: 0,
+        }
+    }
+
+    async fn exchange(&mut self) -> Result<(), Ended> {
+        self.peer.send(&Message::Interested).await?;
+        let mut heard = Instant::now();
+        loop {
+            // Ticks between messages let an idle peer pick up pieces that
+            // others gave back.
+            let message = match tokio::time::timeout(IDLE_TICK, self.peer.recv()).await {
+                Ok(message) => {
+                    heard = Instant::now();
+                    Some(message?)
+                }
+                Err(_) if heard.elapsed() > PEER_TIMEOUT => {
+                    return Err(StormError::Timeout(format!("peer {}", self.peer.addr)).into());
+                }
+                Err(_) => None,
+            };
+            match message {
+                Some(Message::Bitfield(bits)) => {
+                    let pieces: Vec<usize> = (0..self.has.len())
+                        .filter(|i| bits.get(i / 8).is_some_and(|b| b & (0x80 >> (i % 8)) != 0))
+                        .collect();
+                    self.gain(pieces);
+                }
+                Some(Message::Have(index)) => self.gain([index as usize]),
+                Some(Message::Unchoke) => self.choked = false,
+                Some(Message::Choke) => {
+                    // A choking peer drops our requests; the piece goes back.
+                    self.choked = true;
+                    self.drop_piece();
+                }
+                Some(Message::Piece { index, begin, data }) => {
+                    self.receive(index, begin, data).await?
+                }
+                _ => {}
+            }
+            if !self.choked {
+                self.request().await?;
+            }
+        }
+    }
+
+    fn gain(&mut self, pieces: impl IntoIterator<Item = usize>) {
+        let new: Vec<usize> = pieces
+            .into_iter()
+            .filter(|&i| i < self.has.len() && !self.has[i])
+            .collect();
+        for &i in &new {
+            self.has[i] = true;
+        }
+        self.shared.progress.map.add_availability(new);
+    }
+
+    async fn receive(&mut self, index: u32, begin: u32, data: Vec<u8>) -> Result<(), Ended> {
+        let progress = &self.shared.progress;
+        progress
+            .downloaded
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.shared.limiter.acquire(data.len()).await;
+        let Some(piece) = &mut self.current else {
+            return Ok(());
+        };
+        let begin = begin as usize;
+        if piece.index != index as usize || begin + data.len() > piece.data.len() {
+            return Ok(());
+        }
+        piece.data[begin..begin + data.len()].copy_from_slice(&data);
+        piece.received += data.len();
+        piece.outstanding = piece.outstanding.saturating_sub(1);
+        if piece.received < piece.data.len() {
+            return Ok(());
+        }
+        let piece = self.current.take().unwrap();
+        if self.shared.torrent.check_piece(piece.index, &piece.data) {
+            self.shared
+                .finish_piece(piece.index, &piece.data, &progress.from_peers)
+                .map_err(Ended::Storage)?;
+        } else {
+            progress.map.release(piece.index);
+            self.bad_pieces += 1;
+            if self.bad_pieces >= MAX_BAD_PIECES {
+                return Err(StormError::Protocol("too many bad pieces".into()).into());
+            }
+        }
+        Ok(())
+    }
+
+    async fn request(&mut self) -> Result<(), StormError> {
+        if self.current.is_none() {
+            let has = &self.has;
+            let Some(index) = self.shared.progress.map.claim(Pick::Rarest, |i| has[i]) else {
+                return Ok(());
+            };
+            let len = self.shared.torrent.piece_range(index).len() as usize;
+            self.current = Some(PieceBuffer {
+                index,
+                data: vec![0; len],
+                received: 0,
+                requested: 0,
+                outstanding: 0,
+            });
+        }
+        let piece = self.current.as_mut().unwrap();
+        while piece.outstanding < PIPELINE && piece.requested < piece.data.len() {
+            let length = (piece.data.len() - piece.requested).min(BLOCK_SIZE as usize);
+            self.peer
+                .send(&Message::Request {
+                    index: piece.index as u32,
+                    begin: piece.requested as u32,
+                    length: length as u32,
+                })
+                .await?;
+            piece.requested += length;
+            piece.outstanding += 1;
+        }
+        Ok(())
+    }
+
+    fn drop_piece(&mut self) {
+        if let Some(piece) = self.current.take() {
+            self.shared.progress.map.release(piece.index);
+        }
+    }
+}
+
+impl Drop for PeerSession<'_> {
+    fn drop(&mut self) {
+        self.drop_piece();
+        let has = &self.has;
+        self.shared
+            .progress
+            .map
+            .remove_availability((0..has.len()).filter(|&i| has[i]));
+        self.shared.progress.peers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Puts a claimed piece back unless it's been handed on.
+struct Claim<'a> {
+    map: &'a PieceMap,
+    index: usize,
+}
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.map.release(self.index);
+    }
+}
+
+struct Counted<'a>(&'a AtomicUsize);
+
+impl Drop for Counted<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Azureus-style: client code and version, then bytes unique to this run.
+fn peer_id() -> [u8; 20] {
+    let version: String = env!("CARGO_PKG_VERSION")
+        .chars()
+        .filter(char::is_ascii_digit)
+        .chain(std::iter::repeat('0'))
+        .take(4)
+        .collect();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let seed = Sha1::digest(format!("{}-{}", nanos, std::process::id()));
+    let mut id = [0u8; 20];
+    id[..8].copy_from_slice(format!("-SD{}-", version).as_bytes());
+    for (byte, seed) in id[8..].iter_mut().zip(seed.iter()) {
+        *byte = b"0123456789abcdefghijklmnopqrstuvwxyz"[*seed as usize % 36];
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::{self, Value};
+    use crate::metainfo::tests::info;
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use stormdl_core::{ByteRange, DataSink, ResourceInfo};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const PIECE: usize = 32 * 1024;
+
+    // An HTTP mirror that only has /file.bin, and is slow about it.
+    struct Mirror(Vec<u8>);
+
+    #[async_trait]
+    impl Downloader for Mirror {
+        async fn probe(&self, _: &Url) -> Result<ResourceInfo, StormError> {
+            Err(StormError::Other("not probed".into()))
+        }
+
+        async fn fetch_range(
+            &self,
+            url: &Url,
+            range: ByteRange,
+            sink: &mut dyn DataSink,
+        ) -> Result<(), StormError> {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if url.path() != "/file.bin" {
+                return Err(StormError::Http {
+                    status: 404,
+                    message: "Not Found".into(),
+                });
+            }
+            sink.write(Bytes::copy_from_slice(
+                &self.0[range.start as usize..range.end as usize],
+            ))
+        }
+
+        async fn fetch_full(&self, _: &Url, _: &mut dyn DataSink) -> Result<(), StormError> {
+            Err(StormError::Other("not fetched".into()))
+        }
+    }
+
+    // A peer with the even pieces, which also hands out the metadata.
+    async fn seed(listener: TcpListener, torrent: Torrent, data: Vec<u8>) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(serve(stream, torrent.clone(), data.clone()));
+        }
+    }
+
+    async fn serve(mut stream: TcpStream, torrent: Torrent, data: Vec<u8>) -> std::io::Result<()> {
+        let mut hello = [0u8; 68];
+        stream.read_exact(&mut hello).await?;
+        hello[48..].copy_from_slice(b"-XX0000-seedseedseed");
+        stream.write_all(&hello).await?;
+        let bits: Vec<u8> = (0..torrent.pieces.len().div_ceil(8))
+            .map(|_| 0b1010_1010)
+            .collect();
+        let handshake = Value::dict(vec![
+            ("m", Value::dict(vec![("ut_metadata", Value::Int(3))])),
+            ("metadata_size", Value::Int(torrent.info.len() as i64)),
+        ]);
+        for message in [
+            Message::Bitfield(bits),
+            Message::Unchoke,
+            Message::Extended {
+                id: 0,
+                payload: bencode::encode(&handshake),
+            },
+        ] {
+            stream.write_all(&message.encode()).await?;
+        }
+        loop {
+            let len = stream.read_u32().await? as usize;
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body).await?;
+            let reply = match Message::decode(body).unwrap() {
+                Message::Request {
+                    index,
+                    begin,
+                    length,
+                } if index % 2 == 0 => {
+                    let start = index as usize * PIECE + begin as usize;
+                    Message::Piece {
+                        index,
+                        begin,
+                        data: data[start..start + length as usize].to_vec(),
+                    }
+                }
+                Message::Extended { id: 3, payload } => {
+                    let request = bencode::decode(&payload).unwrap();
+                    let piece = request.get("piece").and_then(Value::as_int).unwrap();
+                    let mut payload = bencode::encode(&Value::dict(vec![
+                        ("msg_type", Value::Int(1)),
+                        ("piece", Value::Int(piece)),
+                        ("total_size", Value::Int(torrent.info.len() as i64)),
+                    ]));
+                    payload.extend_from_slice(&torrent.info);
+                    Message::Extended { id: 1, payload }
+                }
+                _ => continue,
+            };
+            stream.write_all(&reply.encode()).await?;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_swarm_and_web_seeds_share_pieces() {
+        let data: Vec<u8> = (0..PIECE * 6 + 1000).map(|i| (i % 253) as u8).collect();
+        let (info, _) = info(&[("file.bin", &data)], PIECE);
+        let torrent = Torrent::from_info(&bencode::encode(&info), Vec::new(), Vec::new()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(seed(listener, torrent.clone(), data.clone()));
+
+        let magnet = Url::parse(&format!(
+            "magnet:?xt=urn:btih:{}&x.pe={}",
+            torrent.info_hash, addr
+        ))
+        .unwrap();
+        let mirror = Arc::new(Mirror(data.clone()));
+        let (resolved, peers) = resolve(&Magnet::parse(&magnet).unwrap(), &*mirror)
+            .await
+            .unwrap();
+        assert_eq!(resolved, torrent);
+        assert_eq!(peers, [addr]);
+
+        let dir = std::env::temp_dir().join(format!("storm-swarm-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let counter = Arc::new(AtomicU64::new(0));
+        let session = Session::new(resolved, &dir, mirror)
+            .unwrap()
+            .with_web_seeds([
+                Url::parse("http://gone.example/file.bin").unwrap(),
+                Url::parse("http://mirror.example/file.bin").unwrap(),
+            ])
+            .with_peers(peers)
+            .with_counter(counter.clone());
+        let progress = session.progress();
+        session.run().await.unwrap();
+
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), data);
+        assert_eq!(progress.pieces(), (7, 7));
+        let from_peers = progress.from_peers.load(Ordering::Relaxed);
+        let from_web_seeds = progress.from_web_seeds.load(Ordering::Relaxed);
+        assert!(from_peers > 0 && from_web_seeds > 0);
+        assert_eq!(from_peers + from_web_seeds, data.len() as u64);
+        assert!(counter.load(Ordering::Relaxed) >= data.len() as u64);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
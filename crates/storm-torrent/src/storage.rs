@@ -0,0 +1,148 @@
+use crate::metainfo::Torrent;
+use parking_lot::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use stormdl_core::{ByteRange, StormError};
+
+// A torrent's files on disk, addressed by their offsets in the torrent.
+pub struct Storage {
+    root: PathBuf,
+    files: Mutex<Vec<Option<File>>>,
+    // Files that had data before this run, the only ones worth rechecking.
+    existing: Vec<bool>,
+}
+
+impl Storage {
+    // Creates the torrent's files under `root` at their full size, keeping
+    // whatever is already there.
+    pub fn open(root: &Path, torrent: &Torrent) -> Result<Self, StormError> {
+        let mut files = Vec::new();
+        let mut existing = Vec::new();
+        for file in &torrent.files {
+            if file.padding {
+                files.push(None);
+                existing.push(false);
+                continue;
+            }
+            let path = root.join(&file.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let handle = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            let len = handle.metadata()?.len();
+            existing.push(len > 0);
+            if len != file.length {
+                handle.set_len(file.length)?;
+            }
+            files.push(Some(handle));
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            files: Mutex::new(files),
+            existing,
+        })
+    }
+
+    pub fn path(&self, torrent: &Torrent, file: usize) -> PathBuf {
+        self.root.join(&torrent.files[file].path)
+    }
+
+    pub fn write(
+        &self,
+        torrent: &Torrent,
+        range: ByteRange,
+        data: &[u8],
+    ) -> Result<(), StormError> {
+        let mut files = self.files.lock();
+        for (index, offset, span) in torrent.spans(range) {
+            let Some(file) = &mut files[index] else {
+                continue;
+            };
+            let at = (span.start - range.start) as usize;
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&data[at..at + span.len() as usize])?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, torrent: &Torrent, range: ByteRange) -> Result<Vec<u8>, StormError> {
+        let mut data = vec![0; range.len() as usize];
+        let mut files = self.files.lock();
+        for (index, offset, span) in torrent.spans(range) {
+            let Some(file) = &mut files[index] else {
+                continue;
+            };
+            let at = (span.start - range.start) as usize;
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut data[at..at + span.len() as usize])?;
+        }
+        Ok(data)
+    }
+
+    // The pieces already on disk from an earlier run, by their hashes.
+    pub fn recheck(&self, torrent: &Torrent) -> Result<Vec<usize>, StormError> {
+        let mut done = Vec::new();
+        for index in 0..torrent.pieces.len() {
+            let range = torrent.piece_range(index);
+            let spans = torrent.spans(range);
+            if !spans.iter().any(|(file, _, _)| self.existing[*file]) {
+                continue;
+            }
+            if torrent.check_piece(index, &self.read(torrent, range)?) {
+                done.push(index);
+            }
+        }
+        Ok(done)
+    }
+
+    pub fn sync(&self) -> Result<(), StormError> {
+        for file in self.files.lock().iter().flatten() {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode;
+    use crate::metainfo::tests::info;
+
+    #[test]
+    fn test_storage() {
+        let (info, data) = info(&[("a.bin", &[1; 300]), ("b/c.bin", &[2; 500])], 256);
+        let torrent = Torrent::from_info(&bencode::encode(&info), Vec::new(), Vec::new()).unwrap();
+        let root = std::env::temp_dir().join(format!("storm-torrent-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let storage = Storage::open(&root, &torrent).unwrap();
+        assert!(storage.recheck(&torrent).unwrap().is_empty());
+        for index in [1, 3] {
+            let range = torrent.piece_range(index);
+            let piece = &data[range.start as usize..range.end as usize];
+            storage.write(&torrent, range, piece).unwrap();
+        }
+        storage.sync().unwrap();
+        drop(storage);
+        assert_eq!(
+            std::fs::metadata(root.join("set/b/c.bin")).unwrap().len(),
+            500
+        );
+
+        let storage = Storage::open(&root, &torrent).unwrap();
+        assert_eq!(storage.recheck(&torrent).unwrap(), [1, 3]);
+        assert_eq!(
+            storage.read(&torrent, ByteRange::new(290, 310)).unwrap(),
+            [[1; 10], [2; 10]].concat()
+        );
+        assert_eq!(storage.path(&torrent, 1), root.join("set/b/c.bin"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+use crate::bencode::{self, Value};
+use crate::metainfo::InfoHash;
+use bytes::Bytes;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use stormdl_core::{DataSink, Downloader, StormError};
+use tokio::net::UdpSocket;
+use url::Url;
+
+const UDP_TIMEOUT: Duration = Duration::from_secs(15);
+const UDP_MAGIC: u64 = 0x41727101980;
+// Used when a tracker doesn't say how often to come back.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1800);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Started,
+    Completed,
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Announce {
+    pub info_hash: InfoHash,
+    pub peer_id: [u8; 20],
+    pub port: u16,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: Option<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceResponse {
+    pub peers: Vec<SocketAddr>,
+    pub interval: Duration,
+}
+
+// Asks an http(s):// or udp:// tracker for peers.
+pub async fn announce(
+    downloader: &dyn Downloader,
+    tracker: &Url,
+    announce: &Announce,
+) -> Result<AnnounceResponse, StormError> {
+    match tracker.scheme() {
+        "http" | "https" => {
+            let url = http_url(tracker, announce);
+            let mut body = Body(Vec::new());
+            downloader.fetch_full(&url, &mut body).await?;
+            parse_response(&body.0)
+        }
+        "udp" => tokio::time::timeout(UDP_TIMEOUT * 2, udp_announce(tracker, announce))
+            .await
+            .map_err(|_| StormError::Timeout(format!("tracker {}", tracker)))?,
+        scheme => Err(StormError::InvalidUrl(format!(
+            "unsupported tracker scheme '{}'",
+            scheme
+        ))),
+    }
+}
+
+struct Body(Vec<u8>);
+
+impl DataSink for Body {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        self.0.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
+}
+
+fn http_url(tracker: &Url, announce: &Announce) -> Url {
+    let bytes = |b: &[u8]| url::form_urlencoded::byte_serialize(b).collect::<String>();
+    let mut query = tracker
+        .query()
+        .map(|q| format!("{}&", q))
+        .unwrap_or_default();
+    query.push_str(&format!(
+        "info_hash={}&peer_id={}&port={}&uploaded=0&downloaded={}&left={}&compact=1",
+        bytes(&announce.info_hash.0),
+        bytes(&announce.peer_id),
+        announce.port,
+        announce.downloaded,
+        announce.left,
+    ));
+    if let Some(event) = announce.event {
+        query.push_str(match event {
+            Event::Started => "&event=started",
+            Event::Completed => "&event=completed",
+            Event::Stopped => "&event=stopped",
+        });
+    }
+    let mut url = tracker.clone();
+    url.set_query(Some(&query));
+    url
+}
+
+fn parse_response(body: &[u8]) -> Result<AnnounceResponse, StormError> {
+    let response = bencode::decode(body)?;
+    if let Some(reason) = response.get("failure reason") {
+        return Err(StormError::Protocol(format!(
+            "tracker refused: {}",
+            String::from_utf8_lossy(reason.as_bytes().unwrap_or_default())
+        )));
+    }
+    let mut peers = Vec::new();
+    match response.get("peers") {
+        Some(Value::Bytes(compact)) => peers.extend(compact_peers(compact, 4)),
+        Some(Value::List(list)) => {
+            for peer in list {
+                let ip = peer.get("ip").and_then(Value::as_str);
+                let port = peer.get("port").and_then(Value::as_int);
+                if let (Some(ip), Some(port)) = (ip, port)
+                    && let (Ok(ip), Ok(port)) = (ip.parse::<IpAddr>(), u16::try_from(port))
+                {
+                    peers.push(SocketAddr::new(ip, port));
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Some(compact) = response.get("peers6").and_then(Value::as_bytes) {
+        peers.extend(compact_peers(compact, 16));
+    }
+    let interval = response
+        .get("interval")
+        .and_then(Value::as_int)
+        .filter(|n| *n > 0)
+        .map_or(DEFAULT_INTERVAL, |n| Duration::from_secs(n as u64));
+    Ok(AnnounceResponse { peers, interval })
+}
+
+// Addresses packed as IP bytes and a big-endian port.
+fn compact_peers(data: &[u8], ip_len: usize) -> Vec<SocketAddr> {
+    data.chunks_exact(ip_len + 2)
+        .map(|chunk| {
+            let ip = match ip_len {
+                4 => IpAddr::from(<[u8; 4]>::try_from(&chunk[..4]).unwrap()),
+                _ => IpAddr::from(<[u8; 16]>::try_from(&chunk[..16]).unwrap()),
+            };
+            let port = u16::from_be_bytes([chunk[ip_len], chunk[ip_len + 1]]);
+            SocketAddr::new(ip, port)
+        })
+        .filter(|addr| addr.port() != 0)
+        .collect()
+}
+
+// BEP 15: a connect round trip for a connection ID, then the announce.
+async fn udp_announce(tracker: &Url, announce: &Announce) -> Result<AnnounceResponse, StormError> {
+    let host = tracker
+        .host_str()
+        .ok_or_else(|| StormError::InvalidUrl(tracker.to_string()))?;
+    let port = tracker
+        .port()
+        .ok_or_else(|| StormError::InvalidUrl(format!("{} has no port", tracker)))?;
+    let addr = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await?
+        .next()
+        .ok_or_else(|| StormError::Network(format!("{} didn't resolve", host)))?;
+    let bind: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+
+    let transaction = transaction_id();
+    let mut request = UDP_MAGIC.to_be_bytes().to_vec();
+    request.extend_from_slice(&0u32.to_be_bytes());
+    request.extend_from_slice(&transaction.to_be_bytes());
+    let reply = udp_exchange(&socket, &request, 0, transaction).await?;
+    let connection = reply
+        .get(..8)
+        .ok_or_else(|| StormError::Protocol("short tracker reply".into()))?;
+
+    let transaction = transaction.wrapping_add(1);
+    let mut request = connection.to_vec();
+    request.extend_from_slice(&1u32.to_be_bytes());
+    request.extend_from_slice(&transaction.to_be_bytes());
+    request.extend_from_slice(&announce.info_hash.0);
+    request.extend_from_slice(&announce.peer_id);
+    request.extend_from_slice(&announce.downloaded.to_be_bytes());
+    request.extend_from_slice(&announce.left.to_be_bytes());
+    request.extend_from_slice(&0u64.to_be_bytes());
+    let event: u32 = match announce.event {
+        None => 0,
+        Some(Event::Completed) => 1,
+        Some(Event::Started) => 2,
+        Some(Event::Stopped) => 3,
+    };
+    request.extend_from_slice(&event.to_be_bytes());
+    request.extend_from_slice(&0u32.to_be_bytes());
+    request.extend_from_slice(&transaction.to_be_bytes());
+    request.extend_from_slice(&(-1i32).to_be_bytes());
+    request.extend_from_slice(&announce.port.to_be_bytes());
+    let reply = udp_exchange(&socket, &request, 1, transaction).await?;
+    let interval = reply
+        .get(..4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .filter(|n| *n > 0)
+        .map_or(DEFAULT_INTERVAL, |n| Duration::from_secs(n as u64));
+    let ip_len = if addr.is_ipv4() { 4 } else { 16 };
+    Ok(AnnounceResponse {
+        peers: compact_peers(reply.get(12..).unwrap_or_default(), ip_len),
+        interval,
+    })
+}
+
+// Sends `request` until a reply to it arrives; returns what follows the
+// reply's action and transaction ID.
+async fn udp_exchange(
+    socket: &UdpSocket,
+    request: &[u8],
+    action: u32,
+    transaction: u32,
+) -> Result<Vec<u8>, StormError> {
+    let mut buf = vec![0u8; 2048];
+    loop {
+        socket.send(request).await?;
+        let Ok(len) = tokio::time::timeout(UDP_TIMEOUT / 3, socket.recv(&mut buf)).await else {
+            continue;
+        };
+        let reply = &buf[..len?];
+        if reply.len() < 8 || reply[4..8] != transaction.to_be_bytes() {
+            continue;
+        }
+        match u32::from_be_bytes(reply[..4].try_into().unwrap()) {
+            found if found == action => return Ok(reply[8..].to_vec()),
+            3 => {
+                return Err(StormError::Protocol(format!(
+                    "tracker refused: {}",
+                    String::from_utf8_lossy(&reply[8..])
+                )));
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn transaction_id() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    nanos ^ std::process::id().rotate_left(16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_announce() {
+        let announce = Announce {
+            info_hash: InfoHash([0xab; 20]),
+            peer_id: *b"-SD0120-abcdefghijkl",
+            port: 6881,
+            downloaded: 10,
+            left: 90,
+            event: Some(Event::Started),
+        };
+        let url = http_url(
+            &Url::parse("http://tracker.example/announce?key=1").unwrap(),
+            &announce,
+        );
+        assert_eq!(
+            url.query(),
+            Some(
+                "key=1&info_hash=%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB\
+                 &peer_id=-SD0120-abcdefghijkl&port=6881&uploaded=0&downloaded=10&left=90\
+                 &compact=1&event=started"
+            )
+        );
+
+        let compact = bencode::encode(&Value::dict(vec![
+            ("interval", Value::Int(900)),
+            (
+                "peers",
+                Value::bytes(vec![10, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0, 0]),
+            ),
+            (
+                "peers6",
+                Value::Bytes([[0u8; 15].as_slice(), &[1, 0x1a, 0xe9]].concat()),
+            ),
+        ]));
+        let response = parse_response(&compact).unwrap();
+        assert_eq!(
+            response.peers,
+            [
+                "10.0.0.1:6881".parse().unwrap(),
+                "[::1]:6889".parse().unwrap()
+            ]
+        );
+        assert_eq!(response.interval, Duration::from_secs(900));
+
+        let dicts = bencode::encode(&Value::dict(vec![(
+            "peers",
+            Value::List(vec![Value::dict(vec![
+                ("ip", Value::bytes("192.168.1.9")),
+                ("port", Value::Int(51413)),
+            ])]),
+        )]));
+        let response = parse_response(&dicts).unwrap();
+        assert_eq!(response.peers, ["192.168.1.9:51413".parse().unwrap()]);
+        assert_eq!(response.interval, DEFAULT_INTERVAL);
+
+        let refused = bencode::encode(&Value::dict(vec![(
+            "failure reason",
+            Value::bytes("unregistered torrent"),
+        )]));
+        assert!(
+            parse_response(&refused)
+                .unwrap_err()
+                .to_string()
+                .contains("unregistered torrent")
+        );
+    }
+}
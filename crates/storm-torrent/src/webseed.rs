@@ -0,0 +1,159 @@
+use crate::metainfo::Torrent;
+use bytes::Bytes;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use stormdl_bandwidth::RateLimiter;
+use stormdl_core::{ByteRange, DataSink, Downloader, MirrorSet, StormError};
+use stormdl_segment::MultiSourceManager;
+use url::Url;
+
+// HTTP mirrors of a torrent's files (BEP 19). Pieces are fetched as byte
+// ranges of the files they fall in, with MultiSourceManager picking the
+// mirror for each one the way it does for a plain download's segments.
+pub(crate) struct WebSeeds {
+    manager: MultiSourceManager,
+    downloader: Arc<dyn Downloader>,
+    torrent: Arc<Torrent>,
+}
+
+impl WebSeeds {
+    pub(crate) fn new(
+        urls: Vec<Url>,
+        torrent: Arc<Torrent>,
+        downloader: Arc<dyn Downloader>,
+    ) -> Self {
+        Self {
+            manager: MultiSourceManager::new(MirrorSet::from(urls), torrent.total_size()),
+            downloader,
+            torrent,
+        }
+    }
+
+    // A piece's bytes from whichever mirror the manager picks. Mirrors that
+    // don't have the file are retired.
+    pub(crate) async fn fetch_piece(
+        &self,
+        index: usize,
+        counted: &AtomicU64,
+        limiter: &RateLimiter,
+    ) -> Result<Vec<u8>, StormError> {
+        let range = self.torrent.piece_range(index);
+        let source = self.manager.assign_segment(index, range);
+        let base = self
+            .manager
+            .get_mirror_url(source)
+            .ok_or_else(|| StormError::Other("no web seed".into()))?;
+        limiter.acquire(range.len() as usize).await;
+        let started = Instant::now();
+        let mut sink = PieceSink {
+            data: Vec::with_capacity(range.len() as usize),
+            limit: range.len() as usize,
+            counted,
+            first_byte: None,
+        };
+        let mut result = Ok(());
+        for (file, offset, span) in self.torrent.spans(range) {
+            if self.torrent.files[file].padding {
+                sink.data.resize(sink.data.len() + span.len() as usize, 0);
+                continue;
+            }
+            let url = self.file_url(&base, file);
+            let within = ByteRange::new(offset, offset + span.len());
+            result = self.downloader.fetch_range(&url, within, &mut sink).await;
+            if result.is_err() {
+                break;
+            }
+        }
+        self.manager.complete_segment(index);
+        if let Some(first_byte) = sink.first_byte {
+            self.manager
+                .record_ttfb(source, first_byte.duration_since(started));
+        }
+        match result {
+            Ok(()) if sink.data.len() as u64 == range.len() => {
+                let elapsed = started.elapsed().as_secs_f64().max(1e-3);
+                self.manager
+                    .record_progress(source, range.len(), range.len() as f64 / elapsed);
+                self.manager.sync_mirror_stats();
+                Ok(sink.data)
+            }
+            Ok(()) => {
+                self.manager.record_error(source);
+                Err(StormError::Protocol(format!(
+                    "web seed {} sent {} bytes for a {}-byte piece",
+                    base,
+                    sink.data.len(),
+                    range.len()
+                )))
+            }
+            Err(e) => {
+                self.manager.record_error(source);
+                let missing = matches!(
+                    e,
+                    StormError::NotFound(_)
+                        | StormError::RangeNotSupported
+                        | StormError::Http {
+                            status: 401 | 403 | 404 | 410,
+                            ..
+                        }
+                );
+                if missing {
+                    self.manager.retire(source);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // A mirror that failed a piece's hash serves other content; it gets
+    // no more pieces while others remain.
+    pub(crate) fn reject(&self, index: usize) {
+        if let Some(source) = self.manager.get_assignment(index) {
+            self.manager.record_error(source);
+            self.manager.retire(source);
+        }
+    }
+
+    // Single-file torrents name the file itself unless the URL ends in a
+    // slash; multi-file ones name the directory holding the torrent's.
+    fn file_url(&self, base: &Url, file: usize) -> Url {
+        if !self.torrent.is_multi_file() && !base.path().ends_with('/') {
+            return base.clone();
+        }
+        let mut url = base.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+            for part in self.torrent.files[file].path.iter() {
+                segments.push(&part.to_string_lossy());
+            }
+        }
+        url
+    }
+}
+
+struct PieceSink<'a> {
+    data: Vec<u8>,
+    limit: usize,
+    counted: &'a AtomicU64,
+    first_byte: Option<Instant>,
+}
+
+impl DataSink for PieceSink<'_> {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        if self.data.len() + data.len() > self.limit {
+            return Err(StormError::TooLarge {
+                size: (self.data.len() + data.len()) as u64,
+                limit: self.limit as u64,
+            });
+        }
+        self.first_byte.get_or_insert_with(Instant::now);
+        self.counted.fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.data.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,715 @@
+use crate::orchestrator::{DownloadEvent, OrchestratorCommand};
+use flume::Sender;
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use stormdl_core::{
+    Credentials, DownloadId, DownloadOptions, DownloadState, Priority, SegmentState,
+};
+use stormdl_integrity::ChecksumSpec;
+use stormdl_protocol::ProxyConfig;
+use tokio::sync::{broadcast, oneshot};
+
+const VERSION: &str = "1.37.0";
+
+const METHODS: &[&str] = &[
+    "aria2.addUri",
+    "aria2.tellStatus",
+    "aria2.tellActive",
+    "aria2.tellWaiting",
+    "aria2.tellStopped",
+    "aria2.pause",
+    "aria2.forcePause",
+    "aria2.unpause",
+    "aria2.remove",
+    "aria2.forceRemove",
+    "aria2.getGlobalStat",
+    "aria2.getVersion",
+    "system.multicall",
+    "system.listMethods",
+];
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(1, message)
+    }
+}
+
+struct Job {
+    url: String,
+    dir: PathBuf,
+    filename: String,
+    path: Option<PathBuf>,
+    total: u64,
+    completed: u64,
+    speed: f64,
+    state: DownloadState,
+    error: Option<String>,
+    segments: Vec<SegmentState>,
+}
+
+impl Job {
+    fn status(&self) -> &'static str {
+        match self.state {
+            DownloadState::Pending => "waiting",
+            DownloadState::Probing
+            | DownloadState::Waiting
+            | DownloadState::Downloading
+            | DownloadState::Scanning => "active",
+            DownloadState::Paused => "paused",
+            DownloadState::Complete => "complete",
+            DownloadState::Failed | DownloadState::Quarantined => "error",
+            DownloadState::Cancelled => "removed",
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        matches!(
+            self.state,
+            DownloadState::Complete
+                | DownloadState::Failed
+                | DownloadState::Quarantined
+                | DownloadState::Cancelled
+        )
+    }
+
+    fn connections(&self) -> usize {
+        if self.state != DownloadState::Downloading {
+            return 0;
+        }
+        let active = self
+            .segments
+            .iter()
+            .filter(|s| s.downloaded > 0 && s.downloaded < s.range.len())
+            .count();
+        active.max(1)
+    }
+
+    fn to_json(&self, id: DownloadId) -> Value {
+        let path = self
+            .path
+            .clone()
+            .unwrap_or_else(|| self.dir.join(&self.filename));
+        let mut status = json!({
+            "gid": gid(id),
+            "status": self.status(),
+            "totalLength": self.total.to_string(),
+            "completedLength": self.completed.to_string(),
+            "uploadLength": "0",
+            "downloadSpeed": (self.speed as u64).to_string(),
+            "uploadSpeed": "0",
+            "connections": self.connections().to_string(),
+            "dir": self.dir.to_string_lossy(),
+            "files": [{
+                "index": "1",
+                "path": path.to_string_lossy(),
+                "length": self.total.to_string(),
+                "completedLength": self.completed.to_string(),
+                "selected": "true",
+                "uris": [{ "uri": self.url, "status": "used" }],
+            }],
+            "segments": self
+                .segments
+                .iter()
+                .map(|s| json!({
+                    "offset": s.range.start.to_string(),
+                    "length": s.range.len().to_string(),
+                    "completedLength": s.downloaded.min(s.range.len()).to_string(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(error) = &self.error {
+            status["errorCode"] = json!("1");
+            status["errorMessage"] = json!(error);
+        }
+        status
+    }
+
+    fn summary(&self, id: DownloadId) -> Value {
+        json!({
+            "id": id.0,
+            "url": self.url,
+            "filename": self.filename,
+            "path": self.path.as_ref().map(|p| p.to_string_lossy()),
+            "state": format!("{:?}", self.state).to_lowercase(),
+            "total": self.total,
+            "downloaded": self.completed,
+            "speed": self.speed as u64,
+            "error": self.error,
+        })
+    }
+}
+
+#[derive(Default)]
+struct RpcState {
+    jobs: HashMap<DownloadId, Job>,
+    order: Vec<DownloadId>,
+    pending_adds: VecDeque<(oneshot::Sender<DownloadId>, Job)>,
+}
+
+impl RpcState {
+    fn apply(&mut self, event: DownloadEvent) -> Option<Value> {
+        match event {
+            DownloadEvent::DownloadAdded {
+                id,
+                filename,
+                total_size,
+                ..
+            } => {
+                if !self.jobs.contains_key(&id)
+                    && let Some((reply, job)) = self.pending_adds.pop_front()
+                {
+                    self.jobs.insert(id, job);
+                    self.order.push(id);
+                    let _ = reply.send(id);
+                }
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    job.filename = filename;
+                    job.total = total_size.unwrap_or(job.total);
+                }
+            }
+            DownloadEvent::ProgressUpdate {
+                id,
+                downloaded,
+                segments,
+            } => {
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    job.completed = downloaded;
+                    SegmentState::merge(&mut job.segments, segments);
+                }
+            }
+            DownloadEvent::SpeedUpdate { id, speed } => {
+                if let Some(job) = self.jobs.get_mut(&id) {
+                    job.speed = speed;
+                }
+            }
+            DownloadEvent::StateChange { id, state } => {
+                let job = self.jobs.get_mut(&id)?;
+                let before = job.status();
+                job.state = state;
+                if job.is_stopped() {
+                    job.speed = 0.0;
+                }
+                return notification(id, before, job.status());
+            }
+            DownloadEvent::Error { id, error } => {
+                let job = self.jobs.get_mut(&id)?;
+                let before = job.status();
+                job.state = DownloadState::Failed;
+                job.error = Some(error);
+                job.speed = 0.0;
+                return notification(id, before, job.status());
+            }
+            DownloadEvent::Complete { id, path, .. } => {
+                let job = self.jobs.get_mut(&id)?;
+                let before = job.status();
+                job.state = DownloadState::Complete;
+                job.completed = job.total.max(job.completed);
+                job.path = Some(path);
+                job.speed = 0.0;
+                return notification(id, before, job.status());
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn list(&self, filter: impl Fn(&Job) -> bool) -> Vec<Value> {
+        self.order
+            .iter()
+            .filter_map(|id| self.jobs.get(id).map(|job| (id, job)))
+            .filter(|(_, job)| filter(job))
+            .map(|(&id, job)| job.to_json(id))
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct Aria2Rpc {
+    cmd_tx: Sender<OrchestratorCommand>,
+    state: Arc<Mutex<RpcState>>,
+    notifications: broadcast::Sender<Value>,
+    secret: Option<String>,
+    default_dir: PathBuf,
+}
+
+impl Aria2Rpc {
+    pub fn new(
+        cmd_tx: Sender<OrchestratorCommand>,
+        secret: Option<String>,
+        default_dir: PathBuf,
+    ) -> Self {
+        Self {
+            cmd_tx,
+            state: Arc::new(Mutex::new(RpcState::default())),
+            notifications: broadcast::channel(256).0,
+            secret,
+            default_dir,
+        }
+    }
+
+    pub fn default_dir(&self) -> &Path {
+        &self.default_dir
+    }
+
+    pub fn apply(&self, event: DownloadEvent) {
+        if let Some(notification) = self.state.lock().apply(event) {
+            let _ = self.notifications.send(notification);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Value> {
+        self.notifications.subscribe()
+    }
+
+    pub fn downloads(&self) -> Vec<Value> {
+        let state = self.state.lock();
+        state
+            .order
+            .iter()
+            .filter_map(|id| state.jobs.get(id).map(|job| job.summary(*id)))
+            .collect()
+    }
+
+    pub fn download(&self, id: DownloadId) -> Option<Value> {
+        self.state.lock().jobs.get(&id).map(|job| job.summary(id))
+    }
+
+    pub async fn handle(&self, request: Value) -> Value {
+        match request {
+            Value::Array(calls) => {
+                let mut responses = Vec::with_capacity(calls.len());
+                for call in calls {
+                    responses.push(self.handle_one(call).await);
+                }
+                Value::Array(responses)
+            }
+            call => self.handle_one(call).await,
+        }
+    }
+
+    async fn handle_one(&self, call: Value) -> Value {
+        let id = call.get("id").cloned().unwrap_or(Value::Null);
+        let method = call.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = match call.get("params") {
+            Some(Value::Array(params)) => params.clone(),
+            _ => Vec::new(),
+        };
+
+        match Box::pin(self.call(method, params)).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": e.code, "message": e.message },
+            }),
+        }
+    }
+
+    pub async fn call(&self, method: &str, mut params: Vec<Value>) -> Result<Value, RpcError> {
+        if method.starts_with("aria2.") {
+            self.authorize(&mut params)?;
+        }
+
+        match method {
+            "aria2.addUri" => self.add_uri(&params).await,
+            "aria2.tellStatus" => {
+                let id = parse_gid(params.first())?;
+                let state = self.state.lock();
+                let job = state
+                    .jobs
+                    .get(&id)
+                    .ok_or_else(|| RpcError::invalid(format!("GID {} is not found", gid(id))))?;
+                Ok(select_keys(job.to_json(id), params.get(1)))
+            }
+            "aria2.tellActive" => {
+                let keys = params.first();
+                let jobs = self.state.lock().list(|j| j.status() == "active");
+                Ok(jobs.into_iter().map(|j| select_keys(j, keys)).collect())
+            }
+            "aria2.tellWaiting" | "aria2.tellStopped" => {
+                let offset = params.first().and_then(Value::as_i64).unwrap_or(0).max(0) as usize;
+                let num = params.get(1).and_then(Value::as_u64).unwrap_or(1000) as usize;
+                let keys = params.get(2);
+                let jobs = if method == "aria2.tellWaiting" {
+                    self.state
+                        .lock()
+                        .list(|j| matches!(j.status(), "waiting" | "paused"))
+                } else {
+                    self.state.lock().list(Job::is_stopped)
+                };
+                Ok(jobs
+                    .into_iter()
+                    .skip(offset)
+                    .take(num)
+                    .map(|j| select_keys(j, keys))
+                    .collect())
+            }
+            "aria2.pause" | "aria2.forcePause" => {
+                self.send_for(&params, OrchestratorCommand::PauseDownload)
+            }
+            "aria2.unpause" => self.send_for(&params, OrchestratorCommand::ResumeDownload),
+            "aria2.remove" | "aria2.forceRemove" => {
+                self.send_for(&params, OrchestratorCommand::CancelDownload)
+            }
+            "aria2.getGlobalStat" => {
+                let state = self.state.lock();
+                let jobs = state.jobs.values();
+                let speed: f64 = jobs.clone().map(|j| j.speed).sum();
+                let count = |status: &str| jobs.clone().filter(|j| j.status() == status).count();
+                let stopped = jobs.clone().filter(|j| j.is_stopped()).count();
+                Ok(json!({
+                    "downloadSpeed": (speed as u64).to_string(),
+                    "uploadSpeed": "0",
+                    "numActive": count("active").to_string(),
+                    "numWaiting": (count("waiting") + count("paused")).to_string(),
+                    "numStopped": stopped.to_string(),
+                    "numStoppedTotal": stopped.to_string(),
+                }))
+            }
+            "aria2.getVersion" => {
+                Ok(json!({ "version": VERSION, "enabledFeatures": ["HTTP", "HTTPS"] }))
+            }
+            "system.listMethods" => Ok(json!(METHODS)),
+            "system.multicall" => {
+                let calls = params
+                    .first()
+                    .and_then(Value::as_array)
+                    .ok_or_else(|| RpcError::invalid("system.multicall expects an array"))?;
+                let mut results = Vec::with_capacity(calls.len());
+                for call in calls {
+                    let method = call.get("methodName").and_then(Value::as_str).unwrap_or("");
+                    let params = call
+                        .get("params")
+                        .and_then(Value::as_array)
+                        .cloned()
+                        .unwrap_or_default();
+                    results.push(match Box::pin(self.call(method, params)).await {
+                        Ok(result) => json!([result]),
+                        Err(e) => json!({ "code": e.code, "message": e.message }),
+                    });
+                }
+                Ok(Value::Array(results))
+            }
+            _ => Err(RpcError::new(
+                -32601,
+                format!("Method not found: {}", method),
+            )),
+        }
+    }
+
+    fn authorize(&self, params: &mut Vec<Value>) -> Result<(), RpcError> {
+        let token = params
+            .first()
+            .and_then(Value::as_str)
+            .and_then(|p| p.strip_prefix("token:"))
+            .map(String::from);
+        if token.is_some() {
+            params.remove(0);
+        }
+        match &self.secret {
+            Some(secret) if token.as_deref() != Some(secret.as_str()) => {
+                Err(RpcError::invalid("Unauthorized"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn add_uri(&self, params: &[Value]) -> Result<Value, RpcError> {
+        let uri = params
+            .first()
+            .and_then(Value::as_array)
+            .and_then(|uris| uris.first())
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::invalid("addUri expects a list of URIs"))?;
+        let url = url::Url::parse(uri).map_err(|e| RpcError::invalid(e.to_string()))?;
+
+        let option = |key: &str| {
+            params
+                .get(1)
+                .and_then(|o| o.get(key))
+                .and_then(Value::as_str)
+                .map(String::from)
+        };
+        let dir = option("dir").map(PathBuf::from);
+        let filename = option("out");
+        let segments = option("split")
+            .or_else(|| option("max-connection-per-server"))
+            .and_then(|s| s.parse().ok());
+        let checksum = option("checksum")
+            .map(|c| ChecksumSpec::parse(&c))
+            .transpose()
+            .map_err(|e| RpcError::invalid(e.to_string()))?;
+        let proxy = option("all-proxy");
+        let auth = option("http-user").map(|user| Credentials::Basic {
+            user,
+            password: option("http-passwd").unwrap_or_default(),
+        });
+
+        let id = self
+            .add(url, dir, filename, segments, checksum, proxy, auth)
+            .await?;
+        Ok(json!(gid(id)))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add(
+        &self,
+        url: url::Url,
+        dir: Option<PathBuf>,
+        filename: Option<String>,
+        segments: Option<usize>,
+        checksum: Option<ChecksumSpec>,
+        proxy: Option<String>,
+        auth: Option<Credentials>,
+    ) -> Result<DownloadId, RpcError> {
+        if let Some(proxy) = &proxy {
+            ProxyConfig::new(proxy).map_err(|e| RpcError::invalid(e.to_string()))?;
+        }
+        let dir = dir.unwrap_or_else(|| self.default_dir.clone());
+        let job = Job {
+            url: url.to_string(),
+            dir: dir.clone(),
+            filename: filename.clone().unwrap_or_default(),
+            path: None,
+            total: 0,
+            completed: 0,
+            speed: 0.0,
+            state: DownloadState::Pending,
+            error: None,
+            segments: Vec::new(),
+        };
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.state.lock().pending_adds.push_back((reply_tx, job));
+
+        let options = DownloadOptions {
+            url: url.clone(),
+            output_dir: dir,
+            filename,
+            segments,
+            priority: Priority::Normal,
+            bandwidth_limit: None,
+            headers: vec![],
+            checksum: checksum.map(|c| c.to_string()),
+            group: None,
+            mirrors: vec![],
+            tags: vec![],
+            note: None,
+            proxy,
+            auth,
+        };
+        self.cmd_tx
+            .send(OrchestratorCommand::AddDownload { url, options })
+            .map_err(|_| RpcError::new(-32603, "Download engine stopped"))?;
+
+        reply_rx
+            .await
+            .map_err(|_| RpcError::new(-32603, "Download engine stopped"))
+    }
+
+    fn send_for(
+        &self,
+        params: &[Value],
+        command: fn(DownloadId) -> OrchestratorCommand,
+    ) -> Result<Value, RpcError> {
+        let id = parse_gid(params.first())?;
+        self.send(id, command)?;
+        Ok(json!(gid(id)))
+    }
+
+    pub fn send(
+        &self,
+        id: DownloadId,
+        command: fn(DownloadId) -> OrchestratorCommand,
+    ) -> Result<(), RpcError> {
+        if !self.state.lock().jobs.contains_key(&id) {
+            return Err(RpcError::invalid(format!("GID {} is not found", gid(id))));
+        }
+        self.cmd_tx
+            .send(command(id))
+            .map_err(|_| RpcError::new(-32603, "Download engine stopped"))
+    }
+}
+
+fn notification(id: DownloadId, before: &str, after: &str) -> Option<Value> {
+    if before == after {
+        return None;
+    }
+    let method = match after {
+        "active" => "aria2.onDownloadStart",
+        "paused" => "aria2.onDownloadPause",
+        "removed" => "aria2.onDownloadStop",
+        "complete" => "aria2.onDownloadComplete",
+        "error" => "aria2.onDownloadError",
+        _ => return None,
+    };
+    Some(json!({ "jsonrpc": "2.0", "method": method, "params": [{ "gid": gid(id) }] }))
+}
+
+pub fn gid(id: DownloadId) -> String {
+    format!("{:016x}", id.0)
+}
+
+fn parse_gid(value: Option<&Value>) -> Result<DownloadId, RpcError> {
+    value
+        .and_then(Value::as_str)
+        .and_then(|s| u64::from_str_radix(s, 16).ok())
+        .map(DownloadId)
+        .ok_or_else(|| RpcError::invalid("Invalid GID"))
+}
+
+fn select_keys(status: Value, keys: Option<&Value>) -> Value {
+    let Some(keys) = keys.and_then(Value::as_array).filter(|k| !k.is_empty()) else {
+        return status;
+    };
+    let mut selected = serde_json::Map::new();
+    for key in keys.iter().filter_map(Value::as_str) {
+        if let Some(value) = status.get(key) {
+            selected.insert(key.to_string(), value.clone());
+        }
+    }
+    Value::Object(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stormdl_core::ByteRange;
+
+    #[tokio::test]
+    async fn test_add_and_tell_status() {
+        let (cmd_tx, cmd_rx) = flume::unbounded();
+        let rpc = Aria2Rpc::new(cmd_tx, Some("s3cret".into()), PathBuf::from("/tmp"));
+
+        let denied = rpc
+            .call("aria2.getGlobalStat", vec![json!("token:wrong")])
+            .await;
+        assert!(denied.is_err());
+
+        let engine = rpc.clone();
+        tokio::spawn(async move {
+            if let Ok(OrchestratorCommand::AddDownload { url, .. }) = cmd_rx.recv_async().await {
+                engine.apply(DownloadEvent::DownloadAdded {
+                    id: DownloadId(42),
+                    url,
+                    filename: "file.iso".into(),
+                    total_size: Some(1000),
+                    group: None,
+                });
+                let mut first = SegmentState::new(0, ByteRange::new(0, 500));
+                first.downloaded = 250;
+                engine.apply(DownloadEvent::ProgressUpdate {
+                    id: DownloadId(42),
+                    downloaded: 250,
+                    segments: vec![first, SegmentState::new(1, ByteRange::new(500, 1000))],
+                });
+            }
+        });
+
+        let gid = rpc
+            .call(
+                "aria2.addUri",
+                vec![
+                    json!("token:s3cret"),
+                    json!(["https://example.com/file.iso"]),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(gid, json!("000000000000002a"));
+
+        tokio::task::yield_now().await;
+        let status = rpc
+            .call(
+                "aria2.tellStatus",
+                vec![
+                    json!("token:s3cret"),
+                    gid,
+                    json!(["status", "completedLength", "segments"]),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            status,
+            json!({
+                "status": "waiting",
+                "completedLength": "250",
+                "segments": [
+                    { "offset": "0", "length": "500", "completedLength": "250" },
+                    { "offset": "500", "length": "500", "completedLength": "0" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_state_changes_notify_subscribers() {
+        let (cmd_tx, _cmd_rx) = flume::unbounded();
+        let rpc = Aria2Rpc::new(cmd_tx, None, PathBuf::from("/tmp"));
+        let mut notifications = rpc.subscribe();
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        let job = Job {
+            url: "https://example.com/file.iso".into(),
+            dir: PathBuf::from("/tmp"),
+            filename: String::new(),
+            path: None,
+            total: 0,
+            completed: 0,
+            speed: 0.0,
+            state: DownloadState::Pending,
+            error: None,
+            segments: Vec::new(),
+        };
+        rpc.state.lock().pending_adds.push_back((reply_tx, job));
+        let id = DownloadId(7);
+        rpc.apply(DownloadEvent::DownloadAdded {
+            id,
+            url: url::Url::parse("https://example.com/file.iso").unwrap(),
+            filename: "file.iso".into(),
+            total_size: Some(1000),
+            group: None,
+        });
+        for state in [
+            DownloadState::Probing,
+            DownloadState::Downloading,
+            DownloadState::Paused,
+            DownloadState::Downloading,
+        ] {
+            rpc.apply(DownloadEvent::StateChange { id, state });
+        }
+        rpc.apply(DownloadEvent::Error {
+            id,
+            error: "boom".into(),
+        });
+
+        let methods: Vec<Value> = std::iter::from_fn(|| notifications.try_recv().ok())
+            .map(|n| n["method"].clone())
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                "aria2.onDownloadStart",
+                "aria2.onDownloadPause",
+                "aria2.onDownloadStart",
+                "aria2.onDownloadError",
+            ]
+        );
+    }
+}
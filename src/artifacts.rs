@@ -0,0 +1,359 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use stormdl_core::StormError;
+use stormdl_integrity::{HashAlgorithm, IncrementalHasher, hash_bytes_with};
+use url::Url;
+
+// Finished downloads are kept under their SHA-256 in `objects/`, and
+// `index/` maps a URL plus its validator and size to one of them, so a
+// repeated fetch of an unchanged file (or of any file whose published
+// SHA-256 is known) is linked from disk instead of downloaded.
+#[derive(Debug, Clone)]
+pub struct ArtifactCache {
+    dir: PathBuf,
+    link: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub validator: Option<String>,
+    pub size: u64,
+    pub sha256: String,
+    // The object's mtime when it was stored. Writing to a restored hard
+    // link changes it, and the entry is then ignored.
+    modified: u64,
+    pub stored_at: i64,
+    pub used_at: i64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GcStats {
+    pub entries: usize,
+    pub objects: usize,
+    pub freed: u64,
+}
+
+impl ArtifactCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            link: true,
+        }
+    }
+
+    // Copies instead of hard-linking, so editing a restored file can't
+    // touch the cached object.
+    pub fn with_copies(mut self) -> Self {
+        self.link = false;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join("objects").join(&sha256[..2]).join(sha256)
+    }
+
+    fn index_path(&self, key: &str) -> PathBuf {
+        self.dir.join("index").join(format!("{}.json", key))
+    }
+
+    // Without a strong validator a URL can't be trusted to still serve the
+    // same bytes; such downloads are only found again by their hash.
+    fn key(url: &Url, validator: Option<&str>, size: u64) -> String {
+        let id = match validator {
+            Some(validator) => format!("{}\n{}\n{}", url, validator, size),
+            None => format!("{}\n\n{}", url, size),
+        };
+        hash_bytes_with(HashAlgorithm::Sha256, id.as_bytes())
+    }
+
+    // The cached object for `url` if it was stored with the same validator
+    // and size and hasn't been changed since.
+    pub fn lookup(&self, url: &Url, validator: Option<&str>, size: u64) -> Option<PathBuf> {
+        validator?;
+        let index = self.index_path(&Self::key(url, validator, size));
+        let mut entry = read_entry(&index)?;
+        let object = self.object_path(&entry.sha256);
+        if entry.validator.as_deref() != validator || !entry.intact(&object) {
+            return None;
+        }
+        entry.used_at = chrono::Utc::now().timestamp();
+        if let Err(e) = write_entry(&index, &entry) {
+            tracing::debug!("Failed to update {}: {}", index.display(), e);
+        }
+        Some(object)
+    }
+
+    // The cached object with this SHA-256, e.g. from `--checksum`. It's
+    // rehashed first: a restored hard link may have been written to since.
+    pub fn find(&self, sha256: &str) -> Option<PathBuf> {
+        let sha256 = sha256.to_ascii_lowercase();
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let object = self.object_path(&sha256);
+        if !object.is_file() {
+            return None;
+        }
+        if hash_file(&object).ok()? != sha256 {
+            let _ = fs::remove_file(&object);
+            return None;
+        }
+        Some(object)
+    }
+
+    // Puts `object` at `dest`, replacing whatever is there.
+    pub fn restore(&self, object: &Path, dest: &Path) -> io::Result<()> {
+        match fs::remove_file(dest) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        if self.link && fs::hard_link(object, dest).is_ok() {
+            return Ok(());
+        }
+        fs::copy(object, dest).map(|_| ())
+    }
+
+    // Adds a finished download; `sha256` skips rehashing when the checksum
+    // check already computed it.
+    pub fn store(
+        &self,
+        path: &Path,
+        url: &Url,
+        validator: Option<&str>,
+        sha256: Option<&str>,
+    ) -> Result<CacheEntry, StormError> {
+        let sha256 = match sha256 {
+            Some(sha256) => sha256.to_ascii_lowercase(),
+            None => hash_file(path)?,
+        };
+        let size = fs::metadata(path)?.len();
+        let object = self.object_path(&sha256);
+        // An existing object is replaced unless it's this very file: it may
+        // be a restored hard link that a later download wrote over.
+        if !same_file(path, &object) {
+            let parent = object.parent().expect("objects live in a subdirectory");
+            fs::create_dir_all(parent)?;
+            let staged = parent.join(format!(".{}.tmp", std::process::id()));
+            let _ = fs::remove_file(&staged);
+            if !self.link || fs::hard_link(path, &staged).is_err() {
+                fs::copy(path, &staged)?;
+            }
+            fs::rename(&staged, &object)?;
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let entry = CacheEntry {
+            url: url.to_string(),
+            validator: validator.map(str::to_string),
+            size,
+            modified: mtime(&object)?,
+            sha256,
+            stored_at: now,
+            used_at: now,
+        };
+        let index = self.index_path(&Self::key(url, validator, size));
+        fs::create_dir_all(index.parent().expect("index is a subdirectory"))?;
+        write_entry(&index, &entry)?;
+        Ok(entry)
+    }
+
+    pub fn entries(&self) -> Vec<CacheEntry> {
+        self.index_files()
+            .into_iter()
+            .filter_map(|path| read_entry(&path))
+            .collect()
+    }
+
+    fn index_files(&self) -> Vec<PathBuf> {
+        let Ok(dir) = fs::read_dir(self.dir.join("index")) else {
+            return Vec::new();
+        };
+        dir.flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect()
+    }
+
+    fn objects(&self) -> Vec<(String, PathBuf, u64)> {
+        let Ok(shards) = fs::read_dir(self.dir.join("objects")) else {
+            return Vec::new();
+        };
+        shards
+            .flatten()
+            .filter_map(|shard| fs::read_dir(shard.path()).ok())
+            .flat_map(|files| files.flatten())
+            .filter_map(|file| {
+                let name = file.file_name().to_str()?.to_string();
+                let size = file.metadata().ok()?.len();
+                Some((name, file.path(), size))
+            })
+            .collect()
+    }
+
+    // Total size of the stored objects.
+    pub fn size(&self) -> u64 {
+        self.objects().iter().map(|(_, _, size)| size).sum()
+    }
+
+    // Drops entries unused since `cutoff` (a Unix time), then the least
+    // recently used ones until the objects fit in `max_size`. Entries whose
+    // object went missing or changed and objects no entry refers to go too.
+    pub fn gc(&self, max_size: Option<u64>, cutoff: Option<i64>) -> GcStats {
+        let mut stats = GcStats::default();
+        let mut live: Vec<(PathBuf, CacheEntry)> = Vec::new();
+        for index in self.index_files() {
+            let keep = read_entry(&index).filter(|entry| {
+                entry.intact(&self.object_path(&entry.sha256))
+                    && cutoff.is_none_or(|cutoff| entry.used_at >= cutoff)
+            });
+            match keep {
+                Some(entry) => live.push((index, entry)),
+                None => {
+                    if fs::remove_file(&index).is_ok() {
+                        stats.entries += 1;
+                    }
+                }
+            }
+        }
+
+        // Several URLs can share an object; it's as recent as its latest use.
+        let mut used: HashMap<String, i64> = HashMap::new();
+        for (_, entry) in &live {
+            let last = used.entry(entry.sha256.clone()).or_insert(entry.used_at);
+            *last = (*last).max(entry.used_at);
+        }
+        let mut objects = self.objects();
+        objects.sort_by_key(|(name, _, _)| used.get(name).copied().unwrap_or(i64::MIN));
+        let mut total: u64 = objects.iter().map(|(_, _, size)| size).sum();
+        for (name, path, size) in objects {
+            let orphan = !used.contains_key(&name);
+            if !orphan && max_size.is_none_or(|max| total <= max) {
+                continue;
+            }
+            if fs::remove_file(&path).is_ok() {
+                stats.objects += 1;
+                stats.freed += size;
+                total -= size;
+                used.remove(&name);
+            }
+        }
+
+        for (index, entry) in live {
+            if !used.contains_key(&entry.sha256) && fs::remove_file(&index).is_ok() {
+                stats.entries += 1;
+            }
+        }
+        stats
+    }
+}
+
+impl CacheEntry {
+    fn intact(&self, object: &Path) -> bool {
+        fs::metadata(object).is_ok_and(|m| m.len() == self.size)
+            && mtime(object).is_ok_and(|modified| modified == self.modified)
+    }
+}
+
+fn mtime(path: &Path) -> io::Result<u64> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64))
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &Path, _: &Path) -> bool {
+    false
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+fn write_entry(path: &Path, entry: &CacheEntry) -> Result<(), StormError> {
+    let data = serde_json::to_vec_pretty(entry).map_err(|e| StormError::Other(e.to_string()))?;
+    let staged = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&staged, data)?;
+    fs::rename(&staged, path)?;
+    Ok(())
+}
+
+pub fn hash_file(path: &Path) -> Result<String, StormError> {
+    let mut hasher = IncrementalHasher::with_algorithm(HashAlgorithm::Sha256);
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_lookup_and_gc() {
+        let dir = std::env::temp_dir().join(format!("storm-cache-{}", std::process::id()));
+        let cache = ArtifactCache::new(dir.join("cache"));
+        let url = Url::parse("https://example.com/a.bin").unwrap();
+        let file = dir.join("a.bin");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&file, b"hello").unwrap();
+
+        let entry = cache.store(&file, &url, Some("\"v1\""), None).unwrap();
+        assert_eq!(
+            entry.sha256,
+            hash_bytes_with(HashAlgorithm::Sha256, b"hello")
+        );
+        let object = cache.lookup(&url, Some("\"v1\""), 5).unwrap();
+        assert!(cache.lookup(&url, Some("\"v2\""), 5).is_none());
+        assert!(cache.lookup(&url, None, 5).is_none());
+        assert_eq!(
+            cache.find(&entry.sha256.to_uppercase()),
+            Some(object.clone())
+        );
+
+        let restored = dir.join("restored.bin");
+        cache.restore(&object, &restored).unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), b"hello");
+
+        // A second URL for the same bytes shares the object.
+        let other = Url::parse("https://mirror.example.com/a.bin").unwrap();
+        cache
+            .store(&file, &other, None, Some(&entry.sha256))
+            .unwrap();
+        assert_eq!(cache.entries().len(), 2);
+        assert_eq!(cache.size(), 5);
+
+        let stats = cache.gc(Some(10), None);
+        assert_eq!((stats.entries, stats.objects), (0, 0));
+        let stats = cache.gc(Some(0), None);
+        assert_eq!((stats.entries, stats.objects, stats.freed), (2, 1, 5));
+        assert!(cache.lookup(&url, Some("\"v1\""), 5).is_none());
+        assert_eq!(fs::read(&restored).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use crate::config::Config;
+use crate::secret::read_secret;
+use anyhow::{Result, bail};
+use clap::Subcommand;
+use stormdl::keychain;
+use url::Url;
+
+#[derive(Subcommand)]
+pub enum AuthCommand {
+    #[command(about = "Save credentials for a host or *.domain pattern")]
+    Add {
+        #[arg(help = "Host or pattern, e.g. cdn.example.com or *.example.com")]
+        pattern: String,
+
+        #[arg(
+            short,
+            long,
+            value_name = "USER[:PASSWORD]",
+            help = "HTTP user; the password is asked for when left out"
+        )]
+        user: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN",
+            num_args = 0..=1,
+            conflicts_with = "user",
+            help = "Bearer token, or with --oauth the refresh token; asked for when no value is given"
+        )]
+        token: Option<Option<String>>,
+
+        #[arg(
+            long,
+            value_name = "TOKEN_URL",
+            conflicts_with = "user",
+            requires = "client_id",
+            help = "OAuth2 token endpoint that trades the refresh token for access tokens as they expire"
+        )]
+        oauth: Option<String>,
+
+        #[arg(long, value_name = "ID", requires = "oauth", help = "OAuth2 client ID")]
+        client_id: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "SECRET",
+            num_args = 0..=1,
+            requires = "oauth",
+            help = "OAuth2 client secret; asked for when no value is given"
+        )]
+        client_secret: Option<Option<String>>,
+
+        #[arg(
+            long,
+            requires = "user",
+            conflicts_with = "token",
+            help = "Only answer HTTP Digest challenges"
+        )]
+        digest: bool,
+    },
+
+    #[command(about = "List saved credential profiles")]
+    List,
+
+    #[command(about = "Forget the credentials saved for a pattern")]
+    Remove {
+        #[arg(help = "Pattern given to `storm auth add`")]
+        pattern: String,
+    },
+}
+
+// The keychain entry `storm auth add` stores a profile's secret under.
+fn secret_name(pattern: &str) -> String {
+    format!("auth:{}", pattern)
+}
+
+fn client_secret_name(pattern: &str) -> String {
+    format!("auth:{}:client", pattern)
+}
+
+// Accepts a bare host, `*.domain` or a URL to take the host from.
+fn normalize(pattern: &str) -> Result<String> {
+    let pattern = match Url::parse(pattern) {
+        Ok(url) if url.host_str().is_some() => url.host_str().unwrap_or_default().to_string(),
+        _ => pattern.trim().trim_end_matches('.').to_ascii_lowercase(),
+    };
+    let wildcard = pattern.strip_prefix("*.").unwrap_or(&pattern);
+    if pattern != "*"
+        && (wildcard.is_empty()
+            || !wildcard
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+    {
+        bail!("'{}' isn't a host name or *.domain pattern", pattern);
+    }
+    Ok(pattern)
+}
+
+pub fn run(command: AuthCommand) -> Result<()> {
+    match command {
+        AuthCommand::Add {
+            pattern,
+            user,
+            token,
+            digest,
+            oauth,
+            client_id,
+            client_secret,
+        } => {
+            let oauth = oauth.map(|token_url| OAuth {
+                token_url,
+                client_id: client_id.unwrap_or_default(),
+                client_secret,
+            });
+            add(&pattern, user, token, digest, oauth)
+        }
+        AuthCommand::List => {
+            list();
+            Ok(())
+        }
+        AuthCommand::Remove { pattern } => remove(&pattern),
+    }
+}
+
+struct OAuth {
+    token_url: String,
+    client_id: String,
+    client_secret: Option<Option<String>>,
+}
+
+fn add(
+    pattern: &str,
+    user: Option<String>,
+    token: Option<Option<String>>,
+    digest: bool,
+    oauth: Option<OAuth>,
+) -> Result<()> {
+    let pattern = normalize(pattern)?;
+    if let Some(oauth) = &oauth {
+        Url::parse(&oauth.token_url)
+            .map_err(|e| anyhow::anyhow!("Invalid --oauth '{}': {}", oauth.token_url, e))?;
+    }
+    let token = match token {
+        None if oauth.is_some() => Some(None),
+        token => token,
+    };
+    let prompt = if oauth.is_some() {
+        "Refresh token"
+    } else {
+        "Token"
+    };
+    let (user, secret) = match (user, token) {
+        (Some(spec), _) => {
+            let (user, password) = match spec.split_once(':') {
+                Some((user, password)) => (user.to_string(), password.to_string()),
+                None => {
+                    let password = read_secret(&format!("Password for {} on {}", spec, pattern))?;
+                    (spec, password)
+                }
+            };
+            (Some(user), password)
+        }
+        (None, Some(Some(token))) => (None, token),
+        (None, Some(None)) => (None, read_secret(&format!("{} for {}", prompt, pattern))?),
+        (None, None) => bail!("Give --user or --token"),
+    };
+    if secret.is_empty() {
+        bail!("The password or token can't be empty");
+    }
+
+    let name = secret_name(&pattern);
+    keychain::set(&name, &secret)?;
+    let mut entry = toml::Table::new();
+    if let Some(user) = &user {
+        entry.insert("user".into(), toml::Value::String(user.clone()));
+    }
+    entry.insert("secret".into(), toml::Value::String(name));
+    if digest {
+        entry.insert("digest".into(), toml::Value::Boolean(true));
+    }
+    if let Some(oauth) = oauth {
+        entry.insert("token_url".into(), toml::Value::String(oauth.token_url));
+        entry.insert("client_id".into(), toml::Value::String(oauth.client_id));
+        let client_secret = match oauth.client_secret {
+            Some(Some(secret)) => Some(secret),
+            Some(None) => Some(read_secret(&format!("Client secret for {}", pattern))?),
+            None => None,
+        };
+        if let Some(client_secret) = client_secret.filter(|s| !s.is_empty()) {
+            let name = client_secret_name(&pattern);
+            keychain::set(&name, &client_secret)?;
+            entry.insert("client_secret".into(), toml::Value::String(name));
+        }
+    }
+    let path = Config::update(&[("credentials", &pattern, toml::Value::Table(entry))])?;
+    println!(
+        "Saved credentials for {} in {} (secret in the {} keychain)",
+        pattern,
+        path.display(),
+        keychain::SERVICE
+    );
+    Ok(())
+}
+
+fn list() {
+    let config = Config::load();
+    let mut profiles = config.credentials.profiles().peekable();
+    if profiles.peek().is_none() {
+        println!("No credential profiles (see `storm auth add --help`)");
+        return;
+    }
+    println!("{:<32} {:<8} {:<16} SECRET", "PATTERN", "TYPE", "USER");
+    for (pattern, entry) in profiles {
+        println!(
+            "{:<32} {:<8} {:<16} {}",
+            pattern,
+            entry.kind(),
+            entry.user.as_deref().unwrap_or("-"),
+            entry.secret
+        );
+    }
+}
+
+fn remove(pattern: &str) -> Result<()> {
+    let pattern = normalize(pattern)?;
+    if !Config::remove("credentials", &pattern)? {
+        bail!("No credentials saved for {}", pattern);
+    }
+    // Secrets named by hand may be shared with other entries; only the one
+    // `storm auth add` created goes.
+    keychain::remove(&secret_name(&pattern))?;
+    keychain::remove(&client_secret_name(&pattern))?;
+    println!("Removed the credentials for {}", pattern);
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use crate::cli::{format_bytes, format_speed};
+use crate::config::SpeedUnits;
+use parking_lot::{Mutex, RwLock};
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const QUEUED: u8 = 0;
+const ACTIVE: u8 = 1;
+const COMPLETE: u8 = 2;
+const FAILED: u8 = 3;
+
+const MAX_ACTIVE_LINES: usize = 8;
+
+struct BatchEntry {
+    name: String,
+    total: AtomicU64,
+    downloaded: Arc<AtomicU64>,
+    status: AtomicU8,
+}
+
+pub struct BatchProgress {
+    entries: RwLock<Vec<Arc<BatchEntry>>>,
+    start_time: Instant,
+    last_sample: Mutex<(u64, Instant)>,
+    lines_drawn: Mutex<usize>,
+    units: SpeedUnits,
+}
+
+#[derive(Clone)]
+pub struct BatchFile {
+    entry: Arc<BatchEntry>,
+}
+
+impl BatchFile {
+    pub fn downloaded(&self) -> Arc<AtomicU64> {
+        self.entry.downloaded.clone()
+    }
+
+    pub fn start(&self, total: u64) {
+        self.entry.total.store(total, Ordering::Relaxed);
+        self.entry.status.store(ACTIVE, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self, success: bool) {
+        let status = if success { COMPLETE } else { FAILED };
+        self.entry.status.store(status, Ordering::Relaxed);
+    }
+}
+
+impl BatchProgress {
+    pub fn new(units: SpeedUnits) -> Self {
+        Self {
+            entries: RwLock::new(Vec::new()),
+            start_time: Instant::now(),
+            last_sample: Mutex::new((0, Instant::now())),
+            lines_drawn: Mutex::new(0),
+            units,
+        }
+    }
+
+    pub fn add_file(&self, name: impl Into<String>) -> BatchFile {
+        let entry = Arc::new(BatchEntry {
+            name: name.into(),
+            total: AtomicU64::new(0),
+            downloaded: Arc::new(AtomicU64::new(0)),
+            status: AtomicU8::new(QUEUED),
+        });
+        self.entries.write().push(entry.clone());
+        BatchFile { entry }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries
+            .read()
+            .iter()
+            .all(|e| e.status.load(Ordering::Relaxed) >= COMPLETE)
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.entries
+            .read()
+            .iter()
+            .filter(|e| e.status.load(Ordering::Relaxed) == FAILED)
+            .count()
+    }
+
+    pub async fn run_display(self: Arc<Self>) {
+        while !self.is_finished() {
+            self.display();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        self.finish();
+    }
+
+    fn totals(&self) -> (u64, u64, usize, usize) {
+        let entries = self.entries.read();
+        let mut downloaded = 0;
+        let mut total = 0;
+        let mut complete = 0;
+        for entry in entries.iter() {
+            let status = entry.status.load(Ordering::Relaxed);
+            let size = entry.total.load(Ordering::Relaxed);
+            downloaded += entry.downloaded.load(Ordering::Relaxed);
+            total += size;
+            if status == COMPLETE {
+                complete += 1;
+            }
+        }
+        (downloaded, total, complete, entries.len())
+    }
+
+    fn display(&self) {
+        let (current, total, complete, count) = self.totals();
+
+        let speed = {
+            let mut last = self.last_sample.lock();
+            let interval = last.1.elapsed().as_secs_f64();
+            let speed = if interval > 0.0 {
+                current.saturating_sub(last.0) as f64 / interval
+            } else {
+                0.0
+            };
+            *last = (current, Instant::now());
+            speed
+        };
+
+        let percent = if total > 0 {
+            (current as f64 / total as f64 * 100.0).min(100.0)
+        } else {
+            0.0
+        };
+
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let avg_speed = if elapsed > 0.0 {
+            current as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta_str = if avg_speed > 0.0 && total > current {
+            let secs = ((total - current) as f64 / avg_speed) as u64;
+            format!("{:02}:{:02}", secs / 60, secs % 60)
+        } else {
+            "--:--".to_string()
+        };
+
+        let mut lines = vec![format!(
+            "[{}] {:5.1}% | {}/{} files | {} / {} | {:>width$} | ETA: {}",
+            bar(percent, 30),
+            percent,
+            complete,
+            count,
+            format_bytes(current),
+            format_bytes(total),
+            format_speed(speed, self.units),
+            eta_str,
+            width = self.units.width()
+        )];
+
+        let entries = self.entries.read();
+        let active: Vec<_> = entries
+            .iter()
+            .filter(|e| e.status.load(Ordering::Relaxed) == ACTIVE)
+            .collect();
+        for entry in active.iter().take(MAX_ACTIVE_LINES) {
+            let size = entry.total.load(Ordering::Relaxed);
+            let done = entry.downloaded.load(Ordering::Relaxed);
+            let file_percent = if size > 0 {
+                (done as f64 / size as f64 * 100.0).min(100.0)
+            } else {
+                0.0
+            };
+            lines.push(format!(
+                "  [{}] {:5.1}% {} ({} / {})",
+                bar(file_percent, 15),
+                file_percent,
+                truncate(&entry.name, 40),
+                format_bytes(done),
+                format_bytes(size)
+            ));
+        }
+        if active.len() > MAX_ACTIVE_LINES {
+            lines.push(format!(
+                "  ... and {} more",
+                active.len() - MAX_ACTIVE_LINES
+            ));
+        }
+        drop(entries);
+
+        self.redraw(&lines);
+    }
+
+    fn redraw(&self, lines: &[String]) {
+        let mut lines_drawn = self.lines_drawn.lock();
+        let mut stderr = io::stderr().lock();
+
+        if *lines_drawn > 0 {
+            let _ = write!(stderr, "\x1b[{}F", *lines_drawn);
+        }
+        for line in lines {
+            let _ = writeln!(stderr, "\x1b[2K{}", line);
+        }
+        for _ in lines.len()..*lines_drawn {
+            let _ = writeln!(stderr, "\x1b[2K");
+        }
+        let _ = stderr.flush();
+
+        *lines_drawn = lines.len().max(*lines_drawn);
+    }
+
+    fn finish(&self) {
+        let (current, _, complete, count) = self.totals();
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let avg_speed = if elapsed > 0.0 {
+            current as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        let mut summary = format!(
+            "[{}] {}/{} files | {} | {:>width$} | {:.1}s",
+            bar(100.0, 30),
+            complete,
+            count,
+            format_bytes(current),
+            format_speed(avg_speed, self.units),
+            elapsed,
+            width = self.units.width()
+        );
+        let failed = self.failed_count();
+        if failed > 0 {
+            summary.push_str(&format!(" | {} failed", failed));
+        }
+        self.redraw(&[summary]);
+    }
+}
+
+impl Default for BatchProgress {
+    fn default() -> Self {
+        Self::new(SpeedUnits::default())
+    }
+}
+
+fn bar(percent: f64, width: usize) -> String {
+    let filled = ((percent / 100.0) * width as f64) as usize;
+    "█".repeat(filled.min(width)) + "░".repeat(width - filled.min(width)).as_str()
+}
+
+fn truncate(name: &str, max: usize) -> String {
+    if name.chars().count() <= max {
+        name.to_string()
+    } else {
+        let head: String = name.chars().take(max - 1).collect();
+        format!("{}…", head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_follow_files() {
+        let progress = BatchProgress::default();
+        assert!(progress.is_finished());
+
+        let first = progress.add_file("first.bin");
+        let second = progress.add_file("second.bin");
+        assert_eq!(progress.len(), 2);
+        assert!(!progress.is_finished());
+        assert_eq!(progress.totals(), (0, 0, 0, 2));
+
+        first.start(1000);
+        second.start(500);
+        first.downloaded().fetch_add(1000, Ordering::Relaxed);
+        second.downloaded().fetch_add(200, Ordering::Relaxed);
+        first.finish(true);
+        assert_eq!(progress.totals(), (1200, 1500, 1, 2));
+        assert!(!progress.is_finished());
+
+        second.finish(false);
+        assert!(progress.is_finished());
+        assert_eq!(progress.totals(), (1200, 1500, 1, 2));
+        assert_eq!(progress.failed_count(), 1);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::artifacts::ArtifactCache;
+use crate::cli::format_bytes;
+use crate::config::{Config, parse_size};
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use std::time::Duration;
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    #[command(about = "List cached downloads, most recently used first")]
+    Ls,
+
+    #[command(about = "Evict old or least recently used files from the cache")]
+    Gc {
+        #[arg(
+            long,
+            value_name = "SIZE",
+            help = "Shrink the cache to this size (default: cache.max_size)"
+        )]
+        max_size: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "AGE",
+            value_parser = crate::duration_arg,
+            help = "Drop files not used for this long (e.g. 12h, 30d)"
+        )]
+        older_than: Option<Duration>,
+    },
+}
+
+pub fn run(command: CacheCommand) -> Result<()> {
+    let config = Config::load();
+    let dir = config
+        .cache
+        .path()
+        .context("No cache directory on this platform; set cache.dir")?;
+    let cache = ArtifactCache::new(dir);
+    match command {
+        CacheCommand::Ls => {
+            ls(&cache);
+            Ok(())
+        }
+        CacheCommand::Gc {
+            max_size,
+            older_than,
+        } => {
+            let max_size = match max_size {
+                Some(size) => Some(
+                    parse_size(&size).with_context(|| format!("Invalid --max-size '{}'", size))?,
+                ),
+                None => config.cache.max_size(),
+            };
+            let cutoff =
+                older_than.map(|age| chrono::Utc::now().timestamp() - age.as_secs() as i64);
+            let stats = cache.gc(max_size, cutoff);
+            println!(
+                "Removed {} entries and {} files ({}); {} left in {}",
+                stats.entries,
+                stats.objects,
+                format_bytes(stats.freed),
+                format_bytes(cache.size()),
+                cache.dir().display()
+            );
+            Ok(())
+        }
+    }
+}
+
+fn ls(cache: &ArtifactCache) {
+    let mut entries = cache.entries();
+    if entries.is_empty() {
+        println!("The cache at {} is empty", cache.dir().display());
+        return;
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.used_at));
+
+    println!(
+        "{:>10}  {:<16}  {:<12}  URL",
+        "SIZE", "LAST USED", "SHA-256"
+    );
+    for entry in &entries {
+        let used = chrono::DateTime::from_timestamp(entry.used_at, 0)
+            .map(|t| {
+                t.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!(
+            "{:>10}  {:<16}  {:<12}  {}",
+            format_bytes(entry.size),
+            used,
+            &entry.sha256[..12],
+            entry.url
+        );
+    }
+    println!(
+        "{} entries, {} in {}",
+        entries.len(),
+        format_bytes(cache.size()),
+        cache.dir().display()
+    );
+}
@@ -0,0 +1,152 @@
+use crate::cli::format_speed;
+use crate::config::{self, Config};
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use stormdl::speedtest;
+use stormdl_core::Downloader;
+use stormdl_protocol::HttpDownloader;
+use url::Url;
+
+const SEGMENT_CANDIDATES: [usize; 6] = [1, 2, 4, 8, 16, 32];
+const BUFFER_CANDIDATES: [u64; 4] = [256 * 1024, 1024 * 1024, 4 * 1024 * 1024, 16 * 1024 * 1024];
+const FSYNC_MAX_OVERHEAD: f64 = 0.10;
+
+pub struct CalibrateArgs {
+    pub sample_size: String,
+    pub disk_sample_size: String,
+    pub output: Option<String>,
+    pub dry_run: bool,
+}
+
+pub fn calibrate(url_str: &str, args: CalibrateArgs) -> Result<()> {
+    let url = Url::parse(url_str).context("Invalid URL")?;
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async move { calibrate_async(url, args).await })
+}
+
+async fn calibrate_async(url: Url, args: CalibrateArgs) -> Result<()> {
+    let sample_size = config::parse_size(&args.sample_size).context("Invalid --sample-size")?;
+    let disk_sample =
+        config::parse_size(&args.disk_sample_size).context("Invalid --disk-sample-size")?;
+
+    let units = Config::load().progress.speed_units;
+    let downloader = Arc::new(HttpDownloader::turbo()?);
+    eprintln!("Probing {}...", url);
+    let info = downloader.probe(&url).await?;
+
+    let total = info.size.unwrap_or(0);
+    if !info.supports_range || total == 0 {
+        anyhow::bail!("Calibration needs a server that supports range requests and reports a size");
+    }
+    let sample = sample_size.min(total);
+
+    eprintln!("Network: {} sample per run", format_size(sample));
+    let best_segments = speedtest::best_segments(
+        &downloader,
+        &url,
+        sample,
+        &SEGMENT_CANDIDATES,
+        |segments, speed| eprintln!("  {:>2} segments: {}", segments, format_speed(speed, units)),
+    )
+    .await?
+    .segments;
+
+    let output_dir = args
+        .output
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")));
+    let scratch = output_dir.join(".storm-calibrate.tmp");
+
+    eprintln!(
+        "Disk: {} written to {}",
+        format_size(disk_sample),
+        output_dir.display()
+    );
+    let mut best_buffer = BUFFER_CANDIDATES[0];
+    let mut best_disk = 0.0;
+    for &buffer in &BUFFER_CANDIDATES {
+        let speed = measure_disk(&scratch, disk_sample, buffer, false)?;
+        eprintln!(
+            "  {:>8} buffer: {}/s",
+            format_size(buffer),
+            format_size(speed as u64)
+        );
+        if speed > best_disk {
+            best_disk = speed;
+            best_buffer = buffer;
+        }
+    }
+
+    let synced = measure_disk(&scratch, disk_sample, best_buffer, true)?;
+    let _ = std::fs::remove_file(&scratch);
+    let fsync_overhead = 1.0 - synced / best_disk.max(1.0);
+    let fsync = fsync_overhead <= FSYNC_MAX_OVERHEAD;
+    eprintln!(
+        "  fsync on completion: {:.0}% slower",
+        fsync_overhead.max(0.0) * 100.0
+    );
+
+    eprintln!();
+    eprintln!("Recommended:");
+    eprintln!("  segments.calibrated_segments = {}", best_segments);
+    eprintln!("  io.write_buffer_size = \"{}\"", format_size(best_buffer));
+    eprintln!("  io.fsync = {}", fsync);
+
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let path = Config::update(&[
+        (
+            "segments",
+            "calibrated_segments",
+            toml::Value::Integer(best_segments as i64),
+        ),
+        (
+            "io",
+            "write_buffer_size",
+            toml::Value::String(format_size(best_buffer)),
+        ),
+        ("io", "fsync", toml::Value::Boolean(fsync)),
+    ])?;
+    eprintln!("Saved to {}", path.display());
+
+    Ok(())
+}
+
+fn measure_disk(path: &Path, total: u64, buffer_size: u64, fsync: bool) -> Result<f64> {
+    let chunk = vec![0xA5u8; buffer_size as usize];
+    let start = Instant::now();
+
+    let mut file = std::fs::File::create(path)?;
+    let mut written = 0;
+    while written < total {
+        let n = (total - written).min(buffer_size) as usize;
+        file.write_all(&chunk[..n])?;
+        written += n as u64;
+    }
+    file.flush()?;
+    if fsync {
+        file.sync_all()?;
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    Ok(total as f64 / elapsed.max(0.001))
+}
+
+fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    if bytes >= MB && bytes.is_multiple_of(MB) {
+        format!("{}MB", bytes / MB)
+    } else if bytes >= KB && bytes.is_multiple_of(KB) {
+        format!("{}KB", bytes / KB)
+    } else if bytes >= MB {
+        format!("{:.1}MB", bytes as f64 / MB as f64)
+    } else {
+        format!("{}B", bytes)
+    }
+}
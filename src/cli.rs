@@ -1,23 +1,54 @@
 #![allow(clippy::type_complexity)]
 #![allow(clippy::too_many_arguments)]
 
+use crate::artifacts::ArtifactCache;
+use crate::batch::{BatchFile, BatchProgress};
+use crate::config::{Config, SpeedUnits};
+use crate::extract::Extraction;
+use crate::hooks::Hooks;
+use crate::journal::{Journal, Status};
+use crate::listfile::ListEntry;
+use crate::profile::{check_schedule, window_left};
+use crate::resume::{Extent, ResumeJournal};
+use crate::stream::{Quality, Stream};
+use crate::template::{Completion, Field, Template};
 use anyhow::{Context, Result};
 use bytes::Bytes;
+use clap::ValueEnum;
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
 use parking_lot::{Mutex, RwLock};
-use std::collections::VecDeque;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
-use stormdl_core::{ByteRange, Downloader, ResourceInfo};
-use stormdl_protocol::HttpDownloader;
-use stormdl_segment::SegmentManager;
-use tokio::sync::Notify;
+use stormdl_bandwidth::{
+    CongestionGate, DownloadQueue, NetworkMonitor, PathBalancer, ProfileGovernor, ProfileSample,
+    QueuedDownload, RateLimiter, TransferProfile,
+};
+use stormdl_core::{
+    ArchiveEntry, ByteRange, ContentPolicy, Credentials, DataSink, DownloadId, DownloadOptions,
+    DownloadState, Downloader, HttpVersion, MirrorSet, MonthlyQuota, Priority, ProgressPacer,
+    QuotaLevel, ResourceInfo, RetryAction, RetryBudget, RetryPolicy, StormError,
+};
+use stormdl_integrity::{ChecksumSpec, HashAlgorithm, PublicKey, StreamVerifier};
+use stormdl_io::DirectWriter;
+use stormdl_metalink::{MetalinkFile, Pieces};
+use stormdl_protocol::{
+    ClientOptions, CookieJar, CustomRequest, Downgrade, DualStack, Har, HostAuth, HttpDownloader,
+    LfsPointer, LocalBind, Negotiated, OciBlob, PreferredProtocol, ProxyConfig, Route, S3Config,
+    parse_header, probe_with_fallback,
+};
+use stormdl_segment::{
+    MultiSourceManager, RangeClaim, SegmentManager, SegmentTracker, SplitHint, WorkQueue,
+};
 use url::Url;
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct DownloadArgs {
     pub output: Option<String>,
     pub name: Option<String>,
@@ -25,88 +56,465 @@ pub struct DownloadArgs {
     pub limit: Option<String>,
     pub turbo: bool,
     pub no_resume: bool,
+    pub cache: Option<ArtifactCache>,
     pub checksum: Option<String>,
+    pub checksum_url: Option<String>,
+    pub signature_url: Option<String>,
+    pub signify_key: Option<String>,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub list_contents: bool,
+    // Some(None) unpacks next to the archive.
+    pub extract: Option<Option<PathBuf>>,
     pub quiet: bool,
     pub mirrors: Vec<String>,
+    pub print_after: Option<Template>,
+    pub direct_io: bool,
+    pub group: Option<String>,
+    pub max_size: Option<String>,
+    pub accept_types: Vec<String>,
+    pub proxy: Option<String>,
+    pub mirror_proxies: Vec<String>,
+    pub headers: Vec<String>,
+    pub method: Option<String>,
+    pub data: Option<String>,
+    pub cookie_file: Option<String>,
+    pub cookies: Vec<String>,
+    pub har: Option<PathBuf>,
+    pub auth: Option<Credentials>,
+    pub location_trusted: bool,
+    pub ssh_key: Option<PathBuf>,
+    pub protocol: PreferredProtocol,
+    pub single_stream: bool,
+    pub no_stream: bool,
+    pub quality: Quality,
+    pub dual_stack: bool,
+    pub interfaces: Vec<String>,
+    pub continue_partial: bool,
+    pub hooks: Option<String>,
+    pub retries: Option<u32>,
+    pub segment_retries: Option<u32>,
+    pub retry_delay: Option<Duration>,
+    pub ready_wait: Option<Duration>,
+    pub max_time: Option<Duration>,
+    pub escalation: Option<Vec<String>>,
+    pub notify: Option<Vec<String>>,
+    pub notify_webhook: Option<String>,
+    pub no_notify: bool,
+    pub progress_interval: Option<u64>,
+    pub low_power: bool,
+    pub progress: ProgressStyle,
+    pub batch: Option<BatchFile>,
+    pub metalink: Option<Arc<MetalinkFile>>,
+    pub pool: Option<BatchPool>,
+    pub config: Config,
 }
 
-struct SegmentTracker {
-    downloaded: AtomicU64,
-    total: u64,
-    remaining_start: AtomicU64,
-    last_progress: Mutex<(u64, Instant)>,
-    active: AtomicBool,
+impl DownloadArgs {
+    pub fn new(config: Config) -> Self {
+        Self {
+            output: None,
+            name: None,
+            segments: None,
+            limit: None,
+            turbo: true,
+            no_resume: false,
+            cache: None,
+            checksum: None,
+            checksum_url: None,
+            signature_url: None,
+            signify_key: None,
+            tags: Vec::new(),
+            note: None,
+            list_contents: false,
+            extract: None,
+            quiet: false,
+            mirrors: Vec::new(),
+            print_after: None,
+            direct_io: false,
+            group: None,
+            max_size: None,
+            accept_types: Vec::new(),
+            proxy: None,
+            mirror_proxies: Vec::new(),
+            headers: Vec::new(),
+            method: None,
+            data: None,
+            cookie_file: None,
+            cookies: Vec::new(),
+            har: None,
+            auth: None,
+            location_trusted: false,
+            ssh_key: None,
+            protocol: PreferredProtocol::Auto,
+            single_stream: false,
+            no_stream: false,
+            quality: Quality::Best,
+            dual_stack: false,
+            interfaces: Vec::new(),
+            continue_partial: false,
+            hooks: None,
+            retries: None,
+            segment_retries: None,
+            retry_delay: None,
+            ready_wait: None,
+            max_time: None,
+            escalation: None,
+            notify: None,
+            notify_webhook: None,
+            no_notify: false,
+            progress_interval: None,
+            low_power: false,
+            progress: ProgressStyle::default(),
+            batch: None,
+            metalink: None,
+            pool: None,
+            config,
+        }
+    }
 }
 
-impl SegmentTracker {
-    fn new(total: u64, start: u64) -> Self {
+// What the files of one batch share: a client per origin, so small files
+// reuse kept-alive connections (or streams of one HTTP/2 connection), and
+// the slots that bound how many large files transfer at once.
+#[derive(Clone)]
+pub struct BatchPool {
+    clients: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Arc<HttpDownloader>>>>>>>,
+    large: Arc<tokio::sync::Semaphore>,
+    small_file_threshold: u64,
+    // How long a small file's probe stands in for probing it again.
+    probe_ttl: Option<Duration>,
+}
+
+impl BatchPool {
+    fn new(concurrent: usize, small_file_threshold: u64, probe_ttl: Option<Duration>) -> Self {
         Self {
-            downloaded: AtomicU64::new(0),
-            total,
-            remaining_start: AtomicU64::new(start),
-            last_progress: Mutex::new((0, Instant::now())),
-            active: AtomicBool::new(true),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            large: Arc::new(tokio::sync::Semaphore::new(concurrent.max(1))),
+            small_file_threshold,
+            probe_ttl,
         }
     }
 
-    fn speed(&self) -> f64 {
-        let (last_bytes, last_time) = *self.last_progress.lock();
-        let current = self.downloaded.load(Ordering::Relaxed);
-        let elapsed = last_time.elapsed().as_secs_f64();
-        if elapsed > 0.5 {
-            (current.saturating_sub(last_bytes)) as f64 / elapsed
-        } else {
-            0.0
+    // Keyed by headers as well as origin: hook scripts can send different
+    // ones per URL, and a client keeps those it was built with.
+    async fn client(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+    ) -> tokio::sync::OwnedMutexGuard<Option<Arc<HttpDownloader>>> {
+        let key = format!("{} {:?}", url.origin().ascii_serialization(), headers);
+        let slot = self.clients.lock().entry(key).or_default().clone();
+        slot.lock_owned().await
+    }
+}
+
+// The start of a file is fetched from this many sources at once to pick
+// the one to lead with.
+const RACE_MIRRORS: usize = 3;
+const RACE_BYTES: u64 = 1024 * 1024;
+// Below this the duplicate megabytes cost more than a better pick saves.
+const RACE_MIN_SIZE: u64 = 32 * 1024 * 1024;
+const RACE_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Keeps what a source sends during the race; the winner's bytes become the
+// start of the file. Race traffic waits on the rate limit like any other.
+#[derive(Clone)]
+struct RaceSink {
+    received: Arc<AtomicU64>,
+    first_byte: Arc<Mutex<Option<Instant>>>,
+    data: Arc<Mutex<Vec<u8>>>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RaceSink {
+    fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            received: Arc::default(),
+            first_byte: Arc::default(),
+            data: Arc::default(),
+            limiter,
         }
     }
+}
 
-    fn update_speed_sample(&self) {
-        let current = self.downloaded.load(Ordering::Relaxed);
-        *self.last_progress.lock() = (current, Instant::now());
+#[async_trait::async_trait]
+impl DataSink for RaceSink {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        self.first_byte.lock().get_or_insert_with(Instant::now);
+        self.received
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.data.lock().extend_from_slice(&data);
+        Ok(())
     }
 
-    fn remaining(&self) -> u64 {
-        self.total
-            .saturating_sub(self.downloaded.load(Ordering::Relaxed))
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
     }
 
-    fn is_complete(&self) -> bool {
-        self.downloaded.load(Ordering::Relaxed) >= self.total
+    async fn ready(&mut self, len: usize) -> Result<(), StormError> {
+        self.limiter.acquire(len).await;
+        Ok(())
     }
 }
 
-struct WorkQueue {
-    ranges: Mutex<VecDeque<(ByteRange, usize)>>,
-    notify: Notify,
+pub(crate) struct RetryState {
+    budget: RetryBudget,
+    sources: Vec<Url>,
+    source: AtomicUsize,
+    mirrors: Option<MultiSourceManager>,
+    tickets: AtomicUsize,
+    single: AtomicBool,
+    serial: tokio::sync::Mutex<()>,
+    failure: Mutex<Option<anyhow::Error>>,
 }
 
-impl WorkQueue {
-    fn new() -> Self {
+impl RetryState {
+    fn new(policy: RetryPolicy, sources: Vec<Url>) -> Self {
         Self {
-            ranges: Mutex::new(VecDeque::new()),
-            notify: Notify::new(),
+            budget: RetryBudget::new(policy, sources.len().saturating_sub(1)),
+            sources,
+            source: AtomicUsize::new(0),
+            mirrors: None,
+            tickets: AtomicUsize::new(0),
+            single: AtomicBool::new(false),
+            serial: tokio::sync::Mutex::new(()),
+            failure: Mutex::new(None),
+        }
+    }
+
+    // Segments are spread over all sources by measured speed rather than
+    // only moving on when one fails.
+    fn with_mirrors(mut self, total_size: u64) -> Self {
+        if self.sources.len() > 1 {
+            let mirrors = MirrorSet::from(self.sources.clone());
+            self.mirrors = Some(MultiSourceManager::new(mirrors, total_size));
+        }
+        self
+    }
+
+    // Races the first RACE_BYTES from up to RACE_MIRRORS sources and returns
+    // the one that finished first. With --mirror it leads and the others
+    // follow as backups, the furthest along first; a metalink's manager
+    // instead starts from the measured speeds rather than static priorities.
+    // Losing or failing the race spends no retries. The winner's bytes are
+    // returned to be written rather than fetched again; the rest are counted
+    // against the monthly quota here.
+    async fn race(
+        &mut self,
+        downloader: &HttpDownloader,
+        limiter: &Arc<RateLimiter>,
+    ) -> Option<(Url, Bytes)> {
+        let contenders = self.sources.len().min(RACE_MIRRORS);
+        let range = ByteRange::new(0, RACE_BYTES);
+        let sinks: Vec<_> = (0..contenders)
+            .map(|_| RaceSink::new(limiter.clone()))
+            .collect();
+        let started = Instant::now();
+        let mut racing: FuturesUnordered<_> = self.sources[..contenders]
+            .iter()
+            .zip(sinks.iter().cloned())
+            .enumerate()
+            .map(|(idx, (url, mut sink))| async move {
+                let fetch = downloader.fetch_range(url, range, &mut sink);
+                let finished = tokio::time::timeout(RACE_TIMEOUT, fetch).await;
+                (idx, matches!(finished, Ok(Ok(()))))
+            })
+            .collect();
+        let mut failed = Vec::new();
+        let winner = loop {
+            match racing.next().await {
+                Some((idx, true)) => break Some(idx),
+                Some((idx, false)) => failed.push(idx),
+                None => break None,
+            }
+        };
+        drop(racing);
+        let wasted: u64 = sinks
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| Some(*idx) != winner)
+            .map(|(_, sink)| sink.received.load(Ordering::Relaxed))
+            .sum();
+        if wasted > 0
+            && let Some(manifest) = Config::open_manifest()
+            && let Err(e) = manifest.add_usage(wasted)
+        {
+            tracing::warn!("Failed to record data usage: {}", e);
+        }
+        let winner = winner?;
+        let fastest = self.sources[winner].clone();
+        let head = Bytes::from(std::mem::take(&mut *sinks[winner].data.lock()));
+        let elapsed = started.elapsed().as_secs_f64().max(1e-3);
+
+        if let Some(mirrors) = &self.mirrors {
+            for (idx, sink) in sinks.iter().enumerate() {
+                let bytes = sink.received.load(Ordering::Relaxed);
+                if failed.contains(&idx) {
+                    mirrors.record_error(idx);
+                } else if bytes > 0 {
+                    mirrors.record_progress(idx, bytes, bytes as f64 / elapsed);
+                }
+                if let Some(first_byte) = *sink.first_byte.lock() {
+                    mirrors.record_ttfb(idx, first_byte.duration_since(started));
+                }
+            }
+            mirrors.sync_mirror_stats();
+        } else {
+            let mut order: Vec<usize> = (0..contenders)
+                .filter(|idx| *idx != winner && !failed.contains(idx))
+                .collect();
+            order
+                .sort_by_key(|idx| std::cmp::Reverse(sinks[*idx].received.load(Ordering::Relaxed)));
+            order.insert(0, winner);
+            order.extend(failed);
+            let mut sources: Vec<Url> =
+                order.iter().map(|idx| self.sources[*idx].clone()).collect();
+            sources.extend(self.sources.drain(contenders..));
+            self.sources = sources;
+        }
+        Some((fastest, head))
+    }
+
+    fn url(&self) -> &Url {
+        let idx = self.source.load(Ordering::Relaxed);
+        &self.sources[idx.min(self.sources.len() - 1)]
+    }
+
+    // Where to fetch the next range from, with a ticket to report back on.
+    fn source_for(&self, range: ByteRange) -> (Option<usize>, &Url) {
+        match &self.mirrors {
+            Some(mirrors) if !self.is_single() => {
+                let ticket = self.tickets.fetch_add(1, Ordering::Relaxed);
+                let idx = mirrors.assign_segment(ticket, range);
+                (Some(ticket), &self.sources[idx])
+            }
+            _ => (None, self.url()),
+        }
+    }
+
+    fn record(&self, ticket: Option<usize>, bytes: u64, elapsed: Duration, failed: bool) {
+        let (Some(mirrors), Some(ticket)) = (&self.mirrors, ticket) else {
+            return;
+        };
+        let Some(idx) = mirrors.get_assignment(ticket) else {
+            return;
+        };
+        mirrors.complete_segment(ticket);
+        if bytes > 0 {
+            mirrors.record_progress(idx, bytes, bytes as f64 / elapsed.as_secs_f64().max(1e-3));
+        }
+        if failed {
+            mirrors.record_error(idx);
+        }
+        mirrors.sync_mirror_stats();
+    }
+
+    fn is_single(&self) -> bool {
+        self.single.load(Ordering::Relaxed)
+    }
+
+    pub(crate) async fn connection(&self) -> Option<tokio::sync::MutexGuard<'_, ()>> {
+        if self.is_single() {
+            Some(self.serial.lock().await)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) async fn recover(
+        &self,
+        segment: usize,
+        ticket: Option<usize>,
+        error: anyhow::Error,
+    ) -> Result<()> {
+        let storm_error = error.downcast_ref::<stormdl_core::StormError>();
+        // A source that can't serve the file at all (a 404, a refused login)
+        // is dropped while others remain, without using up a retry.
+        if let (Some(mirrors), Some(ticket)) = (&self.mirrors, ticket)
+            && storm_error.is_some_and(|e| !e.is_retryable())
+            && let Some(idx) = mirrors.get_assignment(ticket)
+            && mirrors.retire(idx)
+        {
+            tracing::warn!(
+                "Segment {} failed: {}; dropping {}",
+                segment,
+                error,
+                self.sources[idx]
+            );
+            return Ok(());
+        }
+        let action = match storm_error {
+            Some(e) => self.budget.on_failure(segment, e),
+            None => RetryAction::Fail,
+        };
+
+        match action {
+            RetryAction::Retry(delay) => {
+                tracing::warn!(
+                    "Segment {} failed: {}; retrying in {:.1}s",
+                    segment,
+                    error,
+                    delay.as_secs_f64()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            RetryAction::SwitchMirror => {
+                let next = self.source.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::warn!(
+                    "Segment {} failed: {}; switching to {}",
+                    segment,
+                    error,
+                    self.sources[next.min(self.sources.len() - 1)]
+                );
+            }
+            RetryAction::SingleConnection => {
+                self.single.store(true, Ordering::Relaxed);
+                tracing::warn!(
+                    "Segment {} failed: {}; dropping to a single connection",
+                    segment,
+                    error
+                );
+            }
+            RetryAction::Fail => {
+                let retries = self.budget.retries_used();
+                if retries > 0 {
+                    return Err(error.context(format!("Giving up after {} retries", retries)));
+                }
+                return Err(error);
+            }
         }
+        Ok(())
     }
 
-    fn push(&self, range: ByteRange, segment_idx: usize) {
-        self.ranges.lock().push_back((range, segment_idx));
-        self.notify.notify_one();
+    fn fail(&self, error: anyhow::Error) {
+        self.failure.lock().get_or_insert(error);
     }
 
-    fn pop(&self) -> Option<(ByteRange, usize)> {
-        self.ranges.lock().pop_front()
+    fn failed(&self) -> bool {
+        self.failure.lock().is_some()
     }
 
-    fn is_empty(&self) -> bool {
-        self.ranges.lock().is_empty()
+    fn take_failure(&self) -> Option<anyhow::Error> {
+        self.failure.lock().take()
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ProgressStyle {
+    #[default]
+    Bar,
+    Json,
+}
+
 #[allow(dead_code)]
 struct Progress {
     total: u64,
     downloaded: Arc<AtomicU64>,
     segment_progress: Option<Arc<RwLock<Vec<(u64, u64)>>>>,
+    ranges: Vec<ByteRange>,
+    style: ProgressStyle,
+    units: SpeedUnits,
     start_time: Instant,
     last_bytes: u64,
     last_time: Instant,
@@ -115,11 +523,20 @@ struct Progress {
 }
 
 impl Progress {
-    fn new(total: u64, downloaded: Arc<AtomicU64>, done: Arc<AtomicBool>) -> Self {
+    fn new(
+        range: ByteRange,
+        downloaded: Arc<AtomicU64>,
+        done: Arc<AtomicBool>,
+        style: ProgressStyle,
+        units: SpeedUnits,
+    ) -> Self {
         Self {
-            total,
+            total: range.len(),
             downloaded,
             segment_progress: None,
+            ranges: vec![range],
+            style,
+            units,
             start_time: Instant::now(),
             last_bytes: 0,
             last_time: Instant::now(),
@@ -133,21 +550,58 @@ impl Progress {
         downloaded: Arc<AtomicU64>,
         done: Arc<AtomicBool>,
         segment_progress: Arc<RwLock<Vec<(u64, u64)>>>,
-        num_segments: usize,
+        ranges: Vec<ByteRange>,
+        style: ProgressStyle,
+        units: SpeedUnits,
     ) -> Self {
         Self {
             total,
             downloaded,
             segment_progress: Some(segment_progress),
+            num_segments: ranges.len(),
+            ranges,
+            style,
+            units,
             start_time: Instant::now(),
             last_bytes: 0,
             last_time: Instant::now(),
             done,
-            num_segments,
         }
     }
 
-    fn display(&mut self) {
+    fn segment_json(&self, current: u64) -> Vec<serde_json::Value> {
+        let progress: Vec<u64> = match &self.segment_progress {
+            Some(segments) => segments.read().iter().map(|(dl, _)| *dl).collect(),
+            None => vec![current],
+        };
+        self.ranges
+            .iter()
+            .zip(progress)
+            .map(|(range, downloaded)| {
+                serde_json::json!({
+                    "offset": range.start,
+                    "length": range.len(),
+                    "downloaded": downloaded.min(range.len()),
+                })
+            })
+            .collect()
+    }
+
+    fn emit_json(&self, current: u64, speed: f64, done: bool) {
+        let line = serde_json::json!({
+            "downloaded": current,
+            "total": self.total,
+            "speed": speed as u64,
+            "elapsed_ms": self.start_time.elapsed().as_millis() as u64,
+            "done": done,
+            "segments": self.segment_json(current),
+        });
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+
+    fn display(&mut self) -> f64 {
         let current = self.downloaded.load(Ordering::Relaxed);
         let elapsed = self.start_time.elapsed().as_secs_f64();
         let interval = self.last_time.elapsed().as_secs_f64();
@@ -158,6 +612,15 @@ impl Progress {
             0.0
         };
 
+        if self.style == ProgressStyle::Json {
+            self.emit_json(current, speed, false);
+            if interval > 0.1 {
+                self.last_bytes = current;
+                self.last_time = Instant::now();
+            }
+            return speed;
+        }
+
         let avg_speed = if elapsed > 0.0 {
             current as f64 / elapsed
         } else {
@@ -179,7 +642,7 @@ impl Progress {
 
         let bar_width = 30;
         let filled = (percent / 100.0 * bar_width as f64) as usize;
-        let bar: String = "█".repeat(filled) + &"░".repeat(bar_width - filled);
+        let bar: String = "█".repeat(filled) + "░".repeat(bar_width - filled).as_str();
 
         let eta_str = match eta {
             Some(d) => {
@@ -220,14 +683,15 @@ impl Progress {
         };
 
         eprint!(
-            "\r[{}] {:5.1}% | {} / {} | {:>8}/s | ETA: {}{} ",
+            "\r[{}] {:5.1}% | {} / {} | {:>width$} | ETA: {}{} ",
             bar,
             percent,
             format_bytes(current),
             format_bytes(self.total),
-            format_bytes(speed as u64),
+            format_speed(speed, self.units),
             eta_str,
-            segment_str
+            segment_str,
+            width = self.units.width()
         );
         io::stderr().flush().ok();
 
@@ -235,6 +699,7 @@ impl Progress {
             self.last_bytes = current;
             self.last_time = Instant::now();
         }
+        speed
     }
 
     fn finish(&self) {
@@ -246,6 +711,11 @@ impl Progress {
             0.0
         };
 
+        if self.style == ProgressStyle::Json {
+            self.emit_json(current, avg_speed, true);
+            return;
+        }
+
         let segment_str = if self.num_segments > 1 {
             format!(" [{}]", "█".repeat(self.num_segments))
         } else {
@@ -253,17 +723,18 @@ impl Progress {
         };
 
         eprintln!(
-            "\r[{}] 100.0% | {} | {:>8}/s | {:.1}s{}        ",
+            "\r[{}] 100.0% | {} | {:>width$} | {:.1}s{}        ",
             "█".repeat(30),
             format_bytes(current),
-            format_bytes(avg_speed as u64),
+            format_speed(avg_speed, self.units),
             elapsed.as_secs_f64(),
-            segment_str
+            segment_str,
+            width = self.units.width()
         );
     }
 }
 
-fn format_bytes(bytes: u64) -> String {
+pub fn format_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = 1024 * KB;
     const GB: u64 = 1024 * MB;
@@ -279,10 +750,31 @@ fn format_bytes(bytes: u64) -> String {
     }
 }
 
+pub fn format_speed(bytes_per_sec: f64, units: SpeedUnits) -> String {
+    let bytes = format!("{}/s", format_bytes(bytes_per_sec as u64));
+    // Binary multiples, like format_bytes and parse_rate.
+    const K: f64 = 1024.0;
+    let bits = bytes_per_sec * 8.0;
+    let bits = if bits >= K * K * K {
+        format!("{:.2} Gbps", bits / (K * K * K))
+    } else if bits >= K * K {
+        format!("{:.1} Mbps", bits / (K * K))
+    } else if bits >= K {
+        format!("{:.0} kbps", bits / K)
+    } else {
+        format!("{:.0} bps", bits)
+    };
+    match units {
+        SpeedUnits::Bytes => bytes,
+        SpeedUnits::Bits => bits,
+        SpeedUnits::Both => format!("{} ({})", bits, bytes),
+    }
+}
+
 fn calculate_segments(info: &ResourceInfo, args: &DownloadArgs) -> usize {
     let total_size = info.size.unwrap_or(0);
 
-    if let Some(s) = args.segments {
+    if let Some(s) = args.segments.or(args.config.segments.calibrated_segments) {
         return s;
     }
 
@@ -308,195 +800,1982 @@ pub fn download(url_str: &str, args: DownloadArgs) -> Result<()> {
     rt.block_on(async move { download_async(url, args).await })
 }
 
-async fn download_async(url: Url, args: DownloadArgs) -> Result<()> {
-    let downloader = if args.turbo {
-        HttpDownloader::turbo()?
-    } else {
-        HttpDownloader::new()?
+pub fn download_batch(
+    entries: Vec<ListEntry>,
+    args: DownloadArgs,
+    concurrent: usize,
+    journal: Option<PathBuf>,
+) -> Result<()> {
+    // A journal asked for by name must work; the default one is a nicety.
+    let journal = match Journal::open(journal.clone()) {
+        Ok(journal) => Some(journal),
+        Err(e) if journal.is_none() => {
+            tracing::warn!("{:#}; not keeping a session journal", e);
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(download_batch_async(entries, args, concurrent, journal))
+}
+
+async fn download_batch_async(
+    entries: Vec<ListEntry>,
+    args: DownloadArgs,
+    concurrent: usize,
+    journal: Option<Journal>,
+) -> Result<()> {
+    // Files start up to small_file_concurrency at once; only the small ones
+    // run past --concurrent.
+    let pool = BatchPool::new(
+        concurrent,
+        args.config.batch.small_file_threshold(),
+        args.config.batch.probe_ttl(),
+    );
+    let queue = DownloadQueue::new(
+        concurrent
+            .max(args.config.batch.small_file_concurrency)
+            .max(1),
+    );
+    let progress = Arc::new(BatchProgress::new(args.config.progress.speed_units));
+    let mut pending = HashMap::new();
+    let mut failures = Vec::new();
+
+    let record = |entry: &ListEntry, status: Status, error: Option<String>| {
+        if let Some(journal) = &journal {
+            journal.record(entry, status, error);
+        }
     };
 
-    if !args.quiet {
-        eprintln!("Probing {}...", url);
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let url = match Url::parse(&entry.url) {
+            Ok(url) => url,
+            Err(e) => {
+                let e = anyhow::anyhow!("Invalid URL: {}", e);
+                record(&entry, Status::Failed, Some(format!("{:#}", e)));
+                progress.add_file(entry.url.clone()).finish(false);
+                failures.push((entry.url, e));
+                continue;
+            }
+        };
+        let name = entry.out.clone().unwrap_or_else(|| {
+            url.path_segments()
+                .and_then(|mut s| s.next_back())
+                .filter(|s| !s.is_empty())
+                .unwrap_or(url.as_str())
+                .to_string()
+        });
+
+        let mut file_args = args.clone();
+        file_args.name = entry.out.clone();
+        file_args.metalink = entry.metalink.clone();
+        if let Some(dir) = &entry.dir {
+            file_args.output = Some(dir.to_string_lossy().into_owned());
+        }
+        file_args.batch = Some(progress.add_file(name));
+        file_args.pool = Some(pool.clone());
+
+        let id = DownloadId(idx as u64);
+        queue.enqueue(QueuedDownload {
+            id,
+            options: DownloadOptions {
+                url: url.clone(),
+                output_dir: file_args.output.clone().unwrap_or_default().into(),
+                filename: file_args.name.clone(),
+                segments: file_args.segments,
+                priority: Priority::Normal,
+                bandwidth_limit: None,
+                headers: Vec::new(),
+                checksum: None,
+                group: file_args.group.clone(),
+                mirrors: Vec::new(),
+                tags: Vec::new(),
+                note: None,
+                proxy: file_args.proxy.clone(),
+                auth: file_args.auth.clone(),
+            },
+            priority: Priority::Normal,
+        });
+        pending.insert(id, (url, file_args, entry));
     }
 
-    let info = downloader.probe(&url).await?;
+    let display = (!args.quiet).then(|| tokio::spawn(progress.clone().run_display()));
+    let mut running = tokio::task::JoinSet::new();
+    let mut interrupted = false;
+
+    loop {
+        while !interrupted && let Some(next) = queue.dequeue() {
+            let Some((url, file_args, entry)) = pending.remove(&next.id) else {
+                queue.complete(next.id);
+                continue;
+            };
+            running.spawn(async move {
+                let result = download_async(url.clone(), file_args).await;
+                (next.id, url, entry, result)
+            });
+        }
 
-    let total_size = info.size.unwrap_or(0);
-    let num_segments = calculate_segments(&info, &args);
+        let Some(joined) = running.join_next().await else {
+            break;
+        };
+        let (id, url, entry, result) = joined?;
+        queue.complete(id);
+        match result {
+            Err(e) if is_interrupted(&e) => {
+                interrupted = true;
+                record(&entry, Status::Interrupted, None);
+            }
+            Err(e) => {
+                record(&entry, Status::Failed, Some(format!("{:#}", e)));
+                failures.push((url.into(), e));
+            }
+            Ok(()) => record(&entry, Status::Ok, None),
+        }
+    }
+
+    for (_, (_, file_args, entry)) in pending {
+        record(&entry, Status::Interrupted, None);
+        if let Some(batch) = file_args.batch {
+            batch.finish(false);
+        }
+    }
+    if let Some(display) = display {
+        display.await?;
+    }
 
-    let filename = args
-        .name
-        .or(info.filename.clone())
-        .unwrap_or_else(|| "download".to_string());
+    let retry_hint = || {
+        if let Some(journal) = &journal {
+            eprintln!(
+                "Session journal: {}; `storm retry-failed {}` reruns what didn't finish",
+                journal.path().display(),
+                journal.path().display()
+            );
+        }
+    };
+    if interrupted {
+        if !args.quiet {
+            eprintln!("Interrupted; progress saved. Resume with `storm resume`");
+            retry_hint();
+        }
+        return Err(stormdl_core::StormError::Cancelled.into());
+    }
+    for (url, e) in &failures {
+        eprintln!("Failed: {}: {:#}", url, e);
+    }
+    if !failures.is_empty() {
+        retry_hint();
+        anyhow::bail!("{} of {} downloads failed", failures.len(), progress.len());
+    }
+    Ok(())
+}
 
-    let output_dir = args
-        .output
-        .map(PathBuf::from)
-        .unwrap_or_else(|| dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")));
+// --at: holds off until a wall-clock time, checking the clock each minute so
+// a suspended laptop still starts on time after waking.
+pub fn wait_until(at: chrono::DateTime<chrono::Local>, quiet: bool) {
+    if !quiet && let Ok(left) = (at - chrono::Local::now()).to_std() {
+        eprintln!(
+            "Waiting until {} (in {})",
+            at.format("%Y-%m-%d %H:%M:%S"),
+            stormdl_core::format_duration(Duration::from_secs(left.as_secs()))
+        );
+    }
+    while let Ok(left) = (at - chrono::Local::now()).to_std() {
+        std::thread::sleep(left.min(Duration::from_secs(60)));
+    }
+}
 
-    let output_path = output_dir.join(&filename);
+pub fn is_interrupted(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<stormdl_core::StormError>(),
+        Some(stormdl_core::StormError::Cancelled)
+    )
+}
 
-    if !args.quiet {
-        eprintln!("Filename: {}", filename);
-        eprintln!("Size: {}", format_bytes(total_size));
-        if let Some(rtt) = info.connection_rtt {
-            eprintln!("RTT: {:.1}ms", rtt.as_secs_f64() * 1000.0);
+pub fn resume(filter: Option<&str>, quiet: bool) -> Result<()> {
+    let manifest = Config::open_manifest().context("No download manifest found")?;
+    let incomplete = manifest.get_incomplete_downloads()?;
+    let id = filter
+        .and_then(|f| f.parse::<i64>().ok())
+        .filter(|id| incomplete.iter().any(|d| d.id == *id));
+    let entries: Vec<_> = incomplete
+        .into_iter()
+        .filter(|d| match id {
+            Some(id) => d.id == id,
+            None => filter.is_none_or(|f| {
+                d.url.contains(f) || d.filename.contains(f) || d.tags.iter().any(|t| t == f)
+            }),
+        })
+        .collect();
+    drop(manifest);
+
+    if entries.is_empty() {
+        eprintln!("Nothing to resume");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for entry in &entries {
+        let mut args = DownloadArgs::new(Config::load());
+        args.output = entry
+            .output_path
+            .parent()
+            .map(|dir| dir.to_string_lossy().into_owned());
+        args.name = Some(entry.filename.clone());
+        args.headers = entry
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        args.quiet = quiet;
+
+        if let Err(e) = download(&entry.url, args) {
+            if is_interrupted(&e) {
+                return Err(e);
+            }
+            eprintln!("Failed to resume {}: {:#}", entry.url, e);
+            failed += 1;
         }
-        let mode_str = if args.segments.is_some() {
-            " (manual)"
-        } else if info.connection_rtt.is_some() {
-            " (BDP-optimized)"
-        } else if args.turbo {
-            ""
-        } else {
-            " (gentle)"
-        };
-        eprintln!("Segments: {}{}", num_segments, mode_str);
-        eprintln!("Output: {}", output_path.display());
-        eprintln!();
     }
 
-    if !info.supports_range || total_size == 0 {
-        download_single(&downloader, &url, &output_path, total_size, args.quiet).await?;
-    } else {
-        download_segmented_adaptive(
-            &url,
-            &output_path,
-            total_size,
-            num_segments,
-            args.quiet,
-            args.turbo,
-        )
-        .await?;
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} downloads could not be resumed",
+            failed,
+            entries.len()
+        );
     }
+    Ok(())
+}
+
+async fn download_async(url: Url, args: DownloadArgs) -> Result<()> {
+    let batch = args.batch.clone();
+    let downloaded = batch.as_ref().map(|b| b.downloaded()).unwrap_or_default();
 
+    let quota = args.config.quota.quota();
+    let month_used = Config::open_manifest()
+        .and_then(|m| m.month_usage().ok())
+        .unwrap_or(0);
     if !args.quiet {
-        eprintln!("Download complete: {}", output_path.display());
+        warn_quota(&quota, month_used);
     }
 
-    if let Some(expected_hash) = args.checksum {
-        if !args.quiet {
-            eprintln!("Verifying checksum...");
+    let result = match download_file(
+        url.clone(),
+        args.clone(),
+        downloaded.clone(),
+        month_used,
+        true,
+    )
+    .await
+    {
+        Err(e) if e.is::<StaleProbe>() => {
+            tracing::debug!("{} changed since it was last probed; probing again", url);
+            download_file(url, args, downloaded.clone(), month_used, false).await
         }
-        let data = tokio::fs::read(&output_path).await?;
-        let mut hasher = stormdl_integrity::IncrementalHasher::new();
-        hasher.update(&data);
-        let actual_hash = hasher.finalize();
+        result => result,
+    };
+
+    let bytes = downloaded.load(Ordering::Relaxed);
+    if bytes > 0
+        && let Some(manifest) = Config::open_manifest()
+        && let Err(e) = manifest.add_usage(bytes)
+    {
+        tracing::warn!("Failed to record data usage: {}", e);
+    }
+
+    if let Some(batch) = batch {
+        batch.finish(result.is_ok());
+    }
+    result
+}
+
+fn warn_quota(quota: &MonthlyQuota, used: u64) {
+    let Some(limit) = quota.limit else {
+        return;
+    };
+    match quota.level(used) {
+        QuotaLevel::Ok => {}
+        QuotaLevel::Warning => eprintln!(
+            "Warning: {} of {} monthly data quota used",
+            format_bytes(used),
+            format_bytes(limit)
+        ),
+        QuotaLevel::Exceeded => eprintln!(
+            "Warning: monthly data quota of {} exceeded ({} used)",
+            format_bytes(limit),
+            format_bytes(used)
+        ),
+    }
+}
+
+// The file a remembered probe described has changed.
+#[derive(Debug)]
+struct StaleProbe;
+
+impl std::fmt::Display for StaleProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("file changed since it was last probed")
+    }
+}
+
+impl std::error::Error for StaleProbe {}
+
+// Only probes that can stand in for another are kept: the file is small
+// enough to come in one request and has a validator for its If-Range.
+fn remember_probe(url: &Url, info: &ResourceInfo) {
+    let strong_etag = info
+        .etag
+        .as_ref()
+        .is_some_and(|etag| !etag.starts_with("W/"));
+    if info.url != *url
+        || !info.supports_range
+        || info.size.is_none()
+        || !(strong_etag || info.last_modified.is_some())
+    {
+        return;
+    }
+    if let Some(manifest) = Config::open_manifest()
+        && let Err(e) = manifest.record_probe(url.as_str(), info)
+    {
+        tracing::warn!("Failed to record probe of {}: {}", url, e);
+    }
+}
 
-        if actual_hash != expected_hash {
+// Probes again while the server answers 202 Accepted, as APIs do while an
+// export is still being generated, for up to the policy's ready_wait.
+async fn until_ready<T, F>(
+    url: &Url,
+    policy: &RetryPolicy,
+    quiet: bool,
+    mut probe: impl FnMut() -> F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T, StormError>>,
+{
+    let started = Instant::now();
+    loop {
+        let retry_after = match probe().await {
+            Err(StormError::NotReady { retry_after }) => retry_after,
+            result => return Ok(result?),
+        };
+        let Some(delay) = policy.ready_delay(retry_after, started.elapsed()) else {
+            if policy.ready_wait.is_zero() {
+                anyhow::bail!(
+                    "The server is still preparing {}; wait for it with --ready-wait",
+                    url
+                );
+            }
             anyhow::bail!(
-                "Checksum mismatch: expected {}, got {}",
-                expected_hash,
-                actual_hash
+                "{} still isn't ready after {}; wait longer with --ready-wait",
+                url,
+                stormdl_core::format_duration(policy.ready_wait)
             );
+        };
+        if !quiet {
+            eprintln!(
+                "Waiting: the server is still preparing {}; checking again in {}",
+                url,
+                stormdl_core::format_duration(delay)
+            );
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// --data's body: the string itself, @FILE or @- for stdin.
+fn request_body(spec: &str) -> Result<Bytes> {
+    let data = match spec.strip_prefix('@') {
+        Some("-") => {
+            let mut data = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut data)
+                .context("Failed to read --data from stdin")?;
+            data
         }
+        Some(path) => {
+            std::fs::read(path).with_context(|| format!("Failed to read --data file {}", path))?
+        }
+        None => spec.as_bytes().to_vec(),
+    };
+    Ok(Bytes::from(data))
+}
 
-        if !args.quiet {
-            eprintln!("Checksum verified: {}", actual_hash);
+fn recent_probe(url: &Url, pool: &BatchPool) -> Option<ResourceInfo> {
+    let ttl = pool.probe_ttl?;
+    let info = Config::open_manifest()?
+        .recent_probe(url.as_str(), ttl)
+        .ok()??;
+    info.size
+        .is_some_and(|size| size > 0 && size <= pool.small_file_threshold)
+        .then_some(info)
+}
+
+// A host that has answered over HTTP/3 more often than it fell back from
+// it races HTTP/3 against HTTP/2 from the first connection, rather than
+// waiting for an Alt-Svc header to move there.
+fn spoke_http3(url: &Url) -> bool {
+    if !cfg!(feature = "http3") || url.scheme() != "https" {
+        return false;
+    }
+    let (Some(host), Some(manifest)) = (url.host_str(), Config::open_manifest()) else {
+        return false;
+    };
+    manifest
+        .protocol_stats()
+        .unwrap_or_default()
+        .iter()
+        .any(|stats| {
+            stats.host == host
+                && stats.version == HttpVersion::Http3
+                && stats.successes > stats.downgrades
+        })
+}
+
+fn record_protocols(url: &Url, negotiated: &Negotiated, quiet: bool) {
+    let host = url.host_str().unwrap_or_default();
+    if !quiet {
+        for downgrade in &negotiated.downgrades {
+            eprintln!(
+                "{} failed for {}, falling back to {} ({}): {}",
+                downgrade.from.as_str(),
+                host,
+                downgrade.to.as_str(),
+                Downgrade::hint(downgrade.from),
+                downgrade.reason
+            );
         }
     }
 
-    Ok(())
+    let Some(manifest) = Config::open_manifest() else {
+        return;
+    };
+    for version in &negotiated.working {
+        if let Err(e) = manifest.record_protocol_success(host, *version) {
+            tracing::warn!("Failed to record protocol for {}: {}", host, e);
+        }
+    }
+    for downgrade in &negotiated.downgrades {
+        if let Err(e) = manifest.record_downgrade(host, downgrade.from, &downgrade.reason) {
+            tracing::warn!("Failed to record protocol downgrade for {}: {}", host, e);
+        }
+    }
 }
 
-async fn download_single(
-    downloader: &HttpDownloader,
-    url: &Url,
-    output_path: &PathBuf,
-    total_size: u64,
-    quiet: bool,
+// HLS and DASH segments fetched at once unless --segments says otherwise.
+const STREAM_WORKERS: usize = 8;
+
+async fn download_file(
+    url: Url,
+    args: DownloadArgs,
+    downloaded: Arc<AtomicU64>,
+    month_used: u64,
+    reuse_probe: bool,
 ) -> Result<()> {
-    let downloaded = Arc::new(AtomicU64::new(0));
-    let done = Arc::new(AtomicBool::new(false));
+    let started = Instant::now();
+    let quiet = args.quiet || args.batch.is_some();
+    let quota = args.config.quota.quota();
+    if quota.blocks(month_used, 0) {
+        anyhow::bail!(
+            "Monthly data quota exhausted; raise quota.monthly_limit or disable hard_stop"
+        );
+    }
 
-    let progress_downloaded = downloaded.clone();
-    let progress_done = done.clone();
+    let group = match &args.group {
+        Some(name) => Some(args.config.group(name).with_context(|| {
+            let known: Vec<_> = args.config.groups().into_iter().map(|g| g.name).collect();
+            format!(
+                "Unknown download group '{}' (configured: {})",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+        })?),
+        None => None,
+    };
 
-    let progress_handle = if !quiet && total_size > 0 {
-        Some(tokio::spawn(async move {
-            let mut progress =
-                Progress::new(total_size, progress_downloaded, progress_done.clone());
-            while !progress_done.load(Ordering::Relaxed) {
-                progress.display();
-                tokio::time::sleep(Duration::from_millis(100)).await;
-            }
-            progress.finish();
-        }))
+    let limit = match &args.limit {
+        Some(limit) => Some(crate::config::parse_rate(limit).with_context(|| {
+            format!(
+                "Invalid bandwidth limit '{}' (e.g. 10MB/s or 80Mbps)",
+                limit
+            )
+        })?),
+        None => group.as_ref().and_then(|g| g.bandwidth_limit),
+    };
+    let limiter = Arc::new(RateLimiter::new(args.config.restrictions.cap(limit)));
+
+    let max_size = match &args.max_size {
+        Some(size) => Some(
+            crate::config::parse_size(size)
+                .with_context(|| format!("Invalid --max-size '{}'", size))?,
+        ),
+        None => None,
+    };
+    let content_policy = ContentPolicy::new(max_size, args.accept_types.clone());
+
+    let hooks = match args
+        .hooks
+        .as_deref()
+        .map(PathBuf::from)
+        .or_else(|| args.config.hooks.script_path())
+    {
+        Some(path) => Hooks::load(&path)?,
+        None => Hooks::none(),
+    };
+    let url = hooks.rewrite_url(url)?;
+
+    // A torrent's own URL is a magnet link or a local file; only its
+    // --mirror web seeds go over HTTP.
+    let torrent = crate::torrent::is_torrent(&url);
+    let mut sources = if torrent {
+        Vec::new()
+    } else {
+        vec![url.clone()]
+    };
+    if let Some(metalink) = &args.metalink {
+        sources.extend(
+            metalink.sources[1..]
+                .iter()
+                .map(|source| source.url.clone()),
+        );
+    }
+    for mirror in &args.mirrors {
+        sources.push(Url::parse(mirror).with_context(|| format!("Invalid mirror '{}'", mirror))?);
+    }
+    // s3:// sources are fetched from their HTTPS endpoint with signed
+    // requests; the manifest keeps the s3:// URL so resuming signs again.
+    let s3 = sources
+        .iter()
+        .any(|source| source.scheme() == "s3")
+        .then(S3Config::from_env)
+        .transpose()?;
+    if let Some(s3) = &s3 {
+        for source in &mut sources {
+            *source = s3.resolve(source)?;
+        }
+    }
+    // oci:// blobs come from their registry's blob endpoint and are checked
+    // against their digest.
+    let mut blobs = Vec::new();
+    for source in &mut sources {
+        if OciBlob::is_oci(source) {
+            let blob = OciBlob::parse(source)?;
+            *source = blob.url();
+            blobs.push(blob);
+        }
+    }
+    let checksum = match args.checksum.as_deref() {
+        Some(spec) => Some(ChecksumSpec::parse(spec)?),
+        None => match blobs.first() {
+            Some(blob) => Some(ChecksumSpec::parse(&blob.digest())?),
+            None => args
+                .metalink
+                .as_ref()
+                .and_then(|metalink| metalink.checksum().cloned()),
+        },
+    };
+    check_schedule(&args.config.restrictions)?;
+    let scanner = args.config.scan.scanner()?;
+    let signing_key = args
+        .signify_key
+        .as_deref()
+        .map(PublicKey::load)
+        .transpose()
+        .context("Failed to load --signify-key")?
+        .map(Arc::new);
+    let mut interfaces = Vec::new();
+    for name in &args.interfaces {
+        let bind = name
+            .parse::<LocalBind>()
+            .and_then(|bind| bind.check().map(|_| bind))
+            .with_context(|| format!("Invalid interface '{}'", name))?;
+        interfaces.push(Route::bound(bind));
+    }
+
+    let mut options = args
+        .config
+        .client_options(args.turbo, args.proxy.as_deref())?;
+    for spec in &args.mirror_proxies {
+        let (host, target) = spec
+            .split_once('=')
+            .with_context(|| format!("Invalid --mirror-proxy '{}' (expected HOST=PROXY)", spec))?;
+        let host = Url::parse(host)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| host.to_string());
+        let proxy = options.proxy.take().unwrap_or_else(ProxyConfig::direct);
+        options.proxy = Some(proxy.with_host(&host, target)?);
+    }
+    let headers = args
+        .headers
+        .iter()
+        .map(|line| parse_header(line))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid --header")?;
+    // A browser session recorded with --har replays what it sent for the
+    // download.
+    let har = args
+        .har
+        .as_deref()
+        .map(|path| Har::open(path).with_context(|| format!("Failed to read {}", path.display())))
+        .transpose()?;
+    let captured = match &har {
+        Some(har) => har.request(&url).context("Invalid request in --har")?,
+        None => None,
+    };
+    if let (Some(path), None) = (&args.har, &captured)
+        && !quiet
+    {
+        eprintln!(
+            "No request to {} in {}; using its cookies only",
+            url.host_str().unwrap_or_default(),
+            path.display()
+        );
+    }
+    // Explicit headers override the same names set by the hook script or
+    // the HAR.
+    options.headers = hooks.request_headers(&url)?;
+    if let Some(captured) = &captured {
+        options.headers.extend(captured.headers.iter().cloned());
+    }
+    options.headers.extend(headers.iter().cloned());
+    if args.cookie_file.is_some() || !args.cookies.is_empty() || har.is_some() {
+        // With [cookies] enabled these join the persistent jar and are kept
+        // for later runs.
+        let jar = options
+            .cookies
+            .get_or_insert_with(|| Arc::new(CookieJar::new()))
+            .clone();
+        if let Some(har) = &har {
+            har.import_cookies(&jar);
+        }
+        if let Some(path) = &args.cookie_file {
+            jar.import(path)
+                .with_context(|| format!("Failed to import cookies from {}", path))?;
+        }
+        for pairs in &args.cookies {
+            for source in &sources {
+                jar.insert(source, pairs).context("Invalid --cookie")?;
+            }
+        }
+    }
+    options.s3 = s3.as_ref().and_then(S3Config::signer);
+    options.location_trusted = args.location_trusted;
+    // --data alone is a POST, as in curl.
+    options.request = match (&args.method, &args.data) {
+        (None, None) => captured.and_then(|captured| captured.request),
+        (Some(method), None) if method.eq_ignore_ascii_case("GET") => None,
+        (method, data) => {
+            let body = data.as_deref().map(request_body).transpose()?;
+            Some(CustomRequest::new(
+                method.as_deref().unwrap_or("POST"),
+                body,
+            )?)
+        }
+    };
+    let custom_request = options.request.is_some();
+    if let Some(key) = &args.ssh_key {
+        anyhow::ensure!(key.is_file(), "--ssh-key {} is not a file", key.display());
+        options.ssh_key = Some(key.clone());
+    }
+    // --user/--bearer-token win over [credentials] for the host.
+    options.auth = args
+        .config
+        .credentials
+        .host_auth(args.auth.as_ref(), &sources)?;
+    // Registries hand out pull tokens; each one is scoped to its registry,
+    // so a redirect to blob storage leaves it behind.
+    if !blobs.is_empty() {
+        let registry = HttpDownloader::with_options(&options)?;
+        let mut tokens = Vec::new();
+        for blob in &blobs {
+            if let Some(token) = blob
+                .token(&registry, options.auth.as_ref())
+                .await
+                .with_context(|| format!("Failed to get a pull token for {}", blob.url()))?
+            {
+                tokens.push(HostAuth::new(&blob.url(), &Credentials::Bearer(token))?);
+            }
+        }
+        tokens.extend(options.auth.take());
+        options.auth = tokens.into_iter().reduce(HostAuth::or);
+    }
+    options.protocol = args.protocol;
+    if let Some(policy) = &options.host_policy {
+        for source in &sources {
+            policy.check(source)?;
+        }
+    }
+    let retry_policy = args
+        .config
+        .retry
+        .policy(
+            args.retries,
+            args.segment_retries,
+            args.escalation.as_deref(),
+            args.ready_wait,
+        )?
+        .with_delay(args.retry_delay);
+    let output_dir = args
+        .output
+        .clone()
+        .map(PathBuf::from)
+        .or_else(|| group.as_ref().and_then(|g| g.output_dir.clone()))
+        .unwrap_or_else(|| dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")));
+    let deadline = Deadline::new(
+        window_left(&args.config.restrictions),
+        args.max_time.map(|limit| (started, limit)),
+    );
+    let notifier = args.config.notify.notifier(
+        args.notify.as_deref(),
+        args.notify_webhook.as_deref(),
+        args.no_notify,
+    )?;
+    if torrent {
+        return crate::torrent::download(
+            url, sources, &options, &args, output_dir, limiter, downloaded, deadline, notifier,
+            month_used, started,
+        )
+        .await;
+    }
+
+    let proxy = options.proxy.clone();
+    let single_stream = args.single_stream || proxy.as_ref().is_some_and(|p| p.single_stream);
+
+    // A metalink's sources are probed in turn until one answers, and that
+    // one leads.
+    let candidates = args.metalink.as_ref().map_or(1, |m| m.sources.len());
+    // The first file from an origin builds its client while the others
+    // wait, then they all share it.
+    let mut slot = match args.pool.as_ref().filter(|_| candidates == 1) {
+        Some(pool) => Some(pool.client(&sources[0], &options.headers).await),
+        None => None,
+    };
+    let pooled = slot.as_ref().and_then(|slot| (**slot).clone());
+    if pooled.is_some() {
+        slot = None;
+    }
+    // A small file whose URL was probed recently skips the probe once its
+    // origin's client is up. Its one GET carries If-Range, so a file that
+    // changed since fails with StaleProbe and is probed afresh.
+    let known = match (&pooled, &args.pool) {
+        (Some(_), Some(pool)) if reuse_probe => recent_probe(&sources[0], pool),
+        _ => None,
+    };
+    let skipped = known.is_some();
+    let (mut downloader, mut info) = match pooled {
+        Some(downloader) => {
+            let info = match known {
+                Some(info) => info,
+                None => {
+                    if !quiet {
+                        eprintln!("Probing {}...", sources[0]);
+                    }
+                    until_ready(&sources[0], &retry_policy, quiet, || {
+                        downloader.probe(&sources[0])
+                    })
+                    .await?
+                }
+            };
+            (downloader, info)
+        }
+        None => {
+            let mut answered = 0;
+            let negotiated = loop {
+                if !quiet {
+                    eprintln!("Probing {}...", sources[answered]);
+                }
+                let source = &sources[answered];
+                let raced;
+                let options = if options.protocol == PreferredProtocol::Auto && spoke_http3(source)
+                {
+                    raced = ClientOptions {
+                        protocol: PreferredProtocol::Http3,
+                        ..options.clone()
+                    };
+                    &raced
+                } else {
+                    &options
+                };
+                match until_ready(source, &retry_policy, quiet, || {
+                    probe_with_fallback(options, source)
+                })
+                .await
+                {
+                    Ok(negotiated) => break negotiated,
+                    Err(e) if answered + 1 < candidates => {
+                        if !quiet {
+                            eprintln!("{}; trying the next mirror", e);
+                        }
+                        answered += 1;
+                    }
+                    Err(e) => return Err(e),
+                }
+            };
+            sources[..=answered].rotate_right(1);
+            record_protocols(&sources[0], &negotiated, quiet);
+            let downloader = Arc::new(negotiated.downloader);
+            if let Some(mut slot) = slot.take() {
+                *slot = Some(downloader.clone());
+            }
+            (downloader, negotiated.info)
+        }
+    };
+    let target = sources[0].clone();
+    if args.pool.is_some() && !skipped {
+        remember_probe(&target, &info);
+    }
+    if info.url != target {
+        if !quiet {
+            eprintln!("Following landing page to {}", info.url);
+        }
+        sources[0] = info.url.clone();
+    }
+    // A file tracked by Git LFS comes out of the repository's raw link as a
+    // small pointer; the object it names is downloaded from the LFS server
+    // instead, and checked against the pointer's size and sha256.
+    let mut lfs = None;
+    if info.size.is_some_and(|size| size <= LfsPointer::MAX_SIZE)
+        && let Some(endpoint) = LfsPointer::endpoint(&target)
+        && let Some(pointer) = LfsPointer::fetch(&downloader, &target).await?
+    {
+        if !quiet {
+            eprintln!("{} is a Git LFS pointer; resolving the object...", target);
+        }
+        let object = pointer
+            .resolve(&downloader, &endpoint)
+            .await
+            .with_context(|| format!("Failed to resolve the Git LFS object behind {}", target))?;
+        if let Some(auth) = object.auth {
+            options.auth = Some(match options.auth.take() {
+                Some(configured) => auth.or(configured),
+                None => auth,
+            });
+        }
+        if !quiet {
+            eprintln!("Probing {}...", object.url);
+        }
+        let negotiated = probe_with_fallback(&options, &object.url).await?;
+        if let Some(size) = negotiated.info.size
+            && size != pointer.size
+        {
+            anyhow::bail!(
+                "The Git LFS object is {} bytes but its pointer says {}",
+                size,
+                pointer.size
+            );
+        }
+        // The object's link is named after its hash, not the file.
+        let filename = info.filename.take();
+        downloader = Arc::new(negotiated.downloader);
+        info = negotiated.info;
+        info.filename = filename;
+        sources = vec![info.url.clone()];
+        lfs = Some(pointer);
+    }
+    let checksum = match (checksum, &lfs) {
+        (None, Some(pointer)) => Some(ChecksumSpec::parse(&format!("sha256:{}", pointer.oid))?),
+        (checksum, _) => checksum,
+    };
+    content_policy.check(&info)?;
+    if !hooks.should_download(&url, &info)? {
+        anyhow::bail!("Download of {} vetoed by hook script", url);
+    }
+    let stream = if args.no_stream {
+        None
+    } else {
+        Stream::open(&downloader, &info, args.quality, quiet).await?
+    };
+    let streamed = stream.is_some();
+
+    if let (Some(expected), Some(size)) = (args.metalink.as_ref().and_then(|m| m.size), info.size)
+        && expected != size
+    {
+        anyhow::bail!(
+            "{} is {} bytes but the metalink lists {}",
+            target,
+            size,
+            expected
+        );
+    }
+
+    // A stream's size is only known once it has been written.
+    let mut total_size = if streamed { 0 } else { info.size.unwrap_or(0) };
+    if quota.blocks(month_used, total_size) {
+        anyhow::bail!(
+            "Download of {} would exceed the monthly data quota ({} remaining)",
+            format_bytes(total_size),
+            format_bytes(quota.remaining(month_used).unwrap_or(0))
+        );
+    }
+
+    // Small files in a batch skip segmentation: one GET on a reused
+    // connection. Larger ones wait for one of the batch's --concurrent slots.
+    let small = args
+        .pool
+        .as_ref()
+        .is_some_and(|pool| !streamed && total_size > 0 && total_size <= pool.small_file_threshold);
+    let _slot = match &args.pool {
+        Some(pool) if !small => Some(pool.large.clone().acquire_owned().await?),
+        _ => None,
+    };
+    let num_segments = if single_stream || small {
+        1
+    } else {
+        calculate_segments(&info, &args)
+    };
+    if let Some(batch) = &args.batch {
+        batch.start(total_size);
+    }
+
+    let filename = match args.name {
+        Some(name) => name,
+        None => {
+            let name = blobs
+                .first()
+                .map(OciBlob::file_name)
+                .or_else(|| info.filename.clone())
+                .unwrap_or_else(|| "download".to_string());
+            let name = match &stream {
+                Some(stream) => stream.file_name(&info.url, &name),
+                None => name,
+            };
+            hooks.choose_filename(&url, name)?
+        }
+    };
+    let content_type = match &stream {
+        Some(stream) => Some(stream.mime()),
+        None => info.content_type.as_deref(),
+    };
+
+    let output_path = output_dir.join(&filename);
+    // Metalink names can include directories.
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let pacer = args
+        .config
+        .progress
+        .pacer(args.progress_interval, args.low_power);
+    stormdl_core::check_error_page(&filename, info.content_type.as_deref())?;
+
+    let checksum = match (checksum, &args.checksum_url) {
+        (None, Some(source)) => {
+            let remote_name = info.url.path_segments().and_then(|mut s| s.next_back());
+            let mut names = vec![filename.as_str()];
+            names.extend(info.filename.as_deref());
+            names.extend(remote_name.filter(|name| !name.is_empty()));
+            names.dedup();
+            companion_checksum(&downloader, &info.url, source, &names, quiet).await?
+        }
+        (checksum, _) => checksum,
+    };
+    let signature = match &signing_key {
+        Some(key) => {
+            let source = args.signature_url.as_deref().unwrap_or("auto");
+            Some(companion_signature(&downloader, &info.url, source, key, quiet).await?)
+        }
+        None => None,
+    };
+
+    let routes = if !info.supports_range
+        || total_size == 0
+        || single_stream
+        || small
+        || !dedicated_workers(&downloader)
+    {
+        if !interfaces.is_empty() && !quiet {
+            eprintln!(
+                "Warning: this download can't be split across interfaces; using the default route"
+            );
+        }
+        None
+    } else if !interfaces.is_empty() {
+        Some(interfaces)
+    } else if args.dual_stack || args.config.segments.dual_stack {
+        DualStack::resolve(&info.url)
+            .await
+            .map(|stack| stack.routes())
     } else {
         None
     };
-
-    let mut sink = ProgressFileSink::new(output_path, downloaded.clone())?;
-    downloader.fetch_full(url, &mut sink).await?;
-    sink.flush()?;
+
+    if !quiet {
+        eprintln!("Filename: {}", filename);
+        match &stream {
+            Some(stream) => eprintln!("Stream: {}", stream.describe()),
+            None => eprintln!("Size: {}", format_bytes(total_size)),
+        }
+        if let Some(rtt) = info.connection_rtt {
+            eprintln!("RTT: {:.1}ms", rtt.as_secs_f64() * 1000.0);
+        }
+        let mode_str = if single_stream {
+            " (single stream)"
+        } else if small {
+            " (small file)"
+        } else if args.segments.is_some() {
+            " (manual)"
+        } else if args.config.segments.calibrated_segments.is_some() {
+            " (calibrated)"
+        } else if info.connection_rtt.is_some() {
+            " (BDP-optimized)"
+        } else if args.turbo {
+            ""
+        } else {
+            " (gentle)"
+        };
+        if !streamed {
+            eprintln!("Segments: {}{}", num_segments, mode_str);
+        }
+        if let Some(routes) = &routes {
+            let labels: Vec<&str> = routes.iter().map(Route::label).collect();
+            eprintln!("Paths: {}", labels.join(" + "));
+        }
+        if let Some(group) = &group {
+            eprintln!("Group: {}", group.name);
+        }
+        if let Some(proxy) = proxy.as_ref().filter(|p| !p.is_direct()) {
+            let isolation = if proxy.isolate && proxy.is_socks() {
+                " (isolated circuit)"
+            } else {
+                ""
+            };
+            let route = |url: Option<&Url>| match url {
+                Some(url) => format!("{}://{}", url.scheme(), url.host_str().unwrap_or("")),
+                None => "direct".to_string(),
+            };
+            eprintln!("Proxy: {}{}", route(proxy.url.as_ref()), isolation);
+            for (scheme, target) in &proxy.schemes {
+                eprintln!("Proxy for {}: {}", scheme, route(Some(target)));
+            }
+            for (host, target) in &proxy.hosts {
+                eprintln!("Proxy for {}: {}", host, route(target.as_ref()));
+            }
+        }
+        if let Some(limit) = limiter.limit() {
+            eprintln!(
+                "Limit: {}",
+                format_speed(limit as f64, args.config.progress.speed_units)
+            );
+        }
+        eprintln!("Output: {}", output_path.display());
+        eprintln!();
+    }
+
+    let direct_buffer = (args.direct_io || args.config.io.use_direct_io(total_size))
+        .then(|| args.config.io.buffer_size());
+
+    let max_bytes = match (total_size, max_size) {
+        (0, limit) => limit,
+        (size, limit) => Some(limit.map_or(size, |l| l.min(size))),
+    };
+    let validator = info
+        .etag
+        .clone()
+        .filter(|etag| !etag.starts_with("W/"))
+        .or(info.last_modified.clone());
+    // A cached copy of this version, or of the published SHA-256, stands in
+    // for the transfer; the checksum, signature and scan still run on it.
+    let cached = args.cache.as_ref().filter(|_| !streamed).and_then(|cache| {
+        if let Some(object) = cache.lookup(&url, validator.as_deref(), total_size) {
+            return Some((cache, object, true));
+        }
+        let sha256 = checksum
+            .as_ref()
+            .filter(|spec| spec.algorithms().contains(&HashAlgorithm::Sha256))?;
+        let object = tokio::task::block_in_place(|| cache.find(sha256.digest()))?;
+        Some((cache, object, false))
+    });
+    let partial = if args.continue_partial && cached.is_none() && !streamed {
+        find_partial(&output_path)
+    } else {
+        None
+    };
+    // A {sha256} in --print-after is hashed as the file arrives, like a
+    // checksum, rather than by reading the file again.
+    let wants_sha256 = args
+        .print_after
+        .as_ref()
+        .is_some_and(|template| template.uses(Field::Sha256));
+    let verifier = {
+        let path = partial
+            .as_ref()
+            .map_or(output_path.as_path(), |(path, _)| path.as_path());
+        match &checksum {
+            Some(spec) => Some(StreamVerifier::new(path, spec)),
+            None if wants_sha256 => Some(StreamVerifier::digest_only(path, HashAlgorithm::Sha256)),
+            None => None,
+        }
+        .map(Arc::new)
+    };
+    let extraction = args
+        .extract
+        .clone()
+        .map(|dest| Extraction::start(&output_path, dest, verifier.as_deref()));
+
+    let mut retry = RetryState::new(retry_policy, sources);
+    if args.metalink.is_some() {
+        retry = retry.with_mirrors(total_size);
+    }
+    let race = retry.sources.len() > 1
+        && info.supports_range
+        && total_size >= RACE_MIN_SIZE
+        && cached.is_none()
+        && partial.is_none()
+        && !streamed
+        && !single_stream
+        && !small;
+    let mut head = None;
+    if race {
+        if !quiet {
+            eprintln!(
+                "Racing {} mirrors for the first {}...",
+                retry.sources.len().min(RACE_MIRRORS),
+                format_bytes(RACE_BYTES)
+            );
+        }
+        match retry.race(&downloader, &limiter).await {
+            Some((fastest, start)) => {
+                if !quiet {
+                    eprintln!("Fastest mirror: {}", fastest);
+                }
+                head = Some(start);
+            }
+            None if !quiet => eprintln!("No mirror finished the race; keeping the given order"),
+            None => {}
+        }
+    }
+    let retry = Arc::new(retry);
+
+    // Milestones count what's on disk, bytes from an earlier run included.
+    let watch = |already: u64, downloaded: &Arc<AtomicU64>| {
+        let downloaded = downloaded.clone();
+        notifier.clone().filter(|_| total_size > 0).map(|notifier| {
+            notifier.watch(filename.clone(), url.clone(), total_size, move || {
+                already + downloaded.load(Ordering::Relaxed)
+            })
+        })
+    };
+    let mut record = None;
+    if let Some((cache, object, _)) = &cached {
+        cache
+            .restore(object, &output_path)
+            .with_context(|| format!("Failed to restore {} from the cache", filename))?;
+        if !quiet {
+            eprintln!("Restored from cache: {}", output_path.display());
+        }
+    } else if let Some(stream) = stream {
+        total_size = crate::stream::download_stream(
+            stream,
+            downloader.clone(),
+            retry.clone(),
+            &output_path,
+            args.segments.unwrap_or(STREAM_WORKERS),
+            info.http_version,
+            args.turbo,
+            downloaded,
+            limiter,
+            verifier.clone(),
+            pacer,
+            args.progress,
+            args.config.progress.speed_units,
+            deadline,
+            quiet,
+        )
+        .await?;
+    } else if let Some((partial_path, offset)) = partial {
+        if total_size > 0 && offset == total_size && partial_path == output_path {
+            if !quiet {
+                eprintln!("Already complete: {}", output_path.display());
+            }
+            if let Some(template) = &args.print_after {
+                print_completion(
+                    template,
+                    &output_path,
+                    &url,
+                    None,
+                    info.content_type.as_deref(),
+                    started,
+                )?;
+            }
+            return Ok(());
+        }
+        if total_size > 0 && offset > total_size {
+            anyhow::bail!(
+                "{} is larger than the remote file ({} > {}); refusing to continue",
+                partial_path.display(),
+                format_bytes(offset),
+                format_bytes(total_size)
+            );
+        }
+        if !info.supports_range {
+            anyhow::bail!(
+                "Server does not support range requests; cannot continue {}",
+                partial_path.display()
+            );
+        }
+
+        if !quiet {
+            eprintln!(
+                "Continuing {} from {}",
+                partial_path.display(),
+                format_bytes(offset)
+            );
+            if validator.is_none() {
+                eprintln!(
+                    "Warning: server sent no ETag or Last-Modified; cannot verify the partial file"
+                );
+            }
+        }
+
+        let _watch = watch(offset, &downloaded);
+        download_single(
+            &downloader,
+            &retry,
+            &partial_path,
+            offset,
+            validator.as_deref(),
+            total_size,
+            max_bytes,
+            downloaded,
+            limiter,
+            verifier.clone(),
+            pacer,
+            args.progress,
+            args.config.progress.speed_units,
+            deadline,
+            quiet,
+            false,
+        )
+        .await
+        .map_err(|e| match e.downcast_ref::<stormdl_core::StormError>() {
+            Some(stormdl_core::StormError::ResourceChanged) => anyhow::anyhow!(
+                "Remote file changed since {} was written; rerun without --continue",
+                partial_path.display()
+            ),
+            _ => e,
+        })?;
+        if partial_path != output_path {
+            std::fs::rename(&partial_path, &output_path)?;
+        }
+    } else if !info.supports_range || total_size == 0 || single_stream || small {
+        let _watch = watch(0, &downloaded);
+        download_single(
+            &downloader,
+            &retry,
+            &output_path,
+            0,
+            validator.as_deref(),
+            total_size,
+            max_bytes,
+            downloaded,
+            limiter,
+            verifier.clone(),
+            pacer,
+            args.progress,
+            args.config.progress.speed_units,
+            deadline,
+            quiet,
+            skipped,
+        )
+        .await
+        .map_err(|e| match e.downcast_ref::<stormdl_core::StormError>() {
+            Some(stormdl_core::StormError::ResourceChanged) if skipped => StaleProbe.into(),
+            _ => e,
+        })?;
+    } else {
+        // `storm resume` would repeat a custom request as a bare GET.
+        let journal = if args.no_resume || validator.is_none() || custom_request {
+            None
+        } else {
+            ResumeJournal::open(
+                &url,
+                &output_path,
+                total_size,
+                info.etag.as_deref(),
+                info.last_modified.as_deref(),
+            )
+            .map(Arc::new)
+        };
+        if let Some(journal) = &journal {
+            journal.label(&args.tags, args.note.as_deref());
+            journal.set_headers(&headers);
+            record = Some(journal.id());
+        }
+        if !quiet && let Some(journal) = &journal {
+            let written = journal.written().covered();
+            if written > 0 {
+                eprintln!(
+                    "Resuming {} ({} already downloaded)",
+                    output_path.display(),
+                    format_bytes(written)
+                );
+            }
+        }
+
+        let already = journal.as_ref().map_or(0, |j| j.written().covered());
+        let _watch = watch(already, &downloaded);
+        download_segmented_adaptive(
+            downloader.clone(),
+            retry.clone(),
+            &output_path,
+            total_size,
+            num_segments,
+            args.config.split_hint(),
+            direct_buffer,
+            downloaded,
+            limiter,
+            verifier.clone(),
+            pacer,
+            args.progress,
+            args.config.progress.speed_units,
+            info.http_version,
+            quiet,
+            args.turbo,
+            args.turbo && args.config.segments.adaptive(),
+            routes,
+            journal,
+            head,
+            args.config.io.audit,
+            deadline,
+        )
+        .await?;
+    }
+
+    if cached.is_none()
+        && total_size > 0
+        && let Some(pieces) = args.metalink.as_ref().and_then(|m| m.pieces.as_ref())
+    {
+        repair_pieces(
+            &downloader,
+            &retry,
+            &output_path,
+            pieces,
+            total_size,
+            verifier.as_deref(),
+            quiet,
+        )
+        .await?;
+    }
+
+    if args.config.io.fsync {
+        std::fs::File::open(&output_path)?.sync_all()?;
+    }
+
+    if !quiet && cached.is_none() {
+        eprintln!("Download complete: {}", output_path.display());
+    }
+
+    let mut sha256 = None;
+    if let Some(verifier) = verifier {
+        if !quiet && checksum.is_some() {
+            eprintln!("Verifying checksum...");
+        }
+        tracing::debug!(
+            "{} of {} hashed while downloading",
+            format_bytes(verifier.hashed()),
+            format_bytes(total_size)
+        );
+        let (algorithm, actual_hash) =
+            tokio::task::block_in_place(|| verifier.finish()).map_err(|e| match e {
+                stormdl_core::StormError::HashMismatch { expected, actual } => {
+                    anyhow::anyhow!("Checksum mismatch: expected {}, got {}", expected, actual)
+                }
+                e => e.into(),
+            })?;
+
+        if !quiet && checksum.is_some() {
+            eprintln!("Checksum verified ({}): {}", algorithm, actual_hash);
+        }
+        if algorithm == HashAlgorithm::Sha256 {
+            sha256 = Some(actual_hash);
+        }
+    }
+
+    if let (Some(key), Some(signature)) = (signing_key, signature) {
+        if !quiet {
+            eprintln!("Verifying {} signature...", key.kind());
+        }
+        let check = stormdl_integrity::verify_signature_file(&output_path, signature, key)
+            .await
+            .map_err(|e| anyhow::anyhow!("{} ({})", e, output_path.display()))?;
+        if !quiet {
+            match check.comment {
+                Some(comment) => eprintln!("Signature verified ({}): {}", check.signer, comment),
+                None => eprintln!("Signature verified ({})", check.signer),
+            }
+        }
+    }
+
+    if let Some(scanner) = scanner {
+        if !quiet {
+            eprintln!("Scanning for malware...");
+        }
+        if let Err(e) = scanner.check(&output_path).await {
+            if matches!(e, StormError::Quarantined { .. })
+                && let Some(id) = record.or_else(|| {
+                    record_complete(
+                        &url,
+                        &output_path,
+                        total_size,
+                        &args.tags,
+                        args.note.as_deref(),
+                    )
+                })
+                && let Some(manifest) = Config::open_manifest()
+                && let Err(e) = manifest.update_download_state(id, DownloadState::Quarantined)
+            {
+                tracing::warn!("Failed to record quarantine: {}", e);
+            }
+            return Err(e.into());
+        }
+    }
+
+    if let Some(extraction) = extraction {
+        let dest = extraction.dest().to_path_buf();
+        let files = extraction.finish(&output_path).await?;
+        if !quiet {
+            eprintln!("Extracted {} files to {}", files, dest.display());
+        }
+    }
+
+    // Files found by URL are already indexed; ones found by checksum get
+    // an entry for this URL too.
+    if let Some(cache) = args.cache.as_ref().filter(|_| !streamed)
+        && !cached.as_ref().is_some_and(|(_, _, indexed)| *indexed)
+    {
+        let stored = tokio::task::block_in_place(|| {
+            cache.store(&output_path, &url, validator.as_deref(), sha256.as_deref())
+        });
+        match stored {
+            Ok(_) => {
+                if let Some(max_size) = args.config.cache.max_size() {
+                    tokio::task::block_in_place(|| cache.gc(Some(max_size), None));
+                }
+            }
+            Err(e) => tracing::warn!("Failed to add {} to the cache: {}", filename, e),
+        }
+    }
+
+    let listing = args
+        .list_contents
+        .then(|| list_contents(&output_path, quiet))
+        .flatten();
+    let labelled = !args.tags.is_empty() || args.note.is_some();
+    if record.is_none() && (labelled || listing.is_some()) {
+        record = record_complete(
+            &url,
+            &output_path,
+            total_size,
+            &args.tags,
+            args.note.as_deref(),
+        );
+    }
+    if let (Some(record), Some(entries)) = (record, &listing)
+        && let Some(manifest) = Config::open_manifest()
+        && let Err(e) = manifest.set_archive_entries(record, entries)
+    {
+        tracing::warn!("Failed to save archive listing: {}", e);
+    }
+
+    if let Some(template) = &args.print_after {
+        print_completion(template, &output_path, &url, sha256, content_type, started)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn print_completion(
+    template: &Template,
+    path: &Path,
+    url: &Url,
+    sha256: Option<String>,
+    mime: Option<&str>,
+    started: Instant,
+) -> Result<()> {
+    let sha256 = match sha256 {
+        Some(sha256) => Some(sha256),
+        None if template.uses(Field::Sha256) => Some(tokio::task::block_in_place(|| {
+            crate::artifacts::hash_file(path)
+        })?),
+        None => None,
+    };
+    let line = template.render(&Completion {
+        path,
+        url,
+        bytes: std::fs::metadata(path)?.len(),
+        sha256: sha256.as_deref(),
+        mime,
+        elapsed: started.elapsed(),
+    });
+    println!("{}", line);
+    Ok(())
+}
+
+fn list_contents(path: &Path, quiet: bool) -> Option<Vec<ArchiveEntry>> {
+    match tokio::task::block_in_place(|| stormdl_io::list_archive(path)) {
+        Ok(Some(entries)) => {
+            if !quiet {
+                let folders = entries.iter().filter(|e| e.is_dir).count();
+                eprintln!(
+                    "Archive: {} files, {} folders",
+                    entries.len() - folders,
+                    folders
+                );
+            }
+            Some(entries)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            if !quiet {
+                eprintln!("Warning: couldn't list archive contents: {}", e);
+            }
+            None
+        }
+    }
+}
+
+// Downloads without a resume journal leave no manifest entry, so tagged or
+// listed ones get a completed record to show up in `storm list --all`,
+// `storm info` and `storm stats`.
+fn record_complete(
+    url: &Url,
+    output_path: &Path,
+    total: u64,
+    tags: &[String],
+    note: Option<&str>,
+) -> Option<i64> {
+    let manifest = Config::open_manifest()?;
+    let filename = output_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let recorded = manifest
+        .create_download(
+            url.as_str(),
+            &filename,
+            output_path,
+            (total > 0).then_some(total),
+            None,
+            None,
+        )
+        .and_then(|id| {
+            manifest.update_download_state(id, DownloadState::Complete)?;
+            manifest.set_download_labels(id, tags, note)?;
+            Ok(id)
+        });
+    recorded
+        .map_err(|e| tracing::warn!("Failed to record download: {}", e))
+        .ok()
+}
+
+// Fetched before the download starts so a missing signature fails fast
+// instead of after the whole file has arrived.
+async fn companion_signature(
+    downloader: &HttpDownloader,
+    url: &Url,
+    source: &str,
+    key: &PublicKey,
+    quiet: bool,
+) -> Result<Vec<u8>> {
+    if source == "auto" {
+        let (found, signature) = stormdl_integrity::discover_signature(downloader, url, key)
+            .await
+            .with_context(|| format!("No .minisig, .asc or .sig signature found for {}", url))?;
+        if !quiet {
+            eprintln!("Signature: {}", found);
+        }
+        return Ok(signature);
+    }
+
+    let found = url
+        .join(source)
+        .with_context(|| format!("Invalid --signature-url '{}'", source))?;
+    stormdl_integrity::fetch_signature(downloader, &found)
+        .await
+        .with_context(|| format!("Failed to fetch signature from {}", found))
+}
+
+async fn companion_checksum(
+    downloader: &HttpDownloader,
+    url: &Url,
+    source: &str,
+    filenames: &[&str],
+    quiet: bool,
+) -> Result<Option<ChecksumSpec>> {
+    if source == "auto" {
+        let found = stormdl_integrity::discover_checksum(downloader, url, filenames).await;
+        if !quiet {
+            match &found {
+                Some((sums, _)) => eprintln!("Checksum: {}", sums),
+                None => eprintln!("Checksum: no companion checksum file found"),
+            }
+        }
+        return Ok(found.map(|(_, spec)| spec));
+    }
+
+    let sums = url
+        .join(source)
+        .with_context(|| format!("Invalid --checksum-url '{}'", source))?;
+    let spec = stormdl_integrity::fetch_checksum(downloader, &sums, filenames)
+        .await
+        .with_context(|| format!("Failed to read checksum from {}", sums))?;
+    Ok(Some(spec))
+}
+
+fn find_partial(output_path: &Path) -> Option<(PathBuf, u64)> {
+    let name = output_path.file_name()?.to_string_lossy().into_owned();
+    [
+        output_path.to_path_buf(),
+        output_path.with_file_name(format!("{}.part", name)),
+        output_path.with_file_name(format!("{}.crdownload", name)),
+    ]
+    .into_iter()
+    .find_map(|path| {
+        let meta = std::fs::metadata(&path).ok()?;
+        (meta.is_file() && meta.len() > 0).then_some((path, meta.len()))
+    })
+}
+
+// When a download has to stop and be saved as paused: the download window
+// closing, or --max-time running out, whichever comes first.
+#[derive(Clone, Copy)]
+pub(crate) struct Deadline {
+    at: Instant,
+    max_time: Option<Duration>,
+}
+
+impl Deadline {
+    fn new(window: Option<Duration>, max_time: Option<(Instant, Duration)>) -> Option<Self> {
+        let window = window.map(|left| Self {
+            at: Instant::now() + left,
+            max_time: None,
+        });
+        let max_time = max_time.map(|(started, limit)| Self {
+            at: started + limit,
+            max_time: Some(limit),
+        });
+        match (window, max_time) {
+            (Some(window), Some(max_time)) => Some(if window.at < max_time.at {
+                window
+            } else {
+                max_time
+            }),
+            (deadline, None) | (None, deadline) => deadline,
+        }
+    }
+
+    fn error(&self) -> anyhow::Error {
+        match self.max_time {
+            Some(limit) => StormError::Timeout(format!(
+                "stopped after --max-time {}",
+                stormdl_core::format_duration(limit)
+            )),
+            None => StormError::Restricted("the download window closed".into()),
+        }
+        .into()
+    }
+}
+
+pub(crate) async fn deadline_reached(deadline: Option<Deadline>) -> anyhow::Error {
+    match deadline {
+        Some(deadline) => {
+            tokio::time::sleep_until(deadline.at.into()).await;
+            deadline.error()
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn download_single(
+    downloader: &HttpDownloader,
+    retry: &RetryState,
+    output_path: &Path,
+    offset: u64,
+    validator: Option<&str>,
+    total_size: u64,
+    max_bytes: Option<u64>,
+    downloaded: Arc<AtomicU64>,
+    limiter: Arc<RateLimiter>,
+    verifier: Option<Arc<StreamVerifier>>,
+    mut pacer: ProgressPacer,
+    style: ProgressStyle,
+    units: SpeedUnits,
+    deadline: Option<Deadline>,
+    quiet: bool,
+    // The size came from a remembered probe, so even the first request
+    // carries If-Range.
+    revalidate: bool,
+) -> Result<()> {
+    if let Some(verifier) = &verifier {
+        verifier.persisted(ByteRange::new(0, offset));
+    }
+    let done = Arc::new(AtomicBool::new(false));
+
+    let progress_downloaded = downloaded.clone();
+    let progress_done = done.clone();
+    let remaining = total_size.saturating_sub(offset);
+
+    let progress_handle = if !quiet && remaining > 0 {
+        Some(tokio::spawn(async move {
+            let mut progress = Progress::new(
+                ByteRange::new(offset, total_size),
+                progress_downloaded,
+                progress_done.clone(),
+                style,
+                units,
+            );
+            while !progress_done.load(Ordering::Relaxed) {
+                let speed = progress.display();
+                tokio::time::sleep(pacer.observe(speed)).await;
+            }
+            progress.finish();
+        }))
+    } else {
+        None
+    };
+
+    let mut offset = offset;
+    let fetch = async {
+        loop {
+            let mut sink = ProgressFileSink::new(
+                output_path,
+                offset,
+                downloaded.clone(),
+                limiter.clone(),
+                verifier.clone(),
+                max_bytes,
+            );
+            let result = if offset > 0 || revalidate {
+                downloader
+                    .fetch_from(retry.url(), offset, validator, &mut sink)
+                    .await
+            } else {
+                downloader.fetch_full(retry.url(), &mut sink).await
+            };
+            let result = result.map_err(Into::into).and_then(|_| sink.flush());
+            let Err(e) = result else {
+                break Ok(());
+            };
+            if let Err(e) = retry.recover(0, None, e).await {
+                break Err(e);
+            }
+            offset = sink.written;
+        }
+    };
+    let result = tokio::select! {
+        result = fetch => result,
+        error = deadline_reached(deadline) => Err(error),
+    };
 
     done.store(true, Ordering::Relaxed);
     if let Some(handle) = progress_handle {
         handle.await?;
     }
 
-    Ok(())
+    result
+}
+
+static TRANSFERRING: AtomicUsize = AtomicUsize::new(0);
+
+// Ctrl+C handling for one segmented download. While it transfers, Ctrl+C
+// pauses it; once done, Ctrl+C only exits if no other download in the batch
+// still has to checkpoint. The listener stops when the download returns.
+struct CtrlC {
+    listener: tokio::task::JoinHandle<()>,
+    transferring: bool,
+}
+
+impl CtrlC {
+    fn listen(interrupted: Arc<AtomicBool>, retry: Arc<RetryState>, done: Arc<AtomicBool>) -> Self {
+        TRANSFERRING.fetch_add(1, Ordering::AcqRel);
+        let listener = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                if done.load(Ordering::Acquire) {
+                    if TRANSFERRING.load(Ordering::Acquire) == 0 {
+                        std::process::exit(130);
+                    }
+                    return;
+                }
+                interrupted.store(true, Ordering::Release);
+                retry.fail(stormdl_core::StormError::Cancelled.into());
+            }
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        });
+        Self {
+            listener,
+            transferring: true,
+        }
+    }
+
+    fn finished(&mut self) {
+        if std::mem::take(&mut self.transferring) {
+            TRANSFERRING.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl Drop for CtrlC {
+    fn drop(&mut self) {
+        self.finished();
+        self.listener.abort();
+    }
 }
 
 async fn download_segmented_adaptive(
-    url: &Url,
+    downloader: Arc<HttpDownloader>,
+    retry: Arc<RetryState>,
     output_path: &PathBuf,
     total_size: u64,
     num_segments: usize,
+    split_hint: SplitHint,
+    direct_buffer: Option<usize>,
+    downloaded: Arc<AtomicU64>,
+    limiter: Arc<RateLimiter>,
+    verifier: Option<Arc<StreamVerifier>>,
+    mut pacer: ProgressPacer,
+    style: ProgressStyle,
+    units: SpeedUnits,
+    http_version: HttpVersion,
     quiet: bool,
     turbo: bool,
+    adaptive_profile: bool,
+    routes: Option<Vec<Route>>,
+    journal: Option<Arc<ResumeJournal>>,
+    head: Option<Bytes>,
+    audit: bool,
+    deadline: Option<Deadline>,
 ) -> Result<()> {
-    let manager = Arc::new(SegmentManager::with_segments(total_size, num_segments));
+    let paths = routes.map(|routes| {
+        let balancer = Arc::new(PathBalancer::new(routes.len()));
+        (routes, balancer)
+    });
+    let balancer = paths.as_ref().map(|(_, balancer)| balancer.clone());
+    let manager = Arc::new(SegmentManager::with_hint(
+        total_size,
+        num_segments,
+        split_hint,
+    ));
     let segments = manager.get_segments();
+    let num_segments = segments.len();
 
-    {
+    let mut written = journal.as_ref().map(|j| j.written()).unwrap_or_default();
+    let resumed = written.covered();
+    if written.is_empty() {
         let file = File::create(output_path)?;
+        let _ = stormdl_io::make_sparse(&file);
         file.set_len(total_size)?;
     }
+    // The start already fetched by the mirror race counts as this run's
+    // transfer, so progress and the quota see it once.
+    if let Some(head) = head.filter(|head| written.is_empty() && !head.is_empty()) {
+        let mut file = std::fs::OpenOptions::new().write(true).open(output_path)?;
+        file.write_all(&head)?;
+        if let Some(journal) = &journal {
+            journal.begin(0).lock().append(&head);
+        }
+        downloaded.fetch_add(head.len() as u64, Ordering::Relaxed);
+        written.insert(ByteRange::new(0, head.len() as u64));
+    }
+    if let Some(verifier) = &verifier {
+        for range in written.iter() {
+            verifier.persisted(range);
+        }
+    }
 
-    let downloader = Arc::new(if turbo {
-        HttpDownloader::turbo()?
-    } else {
-        HttpDownloader::new()?
-    });
-
-    let downloaded = Arc::new(AtomicU64::new(0));
     let done = Arc::new(AtomicBool::new(false));
     let segment_progress: Arc<RwLock<Vec<(u64, u64)>>> = Arc::new(RwLock::new(
-        segments.iter().map(|s| (0u64, s.range.len())).collect(),
+        segments
+            .iter()
+            .map(|s| (written.covered_in(s.range), s.range.len()))
+            .collect(),
     ));
 
     let trackers: Arc<Vec<Arc<SegmentTracker>>> = Arc::new(
         segments
             .iter()
-            .map(|s| Arc::new(SegmentTracker::new(s.range.len(), s.range.start)))
+            .map(|s| {
+                let tracker = SegmentTracker::new(s.range.len());
+                tracker.add(written.covered_in(s.range));
+                Arc::new(tracker)
+            })
             .collect(),
     );
 
     let work_queue = Arc::new(WorkQueue::new());
+    let monitor = Arc::new(NetworkMonitor::new());
 
     for (idx, segment) in segments.iter().enumerate() {
-        work_queue.push(segment.range, idx);
+        for gap in written.gaps(segment.range) {
+            work_queue.push(gap, idx);
+        }
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let mut ctrl_c = CtrlC::listen(interrupted.clone(), retry.clone(), done.clone());
+    // A closing download window or --max-time stops the workers like Ctrl+C
+    // does, so the download is saved as paused.
+    if deadline.is_some() {
+        let interrupted = interrupted.clone();
+        let retry = retry.clone();
+        let done = done.clone();
+        tokio::spawn(async move {
+            let error = deadline_reached(deadline).await;
+            if !done.load(Ordering::Acquire) {
+                interrupted.store(true, Ordering::Release);
+                retry.fail(error);
+            }
+        });
     }
 
+    let checkpoint_handle = journal.clone().map(|journal| {
+        let done = done.clone();
+        let interrupted = interrupted.clone();
+        let retry = retry.clone();
+        tokio::spawn(async move {
+            while !done.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                journal.checkpoint();
+                if journal.pause_requested() {
+                    interrupted.store(true, Ordering::Release);
+                    retry.fail(stormdl_core::StormError::Cancelled.into());
+                    break;
+                }
+            }
+        })
+    });
+
     let progress_downloaded = downloaded.clone();
     let progress_done = done.clone();
     let progress_segments = segment_progress.clone();
+    let segment_ranges: Vec<ByteRange> = segments.iter().map(|s| s.range).collect();
 
+    let progress_interrupted = interrupted.clone();
     let progress_handle = if !quiet {
         Some(tokio::spawn(async move {
             let mut progress = Progress::with_segments(
-                total_size,
+                total_size - resumed,
                 progress_downloaded,
                 progress_done.clone(),
                 progress_segments,
-                num_segments,
+                segment_ranges,
+                style,
+                units,
             );
             while !progress_done.load(Ordering::Relaxed) {
-                progress.display();
-                tokio::time::sleep(Duration::from_millis(100)).await;
+                let speed = progress.display();
+                tokio::time::sleep(pacer.observe(speed)).await;
+            }
+            if !progress_interrupted.load(Ordering::Acquire) {
+                progress.finish();
+            } else if style == ProgressStyle::Bar {
+                eprintln!();
             }
-            progress.finish();
         }))
     } else {
         None
@@ -505,7 +2784,7 @@ async fn download_segmented_adaptive(
     let rebalance_done = done.clone();
     let rebalance_trackers = trackers.clone();
     let rebalance_queue = work_queue.clone();
-    let rebalance_segments = segments.clone();
+    let rebalance_monitor = monitor.clone();
 
     let rebalance_handle = tokio::spawn(async move {
         tokio::time::sleep(Duration::from_secs(2)).await;
@@ -519,40 +2798,29 @@ async fn download_segmented_adaptive(
             let active_speeds: Vec<f64> = speeds
                 .iter()
                 .zip(rebalance_trackers.iter())
-                .filter(|(_, t)| t.active.load(Ordering::Relaxed) && !t.is_complete())
+                .filter(|(_, t)| t.is_active() && !t.is_complete())
                 .map(|(s, _)| *s)
                 .collect();
 
             if active_speeds.len() > 1 {
                 let avg_speed: f64 = active_speeds.iter().sum::<f64>() / active_speeds.len() as f64;
                 let threshold = avg_speed * 0.3;
-
-                for (idx, (tracker, segment)) in rebalance_trackers
-                    .iter()
-                    .zip(rebalance_segments.iter())
-                    .enumerate()
-                {
+                let steal_hint = SplitHint::new(
+                    rebalance_monitor
+                        .min_efficient_range(avg_speed)
+                        .unwrap_or(0)
+                        .max(split_hint.min_range),
+                    split_hint.alignment,
+                );
+
+                for (idx, tracker) in rebalance_trackers.iter().enumerate() {
                     let speed = speeds[idx];
-                    let remaining = tracker.remaining();
-
                     if speed > 0.0
                         && speed < threshold
-                        && remaining > 512 * 1024
-                        && tracker.active.load(Ordering::Relaxed)
+                        && tracker.remaining() > 512 * 1024
+                        && let Some(stolen) = tracker.steal(steal_hint)
                     {
-                        let current_pos = tracker.remaining_start.load(Ordering::Relaxed)
-                            + tracker.downloaded.load(Ordering::Relaxed);
-                        let end = segment.range.end;
-
-                        if end > current_pos + 256 * 1024 {
-                            let split_point = current_pos + (end - current_pos) / 2;
-                            let steal_range = ByteRange::new(split_point, end);
-                            tracker
-                                .remaining_start
-                                .store(split_point, Ordering::Relaxed);
-
-                            rebalance_queue.push(steal_range, idx);
-                        }
+                        rebalance_queue.push(stolen, idx);
                     }
                 }
             }
@@ -566,55 +2834,79 @@ async fn download_segmented_adaptive(
     } else {
         num_segments + 4
     };
+    let gentle_workers = num_segments.div_ceil(2);
+    let governor = adaptive_profile.then(|| Arc::new(ProfileGovernor::new(TransferProfile::Turbo)));
+    let throttle = Arc::new(Throttle::new(max_workers));
     let active_workers = Arc::new(AtomicU64::new(0));
     let mut handles = Vec::new();
 
     for _ in 0..num_segments {
-        let url = url.clone();
+        let retry = retry.clone();
         let path = output_path.clone();
         let downloaded = downloaded.clone();
         let seg_progress = segment_progress.clone();
-        let dl = downloader.clone();
+        let dl = Worker::new(&downloader, http_version, turbo, paths.as_ref());
         let queue = work_queue.clone();
         let trks = trackers.clone();
         let workers = active_workers.clone();
-        let all_done = done.clone();
+        let mon = monitor.clone();
+        let lim = limiter.clone();
+        let jrnl = journal.clone();
+        let stop = interrupted.clone();
+        let ver = verifier.clone();
+        let gov = governor.clone();
+        let throttle = throttle.clone();
 
-        workers.fetch_add(1, Ordering::Relaxed);
+        workers.fetch_add(1, Ordering::AcqRel);
 
         let handle = tokio::spawn(async move {
-            loop {
+            while !retry.failed() {
+                throttle
+                    .hold(&workers, || {
+                        retry.failed() || trks.iter().all(|t| t.is_complete())
+                    })
+                    .await;
                 let work = queue.pop();
                 match work {
                     Some((range, seg_idx)) => {
-                        let result = download_range(
-                            dl.clone(),
-                            &url,
+                        let _connection = retry.connection().await;
+                        let (ticket, url) = retry.source_for(range);
+                        let started = Instant::now();
+                        let outcome = download_range(
+                            &dl,
+                            url,
                             &path,
                             range,
                             downloaded.clone(),
                             seg_progress.clone(),
                             trks.clone(),
                             seg_idx,
+                            mon.clone(),
+                            direct_buffer,
+                            lim.clone(),
+                            jrnl.clone(),
+                            ver.clone(),
+                            stop.clone(),
                         )
                         .await;
-
-                        if let Err(e) = result {
-                            tracing::error!("Segment {} error: {}", seg_idx, e);
-                        }
+                        retry.record(
+                            ticket,
+                            outcome.0.start - range.start,
+                            started.elapsed(),
+                            outcome.1.is_err(),
+                        );
+                        settle_range(&retry, &queue, seg_idx, ticket, outcome, gov.as_deref())
+                            .await;
                     }
                     None => {
-                        if all_done.load(Ordering::Relaxed) || queue.is_empty() {
-                            let all_complete = trks.iter().all(|t| t.is_complete());
-                            if all_complete {
-                                break;
-                            }
+                        if trks.iter().all(|t| t.is_complete()) {
+                            break;
                         }
                         tokio::time::sleep(Duration::from_millis(50)).await;
                     }
                 }
             }
-            workers.fetch_sub(1, Ordering::Relaxed);
+            workers.fetch_sub(1, Ordering::Release);
         });
 
         handles.push(handle);
@@ -627,11 +2919,28 @@ async fn download_segmented_adaptive(
     let spawn_downloaded = downloaded.clone();
     let spawn_seg_progress = segment_progress.clone();
     let spawn_downloader = downloader.clone();
-    let spawn_url = url.clone();
+    let spawn_retry = retry.clone();
     let spawn_path = output_path.clone();
+    let spawn_monitor = monitor.clone();
+    let spawn_limiter = limiter.clone();
+    let spawn_journal = journal.clone();
+    let spawn_interrupted = interrupted.clone();
+    let spawn_paths = paths.clone();
+    let spawn_balancer = balancer.clone();
+    let spawn_governor = governor.clone();
+    let spawn_verifier = verifier.clone();
+    let spawn_throttle = throttle.clone();
 
     let spawner_handle = tokio::spawn(async move {
-        while !spawn_done.load(Ordering::Relaxed) {
+        let monitor = spawn_monitor;
+        let gate = CongestionGate::new();
+
+        while !spawn_done.load(Ordering::Relaxed) && !spawn_retry.failed() {
+            monitor.record(spawn_downloaded.load(Ordering::Relaxed));
+            if let Some(balancer) = &spawn_balancer {
+                balancer.sample();
+            }
+
             let current_workers = spawn_workers.load(Ordering::Relaxed) as usize;
             let has_work = !spawn_queue.is_empty();
             let all_complete = spawn_trackers.iter().all(|t| t.is_complete());
@@ -640,42 +2949,104 @@ async fn download_segmented_adaptive(
                 break;
             }
 
-            if has_work && current_workers < max_workers {
-                let url = spawn_url.clone();
+            let aggregate_speed = monitor.current_speed();
+            if let Some(governor) = &spawn_governor
+                && let Some(switch) = governor.evaluate(ProfileSample {
+                    speed: aggregate_speed,
+                    connections: spawn_throttle.running(current_workers),
+                    latency: monitor.smoothed_ttfb(),
+                })
+            {
+                tracing::info!(
+                    "Switching from {} to {} profile: {}",
+                    switch.from,
+                    switch.to,
+                    switch.reason
+                );
+                match switch.to {
+                    TransferProfile::Turbo => {
+                        spawn_throttle.set_limit(max_workers);
+                        gate.reset();
+                    }
+                    TransferProfile::Gentle => spawn_throttle.set_limit(gentle_workers),
+                }
+            }
+
+            if has_work
+                && current_workers < spawn_throttle.limit()
+                && !spawn_retry.is_single()
+                && gate.should_spawn(aggregate_speed)
+            {
+                gate.record_spawn(aggregate_speed);
+
+                let retry = spawn_retry.clone();
                 let path = spawn_path.clone();
                 let downloaded = spawn_downloaded.clone();
                 let seg_progress = spawn_seg_progress.clone();
-                let dl = spawn_downloader.clone();
+                let turbo = spawn_governor.as_ref().map_or(turbo, |g| g.is_turbo());
+                let dl = Worker::new(&spawn_downloader, http_version, turbo, spawn_paths.as_ref());
                 let queue = spawn_queue.clone();
                 let trks = spawn_trackers.clone();
                 let workers = spawn_workers.clone();
                 let all_done = spawn_done.clone();
+                let mon = monitor.clone();
+                let lim = spawn_limiter.clone();
+                let jrnl = spawn_journal.clone();
+                let stop = spawn_interrupted.clone();
+                let ver = spawn_verifier.clone();
+                let gov = spawn_governor.clone();
+                let throttle = spawn_throttle.clone();
 
-                workers.fetch_add(1, Ordering::Relaxed);
+                workers.fetch_add(1, Ordering::AcqRel);
 
                 tokio::spawn(async move {
-                    loop {
+                    while !retry.failed() {
+                        throttle
+                            .hold(&workers, || {
+                                retry.failed() || trks.iter().all(|t| t.is_complete())
+                            })
+                            .await;
                         let work = queue.pop();
                         match work {
                             Some((range, seg_idx)) => {
-                                let result = download_range(
-                                    dl.clone(),
-                                    &url,
+                                let _connection = retry.connection().await;
+                                let (ticket, url) = retry.source_for(range);
+                                let started = Instant::now();
+                                let outcome = download_range(
+                                    &dl,
+                                    url,
                                     &path,
                                     range,
                                     downloaded.clone(),
                                     seg_progress.clone(),
                                     trks.clone(),
                                     seg_idx,
+                                    mon.clone(),
+                                    direct_buffer,
+                                    lim.clone(),
+                                    jrnl.clone(),
+                                    ver.clone(),
+                                    stop.clone(),
+                                )
+                                .await;
+                                retry.record(
+                                    ticket,
+                                    outcome.0.start - range.start,
+                                    started.elapsed(),
+                                    outcome.1.is_err(),
+                                );
+                                settle_range(
+                                    &retry,
+                                    &queue,
+                                    seg_idx,
+                                    ticket,
+                                    outcome,
+                                    gov.as_deref(),
                                 )
                                 .await;
-
-                                if let Err(e) = result {
-                                    tracing::error!("Helper segment {} error: {}", seg_idx, e);
-                                }
                             }
                             None => {
-                                if all_done.load(Ordering::Relaxed) {
+                                if all_done.load(Ordering::Acquire) {
                                     break;
                                 }
                                 let all_complete = trks.iter().all(|t| t.is_complete());
@@ -686,7 +3057,7 @@ async fn download_segmented_adaptive(
                             }
                         }
                     }
-                    workers.fetch_sub(1, Ordering::Relaxed);
+                    workers.fetch_sub(1, Ordering::Release);
                 });
             }
 
@@ -698,19 +3069,334 @@ async fn download_segmented_adaptive(
         let _ = handle.await;
     }
 
-    done.store(true, Ordering::Relaxed);
+    done.store(true, Ordering::Release);
+    ctrl_c.finished();
     let _ = rebalance_handle.await;
     let _ = spawner_handle.await;
+    let drain_deadline = Instant::now() + Duration::from_secs(5);
+    while active_workers.load(Ordering::Acquire) > 0 && Instant::now() < drain_deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
 
     if let Some(handle) = progress_handle {
         handle.await?;
     }
+    if let Some((routes, balancer)) = &paths {
+        for (path, route) in routes.iter().enumerate() {
+            tracing::debug!("{}: {}", route.label(), format_bytes(balancer.bytes(path)));
+        }
+    }
+    if let Some(handle) = checkpoint_handle {
+        handle.abort();
+    }
+
+    if interrupted.load(Ordering::Acquire) {
+        let closed = retry.take_failure().filter(|e| {
+            matches!(
+                e.downcast_ref::<StormError>(),
+                Some(StormError::Restricted(_) | StormError::Timeout(_))
+            )
+        });
+        if let Some(journal) = &journal {
+            journal.finish(DownloadState::Paused);
+        }
+        if !quiet {
+            let reason = if let Some(closed) = &closed {
+                match closed.downcast_ref::<StormError>() {
+                    Some(StormError::Timeout(_)) => "Reached --max-time",
+                    _ => "Download window closed",
+                }
+            } else if journal.as_ref().is_some_and(|j| j.pause_requested()) {
+                "Paused"
+            } else {
+                "Interrupted"
+            };
+            match &journal {
+                Some(journal) => eprintln!(
+                    "{}; progress saved. Resume with `storm resume {}`",
+                    reason,
+                    journal.id()
+                ),
+                None => eprintln!("{}", reason),
+            }
+        }
+        return Err(closed.unwrap_or_else(|| stormdl_core::StormError::Cancelled.into()));
+    }
+
+    let failure = match (retry.take_failure(), &journal) {
+        (None, Some(journal)) if audit => repair_corrupted(
+            &downloader,
+            retry.url(),
+            output_path,
+            journal,
+            verifier.as_deref(),
+            quiet,
+        )
+        .await
+        .err(),
+        (failure, _) => failure,
+    };
+    if let Some(journal) = &journal {
+        journal.finish(if failure.is_some() {
+            DownloadState::Failed
+        } else {
+            DownloadState::Complete
+        });
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+async fn settle_range(
+    retry: &RetryState,
+    queue: &WorkQueue,
+    segment_idx: usize,
+    ticket: Option<usize>,
+    (remaining, result): (ByteRange, Result<()>),
+    governor: Option<&ProfileGovernor>,
+) {
+    if let Some(governor) = governor {
+        governor.record_request(result.is_err());
+    }
+    let Err(e) = result else {
+        return;
+    };
+
+    match retry.recover(segment_idx, ticket, e).await {
+        Ok(()) => queue.push(remaining, segment_idx),
+        Err(e) => retry.fail(e),
+    }
+}
+
+const REPAIR_PASSES: usize = 3;
+
+// Each extent was hashed as it arrived; reading it back catches bytes that
+// were damaged on their way to disk, and only those ranges are fetched again.
+async fn repair_corrupted(
+    downloader: &HttpDownloader,
+    url: &Url,
+    path: &Path,
+    journal: &ResumeJournal,
+    verifier: Option<&StreamVerifier>,
+    quiet: bool,
+) -> Result<()> {
+    let mut corrupted = tokio::task::block_in_place(|| journal.corrupted(path))?;
+    let mut passes = 0;
+    while !corrupted.is_empty() {
+        if passes == REPAIR_PASSES {
+            anyhow::bail!(
+                "{} of {} still corrupt after {} repair attempts",
+                format_bytes(corrupted.covered()),
+                path.display(),
+                REPAIR_PASSES
+            );
+        }
+        passes += 1;
+        if !quiet {
+            eprintln!(
+                "Re-downloading {} in {} corrupted range(s)",
+                format_bytes(corrupted.covered()),
+                corrupted.ranges().len()
+            );
+        }
+
+        for range in corrupted.iter() {
+            if let Some(verifier) = verifier {
+                verifier.invalidate(range);
+            }
+            let mut sink = RepairSink {
+                file: open_range_writer(&path.to_path_buf(), range.start, None)?,
+                remaining: range.len(),
+                extent: Some(journal.begin(range.start)),
+            };
+            downloader.fetch_range(url, range, &mut sink).await?;
+            Write::flush(&mut sink.file)?;
+        }
+        corrupted = tokio::task::block_in_place(|| journal.corrupted(path))?;
+    }
+    Ok(())
+}
+
+// Metalink piece hashes pin damage down to pieces, which are fetched
+// again; each pass asks the next source, as the last one may be what sent
+// them damaged.
+async fn repair_pieces(
+    downloader: &HttpDownloader,
+    retry: &RetryState,
+    path: &Path,
+    pieces: &Pieces,
+    size: u64,
+    verifier: Option<&StreamVerifier>,
+    quiet: bool,
+) -> Result<()> {
+    let mut corrupted = tokio::task::block_in_place(|| pieces.corrupted(path, size))?;
+    let mut passes = 0;
+    while !corrupted.is_empty() {
+        if passes == REPAIR_PASSES {
+            anyhow::bail!(
+                "{} piece(s) of {} still don't match the metalink after {} repair attempts",
+                corrupted.len(),
+                path.display(),
+                REPAIR_PASSES
+            );
+        }
+        passes += 1;
+        if !quiet {
+            eprintln!(
+                "Re-downloading {} piece(s) that don't match the metalink",
+                corrupted.len()
+            );
+        }
 
+        let url = &retry.sources[passes % retry.sources.len()];
+        for &range in &corrupted {
+            if let Some(verifier) = verifier {
+                verifier.invalidate(range);
+            }
+            let mut sink = RepairSink {
+                file: open_range_writer(&path.to_path_buf(), range.start, None)?,
+                remaining: range.len(),
+                extent: None,
+            };
+            downloader.fetch_range(url, range, &mut sink).await?;
+            Write::flush(&mut sink.file)?;
+        }
+        corrupted = tokio::task::block_in_place(|| pieces.corrupted(path, size))?;
+    }
     Ok(())
 }
 
+struct Throttle {
+    limit: AtomicUsize,
+    parked: AtomicUsize,
+}
+
+impl Throttle {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit),
+            parked: AtomicUsize::new(0),
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    fn set_limit(&self, limit: usize) {
+        self.limit.store(limit.max(1), Ordering::Relaxed);
+    }
+
+    fn running(&self, workers: usize) -> usize {
+        workers.saturating_sub(self.parked.load(Ordering::Acquire))
+    }
+
+    // Workers over the limit park between ranges rather than exiting, so a
+    // switch back to turbo picks them up again without new connections.
+    async fn hold(&self, workers: &AtomicU64, released: impl Fn() -> bool) {
+        let running =
+            |parked: usize| (workers.load(Ordering::Acquire) as usize).saturating_sub(parked);
+        let park = |parked: usize| (running(parked) > self.limit()).then_some(parked + 1);
+        if self
+            .parked
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, park)
+            .is_err()
+        {
+            return;
+        }
+
+        let unpark = |parked: usize| (running(parked) < self.limit()).then(|| parked - 1);
+        while !released() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if self
+                .parked
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, unpark)
+                .is_ok()
+            {
+                return;
+            }
+        }
+        self.parked.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn dedicated_workers(shared: &HttpDownloader) -> bool {
+    !shared.is_proxied()
+        && shared.host_policy().is_none()
+        && shared.cookies().is_none()
+        && !shared.has_request_headers()
+}
+
+pub(crate) struct Worker {
+    pub(crate) paths: Vec<Arc<HttpDownloader>>,
+    balancer: Option<Arc<PathBalancer>>,
+}
+
+impl Worker {
+    pub(crate) fn new(
+        shared: &Arc<HttpDownloader>,
+        http_version: HttpVersion,
+        turbo: bool,
+        routes: Option<&(Vec<Route>, Arc<PathBalancer>)>,
+    ) -> Self {
+        let http1 = http_version == HttpVersion::Http1_1;
+        if let Some((routes, balancer)) = routes {
+            let paths: Result<Vec<Arc<HttpDownloader>>, _> = routes
+                .iter()
+                .map(|route| {
+                    route
+                        .worker(turbo, http1, shared.socket_options())
+                        .map(Arc::new)
+                })
+                .collect();
+            match paths {
+                Ok(paths) => {
+                    return Self {
+                        paths,
+                        balancer: Some(balancer.clone()),
+                    };
+                }
+                Err(e) => tracing::debug!("Falling back to the default route: {}", e),
+            }
+        }
+        if !http1 || !dedicated_workers(shared) {
+            return Self::shared(shared);
+        }
+
+        match HttpDownloader::keep_alive_worker(turbo, shared.socket_options()) {
+            Ok(dl) => Self {
+                paths: vec![Arc::new(dl)],
+                balancer: None,
+            },
+            Err(e) => {
+                tracing::debug!("Falling back to shared connection pool: {}", e);
+                Self::shared(shared)
+            }
+        }
+    }
+
+    fn shared(shared: &Arc<HttpDownloader>) -> Self {
+        Self {
+            paths: vec![shared.clone()],
+            balancer: None,
+        }
+    }
+
+    pub(crate) fn acquire(&self) -> usize {
+        self.balancer.as_ref().map_or(0, |b| b.acquire())
+    }
+
+    pub(crate) fn release(&self, path: usize) {
+        if let Some(balancer) = &self.balancer {
+            balancer.release(path);
+        }
+    }
+}
+
 async fn download_range(
-    downloader: Arc<HttpDownloader>,
+    worker: &Worker,
     url: &Url,
     path: &PathBuf,
     range: ByteRange,
@@ -718,14 +3404,24 @@ async fn download_range(
     segment_progress: Arc<RwLock<Vec<(u64, u64)>>>,
     trackers: Arc<Vec<Arc<SegmentTracker>>>,
     segment_idx: usize,
-) -> Result<()> {
-    use std::io::{Seek, SeekFrom};
-
-    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
-    file.seek(SeekFrom::Start(range.start))?;
+    monitor: Arc<NetworkMonitor>,
+    direct_buffer: Option<usize>,
+    limiter: Arc<RateLimiter>,
+    journal: Option<Arc<ResumeJournal>>,
+    verifier: Option<Arc<StreamVerifier>>,
+    interrupted: Arc<AtomicBool>,
+) -> (ByteRange, Result<()>) {
+    let file = match open_range_writer(path, range.start, direct_buffer) {
+        Ok(file) => file,
+        Err(e) => return (range, Err(e)),
+    };
 
     let tracker = &trackers[segment_idx];
-    let _range_size = range.len();
+    let connections = trackers.iter().filter(|t| t.is_active()).count() + 1;
+    let timeout = monitor.request_timeout(range.len(), connections);
+    let claim = Arc::new(RangeClaim::new(range));
+    tracker.begin(claim.clone());
+    let path = worker.acquire();
 
     let mut sink = AdaptiveSink {
         file,
@@ -733,70 +3429,261 @@ async fn download_range(
         segment_progress,
         segment_idx,
         tracker: tracker.clone(),
+        claim: claim.clone(),
+        offset: range.start,
         written: 0,
+        monitor,
+        limiter,
+        request_start: Instant::now(),
+        extent: journal.map(|j| j.begin(range.start)),
+        verifier: verifier.clone(),
+        interrupted,
+        path: worker.balancer.clone().map(|b| (b, path)),
     };
 
-    downloader.fetch_range(url, range, &mut sink).await?;
-    sink.file.flush()?;
+    // Whatever arrived before the deadline is kept; the rest goes back in
+    // the queue.
+    let result = tokio::time::timeout(
+        timeout,
+        worker.paths[path].fetch_range(url, range, &mut sink),
+    )
+    .await
+    .unwrap_or_else(|_| {
+        Err(stormdl_core::StormError::Timeout(format!(
+            "{} of {} not done in {}",
+            format_bytes(range.len()),
+            url,
+            stormdl_core::format_duration(timeout)
+        )))
+    });
+    let flushed = Write::flush(&mut sink.file);
+    if flushed.is_ok()
+        && let Some(verifier) = &verifier
+    {
+        verifier.persisted(ByteRange::new(range.start, range.start + sink.written));
+    }
+    tracker.finish(&claim);
+    worker.release(path);
+
+    let remaining = claim.remaining();
+    let result = flushed.map_err(anyhow::Error::from).and_then(|_| {
+        if remaining.is_empty() {
+            return Ok(());
+        }
+        result?;
+        Err(stormdl_core::StormError::Network(format!(
+            "connection closed with {} bytes left",
+            remaining.len()
+        ))
+        .into())
+    });
+
+    (remaining, result)
+}
 
-    Ok(())
+fn open_range_writer(
+    path: &PathBuf,
+    offset: u64,
+    direct_buffer: Option<usize>,
+) -> Result<Box<dyn Write + Send>> {
+    use std::io::{Seek, SeekFrom};
+
+    if let Some(buffer_size) = direct_buffer {
+        match DirectWriter::open(path, offset, buffer_size) {
+            Ok(writer) => return Ok(Box::new(writer)),
+            Err(e) => tracing::debug!("Direct I/O unavailable, using page cache: {}", e),
+        }
+    }
+
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    Ok(Box::new(file))
+}
+
+struct RepairSink {
+    file: Box<dyn Write + Send>,
+    remaining: u64,
+    extent: Option<Arc<Mutex<Extent>>>,
+}
+
+impl stormdl_core::DataSink for RepairSink {
+    fn write(&mut self, data: Bytes) -> Result<(), stormdl_core::StormError> {
+        let len = (data.len() as u64).min(self.remaining) as usize;
+        self.file.write_all(&data[..len])?;
+        if let Some(extent) = &self.extent {
+            extent.lock().append(&data[..len]);
+        }
+        self.remaining -= len as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), stormdl_core::StormError> {
+        Write::flush(&mut self.file)?;
+        Ok(())
+    }
 }
 
 struct ProgressFileSink {
-    file: File,
+    path: PathBuf,
+    file: Option<File>,
     downloaded: Arc<AtomicU64>,
+    limiter: Arc<RateLimiter>,
+    verifier: Option<Arc<StreamVerifier>>,
+    written: u64,
+    max_bytes: Option<u64>,
 }
 
 impl ProgressFileSink {
-    fn new(path: &PathBuf, downloaded: Arc<AtomicU64>) -> Result<Self> {
-        let file = File::create(path)?;
-        Ok(Self { file, downloaded })
+    fn new(
+        path: &Path,
+        offset: u64,
+        downloaded: Arc<AtomicU64>,
+        limiter: Arc<RateLimiter>,
+        verifier: Option<Arc<StreamVerifier>>,
+        max_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            file: None,
+            downloaded,
+            limiter,
+            verifier,
+            written: offset,
+            max_bytes,
+        }
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None if self.written > 0 => {
+                std::fs::OpenOptions::new().append(true).open(&self.path)?
+            }
+            None => File::create(&self.path)?,
+        };
+        Ok(self.file.insert(file))
     }
 
     fn flush(&mut self) -> Result<()> {
-        self.file.flush()?;
+        self.file()?.flush()?;
         Ok(())
     }
 }
 
+#[async_trait::async_trait]
 impl stormdl_core::DataSink for ProgressFileSink {
     fn write(&mut self, data: Bytes) -> Result<(), stormdl_core::StormError> {
-        self.file.write_all(&data)?;
+        let size = self.written + data.len() as u64;
+        if let Some(limit) = self.max_bytes
+            && size > limit
+        {
+            return Err(stormdl_core::StormError::TooLarge { size, limit });
+        }
+
+        if self.file.is_none() && self.written == 0 {
+            let name = self
+                .path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if stormdl_core::expects_binary(&name) && stormdl_core::looks_like_html(&data) {
+                return Err(stormdl_core::StormError::UnexpectedHtml(name));
+            }
+        }
+
+        self.file()?.write_all(&data)?;
+        if let Some(verifier) = &self.verifier {
+            verifier.write(self.written, &data);
+        }
+        self.written = size;
         self.downloaded
             .fetch_add(data.len() as u64, Ordering::Relaxed);
         Ok(())
     }
 
     fn flush(&mut self) -> Result<(), stormdl_core::StormError> {
-        Write::flush(&mut self.file)?;
+        Write::flush(self.file()?)?;
+        Ok(())
+    }
+
+    async fn ready(&mut self, len: usize) -> Result<(), stormdl_core::StormError> {
+        self.limiter.acquire(len).await;
         Ok(())
     }
 }
 
 struct AdaptiveSink {
-    file: File,
+    file: Box<dyn Write + Send>,
     global_downloaded: Arc<AtomicU64>,
     segment_progress: Arc<RwLock<Vec<(u64, u64)>>>,
     segment_idx: usize,
     tracker: Arc<SegmentTracker>,
+    claim: Arc<RangeClaim>,
+    offset: u64,
     written: u64,
+    monitor: Arc<NetworkMonitor>,
+    limiter: Arc<RateLimiter>,
+    request_start: Instant,
+    extent: Option<Arc<Mutex<Extent>>>,
+    verifier: Option<Arc<StreamVerifier>>,
+    interrupted: Arc<AtomicBool>,
+    path: Option<(Arc<PathBalancer>, usize)>,
 }
 
+#[async_trait::async_trait]
 impl stormdl_core::DataSink for AdaptiveSink {
     fn write(&mut self, data: Bytes) -> Result<(), stormdl_core::StormError> {
-        self.file.write_all(&data)?;
-        let len = data.len() as u64;
+        if self.interrupted.load(Ordering::Relaxed) {
+            return Err(stormdl_core::StormError::Cancelled);
+        }
+        if self.written == 0 {
+            let ttfb = self.request_start.elapsed();
+            if self.monitor.is_pathological_ttfb(ttfb) {
+                tracing::warn!(
+                    "Segment {} slow start: first byte after {:.0}ms",
+                    self.segment_idx,
+                    ttfb.as_secs_f64() * 1000.0
+                );
+            }
+            self.monitor.record_ttfb(ttfb);
+        }
+
+        let len = self.claim.reserve(data.len() as u64);
+        if len == 0 {
+            return Err(stormdl_core::StormError::Cancelled);
+        }
+
+        if let Err(e) = self.file.write_all(&data[..len as usize]) {
+            self.claim.release(len);
+            return Err(e.into());
+        }
         self.global_downloaded.fetch_add(len, Ordering::Relaxed);
-        self.tracker.downloaded.fetch_add(len, Ordering::Relaxed);
+        if let Some((balancer, path)) = &self.path {
+            balancer.record(*path, len);
+        }
         self.written += len;
+        let finished = self.claim.remaining().is_empty();
+        if finished {
+            Write::flush(&mut self.file)?;
+        }
+        if let Some(extent) = &self.extent {
+            extent.lock().append(&data[..len as usize]);
+        }
+        if let Some(verifier) = &self.verifier {
+            verifier.write(self.offset + self.written - len, &data[..len as usize]);
+        }
+        self.tracker.add(len);
 
         {
             let mut segs = self.segment_progress.write();
             if let Some(seg) = segs.get_mut(self.segment_idx) {
-                seg.0 = self.tracker.downloaded.load(Ordering::Relaxed);
+                seg.0 = self.tracker.downloaded();
             }
         }
 
+        if finished {
+            return Err(stormdl_core::StormError::Cancelled);
+        }
         Ok(())
     }
 
@@ -804,4 +3691,51 @@ impl stormdl_core::DataSink for AdaptiveSink {
         Write::flush(&mut self.file)?;
         Ok(())
     }
+
+    async fn ready(&mut self, len: usize) -> Result<(), stormdl_core::StormError> {
+        self.limiter.acquire(len).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_fails_on_invalid_url() {
+        let server = tokio::runtime::Runtime::new().unwrap();
+        let url = server
+            .block_on(stormdl_protocol::test_server(vec![7u8; 4096]))
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("storm-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Config::use_data_dir(dir.join("data"));
+        let entry = |url: &str| ListEntry {
+            url: url.to_string(),
+            dir: None,
+            out: None,
+            metalink: None,
+            source: None,
+        };
+
+        let mut args = DownloadArgs::new(Config::default());
+        args.output = Some(dir.to_string_lossy().into_owned());
+        args.quiet = true;
+        args.no_resume = true;
+        args.no_notify = true;
+        let result = download_batch(
+            vec![entry(url.as_str()), entry("not a url")],
+            args,
+            2,
+            Some(dir.join("session.jsonl")),
+        );
+
+        assert_eq!(result.unwrap_err().to_string(), "1 of 2 downloads failed");
+        assert_eq!(
+            std::fs::read(dir.join("file.bin")).unwrap(),
+            vec![7u8; 4096]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
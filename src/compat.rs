@@ -0,0 +1,391 @@
+use anyhow::{Result, bail};
+use std::path::Path;
+
+#[derive(Clone, Copy)]
+enum Mapping {
+    Flag(&'static str),
+    Option(&'static str),
+    OutputFile,
+    Ignore,
+    Unsupported(&'static str),
+}
+
+struct CompatFlag {
+    short: Option<char>,
+    long: &'static str,
+    takes_value: bool,
+    mapping: Mapping,
+}
+
+const fn flag(
+    short: Option<char>,
+    long: &'static str,
+    takes_value: bool,
+    mapping: Mapping,
+) -> CompatFlag {
+    CompatFlag {
+        short,
+        long,
+        takes_value,
+        mapping,
+    }
+}
+
+const WGET: &[CompatFlag] = &[
+    flag(Some('O'), "output-document", true, Mapping::OutputFile),
+    flag(Some('P'), "directory-prefix", true, Mapping::Option("-o")),
+    flag(Some('c'), "continue", false, Mapping::Flag("--continue")),
+    flag(Some('q'), "quiet", false, Mapping::Flag("-q")),
+    flag(None, "limit-rate", true, Mapping::Option("-l")),
+    flag(Some('t'), "tries", true, Mapping::Option("--retries")),
+    flag(Some('T'), "timeout", true, Mapping::Ignore),
+    flag(None, "no-verbose", false, Mapping::Ignore),
+    flag(None, "progress", true, Mapping::Ignore),
+    flag(None, "show-progress", false, Mapping::Ignore),
+    flag(None, "load-cookies", true, Mapping::Option("--cookies")),
+    flag(
+        Some('i'),
+        "input-file",
+        true,
+        Mapping::Option("--input-file"),
+    ),
+    flag(
+        None,
+        "no-check-certificate",
+        false,
+        Mapping::Unsupported("storm always verifies TLS certificates"),
+    ),
+    flag(
+        Some('r'),
+        "recursive",
+        false,
+        Mapping::Unsupported("storm downloads single files"),
+    ),
+];
+
+const CURL: &[CompatFlag] = &[
+    flag(Some('o'), "output", true, Mapping::OutputFile),
+    flag(Some('O'), "remote-name", false, Mapping::Ignore),
+    flag(None, "output-dir", true, Mapping::Option("-o")),
+    flag(Some('L'), "location", false, Mapping::Ignore),
+    flag(Some('C'), "continue-at", true, Mapping::Flag("--continue")),
+    flag(Some('s'), "silent", false, Mapping::Flag("-q")),
+    flag(Some('S'), "show-error", false, Mapping::Ignore),
+    flag(Some('f'), "fail", false, Mapping::Ignore),
+    flag(None, "retry", true, Mapping::Option("--retries")),
+    flag(None, "retry-delay", true, Mapping::Option("--retry-delay")),
+    flag(Some('m'), "max-time", true, Mapping::Option("--max-time")),
+    flag(Some('#'), "progress-bar", false, Mapping::Ignore),
+    flag(None, "limit-rate", true, Mapping::Option("-l")),
+    flag(Some('x'), "proxy", true, Mapping::Option("--proxy")),
+    flag(
+        Some('k'),
+        "insecure",
+        false,
+        Mapping::Unsupported("storm always verifies TLS certificates"),
+    ),
+    flag(Some('H'), "header", true, Mapping::Option("--header")),
+    flag(Some('b'), "cookie", true, Mapping::Option("--cookie")),
+    flag(Some('u'), "user", true, Mapping::Option("--user")),
+    flag(None, "digest", false, Mapping::Flag("--digest")),
+    flag(
+        None,
+        "location-trusted",
+        false,
+        Mapping::Flag("--location-trusted"),
+    ),
+    flag(
+        None,
+        "oauth2-bearer",
+        true,
+        Mapping::Option("--bearer-token"),
+    ),
+    flag(Some('d'), "data", true, Mapping::Option("--data")),
+    flag(Some('X'), "request", true, Mapping::Option("--method")),
+];
+
+const ARIA2: &[CompatFlag] = &[
+    flag(Some('d'), "dir", true, Mapping::Option("-o")),
+    flag(Some('o'), "out", true, Mapping::Option("-n")),
+    flag(Some('s'), "split", true, Mapping::Option("-s")),
+    flag(
+        Some('x'),
+        "max-connection-per-server",
+        true,
+        Mapping::Option("-s"),
+    ),
+    flag(
+        Some('j'),
+        "max-concurrent-downloads",
+        true,
+        Mapping::Option("-c"),
+    ),
+    flag(Some('c'), "continue", false, Mapping::Flag("--continue")),
+    flag(Some('q'), "quiet", false, Mapping::Flag("-q")),
+    flag(None, "max-download-limit", true, Mapping::Option("-l")),
+    flag(
+        None,
+        "max-overall-download-limit",
+        true,
+        Mapping::Option("-l"),
+    ),
+    flag(None, "all-proxy", true, Mapping::Option("--proxy")),
+    flag(Some('m'), "max-tries", true, Mapping::Option("--retries")),
+    flag(None, "retry-wait", true, Mapping::Option("--retry-delay")),
+    flag(Some('k'), "min-split-size", true, Mapping::Ignore),
+    flag(None, "file-allocation", true, Mapping::Ignore),
+    flag(None, "console-log-level", true, Mapping::Ignore),
+    flag(None, "summary-interval", true, Mapping::Ignore),
+    flag(
+        Some('i'),
+        "input-file",
+        true,
+        Mapping::Option("--input-file"),
+    ),
+    flag(None, "checksum", true, Mapping::Option("--checksum")),
+];
+
+fn table(tool: &str) -> Option<&'static [CompatFlag]> {
+    match tool {
+        "wget" => Some(WGET),
+        "curl" => Some(CURL),
+        "aria2" | "aria2c" => Some(ARIA2),
+        _ => None,
+    }
+}
+
+pub fn detect(argv: &[String]) -> Option<Result<Vec<String>>> {
+    let invoked_as = argv
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())?;
+    if table(&invoked_as).is_some() {
+        return Some(translate(&invoked_as, &argv[1..]));
+    }
+
+    let first = argv.get(1)?;
+    if let Some(tool) = first.strip_prefix("--compat=") {
+        Some(translate(tool, &argv[2..]))
+    } else if first == "--compat" {
+        match argv.get(2) {
+            Some(tool) => Some(translate(tool, &argv[3..])),
+            None => Some(Err(anyhow::anyhow!(
+                "--compat needs a tool name (wget, curl or aria2c)"
+            ))),
+        }
+    } else {
+        None
+    }
+}
+
+pub fn translate(tool: &str, args: &[String]) -> Result<Vec<String>> {
+    let Some(flags) = table(tool) else {
+        bail!(
+            "Unknown compatibility mode '{}' (expected wget, curl or aria2c)",
+            tool
+        );
+    };
+
+    let mut out = vec!["storm".to_string()];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            out.extend(iter.by_ref().cloned());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let Some(spec) = flags.iter().find(|f| f.long == name) else {
+                bail!(unknown(tool, arg, flags));
+            };
+            let value = if spec.takes_value {
+                match inline {
+                    Some(value) => Some(value),
+                    None => Some(next_value(tool, arg, &mut iter)?),
+                }
+            } else if inline.as_deref() == Some("false") {
+                continue;
+            } else {
+                None
+            };
+            apply(tool, arg, spec, value, &mut out)?;
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            let chars: Vec<char> = shorts.chars().collect();
+            for (i, &c) in chars.iter().enumerate() {
+                let Some(spec) = flags.iter().find(|f| f.short == Some(c)) else {
+                    bail!(unknown(tool, &format!("-{}", c), flags));
+                };
+                if spec.takes_value {
+                    let rest: String = chars[i + 1..].iter().collect();
+                    let value = if rest.is_empty() {
+                        next_value(tool, arg, &mut iter)?
+                    } else {
+                        rest
+                    };
+                    apply(tool, &format!("-{}", c), spec, Some(value), &mut out)?;
+                    break;
+                }
+                apply(tool, &format!("-{}", c), spec, None, &mut out)?;
+            }
+        } else {
+            out.push(arg.clone());
+        }
+    }
+
+    Ok(out)
+}
+
+fn next_value<'a>(
+    tool: &str,
+    arg: &str,
+    iter: &mut impl Iterator<Item = &'a String>,
+) -> Result<String> {
+    iter.next()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("{} option '{}' needs a value", tool, arg))
+}
+
+fn apply(
+    tool: &str,
+    arg: &str,
+    spec: &CompatFlag,
+    value: Option<String>,
+    out: &mut Vec<String>,
+) -> Result<()> {
+    match spec.mapping {
+        Mapping::Flag(storm) => out.push(storm.to_string()),
+        Mapping::Option(storm) => {
+            out.push(storm.to_string());
+            out.extend(value);
+        }
+        Mapping::OutputFile => {
+            let value = value.unwrap_or_default();
+            if value == "-" {
+                bail!(
+                    "{} option '{}': writing to stdout is not supported",
+                    tool,
+                    arg
+                );
+            }
+            let path = Path::new(&value);
+            if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                out.push("-o".to_string());
+                out.push(dir.to_string_lossy().into_owned());
+            }
+            if let Some(name) = path.file_name() {
+                out.push("-n".to_string());
+                out.push(name.to_string_lossy().into_owned());
+            }
+        }
+        Mapping::Ignore => {}
+        Mapping::Unsupported(hint) => {
+            bail!(
+                "{} option '{}' has no storm equivalent: {}",
+                tool,
+                arg,
+                hint
+            )
+        }
+    }
+    Ok(())
+}
+
+fn unknown(tool: &str, arg: &str, flags: &[CompatFlag]) -> String {
+    let supported: Vec<String> = flags
+        .iter()
+        .filter(|f| !matches!(f.mapping, Mapping::Unsupported(_)))
+        .map(|f| match f.short {
+            Some(short) => format!("-{}/--{}", short, f.long),
+            None => format!("--{}", f.long),
+        })
+        .collect();
+    format!(
+        "Unknown {} option '{}'; supported: {}",
+        tool,
+        arg,
+        supported.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate(
+                "wget",
+                &args("-c -O out/file.iso --limit-rate=2M https://x/f")
+            )
+            .unwrap(),
+            args("storm --continue -o out -n file.iso -l 2M https://x/f")
+        );
+        assert_eq!(
+            translate("curl", &args("-fsSLo file.zip https://x/f")).unwrap(),
+            args("storm -q -n file.zip https://x/f")
+        );
+        assert_eq!(
+            translate("curl", &args("--digest -u me:pw -O https://x/f")).unwrap(),
+            args("storm --digest --user me:pw https://x/f")
+        );
+        assert_eq!(
+            translate("curl", &args("-m 90 --retry-delay 5 -O https://x/f")).unwrap(),
+            args("storm --max-time 90 --retry-delay 5 https://x/f")
+        );
+        assert_eq!(
+            translate("curl", &args("-X PUT -d @query.json -o r.csv https://x/f")).unwrap(),
+            args("storm --method PUT --data @query.json -n r.csv https://x/f")
+        );
+        assert_eq!(
+            translate("curl", &args("--data=a=1 --request=POST https://x/f")).unwrap(),
+            args("storm --data a=1 --method POST https://x/f")
+        );
+        assert_eq!(
+            translate("wget", &args("--load-cookies=c.txt https://x/f")).unwrap(),
+            args("storm --cookies c.txt https://x/f")
+        );
+        assert_eq!(
+            translate("aria2c", &args("-x16 -d /tmp --continue=true https://x/f")).unwrap(),
+            args("storm -s 16 -o /tmp --continue https://x/f")
+        );
+        assert_eq!(
+            translate("wget", &args("-i urls.txt -P out")).unwrap(),
+            args("storm --input-file urls.txt -o out")
+        );
+        assert_eq!(
+            translate("aria2c", &args("--input-file=urls.txt -j 3")).unwrap(),
+            args("storm --input-file urls.txt -c 3")
+        );
+
+        let err = translate("wget", &args("--mirror https://x")).unwrap_err();
+        assert!(err.to_string().contains("Unknown wget option '--mirror'"));
+        let err = translate("curl", &args("-k https://x")).unwrap_err();
+        assert!(err.to_string().contains("no storm equivalent"));
+    }
+
+    #[test]
+    fn test_detect() {
+        assert!(detect(&args("storm https://x")).is_none());
+        assert_eq!(
+            detect(&args("/usr/local/bin/wget -q https://x"))
+                .unwrap()
+                .unwrap(),
+            args("storm -q https://x")
+        );
+        assert_eq!(
+            detect(&args("storm --compat curl -O https://x"))
+                .unwrap()
+                .unwrap(),
+            args("storm https://x")
+        );
+    }
+}
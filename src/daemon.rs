@@ -0,0 +1,248 @@
+use crate::aria2::Aria2Rpc;
+use crate::config::Config;
+use crate::events::EventStream;
+use crate::orchestrator;
+use crate::rest;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::upgrade::Upgraded;
+use hyper::{HeaderMap, Method, Request, Response, StatusCode, header};
+use hyper_util::rt::TokioIo;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+
+pub struct DaemonArgs {
+    pub port: u16,
+    pub listen_all: bool,
+    pub secret: Option<String>,
+    pub dir: Option<String>,
+    pub listen: Option<SocketAddr>,
+    pub token: Option<String>,
+}
+
+pub fn run(args: DaemonArgs) -> Result<()> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(serve(args))
+}
+
+async fn serve(args: DaemonArgs) -> Result<()> {
+    let config = Config::load();
+    let (cmd_tx, cmd_rx) = flume::unbounded();
+    let (event_tx, event_rx) = flume::unbounded();
+    tokio::spawn(orchestrator::run(cmd_rx, event_tx, config));
+
+    let default_dir = args
+        .dir
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")));
+    let rpc = Aria2Rpc::new(cmd_tx, args.secret, default_dir);
+
+    let events = EventStream::new();
+    let state = rpc.clone();
+    let stream = events.clone();
+    tokio::spawn(async move {
+        while let Ok(event) = event_rx.recv_async().await {
+            stream.publish(&event);
+            state.apply(event);
+        }
+    });
+
+    if let Some(addr) = args.listen {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen on {}", addr))?;
+        let token = match args.token.clone() {
+            Some(token) => token,
+            None => {
+                let token = generate_token()?;
+                eprintln!("REST API token: {}", token);
+                token
+            }
+        };
+        eprintln!(
+            "REST API listening on http://{0}/downloads, events on ws://{0}/events",
+            addr
+        );
+        let rpc = rpc.clone();
+        tokio::spawn(accept(listener, move |req| {
+            let rpc = rpc.clone();
+            let events = events.clone();
+            let token = token.clone();
+            async move { rest::handle(rpc, events, &token, req).await }
+        }));
+    }
+
+    let ip = if args.listen_all {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+    let addr = SocketAddr::from((ip, args.port));
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    eprintln!(
+        "aria2-compatible JSON-RPC listening on http://{0}/jsonrpc and ws://{0}/jsonrpc",
+        addr
+    );
+
+    accept(listener, move |req| handle(rpc.clone(), req)).await
+}
+
+// A REST API started without --token still needs one; print it so the
+// user can copy it into their scripts.
+fn generate_token() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("No system randomness for a REST API token"))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+async fn accept<F, Fut>(listener: TcpListener, handler: F) -> Result<()>
+where
+    F: Fn(Request<Incoming>) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Response<Full<Bytes>>> + Send + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let response = handler(req);
+                async move { Ok::<_, hyper::Error>(response.await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades()
+                .await
+            {
+                tracing::debug!("RPC connection error: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(rpc: Aria2Rpc, mut req: Request<Incoming>) -> Response<Full<Bytes>> {
+    if req.uri().path() != "/jsonrpc" {
+        return respond(StatusCode::NOT_FOUND, Bytes::new());
+    }
+
+    match *req.method() {
+        Method::OPTIONS => respond(StatusCode::NO_CONTENT, Bytes::new()),
+        Method::GET if is_websocket(req.headers()) => {
+            upgrade(&mut req, move |ws| serve_websocket(rpc, ws))
+        }
+        Method::POST => {
+            let body = match req.into_body().collect().await {
+                Ok(body) => body.to_bytes(),
+                Err(_) => return respond(StatusCode::BAD_REQUEST, Bytes::new()),
+            };
+            let response = dispatch(&rpc, &body).await;
+            respond(StatusCode::OK, Bytes::from(response.to_string()))
+        }
+        _ => respond(StatusCode::METHOD_NOT_ALLOWED, Bytes::new()),
+    }
+}
+
+async fn dispatch(rpc: &Aria2Rpc, body: &[u8]) -> Value {
+    match serde_json::from_slice(body) {
+        Ok(request) => rpc.handle(request).await,
+        Err(e) => serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32700, "message": format!("Parse error: {}", e) },
+        }),
+    }
+}
+
+pub fn is_websocket(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+pub fn upgrade<F, Fut>(req: &mut Request<Incoming>, serve: F) -> Response<Full<Bytes>>
+where
+    F: FnOnce(WebSocketStream<TokioIo<Upgraded>>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let Some(key) = req.headers().get(header::SEC_WEBSOCKET_KEY) else {
+        return respond(StatusCode::BAD_REQUEST, Bytes::new());
+    };
+    let accept = derive_accept_key(key.as_bytes());
+
+    let upgrade = hyper::upgrade::on(req);
+    tokio::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => {
+                let ws =
+                    WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None)
+                        .await;
+                serve(ws).await;
+            }
+            Err(e) => tracing::debug!("WebSocket upgrade failed: {}", e),
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Full::new(Bytes::new()))
+        .expect("static response parts are valid")
+}
+
+async fn serve_websocket(rpc: Aria2Rpc, ws: WebSocketStream<TokioIo<Upgraded>>) {
+    let (mut sink, mut stream) = ws.split();
+    let mut notifications = rpc.subscribe();
+
+    loop {
+        let outgoing = tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => dispatch(&rpc, text.as_bytes()).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            notification = notifications.recv() => match notification {
+                Ok(notification) => notification,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+        };
+        if sink
+            .send(Message::text(outgoing.to_string()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+fn respond(status: StatusCode, body: Bytes) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json-rpc")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "POST, OPTIONS")
+        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type")
+        .body(Full::new(body))
+        .expect("static response parts are valid")
+}
@@ -0,0 +1,275 @@
+use crate::stream::{Mux, Part, Quality, Queue, fetch_manifest};
+use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::time::Duration;
+use stormdl_protocol::{
+    AdaptationSet, ContentKind, Fmp4Muxer, HttpDownloader, Mpd, Representation, SegmentRef,
+};
+use url::Url;
+
+// The representations to download from a DASH manifest: a video one picked
+// by quality and the best audio, muxed into one file when they come apart.
+// Subtitle tracks are left out.
+pub(crate) struct DashStream {
+    tracks: Vec<Representation>,
+    duration: Option<Duration>,
+}
+
+impl DashStream {
+    pub(crate) async fn open(
+        downloader: &HttpDownloader,
+        url: &Url,
+        quality: Quality,
+        quiet: bool,
+    ) -> Result<Self> {
+        let text = fetch_manifest(downloader, url).await?;
+        let mpd = Mpd::parse(&text, url).with_context(|| format!("Failed to read {}", url))?;
+        Self::choose(mpd, quality, quiet)
+    }
+
+    fn choose(mpd: Mpd, quality: Quality, quiet: bool) -> Result<Self> {
+        let mut sets = mpd.adaptation_sets;
+        sets.retain(|set| !set.representations.is_empty());
+        let video = pick(&sets, ContentKind::Video, quality)?;
+        let audio_quality = match quality {
+            Quality::Worst => Quality::Worst,
+            _ => Quality::Best,
+        };
+        let audio = pick(&sets, ContentKind::Audio, audio_quality)?;
+
+        let mut tracks = Vec::new();
+        if let Some((video, count)) = video {
+            if !quiet {
+                eprintln!(
+                    "Video: {} ({}, {} available)",
+                    describe(&video),
+                    quality,
+                    count
+                );
+            }
+            tracks.push(video);
+        }
+        if let Some((audio, _)) = audio {
+            match tracks.first() {
+                Some(video) if !(is_mp4(video) && is_mp4(&audio)) => {
+                    if !quiet {
+                        eprintln!(
+                            "Warning: only MP4 tracks can be muxed; saving the video without its audio"
+                        );
+                    }
+                }
+                _ => {
+                    if !quiet {
+                        eprintln!("Audio: {}", describe(&audio));
+                    }
+                    tracks.push(audio);
+                }
+            }
+        }
+        if tracks.is_empty() {
+            // Sets without a content type: take them as one muxed stream.
+            let (other, _) = pick(&sets, ContentKind::Other, quality)?
+                .context("DASH manifest has no video or audio to download")?;
+            tracks.push(other);
+        }
+        Ok(Self {
+            tracks,
+            duration: mpd.duration,
+        })
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self.mime() {
+            "video/webm" | "audio/webm" => "webm",
+            "audio/mp4" => "m4a",
+            _ => "mp4",
+        }
+    }
+
+    pub(crate) fn mime(&self) -> &'static str {
+        let webm = self.tracks[0]
+            .mime_type
+            .as_deref()
+            .is_some_and(|mime| mime.ends_with("/webm"));
+        let audio_only = self.tracks.len() == 1
+            && self.tracks[0]
+                .mime_type
+                .as_deref()
+                .is_some_and(|mime| mime.starts_with("audio/"));
+        match (webm, audio_only) {
+            (true, true) => "audio/webm",
+            (true, false) => "video/webm",
+            (false, true) => "audio/mp4",
+            (false, false) => "video/mp4",
+        }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let segments: usize = self.tracks.iter().map(|t| t.segments.len()).sum();
+        match self.duration {
+            Some(duration) => format!(
+                "DASH, {} segments, {}",
+                segments,
+                stormdl_core::format_duration(duration)
+            ),
+            None => format!("DASH, {} segments", segments),
+        }
+    }
+
+    pub(crate) fn mux(&self) -> Mux {
+        if self.muxed() {
+            Mux::Mp4(Box::new(Fmp4Muxer::new(self.tracks.len())))
+        } else {
+            Mux::Concat
+        }
+    }
+
+    fn muxed(&self) -> bool {
+        self.tracks.iter().all(is_mp4)
+    }
+
+    // Every track's initialization first, then the media segments
+    // interleaved by how far into its track each one is.
+    fn parts(self) -> Vec<Part> {
+        let muxed = self.muxed();
+        let mut inits = Vec::new();
+        let mut media = Vec::new();
+        for (track, representation) in self.tracks.into_iter().enumerate() {
+            let count = representation.segments.len();
+            // A SegmentBase header also starts the file fetched whole; only
+            // the muxer, which keeps the first, wants it early.
+            let init = representation.init.filter(|init| {
+                muxed
+                    || !representation
+                        .segments
+                        .iter()
+                        .any(|s| s.uri == init.uri && s.range.is_none())
+            });
+            if let Some(init) = init {
+                inits.push(part(init, track));
+            }
+            for (i, segment) in representation.segments.into_iter().enumerate() {
+                media.push((i, count, part(segment, track)));
+            }
+        }
+        media.sort_by(|(a, a_count, a_part), (b, b_count, b_part)| {
+            match (a * b_count).cmp(&(b * a_count)) {
+                Ordering::Equal => a_part.track.cmp(&b_part.track),
+                order => order,
+            }
+        });
+        inits.extend(media.into_iter().map(|(_, _, part)| part));
+        inits
+    }
+}
+
+pub(crate) async fn produce(stream: DashStream, queue: Queue) -> Result<()> {
+    for part in stream.parts() {
+        if !queue.push(part).await? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// The representation `quality` picks among every set of a kind, and how
+// many there were to pick from.
+fn pick(
+    sets: &[AdaptationSet],
+    kind: ContentKind,
+    quality: Quality,
+) -> Result<Option<(Representation, usize)>> {
+    let sets: Vec<&AdaptationSet> = sets.iter().filter(|set| set.kind == kind).collect();
+    let open: Vec<Representation> = sets
+        .iter()
+        .filter(|set| !set.protected)
+        .flat_map(|set| set.representations.iter().cloned())
+        .collect();
+    if open.is_empty() && !sets.is_empty() {
+        anyhow::bail!("The DASH manifest's streams are DRM-protected");
+    }
+    let count = open.len();
+    Ok(quality
+        .pick(open, |rep| (rep.height, rep.bandwidth))
+        .map(|rep| (rep, count)))
+}
+
+fn part(segment: SegmentRef, track: usize) -> Part {
+    Part {
+        uri: segment.uri,
+        range: segment.range,
+        key: None,
+        track,
+    }
+}
+
+fn is_mp4(representation: &Representation) -> bool {
+    representation
+        .mime_type
+        .as_deref()
+        .is_some_and(|mime| mime.ends_with("/mp4"))
+}
+
+fn describe(representation: &Representation) -> String {
+    let mut parts = Vec::new();
+    if let (Some(width), Some(height)) = (representation.width, representation.height) {
+        parts.push(format!("{}x{}", width, height));
+    }
+    parts.push(format!("{} kbit/s", representation.bandwidth / 1000));
+    if let Some(codecs) = &representation.codecs {
+        parts.push(codecs.clone());
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose() {
+        let xml = r#"<MPD mediaPresentationDuration="PT8S"><Period>
+  <AdaptationSet mimeType="video/mp4">
+    <SegmentTemplate duration="4" initialization="$RepresentationID$-init.mp4" media="$RepresentationID$-$Number$.m4s"/>
+    <Representation id="v1080" bandwidth="6000000" height="1080"/>
+    <Representation id="v480" bandwidth="1000000" height="480"/>
+  </AdaptationSet>
+  <AdaptationSet mimeType="audio/mp4">
+    <SegmentTemplate duration="2" initialization="$RepresentationID$-init.mp4" media="$RepresentationID$-$Number$.m4s"/>
+    <Representation id="a64" bandwidth="64000"/>
+    <Representation id="a128" bandwidth="128000"/>
+  </AdaptationSet>
+  <AdaptationSet mimeType="video/webm">
+    <ContentProtection/>
+    <Representation id="drm" bandwidth="9000000" height="2160"/>
+  </AdaptationSet>
+</Period></MPD>"#;
+        let mpd = || Mpd::parse(xml, &Url::parse("https://example.com/v/").unwrap()).unwrap();
+        let stream = DashStream::choose(mpd(), "720p".parse().unwrap(), true).unwrap();
+        assert_eq!(stream.mime(), "video/mp4");
+        assert!(matches!(stream.mux(), Mux::Mp4(_)));
+        let names: Vec<String> = stream
+            .parts()
+            .iter()
+            .map(|part| format!("{} {}", part.track, part.uri.path()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "0 /v/v480-init.mp4",
+                "1 /v/a128-init.mp4",
+                "0 /v/v480-1.m4s",
+                "1 /v/a128-1.m4s",
+                "1 /v/a128-2.m4s",
+                "0 /v/v480-2.m4s",
+                "1 /v/a128-3.m4s",
+                "1 /v/a128-4.m4s",
+            ]
+        );
+
+        let worst = DashStream::choose(mpd(), Quality::Worst, true).unwrap();
+        let ids: Vec<&str> = worst.tracks.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["v480", "a64"]);
+        assert_eq!(worst.describe(), "DASH, 6 segments, 8s");
+    }
+}
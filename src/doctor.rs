@@ -0,0 +1,79 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use stormdl_core::HttpVersion;
+use stormdl_manifest::ProtocolStats;
+use stormdl_protocol::Downgrade;
+
+const VERSIONS: [HttpVersion; 3] = [HttpVersion::Http3, HttpVersion::Http2, HttpVersion::Http1_1];
+
+pub fn run() -> Result<()> {
+    let manifest = Config::open_manifest().context("No download manifest found")?;
+    let stats = manifest.protocol_stats()?;
+    if stats.is_empty() {
+        println!("No connections recorded yet; run a download first");
+        return Ok(());
+    }
+
+    let mut hosts: BTreeMap<&str, Vec<&ProtocolStats>> = BTreeMap::new();
+    for entry in &stats {
+        hosts.entry(&entry.host).or_default().push(entry);
+    }
+
+    println!("Hosts:");
+    for (host, entries) in &hosts {
+        let observed: Vec<String> = entries
+            .iter()
+            .rev()
+            .map(|s| match (s.successes, s.downgrades) {
+                (ok, 0) => format!("{} ok ({})", s.version.as_str(), ok),
+                (0, failed) => format!("{} failed ({})", s.version.as_str(), failed),
+                (ok, failed) => format!("{} ok ({}), failed ({})", s.version.as_str(), ok, failed),
+            })
+            .collect();
+        println!("  {:<40} {}", host, observed.join(", "));
+    }
+
+    println!();
+    println!("Protocols:");
+    let mut hints = Vec::new();
+    for version in VERSIONS {
+        let entries: Vec<_> = stats.iter().filter(|s| s.version == version).collect();
+        if entries.is_empty() {
+            println!("  {:<9} not tried", version.as_str());
+            continue;
+        }
+        let working = entries.iter().filter(|s| s.successes > 0).count();
+        let failing = entries.iter().filter(|s| s.downgrades > 0).count();
+        println!(
+            "  {:<9} works on {} host(s), fell back on {}",
+            version.as_str(),
+            working,
+            failing
+        );
+
+        if working == 0 && failing > 0 {
+            let last = entries
+                .iter()
+                .filter(|s| s.last_error.is_some())
+                .max_by(|a, b| a.updated_at.cmp(&b.updated_at))
+                .and_then(|s| s.last_error.as_deref())
+                .unwrap_or("unknown error");
+            hints.push(format!(
+                "{} has never succeeded ({}); last error: {}",
+                version.as_str(),
+                Downgrade::hint(version),
+                last
+            ));
+        }
+    }
+
+    if !hints.is_empty() {
+        println!();
+        for hint in hints {
+            println!("hint: {}", hint);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,464 @@
+use crate::config::Config;
+use crate::orchestrator::{self, DownloadEvent, OrchestratorCommand};
+use flume::Sender;
+use futures_util::Stream;
+use std::future::{Future, IntoFuture};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use stormdl_core::{
+    Credentials, DownloadId, DownloadOptions, DownloadProgress, DownloadState, Priority,
+    ProgressReporter, SegmentState, StormError,
+};
+use stormdl_integrity::ChecksumSpec;
+use stormdl_protocol::ProxyConfig;
+use tokio::sync::watch;
+use url::Url;
+
+pub struct Download;
+
+impl Download {
+    pub fn builder(url: Url) -> DownloadBuilder {
+        DownloadBuilder::new(url)
+    }
+}
+
+pub struct DownloadBuilder {
+    options: DownloadOptions,
+    config: Option<Config>,
+    reporter: Option<Arc<dyn ProgressReporter>>,
+}
+
+impl DownloadBuilder {
+    pub fn new(url: Url) -> Self {
+        Self {
+            options: DownloadOptions {
+                url,
+                output_dir: PathBuf::from("."),
+                filename: None,
+                segments: None,
+                priority: Priority::Normal,
+                bandwidth_limit: None,
+                headers: Vec::new(),
+                checksum: None,
+                group: None,
+                mirrors: Vec::new(),
+                tags: Vec::new(),
+                note: None,
+                proxy: None,
+                auth: None,
+            },
+            config: None,
+            reporter: None,
+        }
+    }
+
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.output_dir = dir.into();
+        self
+    }
+
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.options.filename = Some(filename.into());
+        self
+    }
+
+    pub fn segments(mut self, segments: usize) -> Self {
+        self.options.segments = Some(segments);
+        self
+    }
+
+    pub fn mirrors(mut self, mirrors: impl IntoIterator<Item = Url>) -> Self {
+        self.options.mirrors.extend(mirrors);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.options.proxy = Some(proxy.into());
+        self
+    }
+
+    // Only sent to the download's own host, never to mirrors.
+    pub fn basic_auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.options.auth = Some(Credentials::Basic {
+            user: user.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    pub fn digest_auth(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.options.auth = Some(Credentials::Digest {
+            user: user.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: impl Into<String>) -> Self {
+        self.options.auth = Some(Credentials::Bearer(token.into()));
+        self
+    }
+
+    pub fn checksum(mut self, checksum: impl Into<String>) -> Self {
+        self.options.checksum = Some(checksum.into());
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.options.priority = priority;
+        self
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.options.group = Some(group.into());
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn reporter(mut self, reporter: Arc<dyn ProgressReporter>) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    pub async fn start(self) -> Result<DownloadHandle, StormError> {
+        if let Some(checksum) = &self.options.checksum {
+            ChecksumSpec::parse(checksum)?;
+        }
+        if let Some(proxy) = &self.options.proxy {
+            ProxyConfig::new(proxy)?;
+        }
+        let config = self.config.unwrap_or_else(Config::load);
+        let (cmd_tx, cmd_rx) = flume::unbounded();
+        let (event_tx, event_rx) = flume::unbounded();
+        tokio::spawn(orchestrator::run(cmd_rx, event_tx, config));
+
+        let url = self.options.url.clone();
+        cmd_tx
+            .send(OrchestratorCommand::AddDownload {
+                url,
+                options: self.options,
+            })
+            .map_err(|_| engine_stopped())?;
+
+        let mut progress = loop {
+            match event_rx.recv_async().await {
+                Ok(DownloadEvent::DownloadAdded {
+                    id,
+                    filename,
+                    total_size,
+                    ..
+                }) => break Progress::new(id, filename, total_size),
+                Ok(_) => continue,
+                Err(_) => return Err(engine_stopped()),
+            }
+        };
+        let id = progress.id;
+        let (progress_tx, progress_rx) = watch::channel(progress.clone());
+        let reporter = self.reporter;
+        tokio::spawn(async move {
+            while let Ok(event) = event_rx.recv_async().await {
+                if progress.apply(&event) {
+                    if let Some(reporter) = &reporter {
+                        reporter.report(progress.snapshot());
+                    }
+                    progress_tx.send_replace(progress.clone());
+                }
+            }
+        });
+
+        Ok(DownloadHandle {
+            id,
+            cmd_tx,
+            progress: progress_rx,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub id: DownloadId,
+    pub filename: String,
+    pub state: DownloadState,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    pub speed: f64,
+    pub segments: Vec<SegmentState>,
+    pub path: Option<PathBuf>,
+    pub hash: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Progress {
+    fn new(id: DownloadId, filename: String, total: Option<u64>) -> Self {
+        Self {
+            id,
+            filename,
+            state: DownloadState::Pending,
+            downloaded: 0,
+            total,
+            speed: 0.0,
+            segments: Vec::new(),
+            path: None,
+            hash: None,
+            error: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            DownloadState::Complete
+                | DownloadState::Failed
+                | DownloadState::Quarantined
+                | DownloadState::Cancelled
+        )
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total?.saturating_sub(self.downloaded);
+        (self.speed > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / self.speed))
+    }
+
+    pub fn snapshot(&self) -> DownloadProgress {
+        DownloadProgress {
+            id: self.id,
+            downloaded: self.downloaded,
+            total: self.total,
+            speed: self.speed,
+            eta: self.eta(),
+            segments: self.segments.clone(),
+            state: self.state,
+        }
+    }
+
+    fn apply(&mut self, event: &DownloadEvent) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        match event {
+            DownloadEvent::DownloadAdded {
+                id,
+                filename,
+                total_size,
+                ..
+            } if *id == self.id => {
+                self.filename = filename.clone();
+                self.total = *total_size;
+            }
+            DownloadEvent::ProgressUpdate {
+                id,
+                downloaded,
+                segments,
+            } if *id == self.id => {
+                self.downloaded = *downloaded;
+                for segment in segments {
+                    match self.segments.iter_mut().find(|s| s.id == segment.id) {
+                        Some(known) => *known = segment.clone(),
+                        None => self.segments.push(segment.clone()),
+                    }
+                }
+            }
+            DownloadEvent::SpeedUpdate { id, speed } if *id == self.id => self.speed = *speed,
+            DownloadEvent::StateChange { id, state } if *id == self.id => self.state = *state,
+            DownloadEvent::Error { id, error } if *id == self.id => {
+                self.state = DownloadState::Failed;
+                self.error = Some(error.clone());
+            }
+            DownloadEvent::Complete { id, path, hash } if *id == self.id => {
+                self.state = DownloadState::Complete;
+                self.speed = 0.0;
+                self.path = Some(path.clone());
+                self.hash = (!hash.is_empty()).then(|| hash.clone());
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+pub struct DownloadHandle {
+    id: DownloadId,
+    cmd_tx: Sender<OrchestratorCommand>,
+    progress: watch::Receiver<Progress>,
+}
+
+impl DownloadHandle {
+    pub fn id(&self) -> DownloadId {
+        self.id
+    }
+
+    pub fn pause(&self) -> Result<(), StormError> {
+        self.send(OrchestratorCommand::PauseDownload(self.id))
+    }
+
+    pub fn resume(&self) -> Result<(), StormError> {
+        self.send(OrchestratorCommand::ResumeDownload(self.id))
+    }
+
+    pub fn cancel(&self) -> Result<(), StormError> {
+        self.send(OrchestratorCommand::CancelDownload(self.id))
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress.borrow().clone()
+    }
+
+    pub fn watch(&self) -> watch::Receiver<Progress> {
+        self.progress.clone()
+    }
+
+    pub fn progress_stream(&self) -> impl Stream<Item = DownloadProgress> + Send + Unpin + 'static {
+        let mut progress = self.progress.clone();
+        progress.mark_changed();
+        Box::pin(futures_util::stream::unfold(
+            (progress, false),
+            |(mut progress, finished)| async move {
+                if finished {
+                    return None;
+                }
+                progress.changed().await.ok()?;
+                let current = progress.borrow_and_update().clone();
+                let finished = current.is_finished();
+                Some((current.snapshot(), (progress, finished)))
+            },
+        ))
+    }
+
+    pub async fn wait(&self) -> Result<PathBuf, StormError> {
+        let mut progress = self.progress.clone();
+        let progress = progress
+            .wait_for(Progress::is_finished)
+            .await
+            .map_err(|_| engine_stopped())?
+            .clone();
+
+        match progress.state {
+            DownloadState::Complete => Ok(progress.path.unwrap_or_default()),
+            DownloadState::Cancelled => Err(StormError::Cancelled),
+            _ => Err(StormError::Other(
+                progress
+                    .error
+                    .unwrap_or_else(|| "Download failed".to_string()),
+            )),
+        }
+    }
+
+    fn send(&self, command: OrchestratorCommand) -> Result<(), StormError> {
+        self.cmd_tx.send(command).map_err(|_| engine_stopped())
+    }
+}
+
+impl IntoFuture for DownloadHandle {
+    type Output = Result<PathBuf, StormError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { self.wait().await })
+    }
+}
+
+fn engine_stopped() -> StormError {
+    StormError::Other("Download engine stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_tracks_events() {
+        let id = DownloadId(7);
+        let mut progress = Progress::new(id, "file.bin".to_string(), None);
+
+        assert!(progress.apply(&DownloadEvent::DownloadAdded {
+            id,
+            url: Url::parse("https://example.com/file.bin").unwrap(),
+            filename: "file.bin".to_string(),
+            total_size: Some(1000),
+            group: None,
+        }));
+        assert!(progress.apply(&DownloadEvent::ProgressUpdate {
+            id,
+            downloaded: 400,
+            segments: Vec::new(),
+        }));
+        assert!(!progress.apply(&DownloadEvent::SpeedUpdate {
+            id: DownloadId(8),
+            speed: 5.0,
+        }));
+        assert_eq!(progress.total, Some(1000));
+        assert_eq!(progress.downloaded, 400);
+        assert!(!progress.is_finished());
+
+        assert!(progress.apply(&DownloadEvent::Complete {
+            id,
+            path: PathBuf::from("file.bin"),
+            hash: String::new(),
+        }));
+        assert!(!progress.apply(&DownloadEvent::StateChange {
+            id,
+            state: DownloadState::Cancelled,
+        }));
+        assert_eq!(progress.state, DownloadState::Complete);
+        assert_eq!(progress.hash, None);
+    }
+
+    #[tokio::test]
+    async fn test_progress_stream_ends_when_finished() {
+        use futures_util::StreamExt;
+
+        let id = DownloadId(9);
+        let mut progress = Progress::new(id, "file.bin".to_string(), Some(100));
+        let (progress_tx, progress_rx) = watch::channel(progress.clone());
+        let handle = DownloadHandle {
+            id,
+            cmd_tx: flume::unbounded().0,
+            progress: progress_rx,
+        };
+        let stream = handle.progress_stream();
+
+        progress.downloaded = 50;
+        progress.speed = 25.0;
+        progress_tx.send_replace(progress.clone());
+        progress.state = DownloadState::Complete;
+        progress_tx.send_replace(progress.clone());
+        progress_tx.send_replace(progress);
+
+        let updates: Vec<DownloadProgress> = stream.collect().await;
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].state, DownloadState::Complete);
+        assert_eq!(updates[0].downloaded, 50);
+    }
+
+    // A rate limit used to block inside the write path, which panics on a
+    // current-thread runtime.
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_limited_download_on_current_thread() {
+        let body: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        let url = stormdl_protocol::test_server(body.clone()).await.unwrap();
+        let dir = std::env::temp_dir().join(format!("storm-builder-{}", std::process::id()));
+        Config::use_data_dir(dir.join("data"));
+        let mut config = Config::default();
+        config.restrictions.max_speed = Some(128 * 1024);
+
+        let handle = Download::builder(url)
+            .output_dir(&dir)
+            .segments(2)
+            .config(config)
+            .start()
+            .await
+            .unwrap();
+        let path = tokio::time::timeout(Duration::from_secs(30), handle.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), body);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,65 @@
+use crate::orchestrator::DownloadEvent;
+use futures_util::{SinkExt, StreamExt};
+use hyper::upgrade::Upgraded;
+use hyper_util::rt::TokioIo;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Clone)]
+pub struct EventStream {
+    tx: broadcast::Sender<Arc<str>>,
+}
+
+impl EventStream {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(1024).0,
+        }
+    }
+
+    pub fn publish(&self, event: &DownloadEvent) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        match serde_json::to_string(event) {
+            Ok(json) => {
+                let _ = self.tx.send(json.into());
+            }
+            Err(e) => tracing::debug!("Failed to serialize {:?}: {}", event, e),
+        }
+    }
+
+    pub async fn serve(self, ws: WebSocketStream<TokioIo<Upgraded>>) {
+        let (mut sink, mut stream) = ws.split();
+        let mut events = self.tx.subscribe();
+
+        loop {
+            let event = tokio::select! {
+                message = stream.next() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                },
+                event = events.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::debug!("Event stream client lagged by {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            if sink.send(Message::text(event.as_ref())).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
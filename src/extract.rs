@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use stormdl_core::StormError;
+use stormdl_integrity::StreamVerifier;
+use tokio::task::JoinHandle;
+
+// Unpacks a downloaded archive into DEST. With a checksum, tar archives are
+// unpacked from the hashed stream while the download runs, into a staging
+// directory that only replaces anything in DEST once the digest matched.
+pub struct Extraction {
+    dest: PathBuf,
+    staging: PathBuf,
+    streaming: Option<JoinHandle<Result<Option<usize>, StormError>>>,
+}
+
+impl Extraction {
+    pub fn start(archive: &Path, dest: Option<PathBuf>, verifier: Option<&StreamVerifier>) -> Self {
+        let dest = dest.unwrap_or_else(|| default_dest(archive));
+        let name = dest
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "extract".to_string());
+        let staging = dest.with_file_name(format!(".{}.storm-extract", name));
+        let _ = std::fs::remove_dir_all(&staging);
+
+        let streaming = verifier.and_then(StreamVerifier::tap).map(|mut tap| {
+            let staging = staging.clone();
+            tokio::task::spawn_blocking(move || {
+                // The rest of the stream is read too, so the task only
+                // succeeds once the whole file was verified.
+                let result = stormdl_io::extract_tar(&mut tap, &staging).and_then(|files| {
+                    if files.is_some() {
+                        std::io::copy(&mut tap, &mut std::io::sink())?;
+                    }
+                    Ok(files)
+                });
+                if !matches!(result, Ok(Some(_))) {
+                    let _ = std::fs::remove_dir_all(&staging);
+                }
+                result
+            })
+        });
+        Self {
+            dest,
+            staging,
+            streaming,
+        }
+    }
+
+    pub fn dest(&self) -> &Path {
+        &self.dest
+    }
+
+    // Call once the archive is verified and scanned. Zips, and tars whose
+    // stream was cut off (a rewound segment, a slow disk), are unpacked from
+    // the finished file instead.
+    pub async fn finish(mut self, archive: &Path) -> Result<usize> {
+        let streamed = match self.streaming.take() {
+            Some(task) => match task.await? {
+                Ok(files) => files,
+                Err(e) => {
+                    tracing::debug!("Extracting while downloading stopped: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let files = match streamed {
+            Some(files) => files,
+            None => {
+                let _ = std::fs::remove_dir_all(&self.staging);
+                let (path, staging) = (archive.to_path_buf(), self.staging.clone());
+                tokio::task::spawn_blocking(move || stormdl_io::extract_archive(&path, &staging))
+                    .await?
+                    .with_context(|| format!("Failed to extract {}", archive.display()))?
+                    .with_context(|| format!("{} is not a zip or tar archive", archive.display()))?
+            }
+        };
+
+        let (staging, dest) = (self.staging.clone(), self.dest.clone());
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&staging)?;
+            merge(&staging, &dest)
+        })
+        .await?
+        .with_context(|| {
+            format!(
+                "Failed to move extracted files into {}",
+                self.dest.display()
+            )
+        })?;
+        Ok(files)
+    }
+}
+
+impl Drop for Extraction {
+    fn drop(&mut self) {
+        // A streaming task clears its own staging once the tap closes.
+        if self.streaming.is_none() {
+            let _ = std::fs::remove_dir_all(&self.staging);
+        }
+    }
+}
+
+// `pkg-1.2.tar.gz` unpacks into `pkg-1.2` next to it.
+fn default_dest(archive: &Path) -> PathBuf {
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lower = name.to_ascii_lowercase();
+    let stem = [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find(|ext| lower.ends_with(*ext) && lower.len() > ext.len())
+        .map(|ext| &name[..name.len() - ext.len()]);
+    let dir = match stem {
+        Some(stem) => stem.to_string(),
+        None => format!("{}.d", name),
+    };
+    archive.with_file_name(dir)
+}
+
+// Moves the staged tree into `dest`, merging with directories already there
+// and replacing files of the same name.
+fn merge(from: &Path, dest: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let source_dir = entry.file_type()?.is_dir();
+        match std::fs::symlink_metadata(&target) {
+            Ok(meta) if meta.is_dir() && source_dir => {
+                merge(&entry.path(), &target)?;
+                continue;
+            }
+            Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(&target)?,
+            Ok(_) => std::fs::remove_file(&target)?,
+            Err(_) => {}
+        }
+        std::fs::rename(entry.path(), &target)?;
+    }
+    std::fs::remove_dir_all(from)
+}
@@ -0,0 +1,254 @@
+use crate::stream::{Part, PartKey, Quality, Queue, fetch_manifest};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use stormdl_protocol::{
+    HttpDownloader, InitSection, MediaPlaylist, MediaSegment, Playlist, Variant,
+};
+use url::Url;
+
+// A live playlist that can't be reloaded this many times in a row has ended.
+const REFRESH_FAILURES: u32 = 5;
+
+// A media playlist to download, chosen from a master playlist's variants
+// when it has them.
+pub(crate) struct HlsStream {
+    url: Url,
+    playlist: MediaPlaylist,
+}
+
+impl HlsStream {
+    pub(crate) async fn open(
+        downloader: &HttpDownloader,
+        url: &Url,
+        quality: Quality,
+        quiet: bool,
+    ) -> Result<Self> {
+        let variants = match fetch_playlist(downloader, url).await? {
+            Playlist::Media(playlist) => {
+                return Ok(Self {
+                    url: url.clone(),
+                    playlist,
+                });
+            }
+            Playlist::Master(variants) => variants,
+        };
+        let count = variants.len();
+        let variant = quality
+            .pick(variants, |variant| {
+                (
+                    variant.resolution.map(|(_, height)| height),
+                    variant.bandwidth,
+                )
+            })
+            .context("HLS master playlist lists no variants")?;
+        if !quiet {
+            eprintln!(
+                "Variant: {} ({}, {} available)",
+                describe_variant(&variant),
+                quality,
+                count
+            );
+        }
+        match fetch_playlist(downloader, &variant.uri).await? {
+            Playlist::Media(playlist) => Ok(Self {
+                url: variant.uri,
+                playlist,
+            }),
+            Playlist::Master(_) => {
+                anyhow::bail!("{} is a master playlist, not a variant", variant.uri)
+            }
+        }
+    }
+
+    pub(crate) fn is_live(&self) -> bool {
+        !self.playlist.ended
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        if self.playlist.is_fragmented_mp4() {
+            "mp4"
+        } else {
+            "ts"
+        }
+    }
+
+    pub(crate) fn mime(&self) -> &'static str {
+        if self.playlist.is_fragmented_mp4() {
+            "video/mp4"
+        } else {
+            "video/mp2t"
+        }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let segments = self.playlist.segments.len();
+        if self.is_live() {
+            format!("live HLS, {} segments in the playlist", segments)
+        } else {
+            format!(
+                "HLS, {} segments, {}",
+                segments,
+                stormdl_core::format_duration(self.playlist.duration())
+            )
+        }
+    }
+}
+
+fn describe_variant(variant: &Variant) -> String {
+    let mut parts = Vec::new();
+    if let Some((width, height)) = variant.resolution {
+        parts.push(format!("{}x{}", width, height));
+    }
+    parts.push(format!("{} kbit/s", variant.bandwidth / 1000));
+    if let Some(codecs) = &variant.codecs {
+        parts.push(codecs.clone());
+    }
+    parts.join(", ")
+}
+
+async fn fetch_playlist(downloader: &HttpDownloader, url: &Url) -> Result<Playlist> {
+    let text = fetch_manifest(downloader, url).await?;
+    Playlist::parse(&text, url).with_context(|| format!("Failed to read {}", url))
+}
+
+// Queues the playlist's segments, reloading a live one for more until it
+// ends or the recording stops.
+pub(crate) async fn produce(
+    stream: HlsStream,
+    downloader: Arc<HttpDownloader>,
+    queue: Queue,
+    quiet: bool,
+) -> Result<()> {
+    let mut playlist = stream.playlist;
+    let mut last: Option<u64> = None;
+    let mut map: Option<InitSection> = None;
+    let mut failures = 0;
+    loop {
+        let mut added = false;
+        for segment in playlist.segments {
+            if last.is_some_and(|last| segment.sequence <= last) {
+                continue;
+            }
+            if let Some(last) = last
+                && segment.sequence > last + 1
+                && !quiet
+            {
+                eprintln!(
+                    "\nWarning: {} live segment(s) left the playlist before they were fetched",
+                    segment.sequence - last - 1
+                );
+            }
+            last = Some(segment.sequence);
+            added = true;
+            // fMP4 segments only play after their initialization section,
+            // written again whenever it changes.
+            if segment.map != map {
+                map = segment.map.clone();
+                if let Some(init) = &map
+                    && !queue.push(init_part(init)).await?
+                {
+                    return Ok(());
+                }
+            }
+            if !queue.push(media_part(&segment)).await? {
+                return Ok(());
+            }
+        }
+        if playlist.ended {
+            return Ok(());
+        }
+
+        // RFC 8216 6.3.4: reload after a target duration, or half of one
+        // when the last reload had nothing new.
+        let target = playlist.target_duration.max(Duration::from_secs(1));
+        let wait = if added { target } else { target / 2 };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = queue.stopped() => return Ok(()),
+        }
+        playlist = match fetch_playlist(&downloader, &stream.url).await {
+            Ok(Playlist::Media(playlist)) => {
+                failures = 0;
+                playlist
+            }
+            Ok(Playlist::Master(_)) => {
+                anyhow::bail!("{} turned into a master playlist", stream.url)
+            }
+            Err(e) => {
+                failures += 1;
+                if failures >= REFRESH_FAILURES {
+                    return Err(e.context("Live playlist stopped answering"));
+                }
+                tracing::warn!("{:#}; reloading again", e);
+                MediaPlaylist {
+                    segments: Vec::new(),
+                    ended: false,
+                    ..playlist
+                }
+            }
+        };
+    }
+}
+
+fn init_part(init: &InitSection) -> Part {
+    Part {
+        uri: init.uri.clone(),
+        range: init.range,
+        key: None,
+        track: 0,
+    }
+}
+
+fn media_part(segment: &MediaSegment) -> Part {
+    Part {
+        uri: segment.uri.clone(),
+        range: segment.range,
+        key: segment.key.as_ref().map(|key| PartKey {
+            uri: key.uri.clone(),
+            iv: segment.iv(),
+        }),
+        track: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::Stream;
+
+    #[test]
+    fn test_file_name() {
+        let stream = |text: &str| HlsStream {
+            url: Url::parse("https://example.com/").unwrap(),
+            playlist: match Playlist::parse(text, &Url::parse("https://example.com/").unwrap()) {
+                Ok(Playlist::Media(playlist)) => playlist,
+                _ => panic!("not a media playlist"),
+            },
+        };
+        let ts = stream("#EXTM3U\n#EXTINF:4,\na.ts\n#EXT-X-ENDLIST\n");
+        let fmp4 = stream("#EXTM3U\n#EXT-X-MAP:URI=\"init.mp4\"\n#EXTINF:4,\na.m4s\n");
+        assert_eq!(ts.mime(), "video/mp2t");
+        assert!(!ts.is_live());
+        assert!(fmp4.is_live());
+
+        let ts = Stream::Hls(ts);
+        let url = |s: &str| Url::parse(s).unwrap();
+
+        assert_eq!(
+            ts.file_name(
+                &url("https://cdn.example.com/talks/keynote/index.m3u8"),
+                "index.m3u8"
+            ),
+            "keynote.ts"
+        );
+        assert_eq!(
+            ts.file_name(&url("https://cdn.example.com/movie.m3u8"), "movie.m3u8"),
+            "movie.ts"
+        );
+        assert_eq!(
+            Stream::Hls(fmp4).file_name(&url("https://cdn.example.com/master.m3u8"), "master.m3u8"),
+            "master.mp4"
+        );
+    }
+}
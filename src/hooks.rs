@@ -0,0 +1,180 @@
+use anyhow::Result;
+use std::path::Path;
+use stormdl_core::ResourceInfo;
+use url::Url;
+
+#[cfg(feature = "scripting")]
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Default)]
+pub struct Hooks {
+    #[cfg(feature = "scripting")]
+    script: Option<(Engine, AST)>,
+}
+
+impl Hooks {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("Failed to load hook script {}: {}", path.display(), e))?;
+        Ok(Self {
+            script: Some((engine, ast)),
+        })
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn load(path: &Path) -> Result<Self> {
+        let _ = MAX_OPERATIONS;
+        anyhow::bail!(
+            "Hook script {} requires storm built with the 'scripting' feature",
+            path.display()
+        )
+    }
+
+    pub fn rewrite_url(&self, url: Url) -> Result<Url> {
+        #[cfg(feature = "scripting")]
+        if let Some(rewritten) = self.call::<String>("rewrite_url", (url.to_string(),))? {
+            return Url::parse(&rewritten)
+                .map_err(|e| anyhow::anyhow!("rewrite_url returned '{}': {}", rewritten, e));
+        }
+        Ok(url)
+    }
+
+    pub fn request_headers(&self, url: &Url) -> Result<Vec<(String, String)>> {
+        #[cfg(feature = "scripting")]
+        if let Some(map) = self.call::<Map>("request_headers", (url.to_string(),))? {
+            return Ok(map
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect());
+        }
+        let _ = url;
+        Ok(Vec::new())
+    }
+
+    pub fn should_download(&self, url: &Url, info: &ResourceInfo) -> Result<bool> {
+        #[cfg(feature = "scripting")]
+        {
+            let mut details = Map::new();
+            details.insert(
+                "size".into(),
+                info.size.map_or(Dynamic::UNIT, |s| Dynamic::from(s as i64)),
+            );
+            details.insert(
+                "content_type".into(),
+                info.content_type
+                    .clone()
+                    .map_or(Dynamic::UNIT, Dynamic::from),
+            );
+            details.insert(
+                "filename".into(),
+                info.filename.clone().map_or(Dynamic::UNIT, Dynamic::from),
+            );
+            details.insert("supports_range".into(), Dynamic::from(info.supports_range));
+            if let Some(allowed) =
+                self.call::<bool>("should_download", (url.to_string(), details))?
+            {
+                return Ok(allowed);
+            }
+        }
+        let _ = (url, info);
+        Ok(true)
+    }
+
+    pub fn choose_filename(&self, url: &Url, suggested: String) -> Result<String> {
+        #[cfg(feature = "scripting")]
+        if let Some(name) =
+            self.call::<String>("choose_filename", (url.to_string(), suggested.clone()))?
+        {
+            let name = name.trim();
+            if name.is_empty() || name.contains(['/', '\\']) || name == ".." {
+                anyhow::bail!("choose_filename returned an invalid name '{}'", name);
+            }
+            return Ok(name.to_string());
+        }
+        let _ = url;
+        Ok(suggested)
+    }
+
+    #[cfg(feature = "scripting")]
+    fn call<T: Clone + Send + Sync + 'static>(
+        &self,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<Option<T>> {
+        let Some((engine, ast)) = &self.script else {
+            return Ok(None);
+        };
+        if !ast.iter_functions().any(|f| f.name == name) {
+            return Ok(None);
+        }
+        engine
+            .call_fn::<T>(&mut Scope::new(), ast, name, args)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Hook {} failed: {}", name, e))
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hooks() {
+        let path = std::env::temp_dir().join(format!("storm-hooks-{}.rhai", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            fn rewrite_url(url) { url.replace("http://", "https://"); url }
+            fn request_headers(url) { #{ "Authorization": "Bearer abc" } }
+            fn should_download(url, info) { info.size == () || info.size < 1000 }
+            fn choose_filename(url, name) { "renamed-" + name }
+            "#,
+        )
+        .unwrap();
+        let hooks = Hooks::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let url = hooks
+            .rewrite_url(Url::parse("http://example.com/a.iso").unwrap())
+            .unwrap();
+        assert_eq!(url.as_str(), "https://example.com/a.iso");
+        assert_eq!(
+            hooks.request_headers(&url).unwrap(),
+            vec![("Authorization".to_string(), "Bearer abc".to_string())]
+        );
+        assert_eq!(
+            hooks.choose_filename(&url, "a.iso".into()).unwrap(),
+            "renamed-a.iso"
+        );
+
+        let mut info = ResourceInfo {
+            url: url.clone(),
+            size: Some(10),
+            supports_range: true,
+            etag: None,
+            last_modified: None,
+            content_type: None,
+            filename: None,
+            http_version: stormdl_core::HttpVersion::Http1_1,
+            connection_rtt: None,
+        };
+        assert!(hooks.should_download(&url, &info).unwrap());
+        info.size = Some(5000);
+        assert!(!hooks.should_download(&url, &info).unwrap());
+
+        assert_eq!(
+            Hooks::none().choose_filename(&url, "x".into()).unwrap(),
+            "x"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::config::Config;
+use crate::listfile::{self, ListEntry};
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// Bumped only for changes older readers would misread; new fields can be
+// added without it.
+const VERSION: u32 = 1;
+// Journals kept in the sessions directory; older ones are removed.
+const KEEP_SESSIONS: usize = 50;
+
+// A batch's session journal: JSON lines, a `session` line for each run and a
+// `file` line for each entry as it finishes. `storm retry-failed` reruns the
+// entries whose last line isn't `ok` and appends to the same journal.
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+    // Stopped by Ctrl+C, or never started because of it.
+    Interrupted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub url: String,
+    pub dir: Option<PathBuf>,
+    pub out: Option<String>,
+    // The .metalink/.meta4 file the entry was read from.
+    pub metalink: Option<PathBuf>,
+    pub status: Status,
+    pub error: Option<String>,
+    pub finished: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line {
+    Session {
+        version: u32,
+        started: String,
+    },
+    File(Record),
+    #[serde(other)]
+    Unknown,
+}
+
+impl Journal {
+    // Appends to `path` when given, otherwise starts a new journal in the
+    // sessions directory.
+    pub fn open(path: Option<PathBuf>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                let dir = Config::sessions_dir().context("No data directory for the journal")?;
+                std::fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                prune(&dir);
+                let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+                let mut path = dir.join(format!("{}.jsonl", stamp));
+                let mut n = 1;
+                while path.exists() {
+                    n += 1;
+                    path = dir.join(format!("{}-{}.jsonl", stamp, n));
+                }
+                path
+            }
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open journal {}", path.display()))?;
+        let journal = Self {
+            path,
+            file: Mutex::new(file),
+        };
+        journal.write(&Line::Session {
+            version: VERSION,
+            started: now(),
+        })?;
+        Ok(journal)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, entry: &ListEntry, status: Status, error: Option<String>) {
+        let line = Line::File(Record {
+            url: entry.url.clone(),
+            dir: entry.dir.clone(),
+            out: entry.out.clone(),
+            metalink: entry.source.clone(),
+            status,
+            error,
+            finished: now(),
+        });
+        if let Err(e) = self.write(&line) {
+            tracing::warn!("Failed to write to {}: {:#}", self.path.display(), e);
+        }
+    }
+
+    fn write(&self, line: &Line) -> Result<()> {
+        let mut text = serde_json::to_string(line)?;
+        text.push('\n');
+        // One write per line, so a crash can only cut the last one short.
+        let mut file = self.file.lock();
+        file.write_all(text.as_bytes())?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+// Each entry's last record, in the order the entries first appear.
+pub fn read(path: &Path) -> Result<Vec<Record>> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open journal {}", path.display()))?;
+    let mut records: Vec<Record> = Vec::new();
+    let mut positions = HashMap::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Line>(&line) {
+            Ok(Line::Session { version, .. }) if version > VERSION => {
+                anyhow::bail!(
+                    "{} was written by a newer storm (journal version {})",
+                    path.display(),
+                    version
+                );
+            }
+            Ok(Line::File(record)) => {
+                let key = (record.url.clone(), record.dir.clone(), record.out.clone());
+                match positions.get(&key) {
+                    Some(&i) => records[i] = record,
+                    None => {
+                        positions.insert(key, records.len());
+                        records.push(record);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("{}:{}: skipping line: {}", path.display(), number + 1, e),
+        }
+    }
+    Ok(records)
+}
+
+// The entries of a journal that didn't finish, ready to download again.
+pub fn failed_entries(path: &Path) -> Result<Vec<ListEntry>> {
+    let mut metalinks: HashMap<PathBuf, Vec<ListEntry>> = HashMap::new();
+    let mut entries = Vec::new();
+    for record in read(path)? {
+        if record.status == Status::Ok {
+            continue;
+        }
+        let mut entry = ListEntry {
+            dir: record.dir,
+            out: record.out,
+            ..ListEntry::new(record.url)
+        };
+        // Back to the metalink for its mirrors and checksums, when it's
+        // still there.
+        if let Some(source) = record.metalink {
+            if !metalinks.contains_key(&source) {
+                let files = listfile::import_metalink(&source).unwrap_or_else(|e| {
+                    tracing::warn!("{:#}; retrying its files from their URLs", e);
+                    Vec::new()
+                });
+                metalinks.insert(source.clone(), files);
+            }
+            if let Some(file) = metalinks[&source].iter().find(|f| f.out == entry.out) {
+                entry.metalink = file.metalink.clone();
+                entry.source = Some(source);
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+}
+
+fn prune(dir: &Path) {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut journals: Vec<PathBuf> = read
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "jsonl"))
+        .collect();
+    // Names are timestamps, so they sort oldest first.
+    journals.sort();
+    let excess = (journals.len() + 1).saturating_sub(KEEP_SESSIONS);
+    for path in journals.into_iter().take(excess) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let path = std::env::temp_dir().join(format!("storm-journal-{}.jsonl", std::process::id()));
+        let a = ListEntry::new("https://example.com/a.iso");
+        let b = ListEntry {
+            out: Some("b.zip".into()),
+            dir: Some(PathBuf::from("/data")),
+            ..ListEntry::new("https://example.com/b")
+        };
+        let c = ListEntry::new("https://example.com/c");
+
+        let journal = Journal::open(Some(path.clone())).unwrap();
+        journal.record(&a, Status::Ok, None);
+        journal.record(&b, Status::Failed, Some("HTTP 503".into()));
+        journal.record(&c, Status::Interrupted, None);
+        drop(journal);
+        assert_eq!(failed_entries(&path).unwrap(), vec![b.clone(), c.clone()]);
+
+        // A retry run appends; later lines win.
+        let journal = Journal::open(Some(path.clone())).unwrap();
+        journal.record(&c, Status::Ok, None);
+        drop(journal);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut text = text.replace(
+            r#""type":"file","url":"https://example.com/a.iso""#,
+            r#""type":"file","future":1,"url":"https://example.com/a.iso""#,
+        );
+        text.push_str("{\"type\":\"note\"}\n{\"type\":\"file\",\"url\":\n");
+        std::fs::write(&path, text).unwrap();
+
+        let records = read(&path).unwrap();
+        let statuses: Vec<(&str, Status)> =
+            records.iter().map(|r| (r.url.as_str(), r.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                ("https://example.com/a.iso", Status::Ok),
+                ("https://example.com/b", Status::Failed),
+                ("https://example.com/c", Status::Ok),
+            ]
+        );
+        assert_eq!(records[1].error.as_deref(), Some("HTTP 503"));
+        assert_eq!(failed_entries(&path).unwrap(), vec![b]);
+
+        let first = std::fs::read_to_string(&path).unwrap();
+        assert!(first.starts_with(r#"{"type":"session","version":1,"started":""#));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+#[cfg(not(target_os = "macos"))]
+use std::io::Write;
+#[cfg(not(target_os = "macos"))]
+use std::process::{Command, Stdio};
+use stormdl_core::StormError;
+
+// Secrets live in the platform store under this service name: the login
+// Keychain on macOS, DPAPI-protected files on Windows and the Secret
+// Service (GNOME Keyring, KWallet) elsewhere. Config files only name them.
+pub const SERVICE: &str = "storm-dl";
+
+pub fn get(name: &str) -> Result<String, StormError> {
+    let secret = backend::get(name)?;
+    secret.ok_or_else(|| {
+        StormError::Config(format!(
+            "no secret named '{}' in the keychain (add it with `storm secret {}`)",
+            name, name
+        ))
+    })
+}
+
+pub fn set(name: &str, secret: &str) -> Result<(), StormError> {
+    if name.trim().is_empty() {
+        return Err(StormError::Config("secret name can't be empty".into()));
+    }
+    backend::set(name, secret)
+}
+
+pub fn remove(name: &str) -> Result<bool, StormError> {
+    backend::remove(name)
+}
+
+// Runs a helper with `input` on stdin; `None` when it exits unsuccessfully,
+// which the helpers use for "not found".
+#[cfg(not(target_os = "macos"))]
+fn run(mut command: Command, input: Option<&str>) -> Result<Option<String>, StormError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let unavailable =
+        |e: std::io::Error| StormError::Other(format!("Keychain helper {} failed: {}", program, e));
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(unavailable)?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).map_err(unavailable)?;
+    }
+    let output = child.wait_with_output().map_err(unavailable)?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(Some(stdout.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+#[cfg(target_os = "macos")]
+mod backend {
+    use super::SERVICE;
+    use security_framework::passwords;
+    use stormdl_core::StormError;
+
+    // errSecItemNotFound
+    const NOT_FOUND: i32 = -25300;
+
+    fn keychain_error(e: security_framework::base::Error) -> StormError {
+        StormError::Other(format!("Keychain error: {}", e))
+    }
+
+    pub fn get(name: &str) -> Result<Option<String>, StormError> {
+        match passwords::get_generic_password(SERVICE, name) {
+            Ok(secret) => String::from_utf8(secret)
+                .map(Some)
+                .map_err(|_| StormError::Other(format!("secret '{}' isn't UTF-8", name))),
+            Err(e) if e.code() == NOT_FOUND => Ok(None),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+
+    pub fn set(name: &str, secret: &str) -> Result<(), StormError> {
+        passwords::set_generic_password(SERVICE, name, secret.as_bytes()).map_err(keychain_error)
+    }
+
+    pub fn remove(name: &str) -> Result<bool, StormError> {
+        match passwords::delete_generic_password(SERVICE, name) {
+            Ok(()) => Ok(true),
+            Err(e) if e.code() == NOT_FOUND => Ok(false),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod backend {
+    use super::run;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use stormdl_core::StormError;
+
+    // DPAPI ties the file to the Windows account, so copying it elsewhere
+    // doesn't reveal the secret.
+    const PROTECT: &str = "Add-Type -AssemblyName System.Security; \
+        $b = [Text.Encoding]::UTF8.GetBytes([Console]::In.ReadToEnd()); \
+        [Convert]::ToBase64String([Security.Cryptography.ProtectedData]::Protect($b, $null, 'CurrentUser'))";
+    const UNPROTECT: &str = "Add-Type -AssemblyName System.Security; \
+        $b = [Convert]::FromBase64String([Console]::In.ReadToEnd()); \
+        [Text.Encoding]::UTF8.GetString([Security.Cryptography.ProtectedData]::Unprotect($b, $null, 'CurrentUser'))";
+
+    fn powershell(script: &str) -> Command {
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+        command
+    }
+
+    fn path(name: &str) -> Result<PathBuf, StormError> {
+        let file: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        crate::config::Config::data_dir()
+            .map(|d| d.join("secrets").join(format!("{}.dpapi", file)))
+            .ok_or_else(|| StormError::Config("no data directory for secrets".into()))
+    }
+
+    pub fn get(name: &str) -> Result<Option<String>, StormError> {
+        let sealed = match std::fs::read_to_string(path(name)?) {
+            Ok(sealed) => sealed,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        run(powershell(UNPROTECT), Some(&sealed))
+    }
+
+    pub fn set(name: &str, secret: &str) -> Result<(), StormError> {
+        let sealed = run(powershell(PROTECT), Some(secret))?
+            .ok_or_else(|| StormError::Other("DPAPI refused the secret".into()))?;
+        let path = path(name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, sealed)?;
+        Ok(())
+    }
+
+    pub fn remove(name: &str) -> Result<bool, StormError> {
+        match std::fs::remove_file(path(name)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod backend {
+    use super::{SERVICE, run};
+    use std::process::Command;
+    use stormdl_core::StormError;
+
+    // secret-tool ships with libsecret and talks to whichever Secret Service
+    // daemon the desktop runs.
+    fn secret_tool(action: &str, name: &str) -> Command {
+        let mut command = Command::new("secret-tool");
+        command.arg(action);
+        if action == "store" {
+            command.args(["--label", &format!("{}: {}", SERVICE, name)]);
+        }
+        command.args(["service", SERVICE, "name", name]);
+        command
+    }
+
+    pub fn get(name: &str) -> Result<Option<String>, StormError> {
+        run(secret_tool("lookup", name), None)
+    }
+
+    pub fn set(name: &str, secret: &str) -> Result<(), StormError> {
+        run(secret_tool("store", name), Some(secret))?
+            .map(|_| ())
+            .ok_or_else(|| StormError::Other("the Secret Service refused the secret".into()))
+    }
+
+    pub fn remove(name: &str) -> Result<bool, StormError> {
+        let found = get(name)?.is_some();
+        run(secret_tool("clear", name), None)?;
+        Ok(found)
+    }
+}
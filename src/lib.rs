@@ -0,0 +1,12 @@
+pub mod artifacts;
+pub mod config;
+pub mod keychain;
+pub mod notify;
+pub mod orchestrator;
+pub mod profile;
+pub mod speedtest;
+
+mod download;
+
+pub use download::*;
+pub use stormdl_core::{DownloadEvent, DownloadId, DownloadState, Priority, StormError};
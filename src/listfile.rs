@@ -0,0 +1,251 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use stormdl_core::DownloadState;
+use stormdl_metalink::{Metalink, MetalinkFile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    Aria2,
+    Idm,
+    Plain,
+}
+
+impl ListFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ef2") => Self::Idm,
+            Some("aria2") => Self::Aria2,
+            _ => Self::Plain,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListEntry {
+    pub url: String,
+    pub dir: Option<PathBuf>,
+    pub out: Option<String>,
+    // Mirrors, checksums and piece hashes for a file from a metalink.
+    pub metalink: Option<Arc<MetalinkFile>>,
+    // The metalink document it came from.
+    pub source: Option<PathBuf>,
+}
+
+impl ListEntry {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            dir: None,
+            out: None,
+            metalink: None,
+            source: None,
+        }
+    }
+}
+
+pub fn export(entries: &[ListEntry], format: ListFormat) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        match format {
+            ListFormat::Plain => {
+                let _ = writeln!(out, "{}", entry.url);
+            }
+            ListFormat::Aria2 => {
+                let _ = writeln!(out, "{}", entry.url);
+                if let Some(dir) = &entry.dir {
+                    let _ = writeln!(out, "  dir={}", dir.display());
+                }
+                if let Some(name) = &entry.out {
+                    let _ = writeln!(out, "  out={}", name);
+                }
+            }
+            ListFormat::Idm => {
+                let _ = write!(out, "<\r\n{}\r\n>\r\n", entry.url);
+            }
+        }
+    }
+    out
+}
+
+pub fn import(text: &str, format: ListFormat) -> Vec<ListEntry> {
+    let mut entries: Vec<ListEntry> = Vec::new();
+    let mut in_block = false;
+
+    for raw in text.lines() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match format {
+            ListFormat::Plain => {
+                let (url, out) = line
+                    .split_once(char::is_whitespace)
+                    .map_or((line, ""), |(url, out)| (url, out.trim()));
+                let mut entry = ListEntry::new(url);
+                entry.out = (!out.is_empty()).then(|| out.to_string());
+                entries.push(entry);
+            }
+            ListFormat::Aria2 => {
+                if raw.starts_with([' ', '\t']) {
+                    if let Some(entry) = entries.last_mut()
+                        && let Some((key, value)) = line.split_once('=')
+                    {
+                        match key.trim() {
+                            "dir" => entry.dir = Some(PathBuf::from(value.trim())),
+                            "out" => entry.out = Some(value.trim().to_string()),
+                            _ => {}
+                        }
+                    }
+                } else if let Some(url) = line.split('\t').next() {
+                    entries.push(ListEntry::new(url));
+                }
+            }
+            ListFormat::Idm => match line {
+                "<" => in_block = true,
+                ">" => in_block = false,
+                url if in_block && !url.contains(": ") => {
+                    entries.push(ListEntry::new(url));
+                    in_block = false;
+                }
+                _ => {}
+            },
+        }
+    }
+
+    entries
+}
+
+// One entry per file of a .metalink/.meta4 document, named as it says and
+// starting from its best mirror.
+pub fn import_metalink(path: &Path) -> Result<Vec<ListEntry>> {
+    let metalink = Metalink::load(path)?;
+    Ok(metalink
+        .files
+        .into_iter()
+        .map(|file| ListEntry {
+            url: file.sources[0].url.to_string(),
+            out: Some(file.name.clone()),
+            metalink: Some(Arc::new(file)),
+            source: Some(path.to_path_buf()),
+            ..ListEntry::new("")
+        })
+        .collect())
+}
+
+pub fn export_list(format: ListFormat, output: Option<String>, all: bool) -> Result<()> {
+    let manifest = Config::open_manifest().context("No download manifest found")?;
+    let downloads = if all {
+        manifest.get_all_downloads()?
+    } else {
+        manifest.get_incomplete_downloads()?
+    };
+
+    let entries: Vec<ListEntry> = downloads
+        .into_iter()
+        .filter(|d| all || d.state != DownloadState::Failed)
+        .map(|d| ListEntry {
+            url: d.url,
+            dir: d.output_path.parent().map(Path::to_path_buf),
+            out: Some(d.filename),
+            metalink: None,
+            source: None,
+        })
+        .collect();
+    let text = export(&entries, format);
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, text)?;
+            eprintln!("Exported {} downloads to {}", entries.len(), path);
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+pub fn import_list(path: &str, format: Option<ListFormat>, dir: Option<String>) -> Result<()> {
+    let path = Path::new(path);
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = format.unwrap_or_else(|| ListFormat::from_path(path));
+    let default_dir = dir
+        .map(PathBuf::from)
+        .unwrap_or_else(|| dirs::download_dir().unwrap_or_else(|| PathBuf::from(".")));
+
+    let manifest = Config::open_manifest().context("Cannot open the download manifest")?;
+    let mut imported = 0;
+    for entry in import(&text, format) {
+        let Ok(url) = url::Url::parse(&entry.url) else {
+            eprintln!("Skipping invalid URL: {}", entry.url);
+            continue;
+        };
+        let filename = entry.out.unwrap_or_else(|| {
+            url.path_segments()
+                .and_then(|mut s| s.next_back())
+                .filter(|s| !s.is_empty())
+                .unwrap_or("download")
+                .to_string()
+        });
+        let output_path = entry
+            .dir
+            .unwrap_or_else(|| default_dir.clone())
+            .join(&filename);
+        manifest.create_download(url.as_str(), &filename, &output_path, None, None, None)?;
+        imported += 1;
+    }
+
+    eprintln!("Imported {} downloads from {}", imported, path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let entries = vec![
+            ListEntry {
+                url: "https://example.com/a.iso".into(),
+                dir: Some(PathBuf::from("/data/isos")),
+                out: Some("ubuntu.iso".into()),
+                metalink: None,
+                source: None,
+            },
+            ListEntry::new("https://example.com/b.zip"),
+        ];
+
+        let aria2 = export(&entries, ListFormat::Aria2);
+        assert_eq!(import(&aria2, ListFormat::Aria2), entries);
+
+        let urls: Vec<_> = entries.iter().map(|e| ListEntry::new(&e.url)).collect();
+        for format in [ListFormat::Idm, ListFormat::Plain] {
+            assert_eq!(import(&export(&entries, format), format), urls);
+        }
+
+        assert_eq!(
+            import(
+                "https://x/a.iso  ubuntu 24.04.iso\n# skipped\n\nhttps://x/b.zip\n",
+                ListFormat::Plain
+            ),
+            vec![
+                ListEntry {
+                    out: Some("ubuntu 24.04.iso".into()),
+                    ..ListEntry::new("https://x/a.iso")
+                },
+                ListEntry::new("https://x/b.zip"),
+            ]
+        );
+
+        let idm = "<\r\nhttps://x/y\r\nreferer: https://x\r\nUser-Agent: IDM\r\n>\r\n";
+        assert_eq!(
+            import(idm, ListFormat::Idm),
+            vec![ListEntry::new("https://x/y")]
+        );
+    }
+}
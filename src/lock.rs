@@ -0,0 +1,181 @@
+use crate::cli::format_bytes;
+use crate::config::parse_rate;
+use crate::profile::LockedProfile;
+use anyhow::{Context, Result, bail};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use stormdl_core::{Restrictions, TimeWindow};
+
+pub struct LockArgs {
+    pub allow_hosts: Vec<String>,
+    pub max_speed: Option<String>,
+    pub windows: Vec<String>,
+    pub system: bool,
+}
+
+fn profile_path(system: bool) -> Result<PathBuf> {
+    let path = if system {
+        LockedProfile::system_path()
+    } else {
+        LockedProfile::user_path()
+    };
+    path.context("No location for a locked profile on this platform")
+}
+
+// Scripts can pass the password through STORM_PROFILE_PASSWORD instead of
+// the terminal.
+fn password(prompt: &str) -> Result<String> {
+    if let Ok(password) = std::env::var("STORM_PROFILE_PASSWORD") {
+        return Ok(password);
+    }
+    read_hidden(prompt)
+}
+
+pub fn read_hidden(prompt: &str) -> Result<String> {
+    eprint!("{}: ", prompt);
+    io::stderr().flush()?;
+    let echo = Echo::off();
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    drop(echo);
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// Turns terminal echo off while a password is typed (keeping the newline)
+// and restores it on drop.
+struct Echo {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl Echo {
+    #[cfg(unix)]
+    fn off() -> Self {
+        let fd = libc::STDIN_FILENO;
+        // SAFETY: tcgetattr/tcsetattr only read and write the termios struct
+        // passed in, and fail harmlessly when stdin isn't a terminal.
+        let saved = unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                None
+            } else {
+                let mut quiet = termios;
+                quiet.c_lflag &= !libc::ECHO;
+                quiet.c_lflag |= libc::ECHONL;
+                (libc::tcsetattr(fd, libc::TCSANOW, &quiet) == 0).then_some(termios)
+            }
+        };
+        Self { saved }
+    }
+
+    #[cfg(not(unix))]
+    fn off() -> Self {
+        Self {}
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Echo {
+    fn drop(&mut self) {
+        if let Some(termios) = &self.saved {
+            // SAFETY: restores the settings read in `off`.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios);
+            }
+        }
+    }
+}
+
+fn unlock_existing(profile: &LockedProfile) -> Result<()> {
+    if !profile.verify(&password("Profile password")?) {
+        bail!("Wrong password");
+    }
+    Ok(())
+}
+
+fn print_status(path: &std::path::Path, restrictions: &Restrictions) {
+    println!("Locked profile: {}", path.display());
+    if restrictions.allow_hosts.is_empty() {
+        println!("  Hosts:   any");
+    } else {
+        println!("  Hosts:   {}", restrictions.allow_hosts.join(", "));
+    }
+    match restrictions.max_speed {
+        Some(cap) => println!("  Speed:   {}/s", format_bytes(cap)),
+        None => println!("  Speed:   unlimited"),
+    }
+    if restrictions.windows.is_empty() {
+        println!("  Hours:   any time");
+    } else {
+        let windows: Vec<String> = restrictions.windows.iter().map(|w| w.to_string()).collect();
+        println!("  Hours:   {}", windows.join(", "));
+    }
+}
+
+pub fn lock(args: LockArgs) -> Result<()> {
+    let path = profile_path(args.system)?;
+    let existing = LockedProfile::read(&path)?;
+
+    let restrictions = Restrictions {
+        allow_hosts: args.allow_hosts,
+        max_speed: args
+            .max_speed
+            .map(|s| parse_rate(&s).with_context(|| format!("Invalid speed '{}'", s)))
+            .transpose()?,
+        windows: args
+            .windows
+            .iter()
+            .map(|w| w.parse::<TimeWindow>())
+            .collect::<Result<_, _>>()?,
+    };
+
+    if restrictions.is_empty() {
+        let current = match existing {
+            Some(profile) => Some((path, profile)),
+            None => LockedProfile::find(),
+        };
+        match current {
+            Some((path, profile)) => print_status(&path, &profile.restrictions),
+            None => println!("No locked profile (see `storm lock --help`)"),
+        }
+        return Ok(());
+    }
+
+    // Changing a locked profile takes its password; the hash is kept.
+    let profile = match existing {
+        Some(mut profile) => {
+            unlock_existing(&profile)?;
+            profile.restrictions = restrictions;
+            profile
+        }
+        None => {
+            let first = password("New profile password")?;
+            if first.is_empty() {
+                bail!("The profile password can't be empty");
+            }
+            if std::env::var_os("STORM_PROFILE_PASSWORD").is_none()
+                && password("Repeat password")? != first
+            {
+                bail!("Passwords don't match");
+            }
+            LockedProfile::new(&first, restrictions)?
+        }
+    };
+
+    profile
+        .write(&path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    print_status(&path, &profile.restrictions);
+    Ok(())
+}
+
+pub fn unlock(system: bool) -> Result<()> {
+    let path = profile_path(system)?;
+    let Some(profile) = LockedProfile::read(&path)? else {
+        bail!("No locked profile at {}", path.display());
+    };
+    unlock_existing(&profile)?;
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    println!("Removed the locked profile at {}", path.display());
+    Ok(())
+}
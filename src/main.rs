@@ -1,18 +1,73 @@
+mod aria2;
+mod auth;
+mod batch;
+mod cache;
+mod calibrate;
 mod cli;
-mod orchestrator;
+mod compat;
+mod daemon;
+mod dash;
+mod doctor;
+mod events;
+mod extract;
+mod hls;
+mod hooks;
+mod journal;
+mod listfile;
+mod lock;
+mod manage;
+mod presign;
+mod rest;
+mod resume;
+mod secret;
+mod stream;
+mod template;
+mod torrent;
 
-use anyhow::Result;
-use clap::{CommandFactory, Parser, ValueEnum};
+use anyhow::{Context, Result};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{Shell, generate};
 use std::io;
+use std::time::Duration;
+use stormdl::{artifacts, config, orchestrator, profile};
+use stormdl_metalink::Metalink;
+use stormdl_protocol::PreferredProtocol;
+use stormdl_torrent::Torrent;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
 #[command(name = "storm")]
 #[command(author, version, about = "StormDL — the fastest download tool")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(help = "URL to download")]
-    url: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(help = "URLs, magnet links or .metalink/.meta4/.torrent files to download")]
+    urls: Vec<String>,
+
+    #[command(flatten)]
+    download: DownloadOptions,
+
+    #[arg(short, long, help = "Detailed logging")]
+    verbose: bool,
+
+    #[arg(long, value_enum, help = "Generate shell completions")]
+    completions: Option<ShellCompletion>,
+
+    #[cfg(feature = "gui")]
+    #[arg(long, help = "Launch GUI")]
+    gui: bool,
+}
+
+#[derive(clap::Args)]
+struct DownloadOptions {
+    #[arg(
+        short,
+        long,
+        help = "Read URLs from a file, one per line with an optional output name"
+    )]
+    input_file: Option<String>,
 
     #[arg(short, long, help = "Output directory")]
     output: Option<String>,
@@ -26,18 +81,107 @@ struct Args {
     #[arg(short, long, default_value = "3", help = "Max concurrent downloads")]
     concurrent: usize,
 
-    #[arg(short, long, help = "Bandwidth limit (e.g., 10MB/s)")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Record a batch's results in this journal (default: a new one in the data directory)"
+    )]
+    journal: Option<std::path::PathBuf>,
+
+    #[arg(short, long, help = "Bandwidth limit (e.g., 10MB/s or 80Mbps)")]
     limit: Option<String>,
 
     #[arg(long, help = "Conservative mode for sensitive servers")]
     gentle: bool,
 
+    #[arg(
+        long = "continue",
+        help = "Continue a partial file left by storm, wget, curl or a browser"
+    )]
+    continue_partial: bool,
+
     #[arg(long, help = "Don't save resume manifest")]
     no_resume: bool,
 
-    #[arg(long, help = "Verify file against hash after download")]
+    #[arg(
+        long,
+        overrides_with = "no_cache",
+        help = "Reuse unchanged files from the download cache and add new ones to it"
+    )]
+    cache: bool,
+
+    #[arg(long, overrides_with = "cache", help = "Don't use the download cache")]
+    no_cache: bool,
+
+    #[arg(
+        long,
+        help = "Verify the file after download (SHA-256, SHA-1, MD5 or BLAKE3, e.g. sha256:9f86d0...)"
+    )]
     checksum: Option<String>,
 
+    #[arg(
+        long,
+        value_name = "URL|auto",
+        conflicts_with = "checksum",
+        help = "Read the checksum from a SHA256SUMS-style file, or 'auto' to look for one next to the download"
+    )]
+    checksum_url: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "URL|auto",
+        requires = "signify_key",
+        help = "Detached signature to check the file against (default: look for .minisig/.asc/.sig)"
+    )]
+    signature_url: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "KEY",
+        help = "minisign or OpenPGP public key (file or inline) that must have signed the download"
+    )]
+    signify_key: Option<String>,
+
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        help = "Tag the download in the manifest (repeatable or comma-separated)"
+    )]
+    tags: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Note recorded with the download, e.g. why it was fetched"
+    )]
+    note: Option<String>,
+
+    #[arg(
+        long,
+        help = "Record the file listing of a finished zip or tar archive (see storm info)"
+    )]
+    list_contents: bool,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        num_args = 0..=1,
+        help = "Unpack a zip or tar archive after verifying it (default: next to it, named after it)"
+    )]
+    extract: Option<Option<std::path::PathBuf>>,
+
+    #[arg(long, help = "Refuse files larger than this (e.g., 2GB)")]
+    max_size: Option<String>,
+
+    #[arg(
+        long = "accept-type",
+        value_delimiter = ',',
+        help = "Accepted content types (e.g., application/zip,image/*)"
+    )]
+    accept_types: Vec<String>,
+
+    #[arg(long, help = "Bypass the page cache when writing (experimental)")]
+    direct_io: bool,
+
     #[arg(long, help = "Force HTTP/1.1")]
     http1: bool,
 
@@ -47,21 +191,479 @@ struct Args {
     #[arg(long, help = "Force HTTP/3")]
     http3: bool,
 
+    #[arg(short, long, help = "Download group from the config file")]
+    group: Option<String>,
+
+    #[arg(
+        long,
+        help = "Proxy URL (e.g., socks5h://127.0.0.1:9050 for Tor), or 'direct' to ignore HTTP_PROXY/ALL_PROXY"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long = "mirror-proxy",
+        value_name = "HOST=PROXY",
+        help = "Proxy for one mirror host, or HOST=direct to bypass the proxy (repeatable)"
+    )]
+    mirror_proxies: Vec<String>,
+
+    #[arg(
+        short = 'H',
+        long = "header",
+        value_name = "NAME: VALUE",
+        help = "Extra request header, kept for resume (repeatable)"
+    )]
+    headers: Vec<String>,
+
+    #[arg(
+        short = 'X',
+        long,
+        value_name = "METHOD",
+        help = "Request method for the download (default: GET, or POST with --data)"
+    )]
+    method: Option<String>,
+
+    #[arg(
+        short = 'd',
+        long,
+        value_name = "DATA",
+        help = "Request body: a JSON or form string, @FILE or @- for stdin"
+    )]
+    data: Option<String>,
+
+    #[arg(
+        long = "cookies",
+        value_name = "FILE",
+        help = "Import cookies from a Netscape cookies.txt"
+    )]
+    cookie_file: Option<String>,
+
+    #[arg(
+        long = "cookie",
+        value_name = "NAME=VALUE",
+        help = "Cookie to send to the download's hosts (repeatable)"
+    )]
+    cookies: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Replay the headers and cookies a browser-exported HAR recorded for the download"
+    )]
+    har: Option<std::path::PathBuf>,
+
+    #[arg(
+        short = 'u',
+        long,
+        value_name = "USER:PASSWORD",
+        help = "HTTP Basic credentials, sent only to the download's host"
+    )]
+    user: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        conflicts_with = "user",
+        help = "Bearer token, sent only to the download's host"
+    )]
+    bearer_token: Option<String>,
+
+    #[arg(
+        long,
+        requires = "user",
+        help = "Send --user only in answer to an HTTP Digest challenge"
+    )]
+    digest: bool,
+
+    #[arg(
+        long,
+        help = "Keep credentials and Authorization/Cookie headers on redirects to other hosts"
+    )]
+    location_trusted: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Private key for sftp:// logins, tried after ssh-agent"
+    )]
+    ssh_key: Option<std::path::PathBuf>,
+
+    #[arg(long, help = "Use a single connection per download")]
+    single_stream: bool,
+
+    #[arg(
+        long,
+        alias = "no-hls",
+        help = "Save an HLS playlist or DASH manifest as it is instead of downloading its segments"
+    )]
+    no_stream: bool,
+
+    #[arg(
+        long,
+        value_name = "QUALITY",
+        default_value = "best",
+        value_parser = quality_arg,
+        help = "HLS variant or DASH representation to download: best, worst, or the best up to a height (e.g. 720p)"
+    )]
+    quality: stream::Quality,
+
+    #[arg(
+        long,
+        help = "Split segments across IPv4 and IPv6 when the host has both"
+    )]
+    dual_stack: bool,
+
+    #[arg(
+        long = "interface",
+        value_name = "NAME|IP",
+        help = "Bond segment connections across local interfaces or source IPs (repeatable)"
+    )]
+    interfaces: Vec<String>,
+
+    #[arg(long, help = "Rhai script with download hooks")]
+    hooks: Option<String>,
+
+    #[arg(long, help = "Retry budget for the whole download (default: 20)")]
+    retries: Option<u32>,
+
+    #[arg(long, help = "Retries per segment before escalating (default: 3)")]
+    segment_retries: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = duration_arg,
+        help = "Wait this long before each retry instead of backing off (e.g. 30s)"
+    )]
+    retry_delay: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = duration_arg,
+        help = "Keep polling a file the server is still preparing (202 Accepted) for this long (default: 15m; 0 fails at once)"
+    )]
+    ready_wait: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = duration_arg,
+        help = "Stop a download after this long, saving it to resume (e.g. 2h30m)"
+    )]
+    max_time: Option<Duration>,
+
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = time_arg,
+        help = "Wait until this time before starting (e.g. 03:00, \"tomorrow 03:00\", \"in 2h\")"
+    )]
+    at: Option<chrono::DateTime<chrono::Local>>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Escalation after segment retries run out (switch-mirror,single-connection or none)"
+    )]
+    escalation: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "MILESTONES",
+        help = "Notify at these milestones of a long download: percentages, or time left (e.g. 50%,90%,5m)"
+    )]
+    notify: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "POST milestone notifications as JSON to this URL"
+    )]
+    notify_webhook: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "notify",
+        help = "Don't send the milestone notifications set in the config"
+    )]
+    no_notify: bool,
+
+    #[arg(
+        long,
+        help = "Progress refresh interval in milliseconds (default: 100)"
+    )]
+    progress_interval: Option<u64>,
+
+    #[arg(long, help = "Refresh progress rarely to save power on long transfers")]
+    low_power: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "bar",
+        help = "Progress output: bar, or json lines with per-segment offsets on stdout"
+    )]
+    progress: cli::ProgressStyle,
+
     #[arg(long = "mirror", short = 'm', help = "Additional mirror URLs")]
     mirrors: Vec<String>,
 
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = template_arg,
+        help = "Print a line to stdout for each finished file: {path} {name} {url} {bytes} {sha256} {mime} {seconds} {speed}, with \\t and \\n escapes"
+    )]
+    print_after: Option<template::Template>,
+
     #[arg(short, long, help = "Suppress progress output")]
     quiet: bool,
+}
 
-    #[arg(short, long, help = "Detailed logging")]
-    verbose: bool,
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Download URLs (same as passing them to storm directly)")]
+    Add {
+        #[arg(
+            required_unless_present = "input_file",
+            help = "URLs, magnet links or .metalink/.meta4/.torrent files to download"
+        )]
+        urls: Vec<String>,
 
-    #[arg(long, value_enum, help = "Generate shell completions")]
-    completions: Option<ShellCompletion>,
+        #[command(flatten)]
+        download: Box<DownloadOptions>,
+    },
 
-    #[cfg(feature = "gui")]
-    #[arg(long, help = "Launch GUI")]
-    gui: bool,
+    #[command(about = "Download again the files a batch's session journal lists as failed")]
+    RetryFailed {
+        #[arg(
+            value_name = "JOURNAL",
+            help = "Session journal printed at the end of the batch"
+        )]
+        path: std::path::PathBuf,
+
+        #[command(flatten)]
+        download: Box<DownloadOptions>,
+    },
+
+    #[command(about = "Benchmark a URL and save tuned settings to the config file")]
+    Calibrate {
+        #[arg(help = "URL to benchmark against (must support range requests)")]
+        url: String,
+
+        #[arg(long, default_value = "32MB", help = "Bytes to fetch per network run")]
+        sample_size: String,
+
+        #[arg(long, default_value = "256MB", help = "Bytes to write per disk run")]
+        disk_sample_size: String,
+
+        #[arg(short, long, help = "Directory to benchmark writes in")]
+        output: Option<String>,
+
+        #[arg(long, help = "Print recommendations without saving them")]
+        dry_run: bool,
+    },
+
+    #[command(about = "Run in the background with an aria2-compatible JSON-RPC server")]
+    Daemon {
+        #[arg(long, default_value = "6800", help = "Port for the JSON-RPC server")]
+        rpc_listen_port: u16,
+
+        #[arg(long, help = "Listen on all interfaces instead of localhost")]
+        rpc_listen_all: bool,
+
+        #[arg(long, help = "Require this secret token on every RPC call")]
+        rpc_secret: Option<String>,
+
+        #[arg(short, long, help = "Default download directory")]
+        dir: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "ADDR",
+            help = "Also serve a REST API on this address (e.g. 0.0.0.0:8080)"
+        )]
+        listen: Option<std::net::SocketAddr>,
+
+        #[arg(
+            long,
+            help = "Bearer token required by the REST API (generated if omitted)"
+        )]
+        token: Option<String>,
+    },
+
+    #[command(about = "List incomplete downloads recorded in the manifest")]
+    List {
+        #[arg(short, long, help = "Include completed and cancelled downloads")]
+        all: bool,
+
+        #[arg(short, long, help = "Only show downloads with this tag")]
+        tag: Option<String>,
+    },
+
+    #[command(about = "Add or remove tags and notes on a recorded download")]
+    Tag {
+        #[arg(help = "Download ID from `storm list`")]
+        id: i64,
+
+        #[arg(help = "Tags to add (or remove with --remove)")]
+        tags: Vec<String>,
+
+        #[arg(short, long, help = "Remove the given tags instead of adding them")]
+        remove: bool,
+
+        #[arg(long, help = "Replace the note; an empty string clears it")]
+        note: Option<String>,
+    },
+
+    #[command(about = "Summarize recorded downloads by tag")]
+    Stats {
+        #[arg(short, long, help = "Only count downloads with this tag")]
+        tag: Option<String>,
+    },
+
+    #[command(about = "Pause a download; a running storm process stops and saves progress")]
+    Pause {
+        #[arg(help = "Download ID from `storm list`")]
+        id: i64,
+    },
+
+    #[command(about = "Resume interrupted downloads recorded in the manifest")]
+    Resume {
+        #[arg(help = "Download ID, or text matched against the URL or filename")]
+        filter: Option<String>,
+
+        #[arg(short, long, help = "Suppress progress output")]
+        quiet: bool,
+    },
+
+    #[command(about = "Remove a download from the manifest")]
+    Rm {
+        #[arg(help = "Download ID from `storm list`")]
+        id: i64,
+
+        #[arg(long, help = "Also delete the partially downloaded file")]
+        delete_file: bool,
+    },
+
+    #[command(about = "Show details of a download")]
+    Info {
+        #[arg(help = "Download ID from `storm list`")]
+        id: i64,
+    },
+
+    #[command(about = "Summarize which protocols have worked for each host")]
+    Doctor,
+
+    #[command(about = "Export the download queue for aria2, IDM or as plain URLs")]
+    ExportList {
+        #[arg(
+            short,
+            long,
+            value_enum,
+            default_value = "plain",
+            help = "Output format"
+        )]
+        format: listfile::ListFormat,
+
+        #[arg(short, long, help = "Write to a file instead of stdout")]
+        output: Option<String>,
+
+        #[arg(long, help = "Include finished and failed downloads")]
+        all: bool,
+    },
+
+    #[command(about = "Import a download list exported by aria2, IDM or as plain URLs")]
+    ImportList {
+        #[arg(help = "List file to import")]
+        file: String,
+
+        #[arg(
+            short,
+            long,
+            value_enum,
+            help = "Input format (default: from the file extension)"
+        )]
+        format: Option<listfile::ListFormat>,
+
+        #[arg(short, long, help = "Directory for entries without one")]
+        dir: Option<String>,
+    },
+
+    #[command(
+        about = "Lock downloads to allowed hosts, a speed cap and download hours behind a password"
+    )]
+    Lock {
+        #[arg(
+            long = "allow-host",
+            value_name = "HOST",
+            help = "Only allow downloads from this host (repeatable, e.g. *.example.com)"
+        )]
+        allow_hosts: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "RATE",
+            help = "Cap download speed (e.g., 2M, 500K)"
+        )]
+        max_speed: Option<String>,
+
+        #[arg(
+            long = "window",
+            value_name = "HH:MM-HH:MM",
+            help = "Only download during this daily window (repeatable)"
+        )]
+        windows: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Write the system-wide profile (/etc/storm-dl) instead of the user one"
+        )]
+        system: bool,
+    },
+
+    #[command(about = "Remove the locked profile")]
+    Unlock {
+        #[arg(long, help = "Remove the system-wide profile")]
+        system: bool,
+    },
+
+    #[command(about = "Store a password or token in the OS keychain for config.toml to refer to")]
+    Secret {
+        #[arg(help = "Name to store it under, used as `secret = \"NAME\"` in the config")]
+        name: String,
+
+        #[arg(long, help = "Delete the secret instead")]
+        remove: bool,
+    },
+
+    #[command(about = "Print a presigned HTTPS URL for an s3://bucket/key")]
+    Presign {
+        #[arg(help = "Object to share, e.g. s3://bucket/path/file.iso")]
+        url: String,
+
+        #[arg(
+            long,
+            default_value = "1h",
+            value_parser = duration_arg,
+            help = "How long the URL works (e.g. 3600, 15m, 12h; at most 7d)"
+        )]
+        expires: Duration,
+    },
+
+    #[command(about = "Manage credentials picked automatically for matching hosts")]
+    Auth {
+        #[command(subcommand)]
+        command: auth::AuthCommand,
+    },
+
+    #[command(about = "Inspect or trim the download cache")]
+    Cache {
+        #[command(subcommand)]
+        command: cache::CacheCommand,
+    },
 }
 
 #[derive(Clone, ValueEnum)]
@@ -73,7 +675,11 @@ enum ShellCompletion {
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let argv: Vec<String> = std::env::args().collect();
+    let args = match compat::detect(&argv) {
+        Some(translated) => Args::parse_from(translated?),
+        None => Args::parse_from(argv),
+    };
 
     if let Some(shell) = args.completions {
         let shell = match shell {
@@ -92,57 +698,328 @@ fn main() -> Result<()> {
         EnvFilter::new("info")
     };
 
+    // stdout is kept for --print-after lines and JSON progress.
     tracing_subscriber::fmt()
         .with_env_filter(filter)
         .with_target(false)
+        .with_writer(io::stderr)
         .init();
 
+    match args.command {
+        Some(Command::Add { urls, download }) => return download_urls(urls, *download),
+        Some(Command::RetryFailed { path, download }) => {
+            return retry_failed(path, *download);
+        }
+        Some(Command::List { all, tag }) => return manage::list(all, tag.as_deref()),
+        Some(Command::Tag {
+            id,
+            tags,
+            remove,
+            note,
+        }) => return manage::tag(id, &tags, remove, note.as_deref()),
+        Some(Command::Stats { tag }) => return manage::stats(tag.as_deref()),
+        Some(Command::Pause { id }) => return manage::pause(id),
+        Some(Command::Rm { id, delete_file }) => return manage::remove(id, delete_file),
+        Some(Command::Info { id }) => return manage::info(id),
+        Some(Command::Calibrate {
+            url,
+            sample_size,
+            disk_sample_size,
+            output,
+            dry_run,
+        }) => {
+            return calibrate::calibrate(
+                &url,
+                calibrate::CalibrateArgs {
+                    sample_size,
+                    disk_sample_size,
+                    output,
+                    dry_run,
+                },
+            );
+        }
+        Some(Command::Daemon {
+            rpc_listen_port,
+            rpc_listen_all,
+            rpc_secret,
+            dir,
+            listen,
+            token,
+        }) => {
+            return daemon::run(daemon::DaemonArgs {
+                port: rpc_listen_port,
+                listen_all: rpc_listen_all,
+                secret: rpc_secret,
+                dir,
+                listen,
+                token,
+            });
+        }
+        Some(Command::Resume { filter, quiet }) => {
+            return exit_on_interrupt(cli::resume(filter.as_deref(), quiet));
+        }
+        Some(Command::Doctor) => return doctor::run(),
+        Some(Command::ExportList {
+            format,
+            output,
+            all,
+        }) => return listfile::export_list(format, output, all),
+        Some(Command::ImportList { file, format, dir }) => {
+            return listfile::import_list(&file, format, dir);
+        }
+        Some(Command::Lock {
+            allow_hosts,
+            max_speed,
+            windows,
+            system,
+        }) => {
+            return lock::lock(lock::LockArgs {
+                allow_hosts,
+                max_speed,
+                windows,
+                system,
+            });
+        }
+        Some(Command::Unlock { system }) => return lock::unlock(system),
+        Some(Command::Secret { name, remove }) => return secret::secret(&name, remove),
+        Some(Command::Presign { url, expires }) => return presign::presign(&url, expires),
+        Some(Command::Auth { command }) => return auth::run(command),
+        Some(Command::Cache { command }) => return cache::run(command),
+        None => {}
+    }
+
     #[cfg(feature = "gui")]
-    if args.gui || args.url.is_none() {
+    if args.gui || (args.urls.is_empty() && args.download.input_file.is_none()) {
         return run_gui();
     }
 
-    #[cfg(not(feature = "gui"))]
-    if args.url.is_none() {
-        eprintln!("Usage: storm <URL> [OPTIONS]");
-        eprintln!("       storm --help for more information");
+    download_urls(args.urls, args.download)
+}
+
+fn download_urls(urls: Vec<String>, args: DownloadOptions) -> Result<()> {
+    let mut entries = Vec::new();
+    for url in &urls {
+        // `storm file.meta4` downloads the files the metalink lists.
+        let path = std::path::Path::new(url);
+        if Metalink::is_metalink(path) && path.is_file() {
+            entries.extend(listfile::import_metalink(path)?);
+        } else if Torrent::is_torrent(path) && path.is_file() {
+            // `storm file.torrent` downloads the torrent's files.
+            let url = url::Url::from_file_path(std::fs::canonicalize(path)?)
+                .map_err(|_| anyhow::anyhow!("Invalid torrent path {}", url))?;
+            entries.push(listfile::ListEntry::new(url.as_str()));
+        } else {
+            entries.push(listfile::ListEntry::new(url));
+        }
+    }
+    if let Some(path) = &args.input_file
+        && path != "-"
+        && Metalink::is_metalink(std::path::Path::new(path))
+    {
+        entries.extend(listfile::import_metalink(std::path::Path::new(path))?);
+    } else if let Some(path) = &args.input_file {
+        let text = if path == "-" {
+            io::read_to_string(io::stdin())?
+        } else {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read input file {}", path))?
+        };
+        entries.extend(listfile::import(
+            &text,
+            listfile::ListFormat::from_path(std::path::Path::new(path)),
+        ));
+    }
+
+    if entries.is_empty() {
+        if args.input_file.is_some() {
+            eprintln!("No URLs found in the input file");
+        } else {
+            eprintln!("Usage: storm <URL>... [OPTIONS]");
+            eprintln!("       storm --help for more information");
+        }
         std::process::exit(1);
     }
+    let batch = entries.len() > 1 || args.input_file.is_some();
+    run_downloads(entries, args, batch)
+}
+
+// Reruns the entries whose last journal line isn't `ok`, appending to the
+// same journal.
+fn retry_failed(journal: std::path::PathBuf, mut args: DownloadOptions) -> Result<()> {
+    let entries = journal::failed_entries(&journal)?;
+    if entries.is_empty() {
+        eprintln!(
+            "Nothing to retry: every download in {} finished",
+            journal.display()
+        );
+        return Ok(());
+    }
+    if !args.quiet {
+        eprintln!(
+            "Retrying {} download(s) from {}",
+            entries.len(),
+            journal.display()
+        );
+    }
+    args.journal = Some(journal);
+    run_downloads(entries, args, true)
+}
+
+fn run_downloads(
+    mut entries: Vec<listfile::ListEntry>,
+    args: DownloadOptions,
+    batch: bool,
+) -> Result<()> {
+    if batch && (args.name.is_some() || args.checksum.is_some()) {
+        anyhow::bail!("--name and --checksum only apply to a single URL");
+    }
 
-    if let Some(url) = args.url {
-        cli::download(
-            &url,
-            cli::DownloadArgs {
-                output: args.output,
-                name: args.name,
-                segments: args.segments,
-                limit: args.limit,
-                turbo: !args.gentle,
-                no_resume: args.no_resume,
-                checksum: args.checksum,
-                quiet: args.quiet,
-                mirrors: args.mirrors,
-            },
-        )?;
+    let config = config::Config::load();
+    let mut download_args = cli::DownloadArgs {
+        output: args.output,
+        name: args.name,
+        segments: args.segments,
+        limit: args.limit,
+        turbo: !args.gentle && config.segments.profile.is_turbo(),
+        no_resume: args.no_resume,
+        cache: config.cache.cache(match (args.cache, args.no_cache) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }),
+        checksum: args.checksum,
+        checksum_url: args.checksum_url,
+        signature_url: args.signature_url,
+        signify_key: args.signify_key,
+        tags: stormdl_core::parse_tags(&args.tags.join(",")),
+        note: args.note.filter(|note| !note.trim().is_empty()),
+        list_contents: args.list_contents || config.archive.list_contents,
+        extract: args.extract,
+        quiet: args.quiet,
+        mirrors: args.mirrors,
+        print_after: args.print_after,
+        direct_io: args.direct_io,
+        group: args.group,
+        max_size: args.max_size,
+        accept_types: args.accept_types,
+        proxy: args.proxy,
+        mirror_proxies: args.mirror_proxies,
+        headers: args.headers,
+        method: args.method,
+        data: args.data,
+        cookie_file: args.cookie_file,
+        cookies: args.cookies,
+        har: args.har,
+        auth: match (args.user, args.bearer_token) {
+            (Some(user), _) if args.digest => Some(stormdl_core::Credentials::digest(&user)),
+            (Some(user), _) => Some(stormdl_core::Credentials::basic(&user)),
+            (None, Some(token)) => Some(stormdl_core::Credentials::Bearer(token)),
+            (None, None) => None,
+        },
+        location_trusted: args.location_trusted,
+        ssh_key: args.ssh_key,
+        protocol: if args.http3 {
+            PreferredProtocol::Http3
+        } else if args.http2 {
+            PreferredProtocol::Http2
+        } else if args.http1 {
+            PreferredProtocol::Http1
+        } else {
+            PreferredProtocol::Auto
+        },
+        single_stream: args.single_stream,
+        no_stream: args.no_stream,
+        quality: args.quality,
+        dual_stack: args.dual_stack,
+        interfaces: args.interfaces,
+        continue_partial: args.continue_partial,
+        hooks: args.hooks,
+        retries: args.retries,
+        segment_retries: args.segment_retries,
+        retry_delay: args.retry_delay,
+        ready_wait: args.ready_wait,
+        max_time: args.max_time,
+        escalation: args.escalation,
+        notify: args.notify,
+        notify_webhook: args.notify_webhook,
+        no_notify: args.no_notify,
+        progress_interval: args.progress_interval,
+        low_power: args.low_power,
+        progress: args.progress,
+        batch: None,
+        metalink: None,
+        pool: None,
+        config,
+    };
+    if let Some(at) = args.at {
+        cli::wait_until(at, args.quiet);
     }
+    let result = if batch {
+        cli::download_batch(entries, download_args, args.concurrent, args.journal)
+    } else {
+        let entry = entries.remove(0);
+        download_args.name = download_args.name.or(entry.out);
+        download_args.metalink = entry.metalink;
+        cli::download(&entry.url, download_args)
+    };
+    exit_on_interrupt(result)?;
 
     Ok(())
 }
 
+// Value parsers for clap, which prefixes its own context to the message.
+fn duration_arg(spec: &str) -> Result<Duration, String> {
+    stormdl_core::parse_duration(spec).map_err(arg_error)
+}
+
+fn quality_arg(spec: &str) -> Result<stream::Quality, String> {
+    spec.parse()
+}
+
+fn template_arg(spec: &str) -> Result<template::Template, String> {
+    template::Template::parse(spec).map_err(arg_error)
+}
+
+fn time_arg(spec: &str) -> Result<chrono::DateTime<chrono::Local>, String> {
+    stormdl_core::parse_time(spec, &chrono::Local::now()).map_err(arg_error)
+}
+
+fn arg_error(error: stormdl_core::StormError) -> String {
+    match error {
+        stormdl_core::StormError::Config(message) => message,
+        error => error.to_string(),
+    }
+}
+
+fn exit_on_interrupt(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e) if cli::is_interrupted(&e) => std::process::exit(130),
+        result => result,
+    }
+}
+
 #[cfg(feature = "gui")]
 fn run_gui() -> Result<()> {
     let _ = tracing_subscriber::fmt().with_env_filter("info").try_init();
 
     let (cmd_tx, cmd_rx) = flume::unbounded();
     let (event_tx, event_rx) = flume::unbounded();
+    let config = config::Config::load();
+    let groups = config.groups();
+    let setup = config.gui.setup(config.segments.profile);
+    let history = config::Config::manifest_path()
+        .filter(|path| path.exists())
+        .and_then(|path| stormdl_manifest::Manifest::open(&path).ok())
+        .and_then(|manifest| manifest.recent_urls(500).ok())
+        .unwrap_or_default();
 
     std::thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async {
-            orchestrator::run(cmd_rx, event_tx).await;
+            orchestrator::run(cmd_rx, event_tx, config).await;
         });
     });
 
-    stormdl_gui::run_app(cmd_tx, event_rx);
+    stormdl_gui::run_app(cmd_tx, event_rx, groups, history, setup);
     Ok(())
 }
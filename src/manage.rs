@@ -0,0 +1,242 @@
+use crate::cli::format_bytes;
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use stormdl_core::{DownloadState, parse_tags};
+use stormdl_manifest::{Manifest, ManifestEntry};
+
+fn open() -> Result<Manifest> {
+    Config::open_manifest().context("No download manifest found")
+}
+
+fn find(manifest: &Manifest, id: i64) -> Result<ManifestEntry> {
+    manifest
+        .get_download(id)?
+        .with_context(|| format!("No download with ID {} (see `storm list`)", id))
+}
+
+fn progress(manifest: &Manifest, entry: &ManifestEntry) -> Result<(u64, Option<f64>)> {
+    let written = match entry.state {
+        DownloadState::Complete => entry.total_size.unwrap_or(0),
+        _ => manifest.written_ranges(entry.id)?.covered(),
+    };
+    let percent = entry
+        .total_size
+        .filter(|&total| total > 0)
+        .map(|total| written as f64 * 100.0 / total as f64);
+    Ok((written, percent))
+}
+
+fn tagged(entries: Vec<ManifestEntry>, tag: Option<&str>) -> Vec<ManifestEntry> {
+    entries
+        .into_iter()
+        .filter(|e| tag.is_none_or(|tag| e.tags.iter().any(|t| t == tag)))
+        .collect()
+}
+
+pub fn list(all: bool, tag: Option<&str>) -> Result<()> {
+    let manifest = open()?;
+    let entries = if all {
+        manifest.get_all_downloads()?
+    } else {
+        manifest.get_incomplete_downloads()?
+    };
+    let entries = tagged(entries, tag);
+    if entries.is_empty() {
+        let tagged = tag.map(|t| format!(" tagged '{}'", t)).unwrap_or_default();
+        println!(
+            "No {}downloads{}",
+            if all { "" } else { "incomplete " },
+            tagged
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{:>5}  {:<11}  {:>6}  {:>10}  FILE",
+        "ID", "STATE", "DONE", "SIZE"
+    );
+    for entry in &entries {
+        let (_, percent) = progress(&manifest, entry)?;
+        let tags = if entry.tags.is_empty() {
+            String::new()
+        } else {
+            format!("  [{}]", entry.tags.join(", "))
+        };
+        println!(
+            "{:>5}  {:<11}  {:>6}  {:>10}  {}{}",
+            entry.id,
+            format!("{:?}", entry.state),
+            percent.map_or("-".to_string(), |p| format!("{:.0}%", p)),
+            entry.total_size.map_or("-".to_string(), format_bytes),
+            entry.output_path.display(),
+            tags
+        );
+    }
+    Ok(())
+}
+
+pub fn pause(id: i64) -> Result<()> {
+    let manifest = open()?;
+    let entry = find(&manifest, id)?;
+    match entry.state {
+        DownloadState::Complete | DownloadState::Quarantined | DownloadState::Cancelled => {
+            anyhow::bail!("Download {} is already {:?}", id, entry.state)
+        }
+        DownloadState::Paused => println!("Download {} is already paused", id),
+        _ => {
+            manifest.update_download_state(id, DownloadState::Paused)?;
+            println!(
+                "Paused {}; resume with `storm resume {}`",
+                entry.filename, id
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn remove(id: i64, delete_file: bool) -> Result<()> {
+    let manifest = open()?;
+    let entry = find(&manifest, id)?;
+    if delete_file && entry.state != DownloadState::Complete {
+        match std::fs::remove_file(&entry.output_path) {
+            Ok(()) => println!("Deleted {}", entry.output_path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to delete {}", entry.output_path.display()));
+            }
+        }
+    }
+    manifest.delete_download(id)?;
+    println!("Removed download {} ({})", id, entry.filename);
+    Ok(())
+}
+
+pub fn tag(id: i64, tags: &[String], remove: bool, note: Option<&str>) -> Result<()> {
+    let manifest = open()?;
+    let entry = find(&manifest, id)?;
+    let changed = parse_tags(&tags.join(","));
+    let mut tags = entry.tags;
+    if remove {
+        tags.retain(|t| !changed.contains(t));
+    } else {
+        for tag in changed {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    // An empty --note clears the note.
+    let note = match note {
+        Some(note) => Some(note.trim()).filter(|n| !n.is_empty()),
+        None => entry.note.as_deref(),
+    };
+    manifest.set_download_labels(id, &tags, note)?;
+
+    if tags.is_empty() {
+        println!("{} has no tags", entry.filename);
+    } else {
+        println!("Tagged {}: {}", entry.filename, tags.join(", "));
+    }
+    Ok(())
+}
+
+pub fn stats(tag: Option<&str>) -> Result<()> {
+    let manifest = open()?;
+    let entries = tagged(manifest.get_all_downloads()?, tag);
+    if entries.is_empty() {
+        println!("No downloads recorded");
+        return Ok(());
+    }
+
+    // Downloads count once under each of their tags.
+    let mut rows: BTreeMap<&str, (usize, usize, u64)> = BTreeMap::new();
+    for entry in &entries {
+        let labels: Vec<&str> = if entry.tags.is_empty() {
+            vec!["(untagged)"]
+        } else {
+            entry.tags.iter().map(String::as_str).collect()
+        };
+        for label in labels.into_iter().filter(|l| tag.is_none_or(|t| t == *l)) {
+            let row = rows.entry(label).or_default();
+            row.0 += 1;
+            if entry.state == DownloadState::Complete {
+                row.1 += 1;
+                row.2 += entry.total_size.unwrap_or(0);
+            }
+        }
+    }
+
+    println!(
+        "{:<20}  {:>9}  {:>9}  {:>10}",
+        "TAG", "DOWNLOADS", "COMPLETE", "SIZE"
+    );
+    for (label, (count, complete, bytes)) in &rows {
+        println!(
+            "{:<20}  {:>9}  {:>9}  {:>10}",
+            label,
+            count,
+            complete,
+            format_bytes(*bytes)
+        );
+    }
+    Ok(())
+}
+
+pub fn info(id: i64) -> Result<()> {
+    let manifest = open()?;
+    let entry = find(&manifest, id)?;
+    let (written, percent) = progress(&manifest, &entry)?;
+    let segments = manifest.get_segments(id)?;
+
+    println!("ID:        {}", entry.id);
+    println!("URL:       {}", entry.url);
+    println!("File:      {}", entry.output_path.display());
+    println!("State:     {:?}", entry.state);
+    match (entry.total_size, percent) {
+        (Some(total), Some(percent)) => println!(
+            "Progress:  {} of {} ({:.1}%)",
+            format_bytes(written),
+            format_bytes(total),
+            percent
+        ),
+        _ => println!("Progress:  {}", format_bytes(written)),
+    }
+    println!("Segments:  {}", segments.len());
+    if let Some(group) = &entry.group {
+        println!("Group:     {}", group);
+    }
+    if !entry.tags.is_empty() {
+        println!("Tags:      {}", entry.tags.join(", "));
+    }
+    if let Some(note) = &entry.note {
+        println!("Note:      {}", note);
+    }
+    if let Some(etag) = &entry.etag {
+        println!("ETag:      {}", etag);
+    }
+    if let Some(last_modified) = &entry.last_modified {
+        println!("Modified:  {}", last_modified);
+    }
+    println!("Added:     {}", entry.created_at);
+    println!("Updated:   {}", entry.updated_at);
+
+    let contents = manifest.archive_entries(id)?;
+    if !contents.is_empty() {
+        let folders = contents.iter().filter(|e| e.is_dir).count();
+        println!(
+            "Contents:  {} files, {} folders",
+            contents.len() - folders,
+            folders
+        );
+        for entry in &contents {
+            if entry.is_dir {
+                println!("  {:>10}  {}", "", entry.path);
+            } else {
+                println!("  {:>10}  {}", format_bytes(entry.size), entry.path);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,335 @@
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+use stormdl_core::format_duration;
+use tokio::task::JoinHandle;
+use url::Url;
+
+// Milestones don't need to land on the exact byte.
+const TICK: Duration = Duration::from_secs(1);
+// Speed measured over a shorter span gives no estimate worth sending.
+const SETTLE: Duration = Duration::from_secs(10);
+// Weight of the newest sample in the smoothed speed.
+const SMOOTHING: f64 = 0.2;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Milestone {
+    // This share of the file is on disk.
+    Percent(u8),
+    // The estimated time left dropped below this.
+    Remaining(Duration),
+}
+
+impl FromStr for Milestone {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+        if let Some(percent) = spec.strip_suffix('%') {
+            return match percent.trim().parse() {
+                Ok(percent @ 1..=99) => Ok(Milestone::Percent(percent)),
+                _ => Err(format!(
+                    "invalid milestone '{}' (a percentage from 1% to 99%)",
+                    spec
+                )),
+            };
+        }
+        stormdl_core::parse_duration(spec)
+            .map(Milestone::Remaining)
+            .map_err(|_| {
+                format!(
+                    "invalid milestone '{}' (e.g. 50% or 5m for five minutes left)",
+                    spec
+                )
+            })
+    }
+}
+
+impl fmt::Display for Milestone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Milestone::Percent(percent) => write!(f, "{}%", percent),
+            Milestone::Remaining(left) => f.write_str(&format_duration(*left)),
+        }
+    }
+}
+
+// Sends a desktop notification and/or a webhook POST as a long download
+// passes its milestones.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    milestones: Vec<Milestone>,
+    min_duration: Duration,
+    desktop: bool,
+    webhook: Option<Url>,
+}
+
+impl Notifier {
+    pub fn new(milestones: Vec<Milestone>, min_duration: Duration) -> Self {
+        Self {
+            milestones,
+            min_duration,
+            desktop: true,
+            webhook: None,
+        }
+    }
+
+    pub fn with_desktop(mut self, desktop: bool) -> Self {
+        self.desktop = desktop;
+        self
+    }
+
+    pub fn with_webhook(mut self, webhook: Option<Url>) -> Self {
+        self.webhook = webhook;
+        self
+    }
+
+    // Follows a download of `total` bytes, `done` saying how many are on
+    // disk, until the returned guard is dropped.
+    pub fn watch(
+        self,
+        name: String,
+        url: Url,
+        total: u64,
+        done: impl Fn() -> u64 + Send + 'static,
+    ) -> Watch {
+        Watch(tokio::spawn(async move {
+            let mut tracker = Tracker::new(&self.milestones, self.min_duration, total, done());
+            let started = tokio::time::Instant::now();
+            while !tracker.pending.is_empty() {
+                tokio::time::sleep(TICK).await;
+                for reached in tracker.update(done(), started.elapsed()) {
+                    self.send(&name, &url, &reached).await;
+                }
+            }
+        }))
+    }
+
+    async fn send(&self, name: &str, url: &Url, reached: &Reached) {
+        let message = reached.describe(name);
+        if self.desktop {
+            let shown = tokio::task::spawn_blocking(move || desktop(&message)).await;
+            if let Ok(Err(e)) = shown {
+                tracing::warn!("Desktop notification failed: {}", e);
+            }
+        }
+        if let Some(webhook) = &self.webhook {
+            let body = serde_json::json!({
+                "event": "milestone",
+                "milestone": reached.milestone.to_string(),
+                "file": name,
+                "url": url.as_str(),
+                "downloaded": reached.done,
+                "total": reached.total,
+                "percent": reached.percent,
+                "eta_secs": reached.left.as_secs(),
+            });
+            let posted = reqwest::Client::new()
+                .post(webhook.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .timeout(WEBHOOK_TIMEOUT)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = posted {
+                tracing::warn!("Notification webhook {} failed: {}", webhook, e);
+            }
+        }
+    }
+}
+
+// Stops the notifications when the download ends, however it ends.
+pub struct Watch(JoinHandle<()>);
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Reached {
+    milestone: Milestone,
+    done: u64,
+    total: u64,
+    percent: u8,
+    left: Duration,
+}
+
+impl Reached {
+    fn describe(&self, name: &str) -> String {
+        format!("{} is {}% done, {}", name, self.percent, about(self.left))
+    }
+}
+
+fn about(left: Duration) -> String {
+    let minutes = (left.as_secs() + 30) / 60;
+    match minutes {
+        0 => "less than a minute left".to_string(),
+        1 => "about a minute left".to_string(),
+        2..=89 => format!("about {} minutes left", minutes),
+        _ => format!("about {} hours left", (minutes + 30) / 60),
+    }
+}
+
+struct Tracker {
+    pending: Vec<Milestone>,
+    min_duration: Duration,
+    total: u64,
+    last: (u64, Duration),
+    speed: Option<f64>,
+}
+
+impl Tracker {
+    // Milestones a resumed download has already passed are dropped.
+    fn new(milestones: &[Milestone], min_duration: Duration, total: u64, done: u64) -> Self {
+        let percent = percent(done, total);
+        Self {
+            pending: milestones
+                .iter()
+                .copied()
+                .filter(|m| !matches!(m, Milestone::Percent(p) if percent >= *p))
+                .collect(),
+            min_duration,
+            total,
+            last: (done, Duration::ZERO),
+            speed: None,
+        }
+    }
+
+    fn update(&mut self, done: u64, elapsed: Duration) -> Vec<Reached> {
+        let (last_done, last_at) = self.last;
+        let span = elapsed.saturating_sub(last_at).as_secs_f64();
+        if span > 0.0 {
+            let sample = done.saturating_sub(last_done) as f64 / span;
+            self.speed = Some(match self.speed {
+                Some(speed) => speed + SMOOTHING * (sample - speed),
+                None => sample,
+            });
+            self.last = (done, elapsed);
+        }
+        let left = self
+            .speed
+            .filter(|speed| *speed > 0.0 && elapsed >= SETTLE)
+            .map(|speed| Duration::from_secs_f64(self.total.saturating_sub(done) as f64 / speed));
+        let percent = percent(done, self.total);
+        // A download that's short altogether isn't worth interrupting anyone
+        // for; its milestones pass silently.
+        let long = left.filter(|left| elapsed + *left >= self.min_duration);
+
+        let total = self.total;
+        let mut reached = Vec::new();
+        self.pending.retain(|&milestone| {
+            let hit = match milestone {
+                Milestone::Percent(p) => percent >= p,
+                Milestone::Remaining(threshold) => left.is_some_and(|left| left <= threshold),
+            };
+            if hit && let Some(left) = long {
+                reached.push(Reached {
+                    milestone,
+                    done,
+                    total,
+                    percent,
+                    left,
+                });
+            }
+            !hit
+        });
+        reached
+    }
+}
+
+fn percent(done: u64, total: u64) -> u8 {
+    (done as u128 * 100 / total.max(1) as u128).min(100) as u8
+}
+
+// The message goes through the environment so it needs no quoting.
+fn desktop(message: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            "display notification (system attribute \"STORM_NOTIFICATION\") with title \"storm\"",
+        ]);
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; \
+             $n.ShowBalloonTip(10000, 'storm', $env:STORM_NOTIFICATION, 'Info'); \
+             Start-Sleep -Seconds 10; $n.Dispose()",
+        ]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name=storm", "storm", message]);
+        command
+    };
+    let status = command.env("STORM_NOTIFICATION", message).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("exited with {}", status)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_milestones() {
+        assert_eq!("50%".parse(), Ok(Milestone::Percent(50)));
+        assert_eq!(
+            "5m".parse(),
+            Ok(Milestone::Remaining(Duration::from_secs(300)))
+        );
+        assert!("100%".parse::<Milestone>().is_err());
+        assert!("soon".parse::<Milestone>().is_err());
+
+        let milestones = [
+            Milestone::Percent(10),
+            Milestone::Percent(50),
+            Milestone::Remaining(Duration::from_secs(300)),
+        ];
+        let secs = Duration::from_secs;
+        // 1000 bytes a second over 1,000,000 bytes: about 17 minutes, with
+        // 10% already on disk from an earlier run.
+        let mut tracker = Tracker::new(&milestones, secs(600), 1_000_000, 100_000);
+        assert_eq!(tracker.pending.len(), 2);
+        assert!(tracker.update(105_000, secs(5)).is_empty());
+        let reached = tracker.update(600_000, secs(500));
+        assert_eq!(reached.len(), 1);
+        assert_eq!(reached[0].milestone, Milestone::Percent(50));
+        assert_eq!(reached[0].percent, 60);
+        assert!(
+            reached[0]
+                .describe("a.iso")
+                .starts_with("a.iso is 60% done, about")
+        );
+        assert!(tracker.update(690_000, secs(600)).is_empty());
+        let reached = tracker.update(760_000, secs(660));
+        assert_eq!(
+            reached[0].milestone,
+            Milestone::Remaining(Duration::from_secs(300))
+        );
+        assert!(tracker.pending.is_empty());
+
+        // A download done in under a minute notifies nobody.
+        let mut tracker = Tracker::new(&milestones, secs(600), 1_000_000, 0);
+        assert!(tracker.update(500_000, secs(20)).is_empty());
+        assert!(tracker.update(900_000, secs(30)).is_empty());
+        assert!(tracker.pending.is_empty());
+
+        assert_eq!(about(secs(20)), "less than a minute left");
+        assert_eq!(about(secs(290)), "about 5 minutes left");
+        assert_eq!(about(secs(3 * 3600)), "about 3 hours left");
+    }
+}
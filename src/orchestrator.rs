@@ -3,75 +3,33 @@
 #![allow(clippy::redundant_closure)]
 #![allow(clippy::clone_on_copy)]
 
+use crate::config::{Config, CredentialsConfig};
+use crate::profile::check_schedule;
 use bytes::Bytes;
 use flume::{Receiver, Sender};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
+use stormdl_bandwidth::{DownloadQueue, FairScheduler, FairShare, QueuedDownload, RateLimiter};
 use stormdl_core::{
-    ByteRange, DataSink, DownloadId, DownloadState, Downloader, SegmentState, SegmentStatus,
-    StormError,
+    ArchiveEntry, ByteRange, DataSink, DownloadGroup, DownloadId, DownloadOptions, DownloadState,
+    Downloader, FileMap, MonthlyQuota, PageLink, Priority, ProgressPacer, Restrictions,
+    RetryAction, RetryBudget, RetryPolicy, SegmentState, SegmentStatus, SpeedProfile, StormError,
 };
-use stormdl_protocol::HttpDownloader;
-
-#[cfg(feature = "gui")]
-use stormdl_gui::{DownloadEvent, OrchestratorCommand};
-
-#[cfg(not(feature = "gui"))]
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
-pub enum OrchestratorCommand {
-    AddDownload {
-        url: url::Url,
-        options: stormdl_core::DownloadOptions,
-    },
-    PauseDownload(DownloadId),
-    ResumeDownload(DownloadId),
-    CancelDownload(DownloadId),
-    SetBandwidthLimit(Option<u64>),
-}
+use stormdl_integrity::{ChecksumSpec, Scanner};
+use stormdl_manifest::Manifest;
+use stormdl_protocol::{ClientOptions, HttpDownloader, ProxyConfig, S3Config};
+use tokio::sync::{Notify, Semaphore, watch};
 
-#[cfg(not(feature = "gui"))]
-#[derive(Debug, Clone)]
-pub enum DownloadEvent {
-    DownloadAdded {
-        id: DownloadId,
-        url: url::Url,
-        filename: String,
-        total_size: Option<u64>,
-    },
-    ProgressUpdate {
-        id: DownloadId,
-        downloaded: u64,
-        segments: Vec<SegmentState>,
-    },
-    SpeedUpdate {
-        id: DownloadId,
-        speed: f64,
-    },
-    StateChange {
-        id: DownloadId,
-        state: DownloadState,
-    },
-    SegmentRebalanced {
-        id: DownloadId,
-        old_count: usize,
-        new_count: usize,
-    },
-    Error {
-        id: DownloadId,
-        error: String,
-    },
-    Complete {
-        id: DownloadId,
-        path: PathBuf,
-        hash: String,
-    },
-}
+pub use stormdl_core::{DownloadEvent, OrchestratorCommand};
+
+const MAX_PAGE_SIZE: usize = 8 * 1024 * 1024;
+const MAX_PAGE_LINKS: usize = 500;
+const PAGE_PROBES: usize = 8;
 
 static NEXT_ID: AtomicU64 = AtomicU64::new(1);
 
@@ -86,24 +44,255 @@ struct DownloadTask {
     output_path: PathBuf,
     total_size: Option<u64>,
     state: DownloadState,
+    group: Option<String>,
+    priority: Priority,
+    tags: Vec<String>,
+    note: Option<String>,
+    record: Option<i64>,
+}
+
+impl DownloadTask {
+    // Downloads only get a manifest row once something needs to outlive the
+    // session, like labels or an archive listing.
+    fn ensure_record(&mut self, manifest: &Manifest) -> Result<i64, StormError> {
+        if let Some(record) = self.record {
+            return Ok(record);
+        }
+        let record = manifest.create_download(
+            self.url.as_str(),
+            &self.filename,
+            &self.output_path,
+            self.total_size,
+            None,
+            None,
+        )?;
+        manifest.update_download_state(record, self.state)?;
+        self.record = Some(record);
+        Ok(record)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Run,
+    Pause,
+    Cancel,
+}
+
+async fn interrupted(control: &mut watch::Receiver<Control>) -> Control {
+    match control.wait_for(|c| *c != Control::Run).await {
+        Ok(c) => *c,
+        Err(_) => Control::Cancel,
+    }
+}
+
+async fn resumed(control: &mut watch::Receiver<Control>) -> Control {
+    match control.wait_for(|c| *c != Control::Pause).await {
+        Ok(c) => *c,
+        Err(_) => Control::Cancel,
+    }
+}
+
+struct PendingStart {
+    url: url::Url,
+    output_path: PathBuf,
+    slot: Option<GroupSlot>,
+    link: String,
+}
+
+#[derive(Clone)]
+struct GroupSlot {
+    limiter: Arc<RateLimiter>,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl GroupSlot {
+    fn new(group: &DownloadGroup) -> Self {
+        Self {
+            limiter: Arc::new(RateLimiter::new(group.bandwidth_limit)),
+            permits: group
+                .max_concurrent
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ProgressPacing {
+    pacer: ProgressPacer,
+    background: Duration,
+    ui_active: Arc<AtomicBool>,
+    wake: Arc<Notify>,
+}
+
+impl ProgressPacing {
+    fn new(pacer: ProgressPacer, background: Duration) -> Self {
+        Self {
+            pacer,
+            background,
+            ui_active: Arc::new(AtomicBool::new(true)),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    fn set_ui_active(&self, active: bool) {
+        self.ui_active.store(active, Ordering::Release);
+        if active {
+            self.wake.notify_waiters();
+        }
+    }
+
+    async fn wait(&mut self, speed: f64) {
+        let delay = if self.ui_active.load(Ordering::Acquire) {
+            self.pacer.observe(speed)
+        } else {
+            self.background
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = self.wake.notified() => {}
+        }
+    }
 }
 
 pub struct Orchestrator {
     downloads: HashMap<DownloadId, DownloadTask>,
     event_tx: Sender<DownloadEvent>,
     downloader: Arc<HttpDownloader>,
+    groups: HashMap<String, GroupSlot>,
+    queue: DownloadQueue,
+    pending: HashMap<DownloadId, PendingStart>,
+    controls: HashMap<DownloadId, watch::Sender<Control>>,
+    fair: FairScheduler,
+    shares: HashMap<DownloadId, Arc<FairShare>>,
+    finished_tx: Sender<(DownloadId, u64, DownloadState)>,
+    finished_rx: Receiver<(DownloadId, u64, DownloadState)>,
+    listed_tx: Sender<(DownloadId, Vec<ArchiveEntry>)>,
+    listed_rx: Receiver<(DownloadId, Vec<ArchiveEntry>)>,
+    list_archives: bool,
+    scanner: Option<Arc<Scanner>>,
+    restrictions: Restrictions,
+    speed_cap: Option<Arc<RateLimiter>>,
+    schedule_open: bool,
+    schedule_paused: HashSet<DownloadId>,
+    quota: MonthlyQuota,
+    month_used: u64,
+    manifest: Option<Manifest>,
+    client_options: ClientOptions,
+    credentials: CredentialsConfig,
+    turbo: bool,
+    retry_policy: RetryPolicy,
+    pacing: ProgressPacing,
 }
 
 impl Orchestrator {
     pub fn new(event_tx: Sender<DownloadEvent>) -> Self {
+        Self::with_groups(event_tx, Vec::new())
+    }
+
+    pub fn with_config(event_tx: Sender<DownloadEvent>, config: &Config) -> Self {
+        let mut orchestrator = Self::with_groups(event_tx, config.groups());
+        orchestrator.quota = config.quota.quota();
+        orchestrator.list_archives = config.archive.list_contents;
+        orchestrator.restrictions = config.restrictions.clone();
+        orchestrator.speed_cap = config
+            .restrictions
+            .max_speed
+            .map(|cap| Arc::new(RateLimiter::new(Some(cap))));
+        orchestrator.schedule_open = check_schedule(&config.restrictions).is_ok();
+        match config.scan.scanner() {
+            Ok(scanner) => orchestrator.scanner = scanner.map(Arc::new),
+            Err(e) => tracing::warn!("Ignoring scan settings: {}", e),
+        }
+        orchestrator.apply_network(config);
+        orchestrator.credentials = config.credentials.clone();
+        match config.retry.policy(None, None, None, None) {
+            Ok(policy) => orchestrator.retry_policy = policy,
+            Err(e) => tracing::warn!("Ignoring retry settings: {}", e),
+        }
+        orchestrator.pacing = ProgressPacing::new(
+            config.progress.pacer(None, false),
+            config.progress.background_interval(),
+        );
+        orchestrator.manifest = Config::open_manifest();
+        orchestrator.month_used = orchestrator
+            .manifest
+            .as_ref()
+            .and_then(|m| m.month_usage().ok())
+            .unwrap_or(0);
+        orchestrator.send_quota();
+        orchestrator
+    }
+
+    pub fn with_groups(event_tx: Sender<DownloadEvent>, groups: Vec<DownloadGroup>) -> Self {
         let downloader = Arc::new(HttpDownloader::new().expect("Failed to create HTTP client"));
+        let (finished_tx, finished_rx) = flume::unbounded();
+        let (listed_tx, listed_rx) = flume::unbounded();
         Self {
             downloads: HashMap::new(),
             event_tx,
             downloader,
+            groups: groups
+                .iter()
+                .map(|g| (g.name.clone(), GroupSlot::new(g)))
+                .collect(),
+            queue: DownloadQueue::default(),
+            pending: HashMap::new(),
+            controls: HashMap::new(),
+            fair: FairScheduler::new(),
+            shares: HashMap::new(),
+            finished_tx,
+            finished_rx,
+            listed_tx,
+            listed_rx,
+            list_archives: false,
+            scanner: None,
+            restrictions: Restrictions::default(),
+            speed_cap: None,
+            schedule_open: true,
+            schedule_paused: HashSet::new(),
+            quota: MonthlyQuota::unlimited(),
+            month_used: 0,
+            manifest: None,
+            client_options: ClientOptions::default(),
+            credentials: CredentialsConfig::default(),
+            turbo: false,
+            retry_policy: RetryPolicy::default(),
+            pacing: ProgressPacing::new(ProgressPacer::default(), Duration::from_secs(5)),
         }
     }
 
+    fn apply_network(&mut self, config: &Config) {
+        self.turbo = config.segments.profile == SpeedProfile::Turbo;
+        self.client_options = config.client_options(self.turbo, None).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring network settings: {}", e);
+            ClientOptions {
+                host_policy: config.restrictions.host_policy(config.hosts.policy()),
+                ..Default::default()
+            }
+        });
+        match HttpDownloader::with_options(&self.client_options) {
+            Ok(dl) => self.downloader = Arc::new(dl),
+            Err(e) => tracing::warn!("Failed to apply network settings: {}", e),
+        }
+    }
+
+    fn send_quota(&self) {
+        if !self.quota.is_enabled() {
+            return;
+        }
+        let _ = self.event_tx.send(DownloadEvent::QuotaUpdate {
+            used: self.month_used,
+            limit: self.quota.limit,
+            level: self.quota.level(self.month_used),
+        });
+    }
+
+    pub fn set_max_concurrent(&mut self, max: usize) {
+        self.queue.set_max_concurrent(max.max(1));
+        self.start_queued();
+    }
+
     pub async fn handle_command(&mut self, cmd: OrchestratorCommand) {
         match cmd {
             OrchestratorCommand::AddDownload { url, options } => {
@@ -118,14 +307,327 @@ impl Orchestrator {
             OrchestratorCommand::CancelDownload(id) => {
                 self.cancel_download(id).await;
             }
+            OrchestratorCommand::RemoveDownload(id) => {
+                self.remove_download(id).await;
+            }
             OrchestratorCommand::SetBandwidthLimit(_) => {}
+            OrchestratorCommand::SetPriority { id, priority } => {
+                self.set_priority(id, priority);
+            }
+            OrchestratorCommand::MoveDownload { id, before } => {
+                self.move_download(id, before);
+            }
+            OrchestratorCommand::SetLabels { id, tags, note } => {
+                self.set_labels(id, tags, note);
+            }
+            OrchestratorCommand::SetUiActive(active) => {
+                self.pacing.set_ui_active(active);
+            }
+            OrchestratorCommand::GrabLinks(page) => {
+                let downloader = self.downloader.clone();
+                let event_tx = self.event_tx.clone();
+                tokio::spawn(async move {
+                    let event = match grab_links(&downloader, &page).await {
+                        Ok(links) => DownloadEvent::PageLinks { page, links },
+                        Err(e) => DownloadEvent::PageLinksFailed {
+                            page,
+                            error: e.to_string(),
+                        },
+                    };
+                    let _ = event_tx.send(event);
+                });
+            }
+            OrchestratorCommand::Calibrate(url) => {
+                let event_tx = self.event_tx.clone();
+                tokio::spawn(async move {
+                    let event = match crate::speedtest::quick(&url).await {
+                        Ok(calibration) => DownloadEvent::CalibrationFinished { url, calibration },
+                        Err(e) => DownloadEvent::CalibrationFailed {
+                            url,
+                            error: e.to_string(),
+                        },
+                    };
+                    let _ = event_tx.send(event);
+                });
+            }
+            OrchestratorCommand::CompleteSetup(choices) => {
+                let event = match Config::save_setup(&choices) {
+                    Ok(path) => {
+                        self.apply_network(&Config::load());
+                        DownloadEvent::SetupSaved { path }
+                    }
+                    Err(e) => DownloadEvent::SetupFailed {
+                        error: e.to_string(),
+                    },
+                };
+                let _ = self.event_tx.send(event);
+            }
+        }
+    }
+
+    fn set_priority(&mut self, id: DownloadId, priority: Priority) {
+        let Some(task) = self.downloads.get_mut(&id) else {
+            return;
+        };
+        task.priority = priority;
+        self.queue.reorder(id, priority);
+        if let Some(share) = self.shares.get(&id) {
+            share.set_priority(priority);
+        }
+
+        let _ = self
+            .event_tx
+            .send(DownloadEvent::PriorityChanged { id, priority });
+        self.send_queue_order();
+    }
+
+    // Labelled downloads are recorded in the manifest so their tags and notes
+    // show up in `storm list` and `storm stats` after the window closes.
+    fn set_labels(&mut self, id: DownloadId, tags: Vec<String>, note: Option<String>) {
+        let Some(task) = self.downloads.get_mut(&id) else {
+            return;
+        };
+        task.tags = tags;
+        task.note = note.filter(|n| !n.trim().is_empty());
+
+        if let Some(manifest) = &self.manifest {
+            let saved = task.ensure_record(manifest).and_then(|record| {
+                manifest.set_download_labels(record, &task.tags, task.note.as_deref())
+            });
+            if let Err(e) = saved {
+                tracing::warn!("Failed to save labels: {}", e);
+            }
+        }
+
+        let _ = self.event_tx.send(DownloadEvent::LabelsChanged {
+            id,
+            tags: task.tags.clone(),
+            note: task.note.clone(),
+        });
+    }
+
+    fn archive_listed(&mut self, id: DownloadId, entries: Vec<ArchiveEntry>) {
+        if let (Some(manifest), Some(task)) = (&self.manifest, self.downloads.get_mut(&id)) {
+            let saved = task
+                .ensure_record(manifest)
+                .and_then(|record| manifest.set_archive_entries(record, &entries));
+            if let Err(e) = saved {
+                tracing::warn!("Failed to save archive listing: {}", e);
+            }
+        }
+        let _ = self
+            .event_tx
+            .send(DownloadEvent::ArchiveListed { id, entries });
+    }
+
+    fn move_download(&mut self, id: DownloadId, before: DownloadId) {
+        let Some(priority) = self.queue.move_before(id, before) else {
+            return;
+        };
+        if let Some(task) = self.downloads.get_mut(&id) {
+            task.priority = priority;
+        }
+        if let Some(share) = self.shares.get(&id) {
+            share.set_priority(priority);
+        }
+
+        let _ = self
+            .event_tx
+            .send(DownloadEvent::PriorityChanged { id, priority });
+        self.send_queue_order();
+    }
+
+    fn send_queue_order(&self) {
+        let _ = self.event_tx.send(DownloadEvent::QueueChanged {
+            order: self.queue.order(),
+        });
+    }
+
+    // A per-download proxy replaces the default route but keeps the
+    // configured host overrides, so mirrors still go where they were sent.
+    // s3:// sources are rewritten to their HTTPS endpoint, which the client
+    // signs requests to.
+    fn downloader_for(
+        &self,
+        url: &mut url::Url,
+        options: &mut DownloadOptions,
+    ) -> Result<(Arc<HttpDownloader>, bool), StormError> {
+        let s3 = std::iter::once(&*url)
+            .chain(&options.mirrors)
+            .any(|source| source.scheme() == "s3")
+            .then(S3Config::from_env)
+            .transpose()?;
+        if let Some(s3) = &s3 {
+            *url = s3.resolve(url)?;
+            options.url = s3.resolve(&options.url)?;
+            for mirror in &mut options.mirrors {
+                *mirror = s3.resolve(mirror)?;
+            }
         }
+
+        let proxy = match &options.proxy {
+            Some(url) => {
+                let mut proxy = ProxyConfig::new(url)?;
+                if let Some(global) = &self.client_options.proxy {
+                    proxy.hosts = global.hosts.clone();
+                }
+                Some(proxy)
+            }
+            None => self.client_options.proxy.clone(),
+        };
+        let mut sources = vec![options.url.clone()];
+        sources.extend(options.mirrors.iter().cloned());
+        let auth = self
+            .credentials
+            .host_auth(options.auth.as_ref(), &sources)?;
+        if proxy.is_none() && options.headers.is_empty() && auth.is_none() && s3.is_none() {
+            return Ok((self.downloader.clone(), false));
+        }
+        let single_stream = proxy.as_ref().is_some_and(|p| p.single_stream);
+        let mut headers = self.client_options.headers.clone();
+        headers.extend(options.headers.iter().cloned());
+        let downloader = HttpDownloader::with_options(&ClientOptions {
+            proxy,
+            headers,
+            auth,
+            s3: s3.as_ref().and_then(S3Config::signer),
+            ..self.client_options.clone()
+        })?;
+        Ok((Arc::new(downloader), single_stream))
+    }
+
+    fn start_queued(&mut self) {
+        // Outside the locked profile's hours downloads stay queued.
+        while self.schedule_open
+            && let Some(next) = self.queue.dequeue()
+        {
+            let Some(mut start) = self.pending.remove(&next.id) else {
+                self.queue.complete(next.id);
+                continue;
+            };
+
+            let id = next.id;
+            let event_tx = self.event_tx.clone();
+            let finished_tx = self.finished_tx.clone();
+
+            let mut options = next.options;
+            let (downloader, single_stream) =
+                match self.downloader_for(&mut start.url, &mut options) {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        let _ = event_tx.send(DownloadEvent::Error {
+                            id,
+                            error: e.to_string(),
+                        });
+                        let _ = finished_tx.send((id, 0, DownloadState::Failed));
+                        continue;
+                    }
+                };
+
+            if self.quota.blocks(self.month_used, 0) {
+                let _ = event_tx.send(DownloadEvent::Error {
+                    id,
+                    error: "Monthly data quota exhausted".to_string(),
+                });
+                let _ = finished_tx.send((id, 0, DownloadState::Failed));
+                continue;
+            }
+            let quota_remaining = self
+                .quota
+                .hard_stop
+                .then(|| self.quota.remaining(self.month_used))
+                .flatten();
+            let budget = Arc::new(RetryBudget::new(
+                self.retry_policy.clone(),
+                options.mirrors.len(),
+            ));
+            let pacing = self.pacing.clone();
+            let scanner = self.scanner.clone();
+            let speed_cap = self.speed_cap.clone();
+            let turbo = self.turbo;
+            let (control_tx, control) = watch::channel(Control::Run);
+            self.controls.insert(id, control_tx);
+            let priority = self
+                .downloads
+                .get(&id)
+                .map_or(next.priority, |t| t.priority);
+            let share = Arc::new(self.fair.join(start.link, priority));
+            self.shares.insert(id, share.clone());
+
+            tokio::spawn(async move {
+                let _permit = match start.slot.as_ref().and_then(|s| s.permits.clone()) {
+                    Some(permits) => permits.acquire_owned().await.ok(),
+                    None => None,
+                };
+                let group_limiter = start.slot.map(|s| s.limiter).filter(|l| l.is_limited());
+                let limiter = match (group_limiter, speed_cap) {
+                    (Some(group), Some(cap)) if cap.limit() < group.limit() => cap,
+                    (Some(group), _) => group,
+                    (None, cap) => cap.unwrap_or_else(|| Arc::new(RateLimiter::unlimited())),
+                };
+                let (bytes, state) = run_download(
+                    id,
+                    start.url,
+                    start.output_path,
+                    options,
+                    downloader,
+                    single_stream,
+                    turbo,
+                    limiter,
+                    share,
+                    budget,
+                    pacing,
+                    quota_remaining,
+                    scanner,
+                    control,
+                    event_tx,
+                )
+                .await;
+                let _ = finished_tx.send((id, bytes, state));
+            });
+        }
+        self.send_queue_order();
+    }
+
+    fn download_finished(&mut self, id: DownloadId, bytes: u64, state: DownloadState) {
+        self.controls.remove(&id);
+        self.shares.remove(&id);
+        if let Some(task) = self.downloads.get_mut(&id) {
+            task.state = state;
+            if let (Some(manifest), Some(record)) = (&self.manifest, task.record)
+                && let Err(e) = manifest.update_download_state(record, state)
+            {
+                tracing::warn!("Failed to record download state: {}", e);
+            }
+            if state == DownloadState::Complete && self.list_archives {
+                let path = task.output_path.clone();
+                let listed_tx = self.listed_tx.clone();
+                tokio::task::spawn_blocking(move || match stormdl_io::list_archive(&path) {
+                    Ok(Some(entries)) => {
+                        let _ = listed_tx.send((id, entries));
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Failed to list {}: {}", path.display(), e),
+                });
+            }
+        }
+        if bytes > 0 {
+            self.month_used += bytes;
+            if let Some(manifest) = &self.manifest
+                && let Err(e) = manifest.add_usage(bytes)
+            {
+                tracing::warn!("Failed to record data usage: {}", e);
+            }
+            self.send_quota();
+        }
+
+        self.queue.complete(id);
+        self.start_queued();
     }
 
     async fn add_download(&mut self, url: url::Url, options: stormdl_core::DownloadOptions) {
         let id = next_download_id();
         let event_tx = self.event_tx.clone();
-        let downloader = self.downloader.clone();
 
         let filename = options.filename.clone().unwrap_or_else(|| {
             url.path_segments()
@@ -135,6 +637,17 @@ impl Orchestrator {
         });
 
         let output_path = options.output_dir.join(&filename);
+        let slot = options
+            .group
+            .as_ref()
+            .and_then(|name| self.groups.get(name))
+            .cloned();
+        // Downloads in a rate-limited group compete for that limit; the rest
+        // compete per host.
+        let link = match (&options.group, &slot) {
+            (Some(name), Some(slot)) if slot.limiter.is_limited() => format!("group:{}", name),
+            _ => url.host_str().unwrap_or_default().to_string(),
+        };
 
         let task = DownloadTask {
             id,
@@ -143,6 +656,11 @@ impl Orchestrator {
             output_path: output_path.clone(),
             total_size: None,
             state: DownloadState::Pending,
+            group: options.group.clone(),
+            priority: options.priority,
+            tags: Vec::new(),
+            note: None,
+            record: None,
         };
 
         self.downloads.insert(id, task);
@@ -152,14 +670,53 @@ impl Orchestrator {
             url: url.clone(),
             filename: filename.clone(),
             total_size: None,
+            group: options.group.clone(),
+        });
+        let _ = event_tx.send(DownloadEvent::PriorityChanged {
+            id,
+            priority: options.priority,
         });
+        if !options.tags.is_empty() || options.note.is_some() {
+            self.set_labels(id, options.tags.clone(), options.note.clone());
+        }
 
-        tokio::spawn(async move {
-            run_download(id, url, output_path, downloader, event_tx).await;
+        if let Some(Err(e)) = options.checksum.as_deref().map(ChecksumSpec::parse) {
+            if let Some(task) = self.downloads.get_mut(&id) {
+                task.state = DownloadState::Failed;
+            }
+            let _ = event_tx.send(DownloadEvent::Error {
+                id,
+                error: e.to_string(),
+            });
+            let _ = event_tx.send(DownloadEvent::StateChange {
+                id,
+                state: DownloadState::Failed,
+            });
+            return;
+        }
+
+        self.pending.insert(
+            id,
+            PendingStart {
+                url,
+                output_path,
+                slot,
+                link,
+            },
+        );
+        self.queue.enqueue(QueuedDownload {
+            id,
+            priority: options.priority,
+            options,
         });
+        self.start_queued();
     }
 
     async fn pause_download(&mut self, id: DownloadId) {
+        let Some(control) = self.controls.get(&id) else {
+            return;
+        };
+        control.send_replace(Control::Pause);
         if let Some(task) = self.downloads.get_mut(&id) {
             task.state = DownloadState::Paused;
             let _ = self.event_tx.send(DownloadEvent::StateChange {
@@ -170,6 +727,14 @@ impl Orchestrator {
     }
 
     async fn resume_download(&mut self, id: DownloadId) {
+        let Some(control) = self.controls.get(&id) else {
+            return;
+        };
+        if !self.schedule_open {
+            tracing::warn!("Not resuming download {:?}: outside the allowed hours", id);
+            return;
+        }
+        control.send_replace(Control::Run);
         if let Some(task) = self.downloads.get_mut(&id) {
             task.state = DownloadState::Downloading;
             let _ = self.event_tx.send(DownloadEvent::StateChange {
@@ -179,7 +744,41 @@ impl Orchestrator {
         }
     }
 
+    // Pauses running downloads when the locked profile's window closes and
+    // picks them back up, with the queue, when it opens again.
+    async fn enforce_schedule(&mut self) {
+        let open = check_schedule(&self.restrictions).is_ok();
+        if open == self.schedule_open {
+            return;
+        }
+        self.schedule_open = open;
+        if open {
+            for id in std::mem::take(&mut self.schedule_paused) {
+                self.resume_download(id).await;
+            }
+            self.start_queued();
+        } else {
+            let running: Vec<DownloadId> = self
+                .controls
+                .iter()
+                .filter(|(_, control)| *control.borrow() == Control::Run)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in running {
+                self.pause_download(id).await;
+                self.schedule_paused.insert(id);
+            }
+        }
+    }
+
     async fn cancel_download(&mut self, id: DownloadId) {
+        if self.pending.remove(&id).is_some() {
+            self.queue.cancel(id);
+            self.send_queue_order();
+        }
+        if let Some(control) = self.controls.get(&id) {
+            control.send_replace(Control::Cancel);
+        }
         if let Some(task) = self.downloads.get_mut(&id) {
             task.state = DownloadState::Cancelled;
             let _ = self.event_tx.send(DownloadEvent::StateChange {
@@ -188,34 +787,119 @@ impl Orchestrator {
             });
         }
     }
+
+    async fn remove_download(&mut self, id: DownloadId) {
+        if self.pending.contains_key(&id) || self.controls.contains_key(&id) {
+            self.cancel_download(id).await;
+        }
+        self.downloads.remove(&id);
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_download(
     id: DownloadId,
     url: url::Url,
     output_path: PathBuf,
+    options: DownloadOptions,
     downloader: Arc<HttpDownloader>,
+    single_stream: bool,
+    turbo: bool,
+    limiter: Arc<RateLimiter>,
+    share: Arc<FairShare>,
+    budget: Arc<RetryBudget>,
+    mut pacing: ProgressPacing,
+    quota_remaining: Option<u64>,
+    scanner: Option<Arc<Scanner>>,
+    mut control: watch::Receiver<Control>,
     event_tx: Sender<DownloadEvent>,
-) {
+) -> (u64, DownloadState) {
     let _ = event_tx.send(DownloadEvent::StateChange {
         id,
         state: DownloadState::Probing,
     });
 
-    let info = match downloader.probe(&url).await {
-        Ok(info) => info,
-        Err(e) => {
-            let _ = event_tx.send(DownloadEvent::Error {
-                id,
-                error: e.to_string(),
-            });
-            return;
-        }
+    // A server still preparing the file, say an export, answers 202
+    // Accepted until it's done; the download waits for it.
+    let started = Instant::now();
+    let mut info = loop {
+        let error = match downloader.probe(&url).await {
+            Ok(info) => break info,
+            Err(StormError::NotReady { retry_after }) => {
+                match budget.policy().ready_delay(retry_after, started.elapsed()) {
+                    Some(delay) => {
+                        let _ = event_tx.send(DownloadEvent::StateChange {
+                            id,
+                            state: DownloadState::Waiting,
+                        });
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = control.wait_for(|c| *c == Control::Cancel) => {
+                                return (0, DownloadState::Cancelled);
+                            }
+                        }
+                        let _ = event_tx.send(DownloadEvent::StateChange {
+                            id,
+                            state: DownloadState::Probing,
+                        });
+                        continue;
+                    }
+                    None => format!(
+                        "{} still isn't ready after {}",
+                        url,
+                        stormdl_core::format_duration(Duration::from_secs(
+                            started.elapsed().as_secs()
+                        ))
+                    ),
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+        let _ = event_tx.send(DownloadEvent::Error { id, error });
+        return (0, DownloadState::Failed);
     };
+    if let Err(e) = stormdl_protocol::upgrade_http3(&downloader, &mut info).await {
+        tracing::warn!(
+            "HTTP/3 failed for {}, staying on {}: {}",
+            info.url.host_str().unwrap_or_default(),
+            info.http_version.as_str(),
+            e
+        );
+    }
+    let url = info.url.clone();
 
     let total_size = info.size.unwrap_or(0);
-    let num_segments = if info.supports_range && total_size > 0 {
-        stormdl_segment::initial_segments(total_size)
+    if quota_remaining.is_some_and(|remaining| total_size > remaining) {
+        let _ = event_tx.send(DownloadEvent::Error {
+            id,
+            error: "Download would exceed the monthly data quota".to_string(),
+        });
+        return (0, DownloadState::Failed);
+    }
+
+    let name = output_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if let Err(e) = stormdl_core::check_error_page(&name, info.content_type.as_deref()) {
+        let _ = event_tx.send(DownloadEvent::Error {
+            id,
+            error: e.to_string(),
+        });
+        return (0, DownloadState::Failed);
+    }
+
+    let num_segments = if info.supports_range && total_size > 0 && !single_stream {
+        options
+            .segments
+            .unwrap_or_else(|| {
+                if turbo {
+                    stormdl_segment::turbo_segments(total_size)
+                } else {
+                    stormdl_segment::initial_segments(total_size)
+                }
+            })
+            .max(1)
     } else {
         1
     };
@@ -228,19 +912,33 @@ async fn run_download(
             .clone()
             .unwrap_or_else(|| "download".to_string()),
         total_size: Some(total_size),
+        group: None,
     });
 
+    if resumed(&mut control).await == Control::Cancel {
+        return (0, DownloadState::Failed);
+    }
     let _ = event_tx.send(DownloadEvent::StateChange {
         id,
         state: DownloadState::Downloading,
     });
 
-    if let Err(e) = std::fs::File::create(&output_path).and_then(|f| f.set_len(total_size)) {
+    let created = output_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::File::create(&output_path))
+        .and_then(|f| {
+            // Without it NTFS allocates the whole length up front; filesystems
+            // that can't do sparse files just allocate as they always have.
+            let _ = stormdl_io::make_sparse(&f);
+            f.set_len(total_size)
+        });
+    if let Err(e) = created {
         let _ = event_tx.send(DownloadEvent::Error {
             id,
             error: format!("Failed to create file: {}", e),
         });
-        return;
+        return (0, DownloadState::Failed);
     }
 
     let segments: Vec<SegmentState> = stormdl_segment::split_range(total_size, num_segments)
@@ -263,9 +961,13 @@ async fn run_download(
     let progress_handle = tokio::spawn(async move {
         let mut last_bytes = 0u64;
         let mut last_time = Instant::now();
+        let mut speed = 0.0;
+        let mut sent_speed = 0.0;
+        let mut sent_states: Vec<SegmentState> = Vec::new();
+        let mut sent_map: Option<FileMap> = None;
 
         loop {
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            pacing.wait(speed).await;
 
             let current = progress_downloaded.load(Ordering::Relaxed);
             if current >= total_size {
@@ -274,7 +976,7 @@ async fn run_download(
 
             let now = Instant::now();
             let interval = now.duration_since(last_time).as_secs_f64();
-            let speed = if interval > 0.0 {
+            speed = if interval > 0.0 {
                 (current - last_bytes) as f64 / interval
             } else {
                 0.0
@@ -301,49 +1003,150 @@ async fn run_download(
                 })
                 .collect();
 
+            let changed: Vec<SegmentState> = segment_states
+                .iter()
+                .enumerate()
+                .filter(|(idx, state)| sent_states.get(*idx) != Some(*state))
+                .map(|(_, state)| state.clone())
+                .collect();
+            if changed.is_empty() && current == last_bytes && speed == sent_speed {
+                continue;
+            }
+
+            let map = FileMap::from_segments(total_size, &segment_states);
+            if sent_map.as_ref() != Some(&map) {
+                let _ = progress_tx.send(DownloadEvent::FileMapUpdate {
+                    id,
+                    map: map.clone(),
+                });
+                sent_map = Some(map);
+            }
+
             let _ = progress_tx.send(DownloadEvent::ProgressUpdate {
                 id,
                 downloaded: current,
-                segments: segment_states,
+                segments: changed,
             });
 
             let _ = progress_tx.send(DownloadEvent::SpeedUpdate { id, speed });
 
+            sent_speed = speed;
+            sent_states = segment_states;
             last_bytes = current;
             last_time = now;
         }
     });
 
+    let sources: Arc<Vec<url::Url>> = Arc::new(
+        std::iter::once(url)
+            .chain(options.mirrors.iter().cloned())
+            .collect(),
+    );
+    let source = Arc::new(AtomicUsize::new(0));
     let mut handles = Vec::new();
+    let single_connection = Arc::new(AtomicBool::new(false));
+    let serial = Arc::new(tokio::sync::Mutex::new(()));
 
     for (idx, segment) in segments.iter().enumerate() {
-        let url = url.clone();
+        let sources = sources.clone();
+        let source = source.clone();
         let path = output_path.clone();
         let dl = downloader.clone();
         let global_downloaded = downloaded.clone();
         let seg_downloaded = segment_downloaded[idx].clone();
-        let range = segment.range;
+        let mut range = segment.range;
+        let limiter = limiter.clone();
+        let share = share.clone();
+        let budget = budget.clone();
+        let single_connection = single_connection.clone();
+        let serial = serial.clone();
+        let mut control = control.clone();
 
         let handle = tokio::spawn(async move {
-            download_segment(dl, &url, &path, range, global_downloaded, seg_downloaded).await
+            loop {
+                if resumed(&mut control).await == Control::Cancel {
+                    return Err(StormError::Cancelled);
+                }
+                let _connection = if single_connection.load(Ordering::Relaxed) {
+                    Some(serial.lock().await)
+                } else {
+                    None
+                };
+                let before = seg_downloaded.load(Ordering::Relaxed);
+                let url = &sources[source.load(Ordering::Relaxed).min(sources.len() - 1)];
+                let result = tokio::select! {
+                    result = download_segment(
+                        dl.clone(),
+                        url,
+                        &path,
+                        range,
+                        global_downloaded.clone(),
+                        seg_downloaded.clone(),
+                        limiter.clone(),
+                        share.clone(),
+                        control.clone(),
+                    ) => Some(result),
+                    _ = interrupted(&mut control) => None,
+                };
+                let written = seg_downloaded.load(Ordering::Relaxed) - before;
+                let result = match result {
+                    Some(Err(StormError::Cancelled)) | None => {
+                        range = ByteRange::new(range.start + written, range.end);
+                        continue;
+                    }
+                    Some(result) => result,
+                };
+                let error = match result {
+                    Ok(()) if written >= range.len() => return Ok(()),
+                    Ok(()) => StormError::Network(format!(
+                        "connection closed after {} of {} bytes",
+                        written,
+                        range.len()
+                    )),
+                    Err(e) => e,
+                };
+
+                match budget.on_failure(idx, &error) {
+                    RetryAction::Retry(delay) => tokio::time::sleep(delay).await,
+                    RetryAction::SwitchMirror => {
+                        source.fetch_add(1, Ordering::Relaxed);
+                    }
+                    RetryAction::SingleConnection => {
+                        single_connection.store(true, Ordering::Relaxed);
+                    }
+                    RetryAction::Fail => return Err(error),
+                }
+                range = ByteRange::new(range.start + written, range.end);
+            }
         });
 
         handles.push(handle);
     }
 
     let mut has_error = false;
+    let mut cancelled = false;
     for handle in handles {
-        if let Err(e) = handle.await {
+        let error = match handle.await {
+            Ok(Ok(())) => continue,
+            Ok(Err(StormError::Cancelled)) => {
+                cancelled = true;
+                continue;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => format!("Task error: {}", e),
+        };
+        if !has_error {
             has_error = true;
-            let _ = event_tx.send(DownloadEvent::Error {
-                id,
-                error: format!("Task error: {}", e),
-            });
+            let _ = event_tx.send(DownloadEvent::Error { id, error });
         }
     }
 
     progress_handle.abort();
 
+    if cancelled {
+        release_unwritten(&output_path, &segments, &segment_downloaded);
+        return (downloaded.load(Ordering::Relaxed), DownloadState::Cancelled);
+    }
     if has_error {
         let _ = event_tx.send(DownloadEvent::StateChange {
             id,
@@ -362,20 +1165,93 @@ async fn run_download(
             })
             .collect();
 
+        let _ = event_tx.send(DownloadEvent::FileMapUpdate {
+            id,
+            map: FileMap::from_segments(total_size, &segment_states),
+        });
+
         let _ = event_tx.send(DownloadEvent::ProgressUpdate {
             id,
             downloaded: final_downloaded,
             segments: segment_states,
         });
 
+        let verified = match options.checksum.as_deref().map(ChecksumSpec::parse) {
+            Some(Ok(spec)) => stormdl_integrity::verify_file(&output_path, &spec)
+                .await
+                .map(|(_, hash)| hash),
+            Some(Err(e)) => Err(e),
+            None => Ok(String::new()),
+        };
+        let hash = match verified {
+            Ok(hash) => hash,
+            Err(e) => {
+                let _ = event_tx.send(DownloadEvent::Error {
+                    id,
+                    error: e.to_string(),
+                });
+                let _ = event_tx.send(DownloadEvent::StateChange {
+                    id,
+                    state: DownloadState::Failed,
+                });
+                return (final_downloaded, DownloadState::Failed);
+            }
+        };
+
+        if let Some(scanner) = scanner {
+            let _ = event_tx.send(DownloadEvent::StateChange {
+                id,
+                state: DownloadState::Scanning,
+            });
+            if let Err(e) = scanner.check(&output_path).await {
+                let state = match e {
+                    StormError::Quarantined { .. } => DownloadState::Quarantined,
+                    _ => DownloadState::Failed,
+                };
+                let _ = event_tx.send(DownloadEvent::Error {
+                    id,
+                    error: e.to_string(),
+                });
+                let _ = event_tx.send(DownloadEvent::StateChange { id, state });
+                return (final_downloaded, state);
+            }
+        }
+
         let _ = event_tx.send(DownloadEvent::Complete {
             id,
             path: output_path,
-            hash: String::new(),
+            hash,
         });
     }
+
+    let state = if has_error {
+        DownloadState::Failed
+    } else {
+        DownloadState::Complete
+    };
+    (downloaded.load(Ordering::Relaxed), state)
+}
+
+// A cancelled download's remaining ranges will never be written; hands
+// back whatever the filesystem allocated for them so the partial file only
+// takes up the bytes it holds.
+fn release_unwritten(path: &PathBuf, segments: &[SegmentState], written: &[Arc<AtomicU64>]) {
+    let Ok(file) = File::options().write(true).open(path) else {
+        return;
+    };
+    for (segment, written) in segments.iter().zip(written) {
+        let start = segment.range.start + written.load(Ordering::Relaxed);
+        if start >= segment.range.end {
+            continue;
+        }
+        if let Err(e) = stormdl_io::punch_hole(&file, start, segment.range.end - start) {
+            tracing::debug!("Not releasing unwritten space in {}: {}", path.display(), e);
+            return;
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn download_segment(
     downloader: Arc<HttpDownloader>,
     url: &url::Url,
@@ -383,6 +1259,9 @@ async fn download_segment(
     range: ByteRange,
     global_downloaded: Arc<AtomicU64>,
     segment_downloaded: Arc<AtomicU64>,
+    limiter: Arc<RateLimiter>,
+    share: Arc<FairShare>,
+    control: watch::Receiver<Control>,
 ) -> Result<(), StormError> {
     let mut file = File::options()
         .write(true)
@@ -396,6 +1275,9 @@ async fn download_segment(
         file,
         global_downloaded,
         segment_downloaded,
+        limiter,
+        share,
+        control,
     };
 
     downloader.fetch_range(url, range, &mut sink).await?;
@@ -408,10 +1290,17 @@ struct ProgressSink {
     file: File,
     global_downloaded: Arc<AtomicU64>,
     segment_downloaded: Arc<AtomicU64>,
+    limiter: Arc<RateLimiter>,
+    share: Arc<FairShare>,
+    control: watch::Receiver<Control>,
 }
 
+#[async_trait::async_trait]
 impl DataSink for ProgressSink {
     fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        if *self.control.borrow() != Control::Run {
+            return Err(StormError::Cancelled);
+        }
         self.file.write_all(&data).map_err(|e| StormError::Io(e))?;
         let len = data.len() as u64;
         self.global_downloaded.fetch_add(len, Ordering::Relaxed);
@@ -422,12 +1311,92 @@ impl DataSink for ProgressSink {
     fn flush(&mut self) -> Result<(), StormError> {
         self.file.flush().map_err(|e| StormError::Io(e))
     }
+
+    async fn ready(&mut self, len: usize) -> Result<(), StormError> {
+        self.share.admit(len).await;
+        self.limiter.acquire(len).await;
+        Ok(())
+    }
 }
 
-pub async fn run(cmd_rx: Receiver<OrchestratorCommand>, event_tx: Sender<DownloadEvent>) {
-    let mut orchestrator = Orchestrator::new(event_tx);
+struct PageSink {
+    data: Vec<u8>,
+}
 
-    while let Ok(cmd) = cmd_rx.recv_async().await {
-        orchestrator.handle_command(cmd).await;
+impl DataSink for PageSink {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        if self.data.len() + data.len() > MAX_PAGE_SIZE {
+            return Err(StormError::TooLarge {
+                size: (self.data.len() + data.len()) as u64,
+                limit: MAX_PAGE_SIZE as u64,
+            });
+        }
+        self.data.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
+}
+
+async fn grab_links(
+    downloader: &Arc<HttpDownloader>,
+    page: &url::Url,
+) -> Result<Vec<PageLink>, StormError> {
+    let mut sink = PageSink { data: Vec::new() };
+    downloader.fetch_full(page, &mut sink).await?;
+    let html = String::from_utf8_lossy(&sink.data);
+
+    let mut links = stormdl_core::extract_links(&html, page);
+    links.truncate(MAX_PAGE_LINKS);
+
+    let permits = Arc::new(Semaphore::new(PAGE_PROBES));
+    let mut probes = tokio::task::JoinSet::new();
+    for (idx, link) in links.iter().enumerate() {
+        let downloader = downloader.clone();
+        let permits = permits.clone();
+        let url = link.url.clone();
+        probes.spawn(async move {
+            let _permit = permits.acquire_owned().await.ok();
+            (idx, downloader.probe(&url).await.ok())
+        });
+    }
+    while let Some(probe) = probes.join_next().await {
+        if let Ok((idx, Some(info))) = probe {
+            links[idx].size = info.size;
+            if let Some(name) = info.filename {
+                links[idx].filename = name;
+            }
+        }
+    }
+    Ok(links)
+}
+
+pub async fn run(
+    cmd_rx: Receiver<OrchestratorCommand>,
+    event_tx: Sender<DownloadEvent>,
+    config: Config,
+) {
+    let mut orchestrator = Orchestrator::with_config(event_tx, &config);
+    let finished_rx = orchestrator.finished_rx.clone();
+    let listed_rx = orchestrator.listed_rx.clone();
+    let scheduled = !config.restrictions.windows.is_empty();
+    let mut schedule = tokio::time::interval(Duration::from_secs(30));
+
+    loop {
+        tokio::select! {
+            cmd = cmd_rx.recv_async() => match cmd {
+                Ok(cmd) => orchestrator.handle_command(cmd).await,
+                Err(_) => break,
+            },
+            Ok((id, bytes, state)) = finished_rx.recv_async() => {
+                orchestrator.download_finished(id, bytes, state)
+            }
+            Ok((id, entries)) = listed_rx.recv_async() => {
+                orchestrator.archive_listed(id, entries)
+            }
+            _ = schedule.tick(), if scheduled => orchestrator.enforce_schedule().await,
+        }
     }
 }
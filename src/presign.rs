@@ -0,0 +1,14 @@
+use anyhow::{Context, Result, bail};
+use std::time::Duration;
+use stormdl_protocol::S3Config;
+use url::Url;
+
+pub fn presign(url: &str, expires: Duration) -> Result<()> {
+    let url = Url::parse(url).with_context(|| format!("Invalid URL '{}'", url))?;
+    if url.scheme() != "s3" {
+        bail!("Only s3://bucket/key URLs can be presigned");
+    }
+    let config = S3Config::from_env()?;
+    println!("{}", config.presign(&url, expires)?);
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use stormdl_core::{Restrictions, StormError};
+
+#[cfg(unix)]
+const SYSTEM_PROFILE: &str = "/etc/storm-dl/profile.toml";
+
+// Restrictions that only the holder of the password can change or lift.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedProfile {
+    pub password: String,
+    #[serde(default)]
+    pub restrictions: Restrictions,
+}
+
+impl LockedProfile {
+    pub fn new(password: &str, restrictions: Restrictions) -> Result<Self, StormError> {
+        Ok(Self {
+            password: stormdl_integrity::hash_password(password)?,
+            restrictions,
+        })
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        stormdl_integrity::verify_password(&self.password, password)
+    }
+
+    pub fn user_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("storm-dl").join("profile.toml"))
+    }
+
+    // A root-owned profile the restricted account can read but not remove.
+    pub fn system_path() -> Option<PathBuf> {
+        #[cfg(unix)]
+        return Some(PathBuf::from(SYSTEM_PROFILE));
+        #[cfg(not(unix))]
+        return None;
+    }
+
+    pub fn read(path: &Path) -> Result<Option<Self>, StormError> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map(Some)
+                .map_err(|e| StormError::Config(format!("{}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), StormError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string(self).map_err(|e| StormError::Config(e.to_string()))?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    // The system-wide profile wins over a per-user one.
+    pub fn find() -> Option<(PathBuf, Self)> {
+        [Self::system_path(), Self::user_path()]
+            .into_iter()
+            .flatten()
+            .find_map(|path| match Self::read(&path) {
+                Ok(profile) => profile.map(|p| (path, p)),
+                Err(e) => {
+                    tracing::warn!("Ignoring unreadable locked profile: {}", e);
+                    None
+                }
+            })
+    }
+}
+
+fn local_time() -> (u32, u32) {
+    use chrono::Timelike;
+    let now = chrono::Local::now();
+    (now.hour() * 60 + now.minute(), now.second())
+}
+
+pub fn check_schedule(restrictions: &Restrictions) -> Result<(), StormError> {
+    restrictions.check_time(local_time().0)
+}
+
+// Time until the current download window closes; `None` if it never does.
+pub fn window_left(restrictions: &Restrictions) -> Option<Duration> {
+    let (minute, second) = local_time();
+    let minutes = restrictions.open_for(minute)?;
+    Some(Duration::from_secs(
+        (minutes as u64 * 60).saturating_sub(second as u64),
+    ))
+}
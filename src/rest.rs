@@ -0,0 +1,315 @@
+use crate::aria2::Aria2Rpc;
+use crate::daemon;
+use crate::events::EventStream;
+use crate::orchestrator::OrchestratorCommand;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::http::request::Parts;
+use hyper::{Method, Request, Response, StatusCode, header};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::path::{Component, Path, PathBuf};
+use stormdl_core::DownloadId;
+use stormdl_integrity::ChecksumSpec;
+
+#[derive(Deserialize)]
+struct NewDownload {
+    url: String,
+    dir: Option<PathBuf>,
+    filename: Option<String>,
+    segments: Option<usize>,
+    checksum: Option<String>,
+    proxy: Option<String>,
+}
+
+pub async fn handle(
+    rpc: Aria2Rpc,
+    events: EventStream,
+    token: &str,
+    req: Request<Incoming>,
+) -> Response<Full<Bytes>> {
+    let (parts, body) = req.into_parts();
+    if foreign_origin(&parts) {
+        return error(
+            StatusCode::FORBIDDEN,
+            "Cross-origin requests are not allowed",
+        );
+    }
+    if !authorized(&parts, token) {
+        return error(StatusCode::UNAUTHORIZED, "Missing or invalid token");
+    }
+
+    let path: Vec<&str> = parts
+        .uri
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    if path == ["events"] {
+        if parts.method != Method::GET || !daemon::is_websocket(&parts.headers) {
+            return error(
+                StatusCode::UPGRADE_REQUIRED,
+                "Connect with a WebSocket client",
+            );
+        }
+        let mut req = Request::from_parts(parts, body);
+        return daemon::upgrade(&mut req, move |ws| events.serve(ws));
+    }
+
+    match (&parts.method, path.as_slice()) {
+        (&Method::GET, ["downloads"]) => respond(StatusCode::OK, Value::from(rpc.downloads())),
+        (&Method::POST, ["downloads"]) if !is_json(&parts) => error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Content-Type must be application/json",
+        ),
+        (&Method::POST, ["downloads"]) => match body.collect().await {
+            Ok(body) => add(&rpc, &body.to_bytes()).await,
+            Err(_) => error(StatusCode::BAD_REQUEST, "Failed to read request body"),
+        },
+        (&Method::GET, ["downloads", id]) => match parse_id(id).and_then(|id| rpc.download(id)) {
+            Some(download) => respond(StatusCode::OK, download),
+            None => not_found(id),
+        },
+        (&Method::DELETE, ["downloads", id]) => {
+            command(&rpc, id, OrchestratorCommand::CancelDownload)
+        }
+        (&Method::POST, ["downloads", id, "pause"]) => {
+            command(&rpc, id, OrchestratorCommand::PauseDownload)
+        }
+        (&Method::POST, ["downloads", id, "resume"]) => {
+            command(&rpc, id, OrchestratorCommand::ResumeDownload)
+        }
+        (_, ["downloads"] | ["downloads", _] | ["downloads", _, "pause" | "resume"]) => {
+            error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed")
+        }
+        _ => error(StatusCode::NOT_FOUND, "No such endpoint"),
+    }
+}
+
+fn authorized(parts: &Parts, token: &str) -> bool {
+    parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Browsers send Origin on cross-site requests; only pages served from this
+// host may talk to the API, so a malicious site can't drive a local daemon.
+fn foreign_origin(parts: &Parts) -> bool {
+    let Some(origin) = parts.headers.get(header::ORIGIN) else {
+        return false;
+    };
+    let host = parts
+        .headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok());
+    match (origin.to_str().ok(), host) {
+        (Some(origin), Some(host)) => {
+            origin != format!("http://{}", host) && origin != format!("https://{}", host)
+        }
+        _ => true,
+    }
+}
+
+fn is_json(parts: &Parts) -> bool {
+    parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+// Requested directories stay under the daemon's download root: relative ones
+// are joined to it, absolute ones must already lie inside it.
+fn confine(root: &Path, dir: &Path) -> Option<PathBuf> {
+    if dir.components().any(|c| c == Component::ParentDir) {
+        return None;
+    }
+    let dir = root.join(dir);
+    dir.starts_with(root).then_some(dir)
+}
+
+fn plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(n)) if n == name)
+        && components.next().is_none()
+}
+
+async fn add(rpc: &Aria2Rpc, body: &[u8]) -> Response<Full<Bytes>> {
+    let request: NewDownload = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e)),
+    };
+    let url = match url::Url::parse(&request.url) {
+        Ok(url) => url,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid URL: {}", e)),
+    };
+    let checksum = match request
+        .checksum
+        .as_deref()
+        .map(ChecksumSpec::parse)
+        .transpose()
+    {
+        Ok(checksum) => checksum,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &format!("Invalid checksum: {}", e)),
+    };
+    let dir = match request
+        .dir
+        .as_deref()
+        .map(|dir| confine(rpc.default_dir(), dir))
+    {
+        None => None,
+        Some(Some(dir)) => Some(dir),
+        Some(None) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "dir must be inside the download directory",
+            );
+        }
+    };
+    if request
+        .filename
+        .as_deref()
+        .is_some_and(|name| !plain_name(name))
+    {
+        return error(StatusCode::BAD_REQUEST, "filename must not contain a path");
+    }
+
+    match rpc
+        .add(
+            url,
+            dir,
+            request.filename,
+            request.segments,
+            checksum,
+            request.proxy,
+            None,
+        )
+        .await
+    {
+        Ok(id) => respond(
+            StatusCode::CREATED,
+            rpc.download(id).unwrap_or_else(|| json!({ "id": id.0 })),
+        ),
+        Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, &e.message),
+    }
+}
+
+fn command(
+    rpc: &Aria2Rpc,
+    id: &str,
+    command: fn(DownloadId) -> OrchestratorCommand,
+) -> Response<Full<Bytes>> {
+    let Some(parsed) = parse_id(id).filter(|&id| rpc.download(id).is_some()) else {
+        return not_found(id);
+    };
+    match rpc.send(parsed, command) {
+        Ok(()) => respond(
+            StatusCode::ACCEPTED,
+            rpc.download(parsed).unwrap_or(Value::Null),
+        ),
+        Err(e) => error(StatusCode::SERVICE_UNAVAILABLE, &e.message),
+    }
+}
+
+fn parse_id(id: &str) -> Option<DownloadId> {
+    id.parse().ok().map(DownloadId)
+}
+
+fn not_found(id: &str) -> Response<Full<Bytes>> {
+    error(
+        StatusCode::NOT_FOUND,
+        &format!("No download with ID {}", id),
+    )
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    respond(status, json!({ "error": message }))
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Full<Bytes>> {
+    let body = match body {
+        Value::Null => Bytes::new(),
+        body => Bytes::from(body.to_string()),
+    };
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(body))
+        .expect("static response parts are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(uri: &str, headers: &[(header::HeaderName, &str)]) -> Parts {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(name, *value);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn test_token_only_from_header() {
+        let bearer = |value| parts("/downloads", &[(header::AUTHORIZATION, value)]);
+        assert!(!authorized(&parts("/downloads", &[]), "s3cret"));
+        assert!(authorized(&bearer("Bearer s3cret"), "s3cret"));
+        assert!(!authorized(&bearer("Bearer nope"), "s3cret"));
+        assert!(!authorized(&bearer("Bearer s3cre"), "s3cret"));
+        assert!(!authorized(
+            &parts("/downloads?token=s3cret", &[]),
+            "s3cret"
+        ));
+    }
+
+    #[test]
+    fn test_rejects_foreign_origin() {
+        let from = |origin| parts("/", &[(header::HOST, "nas:8080"), (header::ORIGIN, origin)]);
+        assert!(!foreign_origin(&parts("/", &[(header::HOST, "nas:8080")])));
+        assert!(!foreign_origin(&from("http://nas:8080")));
+        assert!(foreign_origin(&from("https://evil.example")));
+        assert!(foreign_origin(&from("null")));
+    }
+
+    #[test]
+    fn test_requires_json_body() {
+        let typed = |value| parts("/downloads", &[(header::CONTENT_TYPE, value)]);
+        assert!(is_json(&typed("application/json")));
+        assert!(is_json(&typed("application/json; charset=utf-8")));
+        assert!(!is_json(&typed("text/plain")));
+        assert!(!is_json(&typed("application/x-www-form-urlencoded")));
+        assert!(!is_json(&parts("/downloads", &[])));
+    }
+
+    #[test]
+    fn test_dir_stays_in_download_root() {
+        let root = Path::new("/srv/downloads");
+        assert_eq!(
+            confine(root, Path::new("iso")),
+            Some(PathBuf::from("/srv/downloads/iso"))
+        );
+        assert_eq!(
+            confine(root, Path::new("/srv/downloads/iso")),
+            Some(PathBuf::from("/srv/downloads/iso"))
+        );
+        assert_eq!(confine(root, Path::new("/etc")), None);
+        assert_eq!(confine(root, Path::new("../etc")), None);
+        assert_eq!(confine(root, Path::new("/srv/downloads/../../etc")), None);
+
+        assert!(plain_name("file.iso"));
+        assert!(!plain_name("../file.iso"));
+        assert!(!plain_name("/etc/passwd"));
+        assert!(!plain_name("a/b"));
+        assert!(!plain_name(".."));
+    }
+}
@@ -0,0 +1,400 @@
+use crate::config::Config;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use stormdl_core::{ByteRange, DownloadState, RangeSet, StormError};
+use stormdl_integrity::IncrementalHasher;
+use stormdl_manifest::Manifest;
+use url::Url;
+
+pub struct Extent {
+    start: u64,
+    hasher: IncrementalHasher,
+}
+
+impl Extent {
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            hasher: IncrementalHasher::new(),
+        }
+    }
+
+    pub fn append(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    fn range(&self) -> ByteRange {
+        ByteRange::new(self.start, self.start + self.hasher.bytes_hashed())
+    }
+}
+
+type Extents = BTreeMap<u64, Arc<Mutex<Extent>>>;
+
+pub struct ResumeJournal {
+    manifest: Mutex<Manifest>,
+    download_id: i64,
+    extents: Mutex<Extents>,
+}
+
+impl ResumeJournal {
+    pub fn open(
+        url: &Url,
+        output_path: &Path,
+        total: u64,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Option<Self> {
+        let manifest = Config::open_manifest()?;
+        Self::with_manifest(manifest, url, output_path, total, etag, last_modified)
+    }
+
+    fn with_manifest(
+        manifest: Manifest,
+        url: &Url,
+        output_path: &Path,
+        total: u64,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Option<Self> {
+        match Self::restore(&manifest, url, output_path, total, etag, last_modified) {
+            Ok(Some((download_id, extents))) => {
+                return Some(Self {
+                    manifest: Mutex::new(manifest),
+                    download_id,
+                    extents: Mutex::new(extents),
+                });
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to read resume state: {}", e),
+        }
+
+        let filename = output_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let download_id = manifest
+            .create_download(
+                url.as_str(),
+                &filename,
+                output_path,
+                Some(total),
+                etag,
+                last_modified,
+            )
+            .map_err(|e| tracing::warn!("Failed to record download: {}", e))
+            .ok()?;
+        if let Err(e) = manifest.update_download_state(download_id, DownloadState::Downloading) {
+            tracing::warn!("Failed to update download state: {}", e);
+        }
+
+        Some(Self {
+            manifest: Mutex::new(manifest),
+            download_id,
+            extents: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn restore(
+        manifest: &Manifest,
+        url: &Url,
+        output_path: &Path,
+        total: u64,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> Result<Option<(i64, Extents)>, StormError> {
+        let Some(entry) = manifest.find_resumable(url.as_str(), output_path)? else {
+            return Ok(None);
+        };
+
+        let file_len = std::fs::metadata(output_path).map(|m| m.len()).ok();
+        let unchanged = entry.total_size == Some(total)
+            && entry.etag.as_deref() == etag
+            && entry.last_modified.as_deref() == last_modified
+            && file_len == Some(total);
+        if !unchanged {
+            manifest.delete_download(entry.id)?;
+            return Ok(None);
+        }
+
+        let mut file = File::open(output_path)?;
+        let mut extents = BTreeMap::new();
+        for segment in manifest.get_segments(entry.id)? {
+            let Some(expected) = segment.hash.as_deref() else {
+                continue;
+            };
+            let len = segment
+                .downloaded_bytes
+                .min(segment.end_byte - segment.start_byte);
+            let extent = read_extent(&mut file, segment.start_byte, len)?;
+            if extent.hasher.finalize() == expected {
+                extents.insert(extent.start, Arc::new(Mutex::new(extent)));
+            } else {
+                tracing::warn!(
+                    "Discarding bytes {}-{} of {}: checksum mismatch",
+                    segment.start_byte,
+                    segment.start_byte + len,
+                    output_path.display()
+                );
+            }
+        }
+        manifest.update_download_state(entry.id, DownloadState::Downloading)?;
+        Ok(Some((entry.id, extents)))
+    }
+
+    pub fn written(&self) -> RangeSet {
+        self.extents
+            .lock()
+            .values()
+            .map(|extent| extent.lock().range())
+            .collect()
+    }
+
+    pub fn id(&self) -> i64 {
+        self.download_id
+    }
+
+    // Tags from a resumed run add to the ones already recorded; a new note
+    // replaces the old one.
+    pub fn label(&self, tags: &[String], note: Option<&str>) {
+        if tags.is_empty() && note.is_none() {
+            return;
+        }
+        let manifest = self.manifest.lock();
+        let saved = manifest.get_download(self.download_id).and_then(|entry| {
+            let (mut merged, old_note) = entry.map(|e| (e.tags, e.note)).unwrap_or_default();
+            for tag in tags {
+                if !merged.contains(tag) {
+                    merged.push(tag.clone());
+                }
+            }
+            manifest.set_download_labels(self.download_id, &merged, note.or(old_note.as_deref()))
+        });
+        if let Err(e) = saved {
+            tracing::warn!("Failed to record tags: {}", e);
+        }
+    }
+
+    // Credentials stay out of the manifest; `storm resume` needs them passed again.
+    pub fn set_headers(&self, headers: &[(String, String)]) {
+        let headers: Vec<(String, String)> = headers
+            .iter()
+            .filter(|(name, _)| !is_credential(name))
+            .cloned()
+            .collect();
+        if headers.is_empty() {
+            return;
+        }
+        if let Err(e) = self
+            .manifest
+            .lock()
+            .set_download_headers(self.download_id, &headers)
+        {
+            tracing::warn!("Failed to record request headers: {}", e);
+        }
+    }
+
+    pub fn pause_requested(&self) -> bool {
+        self.manifest
+            .lock()
+            .get_download(self.download_id)
+            .ok()
+            .flatten()
+            .is_some_and(|entry| entry.state == DownloadState::Paused)
+    }
+
+    pub fn begin(&self, start: u64) -> Arc<Mutex<Extent>> {
+        let extent = Arc::new(Mutex::new(Extent::new(start)));
+        self.extents.lock().insert(start, extent.clone());
+        extent
+    }
+
+    pub fn corrupted(&self, path: &Path) -> io::Result<RangeSet> {
+        let mut file = File::open(path)?;
+        let mut extents = self.extents.lock();
+        let mut corrupted = RangeSet::new();
+        let mut discarded = Vec::new();
+        for (start, extent) in extents.iter() {
+            let extent = extent.lock();
+            let range = extent.range();
+            let on_disk = read_extent(&mut file, *start, range.len())?;
+            if on_disk.hasher.finalize() != extent.hasher.finalize() {
+                tracing::warn!(
+                    "Bytes {}-{} of {} do not match what was received",
+                    range.start,
+                    range.end,
+                    path.display()
+                );
+                corrupted.insert(range);
+                discarded.push(*start);
+            }
+        }
+        for start in discarded {
+            extents.remove(&start);
+        }
+        Ok(corrupted)
+    }
+
+    pub fn checkpoint(&self) {
+        let rows: Vec<(ByteRange, u64, Option<String>)> = self
+            .extents
+            .lock()
+            .values()
+            .map(|extent| {
+                let extent = extent.lock();
+                let range = extent.range();
+                (range, range.len(), Some(extent.hasher.finalize()))
+            })
+            .filter(|(range, _, _)| !range.is_empty())
+            .collect();
+
+        if let Err(e) = self
+            .manifest
+            .lock()
+            .replace_segments(self.download_id, &rows)
+        {
+            tracing::warn!("Failed to checkpoint download progress: {}", e);
+        }
+    }
+
+    pub fn finish(&self, state: DownloadState) {
+        self.checkpoint();
+        if let Err(e) = self
+            .manifest
+            .lock()
+            .update_download_state(self.download_id, state)
+        {
+            tracing::warn!("Failed to update download state: {}", e);
+        }
+    }
+}
+
+fn read_extent(file: &mut File, start: u64, len: u64) -> io::Result<Extent> {
+    let mut extent = Extent::new(start);
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = file.take(len);
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        extent.append(&buf[..n]);
+    }
+    Ok(extent)
+}
+
+fn is_credential(header: &str) -> bool {
+    let name = header.to_ascii_lowercase();
+    matches!(
+        name.as_str(),
+        "authorization" | "proxy-authorization" | "cookie"
+    ) || ["token", "api-key", "apikey", "secret", "password"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_discards_corrupted_extents() {
+        let path = std::env::temp_dir().join(format!("storm-resume-{}.bin", std::process::id()));
+        let url = Url::parse("https://example.com/file.bin").unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, vec![0u8; 1000]).unwrap();
+
+        let manifest = Manifest::open_in_memory().unwrap();
+        let journal =
+            ResumeJournal::with_manifest(manifest, &url, &path, 1000, Some("\"v1\""), None)
+                .unwrap();
+        let owner = journal.begin(0);
+        let stolen = journal.begin(600);
+        owner.lock().append(&data[0..300]);
+        stolen.lock().append(&data[600..800]);
+        journal.checkpoint();
+
+        let mut contents = data.clone();
+        contents[650] ^= 0xff;
+        std::fs::write(&path, &contents).unwrap();
+
+        let manifest = journal.manifest.into_inner();
+        let resumed =
+            ResumeJournal::with_manifest(manifest, &url, &path, 1000, Some("\"v1\""), None)
+                .unwrap();
+        assert_eq!(resumed.download_id, journal.download_id);
+        assert_eq!(resumed.written().ranges(), [ByteRange::new(0, 300)]);
+        assert_eq!(
+            resumed.written().gaps(ByteRange::new(0, 1000)),
+            [ByteRange::new(300, 1000)]
+        );
+
+        let manifest = resumed.manifest.into_inner();
+        let restarted =
+            ResumeJournal::with_manifest(manifest, &url, &path, 1000, Some("\"v2\""), None)
+                .unwrap();
+        assert!(restarted.written().is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_credential_headers_are_not_saved() {
+        let path = std::env::temp_dir().join(format!("storm-headers-{}.bin", std::process::id()));
+        let url = Url::parse("https://example.com/file.bin").unwrap();
+        let manifest = Manifest::open_in_memory().unwrap();
+        let journal =
+            ResumeJournal::with_manifest(manifest, &url, &path, 1000, Some("\"v1\""), None)
+                .unwrap();
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+        journal.set_headers(&[
+            header("Authorization", "Bearer abc"),
+            header("cookie", "session=1"),
+            header("X-Api-Key", "abc"),
+            header("X-Auth-Token", "abc"),
+            header("X-Client", "ci"),
+        ]);
+
+        let entry = journal
+            .manifest
+            .lock()
+            .get_download(journal.download_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.headers, [header("X-Client", "ci")]);
+    }
+
+    #[test]
+    fn test_finds_extents_corrupted_on_disk() {
+        let path = std::env::temp_dir().join(format!("storm-audit-{}.bin", std::process::id()));
+        let url = Url::parse("https://example.com/file.bin").unwrap();
+        let data: Vec<u8> = (0..900u32).map(|i| i as u8).collect();
+
+        let manifest = Manifest::open_in_memory().unwrap();
+        let journal =
+            ResumeJournal::with_manifest(manifest, &url, &path, 900, Some("\"v1\""), None).unwrap();
+        for start in [0, 300, 600] {
+            journal
+                .begin(start)
+                .lock()
+                .append(&data[start as usize..start as usize + 300]);
+        }
+
+        let mut contents = data.clone();
+        contents[350] ^= 0xff;
+        contents[899] ^= 0xff;
+        std::fs::write(&path, &contents).unwrap();
+
+        let corrupted = journal.corrupted(&path).unwrap();
+        assert_eq!(corrupted.ranges(), [ByteRange::new(300, 900)]);
+        assert_eq!(journal.written().ranges(), [ByteRange::new(0, 300)]);
+
+        std::fs::write(&path, &data).unwrap();
+        journal.begin(300).lock().append(&data[300..900]);
+        assert!(journal.corrupted(&path).unwrap().is_empty());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,41 @@
+use crate::lock::read_hidden;
+use anyhow::{Context, Result, bail};
+use std::io::{IsTerminal, Read};
+use stormdl::keychain;
+
+pub fn secret(name: &str, remove: bool) -> Result<()> {
+    if remove {
+        if !keychain::remove(name)? {
+            bail!("No secret named '{}' in the keychain", name);
+        }
+        println!("Removed '{}' from the keychain", name);
+        return Ok(());
+    }
+
+    let secret = read_secret(&format!("Secret for '{}'", name))?;
+    keychain::set(name, &secret)?;
+    println!(
+        "Stored '{}' in the {} keychain; use it in config.toml with secret = \"{}\"",
+        name,
+        keychain::SERVICE,
+        name
+    );
+    Ok(())
+}
+
+// Piped input is taken as the secret, so scripts don't need a terminal.
+pub fn read_secret(prompt: &str) -> Result<String> {
+    let secret = if std::io::stdin().is_terminal() {
+        read_hidden(prompt)?
+    } else {
+        let mut secret = String::new();
+        std::io::stdin()
+            .read_to_string(&mut secret)
+            .context("Failed to read the secret from stdin")?;
+        secret.trim_end_matches(['\r', '\n']).to_string()
+    };
+    if secret.is_empty() {
+        bail!("The secret can't be empty");
+    }
+    Ok(secret)
+}
@@ -0,0 +1,100 @@
+use bytes::Bytes;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use stormdl_core::{ByteRange, Calibration, DataSink, Downloader, StormError};
+use stormdl_protocol::HttpDownloader;
+use url::Url;
+
+const MIN_GAIN: f64 = 0.05;
+const QUICK_SAMPLE: u64 = 16 * 1024 * 1024;
+const QUICK_CANDIDATES: [usize; 5] = [1, 2, 4, 8, 16];
+
+struct CountingSink {
+    bytes: Arc<AtomicU64>,
+}
+
+impl DataSink for CountingSink {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
+}
+
+pub async fn measure(
+    downloader: &Arc<HttpDownloader>,
+    url: &Url,
+    sample: u64,
+    segments: usize,
+) -> Result<f64, StormError> {
+    let bytes = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+
+    let mut handles = Vec::new();
+    for range in stormdl_segment::split_range(sample, segments) {
+        let dl = downloader.clone();
+        let url = url.clone();
+        let bytes = bytes.clone();
+        handles.push(tokio::spawn(async move {
+            let mut sink = CountingSink { bytes };
+            dl.fetch_range(&url, ByteRange::new(range.start, range.end), &mut sink)
+                .await
+        }));
+    }
+
+    for handle in handles {
+        handle
+            .await
+            .map_err(|e| StormError::Other(format!("Task error: {}", e)))??;
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    Ok(bytes.load(Ordering::Relaxed) as f64 / elapsed.max(0.001))
+}
+
+pub async fn best_segments(
+    downloader: &Arc<HttpDownloader>,
+    url: &Url,
+    sample: u64,
+    candidates: &[usize],
+    mut report: impl FnMut(usize, f64),
+) -> Result<Calibration, StormError> {
+    let mut best = Calibration {
+        segments: 1,
+        speed: 0.0,
+    };
+    for &segments in candidates {
+        let speed = measure(downloader, url, sample, segments).await?;
+        report(segments, speed);
+        if speed > best.speed * (1.0 + MIN_GAIN) {
+            best = Calibration { segments, speed };
+        } else {
+            break;
+        }
+    }
+    Ok(best)
+}
+
+pub async fn quick(url: &Url) -> Result<Calibration, StormError> {
+    let downloader = Arc::new(HttpDownloader::turbo()?);
+    let info = downloader.probe(url).await?;
+    let total = info.size.unwrap_or(0);
+    if !info.supports_range || total == 0 {
+        return Err(StormError::Other(
+            "Calibration needs a server that supports range requests and reports a size"
+                .to_string(),
+        ));
+    }
+    best_segments(
+        &downloader,
+        url,
+        QUICK_SAMPLE.min(total),
+        &QUICK_CANDIDATES,
+        |_, _| {},
+    )
+    .await
+}
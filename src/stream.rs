@@ -0,0 +1,688 @@
+#![allow(clippy::too_many_arguments)]
+
+use crate::cli::{
+    Deadline, ProgressStyle, RetryState, Worker, deadline_reached, format_bytes, format_speed,
+};
+use crate::config::SpeedUnits;
+use crate::dash::DashStream;
+use crate::hls::HlsStream;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+use stormdl_bandwidth::RateLimiter;
+use stormdl_core::{
+    ByteRange, DataSink, Downloader, HttpVersion, ProgressPacer, ResourceInfo, StormError,
+};
+use stormdl_integrity::StreamVerifier;
+use stormdl_protocol::{Fmp4Muxer, HttpDownloader, decrypt_segment};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+const MAX_MANIFEST_SIZE: usize = 4 * 1024 * 1024;
+// Playlist and manifest names that say nothing about the stream; the
+// directory holding them usually does.
+const GENERIC_NAMES: [&str; 7] = [
+    "index",
+    "master",
+    "manifest",
+    "playlist",
+    "prog_index",
+    "chunklist",
+    "main",
+];
+
+// A resource made of segments listed elsewhere: an HLS playlist or a DASH
+// manifest.
+pub(crate) enum Stream {
+    Hls(HlsStream),
+    Dash(DashStream),
+}
+
+// Which variant or representation to take: the best, the worst, or the best
+// no taller than a height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Quality {
+    #[default]
+    Best,
+    Worst,
+    Height(u32),
+}
+
+// One piece of a stream to fetch, in the order it's written.
+pub(crate) struct Part {
+    pub(crate) uri: Url,
+    pub(crate) range: Option<ByteRange>,
+    pub(crate) key: Option<PartKey>,
+    // Which of the stream's tracks it belongs to, for muxing.
+    pub(crate) track: usize,
+}
+
+// HLS AES-128: the key's URL and the IV to decrypt with.
+pub(crate) struct PartKey {
+    pub(crate) uri: Url,
+    pub(crate) iv: [u8; 16],
+}
+
+// Where a stream's producer hands parts to the workers.
+pub(crate) struct Queue {
+    jobs: flume::Sender<Job>,
+    window: Arc<Semaphore>,
+    counters: Arc<Counters>,
+    stopping: Arc<AtomicBool>,
+    stop: Arc<Notify>,
+}
+
+pub(crate) enum Mux {
+    // Parts are written as they are.
+    Concat,
+    Mp4(Box<Fmp4Muxer>),
+}
+
+struct Job {
+    index: usize,
+    part: Part,
+    permit: OwnedSemaphorePermit,
+}
+
+struct Fetched {
+    index: usize,
+    track: usize,
+    data: Result<Vec<u8>>,
+    permit: OwnedSemaphorePermit,
+}
+
+#[derive(Default)]
+struct Counters {
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+
+impl Stream {
+    // None when the resource is neither a playlist nor a manifest.
+    pub(crate) async fn open(
+        downloader: &HttpDownloader,
+        info: &ResourceInfo,
+        quality: Quality,
+        quiet: bool,
+    ) -> Result<Option<Self>> {
+        if stormdl_protocol::is_playlist(info) {
+            let stream = HlsStream::open(downloader, &info.url, quality, quiet).await?;
+            Ok(Some(Self::Hls(stream)))
+        } else if stormdl_protocol::is_manifest(info) {
+            let stream = DashStream::open(downloader, &info.url, quality, quiet).await?;
+            Ok(Some(Self::Dash(stream)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        match self {
+            Self::Hls(stream) => stream.is_live(),
+            Self::Dash(_) => false,
+        }
+    }
+
+    pub(crate) fn file_name(&self, url: &Url, name: &str) -> String {
+        let stem = stem(url, name);
+        let extension = match self {
+            Self::Hls(stream) => stream.extension(),
+            Self::Dash(stream) => stream.extension(),
+        };
+        format!("{}.{}", stem, extension)
+    }
+
+    pub(crate) fn mime(&self) -> &'static str {
+        match self {
+            Self::Hls(stream) => stream.mime(),
+            Self::Dash(stream) => stream.mime(),
+        }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Self::Hls(stream) => stream.describe(),
+            Self::Dash(stream) => stream.describe(),
+        }
+    }
+}
+
+// `talk/index.m3u8` is saved as `talk.*`, `movie.mpd` as `movie.*`.
+fn stem<'a>(url: &'a Url, name: &'a str) -> &'a str {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let dir = url
+        .path_segments()
+        .and_then(|segments| segments.filter(|s| !s.is_empty()).rev().nth(1));
+    match dir {
+        Some(dir) if GENERIC_NAMES.contains(&stem.to_ascii_lowercase().as_str()) => dir,
+        _ if stem.is_empty() => "stream",
+        _ => stem,
+    }
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        match spec.trim().to_ascii_lowercase().as_str() {
+            "best" => Ok(Self::Best),
+            "worst" => Ok(Self::Worst),
+            height => height
+                .strip_suffix('p')
+                .unwrap_or(height)
+                .parse()
+                .ok()
+                .filter(|h| *h > 0)
+                .map(Self::Height)
+                .ok_or_else(|| {
+                    format!("expected best, worst or a height like 720p, got '{}'", spec)
+                }),
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Best => f.write_str("best"),
+            Self::Worst => f.write_str("worst"),
+            Self::Height(height) => write!(f, "up to {}p", height),
+        }
+    }
+}
+
+impl Quality {
+    // Ranked by height, then bandwidth. When every choice is taller than a
+    // height, the shortest is taken.
+    pub(crate) fn pick<T>(
+        self,
+        items: Vec<T>,
+        key: impl Fn(&T) -> (Option<u32>, u64),
+    ) -> Option<T> {
+        let rank = |item: &T| {
+            let (height, bandwidth) = key(item);
+            (height.unwrap_or(0), bandwidth)
+        };
+        match self {
+            Self::Best => items.into_iter().max_by_key(rank),
+            Self::Worst => items.into_iter().min_by_key(rank),
+            Self::Height(limit) => {
+                let (fits, taller): (Vec<T>, Vec<T>) = items
+                    .into_iter()
+                    .partition(|item| key(item).0.is_none_or(|height| height <= limit));
+                match fits.into_iter().max_by_key(rank) {
+                    Some(item) => Some(item),
+                    None => taller.into_iter().min_by_key(rank),
+                }
+            }
+        }
+    }
+}
+
+impl Queue {
+    // False once a recording is stopping or the workers have stopped taking
+    // jobs.
+    pub(crate) async fn push(&self, part: Part) -> Result<bool> {
+        if self.stopping.load(Ordering::Acquire) {
+            return Ok(false);
+        }
+        let permit = self.window.clone().acquire_owned().await?;
+        let index = self.counters.total.fetch_add(1, Ordering::Relaxed);
+        Ok(self
+            .jobs
+            .send_async(Job {
+                index,
+                part,
+                permit,
+            })
+            .await
+            .is_ok())
+    }
+
+    // Resolves when a live recording is asked to stop.
+    pub(crate) async fn stopped(&self) {
+        self.stop.notified().await
+    }
+}
+
+impl Mux {
+    fn push(&mut self, track: usize, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Self::Concat => Ok(data),
+            Self::Mp4(muxer) => Ok(muxer.push(track, &data)?),
+        }
+    }
+
+    fn finish(&self) -> Result<()> {
+        match self {
+            Self::Concat => Ok(()),
+            Self::Mp4(muxer) => Ok(muxer.finish()?),
+        }
+    }
+}
+
+pub(crate) async fn fetch_manifest(downloader: &HttpDownloader, url: &Url) -> Result<String> {
+    let mut sink = SegmentSink::new(MAX_MANIFEST_SIZE);
+    downloader
+        .fetch_full(url, &mut sink)
+        .await
+        .with_context(|| format!("Failed to fetch {}", url))?;
+    Ok(String::from_utf8_lossy(&sink.data).into_owned())
+}
+
+// Segments are fetched in parallel and written in order into one file;
+// returns its size. A live playlist is reloaded for new segments until it
+// ends, Ctrl+C is pressed or the deadline passes.
+pub(crate) async fn download_stream(
+    stream: Stream,
+    downloader: Arc<HttpDownloader>,
+    retry: Arc<RetryState>,
+    output_path: &Path,
+    workers: usize,
+    http_version: HttpVersion,
+    turbo: bool,
+    downloaded: Arc<AtomicU64>,
+    limiter: Arc<RateLimiter>,
+    verifier: Option<Arc<StreamVerifier>>,
+    mut pacer: ProgressPacer,
+    style: ProgressStyle,
+    units: SpeedUnits,
+    deadline: Option<Deadline>,
+    quiet: bool,
+) -> Result<u64> {
+    let live = stream.is_live();
+    let workers = workers.max(1);
+    let counters = Arc::new(Counters::default());
+    let stopping = Arc::new(AtomicBool::new(false));
+    let stop = Arc::new(Notify::new());
+    let done = Arc::new(AtomicBool::new(false));
+    let mut tasks = tokio::task::JoinSet::new();
+
+    if live {
+        let stopping = stopping.clone();
+        let stop = stop.clone();
+        tasks.spawn(async move {
+            tokio::select! {
+                Ok(()) = tokio::signal::ctrl_c() => {
+                    eprintln!("\nStopping the recording after the queued segments");
+                }
+                error = deadline_reached(deadline) => {
+                    eprintln!("\n{}; stopping the recording", error);
+                }
+            }
+            stopping.store(true, Ordering::Release);
+            stop.notify_one();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        });
+    }
+
+    let (job_tx, job_rx) = flume::bounded::<Job>(workers);
+    let (result_tx, result_rx) = flume::unbounded::<Fetched>();
+    let keys = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+    for _ in 0..workers {
+        let worker = Worker::new(&downloader, http_version, turbo, None);
+        let jobs = job_rx.clone();
+        let results = result_tx.clone();
+        let retry = retry.clone();
+        let keys = keys.clone();
+        let downloaded = downloaded.clone();
+        let limiter = limiter.clone();
+        tasks.spawn(async move {
+            while let Ok(job) = jobs.recv_async().await {
+                let data = fetch_part(&worker, &retry, &keys, &downloaded, &limiter, &job).await;
+                let fetched = Fetched {
+                    index: job.index,
+                    track: job.part.track,
+                    data,
+                    permit: job.permit,
+                };
+                if results.send_async(fetched).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop((job_rx, result_tx));
+
+    let mut mux = match &stream {
+        Stream::Hls(_) => Mux::Concat,
+        Stream::Dash(stream) => stream.mux(),
+    };
+    let queue = Queue {
+        jobs: job_tx,
+        // Finished segments wait in memory for the ones before them; this
+        // bounds how many can.
+        window: Arc::new(Semaphore::new(workers * 2)),
+        counters: counters.clone(),
+        stopping,
+        stop,
+    };
+    let mut producer = match stream {
+        Stream::Hls(stream) => tokio::spawn(crate::hls::produce(stream, downloader, queue, quiet)),
+        Stream::Dash(stream) => tokio::spawn(crate::dash::produce(stream, queue)),
+    };
+
+    let progress_handle = (!quiet).then(|| {
+        let counters = counters.clone();
+        let downloaded = downloaded.clone();
+        let done = done.clone();
+        let started = downloaded.load(Ordering::Relaxed);
+        tokio::spawn(async move {
+            let mut progress = StreamProgress {
+                counters,
+                downloaded,
+                started,
+                live,
+                style,
+                units,
+                start_time: Instant::now(),
+                last_bytes: started,
+                last_time: Instant::now(),
+            };
+            while !done.load(Ordering::Relaxed) {
+                let speed = progress.display(false);
+                tokio::time::sleep(pacer.observe(speed)).await;
+            }
+            progress.display(true);
+        })
+    });
+
+    let write = async {
+        let mut file = File::create(output_path)
+            .with_context(|| format!("Failed to create {}", output_path.display()))?;
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut written = 0u64;
+        while let Ok(fetched) = result_rx.recv_async().await {
+            pending.insert(
+                fetched.index,
+                (fetched.track, fetched.data?, fetched.permit),
+            );
+            while let Some((track, data, _permit)) = pending.remove(&next) {
+                let data = mux.push(track, data)?;
+                file.write_all(&data)?;
+                if let Some(verifier) = &verifier {
+                    verifier.write(written, &data);
+                }
+                written += data.len() as u64;
+                next += 1;
+                counters.done.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        file.flush()?;
+        (&mut producer).await??;
+        anyhow::ensure!(
+            pending.is_empty() && next == counters.total.load(Ordering::Relaxed),
+            "Stream download stopped with segments missing"
+        );
+        mux.finish()?;
+        Ok(written)
+    };
+    let result = if live {
+        write.await
+    } else {
+        tokio::select! {
+            result = write => result,
+            error = deadline_reached(deadline) => Err(error),
+        }
+    };
+
+    producer.abort();
+    done.store(true, Ordering::Relaxed);
+    if let Some(handle) = progress_handle {
+        handle.await?;
+    }
+    result
+}
+
+async fn fetch_part(
+    worker: &Worker,
+    retry: &RetryState,
+    keys: &tokio::sync::Mutex<HashMap<Url, [u8; 16]>>,
+    downloaded: &Arc<AtomicU64>,
+    limiter: &Arc<RateLimiter>,
+    job: &Job,
+) -> Result<Vec<u8>> {
+    let part = &job.part;
+    let counted = Some((downloaded.clone(), limiter.clone()));
+    let mut data = fetch(worker, retry, job.index, &part.uri, part.range, counted)
+        .await
+        .with_context(|| format!("Failed to fetch segment {}", part.uri))?;
+
+    if let Some(key) = &part.key {
+        // Held while fetching so the workers don't all ask for a new key.
+        let mut keys = keys.lock().await;
+        let secret = match keys.get(&key.uri) {
+            Some(secret) => *secret,
+            None => {
+                let bytes = fetch(worker, retry, job.index, &key.uri, None, None)
+                    .await
+                    .with_context(|| format!("Failed to fetch HLS key {}", key.uri))?;
+                let secret: [u8; 16] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+                    anyhow::anyhow!("HLS key {} is {} bytes, not 16", key.uri, bytes.len())
+                })?;
+                keys.insert(key.uri.clone(), secret);
+                secret
+            }
+        };
+        drop(keys);
+        decrypt_segment(&mut data, &secret, &key.iv)
+            .with_context(|| format!("Failed to decrypt {}", part.uri))?;
+    }
+    Ok(data)
+}
+
+async fn fetch(
+    worker: &Worker,
+    retry: &RetryState,
+    index: usize,
+    uri: &Url,
+    range: Option<ByteRange>,
+    counted: Option<(Arc<AtomicU64>, Arc<RateLimiter>)>,
+) -> Result<Vec<u8>> {
+    loop {
+        let serial = retry.connection().await;
+        let mut sink = SegmentSink::new(usize::MAX);
+        sink.counted = counted.clone();
+        let path = worker.acquire();
+        let result = match range {
+            Some(range) => worker.paths[path].fetch_range(uri, range, &mut sink).await,
+            None => worker.paths[path].fetch_full(uri, &mut sink).await,
+        };
+        worker.release(path);
+        drop(serial);
+        let error = match result {
+            Ok(()) => return Ok(sink.data),
+            Err(e) => e,
+        };
+        // A retry fetches the whole segment again.
+        if let Some((downloaded, _)) = &counted {
+            downloaded.fetch_sub(sink.data.len() as u64, Ordering::Relaxed);
+        }
+        retry.recover(index, None, error.into()).await?;
+    }
+}
+
+struct SegmentSink {
+    data: Vec<u8>,
+    limit: usize,
+    counted: Option<(Arc<AtomicU64>, Arc<RateLimiter>)>,
+}
+
+impl SegmentSink {
+    fn new(limit: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            limit,
+            counted: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DataSink for SegmentSink {
+    fn write(&mut self, data: Bytes) -> Result<(), StormError> {
+        if self.data.len() + data.len() > self.limit {
+            return Err(StormError::TooLarge {
+                size: (self.data.len() + data.len()) as u64,
+                limit: self.limit as u64,
+            });
+        }
+        if let Some((downloaded, _)) = &self.counted {
+            downloaded.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        self.data.extend_from_slice(&data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), StormError> {
+        Ok(())
+    }
+
+    async fn ready(&mut self, len: usize) -> Result<(), StormError> {
+        if let Some((_, limiter)) = &self.counted {
+            limiter.acquire(len).await;
+        }
+        Ok(())
+    }
+}
+
+struct StreamProgress {
+    counters: Arc<Counters>,
+    downloaded: Arc<AtomicU64>,
+    started: u64,
+    live: bool,
+    style: ProgressStyle,
+    units: SpeedUnits,
+    start_time: Instant,
+    last_bytes: u64,
+    last_time: Instant,
+}
+
+impl StreamProgress {
+    fn display(&mut self, finished: bool) -> f64 {
+        let current = self
+            .downloaded
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.started);
+        let done = self.counters.done.load(Ordering::Relaxed);
+        let total = self.counters.total.load(Ordering::Relaxed);
+        let interval = self.last_time.elapsed().as_secs_f64();
+        let elapsed = self.start_time.elapsed();
+        let speed = if finished {
+            current as f64 / elapsed.as_secs_f64().max(1e-3)
+        } else if interval > 0.1 {
+            current.saturating_sub(self.last_bytes) as f64 / interval
+        } else {
+            0.0
+        };
+        if interval > 0.1 {
+            self.last_bytes = current;
+            self.last_time = Instant::now();
+        }
+
+        if self.style == ProgressStyle::Json {
+            let line = serde_json::json!({
+                "downloaded": current,
+                "speed": speed as u64,
+                "elapsed_ms": elapsed.as_millis() as u64,
+                "done": finished,
+                "segments_done": done,
+                "segments_total": total,
+                "live": self.live,
+            });
+            let mut stdout = io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line);
+            let _ = stdout.flush();
+            return speed;
+        }
+
+        let live = if self.live { " (live)" } else { "" };
+        if finished {
+            eprintln!(
+                "\r{} segments{} | {} | {:>width$} | {:.1}s        ",
+                done,
+                live,
+                format_bytes(current),
+                format_speed(speed, self.units),
+                elapsed.as_secs_f64(),
+                width = self.units.width()
+            );
+        } else {
+            eprint!(
+                "\r{}/{} segments{} | {} | {:>width$} ",
+                done,
+                total,
+                live,
+                format_bytes(current),
+                format_speed(speed, self.units),
+                width = self.units.width()
+            );
+            io::stderr().flush().ok();
+        }
+        speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quality() {
+        assert_eq!("best".parse(), Ok(Quality::Best));
+        assert_eq!("Worst".parse(), Ok(Quality::Worst));
+        assert_eq!("720p".parse(), Ok(Quality::Height(720)));
+        assert_eq!("1080".parse(), Ok(Quality::Height(1080)));
+        assert!("0p".parse::<Quality>().is_err());
+        assert!("hd".parse::<Quality>().is_err());
+
+        let streams = vec![
+            (Some(360), 800),
+            (Some(1080), 6000),
+            (Some(720), 3000),
+            (Some(720), 2500),
+        ];
+        let pick = |quality: Quality| quality.pick(streams.clone(), |s| *s);
+        assert_eq!(pick(Quality::Best), Some((Some(1080), 6000)));
+        assert_eq!(pick(Quality::Worst), Some((Some(360), 800)));
+        assert_eq!(pick(Quality::Height(720)), Some((Some(720), 3000)));
+        assert_eq!(pick(Quality::Height(480)), Some((Some(360), 800)));
+        assert_eq!(pick(Quality::Height(240)), Some((Some(360), 800)));
+        assert_eq!(
+            Quality::Best.pick(Vec::<(Option<u32>, u64)>::new(), |s| *s),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stem() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert_eq!(
+            stem(
+                &url("https://cdn.example.com/talks/keynote/index.m3u8"),
+                "index.m3u8"
+            ),
+            "keynote"
+        );
+        assert_eq!(
+            stem(&url("https://cdn.example.com/movie.mpd"), "movie.mpd"),
+            "movie"
+        );
+        assert_eq!(
+            stem(&url("https://cdn.example.com/manifest.mpd"), "manifest.mpd"),
+            "manifest"
+        );
+        assert_eq!(stem(&url("https://cdn.example.com/"), ""), "stream");
+    }
+}
@@ -0,0 +1,194 @@
+use std::path::Path;
+use std::time::Duration;
+use stormdl_core::StormError;
+use url::Url;
+
+const FIELDS: [(&str, Field); 8] = [
+    ("path", Field::Path),
+    ("name", Field::Name),
+    ("url", Field::Url),
+    ("bytes", Field::Bytes),
+    ("sha256", Field::Sha256),
+    ("mime", Field::Mime),
+    ("seconds", Field::Seconds),
+    ("speed", Field::Speed),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Path,
+    Name,
+    Url,
+    Bytes,
+    Sha256,
+    Mime,
+    Seconds,
+    Speed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+// A `--print-after` line such as `{path}\t{sha256}\t{bytes}`. `\t`, `\n`,
+// `\r` and `\\` are unescaped as curl's --write-out does, and `{{` and `}}`
+// are literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+// What a finished download reports through a template.
+pub struct Completion<'a> {
+    pub path: &'a Path,
+    pub url: &'a Url,
+    pub bytes: u64,
+    pub sha256: Option<&'a str>,
+    pub mime: Option<&'a str>,
+    pub elapsed: Duration,
+}
+
+impl Template {
+    pub fn parse(spec: &str) -> Result<Self, StormError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = spec.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.peek() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('\\') => text.push('\\'),
+                    _ => {
+                        text.push('\\');
+                        continue;
+                    }
+                },
+                '{' if chars.peek() == Some(&'{') => text.push('{'),
+                '}' if chars.peek() == Some(&'}') => text.push('}'),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(StormError::Config(format!(
+                                    "unclosed '{{{}' (write '{{{{' for a literal brace)",
+                                    name
+                                )));
+                            }
+                        }
+                    }
+                    let field = FIELDS
+                        .iter()
+                        .find(|(known, _)| *known == name)
+                        .map(|(_, field)| *field)
+                        .ok_or_else(|| {
+                            let known: Vec<String> =
+                                FIELDS.iter().map(|(n, _)| format!("{{{}}}", n)).collect();
+                            StormError::Config(format!(
+                                "unknown field '{{{}}}' (expected {})",
+                                name,
+                                known.join(", ")
+                            ))
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                    continue;
+                }
+                '}' => {
+                    return Err(StormError::Config(
+                        "unmatched '}' (write '}}' for a literal brace)".into(),
+                    ));
+                }
+                c => {
+                    text.push(c);
+                    continue;
+                }
+            }
+            chars.next();
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    pub fn uses(&self, field: Field) -> bool {
+        self.parts.contains(&Part::Field(field))
+    }
+
+    pub fn render(&self, done: &Completion) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Field(Field::Path) => out.push_str(&done.path.display().to_string()),
+                Part::Field(Field::Name) => {
+                    if let Some(name) = done.path.file_name() {
+                        out.push_str(&name.to_string_lossy());
+                    }
+                }
+                Part::Field(Field::Url) => out.push_str(done.url.as_str()),
+                Part::Field(Field::Bytes) => out.push_str(&done.bytes.to_string()),
+                Part::Field(Field::Sha256) => out.push_str(done.sha256.unwrap_or_default()),
+                Part::Field(Field::Mime) => out.push_str(done.mime.unwrap_or_default()),
+                Part::Field(Field::Seconds) => {
+                    out.push_str(&format!("{:.3}", done.elapsed.as_secs_f64()))
+                }
+                // Bytes per second, as curl's %{speed_download}.
+                Part::Field(Field::Speed) => {
+                    let secs = done.elapsed.as_secs_f64();
+                    let speed = if secs > 0.0 {
+                        done.bytes as f64 / secs
+                    } else {
+                        0.0
+                    };
+                    out.push_str(&format!("{:.0}", speed));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let url = Url::parse("https://example.com/a%20b.iso").unwrap();
+        let done = Completion {
+            path: Path::new("/tmp/dl/a b.iso"),
+            url: &url,
+            bytes: 2048,
+            sha256: Some("9f86d0"),
+            mime: None,
+            elapsed: Duration::from_millis(500),
+        };
+        let render = |spec: &str| Template::parse(spec).unwrap().render(&done);
+
+        assert_eq!(
+            render(r"{path}\t{sha256}\t{bytes}"),
+            "/tmp/dl/a b.iso\t9f86d0\t2048"
+        );
+        assert_eq!(
+            render(r"{name} {url} [{mime}] {seconds}s {speed}B/s\n"),
+            "a b.iso https://example.com/a%20b.iso [] 0.500s 4096B/s\n"
+        );
+        assert_eq!(render(r"{{json}} C:\dl \\"), r"{json} C:\dl \");
+
+        assert!(Template::parse("{size}").is_err());
+        assert!(Template::parse("{path").is_err());
+        assert!(Template::parse("done}").is_err());
+        assert!(Template::parse("{bytes}").unwrap().uses(Field::Bytes));
+        assert!(!Template::parse("{bytes}").unwrap().uses(Field::Sha256));
+    }
+}
@@ -0,0 +1,269 @@
+#![allow(clippy::too_many_arguments)]
+
+use crate::cli::{
+    Deadline, DownloadArgs, ProgressStyle, deadline_reached, format_bytes, format_speed,
+    print_completion,
+};
+use crate::config::SpeedUnits;
+use anyhow::{Context, Result};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+use stormdl::notify::Notifier;
+use stormdl_bandwidth::RateLimiter;
+use stormdl_core::Downloader;
+use stormdl_protocol::{ClientOptions, HttpDownloader};
+use stormdl_torrent::{Magnet, Progress, Session, Torrent};
+use url::Url;
+
+// A magnet link, or a .torrent file named on the command line; main turns
+// local paths into file:// URLs.
+pub(crate) fn is_torrent(url: &Url) -> bool {
+    Magnet::is_magnet(url)
+        || (url.scheme() == "file"
+            && url
+                .to_file_path()
+                .is_ok_and(|path| Torrent::is_torrent(&path)))
+}
+
+// Downloads a torrent's files into `output_dir`, from its swarm and from
+// its web seeds plus any --mirror URLs. Pieces already on disk from an
+// earlier run are checked and kept, so an interrupted torrent continues
+// where it stopped.
+pub(crate) async fn download(
+    url: Url,
+    mirrors: Vec<Url>,
+    options: &ClientOptions,
+    args: &DownloadArgs,
+    output_dir: PathBuf,
+    limiter: Arc<RateLimiter>,
+    downloaded: Arc<AtomicU64>,
+    deadline: Option<Deadline>,
+    notifier: Option<Notifier>,
+    month_used: u64,
+    started: Instant,
+) -> Result<()> {
+    let quiet = args.quiet || args.batch.is_some();
+    let downloader: Arc<dyn Downloader> = Arc::new(HttpDownloader::with_options(options)?);
+
+    let (mut torrent, peers) = if Magnet::is_magnet(&url) {
+        let magnet = Magnet::parse(&url)?;
+        if !quiet {
+            eprintln!(
+                "Fetching metadata for {}...",
+                magnet
+                    .name
+                    .as_deref()
+                    .unwrap_or(&magnet.info_hash.to_string())
+            );
+        }
+        stormdl_torrent::resolve(&magnet, &*downloader).await?
+    } else {
+        let path = url
+            .to_file_path()
+            .map_err(|_| anyhow::anyhow!("Invalid torrent path {}", url))?;
+        let torrent =
+            Torrent::load(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        (torrent, Vec::new())
+    };
+    if let Some(name) = &args.name {
+        rename(&mut torrent, name);
+    }
+
+    let total_size = torrent.total_size();
+    let quota = args.config.quota.quota();
+    if quota.blocks(month_used, total_size) {
+        anyhow::bail!(
+            "Download of {} would exceed the monthly data quota ({} remaining)",
+            format_bytes(total_size),
+            format_bytes(quota.remaining(month_used).unwrap_or(0))
+        );
+    }
+    if let Some(batch) = &args.batch {
+        batch.start(total_size);
+    }
+
+    let session = Session::new(torrent, &output_dir, downloader)?
+        .with_web_seeds(mirrors)
+        .with_peers(peers)
+        .with_limiter(limiter.clone())
+        .with_counter(downloaded.clone());
+    let torrent = session.torrent().clone();
+    let output_path = output_dir.join(&torrent.name);
+    if !quiet {
+        eprintln!("Torrent: {}", torrent.name);
+        let files = torrent.files.iter().filter(|f| !f.padding).count();
+        if files > 1 {
+            eprintln!("Files: {}", files);
+        }
+        eprintln!("Size: {}", format_bytes(total_size));
+        eprintln!(
+            "Pieces: {} x {}",
+            torrent.pieces.len(),
+            format_bytes(torrent.piece_length)
+        );
+        if !torrent.trackers.is_empty() {
+            eprintln!("Trackers: {}", torrent.trackers.len());
+        }
+        if let Some(limit) = limiter.limit() {
+            eprintln!(
+                "Limit: {}",
+                format_speed(limit as f64, args.config.progress.speed_units)
+            );
+        }
+        eprintln!("Output: {}", output_path.display());
+        eprintln!();
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+    let progress_handle = (!quiet).then(|| {
+        let mut progress = TorrentProgress {
+            progress: session.progress(),
+            started: downloaded.load(Ordering::Relaxed),
+            style: args.progress,
+            units: args.config.progress.speed_units,
+            start_time: Instant::now(),
+            last_bytes: downloaded.load(Ordering::Relaxed),
+            last_time: Instant::now(),
+        };
+        let mut pacer = args
+            .config
+            .progress
+            .pacer(args.progress_interval, args.low_power);
+        let done = done.clone();
+        tokio::spawn(async move {
+            while !done.load(Ordering::Relaxed) {
+                let speed = progress.display(false);
+                tokio::time::sleep(pacer.observe(speed)).await;
+            }
+            progress.display(true);
+        })
+    });
+
+    let _watch = notifier.map(|notifier| {
+        let progress = session.progress();
+        let piece_length = torrent.piece_length;
+        notifier.watch(torrent.name.clone(), url.clone(), total_size, move || {
+            (progress.pieces().0 as u64 * piece_length).min(total_size)
+        })
+    });
+    let result = tokio::select! {
+        result = session.run() => result.map_err(anyhow::Error::from),
+        error = deadline_reached(deadline) => Err(error),
+    };
+    done.store(true, Ordering::Relaxed);
+    if let Some(handle) = progress_handle {
+        handle.await?;
+    }
+    result?;
+
+    if !quiet {
+        eprintln!("Download complete: {}", output_path.display());
+    }
+    if let Some(template) = &args.print_after {
+        for file in torrent.files.iter().filter(|f| !f.padding) {
+            print_completion(
+                template,
+                &output_dir.join(&file.path),
+                &url,
+                None,
+                None,
+                started,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// --name replaces the torrent's top-level name: the file for a single-file
+// torrent, the directory for a multi-file one.
+fn rename(torrent: &mut Torrent, name: &str) {
+    for file in &mut torrent.files {
+        let mut path = PathBuf::from(name);
+        path.extend(file.path.iter().skip(1));
+        file.path = path;
+    }
+    torrent.name = name.to_string();
+}
+
+struct TorrentProgress {
+    progress: Arc<Progress>,
+    started: u64,
+    style: ProgressStyle,
+    units: SpeedUnits,
+    start_time: Instant,
+    last_bytes: u64,
+    last_time: Instant,
+}
+
+impl TorrentProgress {
+    fn display(&mut self, finished: bool) -> f64 {
+        let current = self
+            .progress
+            .downloaded
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.started);
+        let (done, total) = self.progress.pieces();
+        let peers = self.progress.peers.load(Ordering::Relaxed);
+        let web_seeds = self.progress.web_seeds.load(Ordering::Relaxed);
+        let interval = self.last_time.elapsed().as_secs_f64();
+        let elapsed = self.start_time.elapsed();
+        let speed = if finished {
+            current as f64 / elapsed.as_secs_f64().max(1e-3)
+        } else if interval > 0.1 {
+            current.saturating_sub(self.last_bytes) as f64 / interval
+        } else {
+            0.0
+        };
+        if interval > 0.1 {
+            self.last_bytes = current;
+            self.last_time = Instant::now();
+        }
+
+        if self.style == ProgressStyle::Json {
+            let line = serde_json::json!({
+                "downloaded": current,
+                "speed": speed as u64,
+                "elapsed_ms": elapsed.as_millis() as u64,
+                "done": finished,
+                "pieces_done": done,
+                "pieces_total": total,
+                "peers": peers,
+                "web_seeds": web_seeds,
+                "from_peers": self.progress.from_peers.load(Ordering::Relaxed),
+                "from_web_seeds": self.progress.from_web_seeds.load(Ordering::Relaxed),
+            });
+            let mut stdout = io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line);
+            let _ = stdout.flush();
+            return speed;
+        }
+
+        if finished {
+            eprintln!(
+                "\r{}/{} pieces | {} | {:>width$} | {:.1}s                    ",
+                done,
+                total,
+                format_bytes(current),
+                format_speed(speed, self.units),
+                elapsed.as_secs_f64(),
+                width = self.units.width()
+            );
+        } else {
+            eprint!(
+                "\r{}/{} pieces | {} peers, {} web seeds | {} | {:>width$} ",
+                done,
+                total,
+                peers,
+                web_seeds,
+                format_bytes(current),
+                format_speed(speed, self.units),
+                width = self.units.width()
+            );
+            io::stderr().flush().ok();
+        }
+        speed
+    }
+}